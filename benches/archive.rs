@@ -0,0 +1,143 @@
+//! Benchmarks of reading, writing and walking archives, each run on
+//! generated archives at three scales: about 1 MB in a few resources,
+//! about 50 MB in a few resources, and 500 small resources across
+//! several frames.
+//!
+//! Run with `cargo bench --bench archive`. To compare a change, save a
+//! baseline first with `-- --save-baseline before`, then run again with
+//! `-- --baseline before`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use webarchive::{WebArchive, WebResource};
+
+/// Bytes which don't compress, as media in archives mostly doesn't
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// A frame at `url` showing `subresources` images of `size` bytes each
+fn frame(url: &str, subresources: usize, size: usize, seed: u64) -> WebArchive {
+    let images: Vec<WebResource> = (0..subresources)
+        .map(|index| WebResource {
+            url: format!("{}images/{}.jpg", url, index),
+            data: noise(size, seed + index as u64),
+            mime_type: "image/jpeg".to_string(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        })
+        .collect();
+
+    let html: String = images
+        .iter()
+        .map(|image| format!("<img src=\"{}\">\n", image.url))
+        .collect();
+    let mut archive = WebArchive::from_html(&html, url);
+    archive.subresources = Some(images);
+    archive
+}
+
+/// An archive of `frames` frames, each with `subresources` images of
+/// `size` bytes
+fn generated_archive(frames: usize, subresources: usize, size: usize) -> WebArchive {
+    let mut archive = frame("https://example.com/", subresources, size, 1);
+    if frames > 1 {
+        let subframe_archives = (1..frames)
+            .map(|index| {
+                let url = format!("https://example.com/frame-{}/", index);
+                frame(&url, subresources, size, (index * subresources) as u64)
+            })
+            .collect();
+        archive.subframe_archives = Some(subframe_archives);
+    }
+    archive
+}
+
+fn scales() -> Vec<(&'static str, WebArchive)> {
+    vec![
+        ("1 MB", generated_archive(1, 10, 100 << 10)),
+        ("50 MB", generated_archive(1, 20, 5 << 19)),
+        ("500 resources", generated_archive(5, 99, 4 << 10)),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+
+    for (scale, archive) in scales() {
+        let binary = archive.to_vec_binary().unwrap();
+        let xml = archive.to_vec_xml().unwrap();
+        group.throughput(Throughput::Bytes(archive.total_size() as u64));
+
+        for (format, bytes) in [("binary", binary), ("xml", xml)] {
+            group.bench_with_input(BenchmarkId::new(format, scale), &bytes, |b, bytes| {
+                b.iter(|| webarchive::from_bytes::<WebArchive>(bytes).unwrap())
+            });
+        }
+    }
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+    group.sample_size(10);
+
+    for (scale, archive) in scales() {
+        group.throughput(Throughput::Bytes(archive.total_size() as u64));
+
+        group.bench_with_input(BenchmarkId::new("binary", scale), &archive, |b, archive| {
+            b.iter(|| archive.to_vec_binary().unwrap())
+        });
+        // into a buffer which grows as it is written, as before
+        // `to_vec_xml` reserved it up front
+        group.bench_with_input(
+            BenchmarkId::new("xml, growing buffer", scale),
+            &archive,
+            |b, archive| {
+                b.iter(|| {
+                    let mut buf = Vec::new();
+                    webarchive::to_writer_xml(&mut buf, archive).unwrap();
+                    buf
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("xml", scale), &archive, |b, archive| {
+            b.iter(|| archive.to_vec_xml().unwrap())
+        });
+    }
+}
+
+fn walk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("walk");
+
+    for (scale, archive) in scales() {
+        group.bench_with_input(
+            BenchmarkId::new("iter_resources", scale),
+            &archive,
+            |b, archive| {
+                b.iter(|| {
+                    archive
+                        .iter_resources()
+                        .map(|resource| resource.data.len())
+                        .sum::<usize>()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("plan_extraction", scale),
+            &archive,
+            |b, archive| b.iter(|| archive.plan_extraction().len()),
+        );
+    }
+}
+
+criterion_group!(benches, parse, serialize, walk);
+criterion_main!(benches);
@@ -0,0 +1,153 @@
+//! Compares the peak memory used to read a large XML archive by the
+//! `plist` crate's reader, which webarchive used to read archives with,
+//! and by webarchive's own.
+//!
+//! The archive, of about 100 MB, is generated in a temporary file each
+//! run. Run with `cargo bench --bench xml_memory`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use webarchive::{WebArchive, WebArchiveBuilder, WebResource};
+
+/// The system allocator, keeping track of how much is allocated.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc(layout);
+        if !pointer.is_null() {
+            grow(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // while the data moves, both the old and new allocations exist
+        let moved = System.realloc(pointer, layout, new_size);
+        if !moved.is_null() {
+            grow(new_size);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        moved
+    }
+}
+
+fn grow(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(allocated, Ordering::Relaxed);
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Run `read`, giving what it returns, how long it took, and the most
+/// memory allocated at once while it ran, beyond what already was
+fn measure<T>(read: impl FnOnce() -> T) -> (T, f64, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let start = Instant::now();
+
+    let result = read();
+
+    let seconds = start.elapsed().as_secs_f64();
+    (result, seconds, PEAK.load(Ordering::Relaxed) - before)
+}
+
+/// Bytes which don't compress, as media in archives mostly doesn't
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// An archive of a page with a video and some images, with about 75 MB
+/// of data, as most of a large archive's size is often in one resource
+fn large_archive() -> WebArchive {
+    let mut builder = WebArchiveBuilder::new(WebResource {
+        url: "https://example.com/".to_string(),
+        data: b"<!doctype html><video src=\"video.mp4\"></video>".repeat(1000),
+        mime_type: "text/html".to_string(),
+        text_encoding_name: Some("UTF-8".to_string()),
+        frame_name: None,
+        response: None,
+    });
+
+    let video = WebResource {
+        url: "https://example.com/video.mp4".to_string(),
+        data: noise(60 << 20, 1),
+        mime_type: "video/mp4".to_string(),
+        text_encoding_name: None,
+        frame_name: None,
+        response: None,
+    };
+    builder.add_subresource(video).unwrap();
+
+    for index in 0..10 {
+        let image = WebResource {
+            url: format!("https://example.com/images/{}.jpg", index),
+            data: noise(3 << 19, index + 2),
+            mime_type: "image/jpeg".to_string(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        };
+        builder.add_subresource(image).unwrap();
+    }
+
+    builder.build()
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1 << 20) as f64
+}
+
+fn main() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let data_size = {
+        let archive = large_archive();
+        webarchive::to_file_xml(file.path(), &archive).unwrap();
+        archive.summary_info().unwrap().total_size
+    };
+    let file_size = file.as_file().metadata().unwrap().len() as usize;
+    println!(
+        "XML archive of {:.1} MB, holding {:.1} MB of data",
+        megabytes(file_size),
+        megabytes(data_size)
+    );
+
+    let (old, old_seconds, old_peak) =
+        measure(|| plist::from_file::<_, WebArchive>(file.path()).unwrap());
+    let (new, new_seconds, new_peak) =
+        measure(|| webarchive::from_file::<_, WebArchive>(file.path()).unwrap());
+
+    for (name, seconds, peak) in [
+        ("plist::from_file", old_seconds, old_peak),
+        ("webarchive::from_file", new_seconds, new_peak),
+    ] {
+        println!(
+            "{:<24}{:>8.1} MB peak ({:.2}× the data){:>8.2} s",
+            name,
+            megabytes(peak),
+            peak as f64 / data_size as f64,
+            seconds
+        );
+    }
+
+    assert!(old == new, "the archives read differ");
+    assert!(new_peak < old_peak, "reading used no less memory");
+}
@@ -0,0 +1,21 @@
+//! Write an arbitrary archive, in both formats, and read it back,
+//! expecting the same archive.
+//!
+//! Run with `cargo fuzz run round_trip`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use webarchive::WebArchive;
+
+fuzz_target!(|archive: WebArchive| {
+    let binary = archive.to_vec_binary().unwrap();
+    assert_eq!(
+        webarchive::from_bytes::<WebArchive>(&binary).unwrap(),
+        archive
+    );
+
+    let mut xml = Vec::new();
+    webarchive::to_writer_xml(&mut xml, &archive).unwrap();
+    assert_eq!(webarchive::from_bytes::<WebArchive>(&xml).unwrap(), archive);
+});
@@ -0,0 +1,256 @@
+//! Generating arbitrary resources and archives, for fuzzing.
+//!
+//! Rather than filling each field with arbitrary text, resources are
+//! given plausible URLs, MIME types and encodings, so that code which
+//! parses them gets past doing so. Archives nest at most two levels of
+//! subframes deep, with a bounded number of resources of bounded size,
+//! so that generating one always finishes quickly.
+
+use crate::{Headers, ResourceResponse, WebArchive, WebResource};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// How many levels of subframes an archive may have.
+const MAX_DEPTH: usize = 2;
+
+/// The most subresources an archive may have.
+const MAX_SUBRESOURCES: usize = 8;
+
+/// The most subframe archives an archive may have.
+const MAX_SUBFRAMES: usize = 3;
+
+/// The most bytes of data a resource may have.
+const MAX_DATA_LEN: usize = 4096;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const ALPHANUMERIC: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_";
+
+const MAIN_MIME_TYPES: &[&str] = &[
+    "text/html",
+    "application/xhtml+xml",
+    "text/plain",
+    "image/png",
+];
+
+const SUBRESOURCE_MIME_TYPES: &[&str] = &[
+    "text/html",
+    "text/css",
+    "application/javascript",
+    "image/png",
+    "image/jpeg",
+    "image/svg+xml",
+    "font/woff2",
+    "application/octet-stream",
+];
+
+const TEXT_ENCODINGS: &[&str] = &["UTF-8", "utf-8", "ISO-8859-1", "windows-1252", "Shift_JIS"];
+
+const EXTENSIONS: &[&str] = &["", ".html", ".css", ".js", ".png", ".jpg", ".svg"];
+
+impl<'a> Arbitrary<'a> for WebResource {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let host = host(u)?;
+        resource(u, &host, SUBRESOURCE_MIME_TYPES)
+    }
+}
+
+impl<'a> Arbitrary<'a> for WebArchive {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        archive(u, MAX_DEPTH)
+    }
+}
+
+fn archive(u: &mut Unstructured, depth: usize) -> Result<WebArchive> {
+    let host = host(u)?;
+    let main_resource = resource(u, &host, MAIN_MIME_TYPES)?;
+
+    let subresources = if u.arbitrary()? {
+        let count = u.int_in_range(0..=MAX_SUBRESOURCES)?;
+        let subresources = (0..count)
+            .map(|_| resource(u, &host, SUBRESOURCE_MIME_TYPES))
+            .collect::<Result<_>>()?;
+        Some(subresources)
+    } else {
+        None
+    };
+
+    let subframe_archives = if depth > 0 && u.arbitrary()? {
+        let count = u.int_in_range(0..=MAX_SUBFRAMES)?;
+        let subframe_archives = (0..count)
+            .map(|_| archive(u, depth - 1))
+            .collect::<Result<_>>()?;
+        Some(subframe_archives)
+    } else {
+        None
+    };
+
+    Ok(WebArchive {
+        main_resource,
+        subresources,
+        subframe_archives,
+    })
+}
+
+/// A resource, usually on `host`, of one of `mime_types`
+fn resource(u: &mut Unstructured, host: &str, mime_types: &[&str]) -> Result<WebResource> {
+    let url = if u.ratio(3, 4)? {
+        url(u, host)?
+    } else {
+        let other_host = self::host(u)?;
+        url(u, &other_host)?
+    };
+    let mime_type = u.choose(mime_types)?.to_string();
+    let text_encoding_name = option(u, |u| Ok(u.choose(TEXT_ENCODINGS)?.to_string()))?;
+    let frame_name = option(u, |u| token(u, ALPHANUMERIC, 1, 8))?;
+
+    let len = u.int_in_range(0..=MAX_DATA_LEN)?.min(u.len());
+    let data = u.bytes(len)?.to_vec();
+
+    let response = match u.int_in_range(0..=2)? {
+        0 => None,
+        1 => {
+            let mut headers = Headers::default();
+            headers.set("Content-Type", &mime_type);
+            let status = *u.choose(&[200, 203, 304, 404])?;
+            let response = ResourceResponse::new(
+                Some(url.clone()),
+                Some(status),
+                headers,
+                Some(mime_type.clone()),
+            );
+            Some(
+                response
+                    .to_bytes()
+                    .expect("A plain response can always be written"),
+            )
+        }
+        _ => {
+            let len = u.int_in_range(0..=64)?.min(u.len());
+            Some(u.bytes(len)?.to_vec())
+        }
+    };
+
+    Ok(WebResource {
+        url,
+        data,
+        mime_type,
+        text_encoding_name,
+        frame_name,
+        response,
+    })
+}
+
+fn host(u: &mut Unstructured) -> Result<String> {
+    let name = token(u, LOWERCASE, 1, 12)?;
+    let domain = u.choose(&["com", "org", "net", "example"])?;
+    Ok(format!("{}.{}", name, domain))
+}
+
+/// A URL on `host`, with a few path segments and perhaps a query
+fn url(u: &mut Unstructured, host: &str) -> Result<String> {
+    let scheme = u.choose(&["https", "http"])?;
+    let mut url = format!("{}://{}/", scheme, host);
+
+    let segments = u.int_in_range(0..=3)?;
+    for index in 0..segments {
+        url.push_str(&token(u, ALPHANUMERIC, 1, 10)?);
+        if index + 1 < segments {
+            url.push('/');
+        }
+    }
+    if segments > 0 {
+        url.push_str(u.choose(EXTENSIONS)?);
+    }
+
+    if u.ratio(1, 4)? {
+        url.push_str("?q=");
+        url.push_str(&token(u, ALPHANUMERIC, 1, 8)?);
+    }
+
+    Ok(url)
+}
+
+/// Between `min` and `max` characters from `alphabet`
+fn token(u: &mut Unstructured, alphabet: &[u8], min: usize, max: usize) -> Result<String> {
+    let len = u.int_in_range(min..=max)?;
+    (0..len)
+        .map(|_| Ok(char::from(*u.choose(alphabet)?)))
+        .collect()
+}
+
+fn option<T>(
+    u: &mut Unstructured,
+    f: impl FnOnce(&mut Unstructured) -> Result<T>,
+) -> Result<Option<T>> {
+    if u.arbitrary()? {
+        Ok(Some(f(u)?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_DATA_LEN, MAX_DEPTH, MAX_SUBRESOURCES};
+    use crate::WebArchive;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    /// Bytes to generate from, the same each run
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn depth(archive: &WebArchive) -> usize {
+        archive
+            .subframe_archives
+            .iter()
+            .flatten()
+            .map(|subframe| depth(subframe) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn bounded() {
+        for seed in 0..200 {
+            let bytes = noise(1 << 16, seed);
+            let mut u = Unstructured::new(&bytes);
+            let archive = WebArchive::arbitrary(&mut u).unwrap();
+
+            assert!(depth(&archive) <= MAX_DEPTH);
+            for (_, frame) in archive.iter_frames() {
+                let subresources = frame.subresources.as_deref().unwrap_or_default();
+                assert!(subresources.len() <= MAX_SUBRESOURCES);
+            }
+            for resource in archive.iter_resources() {
+                assert!(resource.data.len() <= MAX_DATA_LEN);
+                url::Url::parse(&resource.url).unwrap();
+            }
+        }
+
+        // running out of bytes still gives an archive
+        assert!(WebArchive::arbitrary(&mut Unstructured::new(&[])).is_ok());
+    }
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..50 {
+            let bytes = noise(1 << 14, seed);
+            let archive = WebArchive::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+
+            let binary = archive.to_vec_binary().unwrap();
+            assert_eq!(crate::from_bytes::<WebArchive>(&binary).unwrap(), archive);
+
+            let mut xml = Vec::new();
+            crate::to_writer_xml(&mut xml, &archive).unwrap();
+            assert_eq!(crate::from_bytes::<WebArchive>(&xml).unwrap(), archive);
+        }
+    }
+}
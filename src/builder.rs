@@ -0,0 +1,422 @@
+use crate::estimate::{Delta, SizeEstimate};
+use crate::{Error, Result, WebArchive, WebResource};
+
+/// Incrementally assembles a [`WebArchive`], optionally keeping
+/// it within a size budget.
+///
+/// ```rust
+/// # use anyhow::Result;
+/// use webarchive::{WebArchiveBuilder, WebResource};
+///
+/// # fn main() -> Result<()> {
+/// let page = WebResource {
+///     url: "https://example.com/".to_string(),
+///     data: br#"<img src="big.png">"#.to_vec(),
+///     mime_type: "text/html".to_string(),
+///     text_encoding_name: Some("UTF-8".to_string()),
+///     frame_name: None,
+///     response: None,
+/// };
+///
+/// let image = WebResource {
+///     url: "https://example.com/big.png".to_string(),
+///     data: vec![0; 2048],
+///     mime_type: "image/png".to_string(),
+///     text_encoding_name: None,
+///     frame_name: None,
+///     response: None,
+/// };
+///
+/// let mut builder = WebArchiveBuilder::new(page).max_total_size(1024);
+/// assert!(builder.add_subresource(image).is_err());
+///
+/// let archive = builder.build();
+/// assert!(archive.subresources.is_none());
+/// assert!(archive.fits_within(1024));
+/// # Ok(())
+/// # }
+/// ```
+pub struct WebArchiveBuilder {
+    main_resource: WebResource,
+    subresources: Vec<WebResource>,
+    subframe_archives: Vec<WebArchive>,
+    max_total_size: Option<usize>,
+    estimate: SizeEstimate,
+}
+
+impl WebArchiveBuilder {
+    /// Start building an archive which displays `main_resource`.
+    pub fn new(main_resource: WebResource) -> Self {
+        let mut estimate = SizeEstimate::default();
+        let mut delta = estimate.resource_delta(&main_resource);
+        estimate.string_delta(&mut delta, "WebMainResource");
+        estimate.apply(delta);
+
+        WebArchiveBuilder {
+            main_resource,
+            subresources: Vec::new(),
+            subframe_archives: Vec::new(),
+            max_total_size: None,
+            estimate,
+        }
+    }
+
+    /// Refuse to add resources which would make the archive larger
+    /// than `bytes` once serialised as a binary property list.
+    ///
+    /// The size includes resource data, response data, and the
+    /// property list structure itself, as predicted by
+    /// [`WebArchive::estimated_binary_size`].
+    pub fn max_total_size(mut self, bytes: usize) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+
+    /// The predicted size of the archive built so far, serialised
+    /// as a binary property list.
+    pub fn estimated_size(&self) -> usize {
+        self.estimate
+            .total_with(&Delta::default(), &self.top_level_collections(0, 0))
+    }
+
+    /// Add a subresource to the archive.
+    ///
+    /// Returns [`Error::BudgetExceeded`], without adding the resource,
+    /// if it would take the archive over its maximum total size.
+    pub fn add_subresource(&mut self, resource: WebResource) -> Result<&mut Self> {
+        let mut delta = self.estimate.resource_delta(&resource);
+        self.estimate.string_delta(&mut delta, "WebSubresources");
+        self.check_budget(&resource.url, &delta, 1, 0)?;
+
+        self.estimate.apply(delta);
+        self.subresources.push(resource);
+        Ok(self)
+    }
+
+    /// The predicted size of the archive with `resource` added as a
+    /// subresource, serialised as a binary property list.
+    pub(crate) fn estimated_size_with(&self, resource: &WebResource) -> usize {
+        let mut delta = self.estimate.resource_delta(resource);
+        self.estimate.string_delta(&mut delta, "WebSubresources");
+        self.estimate
+            .total_with(&delta, &self.top_level_collections(1, 0))
+    }
+
+    /// Add a subframe archive to the archive.
+    ///
+    /// Returns [`Error::BudgetExceeded`], without adding the frame,
+    /// if it would take the archive over its maximum total size.
+    pub fn add_subframe_archive(&mut self, archive: WebArchive) -> Result<&mut Self> {
+        let mut delta = self.estimate.archive_delta(&archive);
+        self.estimate
+            .string_delta(&mut delta, "WebSubframeArchives");
+        self.check_budget(&archive.main_resource.url, &delta, 0, 1)?;
+
+        self.estimate.apply(delta);
+        self.subframe_archives.push(archive);
+        Ok(self)
+    }
+
+    /// The predicted size of the archive with `archive` added as a
+    /// subframe archive, serialised as a binary property list.
+    pub(crate) fn estimated_size_with_subframe_archive(&self, archive: &WebArchive) -> usize {
+        let mut delta = self.estimate.archive_delta(archive);
+        self.estimate
+            .string_delta(&mut delta, "WebSubframeArchives");
+        self.estimate
+            .total_with(&delta, &self.top_level_collections(0, 1))
+    }
+
+    /// The number of subframe archives added so far.
+    pub(crate) fn subframe_archive_count(&self) -> usize {
+        self.subframe_archives.len()
+    }
+
+    /// Add a subresource to the subframe archive at `frame`, a path of
+    /// indexes through the subframe archives added so far.
+    ///
+    /// Returns [`Error::BudgetExceeded`], without adding the resource,
+    /// if it would take the archive over its maximum total size.
+    pub(crate) fn add_frame_subresource(
+        &mut self,
+        frame: &[usize],
+        resource: WebResource,
+    ) -> Result<&mut Self> {
+        let delta = self
+            .estimate
+            .frame_subresource_delta(self.frame(frame), &resource);
+        self.check_budget(&resource.url, &delta, 0, 0)?;
+
+        self.estimate.apply(delta);
+        self.frame_mut(frame)
+            .subresources
+            .get_or_insert_with(Vec::new)
+            .push(resource);
+        Ok(self)
+    }
+
+    /// The predicted size of the archive with `resource` added to the
+    /// subresources of the subframe archive at `frame`, serialised as a
+    /// binary property list.
+    pub(crate) fn estimated_size_with_in_frame(
+        &self,
+        frame: &[usize],
+        resource: &WebResource,
+    ) -> usize {
+        let delta = self
+            .estimate
+            .frame_subresource_delta(self.frame(frame), resource);
+        self.estimate
+            .total_with(&delta, &self.top_level_collections(0, 0))
+    }
+
+    /// Finish building the archive.
+    pub fn build(self) -> WebArchive {
+        WebArchive {
+            main_resource: self.main_resource,
+            subresources: Some(self.subresources).filter(|list| !list.is_empty()),
+            subframe_archives: Some(self.subframe_archives).filter(|list| !list.is_empty()),
+        }
+    }
+
+    fn check_budget(
+        &self,
+        url: &str,
+        delta: &Delta,
+        new_subresources: usize,
+        new_subframe_archives: usize,
+    ) -> Result<()> {
+        let max_total_size = match self.max_total_size {
+            Some(max_total_size) => max_total_size,
+            None => return Ok(()),
+        };
+
+        let would_be = self.estimate.total_with(
+            delta,
+            &self.top_level_collections(new_subresources, new_subframe_archives),
+        );
+
+        if would_be > max_total_size {
+            return Err(Error::BudgetExceeded {
+                url: url.to_string(),
+                would_be,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn frame(&self, path: &[usize]) -> &WebArchive {
+        let (first, rest) = path.split_first().expect("A frame path is never empty");
+        rest.iter()
+            .fold(&self.subframe_archives[*first], |frame, index| {
+                &frame
+                    .subframe_archives
+                    .as_ref()
+                    .expect("A frame path only goes through frames with subframe archives")[*index]
+            })
+    }
+
+    fn frame_mut(&mut self, path: &[usize]) -> &mut WebArchive {
+        let (first, rest) = path.split_first().expect("A frame path is never empty");
+        rest.iter()
+            .fold(&mut self.subframe_archives[*first], |frame, index| {
+                &mut frame
+                    .subframe_archives
+                    .as_mut()
+                    .expect("A frame path only goes through frames with subframe archives")[*index]
+            })
+    }
+
+    /// The archive's own dictionary and lists, which change shape
+    /// as items are added, and so are not part of the running estimate.
+    fn top_level_collections(
+        &self,
+        new_subresources: usize,
+        new_subframe_archives: usize,
+    ) -> Vec<(usize, usize)> {
+        let mut collections = Vec::new();
+        let mut entries = 1;
+
+        for len in [
+            self.subresources.len() + new_subresources,
+            self.subframe_archives.len() + new_subframe_archives,
+        ] {
+            if len > 0 {
+                collections.push((len, len));
+                entries += 1;
+            }
+        }
+
+        collections.push((entries, entries * 2));
+        collections
+    }
+}
+
+impl WebArchive {
+    /// An archive of a single HTML document, stored at `url`,
+    /// with no subresources.
+    pub fn from_html(html: &str, url: impl Into<String>) -> Self {
+        WebArchive::from_utf8(html, "text/html", url)
+    }
+
+    /// An archive of a single plain text document, stored at `url`.
+    ///
+    /// As with [`from_html`](WebArchive::from_html), the text encoding
+    /// is recorded as `UTF-8`, spelled that way, so an archive built by
+    /// hand with `utf-8` is not equal to it.
+    ///
+    /// ```rust
+    /// use webarchive::{WebArchive, WebResource};
+    ///
+    /// let archive = WebArchive::from_text("hello world", "about:hello");
+    ///
+    /// assert_eq!(
+    ///     archive,
+    ///     WebArchive {
+    ///         main_resource: WebResource {
+    ///             url: "about:hello".to_string(),
+    ///             data: b"hello world".to_vec(),
+    ///             mime_type: "text/plain".to_string(),
+    ///             text_encoding_name: Some("UTF-8".to_string()),
+    ///             frame_name: None,
+    ///             response: None,
+    ///         },
+    ///         subresources: None,
+    ///         subframe_archives: None,
+    ///     }
+    /// );
+    /// ```
+    pub fn from_text(text: &str, url: impl Into<String>) -> Self {
+        WebArchive::from_utf8(text, "text/plain", url)
+    }
+
+    /// An archive of a single resource of any type, stored at `url`.
+    ///
+    /// No text encoding is recorded; use [`from_html`](WebArchive::from_html)
+    /// or [`from_text`](WebArchive::from_text) for text.
+    pub fn from_binary(
+        data: impl Into<Vec<u8>>,
+        mime_type: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        WebArchive {
+            main_resource: WebResource {
+                url: url.into(),
+                data: data.into(),
+                mime_type: mime_type.into(),
+                text_encoding_name: None,
+                frame_name: None,
+                response: None,
+            },
+            subresources: None,
+            subframe_archives: None,
+        }
+    }
+
+    fn from_utf8(text: &str, mime_type: &str, url: impl Into<String>) -> Self {
+        let mut archive = WebArchive::from_binary(text, mime_type, url);
+        archive.main_resource.text_encoding_name = Some("UTF-8".to_string());
+        archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebArchiveBuilder;
+    use crate::test_support::resource;
+    use crate::{Error, WebArchive};
+
+    #[test]
+    fn budget() {
+        let psxdatacenter: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let mut frames = psxdatacenter.subframe_archives.unwrap().into_iter();
+        let nav = frames.nth(1).unwrap();
+
+        let mut builder = WebArchiveBuilder::new(nav.main_resource).max_total_size(140_000);
+        let mut rejected = Vec::new();
+
+        for subresource in nav.subresources.unwrap() {
+            let url = subresource.url.clone();
+
+            match builder.add_subresource(subresource) {
+                Ok(_) => assert!(builder.estimated_size() <= 140_000),
+                Err(Error::BudgetExceeded {
+                    url: rejected_url,
+                    would_be,
+                }) => {
+                    assert_eq!(rejected_url, url);
+                    assert!(would_be > 140_000);
+                    rejected.push(url);
+                }
+                Err(error) => panic!("Unexpected error {}", error),
+            }
+        }
+
+        // the 107 KB background image fits, but not everything after it
+        assert!(!rejected.is_empty());
+        assert!(!rejected.contains(&"http://psxdatacenter.com/images/texgrey.jpg".to_string()));
+
+        let estimated_size = builder.estimated_size();
+        let archive = builder.build();
+        let actual_size = archive.to_vec_binary().unwrap().len();
+
+        assert!(actual_size <= 140_000);
+        assert!(estimated_size.abs_diff(actual_size) <= 16);
+        assert_eq!(archive.estimated_binary_size(), estimated_size);
+    }
+
+    #[test]
+    fn no_budget() {
+        let mut builder =
+            WebArchiveBuilder::new(resource("about:main", "application/octet-stream", [1; 100]));
+        builder
+            .add_subresource(resource("about:a", "application/octet-stream", [1; 100]))
+            .unwrap()
+            .add_subframe_archive(
+                WebArchiveBuilder::new(resource(
+                    "about:frame",
+                    "application/octet-stream",
+                    [1; 100],
+                ))
+                .build(),
+            )
+            .unwrap();
+
+        let frame_resource = resource("about:b", "application/octet-stream", [1; 100]);
+        let estimated_with = builder.estimated_size_with_in_frame(&[0], &frame_resource);
+        builder.add_frame_subresource(&[0], frame_resource).unwrap();
+        assert_eq!(builder.estimated_size(), estimated_with);
+
+        let estimated_size = builder.estimated_size();
+        let archive = builder.build();
+
+        assert_eq!(archive.subresources.as_ref().map(Vec::len), Some(1));
+        assert_eq!(archive.subframe_archives.as_ref().map(Vec::len), Some(1));
+        let frame = &archive.subframe_archives.as_ref().unwrap()[0];
+        assert_eq!(frame.subresources.as_ref().map(Vec::len), Some(1));
+        assert_eq!(estimated_size, archive.to_vec_binary().unwrap().len());
+    }
+
+    #[test]
+    fn single_document_archives() {
+        let page = WebArchive::from_html("<p>Crouton</p>", "https://crouton.net/");
+        assert_eq!(page.main_resource.mime_type, "text/html");
+        assert_eq!(
+            page.main_resource.text_encoding_name.as_deref(),
+            Some("UTF-8")
+        );
+        assert!(page.subresources.is_none());
+        assert_eq!(page.title().unwrap(), None);
+
+        let image = WebArchive::from_binary(vec![0x89, b'P', b'N', b'G'], "image/png", "about:png");
+        assert!(image.main_resource.text_encoding_name.is_none());
+
+        let mut xml = Vec::new();
+        crate::to_writer_xml(&mut xml, &image).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(!xml.contains("WebResourceTextEncodingName"));
+        assert!(!xml.contains("WebSubresources"));
+    }
+}
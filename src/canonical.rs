@@ -0,0 +1,151 @@
+use crate::{UrlNormalization, WebArchive, WebResource};
+
+impl WebArchive {
+    /// Put the archive in a standard form, so archives of the same
+    /// content compare equal however they were saved, at any depth:
+    ///
+    /// - URLs are put in the [default](UrlNormalization::default)
+    ///   normal form, lowercasing their scheme and host, removing
+    ///   default ports and fragments, and resolving `.` and `..`
+    ///   segments.
+    /// - Subresources are sorted by URL, and subframe archives by the
+    ///   URL of their main resource.
+    /// - Empty frame names and text encoding names are removed, as are
+    ///   empty lists of subresources and subframe archives.
+    ///
+    /// This is lossy: the order resources were saved in, and the exact
+    /// form of their URLs, can not be recovered. It does not change
+    /// how Safari displays the archive, as URLs are normalised the same
+    /// way when references are resolved. References within HTML and CSS
+    /// resources are left as they are.
+    pub fn canonicalize(&mut self) {
+        self.canonicalize_with(UrlNormalization::default());
+    }
+
+    /// Put the archive in a standard form, like
+    /// [`canonicalize`](WebArchive::canonicalize), normalising URLs as
+    /// `normalization` says.
+    pub fn canonicalize_with(&mut self, normalization: UrlNormalization) {
+        canonicalize_resource(&mut self.main_resource, normalization);
+
+        if let Some(subresources) = &mut self.subresources {
+            for subresource in subresources.iter_mut() {
+                canonicalize_resource(subresource, normalization);
+            }
+            subresources.sort_by(|a, b| a.url.cmp(&b.url));
+        }
+        self.subresources
+            .take_if(|subresources| subresources.is_empty());
+
+        if let Some(subframe_archives) = &mut self.subframe_archives {
+            for subframe_archive in subframe_archives.iter_mut() {
+                subframe_archive.canonicalize_with(normalization);
+            }
+            subframe_archives.sort_by(|a, b| a.main_resource.url.cmp(&b.main_resource.url));
+        }
+        self.subframe_archives
+            .take_if(|subframe_archives| subframe_archives.is_empty());
+    }
+}
+
+fn canonicalize_resource(resource: &mut WebResource, normalization: UrlNormalization) {
+    resource.url = normalization.normalize(&resource.url);
+
+    resource.frame_name.take_if(|name| name.is_empty());
+    resource
+        .text_encoding_name
+        .take_if(|encoding| encoding.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::text_resource;
+    use crate::{UrlNormalization, WebArchive};
+
+    fn frame(url: &str) -> WebArchive {
+        WebArchive {
+            main_resource: text_resource(url, "text/html", b"<p>Frame"),
+            subresources: None,
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn canonicalize() {
+        let mut first = WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", b"<p>Hello"),
+            subresources: Some(vec![
+                text_resource("https://example.com/a.css", "text/html", b"a"),
+                text_resource("https://example.com/b.css", "text/html", b"b"),
+            ]),
+            subframe_archives: Some(vec![
+                frame("https://example.com/frame-a.html"),
+                frame("https://example.com/frame-b.html"),
+            ]),
+        };
+
+        let mut main_resource = text_resource("HTTPS://Example.com:443", "text/html", b"<p>Hello");
+        main_resource.frame_name = Some(String::new());
+        let mut second = WebArchive {
+            main_resource,
+            subresources: Some(vec![
+                text_resource("https://example.com/styles/../b.css", "text/html", b"b"),
+                text_resource("https://example.com/./a.css", "text/html", b"a"),
+            ]),
+            subframe_archives: Some(vec![
+                WebArchive {
+                    subresources: Some(vec![]),
+                    ..frame("https://EXAMPLE.com/frame-b.html")
+                },
+                frame("https://example.com/frame-a.html"),
+            ]),
+        };
+
+        // bytes written for each archive differ before
+        assert_ne!(
+            first.to_vec_binary().unwrap(),
+            second.to_vec_binary().unwrap()
+        );
+        assert_ne!(first, second);
+
+        first.canonicalize();
+        second.canonicalize();
+        assert_eq!(first, second);
+        assert_eq!(
+            first.to_vec_binary().unwrap(),
+            second.to_vec_binary().unwrap()
+        );
+    }
+
+    #[test]
+    fn canonicalize_with() {
+        let archive = || WebArchive {
+            main_resource: text_resource("https://example.com/#top", "text/html", b"<p>Hello"),
+            subresources: Some(vec![text_resource(
+                "https://example.com/a.css?utm_source=feed",
+                "text/html",
+                b"a",
+            )]),
+            subframe_archives: None,
+        };
+
+        let mut standard = archive();
+        standard.canonicalize();
+        assert_eq!(standard.main_resource.url, "https://example.com/");
+        assert_eq!(
+            standard.subresources.unwrap()[0].url,
+            "https://example.com/a.css?utm_source=feed"
+        );
+
+        let mut loose = archive();
+        loose.canonicalize_with(UrlNormalization::loose());
+        assert_eq!(
+            loose.subresources.unwrap()[0].url,
+            "https://example.com/a.css"
+        );
+
+        let mut exact = archive();
+        exact.canonicalize_with(UrlNormalization::exact());
+        assert_eq!(exact.main_resource.url, "https://example.com/#top");
+    }
+}
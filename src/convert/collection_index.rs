@@ -0,0 +1,179 @@
+use super::sitemap::w3c_datetime;
+use crate::ArchiveSummary;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use std::fmt::Write;
+
+/// The page [`to_collection_index`] fills in.
+const TEMPLATE: &str = include_str!("collection_index.html");
+
+/// One archive in a collection index, see [`to_collection_index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The archive's file name, as shown in the index.
+    pub name: String,
+
+    /// The URL to link to the archive at, usually relative to the index.
+    pub href: String,
+
+    /// The archive's [summary](crate::WebArchive::summary_info), or why
+    /// it could not be read.
+    pub summary: Result<ArchiveSummary, String>,
+
+    /// The URL of an image to show for the archive, such as its
+    /// [thumbnail candidate](crate::WebArchive::thumbnail_candidate)
+    /// written beside the index, or as a `data:` URL.
+    pub thumbnail: Option<String>,
+}
+
+/// Make a static HTML page listing a collection of archives, as a
+/// gallery of their titles, capture dates, sizes, how
+/// [complete](crate::WebArchive::completeness) they are and their
+/// thumbnails, each linking to its archive.
+///
+/// The page refers to nothing but the archives and thumbnails, as its
+/// style is inline. Archives which could not be read are listed too,
+/// with their error.
+pub fn to_collection_index(title: &str, entries: &[IndexEntry]) -> String {
+    let mut items = String::new();
+    for entry in entries {
+        write_entry(&mut items, entry).expect("Writing to a string can not fail");
+    }
+
+    let failed = entries
+        .iter()
+        .filter(|entry| entry.summary.is_err())
+        .count();
+    let count = match failed {
+        0 => format!("{} archive(s)", entries.len()),
+        failed => format!(
+            "{} archive(s), of which {} could not be read",
+            entries.len(),
+            failed
+        ),
+    };
+
+    TEMPLATE
+        .replace("{{title}}", &encode_text(title))
+        .replace("{{count}}", &count)
+        .replace("{{entries}}", &items)
+}
+
+fn write_entry(html: &mut String, entry: &IndexEntry) -> std::fmt::Result {
+    let href = encode_double_quoted_attribute(&entry.href);
+    let name = encode_text(&entry.name);
+
+    let summary = match &entry.summary {
+        Ok(summary) => summary,
+        Err(error) => {
+            writeln!(html, "<li class=\"failed\">")?;
+            writeln!(html, "<div class=\"details\">")?;
+            writeln!(html, "<h2><a href=\"{}\">{}</a></h2>", href, name)?;
+            writeln!(html, "<p class=\"error\">{}</p>", encode_text(error))?;
+            writeln!(html, "</div>")?;
+            return writeln!(html, "</li>");
+        }
+    };
+
+    writeln!(html, "<li>")?;
+    if let Some(thumbnail) = &entry.thumbnail {
+        writeln!(
+            html,
+            "<a class=\"thumbnail\" href=\"{}\"><img src=\"{}\" alt=\"\" loading=\"lazy\"></a>",
+            href,
+            encode_double_quoted_attribute(thumbnail)
+        )?;
+    }
+    writeln!(html, "<div class=\"details\">")?;
+    writeln!(
+        html,
+        "<h2><a href=\"{}\">{}</a></h2>",
+        href,
+        encode_text(summary.title.as_deref().unwrap_or(&summary.url))
+    )?;
+    writeln!(html, "<p>{}</p>", encode_text(&summary.url))?;
+
+    write!(html, "<p>")?;
+    if let Some(captured_at) = summary.captured_at {
+        write!(
+            html,
+            "<time datetime=\"{}\">{}</time> · ",
+            w3c_datetime(captured_at),
+            httpdate::fmt_http_date(captured_at)
+        )?;
+    }
+    // rounded down, so only archives with nothing missing are 100%
+    writeln!(
+        html,
+        "{} · {}% complete · {}</p>",
+        format_size(summary.total_size),
+        (summary.completeness.ratio() * 100.0).floor(),
+        name
+    )?;
+    writeln!(html, "</div>")?;
+    writeln!(html, "</li>")
+}
+
+/// A size in bytes, in the largest binary unit it has at least one of.
+fn format_size(bytes: usize) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_size, to_collection_index, IndexEntry};
+    use crate::WebArchive;
+
+    #[test]
+    fn collection_index() {
+        let crouton: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let psxdatacenter: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let entries = [
+            IndexEntry {
+                name: "crouton.webarchive".to_string(),
+                href: "crouton.webarchive".to_string(),
+                summary: Ok(crouton.summary_info().unwrap()),
+                thumbnail: Some("thumbnails/crouton.png".to_string()),
+            },
+            IndexEntry {
+                name: "psxdatacenter.webarchive".to_string(),
+                href: "psxdatacenter.webarchive".to_string(),
+                summary: Ok(psxdatacenter.summary_info().unwrap()),
+                thumbnail: Some("thumbnails/psxdatacenter.jpg".to_string()),
+            },
+            IndexEntry {
+                name: "truncated & broken.webarchive".to_string(),
+                href: "truncated%20&%20broken.webarchive".to_string(),
+                summary: Err("Could not parse <plist>".to_string()),
+                thumbnail: None,
+            },
+        ];
+
+        assert_eq!(
+            to_collection_index("Archives", &entries),
+            include_str!("../../fixtures/collection-index.html")
+        );
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(134), "134 bytes");
+        assert_eq!(format_size(5316), "5.2 KiB");
+        assert_eq!(format_size(2_381_434), "2.3 MiB");
+        assert_eq!(format_size(5 << 40), "5120.0 GiB");
+    }
+}
@@ -0,0 +1,157 @@
+use super::readability;
+use crate::references::{base_url, resolve_url};
+use crate::{Error, Result, WebArchive};
+
+/// How [`to_markdown`] refers to images stored in the archive.
+///
+/// Images which are not stored are always referred to by URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageMode {
+    /// Embed images as `data:` URLs, so the Markdown stands alone.
+    DataUrl,
+
+    /// Refer to images as files within this directory, at the paths
+    /// the `extract` command would write them to: their URL without
+    /// its scheme, such as `example.com/images/photo.jpg`.
+    Files(String),
+
+    /// Refer to images by their original URL.
+    Remote,
+}
+
+/// Options for [`to_markdown`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// How to refer to images stored in the archive.
+    pub images: ImageMode,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            images: ImageMode::DataUrl,
+        }
+    }
+}
+
+/// Convert the article within an archive's main document to Markdown,
+/// as a reader mode would display it.
+///
+/// Navigation, advertising, comments and other page furniture are
+/// left out, as far as they can be identified. The archive's
+/// [`title`](WebArchive::title) is added as a heading if the article
+/// doesn't have one. Links are made absolute, and images are referred
+/// to as set by `options`.
+///
+/// Returns [`Error::NotHtml`] if the main resource is not HTML.
+pub fn to_markdown(archive: &WebArchive, options: &MarkdownOptions) -> Result<String> {
+    let main_resource = &archive.main_resource;
+
+    let text = match main_resource.decoded_text() {
+        Some(text) if main_resource.is_html() => text,
+        _ => return Err(Error::NotHtml(main_resource.mime_type.clone())),
+    };
+
+    let document = scraper::Html::parse_document(&text);
+    let base = base_url(
+        &main_resource.url,
+        scraper::Selector::parse("base[href]")
+            .ok()
+            .and_then(|selector| document.select(&selector).next())
+            .and_then(|base| base.value().attr("href")),
+    );
+
+    let article = readability::article(&document);
+    let html = readability::to_html(
+        article,
+        &mut |tag, attribute, value| match (tag, attribute) {
+            ("a", "href") => resolve_url(base.as_ref(), value),
+            ("img", "src") => {
+                let url = resolve_url(base.as_ref(), value)?;
+                Some(image_url(archive, url, &options.images))
+            }
+            _ => Some(value.to_string()),
+        },
+    );
+
+    let mut markdown = html2md::parse_html(&html).trim().to_string();
+
+    let has_heading = article
+        .descendent_elements()
+        .any(|element| element.value().name() == "h1");
+    if !has_heading {
+        if let Some(title) = archive.title()? {
+            markdown = format!("# {}\n\n{}", title, markdown);
+        }
+    }
+
+    markdown.push('\n');
+    Ok(markdown)
+}
+
+fn image_url(archive: &WebArchive, url: String, images: &ImageMode) -> String {
+    let resource = match archive.get_resource_by_url(&url) {
+        Some(resource) => resource,
+        None => return url,
+    };
+
+    match images {
+        ImageMode::DataUrl => resource.to_data_url(),
+        ImageMode::Files(directory) => {
+            let path = url.split_once("//").map_or(url.as_str(), |(_, path)| path);
+            format!("{}/{}", directory.trim_end_matches('/'), path)
+        }
+        ImageMode::Remote => url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_markdown, ImageMode, MarkdownOptions};
+    use crate::WebArchive;
+
+    fn article() -> WebArchive {
+        crate::from_file("fixtures/article.webarchive")
+            .expect("Could not read article webarchive fixture")
+    }
+
+    #[test]
+    fn article_to_markdown() {
+        let options = MarkdownOptions {
+            images: ImageMode::Files("croutons_files".to_string()),
+        };
+
+        assert_eq!(
+            to_markdown(&article(), &options).unwrap(),
+            include_str!("../../fixtures/article.md")
+        );
+    }
+
+    #[test]
+    fn article_image_modes() {
+        let markdown = to_markdown(&article(), &MarkdownOptions::default()).unwrap();
+        assert!(markdown.contains("![A bowl of golden croutons](data:image/png;base64,iVBORw0KGgo"));
+
+        let options = MarkdownOptions {
+            images: ImageMode::Remote,
+        };
+        let markdown = to_markdown(&article(), &options).unwrap();
+        assert!(markdown.contains(
+            "![A bowl of golden croutons](https://kitchen.example.com/2020/05/images/croutons.png)"
+        ));
+    }
+
+    #[test]
+    fn crouton_to_markdown() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let options = MarkdownOptions {
+            images: ImageMode::Remote,
+        };
+
+        assert_eq!(
+            to_markdown(&archive, &options).unwrap(),
+            "# Crouton\n\n![Crouton](https://crouton.net/crouton.png)\n"
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! Conversion of Web Archives to other formats.
+
+mod collection_index;
+#[cfg(feature = "readability")]
+mod markdown;
+#[cfg(feature = "readability")]
+mod readability;
+mod single_html;
+mod sitemap;
+
+pub use collection_index::{to_collection_index, IndexEntry};
+#[cfg(feature = "readability")]
+pub use markdown::{to_markdown, ImageMode, MarkdownOptions};
+pub use single_html::{to_single_html, to_single_html_with_hooks};
+pub use sitemap::to_sitemap;
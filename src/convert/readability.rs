@@ -0,0 +1,231 @@
+//! Finding the main content of an HTML document, in the style of
+//! Arc90's Readability.
+//!
+//! Paragraph-like elements are scored by how much prose they contain,
+//! and each adds its score to its parent, and half of it to its
+//! grandparent. Elements whose class or id suggest they are content
+//! are favoured, and those suggesting navigation, advertising, or
+//! comments are penalised. The highest-scoring element, adjusted for
+//! how much of its text is links, is taken to be the article.
+
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+
+/// Elements which are never part of an article.
+const SKIPPED_TAGS: &[&str] = &[
+    "aside", "button", "embed", "footer", "form", "header", "iframe", "input", "link", "meta",
+    "nav", "noscript", "object", "script", "select", "style", "svg", "template", "textarea",
+];
+
+/// Words in a class or id suggesting an element is not part of an article.
+const UNLIKELY_WORDS: &[&str] = &[
+    "ad",
+    "ads",
+    "advert",
+    "banner",
+    "breadcrumb",
+    "breadcrumbs",
+    "comment",
+    "comments",
+    "cookie",
+    "footer",
+    "menu",
+    "nav",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+];
+
+/// Words in a class or id suggesting an element is an article.
+const POSITIVE_WORDS: &[&str] = &[
+    "article", "body", "content", "entry", "main", "post", "story", "text",
+];
+
+/// Attributes kept on elements in the extracted article.
+const KEPT_ATTRIBUTES: &[&str] = &["href", "src", "alt", "title"];
+
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+
+/// Find the element containing the document's article,
+/// or its `<body>` if nothing looks like one.
+pub(crate) fn article(document: &Html) -> ElementRef<'_> {
+    let mut scores = HashMap::new();
+
+    for element in document.root_element().descendent_elements() {
+        if !matches!(element.value().name(), "p" | "pre" | "td" | "blockquote")
+            || element
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(is_unlikely)
+        {
+            continue;
+        }
+
+        let text = text_of(element);
+        let text = text.trim();
+        if text.chars().count() < 25 {
+            continue;
+        }
+
+        let score =
+            1.0 + text.matches(',').count() as f64 + (text.chars().count() as f64 / 100.0).min(3.0);
+
+        let ancestors = element.ancestors().filter_map(ElementRef::wrap);
+        for (ancestor, share) in ancestors.zip([1.0, 0.5]) {
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| initial_score(ancestor)) += score * share;
+        }
+    }
+
+    let best = scores
+        .iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(*id)?)?;
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(a, a_score), (b, b_score)| {
+            a_score
+                .total_cmp(b_score)
+                // prefer the earlier element when scores are equal
+                .then_with(|| b.id().cmp(&a.id()))
+        })
+        .map(|(element, _)| element);
+
+    best.or_else(|| {
+        document
+            .root_element()
+            .child_elements()
+            .find(|element| element.value().name() == "body")
+    })
+    .unwrap_or_else(|| document.root_element())
+}
+
+/// Serialise an element, and its descendants, as HTML, without
+/// elements which are unlikely to be part of an article, and with
+/// only a few attributes, which can be rewritten by `rewrite`.
+pub(crate) fn to_html(
+    element: ElementRef<'_>,
+    rewrite: &mut impl FnMut(&str, &str, &str) -> Option<String>,
+) -> String {
+    let mut html = String::new();
+    write_element(&mut html, element, rewrite);
+    html
+}
+
+fn write_element(
+    html: &mut String,
+    element: ElementRef<'_>,
+    rewrite: &mut impl FnMut(&str, &str, &str) -> Option<String>,
+) {
+    let name = element.value().name();
+
+    html.push('<');
+    html.push_str(name);
+    for attribute in KEPT_ATTRIBUTES {
+        if let Some(value) = element
+            .value()
+            .attr(attribute)
+            .and_then(|value| rewrite(name, attribute, value))
+        {
+            html.push_str(&format!(
+                " {}=\"{}\"",
+                attribute,
+                html_escape::encode_double_quoted_attribute(&value)
+            ));
+        }
+    }
+    html.push('>');
+
+    if VOID_TAGS.contains(&name) {
+        return;
+    }
+
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => html.push_str(&html_escape::encode_text(&**text)),
+            Node::Element(_) => {
+                let child = ElementRef::wrap(child).expect("Node is an element");
+                if !is_unlikely(child) {
+                    write_element(html, child, rewrite);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    html.push_str("</");
+    html.push_str(name);
+    html.push('>');
+}
+
+fn is_unlikely(element: ElementRef<'_>) -> bool {
+    let name = element.value().name();
+
+    if SKIPPED_TAGS.contains(&name) {
+        return true;
+    }
+
+    if matches!(name, "html" | "body" | "article" | "main") {
+        return false;
+    }
+
+    class_weight(element) < 0.0
+}
+
+/// A bonus or penalty for an element's class and id.
+fn class_weight(element: ElementRef<'_>) -> f64 {
+    let names = [element.value().attr("class"), element.value().attr("id")];
+    let words: Vec<String> = names
+        .into_iter()
+        .flatten()
+        .flat_map(|names| names.split(|c: char| !c.is_ascii_alphanumeric()))
+        .map(str::to_ascii_lowercase)
+        .collect();
+
+    let has = |list: &[&str]| words.iter().any(|word| list.contains(&word.as_str()));
+
+    match (has(POSITIVE_WORDS), has(UNLIKELY_WORDS)) {
+        (true, false) => 25.0,
+        (false, true) => -25.0,
+        _ => 0.0,
+    }
+}
+
+fn initial_score(element: ElementRef<'_>) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" => 10.0,
+        "div" | "main" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "form" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+
+    tag_score + class_weight(element)
+}
+
+fn text_of(element: ElementRef<'_>) -> String {
+    element.text().collect()
+}
+
+/// The proportion of an element's text which is within links.
+fn link_density(element: ElementRef<'_>) -> f64 {
+    let length = text_of(element).chars().count();
+    if length == 0 {
+        return 0.0;
+    }
+
+    let link_length: usize = element
+        .descendent_elements()
+        .filter(|descendant| descendant.value().name() == "a")
+        .map(|link| text_of(link).chars().count())
+        .sum();
+
+    link_length as f64 / length as f64
+}
@@ -0,0 +1,311 @@
+use crate::optimize::normalize_url;
+use crate::{OperationHooks, Phase, Progress, Result, WebArchive, WebResource};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use std::collections::HashMap;
+
+/// Convert an archive into a single HTML document, which shows the
+/// archived page without referring to anything outside of itself.
+///
+/// References to images, fonts and other subresources are replaced
+/// with `data:` URLs holding their contents, as are references to
+/// stylesheets, once the references within those stylesheets have been
+/// replaced in turn. Subframes are converted the same way and embedded
+/// as `data:` URLs too. References to anything not stored in the
+/// archive are left as they are, as are scripts.
+///
+/// Archives of a bare image, video, PDF or other file, as Safari saves
+/// when one is opened on its own, are converted to a minimal page which
+/// shows it, embedded as a `data:` URL, titled with its file name.
+pub fn to_single_html(archive: &WebArchive) -> Result<String> {
+    to_single_html_with_hooks(archive, &OperationHooks::default())
+}
+
+/// Convert an archive into a single HTML document, as [`to_single_html`]
+/// does, reporting each resource and frame inlined to `hooks`, and
+/// stopping with [`Error::Cancelled`] if they say to.
+pub fn to_single_html_with_hooks(
+    archive: &WebArchive,
+    hooks: &OperationHooks<'_>,
+) -> Result<String> {
+    let mut progress = Progress::new(Phase::Converting);
+    let mut inlined = |resource: &WebResource| -> Result<()> {
+        progress.items_done += 1;
+        progress.bytes_done += resource.data.len() as u64;
+        hooks.report(progress);
+        hooks.check()
+    };
+
+    let main_resource = &archive.main_resource;
+    if !main_resource.is_html() {
+        let html = wrapper_page(main_resource);
+        inlined(main_resource)?;
+        return Ok(html);
+    }
+
+    // stored resources which are not documents, by their normalized URL
+    let mut data_urls = HashMap::new();
+    for resource in archive.iter_resources() {
+        if !resource.is_text() && !resource.mime_essence().is_empty() {
+            if let std::collections::hash_map::Entry::Vacant(entry) =
+                data_urls.entry(normalize_url(&resource.url))
+            {
+                entry.insert(resource.to_data_url());
+                inlined(resource)?;
+            }
+        }
+    }
+
+    // stylesheets may only refer to those, so can be converted next
+    let mut stylesheets = HashMap::new();
+    for resource in archive.iter_resources() {
+        if resource.is_css() && !stylesheets.contains_key(&normalize_url(&resource.url)) {
+            let text = inlined_text(resource, &data_urls)?;
+            stylesheets.insert(
+                normalize_url(&resource.url),
+                text_data_url("text/css", &text),
+            );
+            inlined(resource)?;
+        }
+    }
+    data_urls.extend(stylesheets);
+
+    inlined_frame(archive, &data_urls, &mut inlined)
+}
+
+/// The text of `archive`'s main resource, with references to stored
+/// resources and subframes replaced with `data:` URLs.
+///
+/// `inlined` is called with each subframe's main resource once it has
+/// been converted.
+fn inlined_frame(
+    archive: &WebArchive,
+    data_urls: &HashMap<String, String>,
+    inlined: &mut impl FnMut(&WebResource) -> Result<()>,
+) -> Result<String> {
+    let mut frame_urls = HashMap::new();
+    for subframe_archive in archive.subframe_archives.iter().flatten() {
+        if subframe_archive.main_resource.is_html() {
+            let html = inlined_frame(subframe_archive, data_urls, inlined)?;
+            inlined(&subframe_archive.main_resource)?;
+            frame_urls.insert(
+                normalize_url(&subframe_archive.main_resource.url),
+                text_data_url("text/html", &html),
+            );
+        }
+    }
+
+    rewritten_text(&archive.main_resource, |url| {
+        frame_urls.get(url).or_else(|| data_urls.get(url)).cloned()
+    })
+}
+
+/// The text of `resource`, with references to stored resources
+/// replaced with `data:` URLs.
+fn inlined_text(resource: &WebResource, data_urls: &HashMap<String, String>) -> Result<String> {
+    rewritten_text(resource, |url| data_urls.get(url).cloned())
+}
+
+/// The text of `resource` once its references have been rewritten
+/// by `f`, leaving `resource` itself as it is.
+fn rewritten_text(resource: &WebResource, f: impl FnMut(&str) -> Option<String>) -> Result<String> {
+    let mut copy = WebResource {
+        data: resource.data.clone(),
+        url: resource.url.clone(),
+        frame_name: None,
+        mime_type: resource.mime_type.clone(),
+        text_encoding_name: resource.text_encoding_name.clone(),
+        response: None,
+    };
+    copy.rewrite_references(f)?;
+    Ok(copy.decoded_text().unwrap_or_default().into_owned())
+}
+
+/// A page showing a resource which is not HTML, as browsers do when
+/// one is opened on its own
+fn wrapper_page(resource: &WebResource) -> String {
+    let mime_type = resource.mime_essence();
+    let src = encode_double_quoted_attribute(&resource.to_data_url()).into_owned();
+
+    let body = match mime_type.split('/').next().unwrap_or_default() {
+        "image" => format!(r#"<img src="{}" alt="">"#, src),
+        "video" => format!(r#"<video src="{}" controls></video>"#, src),
+        "audio" => format!(r#"<audio src="{}" controls></audio>"#, src),
+        _ if resource.is_text() => format!(
+            "<pre>{}</pre>",
+            encode_text(&resource.decoded_text().unwrap_or_default())
+        ),
+        _ => format!(
+            r#"<embed src="{}" type="{}">"#,
+            src,
+            encode_double_quoted_attribute(&mime_type)
+        ),
+    };
+
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html>\n",
+            "<head>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n",
+            "<title>{}</title>\n",
+            "<style>html, body {{ height: 100%; margin: 0 }} ",
+            "body {{ display: flex; align-items: center; justify-content: center }} ",
+            "img, video {{ max-width: 100%; max-height: 100% }} ",
+            "embed {{ width: 100%; height: 100% }} ",
+            "pre {{ align-self: flex-start; margin: 1em; white-space: pre-wrap }}</style>\n",
+            "</head>\n",
+            "<body>\n",
+            "{}\n",
+            "</body>\n",
+            "</html>\n",
+        ),
+        encode_text(file_name(&resource.url)),
+        body
+    )
+}
+
+/// The last part of a URL's path, or the whole URL if it has none
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.split_once("//")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .and_then(|(_, path)| path.split('/').rfind(|segment| !segment.is_empty()))
+        .unwrap_or(url)
+}
+
+/// A base64 `data:` URL holding `text` encoded as UTF-8, whatever the
+/// encoding it was archived with.
+fn text_data_url(mime_type: &str, text: &str) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:{};charset=utf-8;base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_single_html, to_single_html_with_hooks};
+    use crate::test_support::resource;
+    use crate::{Error, OperationHooks, Phase, WebArchive};
+    use std::sync::Mutex;
+
+    #[test]
+    fn single_html_crouton() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let html = to_single_html(&archive).unwrap();
+
+        let png = archive.subresources.as_ref().unwrap()[0].to_data_url();
+        assert!(html.contains(&format!(r#"<img src="{}" alt="Crouton">"#, png)));
+        assert!(!html.contains("crouton.png"));
+    }
+
+    #[test]
+    fn single_html_stylesheets_and_frames() {
+        let mut frame = resource(
+            "https://example.com/frame.html",
+            "text/html",
+            "<img src=dot.gif>café".as_bytes(),
+        );
+        frame.text_encoding_name = Some("UTF-8".to_string());
+
+        let archive = WebArchive {
+            main_resource: resource(
+                "https://example.com/",
+                "text/html",
+                br#"<link rel="stylesheet" href="style.css"><iframe src="frame.html"></iframe><img src="missing.png">"#,
+            ),
+            subresources: Some(vec![
+                resource(
+                    "https://example.com/style.css",
+                    "text/css",
+                    b"body { background: url(dot.gif) }",
+                ),
+                resource("https://example.com/dot.gif", "image/gif", b"GIF89a"),
+            ]),
+            subframe_archives: Some(vec![WebArchive {
+                main_resource: frame,
+                subresources: None,
+                subframe_archives: None,
+            }]),
+        };
+
+        let html = to_single_html(&archive).unwrap();
+        assert_eq!(
+            html,
+            concat!(
+                r#"<link rel="stylesheet" href="data:text/css;charset=utf-8;base64,Ym9keSB7IGJhY2tncm91bmQ6IHVybCgiZGF0YTppbWFnZS9naWY7YmFzZTY0LFIwbEdPRGxoIikgfQ==">"#,
+                r#"<iframe src="data:text/html;charset=utf-8;base64,PGltZyBzcmM9ImRhdGE6aW1hZ2UvZ2lmO2Jhc2U2NCxSMGxHT0RsaCI+Y2Fmw6k="></iframe>"#,
+                r#"<img src="missing.png">"#,
+            )
+        );
+    }
+
+    #[test]
+    fn single_html_not_html() {
+        let archive: WebArchive = crate::from_file("fixtures/image-only.webarchive")
+            .expect("Could not read image-only webarchive fixture");
+        let png = archive.main_resource.to_data_url();
+
+        let html = to_single_html(&archive).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>crouton.png</title>\n"));
+        assert!(html.contains(&format!("<body>\n<img src=\"{}\" alt=\"\">\n</body>", png)));
+
+        let pdf = WebArchive {
+            main_resource: resource(
+                "https://example.com/reports/q3.pdf?download=1",
+                "application/pdf",
+                b"%PDF-1.4",
+            ),
+            subresources: None,
+            subframe_archives: None,
+        };
+        let html = to_single_html(&pdf).unwrap();
+        assert!(html.contains("<title>q3.pdf</title>\n"));
+        assert!(html.contains(
+            r#"<embed src="data:application/pdf;base64,JVBERi0xLjQ=" type="application/pdf">"#
+        ));
+
+        let text = WebArchive {
+            main_resource: resource("https://example.com/", "text/plain", b"1 < 2"),
+            subresources: None,
+            subframe_archives: None,
+        };
+        let html = to_single_html(&text).unwrap();
+        assert!(html.contains("<title>https://example.com/</title>\n"));
+        assert!(html.contains("<pre>1 &lt; 2</pre>"));
+    }
+
+    #[test]
+    fn single_html_hooks() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let reports = Mutex::new(Vec::new());
+        let hooks = OperationHooks::new().on_progress(|progress| {
+            assert_eq!(progress.phase, Phase::Converting);
+            reports.lock().unwrap().push(progress.items_done);
+        });
+        assert_eq!(
+            to_single_html_with_hooks(&archive, &hooks).unwrap(),
+            to_single_html(&archive).unwrap()
+        );
+        drop(hooks);
+
+        // each image, then each frame
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports, (1..=reports.len()).collect::<Vec<_>>());
+        assert!(reports.len() > archive.subframe_archives.as_ref().unwrap().len());
+
+        let hooks = OperationHooks::new().cancel_when(|| true);
+        assert!(matches!(
+            to_single_html_with_hooks(&archive, &hooks),
+            Err(Error::Cancelled)
+        ));
+    }
+}
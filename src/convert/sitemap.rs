@@ -0,0 +1,247 @@
+use crate::{Result, WebArchive};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write a [sitemap](https://www.sitemaps.org/protocol.html) listing
+/// the HTML documents in `archives`, for feeding archived sites to
+/// search indexers.
+///
+/// Each archive's main resource, and those of its subframes at any
+/// depth, get a `<url>`, in document order, unless they are not HTML,
+/// are not `http` or `https` URLs, or have been listed already. Their
+/// `<lastmod>` is from the `Last-Modified` or `Date` header of the
+/// document's archived response, or, as browsers rarely archive one
+/// for documents, the latest `Date` of any response in its frame.
+/// Documents with neither have no `<lastmod>`.
+///
+/// Returns an error if writing fails, or an archived response can
+/// not be parsed.
+pub fn to_sitemap<W: Write>(archives: &[&WebArchive], mut writer: W) -> Result<()> {
+    let mut urls: Vec<(&str, Option<SystemTime>)> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+
+    for archive in archives {
+        for (_, frame) in archive.iter_frames() {
+            let document = &frame.main_resource;
+            if !document.is_html()
+                || !(document.url.starts_with("http://") || document.url.starts_with("https://"))
+            {
+                continue;
+            }
+
+            let modified = modified(frame)?;
+            match positions.get(document.url.as_str()) {
+                // keep the first listing, with the latest date of any
+                Some(&position) => {
+                    let lastmod = &mut urls[position].1;
+                    *lastmod = (*lastmod).max(modified);
+                }
+                None => {
+                    positions.insert(document.url.as_str(), urls.len());
+                    urls.push((&document.url, modified));
+                }
+            }
+        }
+    }
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#
+    )?;
+    for (url, lastmod) in urls {
+        writeln!(writer, "  <url>")?;
+        writeln!(writer, "    <loc>{}</loc>", escape_xml(url))?;
+        if let Some(lastmod) = lastmod {
+            writeln!(writer, "    <lastmod>{}</lastmod>", w3c_datetime(lastmod))?;
+        }
+        writeln!(writer, "  </url>")?;
+    }
+    writeln!(writer, "</urlset>")?;
+
+    Ok(())
+}
+
+/// When a frame's document last changed, as well as can be told from
+/// the archived responses.
+fn modified(frame: &WebArchive) -> Result<Option<SystemTime>> {
+    if let Some(response) = frame.main_resource.parse_response()? {
+        if let Some(date) = response.last_modified().or_else(|| response.date()) {
+            return Ok(Some(date));
+        }
+    }
+
+    let mut captured_at = None;
+    for resource in frame.subresources.iter().flatten() {
+        captured_at = captured_at.max(
+            resource
+                .parse_response()?
+                .and_then(|response| response.date()),
+        );
+    }
+    Ok(captured_at)
+}
+
+/// Escape the characters the sitemap protocol requires to be entities.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\'' => escaped.push_str("&apos;"),
+            '"' => escaped.push_str("&quot;"),
+            '>' => escaped.push_str("&gt;"),
+            '<' => escaped.push_str("&lt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A time in the W3C Datetime format sitemaps use, in UTC, such as
+/// `2020-05-30T05:11:56+00:00`.
+pub(super) fn w3c_datetime(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // the proleptic Gregorian calendar date of a day since the epoch,
+    // by Howard Hinnant's `civil_from_days`
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00:00",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_sitemap, w3c_datetime};
+    use crate::test_support::text_resource;
+    use crate::WebArchive;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Check a sitemap is well-formed, and has the structure the
+    /// sitemap schema requires, returning each `<loc>` and `<lastmod>`.
+    fn parse_sitemap(xml: &str) -> Vec<(String, Option<String>)> {
+        const NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+        let document = roxmltree::Document::parse(xml).expect("sitemaps are well-formed");
+        let urlset = document.root_element();
+        assert_eq!(urlset.tag_name().name(), "urlset");
+        assert_eq!(urlset.tag_name().namespace(), Some(NAMESPACE));
+
+        urlset
+            .children()
+            .filter(|node| node.is_element())
+            .map(|url| {
+                assert_eq!(url.tag_name().name(), "url");
+                let children: Vec<_> = url.children().filter(|node| node.is_element()).collect();
+                assert!(children
+                    .iter()
+                    .all(|child| child.tag_name().namespace() == Some(NAMESPACE)));
+
+                let names: Vec<&str> = children
+                    .iter()
+                    .map(|child| child.tag_name().name())
+                    .collect();
+                assert!(
+                    names == ["loc"] || names == ["loc", "lastmod"],
+                    "<loc> is required, and may be followed by <lastmod>"
+                );
+
+                let text = |index: usize| {
+                    children
+                        .get(index)
+                        .map(|child| child.text().unwrap().to_string())
+                };
+                (text(0).unwrap(), text(1))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sitemap() {
+        let crouton: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let psxdatacenter: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let search = WebArchive {
+            main_resource: text_resource(
+                "https://example.com/search?q=crouton&page=2",
+                "text/html",
+                b"<p>Results",
+            ),
+            subresources: None,
+            subframe_archives: None,
+        };
+
+        let mut output = Vec::new();
+        to_sitemap(&[&crouton, &psxdatacenter, &search, &crouton], &mut output).unwrap();
+        let xml = String::from_utf8(output).unwrap();
+        assert!(xml.contains("<loc>https://example.com/search?q=crouton&amp;page=2</loc>"));
+
+        let urls = parse_sitemap(&xml);
+        let urls: Vec<(&str, Option<&str>)> = urls
+            .iter()
+            .map(|(loc, lastmod)| (loc.as_str(), lastmod.as_deref()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                // from the PNG's response, as the page has none
+                ("https://crouton.net/", Some("2020-05-30T05:11:56+00:00")),
+                ("http://psxdatacenter.com/ntsc-j_list.html", None),
+                (
+                    "http://psxdatacenter.com/banner.html",
+                    Some("2020-05-29T16:57:51+00:00")
+                ),
+                (
+                    "http://psxdatacenter.com/nav.html",
+                    Some("2020-05-29T16:57:51+00:00")
+                ),
+                (
+                    "http://psxdatacenter.com/jbanner.html",
+                    Some("2020-05-29T16:57:52+00:00")
+                ),
+                ("http://psxdatacenter.com/jlist.html", None),
+                ("https://example.com/search?q=crouton&page=2", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn sitemap_dates() {
+        assert_eq!(w3c_datetime(UNIX_EPOCH), "1970-01-01T00:00:00+00:00");
+        assert_eq!(
+            w3c_datetime(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00+00:00"
+        );
+        assert_eq!(
+            w3c_datetime(UNIX_EPOCH + Duration::from_secs(1_401_148_388)),
+            "2014-05-26T23:53:08+00:00"
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use crate::digest::sha256_hex;
+use crate::{Result, WebArchive, WebResource};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Subresources of one frame which all have the same data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateGroup {
+    /// The SHA-256 digest of the data, as lowercase hexadecimal.
+    pub sha256: String,
+
+    /// The size of each copy of the data, in bytes.
+    pub size: usize,
+
+    /// The URL of each copy, in document order. The first is the
+    /// one [`dedupe`](WebArchive::dedupe) keeps.
+    pub urls: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// How many bytes of resource data the copies after the first take up.
+    pub fn wasted_bytes(&self) -> usize {
+        self.size * (self.urls.len() - 1)
+    }
+}
+
+/// What [`WebArchive::dedupe`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DedupeReport {
+    /// The groups of duplicates which were collapsed.
+    pub groups: Vec<DuplicateGroup>,
+
+    /// How many subresources were removed.
+    pub removed: usize,
+
+    /// How many references within HTML and CSS resources were
+    /// changed to point at a kept copy.
+    pub rewritten_references: usize,
+}
+
+impl DedupeReport {
+    /// How many bytes of resource data were removed.
+    pub fn wasted_bytes(&self) -> usize {
+        self.groups.iter().map(DuplicateGroup::wasted_bytes).sum()
+    }
+}
+
+/// Which URLs references should be changed to, frame by frame, after
+/// [`dedupe_across_urls`](WebArchive::dedupe_across_urls) has removed
+/// subresources stored under them.
+///
+/// Frames are identified by their position in the order of
+/// [`iter_frames`](WebArchive::iter_frames), so frames should not be
+/// added or removed before the map is applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RewriteMap {
+    frames: Vec<BTreeMap<String, String>>,
+}
+
+impl RewriteMap {
+    /// Whether there is nothing to rewrite.
+    pub fn is_empty(&self) -> bool {
+        self.frames.iter().all(BTreeMap::is_empty)
+    }
+
+    /// How many URLs are rewritten, across all frames.
+    pub fn len(&self) -> usize {
+        self.frames.iter().map(BTreeMap::len).sum()
+    }
+
+    /// The URL which references to `url` should be changed to, in the
+    /// frame at position `frame` in the order of
+    /// [`iter_frames`](WebArchive::iter_frames).
+    pub fn get(&self, frame: usize, url: &str) -> Option<&str> {
+        self.frames.get(frame)?.get(url).map(String::as_str)
+    }
+
+    /// Each frame's position, with a removed URL and the
+    /// URL of the copy which was kept in its place.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.frames.iter().enumerate().flat_map(|(frame, urls)| {
+            urls.iter()
+                .map(move |(from, to)| (frame, from.as_str(), to.as_str()))
+        })
+    }
+}
+
+impl WebArchive {
+    /// Find subresources which are stored more than once within
+    /// the same frame, in this archive or any subframe archive.
+    ///
+    /// Normally only copies with the same URL and data are grouped.
+    /// With `across_urls`, copies with the same data but different
+    /// URLs are grouped too.
+    ///
+    /// Copies in different frames are never grouped, as each frame
+    /// only loads subresources from its own archive. Main resources
+    /// are never included, as they can not be removed.
+    pub fn duplicate_groups(&self, across_urls: bool) -> Vec<DuplicateGroup> {
+        self.iter_frames()
+            .flat_map(|(_, frame)| frame_groups(frame, across_urls))
+            .map(|group| group.group)
+            .collect()
+    }
+
+    /// Remove the subresources found by
+    /// [`duplicate_groups`](WebArchive::duplicate_groups), keeping the
+    /// first copy in each group.
+    ///
+    /// With `across_urls`, references to the URLs of removed copies,
+    /// in the frame's HTML and CSS resources, are rewritten to the URL
+    /// of the kept copy, so the page still displays the same way.
+    ///
+    /// Note that the binary property list format already stores
+    /// identical data only once, so removing copies mostly shrinks
+    /// XML property lists, and in-memory archives.
+    pub fn dedupe(&mut self, across_urls: bool) -> Result<DedupeReport> {
+        let mut report = DedupeReport::default();
+        let mut rewrites = RewriteMap::default();
+
+        self.remove_duplicates(across_urls, &mut report, &mut rewrites);
+        report.rewritten_references = self.apply_rewrite_map(&rewrites)?;
+
+        Ok(report)
+    }
+
+    /// Remove subresources which have the same data as another in
+    /// their frame, keeping the first copy, and return which URLs
+    /// references should be changed to, to lead to the kept copies.
+    ///
+    /// Unlike [`dedupe`](WebArchive::dedupe), references are left
+    /// alone, so the page may not display correctly until the map
+    /// is passed to [`apply_rewrite_map`](WebArchive::apply_rewrite_map).
+    pub fn dedupe_across_urls(&mut self) -> RewriteMap {
+        let mut rewrites = RewriteMap::default();
+        self.remove_duplicates(true, &mut DedupeReport::default(), &mut rewrites);
+        rewrites
+    }
+
+    /// Change references in the HTML and CSS resources of each frame,
+    /// following `rewrites`, returning how many were changed.
+    pub fn apply_rewrite_map(&mut self, rewrites: &RewriteMap) -> Result<usize> {
+        let mut frame = 0;
+        self.apply_frame_rewrites(rewrites, &mut frame)
+    }
+
+    fn apply_frame_rewrites(&mut self, rewrites: &RewriteMap, frame: &mut usize) -> Result<usize> {
+        let mut rewritten = 0;
+
+        if let Some(urls) = rewrites.frames.get(*frame).filter(|urls| !urls.is_empty()) {
+            let rewrite = |resource: &mut WebResource| {
+                resource.rewrite_references(|url| urls.get(url).cloned())
+            };
+
+            rewritten += rewrite(&mut self.main_resource)?;
+            for subresource in self.subresources.iter_mut().flatten() {
+                rewritten += rewrite(subresource)?;
+            }
+        }
+        *frame += 1;
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            rewritten += subframe_archive.apply_frame_rewrites(rewrites, frame)?;
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Remove duplicates from this frame, then each subframe archive,
+    /// noting them in `report`, and the URLs no longer stored in
+    /// `rewrites`.
+    fn remove_duplicates(
+        &mut self,
+        across_urls: bool,
+        report: &mut DedupeReport,
+        rewrites: &mut RewriteMap,
+    ) {
+        let groups = frame_groups(self, across_urls);
+
+        let removed: HashSet<usize> = groups
+            .iter()
+            .flat_map(|group| group.indices[1..].iter().copied())
+            .collect();
+
+        if let Some(subresources) = &mut self.subresources {
+            let mut index = 0;
+            subresources.retain(|_| {
+                index += 1;
+                !removed.contains(&(index - 1))
+            });
+
+            if subresources.is_empty() {
+                self.subresources = None;
+            }
+        }
+
+        report.removed += removed.len();
+
+        let kept: HashSet<&str> = self
+            .subresources
+            .iter()
+            .flatten()
+            .map(|subresource| subresource.url.as_str())
+            .collect();
+
+        rewrites.frames.push(
+            groups
+                .iter()
+                .flat_map(|FrameGroup { group, .. }| {
+                    group.urls[1..]
+                        .iter()
+                        .filter(|url| !kept.contains(url.as_str()))
+                        .map(|url| (url.clone(), group.urls[0].clone()))
+                })
+                .collect(),
+        );
+
+        report
+            .groups
+            .extend(groups.into_iter().map(|group| group.group));
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            subframe_archive.remove_duplicates(across_urls, report, rewrites);
+        }
+    }
+}
+
+/// A [`DuplicateGroup`] along with where each copy is
+/// among the frame's subresources.
+struct FrameGroup {
+    group: DuplicateGroup,
+    indices: Vec<usize>,
+}
+
+/// Group the duplicated subresources of a single frame,
+/// ignoring its subframe archives.
+fn frame_groups(frame: &WebArchive, across_urls: bool) -> Vec<FrameGroup> {
+    let mut groups: Vec<FrameGroup> = Vec::new();
+    let mut positions: HashMap<(String, Option<&str>), usize> = HashMap::new();
+
+    for (index, subresource) in frame.subresources.iter().flatten().enumerate() {
+        let sha256 = sha256_hex(&subresource.data);
+        let key = (
+            sha256.clone(),
+            (!across_urls).then_some(subresource.url.as_str()),
+        );
+
+        match positions.get(&key) {
+            Some(&position) => {
+                let FrameGroup { group, indices } = &mut groups[position];
+                group.urls.push(subresource.url.clone());
+                indices.push(index);
+            }
+            None => {
+                positions.insert(key, groups.len());
+                groups.push(FrameGroup {
+                    group: DuplicateGroup {
+                        sha256,
+                        size: subresource.data.len(),
+                        urls: vec![subresource.url.clone()],
+                    },
+                    indices: vec![index],
+                });
+            }
+        }
+    }
+
+    groups.retain(|FrameGroup { group, .. }| group.urls.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{WebArchive, WebResource};
+
+    fn page() -> WebArchive {
+        let mut html = resource(
+            "https://example.com/",
+            "text/html",
+            br#"<img src="a.png"><img src="b.png"><link rel="stylesheet" href="style.css">"#,
+        );
+        html.text_encoding_name = Some("UTF-8".to_string());
+
+        WebArchive {
+            main_resource: html,
+            subresources: Some(vec![
+                resource("https://example.com/a.png", "image/png", [1; 64]),
+                resource("https://example.com/b.png", "image/png", [1; 64]),
+                resource(
+                    "https://example.com/style.css",
+                    "text/css",
+                    b"body { background: url(b.png) }",
+                ),
+                resource("https://example.com/a.png", "image/png", [1; 64]),
+                resource("https://example.com/c.png", "image/png", [2; 32]),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn duplicate_groups() {
+        let archive = page();
+
+        let groups = archive.duplicate_groups(false);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].urls, ["https://example.com/a.png"; 2]);
+        assert_eq!(groups[0].wasted_bytes(), 64);
+
+        let groups = archive.duplicate_groups(true);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].urls,
+            [
+                "https://example.com/a.png",
+                "https://example.com/b.png",
+                "https://example.com/a.png",
+            ]
+        );
+        assert_eq!(groups[0].wasted_bytes(), 128);
+    }
+
+    #[test]
+    fn dedupe_same_url() {
+        let mut archive = page();
+        let report = archive.dedupe(false).unwrap();
+
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.rewritten_references, 0);
+        assert_eq!(report.wasted_bytes(), 64);
+        assert_eq!(archive.subresources.as_ref().map(Vec::len), Some(4));
+        assert!(archive.duplicate_groups(false).is_empty());
+    }
+
+    #[test]
+    fn dedupe_across_urls() {
+        let mut archive = page();
+        let report = archive.dedupe(true).unwrap();
+
+        assert_eq!(report.removed, 2);
+        assert_eq!(report.rewritten_references, 2);
+
+        let urls: Vec<&str> = archive
+            .iter_resources()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/",
+                "https://example.com/a.png",
+                "https://example.com/style.css",
+                "https://example.com/c.png",
+            ]
+        );
+        assert!(archive.missing_subresources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dedupe_keeps_other_frames() {
+        let psxdatacenter: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        // texgrey.jpg is stored by several frames, but only once in each
+        let mut archive = psxdatacenter;
+        let resources = archive.iter_resources().count();
+        archive.dedupe(false).unwrap();
+        assert_eq!(archive.iter_resources().count(), resources);
+    }
+
+    #[test]
+    fn dedupe_across_frames() {
+        fn frame(url: &str, html: &[u8], subresources: Vec<WebResource>) -> WebArchive {
+            let mut main_resource = resource(url, "text/html", html);
+            main_resource.text_encoding_name = Some("UTF-8".to_string());
+
+            WebArchive {
+                main_resource,
+                subresources: Some(subresources),
+                subframe_archives: None,
+            }
+        }
+
+        // each frame keeps a different copy, so references must be rewritten frame by frame
+        let mut archive = frame(
+            "https://example.com/",
+            br#"<iframe src="one.html"></iframe><iframe src="two.html"></iframe><img src="missing.png">"#,
+            Vec::new(),
+        );
+        archive.subframe_archives = Some(vec![
+            frame(
+                "https://example.com/one.html",
+                br#"<img src="a.png"><img src="b.png">"#,
+                vec![
+                    resource("https://example.com/a.png", "image/png", [1; 64]),
+                    resource("https://example.com/b.png", "image/png", [1; 64]),
+                ],
+            ),
+            frame(
+                "https://example.com/two.html",
+                br#"<img src="b.png"><style>p { background: url(a.png) }</style>"#,
+                vec![
+                    resource("https://example.com/b.png", "image/png", [1; 64]),
+                    resource("https://example.com/a.png", "image/png", [1; 64]),
+                ],
+            ),
+        ]);
+        let missing = archive.missing_subresources().unwrap();
+
+        let rewrites = archive.dedupe_across_urls();
+        let entries: Vec<(usize, &str, &str)> = rewrites.iter().collect();
+        assert_eq!(
+            entries,
+            [
+                (1, "https://example.com/b.png", "https://example.com/a.png"),
+                (2, "https://example.com/a.png", "https://example.com/b.png"),
+            ]
+        );
+        assert_eq!(rewrites.len(), 2);
+        assert_eq!(
+            rewrites.get(2, "https://example.com/a.png"),
+            Some("https://example.com/b.png")
+        );
+        assert_eq!(rewrites.get(0, "https://example.com/a.png"), None);
+        assert_eq!(archive.iter_resources().count(), 5);
+
+        assert_eq!(archive.apply_rewrite_map(&rewrites).unwrap(), 2);
+        assert_eq!(archive.missing_subresources().unwrap(), missing);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::digest::sha256_hex;
+use crate::{WebArchive, WebResource};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How a resource differs between two archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Only the new archive stores the URL.
+    Added,
+
+    /// Only the old archive stores the URL.
+    Removed,
+
+    /// Both archives store the URL, with different data or MIME types.
+    Modified,
+}
+
+/// One side's copy of a resource listed by [`WebArchive::diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ResourceVersion {
+    /// The MIME type of the resource.
+    pub mime_type: String,
+
+    /// The size of its data, in bytes.
+    pub size: usize,
+
+    /// The SHA-256 digest of its data, as lowercase hexadecimal.
+    pub sha256: String,
+}
+
+impl ResourceVersion {
+    fn new(resource: &WebResource) -> Self {
+        ResourceVersion {
+            mime_type: resource.mime_type.clone(),
+            size: resource.data.len(),
+            sha256: sha256_hex(&resource.data),
+        }
+    }
+}
+
+/// How the content of a modified resource changed, as worked out by
+/// [`WebArchive::diff_content`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ContentDiff {
+    /// Both copies are text. Each hunk of the unified diff between
+    /// them, decoded with their own encodings, starting with its `@@`
+    /// line. There are none if only the encoding changed.
+    Text {
+        /// The hunks, in order.
+        hunks: Vec<String>,
+    },
+
+    /// At least one copy is not text, so only the sizes and digests
+    /// of the copies are compared.
+    Binary,
+
+    /// At least one copy is larger than the limit, so the text was
+    /// not compared.
+    TooLarge {
+        /// The limit, in bytes.
+        max_size: usize,
+    },
+}
+
+/// One resource which differs between two archives.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ResourceChange {
+    /// The URL the resource is stored at.
+    pub url: String,
+
+    /// How it differs.
+    pub kind: ChangeKind,
+
+    /// The old archive's copy, unless it was added.
+    pub old: Option<ResourceVersion>,
+
+    /// The new archive's copy, unless it was removed.
+    pub new: Option<ResourceVersion>,
+
+    /// How its content changed, if it was modified and content was
+    /// compared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<ContentDiff>,
+}
+
+/// The differences between two archives, as listed by
+/// [`WebArchive::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ArchiveDiff {
+    /// Removed and modified resources, by URL, followed by added
+    /// resources, by URL.
+    pub changes: Vec<ResourceChange>,
+}
+
+impl ArchiveDiff {
+    /// Whether the archives store the same resources.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// How [`WebArchive::diff_content`] compares text.
+#[cfg(feature = "diff")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentDiffOptions {
+    /// The largest resource, in bytes, whose text is compared. Larger
+    /// ones are listed as [`ContentDiff::TooLarge`], so comparing giant
+    /// resources does not use unbounded time and memory.
+    pub max_size: usize,
+
+    /// How many unchanged lines to show around each change.
+    pub context_lines: usize,
+}
+
+#[cfg(feature = "diff")]
+impl Default for ContentDiffOptions {
+    fn default() -> Self {
+        ContentDiffOptions {
+            max_size: 1024 * 1024,
+            context_lines: 3,
+        }
+    }
+}
+
+impl WebArchive {
+    /// List the resources which differ between this archive and `new`,
+    /// at any depth, matching them by URL.
+    ///
+    /// Resources are modified if their data or MIME type differs. If
+    /// an archive stores a URL more than once, the last copy, in
+    /// document order, is compared.
+    pub fn diff(&self, new: &WebArchive) -> ArchiveDiff {
+        diff(self, new, |_, _| None)
+    }
+
+    /// List the resources which differ between this archive and `new`,
+    /// like [`diff`](WebArchive::diff), also working out how the
+    /// content of each modified resource changed.
+    #[cfg(feature = "diff")]
+    pub fn diff_content(&self, new: &WebArchive, options: &ContentDiffOptions) -> ArchiveDiff {
+        diff(self, new, |old, new| Some(content_diff(old, new, options)))
+    }
+}
+
+fn diff(
+    old: &WebArchive,
+    new: &WebArchive,
+    mut content: impl FnMut(&WebResource, &WebResource) -> Option<ContentDiff>,
+) -> ArchiveDiff {
+    let old_resources = by_url(old);
+    let new_resources = by_url(new);
+
+    let mut changes = Vec::new();
+    for (url, old) in &old_resources {
+        let change = match new_resources.get(url) {
+            None => ResourceChange {
+                url: url.to_string(),
+                kind: ChangeKind::Removed,
+                old: Some(ResourceVersion::new(old)),
+                new: None,
+                content: None,
+            },
+            Some(new) if new.mime_type != old.mime_type || new.data != old.data => ResourceChange {
+                url: url.to_string(),
+                kind: ChangeKind::Modified,
+                old: Some(ResourceVersion::new(old)),
+                new: Some(ResourceVersion::new(new)),
+                content: content(old, new),
+            },
+            Some(_) => continue,
+        };
+        changes.push(change);
+    }
+
+    for (url, new) in &new_resources {
+        if !old_resources.contains_key(url) {
+            changes.push(ResourceChange {
+                url: url.to_string(),
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(ResourceVersion::new(new)),
+                content: None,
+            });
+        }
+    }
+
+    ArchiveDiff { changes }
+}
+
+fn by_url(archive: &WebArchive) -> BTreeMap<&str, &WebResource> {
+    archive
+        .iter_resources()
+        .map(|resource| (resource.url.as_str(), resource))
+        .collect()
+}
+
+#[cfg(feature = "diff")]
+fn content_diff(old: &WebResource, new: &WebResource, options: &ContentDiffOptions) -> ContentDiff {
+    if old.data.len() > options.max_size || new.data.len() > options.max_size {
+        return ContentDiff::TooLarge {
+            max_size: options.max_size,
+        };
+    }
+
+    let (Some(old_text), Some(new_text)) = (old.decoded_text(), new.decoded_text()) else {
+        return ContentDiff::Binary;
+    };
+
+    let hunks = similar::TextDiff::from_lines(old_text.as_ref(), new_text.as_ref())
+        .unified_diff()
+        .context_radius(options.context_lines)
+        .iter_hunks()
+        .map(|hunk| hunk.to_string())
+        .collect();
+    ContentDiff::Text { hunks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeKind, ResourceVersion};
+    use crate::WebArchive;
+
+    fn fixtures() -> (WebArchive, WebArchive) {
+        (
+            crate::from_file("fixtures/diff/old.webarchive").unwrap(),
+            crate::from_file("fixtures/diff/new.webarchive").unwrap(),
+        )
+    }
+
+    #[test]
+    fn diff() {
+        let (old, new) = fixtures();
+        let diff = old.diff(&new);
+
+        let changes: Vec<_> = diff
+            .changes
+            .iter()
+            .map(|change| (change.url.as_str(), change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("http://example.com/", ChangeKind::Modified),
+                ("http://example.com/logo.png", ChangeKind::Modified),
+                ("http://example.com/style.css", ChangeKind::Removed),
+                ("http://example.com/print.css", ChangeKind::Added),
+            ]
+        );
+        assert!(diff.changes.iter().all(|change| change.content.is_none()));
+
+        let logo = &diff.changes[1];
+        assert_eq!(
+            logo.old,
+            Some(ResourceVersion {
+                mime_type: "image/png".to_string(),
+                size: 32,
+                sha256: "241bf8c68558ee6c2fa2e43b89919ee47bec95c3a00fe5da1a2dfb914c2f02da"
+                    .to_string(),
+            })
+        );
+        assert_eq!(logo.new.as_ref().unwrap().size, 40);
+        assert_eq!(diff.changes[2].new, None);
+        assert_eq!(diff.changes[3].old, None);
+
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[cfg(feature = "diff")]
+    #[test]
+    fn diff_content() {
+        use super::{ContentDiff, ContentDiffOptions};
+
+        let (old, new) = fixtures();
+        let diff = old.diff_content(&new, &ContentDiffOptions::default());
+
+        // the old page is ISO-8859-1 and the new one UTF-8, but
+        // the café in the title decodes the same from each
+        assert_eq!(
+            diff.changes[0].content,
+            Some(ContentDiff::Text {
+                hunks: vec![concat!(
+                    "@@ -2,14 +2,15 @@\n",
+                    " <html>\n",
+                    " <head>\n",
+                    " <title>Café menu</title>\n",
+                    "-<link rel=\"stylesheet\" href=\"style.css\">\n",
+                    "+<link rel=\"stylesheet\" href=\"print.css\">\n",
+                    " </head>\n",
+                    " <body>\n",
+                    " <h1>Café menu</h1>\n",
+                    " <ul>\n",
+                    " <li>Espresso</li>\n",
+                    "-<li>Latte</li>\n",
+                    "+<li>Flat white</li>\n",
+                    " <li>Mocha</li>\n",
+                    "+<li>Cortado</li>\n",
+                    " </ul>\n",
+                    " <img src=\"logo.png\">\n",
+                    " </body>\n",
+                )
+                .to_string()]
+            })
+        );
+        assert_eq!(diff.changes[1].content, Some(ContentDiff::Binary));
+        assert_eq!(diff.changes[2].content, None);
+        assert_eq!(diff.changes[3].content, None);
+
+        let options = ContentDiffOptions {
+            max_size: 200,
+            context_lines: 0,
+        };
+        let diff = old.diff_content(&new, &options);
+        assert_eq!(
+            diff.changes[0].content,
+            Some(ContentDiff::TooLarge { max_size: 200 })
+        );
+
+        let options = ContentDiffOptions {
+            context_lines: 0,
+            ..Default::default()
+        };
+        let diff = old.diff_content(&new, &options);
+        let Some(ContentDiff::Text { hunks }) = &diff.changes[0].content else {
+            panic!("Main resource was not compared as text");
+        };
+        assert_eq!(
+            hunks[1],
+            "@@ -11 +11 @@\n-<li>Latte</li>\n+<li>Flat white</li>\n"
+        );
+        assert_eq!(hunks.len(), 3);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::WebResource;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fmt;
+use std::str::FromStr;
+
+/// A hash function for checksumming resource data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DigestAlgorithm {
+    /// SHA-256.
+    #[default]
+    Sha256,
+    /// SHA-384.
+    Sha384,
+    /// SHA-512.
+    Sha512,
+    /// SHA-1, for comparing with older tools. Not collision resistant.
+    Sha1,
+    /// MD5, for comparing with older tools. Not collision resistant.
+    Md5,
+}
+
+impl DigestAlgorithm {
+    /// The digest of `data`, as lowercase hexadecimal.
+    pub fn hex_digest(&self, data: &[u8]) -> String {
+        to_hex(&self.digest(data))
+    }
+
+    /// The digest of `data`.
+    pub(crate) fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+            DigestAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+            DigestAlgorithm::Sha1 => sha1::Sha1::digest(data).to_vec(),
+            DigestAlgorithm::Md5 => md5::Md5::digest(data).to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Sha384 => "sha384",
+            DigestAlgorithm::Sha512 => "sha512",
+            DigestAlgorithm::Sha1 => "sha1",
+            DigestAlgorithm::Md5 => "md5",
+        })
+    }
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = String;
+
+    /// Parse an algorithm name, such as `sha256`, ignoring case and dashes.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha256" => Ok(DigestAlgorithm::Sha256),
+            "sha384" => Ok(DigestAlgorithm::Sha384),
+            "sha512" => Ok(DigestAlgorithm::Sha512),
+            "sha1" => Ok(DigestAlgorithm::Sha1),
+            "md5" => Ok(DigestAlgorithm::Md5),
+            _ => Err(format!("unknown digest algorithm {:?}", name)),
+        }
+    }
+}
+
+impl WebResource {
+    /// The digest of the resource's data, as lowercase hexadecimal.
+    pub fn hex_digest(&self, algorithm: DigestAlgorithm) -> String {
+        algorithm.hex_digest(&self.data)
+    }
+}
+
+/// Format bytes, such as a digest, as lowercase hexadecimal.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The SHA-256 digest of `data`, as lowercase hexadecimal.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestAlgorithm;
+
+    #[test]
+    fn hex_digests() {
+        assert_eq!(
+            DigestAlgorithm::Sha256.hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            DigestAlgorithm::Sha1.hex_digest(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            DigestAlgorithm::Md5.hex_digest(b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn parse_algorithms() {
+        assert_eq!("SHA-256".parse(), Ok(DigestAlgorithm::Sha256));
+        assert_eq!("sha384".parse(), Ok(DigestAlgorithm::Sha384));
+        assert_eq!("SHA512".parse(), Ok(DigestAlgorithm::Sha512));
+        assert_eq!("sha1".parse(), Ok(DigestAlgorithm::Sha1));
+        assert_eq!("md5".parse(), Ok(DigestAlgorithm::Md5));
+        assert!("crc32".parse::<DigestAlgorithm>().is_err());
+    }
+}
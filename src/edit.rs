@@ -0,0 +1,531 @@
+use crate::{Error, FrameSelector, Result, UrlNormalization, WebArchive, WebResource};
+
+impl WebArchive {
+    /// Find the resource stored at `url`, at any depth: the main resource,
+    /// a subresource, or a resource within a subframe archive.
+    ///
+    /// A resource stored at exactly `url` is preferred, but failing
+    /// that, URLs are compared in the [default](UrlNormalization::default)
+    /// normal form, so `http://Example.com:80/a#top` finds a resource
+    /// stored at `http://example.com/a`. If more than one resource
+    /// matches, the first in document order is returned.
+    pub fn get_resource_by_url(&self, url: &str) -> Option<&WebResource> {
+        self.get_resource_by_url_with(url, UrlNormalization::default())
+    }
+
+    /// Find the resource stored at `url`, like
+    /// [`get_resource_by_url`](WebArchive::get_resource_by_url), comparing
+    /// URLs as `normalization` says.
+    pub fn get_resource_by_url_with(
+        &self,
+        url: &str,
+        normalization: UrlNormalization,
+    ) -> Option<&WebResource> {
+        let position = self.resource_position(url, normalization)?;
+        self.iter_resources().nth(position)
+    }
+
+    /// Mutable version of [`get_resource_by_url`](WebArchive::get_resource_by_url).
+    pub fn get_resource_by_url_mut(&mut self, url: &str) -> Option<&mut WebResource> {
+        let position = self.resource_position(url, UrlNormalization::default())?;
+        self.nth_resource_mut(&mut { position })
+    }
+
+    /// The position of the resource at `url` in
+    /// [`iter_resources`](WebArchive::iter_resources)
+    fn resource_position(&self, url: &str, normalization: UrlNormalization) -> Option<usize> {
+        self.iter_resources()
+            .position(|resource| resource.url == url)
+            .or_else(|| {
+                let url = normalization.normalize(url);
+                self.iter_resources()
+                    .position(|resource| normalization.normalize(&resource.url) == url)
+            })
+    }
+
+    /// Modify the resource stored at `url`, wherever it is in the archive.
+    ///
+    /// Once `f` has run, the resource is checked for changes which
+    /// would leave the archive inconsistent:
+    ///
+    /// - If its MIME type was changed to a text type, and it has no
+    ///   `text_encoding_name`, the encoding is set to UTF-8.
+    /// - If its URL was changed to one which another resource already
+    ///   has, the URL change is undone, and [`Error::DuplicateUrl`] is
+    ///   returned. Any other changes made by `f` are kept.
+    ///
+    /// Returns `Ok(false)`, without calling `f`, if there is no
+    /// resource at `url`.
+    pub fn update_resource(&mut self, url: &str, f: impl FnOnce(&mut WebResource)) -> Result<bool> {
+        let position = match self
+            .iter_resources()
+            .position(|resource| resource.url == url)
+        {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+
+        let resource = self
+            .nth_resource_mut(&mut { position })
+            .expect("Resource was found by position");
+        let old_mime_type = resource.mime_type.clone();
+
+        f(resource);
+
+        if resource.mime_type != old_mime_type
+            && resource.is_text()
+            && resource.text_encoding_name.is_none()
+        {
+            resource.text_encoding_name = Some("UTF-8".to_string());
+        }
+
+        if resource.url == url {
+            return Ok(true);
+        }
+
+        let new_url = resource.url.clone();
+        let collides = self
+            .iter_resources()
+            .enumerate()
+            .any(|(index, resource)| index != position && resource.url == new_url);
+
+        if collides {
+            let resource = self
+                .nth_resource_mut(&mut { position })
+                .expect("Resource was found by position");
+            resource.url = url.to_string();
+            return Err(Error::DuplicateUrl(new_url));
+        }
+
+        Ok(true)
+    }
+
+    /// Add a resource to the archive's subresources.
+    ///
+    /// If a resource with the same URL is already stored anywhere in
+    /// the archive, and `replace` is set, that resource's data, MIME type
+    /// and encoding are replaced instead, and its archived response is
+    /// removed, as it no longer describes the data. The previous
+    /// resource is returned. If `replace` is not set,
+    /// [`Error::DuplicateUrl`] is returned and nothing changes.
+    pub fn add_subresource(
+        &mut self,
+        resource: WebResource,
+        replace: bool,
+    ) -> Result<Option<WebResource>> {
+        match self.get_resource_by_url_mut(&resource.url) {
+            Some(_) if !replace => Err(Error::DuplicateUrl(resource.url)),
+            Some(existing) => {
+                let frame_name = existing.frame_name.take();
+                let previous = std::mem::replace(existing, resource);
+                existing.frame_name = frame_name.or(existing.frame_name.take());
+                Ok(Some(previous))
+            }
+            None => {
+                self.subresources
+                    .get_or_insert_with(Vec::new)
+                    .push(resource);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the first subresource stored at `url`, in document
+    /// order, at any depth, and return it.
+    ///
+    /// Main resources are never removed; use
+    /// [`take_subframe`](WebArchive::take_subframe) to remove a frame.
+    pub fn remove_subresource(&mut self, url: &str) -> Option<WebResource> {
+        if let Some(subresources) = &mut self.subresources {
+            if let Some(index) = subresources
+                .iter()
+                .position(|subresource| subresource.url == url)
+            {
+                let removed = subresources.remove(index);
+                if subresources.is_empty() {
+                    self.subresources = None;
+                }
+                return Some(removed);
+            }
+        }
+
+        self.subframe_archives
+            .iter_mut()
+            .flatten()
+            .find_map(|archive| archive.remove_subresource(url))
+    }
+
+    /// Remove every subresource, at any depth, for which `f` returns
+    /// `true`, and return them in document order.
+    ///
+    /// Main resources are never passed to `f`, nor removed.
+    pub fn strip(&mut self, mut f: impl FnMut(&WebResource) -> bool) -> Vec<WebResource> {
+        let mut stripped = Vec::new();
+        self.strip_into(&mut f, &mut stripped);
+        stripped
+    }
+
+    fn strip_into(
+        &mut self,
+        f: &mut impl FnMut(&WebResource) -> bool,
+        stripped: &mut Vec<WebResource>,
+    ) {
+        if let Some(subresources) = self.subresources.take() {
+            let (removed, kept): (Vec<WebResource>, Vec<WebResource>) = subresources
+                .into_iter()
+                .partition(|subresource| f(subresource));
+            stripped.extend(removed);
+            self.subresources = Some(kept).filter(|kept| !kept.is_empty());
+        }
+
+        for archive in self.subframe_archives.iter_mut().flatten() {
+            archive.strip_into(f, stripped);
+        }
+    }
+
+    /// Make the resource stored at `url` the archive's main resource.
+    ///
+    /// The resource may be a subresource, at any depth, or the main
+    /// resource of a subframe archive. A subframe is flattened into
+    /// this archive: its subresources and subframe archives are moved
+    /// up, except for subresources whose URLs are already among this
+    /// archive's own subresources.
+    ///
+    /// With `keep_old`, the previous main resource becomes a
+    /// subresource, unless one with its URL is already stored.
+    /// The previous main resource is returned if it was not kept.
+    ///
+    /// Returns [`Error::ResourceNotFound`], leaving the archive
+    /// unchanged, if no resource has the URL. If the main resource
+    /// already has the URL, nothing changes.
+    pub fn set_main_resource(&mut self, url: &str, keep_old: bool) -> Result<Option<WebResource>> {
+        if self.main_resource.url == url {
+            return Ok(None);
+        }
+
+        let mut main_resource = match self.remove_subresource(url) {
+            Some(subresource) => subresource,
+            None => match self.take_subframe(&FrameSelector::Url(url.to_string())) {
+                Some(frame) => self.flatten_frame(frame),
+                None => return Err(Error::ResourceNotFound(url.to_string())),
+            },
+        };
+
+        main_resource.frame_name = None;
+        let mut previous = std::mem::replace(&mut self.main_resource, main_resource);
+        previous.frame_name = None;
+
+        if keep_old && !self.has_own_subresource(&previous.url) {
+            self.subresources
+                .get_or_insert_with(Vec::new)
+                .push(previous);
+            Ok(None)
+        } else {
+            Ok(Some(previous))
+        }
+    }
+
+    /// Move the subresources and subframe archives of `frame` into
+    /// this archive, and return its main resource.
+    fn flatten_frame(&mut self, frame: WebArchive) -> WebResource {
+        for subresource in frame.subresources.into_iter().flatten() {
+            if !self.has_own_subresource(&subresource.url) {
+                self.subresources
+                    .get_or_insert_with(Vec::new)
+                    .push(subresource);
+            }
+        }
+
+        if let Some(subframe_archives) = frame.subframe_archives {
+            self.subframe_archives
+                .get_or_insert_with(Vec::new)
+                .extend(subframe_archives);
+        }
+
+        frame.main_resource
+    }
+
+    /// Whether this archive, not counting its subframe archives,
+    /// has a subresource stored at `url`.
+    pub(crate) fn has_own_subresource(&self, url: &str) -> bool {
+        self.subresources
+            .iter()
+            .flatten()
+            .any(|subresource| subresource.url == url)
+    }
+
+    /// Call `f` with every resource in the archive, at any depth.
+    pub(crate) fn for_each_resource_mut(&mut self, f: &mut impl FnMut(&mut WebResource)) {
+        f(&mut self.main_resource);
+        self.subresources.iter_mut().flatten().for_each(&mut *f);
+
+        for archive in self.subframe_archives.iter_mut().flatten() {
+            archive.for_each_resource_mut(f);
+        }
+    }
+
+    /// Find the resource at position `n` in the order of
+    /// [`iter_resources`](WebArchive::iter_resources).
+    fn nth_resource_mut(&mut self, n: &mut usize) -> Option<&mut WebResource> {
+        if *n == 0 {
+            return Some(&mut self.main_resource);
+        }
+        *n -= 1;
+
+        let subresources = self.subresources.as_deref_mut().unwrap_or_default();
+        if *n < subresources.len() {
+            return Some(&mut subresources[*n]);
+        }
+        *n -= subresources.len();
+
+        self.subframe_archives
+            .iter_mut()
+            .flatten()
+            .find_map(|archive| archive.nth_resource_mut(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{Error, WebArchive};
+
+    fn psxdatacenter() -> WebArchive {
+        crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture")
+    }
+
+    #[test]
+    fn get_resource_by_url() {
+        let mut archive = psxdatacenter();
+
+        let logo = archive
+            .get_resource_by_url("http://psxdatacenter.com/images/logo.jpg")
+            .expect("Resource not found");
+        assert_eq!(logo.mime_type, "image/jpeg");
+
+        let jlist = archive
+            .get_resource_by_url_mut("http://psxdatacenter.com/jlist.html")
+            .expect("Resource not found");
+        assert_eq!(jlist.frame_name.as_deref(), Some("jlist"));
+
+        assert!(archive.get_resource_by_url("about:blank").is_none());
+        assert!(archive.get_resource_by_url_mut("about:blank").is_none());
+    }
+
+    #[test]
+    fn add_subresource() {
+        let mut archive = psxdatacenter();
+
+        let font = resource("http://psxdatacenter.com/font.woff2", "font/woff2", b"wOF2");
+        assert!(archive.add_subresource(font, false).unwrap().is_none());
+        assert_eq!(archive.subresources.as_ref().map(Vec::len), Some(1));
+
+        let logo = || {
+            resource(
+                "http://psxdatacenter.com/images/logo.jpg",
+                "image/gif",
+                b"GIF89a",
+            )
+        };
+        assert!(matches!(
+            archive.add_subresource(logo(), false),
+            Err(Error::DuplicateUrl(url)) if url == "http://psxdatacenter.com/images/logo.jpg"
+        ));
+
+        let previous = archive.add_subresource(logo(), true).unwrap().unwrap();
+        assert_eq!(previous.mime_type, "image/jpeg");
+
+        let replaced = archive
+            .get_resource_by_url("http://psxdatacenter.com/images/logo.jpg")
+            .unwrap();
+        assert_eq!(replaced.data, b"GIF89a");
+        assert!(replaced.response.is_none());
+        assert_eq!(archive.subresources.as_ref().map(Vec::len), Some(1));
+
+        // replacing a frame's main resource keeps it in its frame
+        let mut banner = logo();
+        banner.url = "http://psxdatacenter.com/jlist.html".to_string();
+        archive.add_subresource(banner, true).unwrap();
+        let jlist = archive
+            .get_resource_by_url("http://psxdatacenter.com/jlist.html")
+            .unwrap();
+        assert_eq!(jlist.frame_name.as_deref(), Some("jlist"));
+    }
+
+    #[test]
+    fn remove_subresource() {
+        let mut archive = psxdatacenter();
+        let texgrey = "http://psxdatacenter.com/images/texgrey.jpg";
+        let count = archive.iter_resources().count();
+
+        let removed = archive.remove_subresource(texgrey).unwrap();
+        assert_eq!(removed.data.len(), 107_128);
+        assert_eq!(archive.iter_resources().count(), count - 1);
+
+        // other frames' copies are left alone
+        assert!(archive.get_resource_by_url(texgrey).is_some());
+
+        // as are main resources
+        assert!(archive
+            .remove_subresource("http://psxdatacenter.com/nav.html")
+            .is_none());
+        assert!(archive.remove_subresource("about:blank").is_none());
+    }
+
+    #[test]
+    fn strip() {
+        let mut archive = psxdatacenter();
+        let count = archive.iter_resources().count();
+
+        let stripped = archive.strip(|resource| resource.url.ends_with(".jpg"));
+        assert!(stripped
+            .iter()
+            .all(|resource| resource.mime_type == "image/jpeg"));
+        assert_eq!(archive.iter_resources().count(), count - stripped.len());
+        assert!(archive
+            .iter_resources()
+            .all(|resource| !resource.url.ends_with(".jpg")));
+
+        // the banner frame had only JPEGs
+        let banner = &archive.subframe_archives.as_ref().unwrap()[0];
+        assert!(banner.subresources.is_none());
+
+        assert!(archive.strip(|_| true).len() == 11);
+        assert_eq!(archive.iter_resources().count(), 5);
+    }
+
+    #[test]
+    fn update_resource() {
+        let mut archive = psxdatacenter();
+
+        let updated = archive
+            .update_resource("http://psxdatacenter.com/images/logo.jpg", |resource| {
+                resource.url = "http://psxdatacenter.com/images/logo.txt".to_string();
+                resource.mime_type = "text/plain".to_string();
+            })
+            .unwrap();
+        assert!(updated);
+
+        assert!(archive
+            .get_resource_by_url("http://psxdatacenter.com/images/logo.jpg")
+            .is_none());
+        let logo = archive
+            .get_resource_by_url("http://psxdatacenter.com/images/logo.txt")
+            .expect("Resource not found");
+        assert_eq!(logo.text_encoding_name.as_deref(), Some("UTF-8"));
+
+        let updated = archive
+            .update_resource("about:blank", |_| panic!("Nothing to update"))
+            .unwrap();
+        assert!(!updated);
+    }
+
+    #[test]
+    fn update_resource_duplicate_url() {
+        let mut archive = psxdatacenter();
+
+        let result = archive.update_resource("http://psxdatacenter.com/nav.html", |resource| {
+            resource.url = "http://psxdatacenter.com/banner.html".to_string();
+            resource.data.clear();
+        });
+
+        match result {
+            Err(Error::DuplicateUrl(url)) => {
+                assert_eq!(url, "http://psxdatacenter.com/banner.html")
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let nav = archive
+            .get_resource_by_url("http://psxdatacenter.com/nav.html")
+            .expect("URL change was not undone");
+        assert!(nav.data.is_empty());
+        assert_eq!(
+            archive
+                .iter_resources()
+                .filter(|resource| resource.url == "http://psxdatacenter.com/banner.html")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn set_main_resource_to_frame() {
+        let mut archive = psxdatacenter();
+
+        let previous = archive
+            .set_main_resource("http://psxdatacenter.com/banner.html", true)
+            .unwrap();
+        assert!(previous.is_none());
+
+        assert_eq!(
+            archive.main_resource.url,
+            "http://psxdatacenter.com/banner.html"
+        );
+        assert!(archive.main_resource.frame_name.is_none());
+
+        // the banner's own subresources come first, then the old main resource
+        let urls: Vec<&str> = archive
+            .subresources
+            .iter()
+            .flatten()
+            .map(|subresource| subresource.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "http://psxdatacenter.com/images/texgrey.jpg",
+                "http://psxdatacenter.com/images/logo.jpg",
+                "http://psxdatacenter.com/ntsc-j_list.html",
+            ]
+        );
+
+        // the other frames are untouched
+        let frames: Vec<&str> = archive
+            .subframe_archives
+            .iter()
+            .flatten()
+            .map(|frame| frame.main_resource.url.as_str())
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                "http://psxdatacenter.com/nav.html",
+                "http://psxdatacenter.com/jbanner.html",
+                "http://psxdatacenter.com/jlist.html",
+            ]
+        );
+    }
+
+    #[test]
+    fn set_main_resource_to_subresource() {
+        let mut archive = psxdatacenter();
+        let resources = archive.iter_resources().count();
+
+        let previous = archive
+            .set_main_resource("http://psxdatacenter.com/images/logo.jpg", false)
+            .unwrap()
+            .expect("Previous main resource was kept");
+        assert_eq!(previous.url, "http://psxdatacenter.com/ntsc-j_list.html");
+        assert_eq!(
+            archive.main_resource.url,
+            "http://psxdatacenter.com/images/logo.jpg"
+        );
+        assert_eq!(archive.iter_resources().count(), resources - 1);
+    }
+
+    #[test]
+    fn set_missing_main_resource() {
+        let mut archive = psxdatacenter();
+
+        assert!(matches!(
+            archive.set_main_resource("http://psxdatacenter.com/nope.html", true),
+            Err(Error::ResourceNotFound(_))
+        ));
+        assert_eq!(
+            archive.main_resource.url,
+            "http://psxdatacenter.com/ntsc-j_list.html"
+        );
+    }
+}
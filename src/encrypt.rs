@@ -0,0 +1,147 @@
+//! Reading and writing archives encrypted with [age](https://age-encryption.org).
+
+use crate::{Error, Result, WebArchive};
+use std::io::{Read, Write};
+
+/// The start of every binary age file.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+/// Whether `data` looks like an age-encrypted file, such as one
+/// written by [`to_writer_encrypted`].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
+
+/// Write an archive as a binary property list, encrypted with age so
+/// that any of `recipients` can decrypt it.
+///
+/// ```
+/// # fn main() -> webarchive::Result<()> {
+/// let archive: webarchive::WebArchive = webarchive::from_file("fixtures/crouton.webarchive")?;
+/// let identity = age::x25519::Identity::generate();
+/// let recipient = identity.to_public();
+///
+/// let mut encrypted = Vec::new();
+/// webarchive::to_writer_encrypted(&mut encrypted, &archive, [&recipient as _])?;
+///
+/// let decrypted = webarchive::from_reader_encrypted(&encrypted[..], [&identity as _])?;
+/// assert_eq!(decrypted, archive);
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_writer_encrypted<'a, W: Write>(
+    writer: W,
+    archive: &WebArchive,
+    recipients: impl IntoIterator<Item = &'a dyn age::Recipient>,
+) -> Result<()> {
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.into_iter()).map_err(Error::Encrypt)?;
+
+    let mut writer = encryptor.wrap_output(writer)?;
+    crate::to_writer_binary(&mut writer, archive)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Read an archive written by [`to_writer_encrypted`], or by `age`
+/// itself, decrypting it with any of `identities`.
+///
+/// If none of `identities` can decrypt it, this fails with
+/// [`Error::NoMatchingIdentity`], whereas encrypted data which is
+/// damaged fails with [`Error::Decrypt`], and an archive which is
+/// damaged fails as [`from_bytes`](crate::from_bytes) would.
+pub fn from_reader_encrypted<'a, R: Read>(
+    reader: R,
+    identities: impl IntoIterator<Item = &'a dyn age::Identity>,
+) -> Result<WebArchive> {
+    let decrypt = |error| match error {
+        age::DecryptError::NoMatchingKeys => Error::NoMatchingIdentity,
+        error => Error::Decrypt(error),
+    };
+
+    let decryptor = age::Decryptor::new(reader).map_err(decrypt)?;
+    let mut reader = decryptor.decrypt(identities.into_iter()).map_err(decrypt)?;
+
+    // the payload is authenticated as it is read, so errors here mean it is damaged
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .map_err(|error| Error::Decrypt(age::DecryptError::Io(error)))?;
+
+    Ok(crate::from_bytes(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_reader_encrypted, is_encrypted, to_writer_encrypted};
+    use crate::{Error, WebArchive};
+
+    fn crouton() -> WebArchive {
+        crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture")
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let archive = crouton();
+        let identity = age::x25519::Identity::generate();
+        let other = age::x25519::Identity::generate();
+
+        let mut encrypted = Vec::new();
+        to_writer_encrypted(
+            &mut encrypted,
+            &archive,
+            [&other.to_public() as _, &identity.to_public() as _],
+        )
+        .unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(
+            &std::fs::read("fixtures/crouton.webarchive").unwrap()
+        ));
+
+        for key in [&identity, &other] {
+            let decrypted = from_reader_encrypted(&encrypted[..], [key as _]).unwrap();
+            assert_eq!(decrypted, archive);
+        }
+    }
+
+    #[test]
+    fn encrypt_errors() {
+        let identity = age::x25519::Identity::generate();
+        let mut encrypted = Vec::new();
+        to_writer_encrypted(&mut encrypted, &crouton(), [&identity.to_public() as _]).unwrap();
+
+        let wrong_key = age::x25519::Identity::generate();
+        assert!(matches!(
+            from_reader_encrypted(&encrypted[..], [&wrong_key as _]),
+            Err(Error::NoMatchingIdentity)
+        ));
+
+        // damage the payload, rather than the header naming the recipients
+        let mut damaged = encrypted.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 0xff;
+        assert!(matches!(
+            from_reader_encrypted(&damaged[..], [&identity as _]),
+            Err(Error::Decrypt(_))
+        ));
+
+        assert!(matches!(
+            from_reader_encrypted(&encrypted[..40], [&identity as _]),
+            Err(Error::Decrypt(_))
+        ));
+
+        // an intact envelope around something which is not an archive
+        let mut not_archive = Vec::new();
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(&identity.to_public() as _)).unwrap();
+        let mut writer = encryptor.wrap_output(&mut not_archive).unwrap();
+        std::io::Write::write_all(&mut writer, b"not a property list").unwrap();
+        writer.finish().unwrap();
+        assert!(matches!(
+            from_reader_encrypted(&not_archive[..], [&identity as _]),
+            Err(Error::Plist(_))
+        ));
+    }
+}
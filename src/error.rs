@@ -0,0 +1,220 @@
+use std::fmt;
+
+/// Errors which can occur while working with Web Archives.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The archive could not be read or written as a property list.
+    Plist(plist::Error),
+
+    /// An I/O error occurred while reading or writing data.
+    Io(std::io::Error),
+
+    /// An HTML document could not be parsed.
+    Html(String),
+
+    /// Adding the resource at `url` would make the archive
+    /// `would_be` bytes, more than its maximum total size.
+    BudgetExceeded { url: String, would_be: usize },
+
+    /// Another resource in the archive already has this URL.
+    DuplicateUrl(String),
+
+    /// A resource's archived response data could not be understood.
+    InvalidResponse(String),
+
+    /// An HTML document was needed, but the resource
+    /// has this other MIME type.
+    NotHtml(String),
+
+    /// The archive has no embedded checksum manifest.
+    NoManifest,
+
+    /// The archive's embedded checksum manifest could not be read.
+    InvalidManifest(String),
+
+    /// A size in bytes could not be understood.
+    InvalidSize(String),
+
+    /// No resource in the archive has this URL.
+    ResourceNotFound(String),
+
+    /// A pattern for matching URLs could not be understood.
+    InvalidPattern(String),
+
+    /// A [`FilterExpression`](crate::FilterExpression) could not be
+    /// understood, because of `message` at the byte offset `position`.
+    InvalidFilter { message: String, position: usize },
+
+    /// No subframe archive matches this selector.
+    FrameNotFound(String),
+
+    /// A URL could not be understood.
+    InvalidUrl(String),
+
+    /// Extracted files can not be repacked into an archive, as when a
+    /// frame's main resource was not extracted.
+    IncompleteExtraction(String),
+
+    /// The operation was cancelled by its
+    /// [`OperationHooks`](crate::OperationHooks).
+    Cancelled,
+
+    /// The archive has things Safari would not have written, as
+    /// [`ParseOptions::strict_apple`](crate::ParseOptions::strict_apple)
+    /// forbids.
+    NotSafari(Vec<crate::StrictViolation>),
+
+    /// An HTTP request could not be made.
+    #[cfg(feature = "fetch")]
+    Fetch(reqwest::Error),
+
+    /// Fetching took longer than the deadline set in its
+    /// [`FetchOptions`](crate::FetchOptions).
+    #[cfg(feature = "fetch")]
+    DeadlineExceeded,
+
+    /// The browser capturing a page could not be reached, or failed to
+    /// load it, as by [`WebArchive::render`](crate::WebArchive::render).
+    #[cfg(feature = "cdp")]
+    Browser(String),
+
+    /// An archive could not be encrypted.
+    #[cfg(feature = "encrypt")]
+    Encrypt(age::EncryptError),
+
+    /// An encrypted archive could not be decrypted, as it is damaged
+    /// or not in a form which is understood.
+    #[cfg(feature = "encrypt")]
+    Decrypt(age::DecryptError),
+
+    /// None of the given identities can decrypt an encrypted archive.
+    #[cfg(feature = "encrypt")]
+    NoMatchingIdentity,
+}
+
+/// Shorthand for results whose error type is this crate's [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Plist(error) => write!(f, "invalid property list: {}", error),
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Html(message) => write!(f, "could not parse HTML: {}", message),
+            Error::BudgetExceeded { url, would_be } => write!(
+                f,
+                "adding {:?} would exceed the size budget ({} bytes)",
+                url, would_be
+            ),
+            Error::DuplicateUrl(url) => {
+                write!(f, "a resource with the URL {:?} already exists", url)
+            }
+            Error::InvalidResponse(message) => write!(f, "invalid archived response: {}", message),
+            Error::NotHtml(mime_type) => write!(f, "expected HTML, not {:?}", mime_type),
+            Error::NoManifest => write!(f, "no checksum manifest is embedded"),
+            Error::InvalidManifest(message) => write!(f, "invalid checksum manifest: {}", message),
+            Error::InvalidSize(size) => write!(f, "invalid size {:?}", size),
+            Error::ResourceNotFound(url) => {
+                write!(f, "no resource with the URL {:?} exists", url)
+            }
+            Error::InvalidPattern(message) => write!(f, "invalid URL pattern: {}", message),
+            Error::InvalidFilter { message, position } => {
+                write!(f, "invalid filter at position {}: {}", position, message)
+            }
+            Error::FrameNotFound(selector) => write!(f, "{} not found", selector),
+            Error::InvalidUrl(message) => write!(f, "invalid URL: {}", message),
+            Error::IncompleteExtraction(message) => {
+                write!(f, "incomplete extraction: {}", message)
+            }
+            Error::Cancelled => write!(f, "the operation was cancelled"),
+            Error::NotSafari(violations) => {
+                write!(f, "Safari would not write this archive: ")?;
+                match violations.as_slice() {
+                    [] => write!(f, "it breaks a strict rule"),
+                    [violation] => write!(f, "{}", violation),
+                    [violation, rest @ ..] => {
+                        write!(f, "{} (and {} more)", violation, rest.len())
+                    }
+                }
+            }
+            #[cfg(feature = "fetch")]
+            Error::Fetch(error) => write!(f, "HTTP request failed: {}", error),
+            #[cfg(feature = "fetch")]
+            Error::DeadlineExceeded => write!(f, "the deadline for fetching passed"),
+            #[cfg(feature = "cdp")]
+            Error::Browser(message) => write!(f, "browser error: {}", message),
+            #[cfg(feature = "encrypt")]
+            Error::Encrypt(error) => write!(f, "could not encrypt: {}", error),
+            #[cfg(feature = "encrypt")]
+            Error::Decrypt(error) => write!(f, "could not decrypt: {}", error),
+            #[cfg(feature = "encrypt")]
+            Error::NoMatchingIdentity => {
+                write!(f, "none of the given identities can decrypt this archive")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Plist(error) => Some(error),
+            Error::Io(error) => Some(error),
+            #[cfg(feature = "fetch")]
+            Error::Fetch(error) => Some(error),
+            #[cfg(feature = "fetch")]
+            Error::DeadlineExceeded => None,
+            #[cfg(feature = "cdp")]
+            Error::Browser(_) => None,
+            #[cfg(feature = "encrypt")]
+            Error::Encrypt(error) => Some(error),
+            #[cfg(feature = "encrypt")]
+            Error::Decrypt(error) => Some(error),
+            #[cfg(feature = "encrypt")]
+            Error::NoMatchingIdentity => None,
+            Error::Html(_)
+            | Error::BudgetExceeded { .. }
+            | Error::DuplicateUrl(_)
+            | Error::InvalidResponse(_)
+            | Error::NotHtml(_)
+            | Error::NoManifest
+            | Error::InvalidManifest(_)
+            | Error::InvalidSize(_)
+            | Error::ResourceNotFound(_)
+            | Error::InvalidPattern(_)
+            | Error::InvalidFilter { .. }
+            | Error::FrameNotFound(_)
+            | Error::InvalidUrl(_)
+            | Error::IncompleteExtraction(_)
+            | Error::Cancelled
+            | Error::NotSafari(_) => None,
+        }
+    }
+}
+
+impl From<plist::Error> for Error {
+    fn from(error: plist::Error) -> Self {
+        Error::Plist(error)
+    }
+}
+
+#[cfg(feature = "fetch")]
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::Fetch(error)
+    }
+}
+
+#[cfg(feature = "cdp")]
+impl From<chromiumoxide::error::CdpError> for Error {
+    fn from(error: chromiumoxide::error::CdpError) -> Self {
+        Error::Browser(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
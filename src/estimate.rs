@@ -0,0 +1,350 @@
+//! A model of the binary property list writer, used to predict the size
+//! of a serialised archive without serialising it.
+//!
+//! A binary plist is an 8 byte header, followed by every object in the
+//! plist, an offset table pointing at each object, and a 32 byte trailer.
+//! Strings and data are deduplicated by the writer, so identical values
+//! (such as every dictionary key, or an image shared by several frames)
+//! are stored only once. Dictionaries and arrays are never deduplicated,
+//! and are stored as a list of references to other objects, each of which
+//! is sized to fit the total number of objects.
+
+use crate::{WebArchive, WebResource};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+const HEADER_SIZE: usize = 8;
+const TRAILER_SIZE: usize = 32;
+
+/// Identifies a data value by its length and a hash of its contents,
+/// so the estimate need not hold on to the data itself.
+type DataKey = (usize, u64);
+
+fn data_key(data: &[u8]) -> DataKey {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    (data.len(), hasher.finish())
+}
+
+/// The size of the marker, and length if needed, written before a
+/// string, data, array or dictionary of `len` elements.
+fn marker_size(len: usize) -> usize {
+    match len {
+        0..=0x0e => 1,
+        0x0f..=0xff => 3,
+        0x100..=0xffff => 4,
+        0x1_0000..=0xffff_ffff => 6,
+        _ => 10,
+    }
+}
+
+/// The number of bytes used for a reference to, or offset of, `max_value`.
+fn reference_size(max_value: usize) -> usize {
+    let significant_bits = usize::BITS - max_value.leading_zeros();
+    let significant_bytes = significant_bits.div_ceil(8);
+    significant_bytes.max(1).next_power_of_two() as usize
+}
+
+fn string_size(string: &str) -> usize {
+    if string.is_ascii() {
+        marker_size(string.len()) + string.len()
+    } else {
+        let utf16_len = string.encode_utf16().count();
+        marker_size(utf16_len) + utf16_len * 2
+    }
+}
+
+/// Objects which would be added to the plist, not yet part of the estimate.
+#[derive(Default)]
+pub(crate) struct Delta {
+    strings: Vec<String>,
+    data: Vec<DataKey>,
+    value_bytes: usize,
+    collections: usize,
+    collection_bytes: usize,
+    references: usize,
+}
+
+/// A running estimate of the size of a binary plist.
+#[derive(Default)]
+pub(crate) struct SizeEstimate {
+    strings: HashSet<String>,
+    data: HashSet<DataKey>,
+    value_bytes: usize,
+    collections: usize,
+    collection_bytes: usize,
+    references: usize,
+}
+
+impl SizeEstimate {
+    pub(crate) fn of_archive(archive: &WebArchive) -> Self {
+        let mut estimate = SizeEstimate::default();
+        let delta = estimate.archive_delta(archive);
+        estimate.apply(delta);
+        estimate
+    }
+
+    /// The size of the plist, with any extra collections of
+    /// the given lengths, and objects from `delta`, included.
+    pub(crate) fn total_with(&self, delta: &Delta, extra_collections: &[(usize, usize)]) -> usize {
+        let objects = self.strings.len()
+            + self.data.len()
+            + self.collections
+            + delta.strings.len()
+            + delta.data.len()
+            + delta.collections
+            + extra_collections.len();
+
+        let references = self.references
+            + delta.references
+            + extra_collections
+                .iter()
+                .map(|(_, references)| references)
+                .sum::<usize>();
+
+        let collection_bytes = self.collection_bytes
+            + delta.collection_bytes
+            + extra_collections
+                .iter()
+                .map(|(len, _)| marker_size(*len))
+                .sum::<usize>();
+
+        let objects_end = HEADER_SIZE
+            + self.value_bytes
+            + delta.value_bytes
+            + collection_bytes
+            + references * reference_size(objects.saturating_sub(1));
+
+        objects_end + objects * reference_size(objects_end) + TRAILER_SIZE
+    }
+
+    pub(crate) fn total(&self) -> usize {
+        self.total_with(&Delta::default(), &[])
+    }
+
+    pub(crate) fn apply(&mut self, delta: Delta) {
+        self.strings.extend(delta.strings);
+        self.data.extend(delta.data);
+        self.value_bytes += delta.value_bytes;
+        self.collections += delta.collections;
+        self.collection_bytes += delta.collection_bytes;
+        self.references += delta.references;
+    }
+
+    pub(crate) fn string_delta(&self, delta: &mut Delta, string: &str) {
+        if !self.strings.contains(string) && !delta.strings.iter().any(|s| s == string) {
+            delta.value_bytes += string_size(string);
+            delta.strings.push(string.to_string());
+        }
+    }
+
+    fn data_delta(&self, delta: &mut Delta, data: &[u8]) {
+        let key = data_key(data);
+
+        if !self.data.contains(&key) && !delta.data.contains(&key) {
+            delta.value_bytes += marker_size(data.len()) + data.len();
+            delta.data.push(key);
+        }
+    }
+
+    fn collection_delta(delta: &mut Delta, len: usize, references: usize) {
+        delta.collections += 1;
+        delta.collection_bytes += marker_size(len);
+        delta.references += references;
+    }
+
+    /// The objects a resource's dictionary would add.
+    pub(crate) fn resource_delta(&self, resource: &WebResource) -> Delta {
+        let mut delta = Delta::default();
+        self.add_resource(&mut delta, resource);
+        delta
+    }
+
+    /// The objects an archive's dictionary would add.
+    pub(crate) fn archive_delta(&self, archive: &WebArchive) -> Delta {
+        let mut delta = Delta::default();
+        self.add_archive(&mut delta, archive);
+        delta
+    }
+
+    /// The objects adding `resource` to `frame`'s subresources would
+    /// add, along with the growth of its list and dictionary.
+    pub(crate) fn frame_subresource_delta(
+        &self,
+        frame: &WebArchive,
+        resource: &WebResource,
+    ) -> Delta {
+        let mut delta = self.resource_delta(resource);
+
+        match &frame.subresources {
+            Some(subresources) => {
+                delta.collection_bytes +=
+                    marker_size(subresources.len() + 1) - marker_size(subresources.len());
+                delta.references += 1;
+            }
+            None => {
+                self.string_delta(&mut delta, "WebSubresources");
+                Self::collection_delta(&mut delta, 1, 1);
+
+                let entries = 1 + usize::from(frame.subframe_archives.is_some());
+                delta.collection_bytes += marker_size(entries + 1) - marker_size(entries);
+                delta.references += 2;
+            }
+        }
+
+        delta
+    }
+
+    fn add_resource(&self, delta: &mut Delta, resource: &WebResource) {
+        let mut entries = 0;
+
+        let mut string_entry = |delta: &mut Delta, key: &str, value: &str| {
+            self.string_delta(delta, key);
+            self.string_delta(delta, value);
+            entries += 1;
+        };
+
+        string_entry(delta, "WebResourceURL", &resource.url);
+        string_entry(delta, "WebResourceMIMEType", &resource.mime_type);
+        if let Some(frame_name) = &resource.frame_name {
+            string_entry(delta, "WebResourceFrameName", frame_name);
+        }
+        if let Some(text_encoding_name) = &resource.text_encoding_name {
+            string_entry(delta, "WebResourceTextEncodingName", text_encoding_name);
+        }
+
+        self.string_delta(delta, "WebResourceData");
+        self.data_delta(delta, &resource.data);
+        entries += 1;
+
+        if let Some(response) = &resource.response {
+            self.string_delta(delta, "WebResourceResponse");
+            self.data_delta(delta, response);
+            entries += 1;
+        }
+
+        Self::collection_delta(delta, entries, entries * 2);
+    }
+
+    fn add_archive(&self, delta: &mut Delta, archive: &WebArchive) {
+        let mut entries = 1;
+
+        self.string_delta(delta, "WebMainResource");
+        self.add_resource(delta, &archive.main_resource);
+
+        if let Some(subresources) = &archive.subresources {
+            self.string_delta(delta, "WebSubresources");
+            Self::collection_delta(delta, subresources.len(), subresources.len());
+            for subresource in subresources {
+                self.add_resource(delta, subresource);
+            }
+            entries += 1;
+        }
+
+        if let Some(subframe_archives) = &archive.subframe_archives {
+            self.string_delta(delta, "WebSubframeArchives");
+            Self::collection_delta(delta, subframe_archives.len(), subframe_archives.len());
+            for subframe_archive in subframe_archives {
+                self.add_archive(delta, subframe_archive);
+            }
+            entries += 1;
+        }
+
+        Self::collection_delta(delta, entries, entries * 2);
+    }
+}
+
+impl WebArchive {
+    /// Predict the size in bytes of this archive once serialised as a
+    /// binary property list, without serialising it.
+    ///
+    /// This models the layout produced by [`to_writer_binary`](crate::to_writer_binary),
+    /// including its deduplication of identical strings and data, such
+    /// as resources stored in more than one frame.
+    pub fn estimated_binary_size(&self) -> usize {
+        SizeEstimate::of_archive(self).total()
+    }
+
+    /// Whether this archive, serialised as a binary property list,
+    /// is expected to be no larger than `bytes`.
+    ///
+    /// See [`estimated_binary_size`](WebArchive::estimated_binary_size).
+    pub fn fits_within(&self, bytes: usize) -> bool {
+        self.estimated_binary_size() <= bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reference_size;
+    use crate::test_support::{resource, text_resource};
+    use crate::{WebArchive, WebResource};
+
+    fn assert_estimate_close(archive: &WebArchive) {
+        let actual = archive
+            .to_vec_binary()
+            .expect("Could not serialise archive")
+            .len();
+        let estimate = archive.estimated_binary_size();
+
+        assert!(
+            estimate.abs_diff(actual) <= actual / 1000 + 16,
+            "estimated {} bytes, but serialised to {} bytes",
+            estimate,
+            actual
+        );
+    }
+
+    #[test]
+    fn reference_sizes() {
+        assert_eq!(reference_size(0), 1);
+        assert_eq!(reference_size(255), 1);
+        assert_eq!(reference_size(256), 2);
+        assert_eq!(reference_size(65_535), 2);
+        assert_eq!(reference_size(65_536), 4);
+        assert_eq!(reference_size(1 << 32), 8);
+    }
+
+    #[test]
+    fn estimate_fixtures() {
+        for fixture in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+        ] {
+            let archive: WebArchive =
+                crate::from_file(fixture).expect("Could not read webarchive fixture");
+            assert_estimate_close(&archive);
+        }
+    }
+
+    #[test]
+    fn estimate_many_resources() {
+        // enough objects to need two byte references, with plenty of
+        // duplicated and non-ASCII values, and long arrays
+        let subresources = (0..400)
+            .map(|index| WebResource {
+                text_encoding_name: (index % 2 == 0).then(|| "UTF-8".to_string()),
+                response: (index % 3 == 0).then(|| vec![0; index]),
+                ..resource(
+                    &format!("https://example.com/ünïcode/{}.txt", index),
+                    "text/plain",
+                    vec![b'x'; index % 20 * 300],
+                )
+            })
+            .collect();
+
+        let archive = WebArchive {
+            main_resource: WebResource {
+                frame_name: Some(String::new()),
+                ..text_resource("https://example.com/", "text/html", b"<html></html>")
+            },
+            subresources: Some(subresources),
+            subframe_archives: Some(Vec::new()),
+        };
+
+        assert_estimate_close(&archive);
+        assert!(archive.fits_within(archive.estimated_binary_size()));
+        assert!(!archive.fits_within(archive.estimated_binary_size() - 1));
+    }
+}
@@ -0,0 +1,708 @@
+use crate::filter::mime_matches;
+use crate::{glob_matches, parse_size, Error, ResourceFilter, Result, WebResource};
+use std::fmt;
+
+/// The fields a filter expression can test.
+const FIELDS: &str = "url, mime, size, encoding, frame, has_response";
+
+/// A condition on resources, combining tests of their fields with
+/// `and`, `or`, `not` and parentheses, as in
+/// `mime~^image/ and size>100k and url!~cdn\.example`.
+///
+/// The fields are:
+///
+/// - `url`, where `=` matches a wildcard pattern, as in [`glob_matches`]
+/// - `mime`, the MIME type without parameters, where `=` matches a
+///   pattern such as `image/png` or `image/*`
+/// - `encoding`, the text encoding name, compared ignoring case, and
+///   empty for resources without one
+/// - `size`, the number of bytes of data, compared with `=`, `!=`,
+///   `<`, `<=`, `>` or `>=` to a size such as `512`, `10k` or `1.5M`,
+///   as [`parse_size`] reads
+/// - `frame`, which holds for the main resources of frames
+/// - `has_response`, which holds for resources with an archived
+///   response
+///
+/// `~` tests the text fields against a regular expression, which
+/// matches anywhere unless anchored, and `!=` and `!~` hold where `=`
+/// and `~` don't. `frame` and `has_response` stand alone, as in
+/// `not has_response`.
+///
+/// `not` binds tightest, then `and`, then `or`. Values run until a
+/// space, or a `)` which doesn't close a `(` within them, and can be
+/// quoted with `'` or `"` to include either, with `\` before a quote
+/// to include it.
+///
+/// ```rust
+/// # use anyhow::Result;
+/// use webarchive::{FilterExpression, WebArchive};
+///
+/// # fn main() -> Result<()> {
+/// let archive: WebArchive = webarchive::from_file("fixtures/psxdatacenter.webarchive")?;
+///
+/// let expression = FilterExpression::parse("mime=image/gif and (size<1450 or url~news)")?;
+/// let matched = archive
+///     .iter_resources()
+///     .filter(|resource| expression.matches(resource, false));
+/// assert_eq!(matched.count(), 4);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FilterExpression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Test(Test),
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Url(TextTest),
+    Mime(TextTest),
+    Encoding(TextTest),
+    Size(Comparison, usize),
+    Frame,
+    HasResponse,
+}
+
+#[derive(Debug, Clone)]
+enum TextTest {
+    Equals(String),
+    Regex(regex::Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Compare(Comparison),
+    Matches,
+    NotMatches,
+}
+
+impl Operator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Operator::Compare(Comparison::Equal) => "=",
+            Operator::Compare(Comparison::NotEqual) => "!=",
+            Operator::Compare(Comparison::Less) => "<",
+            Operator::Compare(Comparison::LessOrEqual) => "<=",
+            Operator::Compare(Comparison::Greater) => ">",
+            Operator::Compare(Comparison::GreaterOrEqual) => ">=",
+            Operator::Matches => "~",
+            Operator::NotMatches => "!~",
+        }
+    }
+}
+
+impl FilterExpression {
+    /// Parse a filter expression.
+    ///
+    /// Fails with [`Error::InvalidFilter`] giving where in `source`
+    /// the problem is, such as an unknown field, an operator the field
+    /// can't be compared with, or an invalid size or regular
+    /// expression.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            source,
+            position: 0,
+        };
+        let root = parser.parse_or()?;
+
+        parser.skip_whitespace();
+        match parser.rest().chars().next() {
+            None => Ok(FilterExpression {
+                source: source.to_string(),
+                root,
+            }),
+            Some(')') => Err(parser.error(parser.position, "unmatched `)`")),
+            Some(_) => Err(parser.error(
+                parser.position,
+                format!("expected `and` or `or`, not `{}`", parser.next_word()),
+            )),
+        }
+    }
+
+    /// Whether the expression holds for `resource`, where
+    /// `is_main_resource` says whether it is the main resource of its
+    /// frame.
+    pub fn matches(&self, resource: &WebResource, is_main_resource: bool) -> bool {
+        self.root.matches(resource, is_main_resource)
+    }
+}
+
+impl Node {
+    fn matches(&self, resource: &WebResource, is_main_resource: bool) -> bool {
+        match self {
+            Node::And(left, right) => {
+                left.matches(resource, is_main_resource)
+                    && right.matches(resource, is_main_resource)
+            }
+            Node::Or(left, right) => {
+                left.matches(resource, is_main_resource)
+                    || right.matches(resource, is_main_resource)
+            }
+            Node::Not(node) => !node.matches(resource, is_main_resource),
+            Node::Test(Test::Url(TextTest::Equals(pattern))) => {
+                glob_matches(pattern, &resource.url)
+            }
+            Node::Test(Test::Url(TextTest::Regex(regex))) => regex.is_match(&resource.url),
+            Node::Test(Test::Mime(TextTest::Equals(pattern))) => {
+                mime_matches(pattern, &resource.mime_essence())
+            }
+            Node::Test(Test::Mime(TextTest::Regex(regex))) => {
+                regex.is_match(&resource.mime_essence())
+            }
+            Node::Test(Test::Encoding(test)) => {
+                let encoding = resource.text_encoding_name.as_deref().unwrap_or_default();
+                match test {
+                    TextTest::Equals(name) => name.eq_ignore_ascii_case(encoding),
+                    TextTest::Regex(regex) => regex.is_match(encoding),
+                }
+            }
+            Node::Test(Test::Size(comparison, size)) => {
+                let actual = resource.data.len();
+                match comparison {
+                    Comparison::Equal => actual == *size,
+                    Comparison::NotEqual => actual != *size,
+                    Comparison::Less => actual < *size,
+                    Comparison::LessOrEqual => actual <= *size,
+                    Comparison::Greater => actual > *size,
+                    Comparison::GreaterOrEqual => actual >= *size,
+                }
+            }
+            Node::Test(Test::Frame) => is_main_resource,
+            Node::Test(Test::HasResponse) => resource.response.is_some(),
+        }
+    }
+}
+
+/// Expressions are the same if they were parsed from the same text.
+impl PartialEq for FilterExpression {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for FilterExpression {}
+
+/// Shows the text the expression was parsed from.
+impl fmt::Display for FilterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl ResourceFilter {
+    /// A filter matching the resources for which a
+    /// [`FilterExpression`] holds, such as
+    /// `mime~^image/ and size>100k`.
+    ///
+    /// ```rust
+    /// # use anyhow::Result;
+    /// use webarchive::{ResourceFilter, WebArchive};
+    ///
+    /// # fn main() -> Result<()> {
+    /// let archive: WebArchive = webarchive::from_file("fixtures/psxdatacenter.webarchive")?;
+    ///
+    /// let filter = ResourceFilter::parse("mime=image/jpeg and not url~/buttons/")?;
+    /// assert_eq!(archive.filter_resources(&filter).count(), 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(expression: &str) -> Result<Self> {
+        Ok(ResourceFilter {
+            expression: Some(FilterExpression::parse(expression)?),
+            ..Default::default()
+        })
+    }
+}
+
+/// A recursive descent parser over the text of an expression
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn error(&self, position: usize, message: impl Into<String>) -> Error {
+        Error::InvalidFilter {
+            message: message.into(),
+            position,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// The word at the current position, for error messages
+    fn next_word(&self) -> &'a str {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .unwrap_or(rest.len())
+            .max(rest.chars().next().map_or(0, char::len_utf8));
+        &rest[..end]
+    }
+
+    /// Move past `keyword`, in any case, if it is the next word
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let is_keyword = rest
+            .get(..keyword.len())
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+            && !rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if is_keyword {
+            self.position += keyword.len();
+        }
+        is_keyword
+    }
+
+    fn parse_or(&mut self) -> Result<Node> {
+        let mut node = self.parse_and()?;
+        while self.eat_keyword("or") {
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node> {
+        let mut node = self.parse_not()?;
+        while self.eat_keyword("and") {
+            node = Node::And(Box::new(node), Box::new(self.parse_not()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_not(&mut self) -> Result<Node> {
+        if self.eat_keyword("not") {
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        if self.rest().starts_with('(') {
+            self.position += 1;
+            let node = self.parse_or()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with(')') {
+                return Err(self.error(start, "unclosed `(`"));
+            }
+            self.position += 1;
+            return Ok(node);
+        }
+
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Err(match rest.chars().next() {
+                None => self.error(start, "expected a condition"),
+                Some(_) => self.error(
+                    start,
+                    format!("expected a field, not `{}`", self.next_word()),
+                ),
+            });
+        }
+        let field = &rest[..length];
+        self.position += length;
+
+        let field = field.to_ascii_lowercase();
+        let node = match field.as_str() {
+            "frame" => self.parse_boolean(&field, Test::Frame)?,
+            "has_response" => self.parse_boolean(&field, Test::HasResponse)?,
+            "url" => self.parse_text_test(&field, Test::Url)?,
+            "mime" => self.parse_text_test(&field, Test::Mime)?,
+            "encoding" => self.parse_text_test(&field, Test::Encoding)?,
+            "size" => {
+                let (position, operator) = self.parse_operator(&field)?;
+                let Operator::Compare(comparison) = operator else {
+                    return Err(self.error(
+                        position,
+                        format!("`size` can't be compared with `{}`", operator.as_str()),
+                    ));
+                };
+                let (position, value) = self.parse_value()?;
+                let size = parse_size(&value)
+                    .map_err(|_| self.error(position, format!("invalid size {:?}", value)))?;
+                Node::Test(Test::Size(comparison, size))
+            }
+            _ => {
+                return Err(self.error(
+                    start,
+                    format!("unknown field `{}`; expected one of {}", field, FIELDS),
+                ))
+            }
+        };
+
+        Ok(node)
+    }
+
+    /// A field which stands alone, without an operator
+    fn parse_boolean(&mut self, field: &str, test: Test) -> Result<Node> {
+        let after = self.position;
+        if self.parse_operator(field).is_ok() {
+            return Err(self.error(
+                after,
+                format!(
+                    "`{}` takes no value; use `not {}` for the opposite",
+                    field, field
+                ),
+            ));
+        }
+        self.position = after;
+        Ok(Node::Test(test))
+    }
+
+    /// A test of a text field, wrapped in a `not` for `!=` and `!~`
+    fn parse_text_test(&mut self, field: &str, test: fn(TextTest) -> Test) -> Result<Node> {
+        let (position, operator) = self.parse_operator(field)?;
+        let (value_position, value) = self.parse_value()?;
+
+        let text_test = match operator {
+            Operator::Compare(Comparison::Equal | Comparison::NotEqual) => TextTest::Equals(value),
+            Operator::Matches | Operator::NotMatches => {
+                let regex = regex::Regex::new(&value).map_err(|error| {
+                    self.error(
+                        value_position,
+                        format!("invalid regular expression: {}", error),
+                    )
+                })?;
+                TextTest::Regex(regex)
+            }
+            Operator::Compare(_) => {
+                return Err(self.error(
+                    position,
+                    format!("`{}` can't be compared with `{}`", field, operator.as_str()),
+                ))
+            }
+        };
+
+        let node = Node::Test(test(text_test));
+        match operator {
+            Operator::Compare(Comparison::NotEqual) | Operator::NotMatches => {
+                Ok(Node::Not(Box::new(node)))
+            }
+            _ => Ok(node),
+        }
+    }
+
+    /// The operator after `field`, and where it is
+    fn parse_operator(&mut self, field: &str) -> Result<(usize, Operator)> {
+        self.skip_whitespace();
+        let position = self.position;
+        let rest = self.rest();
+
+        let (length, operator) = [
+            ("!=", Operator::Compare(Comparison::NotEqual)),
+            ("!~", Operator::NotMatches),
+            ("<=", Operator::Compare(Comparison::LessOrEqual)),
+            (">=", Operator::Compare(Comparison::GreaterOrEqual)),
+            ("=", Operator::Compare(Comparison::Equal)),
+            ("<", Operator::Compare(Comparison::Less)),
+            (">", Operator::Compare(Comparison::Greater)),
+            ("~", Operator::Matches),
+        ]
+        .into_iter()
+        .find(|(symbol, _)| rest.starts_with(symbol))
+        .map(|(symbol, operator)| (symbol.len(), operator))
+        .ok_or_else(|| self.error(position, format!("expected an operator after `{}`", field)))?;
+
+        self.position += length;
+        Ok((position, operator))
+    }
+
+    /// A quoted or bare value, and where it starts
+    fn parse_value(&mut self) -> Result<(usize, String)> {
+        self.skip_whitespace();
+        let start = self.position;
+        let rest = self.rest();
+
+        match rest.chars().next() {
+            None => Err(self.error(start, "expected a value")),
+            Some(quote @ ('"' | '\'')) => {
+                let mut value = String::new();
+                let mut chars = rest.char_indices().skip(1);
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' if rest[index + 1..].starts_with(quote) => {
+                            value.push(quote);
+                            chars.next();
+                        }
+                        c if c == quote => {
+                            self.position += index + 1;
+                            return Ok((start, value));
+                        }
+                        c => value.push(c),
+                    }
+                }
+                Err(self.error(start, format!("unclosed `{}`", quote)))
+            }
+            Some(_) => {
+                let mut depth = 0usize;
+                let end = rest
+                    .char_indices()
+                    .find(|&(_, c)| match c {
+                        c if c.is_whitespace() => true,
+                        '(' => {
+                            depth += 1;
+                            false
+                        }
+                        ')' if depth == 0 => true,
+                        ')' => {
+                            depth -= 1;
+                            false
+                        }
+                        _ => false,
+                    })
+                    .map_or(rest.len(), |(index, _)| index);
+                if end == 0 {
+                    return Err(self.error(start, "expected a value"));
+                }
+                self.position += end;
+                Ok((start, rest[..end].to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilterExpression;
+    use crate::test_support::{resource, text_resource};
+    use crate::{Error, ResourceFilter, WebArchive, WebResource};
+
+    fn matches(expression: &str, resource: &WebResource) -> bool {
+        FilterExpression::parse(expression)
+            .unwrap_or_else(|error| panic!("{:?} did not parse: {}", expression, error))
+            .matches(resource, false)
+    }
+
+    /// The message and position of the error parsing `expression`
+    fn error(expression: &str) -> (String, usize) {
+        match FilterExpression::parse(expression) {
+            Err(Error::InvalidFilter { message, position }) => (message, position),
+            other => panic!("Unexpected result {:?} for {:?}", other, expression),
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let image = resource(
+            "https://cdn.example/images/photo.png?size=2",
+            "image/PNG",
+            vec![b'a'; 150 * 1024],
+        );
+        let page = text_resource(
+            "https://example.com/",
+            "text/html; charset=utf-8",
+            vec![b'a'; 500],
+        );
+
+        assert!(matches("url=https://cdn.example/*", &image));
+        assert!(!matches("url=*.png", &image));
+        assert!(matches("url~\\.png", &image));
+        assert!(matches("url!~^http:", &image));
+        assert!(matches("url!=https://example.com/", &image));
+
+        assert!(matches("mime=image/png", &image));
+        assert!(matches("mime=image/*", &image));
+        assert!(matches("mime~^image/", &image));
+        assert!(matches("mime=text/html", &page));
+        assert!(!matches("mime~charset", &page));
+
+        assert!(matches("encoding=utf-8", &page));
+        assert!(matches("encoding=''", &image));
+        assert!(matches("encoding!=''", &page));
+        assert!(matches("encoding~^UTF", &page));
+
+        assert!(matches("size>100k", &image));
+        assert!(matches("size>=150KiB", &image));
+        assert!(matches("size<=150k", &image));
+        assert!(!matches("size<150k", &image));
+        assert!(matches("size=500", &page));
+        assert!(matches("size!=501", &page));
+
+        let mut answered = resource("https://example.com/", "text/html", vec![b'a'; 500]);
+        answered.response = Some(Vec::new());
+        assert!(matches("has_response", &answered));
+        assert!(!matches("has_response", &page));
+        assert!(matches("not has_response", &page));
+
+        let frame = FilterExpression::parse("frame").unwrap();
+        assert!(frame.matches(&page, true));
+        assert!(!frame.matches(&page, false));
+    }
+
+    #[test]
+    fn precedence() {
+        let image = resource("https://example.com/a.png", "image/png", vec![b'a'; 10]);
+
+        // `and` binds tighter than `or`
+        assert!(matches(
+            "mime=image/png or mime=text/css and size>1k",
+            &image
+        ));
+        assert!(!matches(
+            "(mime=image/png or mime=text/css) and size>1k",
+            &image
+        ));
+        assert!(matches("size>1k and mime=text/css or url~png", &image));
+
+        // `not` binds tighter than `and`
+        assert!(!matches("not size<1k and mime=image/png", &image));
+        assert!(matches("not (size>1k and mime=image/png)", &image));
+        assert!(matches("not not mime=image/png", &image));
+
+        // keywords in any case, and with or without spaces around parentheses
+        assert!(matches("NOT(size>1k)AND(url~png)", &image));
+        assert!(matches("((( mime=image/png )))", &image));
+    }
+
+    #[test]
+    fn values() {
+        let page = resource(
+            "https://example.com/a b(1).html",
+            "text/html",
+            vec![b'a'; 10],
+        );
+
+        assert!(matches("url~'a b'", &page));
+        assert!(matches("url~\"a b\\(1\\)\"", &page));
+        assert!(matches("url~'it\\'s' or url~html", &page));
+        assert!(matches("(url~(1) and mime=text/html)", &page));
+        assert!(matches("url~(x|1)", &page));
+
+        // backslashes not before a quote are kept for the regular expression
+        assert!(matches("url~'\\.html$'", &page));
+        assert!(!matches("url~'\\.htm$'", &page));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(""), ("expected a condition".to_string(), 0));
+        assert_eq!(error("  "), ("expected a condition".to_string(), 2));
+        assert_eq!(
+            error("size>1k and colour=red"),
+            (
+                "unknown field `colour`; expected one of url, mime, size, encoding, frame, has_response"
+                    .to_string(),
+                12
+            )
+        );
+        assert_eq!(
+            error("url"),
+            ("expected an operator after `url`".to_string(), 3)
+        );
+        assert_eq!(
+            error("size~1k"),
+            ("`size` can't be compared with `~`".to_string(), 4)
+        );
+        assert_eq!(
+            error("mime < image/png"),
+            ("`mime` can't be compared with `<`".to_string(), 5)
+        );
+        assert_eq!(
+            error("size > lots"),
+            ("invalid size \"lots\"".to_string(), 7)
+        );
+        assert_eq!(error("url ="), ("expected a value".to_string(), 5));
+        assert_eq!(error("url=)"), ("expected a value".to_string(), 4));
+        assert_eq!(error("url='abc"), ("unclosed `'`".to_string(), 4));
+        assert_eq!(error("(url=a or url=b"), ("unclosed `(`".to_string(), 0));
+        assert_eq!(error("url=a)"), ("unmatched `)`".to_string(), 5));
+        assert_eq!(
+            error("url=a url=b"),
+            ("expected `and` or `or`, not `url=b`".to_string(), 6)
+        );
+        assert_eq!(error("url=a and"), ("expected a condition".to_string(), 9));
+        assert_eq!(
+            error("not = 1"),
+            ("expected a field, not `=`".to_string(), 4)
+        );
+        assert_eq!(
+            error("has_response=yes"),
+            (
+                "`has_response` takes no value; use `not has_response` for the opposite"
+                    .to_string(),
+                12
+            )
+        );
+
+        let (message, position) = error("mime=text/html or url~(unclosed");
+        assert!(
+            message.starts_with("invalid regular expression"),
+            "{}",
+            message
+        );
+        assert_eq!(position, 22);
+
+        // positions are byte offsets
+        assert_eq!(
+            error("url=é size"),
+            ("expected `and` or `or`, not `size`".to_string(), 7)
+        );
+
+        assert_eq!(
+            FilterExpression::parse("size>").unwrap_err().to_string(),
+            "invalid filter at position 5: expected a value"
+        );
+    }
+
+    #[test]
+    fn filter_resources() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let filter =
+            ResourceFilter::parse("mime=image/jpeg and size>30k and url!~texgrey").unwrap();
+        let urls: Vec<&str> = archive
+            .filter_resources(&filter)
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(urls, ["http://psxdatacenter.com/images/logo.jpg"]);
+
+        // combined with the other criteria
+        let filter = ResourceFilter {
+            subresources_only: true,
+            ..ResourceFilter::parse("mime=text/html or frame").unwrap()
+        };
+        assert_eq!(archive.filter_resources(&filter).count(), 0);
+
+        let frames = ResourceFilter::parse("frame").unwrap();
+        assert_eq!(
+            archive.filter_resources(&frames).count(),
+            archive.iter_frames().count()
+        );
+    }
+}
@@ -0,0 +1,2009 @@
+//! Working out where extracting an archive writes each resource.
+
+use crate::digest::sha256_hex;
+use crate::{
+    DigestAlgorithm, Error, FramePath, OperationHooks, Phase, Progress, Result, WebArchive,
+    WebResource,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// How much of a resource to write between progress reports.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The name of the file written for an HTML main resource with
+/// [`PlanOptions::main_as_index`].
+const INDEX_FILE_NAME: &str = "index.html";
+
+/// The folder resources with URL schemes no [`SchemeHandlers`] know are
+/// written into.
+const UNKNOWN_SCHEME_FOLDER: &str = "_scheme";
+
+/// The folder resources with `data:` URLs are written into.
+const DATA_URL_FOLDER: &str = "_data";
+
+/// The folder resources with `about:` URLs are written into.
+const ABOUT_URL_FOLDER: &str = "_about";
+
+/// How many hexadecimal digits of the SHA-256 digest of its URL name a
+/// resource whose URL has no path to name it after.
+const URL_DIGEST_LENGTH: usize = 16;
+
+/// The longest, in bytes, a file or folder name is made, leaving room
+/// to number it within the 255 bytes most file systems allow.
+const MAX_FILE_NAME_LENGTH: usize = 240;
+
+/// How many hexadecimal digits of the SHA-256 digest of a name too long
+/// to write end the shortened name.
+const TRUNCATED_DIGEST_LENGTH: usize = 16;
+
+/// The longest extension a shortened name keeps.
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// Where extracting an archive writes one resource, as planned by
+/// [`WebArchive::plan_extraction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedFile<'a> {
+    /// The resource to write.
+    pub resource: &'a WebResource,
+
+    /// Whether the resource is the main resource of its frame.
+    pub is_main_resource: bool,
+
+    /// Where to write the resource, relative to the folder
+    /// the archive is extracted into.
+    pub path: PathBuf,
+
+    /// Where the file's name came from.
+    pub name_source: NameSource,
+
+    /// Why the file's name is not simply the end of its URL, or of the
+    /// name its response declares, in the order they applied.
+    pub renamed: Vec<RenameReason>,
+
+    /// The frame whose archive holds the resource.
+    pub frame: FramePath,
+}
+
+/// Where the name of a [`PlannedFile`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum NameSource {
+    /// The resource's URL.
+    Url,
+
+    /// The `filename` of the `Content-Disposition` header of the
+    /// resource's archived response.
+    ContentDisposition,
+}
+
+/// Why a [`PlannedFile`] was given a name other than the one its URL,
+/// or response, would give it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum RenameReason {
+    /// The URL names a folder, or only a host, so the file was named
+    /// `_unnamed_index`, or `index.html` in the
+    /// [wget layout](ExtractLayout::Wget).
+    UnnamedIndex,
+
+    /// Another file needs a folder where this one would be, so it was
+    /// written inside that folder as its `_unnamed_index`, or
+    /// `index.html`.
+    FolderConflict,
+
+    /// Characters which are not allowed in file names were replaced in
+    /// the path its URL gives, or removed from the name its response
+    /// declared, along with any folders.
+    Sanitized,
+
+    /// A folder or file name was too long for most file systems, so
+    /// was shortened, with the digest of the whole name added.
+    Truncated,
+
+    /// Another file already had the name, ignoring case, so it was
+    /// numbered.
+    Collision,
+
+    /// The file is an HTML main resource, so was named `index.html`.
+    MainAsIndex,
+
+    /// No [`SchemeHandlers`] know the URL's scheme, so the file was
+    /// named after the digest of its URL, in the `_scheme` folder.
+    UnknownScheme,
+
+    /// A handler [registered](SchemeHandlers::register) for the URL's
+    /// scheme named the file.
+    SchemeHandler,
+
+    /// The URL is a `data:` or `about:` URL, with no path to name the
+    /// file after, so it was named after the digest of the URL, in the
+    /// `_data` or `_about` folder.
+    NoPath,
+}
+
+/// A file an extraction wants to write, before names which clash are
+/// worked out
+struct Wanted<'a> {
+    resource: &'a WebResource,
+    is_main_resource: bool,
+    path: PathBuf,
+    name_source: NameSource,
+    renamed: Vec<RenameReason>,
+    frame: FramePath,
+    index_html: bool,
+}
+
+/// A record of the files an extraction wrote, kept beside them as
+/// [`FILE_NAME`](ExtractionManifest::FILE_NAME), so that they can be
+/// traced back to the resources they came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ExtractionManifest {
+    /// Every file written, in the order they were written.
+    pub files: Vec<ExtractedFile>,
+}
+
+/// One file in an [`ExtractionManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ExtractedFile {
+    /// Where the file was written, relative to the folder the archive
+    /// was extracted into, with `/` between folders.
+    pub path: String,
+
+    /// The URL of the resource written.
+    pub url: String,
+
+    /// The resource's MIME type.
+    pub mime_type: String,
+
+    /// The encoding of the file's contents, if it is text.
+    pub text_encoding_name: Option<String>,
+
+    /// The length of the file, in bytes.
+    pub size: u64,
+
+    /// The hexadecimal SHA-256 digest of the file's contents.
+    pub sha256: String,
+
+    /// The positions of the subframe archives leading from the root
+    /// archive to the one holding the resource, which is empty for the
+    /// root archive itself.
+    pub frame: Vec<usize>,
+
+    /// Whether the resource is the main resource of its frame.
+    #[serde(default)]
+    pub is_main_resource: bool,
+
+    /// The resource's frame name, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_name: Option<String>,
+
+    /// Where the file's name came from.
+    pub name_source: NameSource,
+
+    /// Why the file's name is not simply the end of its URL.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed: Vec<RenameReason>,
+
+    /// The encoding the resource was stored in, if the file was
+    /// written in another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcoded_from: Option<String>,
+
+    /// Where the resource's archived response was written, relative to
+    /// the same folder, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+}
+
+impl ExtractionManifest {
+    /// The name of the manifest file, in the folder an archive was
+    /// extracted into.
+    pub const FILE_NAME: &'static str = "webarchive-manifest.json";
+
+    /// Read the manifest of the extraction into `folder`.
+    pub fn read_from(folder: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read(folder.as_ref().join(Self::FILE_NAME))?;
+        Ok(serde_json::from_slice(&json).map_err(std::io::Error::from)?)
+    }
+
+    /// Write the manifest into `folder`, as pretty-printed JSON.
+    pub fn write_to(&self, folder: impl AsRef<Path>) -> Result<()> {
+        let mut json = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        json.push(b'\n');
+        std::fs::write(folder.as_ref().join(Self::FILE_NAME), json)?;
+        Ok(())
+    }
+
+    /// The file written for the resource with `url`.
+    pub fn get(&self, url: &str) -> Option<&ExtractedFile> {
+        self.files.iter().find(|file| file.url == url)
+    }
+}
+
+impl ExtractedFile {
+    /// The record of writing `contents` where `planned` says.
+    ///
+    /// `contents` is usually the planned resource, but may be a copy
+    /// changed before it was written, such as to convert its links.
+    pub fn new(planned: &PlannedFile<'_>, contents: &WebResource) -> Self {
+        ExtractedFile {
+            path: manifest_path(&planned.path),
+            url: planned.resource.url.clone(),
+            mime_type: contents.mime_type.clone(),
+            text_encoding_name: contents.text_encoding_name.clone(),
+            size: contents.data.len() as u64,
+            sha256: contents.hex_digest(DigestAlgorithm::Sha256),
+            frame: planned.frame.segments()[1..]
+                .iter()
+                .map(|segment| segment.index)
+                .collect(),
+            is_main_resource: planned.is_main_resource,
+            frame_name: planned.resource.frame_name.clone(),
+            name_source: planned.name_source,
+            renamed: planned.renamed.clone(),
+            transcoded_from: None,
+            response: None,
+        }
+    }
+}
+
+/// A relative path as written in an [`ExtractionManifest`], with `/`
+/// between folders whatever the platform
+fn manifest_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Options for [`WebArchive::plan_extraction_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanOptions {
+    /// Name files after the `filename` their archived responses'
+    /// `Content-Disposition` headers give, where there is one, rather
+    /// than the end of their URL.
+    pub use_response_filenames: bool,
+
+    /// Write each frame's resources into a folder of its own: `main`
+    /// for the root archive, and one named after the frame's position
+    /// and main resource, as in `frame-0-banner.html`, for each
+    /// subframe archive.
+    pub by_frame: bool,
+
+    /// Name the root archive's main resource `index.html` where it is
+    /// HTML, and likewise subframes' main resources whose URLs name a
+    /// folder, so the files can be served as they are by a static file
+    /// server.
+    pub main_as_index: bool,
+
+    /// How the paths files are written to are made from their URLs.
+    pub layout: ExtractLayout,
+
+    /// How the paths of resources with URL schemes other than the
+    /// [built-in ones](SchemeHandlers::BUILT_IN) are made.
+    pub schemes: SchemeHandlers,
+}
+
+type SchemeHandler = Arc<dyn Fn(&str) -> PathBuf + Send + Sync>;
+
+/// Ways to make the path a resource is written to from URLs with
+/// schemes [`ExtractLayout`] knows nothing of, such as the `cid:` URLs
+/// of email attachments or Quick Look's `x-apple-ql-id:` URLs.
+///
+/// Resources whose URL schemes are [built in](SchemeHandlers::BUILT_IN)
+/// are written where their layout says, except that `data:` and
+/// `about:` URLs, which have no path, are named as the fallback names
+/// files, in the `_data` and `_about` folders. Those with schemes neither
+/// built in nor [registered](SchemeHandlers::register) are written to
+/// the `_scheme` folder, named after the first 16 hexadecimal digits of
+/// the SHA-256 digest of their URL and their
+/// [guessed extension](WebResource::guessed_extension), as in
+/// `_scheme/3f2a9c0d1e4b5a67.png`, so the same URL is always written to
+/// the same path.
+///
+/// ```rust
+/// use std::path::PathBuf;
+///
+/// let schemes = webarchive::SchemeHandlers::new().register("cid", |url| {
+///     PathBuf::from("attachments").join(url.trim_start_matches("cid:"))
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct SchemeHandlers {
+    handlers: BTreeMap<String, SchemeHandler>,
+}
+
+impl SchemeHandlers {
+    /// The URL schemes whose resources are written where the
+    /// [`ExtractLayout`] says, or for `data:` and `about:`, in a folder
+    /// of their own, unless a handler is registered for them.
+    pub const BUILT_IN: &'static [&'static str] =
+        &["http", "https", "ftp", "file", "data", "about"];
+
+    /// Only the built-in handling, with the fallback for other schemes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the path of resources whose URL has `scheme`, ignoring
+    /// case, with `handler`, instead of the built-in handling or the
+    /// fallback.
+    ///
+    /// `handler` is given the whole URL, and returns a path relative to
+    /// the folder the archive is extracted into. Empty, `.` and `..`
+    /// segments of the path, and any root, are left out, and characters
+    /// which are not allowed in file names replaced, so nothing is
+    /// written outside the folder. If nothing is left, the fallback
+    /// name is used. Names which clash are numbered as any others are.
+    pub fn register(
+        mut self,
+        scheme: &str,
+        handler: impl Fn(&str) -> PathBuf + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers
+            .insert(scheme.to_ascii_lowercase(), Arc::new(handler));
+        self
+    }
+
+    /// Whether a handler is registered for `scheme`, ignoring case.
+    pub fn is_registered(&self, scheme: &str) -> bool {
+        self.handlers.contains_key(&scheme.to_ascii_lowercase())
+    }
+
+    /// The path `resource` is written to, and why, if its URL's scheme
+    /// is registered, unknown, or has no path, or `None` if the layout
+    /// says where
+    pub(crate) fn path(&self, resource: &WebResource) -> Option<(PathBuf, RenameReason)> {
+        let url = url::Url::parse(&resource.url).ok()?;
+        let (folder, reason) = match self.handlers.get(url.scheme()) {
+            Some(handler) => {
+                let path = safe_relative_path(&handler(&resource.url));
+                if !path.as_os_str().is_empty() {
+                    return Some((path, RenameReason::SchemeHandler));
+                }
+                (UNKNOWN_SCHEME_FOLDER, RenameReason::UnknownScheme)
+            }
+            None => match url.scheme() {
+                "data" => (DATA_URL_FOLDER, RenameReason::NoPath),
+                "about" => (ABOUT_URL_FOLDER, RenameReason::NoPath),
+                scheme if Self::BUILT_IN.contains(&scheme) => return None,
+                _ => (UNKNOWN_SCHEME_FOLDER, RenameReason::UnknownScheme),
+            },
+        };
+
+        Some((digest_path(folder, resource), reason))
+    }
+}
+
+impl fmt::Debug for SchemeHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl PartialEq for SchemeHandlers {
+    fn eq(&self, other: &Self) -> bool {
+        self.handlers.len() == other.handlers.len()
+            && self
+                .handlers
+                .iter()
+                .zip(&other.handlers)
+                .all(|((a, a_handler), (b, b_handler))| a == b && Arc::ptr_eq(a_handler, b_handler))
+    }
+}
+
+impl Eq for SchemeHandlers {}
+
+/// How [`WebArchive::plan_extraction_with`] makes the path a resource
+/// is written to from its URL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExtractLayout {
+    /// The URL without its scheme, as
+    /// [`plan_extraction`](WebArchive::plan_extraction) describes.
+    #[default]
+    Default,
+
+    /// The layout `wget --mirror` saves a site in, so tools which work
+    /// on its mirrors work on the extracted files too:
+    ///
+    /// - Each host has a folder, with its port after a `_` if it isn't
+    ///   the scheme's usual one, as in `example.com_8080`. wget writes
+    ///   a `:`, but that is not allowed in file names everywhere.
+    /// - URLs ending in `/`, or with no path, are named `index.html`.
+    /// - The query follows the name, with the `?` written as `%3F`, as
+    ///   in `page.php%3Fid=1`. wget itself writes the `?`, and `%3F`
+    ///   where its `--convert-links` links to the file, but `?` is not
+    ///   allowed in file names everywhere.
+    /// - Percent-encoded characters are decoded, except for `/`,
+    ///   control characters and bytes which aren't UTF-8, which are
+    ///   written percent-encoded, as in `a%2Fb`.
+    /// - Empty, `.` and `..` path segments, and fragments, are left out.
+    ///
+    /// URLs wget can't fetch, such as `data:` and `file:` URLs, are
+    /// written where the [default layout](ExtractLayout::Default) would.
+    /// Clashing names are numbered as in the default layout too.
+    Wget,
+}
+
+impl WebArchive {
+    /// Work out where extracting this archive writes each resource.
+    ///
+    /// Each resource is written to a path made from its URL without the
+    /// scheme, as in `example.com/images/logo.png`, with `_unnamed_index`
+    /// and an extension naming any which end in `/` or have no path, as
+    /// in `https://example.com`. Empty, `.` and `..` path segments are
+    /// left out, so nothing is written outside the folder. Where one
+    /// resource's path is a folder another's is inside, as with
+    /// `https://example.com/docs` and `https://example.com/docs/intro.html`,
+    /// the first is written inside that folder as its `_unnamed_index`.
+    /// `data:` and `about:` URLs, which have no path, are written to the
+    /// `_data` and `_about` folders, named after the digest of their URL
+    /// and their [guessed extension](WebResource::guessed_extension).
+    ///
+    /// Resources are planned in the same order as
+    /// [`iter_resources`](WebArchive::iter_resources), once for each
+    /// URL, as later resources with the same URL would be written over
+    /// the first. Where two URLs lead to the same path, ignoring case as
+    /// many file systems do, the later one gets a numbered name, as in
+    /// `logo-2.png`.
+    ///
+    /// Characters which are not allowed in file names on common file
+    /// systems, such as `?` and `:`, are replaced with `_` in every
+    /// folder and file name. Names longer than 240 bytes are shortened,
+    /// keeping their start and extension with the first 16 hexadecimal
+    /// digits of the SHA-256 digest of the whole name between them, so
+    /// they fit within the 255 bytes most file systems allow.
+    ///
+    /// This does not touch the file system, so the same plan can be
+    /// made again to check an extraction.
+    pub fn plan_extraction(&self) -> Vec<PlannedFile<'_>> {
+        self.plan_extraction_with(&PlanOptions::default())
+    }
+
+    /// Work out where extracting this archive writes each resource, as
+    /// [`plan_extraction`](WebArchive::plan_extraction) does, with
+    /// `options`.
+    ///
+    /// With [`use_response_filenames`](PlanOptions::use_response_filenames),
+    /// a resource whose archived response declares a file name in its
+    /// `Content-Disposition` header is written to that name, in the
+    /// folder its URL leads to. The name is stripped of any folders and
+    /// of characters which are not allowed in file names, and collides
+    /// with others as a name from a URL would.
+    ///
+    /// With [`by_frame`](PlanOptions::by_frame), each path is inside the
+    /// folder of the frame the resource is first found in. Resources
+    /// the root archive shares with its frames are in `main`, as it is
+    /// planned first. Frames inside others are named by each position
+    /// leading to them, as in `frame-1-0-inner.html`.
+    ///
+    /// With [`main_as_index`](PlanOptions::main_as_index), an HTML main
+    /// resource is written as `index.html` rather than `_unnamed_index`,
+    /// whatever its MIME type's usual extension. The root archive's is
+    /// renamed wherever its URL leads, subframes' only where theirs
+    /// names a folder. A name the resource's response declares is kept
+    /// instead, and a stored `index.html` in the same folder is numbered
+    /// as any other clashing name would be.
+    ///
+    /// Resources whose URLs have schemes other than the built-in ones,
+    /// such as `cid:`, are written where [`schemes`](PlanOptions::schemes)
+    /// says, in either layout.
+    pub fn plan_extraction_with(&self, options: &PlanOptions) -> Vec<PlannedFile<'_>> {
+        let mut urls = HashSet::new();
+        let mut wanted = Vec::new();
+
+        for (frame, archive) in self.iter_frames() {
+            let resources = std::iter::once((&archive.main_resource, true)).chain(
+                archive
+                    .subresources
+                    .iter()
+                    .flatten()
+                    .map(|subresource| (subresource, false)),
+            );
+
+            for (resource, is_main_resource) in resources {
+                if !urls.insert(resource.url.as_str()) {
+                    continue;
+                }
+
+                let mut renamed = Vec::new();
+                let (mut path, unnamed) = match options.schemes.path(resource) {
+                    Some((path, reason)) => {
+                        renamed.push(reason);
+                        (path, false)
+                    }
+                    None => match options.layout {
+                        ExtractLayout::Default => url_path(resource),
+                        ExtractLayout::Wget => {
+                            wget_path(&resource.url).unwrap_or_else(|| url_path(resource))
+                        }
+                    },
+                };
+                if options.by_frame {
+                    path = frame_folder(&frame, archive).join(path);
+                }
+                let mut name_source = NameSource::Url;
+                if unnamed {
+                    renamed.push(RenameReason::UnnamedIndex);
+                }
+                if options.use_response_filenames {
+                    if let Some((file_name, sanitized)) = response_file_name(resource) {
+                        path.set_file_name(file_name);
+                        name_source = NameSource::ContentDisposition;
+                        renamed.clear();
+                        if sanitized {
+                            renamed.push(RenameReason::Sanitized);
+                        }
+                    }
+                }
+
+                let as_index = options.main_as_index && is_main_resource && resource.is_html();
+                if as_index && name_source == NameSource::Url && (unnamed || frame.is_root()) {
+                    path.set_file_name(INDEX_FILE_NAME);
+                    renamed.clear();
+                    renamed.push(RenameReason::MainAsIndex);
+                }
+                let (path, sanitized, truncated) = fit_path(&path);
+                if sanitized && !renamed.contains(&RenameReason::Sanitized) {
+                    renamed.push(RenameReason::Sanitized);
+                }
+                if truncated {
+                    renamed.push(RenameReason::Truncated);
+                }
+
+                wanted.push(Wanted {
+                    resource,
+                    is_main_resource,
+                    path,
+                    name_source,
+                    renamed,
+                    frame: frame.clone(),
+                    index_html: as_index || options.layout == ExtractLayout::Wget,
+                });
+            }
+        }
+
+        // every folder some file needs, which no file can be named
+        let folders: HashSet<String> = wanted
+            .iter()
+            .flat_map(|wanted| wanted.path.ancestors().skip(1))
+            .filter(|folder| !folder.as_os_str().is_empty())
+            .map(|folder| folder.to_string_lossy().to_lowercase())
+            .collect();
+        let mut taken = folders.clone();
+
+        let mut planned = Vec::with_capacity(wanted.len());
+        for mut wanted in wanted {
+            // a file whose path is also a folder goes inside it, as
+            // `https://example.com/docs` does beside `/docs/intro.html`
+            if folders.contains(&wanted.path.to_string_lossy().to_lowercase()) {
+                if wanted.index_html {
+                    wanted.path.push(INDEX_FILE_NAME);
+                } else {
+                    wanted.path.push(unnamed_index(wanted.resource));
+                }
+                wanted.renamed.push(RenameReason::FolderConflict);
+            }
+
+            let (number, path) = (1..)
+                .map(|number| (number, numbered(&wanted.path, number)))
+                .find(|(_, path)| taken.insert(path.to_string_lossy().to_lowercase()))
+                .expect("Some numbered path is always free");
+            if number > 1 {
+                wanted.renamed.push(RenameReason::Collision);
+            }
+
+            planned.push(PlannedFile {
+                resource: wanted.resource,
+                is_main_resource: wanted.is_main_resource,
+                path,
+                name_source: wanted.name_source,
+                renamed: wanted.renamed,
+                frame: wanted.frame,
+            });
+        }
+
+        planned
+    }
+
+    /// Write each resource to a file inside `folder`, where
+    /// [`plan_extraction`](WebArchive::plan_extraction) says, returning
+    /// the paths written.
+    ///
+    /// The files planned and the bytes written are reported to `hooks`
+    /// as they go. If they say to stop, this fails with
+    /// [`Error::Cancelled`], removing the file it was part way through
+    /// writing, but keeping those already written in full.
+    pub fn extract_to(
+        &self,
+        folder: impl AsRef<Path>,
+        hooks: &OperationHooks<'_>,
+    ) -> Result<Vec<PathBuf>> {
+        let folder = folder.as_ref();
+        let plan = self.plan_extraction();
+
+        let mut progress = Progress::new(Phase::Extracting);
+        progress.items_total = Some(plan.len());
+        progress.bytes_total = Some(
+            plan.iter()
+                .map(|planned| planned.resource.data.len() as u64)
+                .sum(),
+        );
+        hooks.report(progress);
+
+        let mut written = Vec::with_capacity(plan.len());
+        for planned in &plan {
+            hooks.check()?;
+
+            let path = folder.join(&planned.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let result = write_file(&path, &planned.resource.data, hooks, &mut progress);
+            if result.is_err() {
+                let _ = std::fs::remove_file(&path);
+            }
+            result?;
+
+            progress.items_done += 1;
+            hooks.report(progress);
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+}
+
+/// Write `data` to the file at `path` in chunks, adding each to the
+/// bytes done in `progress`, and stopping if `hooks` say to.
+fn write_file(
+    path: &Path,
+    data: &[u8],
+    hooks: &OperationHooks<'_>,
+    progress: &mut Progress,
+) -> Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        if hooks.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        file.write_all(chunk)?;
+        progress.bytes_done += chunk.len() as u64;
+        hooks.report(*progress);
+    }
+    Ok(())
+}
+
+/// The path a resource is written to before any collisions are resolved
+fn url_path(resource: &WebResource) -> (PathBuf, bool) {
+    let (url, is_folder) = match resource.url.split_once("//") {
+        // a URL of only a host, as in `https://example.com`, is the
+        // same as one with a `/` path
+        Some((_, rest)) => (rest, rest.ends_with('/') || !rest.contains('/')),
+        None => (resource.url.as_str(), resource.url.ends_with('/')),
+    };
+
+    let mut path: PathBuf = url
+        .split('/')
+        .filter(|segment| !matches!(*segment, "" | "." | ".."))
+        .collect();
+
+    let unnamed = is_folder || path.as_os_str().is_empty();
+    if unnamed {
+        // We need to generate a file name, as there wasn't one given
+        path.push(unnamed_index(resource));
+    }
+
+    (path, unnamed)
+}
+
+/// The path in `folder` a resource whose URL can't name it is written
+/// to, named after the digest of its URL
+fn digest_path(folder: &str, resource: &WebResource) -> PathBuf {
+    let digest = sha256_hex(resource.url.as_bytes());
+    let name = format!(
+        "{}.{}",
+        &digest[..URL_DIGEST_LENGTH],
+        resource.guessed_extension()
+    );
+    Path::new(folder).join(name)
+}
+
+/// `path` with characters which are not allowed in file names replaced
+/// in each folder and name, and those too long to write shortened, and
+/// whether each of those changed it
+fn fit_path(path: &Path) -> (PathBuf, bool, bool) {
+    let mut sanitized = false;
+    let mut truncated = false;
+    let path = path
+        .components()
+        .map(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            let replaced = sanitize_segment(&name);
+            sanitized |= replaced != name;
+            let (shortened, was_truncated) = shorten_file_name(replaced);
+            truncated |= was_truncated;
+            shortened
+        })
+        .collect();
+
+    (path, sanitized, truncated)
+}
+
+/// A folder or file name with characters which are not allowed in file
+/// names on common file systems replaced, and without the dots or spaces
+/// at its end which some drop
+fn sanitize_segment(name: &str) -> String {
+    let name = replace_disallowed(name);
+    match name.trim_end_matches(['.', ' ']) {
+        "" => "_".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// `name` shortened to [`MAX_FILE_NAME_LENGTH`] bytes if it is longer,
+/// keeping its start and any extension, with the digest of the whole
+/// name between them, and whether it had to be
+fn shorten_file_name(name: String) -> (String, bool) {
+    if name.len() <= MAX_FILE_NAME_LENGTH {
+        return (name, false);
+    }
+
+    let digest = sha256_hex(name.as_bytes());
+    let mut suffix = format!("-{}", &digest[..TRUNCATED_DIGEST_LENGTH]);
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.is_empty() && extension.len() <= MAX_EXTENSION_LENGTH);
+    if let Some(extension) = extension {
+        suffix.push('.');
+        suffix.push_str(extension);
+    }
+
+    let mut end = MAX_FILE_NAME_LENGTH - suffix.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    (format!("{}{}", &name[..end], suffix), true)
+}
+
+/// `path` with only the folders and names which stay inside the folder
+/// it is relative to, made safe to write to
+fn safe_relative_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => sanitize_file_name(&name.to_string_lossy()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The path `wget --mirror` saves a URL to, as
+/// [`ExtractLayout::Wget`] describes, and whether it had to be named
+/// `index.html`, or `None` if wget can't fetch it
+fn wget_path(url: &str) -> Option<(PathBuf, bool)> {
+    let url = url::Url::parse(url).ok()?;
+    if !matches!(url.scheme(), "http" | "https" | "ftp") {
+        return None;
+    }
+
+    let mut path = PathBuf::from(match url.port() {
+        Some(port) => format!("{}:{}", url.host_str()?, port),
+        None => url.host_str()?.to_string(),
+    });
+
+    let mut segments: Vec<String> = url.path_segments()?.map(wget_file_name).collect();
+    let file_name = segments.pop().unwrap_or_default();
+    path.extend(
+        segments
+            .iter()
+            .filter(|segment| !matches!(segment.as_str(), "" | "." | "..")),
+    );
+
+    let unnamed = matches!(file_name.as_str(), "" | "." | "..");
+    let mut file_name = if unnamed {
+        INDEX_FILE_NAME.to_string()
+    } else {
+        file_name
+    };
+    if let Some(query) = url.query() {
+        file_name.push_str("%3F");
+        file_name.push_str(&wget_file_name(query));
+    }
+    path.push(file_name);
+
+    Some((path, unnamed))
+}
+
+/// A part of a URL as wget writes it in a file name: decoded, but
+/// with `/`, control characters and bytes which aren't UTF-8
+/// percent-encoded
+fn wget_file_name(part: &str) -> String {
+    let mut bytes = Vec::with_capacity(part.len());
+    let mut input = part.as_bytes();
+    while let Some((&byte, rest)) = input.split_first() {
+        let decoded = match rest {
+            [high, low, ..] if byte == b'%' => char::from(*high)
+                .to_digit(16)
+                .zip(char::from(*low).to_digit(16))
+                .map(|(high, low)| (high * 16 + low) as u8),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                input = &rest[2..];
+            }
+            None => {
+                bytes.push(byte);
+                input = rest;
+            }
+        }
+    }
+
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '/' || c.is_ascii_control() {
+                name.push_str(&format!("%{:02X}", c as u32));
+            } else {
+                name.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+/// The folder a frame's resources are written into when extracting by
+/// frame, named after the end of its main resource's URL, or else its
+/// frame name or host
+fn frame_folder(frame: &FramePath, archive: &WebArchive) -> PathBuf {
+    if frame.is_root() {
+        return PathBuf::from("main");
+    }
+
+    let mut folder = String::from("frame");
+    for segment in &frame.segments()[1..] {
+        folder.push_str(&format!("-{}", segment.index));
+    }
+
+    let resource = &archive.main_resource;
+    let url = url::Url::parse(&resource.url).ok();
+    let name = url
+        .as_ref()
+        .and_then(|url| url.path_segments()?.next_back())
+        .filter(|name| !name.is_empty())
+        .or(resource.frame_name.as_deref())
+        .or_else(|| url.as_ref()?.host_str())
+        .and_then(sanitize_file_name);
+    if let Some(name) = name {
+        folder.push('-');
+        folder.push_str(&name);
+    }
+
+    PathBuf::from(folder)
+}
+
+/// The name of the file written for a resource whose URL names a folder
+fn unnamed_index(resource: &WebResource) -> String {
+    format!("_unnamed_index.{}", resource.guessed_extension())
+}
+
+/// The file name the `Content-Disposition` header of a resource's
+/// archived response gives, made safe to write to, and whether that
+/// changed it
+fn response_file_name(resource: &WebResource) -> Option<(String, bool)> {
+    let response = resource.parse_response().ok()??;
+    let header = response.headers.get("Content-Disposition")?;
+    let declared = content_disposition_file_name(header)?;
+    let file_name = sanitize_file_name(&declared)?;
+    let sanitized = file_name != declared;
+    Some((file_name, sanitized))
+}
+
+/// The file name a `Content-Disposition` header value gives, preferring
+/// its RFC 5987 `filename*` parameter to its plain `filename`
+fn content_disposition_file_name(header: &str) -> Option<String> {
+    let mut file_name = None;
+
+    let mut rest = header.split_once(';')?.1;
+    while !rest.trim().is_empty() {
+        let (name, after_name) = rest.split_once('=')?;
+        let name = name.trim().to_ascii_lowercase();
+        let after_name = after_name.trim_start();
+
+        // values are tokens, or quoted strings which may hold `;`
+        let value = match after_name.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = index + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                let after_value = &quoted[end..];
+                rest = after_value.split_once(';').map_or("", |(_, rest)| rest);
+                value
+            }
+            None => {
+                let (value, after_value) = after_name.split_once(';').unwrap_or((after_name, ""));
+                rest = after_value;
+                value.trim().to_string()
+            }
+        };
+
+        match name.as_str() {
+            "filename*" => {
+                if let Some(decoded) = decode_ext_value(&value) {
+                    return Some(decoded);
+                }
+            }
+            "filename" => file_name = Some(value),
+            _ => {}
+        }
+    }
+
+    file_name
+}
+
+/// Decode an RFC 5987 extended value, as in `UTF-8''caf%C3%A9.pdf`
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut input = encoded.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let high = char::from(input.next()?).to_digit(16)?;
+            let low = char::from(input.next()?).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    let encoding = encoding_rs::Encoding::for_label(charset.trim().as_bytes())?;
+    let (decoded, had_errors) = encoding.decode_without_bom_handling(&bytes);
+    (!had_errors).then(|| decoded.into_owned())
+}
+
+/// A declared file name without any folders, or characters which are
+/// not allowed in file names on common file systems, or `None` if
+/// nothing is left
+fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = replace_disallowed(name);
+    let name = name.trim().trim_end_matches('.');
+
+    (!name.is_empty() && !name.starts_with('.')).then(|| name.to_string())
+}
+
+/// `name` without control characters, and with those which are not
+/// allowed in file names on common file systems replaced with `_`
+fn replace_disallowed(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' | '\\' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// `path` with `-number` added before its extension, unless `number` is 1
+fn numbered(path: &Path, number: usize) -> PathBuf {
+    if number == 1 {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_disposition_file_name, sanitize_file_name};
+    use crate::test_support::resource;
+    use crate::{
+        Error, ExtractLayout, ExtractedFile, ExtractionManifest, Headers, NameSource,
+        OperationHooks, PlanOptions, RenameReason, ResourceResponse, SchemeHandlers, WebArchive,
+        WebResource,
+    };
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Barrier;
+
+    fn paths(archive: &WebArchive) -> Vec<(&str, PathBuf)> {
+        archive
+            .plan_extraction()
+            .into_iter()
+            .map(|planned| (planned.resource.url.as_str(), planned.path))
+            .collect()
+    }
+
+    #[test]
+    fn plan_extraction() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let plan = archive.plan_extraction();
+
+        assert_eq!(plan.len(), 2);
+        assert!(plan[0].is_main_resource);
+        assert_eq!(
+            plan[0].path,
+            PathBuf::from("crouton.net/_unnamed_index.shtml")
+        );
+        assert!(!plan[1].is_main_resource);
+        assert_eq!(plan[1].path, PathBuf::from("crouton.net/crouton.png"));
+
+        // resources shared between frames are only planned once
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let plan = archive.plan_extraction();
+        let urls: std::collections::HashSet<_> = archive
+            .iter_resources()
+            .map(|resource| &resource.url)
+            .collect();
+        assert_eq!(plan.len(), urls.len());
+        assert_eq!(
+            plan.iter()
+                .filter(|planned| planned.path.ends_with("texgrey.jpg"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn plan_extraction_collisions() {
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource(
+                    "https://example.com/../../etc/passwd",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource("file:///Logo.png", "text/html", "<p>hello</p>"),
+                resource("https://example.com/a.png", "text/html", "<p>hello</p>"),
+                resource("http://example.com/A.png", "text/html", "<p>hello</p>"),
+                resource("http://example.com/a.png", "text/html", "<p>hello</p>"),
+                resource("https://example.com/a.png", "text/html", "<p>hello</p>"),
+            ]),
+            subframe_archives: None,
+        };
+
+        assert_eq!(
+            paths(&archive),
+            [
+                (
+                    "https://example.com/",
+                    PathBuf::from("example.com/_unnamed_index.shtml")
+                ),
+                (
+                    "https://example.com/../../etc/passwd",
+                    PathBuf::from("example.com/etc/passwd")
+                ),
+                ("file:///Logo.png", PathBuf::from("Logo.png")),
+                (
+                    "https://example.com/a.png",
+                    PathBuf::from("example.com/a.png")
+                ),
+                (
+                    "http://example.com/A.png",
+                    PathBuf::from("example.com/A-2.png")
+                ),
+                (
+                    "http://example.com/a.png",
+                    PathBuf::from("example.com/a-3.png")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_extraction_folders() {
+        let archive: WebArchive = crate::from_file("fixtures/host-only.webarchive")
+            .expect("Could not read host-only webarchive fixture");
+
+        assert_eq!(
+            paths(&archive),
+            [
+                (
+                    "https://example.com",
+                    PathBuf::from("example.com/_unnamed_index.shtml")
+                ),
+                (
+                    "https://example.com/style.css",
+                    PathBuf::from("example.com/style.css")
+                ),
+                (
+                    "https://example.com/docs",
+                    PathBuf::from("example.com/docs/_unnamed_index.shtml")
+                ),
+                (
+                    "https://example.com/docs/intro.html",
+                    PathBuf::from("example.com/docs/intro.html")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_extraction_by_frame() {
+        let named = |url: &str, frame_name: &str| WebResource {
+            frame_name: Some(frame_name.to_string()),
+            ..resource(url, "text/html", "<p>hello</p>")
+        };
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![resource(
+                "https://example.com/logo.png",
+                "text/html",
+                "<p>hello</p>",
+            )]),
+            subframe_archives: Some(vec![
+                WebArchive {
+                    main_resource: named("https://example.com/nav.html", "nav"),
+                    subresources: Some(vec![
+                        resource("https://example.com/logo.png", "text/html", "<p>hello</p>"),
+                        resource(
+                            "https://example.com/button.png",
+                            "text/html",
+                            "<p>hello</p>",
+                        ),
+                    ]),
+                    subframe_archives: Some(vec![WebArchive {
+                        main_resource: named("https://ads.example.net/", "ad"),
+                        subresources: None,
+                        subframe_archives: None,
+                    }]),
+                },
+                WebArchive {
+                    main_resource: named("https://example.com/docs/", "content"),
+                    subresources: None,
+                    subframe_archives: None,
+                },
+            ]),
+        };
+
+        let plan = archive.plan_extraction_with(&PlanOptions {
+            by_frame: true,
+            ..PlanOptions::default()
+        });
+        let paths: Vec<_> = plan
+            .iter()
+            .map(|planned| (planned.resource.url.as_str(), planned.path.clone()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (
+                    "https://example.com/",
+                    PathBuf::from("main/example.com/_unnamed_index.shtml")
+                ),
+                (
+                    "https://example.com/logo.png",
+                    PathBuf::from("main/example.com/logo.png")
+                ),
+                (
+                    "https://example.com/nav.html",
+                    PathBuf::from("frame-0-nav.html/example.com/nav.html")
+                ),
+                (
+                    "https://example.com/button.png",
+                    PathBuf::from("frame-0-nav.html/example.com/button.png")
+                ),
+                (
+                    "https://ads.example.net/",
+                    PathBuf::from("frame-0-0-ad/ads.example.net/_unnamed_index.shtml")
+                ),
+                (
+                    "https://example.com/docs/",
+                    PathBuf::from("frame-1-content/example.com/docs/_unnamed_index.shtml")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_extraction_main_as_index() {
+        let frame = |url: &str, subresources: Vec<WebResource>| WebArchive {
+            main_resource: resource(url, "text/html", "<p>hello</p>"),
+            subresources: Some(subresources),
+            subframe_archives: None,
+        };
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![resource(
+                "https://example.com/index.html",
+                "text/html",
+                "<p>hello</p>",
+            )]),
+            subframe_archives: Some(vec![
+                frame("https://example.com/nav/", vec![]),
+                frame("https://example.com/page.php", vec![]),
+                frame(
+                    "https://example.com/docs",
+                    vec![resource(
+                        "https://example.com/docs/intro.html",
+                        "text/html",
+                        "<p>hello</p>",
+                    )],
+                ),
+                WebArchive {
+                    main_resource: resource(
+                        "https://example.com/logo/",
+                        "image/png",
+                        "<p>hello</p>",
+                    ),
+                    subresources: None,
+                    subframe_archives: None,
+                },
+            ]),
+        };
+
+        let plan = archive.plan_extraction_with(&PlanOptions {
+            main_as_index: true,
+            ..PlanOptions::default()
+        });
+        let paths: Vec<_> = plan
+            .iter()
+            .map(|planned| {
+                (
+                    planned.resource.url.as_str(),
+                    planned.path.to_str().unwrap(),
+                    planned.renamed.as_slice(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (
+                    "https://example.com/",
+                    "example.com/index.html",
+                    &[RenameReason::MainAsIndex][..]
+                ),
+                (
+                    "https://example.com/index.html",
+                    "example.com/index-2.html",
+                    &[RenameReason::Collision]
+                ),
+                (
+                    "https://example.com/nav/",
+                    "example.com/nav/index.html",
+                    &[RenameReason::MainAsIndex]
+                ),
+                ("https://example.com/page.php", "example.com/page.php", &[]),
+                (
+                    "https://example.com/docs",
+                    "example.com/docs/index.html",
+                    &[RenameReason::FolderConflict]
+                ),
+                (
+                    "https://example.com/docs/intro.html",
+                    "example.com/docs/intro.html",
+                    &[]
+                ),
+                (
+                    "https://example.com/logo/",
+                    "example.com/logo/_unnamed_index.png",
+                    &[RenameReason::UnnamedIndex]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_extraction_long_names() {
+        let folder = "f".repeat(300);
+        let file = format!("{}.png", "é".repeat(150));
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource(
+                    &format!("https://example.com/{}/a.png", folder),
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    &format!("https://example.com/{}/b.png", folder),
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    &format!("https://example.com/{}", file),
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    "https://example.com/page.php?id=1&sort=a:b",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+            ]),
+            subframe_archives: None,
+        };
+
+        let plan = archive.plan_extraction();
+        let paths: Vec<_> = plan.iter().map(|planned| planned.path.as_path()).collect();
+
+        // the same folder is shortened the same way for each file in it
+        let shortened = paths[1].parent().unwrap();
+        assert_eq!(paths[2].parent().unwrap(), shortened);
+        let shortened = shortened.file_name().unwrap().to_str().unwrap();
+        assert_eq!(shortened.len(), 240);
+        assert!(shortened.starts_with("fff"));
+        assert!(shortened.ends_with("-288ba0fa003c6710"));
+
+        let name = paths[3].file_name().unwrap().to_str().unwrap();
+        assert!(name.len() <= 240);
+        assert!(name.starts_with("éé"));
+        assert!(name.ends_with(".png"));
+
+        assert_eq!(
+            paths[4],
+            PathBuf::from("example.com/page.php_id=1&sort=a_b")
+        );
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let written = archive
+            .extract_to(temp.path(), &OperationHooks::default())
+            .unwrap();
+        assert_eq!(written.len(), 5);
+    }
+
+    #[test]
+    fn wget_paths() {
+        // as saved by `wget -x` (GNU Wget 1.21.3), but for `?`
+        let cases = [
+            ("http://example.com/", Some("example.com/index.html")),
+            ("https://example.com", Some("example.com/index.html")),
+            (
+                "http://Example.COM/Docs/",
+                Some("example.com/Docs/index.html"),
+            ),
+            ("http://127.0.0.1:8765/", Some("127.0.0.1:8765/index.html")),
+            ("https://example.com:443/a.png", Some("example.com/a.png")),
+            ("ftp://example.com/pub/a.zip", Some("example.com/pub/a.zip")),
+            (
+                "http://example.com/page.php?id=1&x=2",
+                Some("example.com/page.php%3Fid=1&x=2"),
+            ),
+            (
+                "http://example.com/?q=1",
+                Some("example.com/index.html%3Fq=1"),
+            ),
+            (
+                "http://example.com/dir/?",
+                Some("example.com/dir/index.html%3F"),
+            ),
+            (
+                "http://example.com/search?path=a/b",
+                Some("example.com/search%3Fpath=a%2Fb"),
+            ),
+            (
+                "http://example.com/q?a=%3F%2F",
+                Some("example.com/q%3Fa=?%2F"),
+            ),
+            (
+                "http://example.com/sp%20ace?x%20y",
+                Some("example.com/sp ace%3Fx y"),
+            ),
+            (
+                "http://example.com/a%20b.html",
+                Some("example.com/a b.html"),
+            ),
+            (
+                "http://example.com/caf%C3%A9.html",
+                Some("example.com/café.html"),
+            ),
+            (
+                "http://example.com/e%E2%82%AC.txt",
+                Some("example.com/e€.txt"),
+            ),
+            ("http://example.com/c%C2%85", Some("example.com/c\u{85}")),
+            ("http://example.com/a%25b", Some("example.com/a%b")),
+            ("http://example.com/a%2Fb", Some("example.com/a%2Fb")),
+            ("http://example.com/%0A", Some("example.com/%0A")),
+            ("http://example.com/x%09y", Some("example.com/x%09y")),
+            ("http://example.com/d%7Fel", Some("example.com/d%7Fel")),
+            (
+                "http://example.com/star*.png",
+                Some("example.com/star*.png"),
+            ),
+            (
+                "http://example.com/col:on.png",
+                Some("example.com/col:on.png"),
+            ),
+            ("http://example.com/a//b.png", Some("example.com/a/b.png")),
+            ("http://example.com/a/../b.css", Some("example.com/b.css")),
+            ("http://example.com/x.js#frag", Some("example.com/x.js")),
+            // not valid UTF-8, which wget writes as it is
+            ("http://example.com/%FFz", Some("example.com/%FFz")),
+            ("data:text/plain,hello", None),
+            ("file:///Logo.png", None),
+            ("about:blank", None),
+        ];
+
+        for (url, expected) in cases {
+            let path = super::wget_path(url).map(|(path, _)| path);
+            assert_eq!(path, expected.map(PathBuf::from), "{}", url);
+        }
+    }
+
+    #[test]
+    fn plan_extraction_wget_layout() {
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource("https://example.com/docs", "text/html", "<p>hello</p>"),
+                resource(
+                    "https://example.com/docs/intro.html",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    "https://example.com/Page.php?id=1",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    "https://example.com/page.php?id=1",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource("file:///Logo.png", "text/html", "<p>hello</p>"),
+            ]),
+            subframe_archives: None,
+        };
+
+        let plan = archive.plan_extraction_with(&PlanOptions {
+            layout: ExtractLayout::Wget,
+            ..PlanOptions::default()
+        });
+        let paths: Vec<_> = plan
+            .iter()
+            .map(|planned| {
+                (
+                    planned.resource.url.as_str(),
+                    planned.path.to_str().unwrap(),
+                    planned.renamed.as_slice(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (
+                    "https://example.com/",
+                    "example.com/index.html",
+                    &[RenameReason::UnnamedIndex][..]
+                ),
+                (
+                    "https://example.com/docs",
+                    "example.com/docs/index.html",
+                    &[RenameReason::FolderConflict]
+                ),
+                (
+                    "https://example.com/docs/intro.html",
+                    "example.com/docs/intro.html",
+                    &[]
+                ),
+                (
+                    "https://example.com/Page.php?id=1",
+                    "example.com/Page.php%3Fid=1",
+                    &[]
+                ),
+                (
+                    "https://example.com/page.php?id=1",
+                    "example.com/page-2.php%3Fid=1",
+                    &[RenameReason::Collision]
+                ),
+                ("file:///Logo.png", "Logo.png", &[]),
+            ]
+        );
+    }
+
+    /// A resource served with a `Content-Disposition` header
+    fn attachment(url: &str, content_disposition: &str) -> WebResource {
+        let mut headers = Headers::default();
+        headers.append("Content-Disposition", content_disposition);
+        let response = ResourceResponse::new(Some(url.to_string()), Some(200), headers, None);
+
+        WebResource {
+            response: Some(response.to_bytes().unwrap()),
+            ..resource(url, "application/pdf", "<p>hello</p>")
+        }
+    }
+
+    #[test]
+    fn plan_extraction_unknown_schemes() {
+        let archive = || WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource("cid:logo@example.com", "image/png", "<p>hello</p>"),
+                resource(
+                    "x-apple-ql-id://8F3A2C/Preview.html",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    "safari-resource:/ErrorPage.css",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource("about:blank", "text/html", "<p>hello</p>"),
+                resource(
+                    "data:image/png;base64,iVBORw0KGgo/AAAA",
+                    "image/png",
+                    "<p>hello</p>",
+                ),
+            ]),
+            subframe_archives: None,
+        };
+        let planned =
+            |archive: &WebArchive, options: &PlanOptions| -> Vec<(PathBuf, Vec<RenameReason>)> {
+                archive
+                    .plan_extraction_with(options)
+                    .into_iter()
+                    .map(|planned| (planned.path, planned.renamed))
+                    .collect()
+            };
+
+        let fallback = planned(&archive(), &PlanOptions::default());
+        assert_eq!(
+            fallback[1..],
+            [
+                (
+                    PathBuf::from("_scheme/b65939b5a59495c7.png"),
+                    vec![RenameReason::UnknownScheme]
+                ),
+                (
+                    PathBuf::from("_scheme/fae626838ed5574a.html"),
+                    vec![RenameReason::UnknownScheme]
+                ),
+                (
+                    PathBuf::from("_scheme/aef9896e2d1abc7f.css"),
+                    vec![RenameReason::UnknownScheme]
+                ),
+                (
+                    PathBuf::from("_about/4fa72d735a519ee1.shtml"),
+                    vec![RenameReason::NoPath]
+                ),
+                (
+                    PathBuf::from("_data/b07993fe74b01e14.png"),
+                    vec![RenameReason::NoPath]
+                ),
+            ]
+        );
+
+        // the same URLs always get the same names, whatever else the
+        // archive holds, and in either layout
+        let mut reordered = archive();
+        reordered.subresources.as_mut().unwrap().reverse();
+        let wget = PlanOptions {
+            layout: ExtractLayout::Wget,
+            ..PlanOptions::default()
+        };
+        for (archive, options) in [(&archive(), &wget), (&reordered, &PlanOptions::default())] {
+            let plan = archive.plan_extraction_with(options);
+            for (url, (path, _)) in ["cid:logo@example.com", "safari-resource:/ErrorPage.css"]
+                .iter()
+                .zip([&fallback[1], &fallback[3]])
+            {
+                let planned = plan.iter().find(|planned| planned.resource.url == *url);
+                assert_eq!(&planned.unwrap().path, path);
+            }
+        }
+
+        // registered handlers come first, even for built-in schemes,
+        // and can't write outside the folder
+        let options = PlanOptions {
+            schemes: SchemeHandlers::new()
+                .register("CID", |url| {
+                    PathBuf::from("attachments").join(url.trim_start_matches("cid:"))
+                })
+                .register("about", |_| PathBuf::from("/../pages/../blank?.html"))
+                .register("x-apple-ql-id", |_| PathBuf::from("..")),
+            ..PlanOptions::default()
+        };
+        assert!(options.schemes.is_registered("cid"));
+        assert!(!options.schemes.is_registered("safari-resource"));
+        assert_eq!(
+            planned(&archive(), &options)[1..],
+            [
+                (
+                    PathBuf::from("attachments/logo@example.com"),
+                    vec![RenameReason::SchemeHandler]
+                ),
+                (
+                    PathBuf::from("_scheme/fae626838ed5574a.html"),
+                    vec![RenameReason::UnknownScheme]
+                ),
+                (
+                    PathBuf::from("_scheme/aef9896e2d1abc7f.css"),
+                    vec![RenameReason::UnknownScheme]
+                ),
+                (
+                    PathBuf::from("pages/blank_.html"),
+                    vec![RenameReason::SchemeHandler]
+                ),
+                (
+                    PathBuf::from("_data/b07993fe74b01e14.png"),
+                    vec![RenameReason::NoPath]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_disposition_file_names() {
+        let file_name = |header| content_disposition_file_name(header);
+
+        assert_eq!(
+            file_name("attachment; filename=report-final.pdf").as_deref(),
+            Some("report-final.pdf")
+        );
+        assert_eq!(
+            file_name(r#"attachment; filename="Q3 report; final \"v2\".pdf"; size=1024"#)
+                .as_deref(),
+            Some(r#"Q3 report; final "v2".pdf"#)
+        );
+        assert_eq!(
+            file_name(
+                r#"attachment; filename="EURO rates.txt"; filename*=UTF-8''%e2%82%ac%20rates.txt"#
+            )
+            .as_deref(),
+            Some("€ rates.txt")
+        );
+        assert_eq!(
+            file_name("attachment; FILENAME*=iso-8859-1'en'caf%E9.txt").as_deref(),
+            Some("café.txt")
+        );
+
+        // undecodable extended values fall back to the plain name
+        assert_eq!(
+            file_name(r#"attachment; filename*=UTF-8''%ff%zz.txt; filename="plain.txt""#)
+                .as_deref(),
+            Some("plain.txt")
+        );
+        assert_eq!(file_name("inline"), None);
+        assert_eq!(file_name("attachment; size=1024"), None);
+    }
+
+    #[test]
+    fn sanitize_file_names() {
+        assert_eq!(
+            sanitize_file_name("../../etc/passwd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            sanitize_file_name(r"C:\Temp\what?.pdf").as_deref(),
+            Some("what_.pdf")
+        );
+        assert_eq!(
+            sanitize_file_name("report\u{0}.pdf. ").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name(".bashrc"), None);
+        assert_eq!(sanitize_file_name(" "), None);
+    }
+
+    #[test]
+    fn plan_extraction_response_filenames() {
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/reports/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                attachment(
+                    "https://example.com/download/8f3a2c",
+                    r#"attachment; filename="report-final.pdf""#,
+                ),
+                attachment(
+                    "https://example.com/download/d41d8c",
+                    "attachment; filename*=UTF-8''Report-Final.pdf",
+                ),
+                attachment("https://example.com/download/0cc175", "attachment"),
+            ]),
+            subframe_archives: None,
+        };
+
+        let names = |options: &PlanOptions| -> Vec<(PathBuf, NameSource)> {
+            archive
+                .plan_extraction_with(options)
+                .into_iter()
+                .map(|planned| (planned.path, planned.name_source))
+                .collect()
+        };
+
+        assert_eq!(
+            names(&PlanOptions::default())[1..],
+            [
+                (
+                    PathBuf::from("example.com/download/8f3a2c"),
+                    NameSource::Url
+                ),
+                (
+                    PathBuf::from("example.com/download/d41d8c"),
+                    NameSource::Url
+                ),
+                (
+                    PathBuf::from("example.com/download/0cc175"),
+                    NameSource::Url
+                ),
+            ]
+        );
+        assert_eq!(
+            names(&PlanOptions {
+                use_response_filenames: true,
+                ..PlanOptions::default()
+            }),
+            [
+                (
+                    PathBuf::from("example.com/reports/_unnamed_index.shtml"),
+                    NameSource::Url
+                ),
+                (
+                    PathBuf::from("example.com/download/report-final.pdf"),
+                    NameSource::ContentDisposition
+                ),
+                (
+                    PathBuf::from("example.com/download/Report-Final-2.pdf"),
+                    NameSource::ContentDisposition
+                ),
+                (
+                    PathBuf::from("example.com/download/0cc175"),
+                    NameSource::Url
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_extraction_renamed() {
+        let renamed = |archive: &WebArchive, options: &PlanOptions| -> Vec<Vec<RenameReason>> {
+            archive
+                .plan_extraction_with(options)
+                .into_iter()
+                .map(|planned| planned.renamed)
+                .collect()
+        };
+
+        let archive: WebArchive = crate::from_file("fixtures/host-only.webarchive")
+            .expect("Could not read host-only webarchive fixture");
+        assert_eq!(
+            renamed(&archive, &PlanOptions::default()),
+            [
+                vec![RenameReason::UnnamedIndex],
+                vec![],
+                vec![RenameReason::FolderConflict],
+                vec![],
+            ]
+        );
+
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource("https://example.com/a.png", "text/html", "<p>hello</p>"),
+                resource("https://example.com/A.png", "text/html", "<p>hello</p>"),
+                attachment(
+                    "https://example.com/download/8f3a2c",
+                    r#"attachment; filename="../a?.png""#,
+                ),
+                attachment(
+                    "https://example.com/download/d41d8c",
+                    r#"attachment; filename="a_.png""#,
+                ),
+            ]),
+            subframe_archives: None,
+        };
+        assert_eq!(
+            renamed(
+                &archive,
+                &PlanOptions {
+                    use_response_filenames: true,
+                    ..PlanOptions::default()
+                }
+            ),
+            [
+                vec![RenameReason::UnnamedIndex],
+                vec![],
+                vec![RenameReason::Collision],
+                vec![RenameReason::Sanitized],
+                vec![RenameReason::Collision],
+            ]
+        );
+    }
+
+    #[test]
+    fn extraction_manifest() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let manifest = ExtractionManifest {
+            files: archive
+                .plan_extraction()
+                .iter()
+                .map(|planned| ExtractedFile::new(planned, planned.resource))
+                .collect(),
+        };
+        manifest.write_to(temp.path()).unwrap();
+        assert_eq!(
+            ExtractionManifest::read_from(temp.path()).unwrap(),
+            manifest
+        );
+
+        let logo = manifest
+            .get("http://psxdatacenter.com/images/logo.jpg")
+            .unwrap();
+        assert_eq!(logo.path, "psxdatacenter.com/images/logo.jpg");
+        assert_eq!(logo.size, 32099);
+        assert_eq!(
+            logo.sha256,
+            "b57e8ebab9a4d01f88422408fffdfab3cf43c1312eeb2e68e6a5406b481781d6"
+        );
+        assert_eq!(logo.frame, [0]);
+        assert!(manifest.files[0].frame.is_empty());
+    }
+
+    #[test]
+    fn extraction_manifest_renames() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let long = format!("https://example.com/{}.png", "a".repeat(300));
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", "<p>hello</p>"),
+            subresources: Some(vec![
+                resource(&long, "text/html", "<p>hello</p>"),
+                resource(
+                    "https://example.com/search?q=a",
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+                resource(
+                    &format!("https://example.com/{}?q=a", "b".repeat(300)),
+                    "text/html",
+                    "<p>hello</p>",
+                ),
+            ]),
+            subframe_archives: None,
+        };
+
+        let manifest = ExtractionManifest {
+            files: archive
+                .plan_extraction()
+                .iter()
+                .map(|planned| ExtractedFile::new(planned, planned.resource))
+                .collect(),
+        };
+        manifest.write_to(temp.path()).unwrap();
+        let manifest = ExtractionManifest::read_from(temp.path()).unwrap();
+
+        let truncated = manifest.get(&long).unwrap();
+        assert_eq!(truncated.renamed, [RenameReason::Truncated]);
+        assert!(truncated.path.ends_with(".png"));
+        assert_eq!(
+            manifest
+                .get("https://example.com/search?q=a")
+                .unwrap()
+                .renamed,
+            [RenameReason::Sanitized]
+        );
+        assert_eq!(
+            manifest.files[3].renamed,
+            [RenameReason::Sanitized, RenameReason::Truncated]
+        );
+
+        let json =
+            std::fs::read_to_string(temp.path().join(ExtractionManifest::FILE_NAME)).unwrap();
+        assert!(json.contains(r#""truncated""#));
+        assert!(json.contains(r#""sanitized""#));
+    }
+
+    #[test]
+    fn extract_to() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        let written = archive
+            .extract_to(temp.path(), &OperationHooks::default())
+            .unwrap();
+
+        assert_eq!(written.len(), archive.plan_extraction().len());
+        for (path, planned) in written.iter().zip(archive.plan_extraction()) {
+            assert_eq!(*path, temp.path().join(&planned.path));
+            assert_eq!(std::fs::read(path).unwrap(), planned.resource.data);
+        }
+    }
+
+    #[test]
+    fn extract_to_cancelled() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        // cancel part way through writing the large list of games
+        let plan = archive.plan_extraction();
+        let index = plan
+            .iter()
+            .position(|planned| planned.resource.url == "http://psxdatacenter.com/jlist.html")
+            .unwrap();
+        let before: u64 = plan[..index]
+            .iter()
+            .map(|planned| planned.resource.data.len() as u64)
+            .sum();
+        assert!(plan[index].resource.data.len() > 1_000_000);
+
+        let stop = AtomicBool::new(false);
+        let signalled = AtomicBool::new(false);
+        let barrier = Barrier::new(2);
+
+        let result = std::thread::scope(|scope| {
+            // another thread, such as a GUI's, decides to stop, while
+            // the extraction waits for it
+            scope.spawn(|| {
+                barrier.wait();
+                stop.store(true, Ordering::Relaxed);
+                barrier.wait();
+            });
+
+            let hooks = OperationHooks::new()
+                .on_progress(|progress| {
+                    if progress.bytes_done >= before + 500_000
+                        && !signalled.swap(true, Ordering::Relaxed)
+                    {
+                        assert_eq!(progress.items_done, index);
+                        barrier.wait();
+                        barrier.wait();
+                    }
+                })
+                .cancel_when(|| stop.load(Ordering::Relaxed));
+
+            archive.extract_to(temp.path(), &hooks)
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+
+        // the files before it are kept whole, but it is removed
+        for planned in &plan[..index] {
+            let data = std::fs::read(temp.path().join(&planned.path)).unwrap();
+            assert_eq!(data, planned.resource.data);
+        }
+        assert!(!temp.path().join(&plan[index].path).exists());
+        for planned in &plan[index + 1..] {
+            assert!(!temp.path().join(&planned.path).exists());
+        }
+    }
+}
@@ -0,0 +1,760 @@
+use crate::retry::TokioClock;
+use crate::text::parse_content_type;
+use crate::{
+    CaptureInfo, Error, Headers, OperationHooks, Phase, Progress, ReferenceKind, ResourceResponse,
+    Result, RetryPolicy, WebArchive, WebResource,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Options for fetching resources over HTTP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchOptions {
+    /// How long to wait for each request to complete.
+    pub timeout: Duration,
+
+    /// How long the whole operation may take, including every request
+    /// and retry. Requests not yet made when it passes fail with
+    /// [`Error::DeadlineExceeded`].
+    pub deadline: Option<Duration>,
+
+    /// When to retry requests which fail.
+    pub retry: RetryPolicy,
+
+    /// The most requests to have in flight at once, across all origins.
+    pub concurrency: usize,
+
+    /// The most requests to have in flight at once to any one origin,
+    /// as browsers limit themselves to.
+    pub concurrency_per_origin: usize,
+
+    /// The `User-Agent` header to send.
+    pub user_agent: String,
+
+    /// Record when and how [`WebArchive::fetch`] captured the page, in
+    /// a [`CaptureInfo`] stored in the archive.
+    pub capture_info: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            timeout: Duration::from_secs(30),
+            deadline: None,
+            retry: RetryPolicy::default(),
+            concurrency: 24,
+            concurrency_per_origin: 6,
+            user_agent: format!("webarchive/{}", env!("CARGO_PKG_VERSION")),
+            capture_info: true,
+        }
+    }
+}
+
+/// Makes the requests for one operation, such as
+/// [`WebArchive::fetch`], as its [`FetchOptions`] say.
+pub(crate) struct Fetcher<'a> {
+    client: reqwest::Client,
+    options: &'a FetchOptions,
+    deadline: Option<Instant>,
+    limiter: Limiter,
+}
+
+impl<'a> Fetcher<'a> {
+    /// Start an operation, and the clock on its deadline.
+    pub(crate) fn new(options: &'a FetchOptions) -> Result<Self> {
+        Ok(Fetcher {
+            client: reqwest::Client::builder()
+                .user_agent(&options.user_agent)
+                .build()?,
+            options,
+            deadline: options.deadline.map(|deadline| Instant::now() + deadline),
+            limiter: Limiter::new(options),
+        })
+    }
+
+    /// Make a `GET` request for `url`, after `customize` has added to
+    /// it, retrying as the options say.
+    ///
+    /// Responses with an unsuccessful status are returned as they
+    /// are, once they have been retried, rather than as errors.
+    pub(crate) async fn get(
+        &self,
+        url: &str,
+        customize: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let retry = &self.options.retry;
+
+        retry
+            .run(
+                &TokioClock,
+                self.options.timeout,
+                self.deadline,
+                |timeout| {
+                    let request = customize(self.client.get(url)).timeout(timeout);
+                    async move { Ok(request.send().await?) }
+                },
+                |result| match result {
+                    Ok(response) => retry.retries_status(response.status().as_u16()),
+                    Err(Error::Fetch(error)) => error.is_timeout() || error.is_connect(),
+                    Err(_) => false,
+                },
+            )
+            .await
+    }
+
+    /// Run `requests`, each for the URL it is paired with, no more at
+    /// once than the options allow, returning their results in the
+    /// same order as `requests`, however long each takes.
+    pub(crate) async fn run_limited<T, F: Future<Output = T>>(
+        &self,
+        requests: impl IntoIterator<Item = (String, F)>,
+    ) -> Vec<T> {
+        futures::future::join_all(
+            requests
+                .into_iter()
+                .map(|(url, request)| self.limiter.run(url, request)),
+        )
+        .await
+    }
+}
+
+/// Limits how many requests run at once, overall and to each origin.
+struct Limiter {
+    overall: Semaphore,
+    per_origin: usize,
+    origins: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl Limiter {
+    fn new(options: &FetchOptions) -> Self {
+        Limiter {
+            overall: Semaphore::new(options.concurrency.max(1)),
+            per_origin: options.concurrency_per_origin.max(1),
+            origins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn run<T>(&self, url: String, request: impl Future<Output = T>) -> T {
+        let origin = match url::Url::parse(&url) {
+            Ok(url) => url.origin().ascii_serialization(),
+            Err(_) => url,
+        };
+        let semaphore = self
+            .origins
+            .lock()
+            .unwrap()
+            .entry(origin)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_origin)))
+            .clone();
+
+        // wait for the origin first, so requests queued for a busy
+        // origin don't hold up requests to others
+        let _origin = semaphore.acquire().await;
+        let _overall = self.overall.acquire().await;
+        request.await
+    }
+}
+
+/// A URL which could not be fetched, in a [`FetchReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FetchFailure {
+    /// The URL requested.
+    pub url: String,
+
+    /// Why it could not be fetched.
+    pub reason: String,
+}
+
+/// The result of fetching subresources, as by
+/// [`WebArchive::fetch_missing`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FetchReport {
+    /// The URLs fetched and added to the archive, in the order they
+    /// were added.
+    pub fetched: Vec<String>,
+
+    /// The URLs which could not be fetched, in the order they were found.
+    pub failed: Vec<FetchFailure>,
+}
+
+impl WebArchive {
+    /// Make an archive of the page at `url`, by fetching it and then
+    /// its subresources, as [`fetch_missing`](WebArchive::fetch_missing)
+    /// does.
+    ///
+    /// Fails if the page itself can not be fetched, but not if any of
+    /// its subresources can not, which are listed in the report instead.
+    ///
+    /// Unless [`capture_info`](FetchOptions::capture_info) is turned off,
+    /// when the capture started, the URL requested and the `User-Agent`
+    /// sent are recorded in the archive, to be read back with
+    /// [`capture_info`](WebArchive::capture_info).
+    pub async fn fetch(url: &str, options: &FetchOptions) -> Result<(WebArchive, FetchReport)> {
+        Self::fetch_with_hooks(url, options, &OperationHooks::default()).await
+    }
+
+    /// Make an archive of the page at `url`, as
+    /// [`fetch`](WebArchive::fetch) does, reporting the resources
+    /// fetched to `hooks`.
+    ///
+    /// If they say to stop, no more requests are made, and this fails
+    /// with [`Error::Cancelled`] once those in flight have finished.
+    pub async fn fetch_with_hooks(
+        url: &str,
+        options: &FetchOptions,
+        hooks: &OperationHooks<'_>,
+    ) -> Result<(WebArchive, FetchReport)> {
+        let fetcher = Fetcher::new(options)?;
+        hooks.check()?;
+        let capture_info = CaptureInfo::new(url, Some(options.user_agent.clone()));
+        let main_resource = fetch_resource(&fetcher, url).await?;
+
+        let mut progress = Progress::new(Phase::Fetching);
+        progress.items_done = 1;
+        progress.items_total = Some(1);
+        progress.bytes_done = main_resource.data.len() as u64;
+        hooks.report(progress);
+
+        let mut archive = WebArchive {
+            main_resource,
+            subresources: None,
+            subframe_archives: None,
+        };
+        let report = archive
+            .fetch_missing_with(&fetcher, hooks, progress)
+            .await?;
+
+        if options.capture_info {
+            archive.embed_capture_info(&capture_info);
+        }
+
+        Ok((archive, report))
+    }
+
+    /// Fetch the `http` and `https` URLs the archive refers to, but does
+    /// not store, as listed by
+    /// [`missing_subresources`](WebArchive::missing_subresources), and
+    /// add them to its subresources. The references within those which
+    /// are fetched, such as images in stylesheets, are then fetched in
+    /// turn. Documents in frames are not fetched.
+    ///
+    /// Requests are made concurrently, as set by `options`, but
+    /// subresources are added in the order they are referred to, so
+    /// fetching the same page always gives the same archive.
+    pub async fn fetch_missing(&mut self, options: &FetchOptions) -> Result<FetchReport> {
+        self.fetch_missing_with_hooks(options, &OperationHooks::default())
+            .await
+    }
+
+    /// Fetch the subresources the archive is missing, as
+    /// [`fetch_missing`](WebArchive::fetch_missing) does, reporting
+    /// each request as it finishes to `hooks`.
+    ///
+    /// If they say to stop, no more requests are made, and this fails
+    /// with [`Error::Cancelled`] once those in flight have finished,
+    /// leaving the archive as it was.
+    pub async fn fetch_missing_with_hooks(
+        &mut self,
+        options: &FetchOptions,
+        hooks: &OperationHooks<'_>,
+    ) -> Result<FetchReport> {
+        let fetcher = Fetcher::new(options)?;
+        self.fetch_missing_with(&fetcher, hooks, Progress::new(Phase::Fetching))
+            .await
+    }
+
+    /// Fetch the subresources the archive is missing, adding the
+    /// requests made to those already counted in `progress`.
+    async fn fetch_missing_with(
+        &mut self,
+        fetcher: &Fetcher<'_>,
+        hooks: &OperationHooks<'_>,
+        progress: Progress,
+    ) -> Result<FetchReport> {
+        let mut report = FetchReport::default();
+        let mut requested = HashSet::new();
+        let stored = self.subresources.as_ref().map(Vec::len);
+        let progress = Mutex::new(progress);
+
+        loop {
+            let urls: Vec<String> = self
+                .missing_subresources()?
+                .into_iter()
+                .filter(|reference| {
+                    reference.kind != ReferenceKind::Frame
+                        && (reference.url.starts_with("http://")
+                            || reference.url.starts_with("https://"))
+                        && requested.insert(reference.url.clone())
+                })
+                .map(|reference| reference.url)
+                .collect();
+
+            if urls.is_empty() {
+                return Ok(report);
+            }
+
+            {
+                // more are found as each round's resources are fetched
+                let mut progress = progress.lock().unwrap();
+                let done = progress.items_done;
+                *progress.items_total.get_or_insert(done) += urls.len();
+            }
+
+            let results = fetcher
+                .run_limited(urls.iter().map(|url| {
+                    (url.clone(), async {
+                        hooks.check()?;
+                        let result = fetch_resource(fetcher, url).await;
+
+                        let mut progress = progress.lock().unwrap();
+                        progress.items_done += 1;
+                        if let Ok(resource) = &result {
+                            progress.bytes_done += resource.data.len() as u64;
+                        }
+                        hooks.report(*progress);
+                        result
+                    })
+                }))
+                .await;
+
+            for (url, result) in urls.into_iter().zip(results) {
+                match result {
+                    Ok(resource) => {
+                        self.subresources
+                            .get_or_insert_with(Vec::new)
+                            .push(resource);
+                        report.fetched.push(url);
+                    }
+                    Err(Error::Cancelled) => {
+                        match stored {
+                            Some(stored) => self.subresources.as_mut().unwrap().truncate(stored),
+                            None => self.subresources = None,
+                        }
+                        return Err(Error::Cancelled);
+                    }
+                    Err(error) => report.failed.push(FetchFailure {
+                        url,
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Fetch `url` as a resource, failing on any unsuccessful status.
+async fn fetch_resource(fetcher: &Fetcher<'_>, url: &str) -> Result<WebResource> {
+    let response = fetcher
+        .get(url, |request| request)
+        .await?
+        .error_for_status()?;
+
+    let mut resource = WebResource::from_reqwest_response(response).await?;
+    // stored under the URL it is referred to by, before any redirects
+    resource.url = url.to_string();
+    Ok(resource)
+}
+
+/// The MIME type and charset from a response's `Content-Type` header,
+/// guessing the MIME type from `url` if there is none.
+fn content_type(headers: &reqwest::header::HeaderMap, url: &str) -> (String, Option<String>) {
+    parse_content_type(
+        headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default(),
+        url,
+    )
+}
+
+impl WebResource {
+    /// Make a resource of a response, reading its body.
+    ///
+    /// The resource's URL is the one the response came from, after
+    /// any redirects. Its MIME type and encoding come from the
+    /// `Content-Type` header, and its status and headers are kept as
+    /// its archived [`response`](WebResource::response), whatever the
+    /// status is.
+    pub async fn from_reqwest_response(response: reqwest::Response) -> Result<WebResource> {
+        let url = response.url().clone();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let data = response.bytes().await?.to_vec();
+
+        resource_from_response(&url, status, &headers, data)
+    }
+
+    /// Make a resource of a response from [`reqwest::blocking`],
+    /// reading its body, as
+    /// [`from_reqwest_response`](WebResource::from_reqwest_response) does.
+    pub fn from_blocking_reqwest_response(
+        response: reqwest::blocking::Response,
+    ) -> Result<WebResource> {
+        let url = response.url().clone();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let data = response.bytes()?.to_vec();
+
+        resource_from_response(&url, status, &headers, data)
+    }
+}
+
+fn resource_from_response(
+    url: &reqwest::Url,
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    data: Vec<u8>,
+) -> Result<WebResource> {
+    let (mime_type, charset) = content_type(headers, url.as_str());
+
+    let mut archived_headers = Headers::default();
+    for (name, value) in headers {
+        archived_headers.append(name.as_str(), &String::from_utf8_lossy(value.as_bytes()));
+    }
+    let response = ResourceResponse::new(
+        Some(url.to_string()),
+        Some(status.as_u16()),
+        archived_headers,
+        Some(mime_type.clone()),
+    );
+
+    Ok(WebResource {
+        data,
+        url: url.to_string(),
+        frame_name: None,
+        mime_type,
+        text_encoding_name: charset,
+        response: Some(response.to_bytes()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_server::{Response, TestServer};
+    use crate::test_support::resource;
+    use crate::{Error, FetchOptions, OperationHooks, Phase, RetryPolicy, WebArchive, WebResource};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// A server for images which each take `latency` to serve, less
+    /// for later images, so they finish in the opposite order to that
+    /// they are requested in, counting the most requests it sees at once.
+    fn slow_server(latency: Duration) -> (TestServer, Arc<AtomicUsize>) {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let most = most_in_flight.clone();
+        let server = TestServer::start(move |request| {
+            let number: u32 = request
+                .path
+                .trim_start_matches("/image")
+                .trim_end_matches(".gif")
+                .parse()
+                .unwrap_or(0);
+
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(latency / (number + 1));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            Response::ok(format!("GIF89a{}", request.path).into_bytes())
+        });
+
+        (server, most_in_flight)
+    }
+
+    #[test]
+    fn fetch_concurrently() {
+        let latency = Duration::from_millis(400);
+        let (first, first_most) = slow_server(latency);
+        let (second, second_most) = slow_server(latency);
+
+        let mut html = String::new();
+        for number in 0..8 {
+            for server in [&first, &second] {
+                html.push_str(&format!(
+                    r#"<img src="{}">"#,
+                    server.url(&format!("/image{}.gif", number))
+                ));
+            }
+        }
+        let page = TestServer::start(move |_| {
+            Response::ok(html.clone().into_bytes()).header("Content-Type", "text/html")
+        });
+
+        let options = FetchOptions {
+            concurrency_per_origin: 4,
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let (archive, report) = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(WebArchive::fetch(&page.url("/"), &options))
+            .unwrap();
+        let elapsed = started.elapsed();
+
+        assert!(report.failed.is_empty());
+        assert_eq!(report.fetched.len(), 16);
+
+        // one at a time, these would take over 2.7 seconds
+        assert!(elapsed < latency * 4, "took {:?}", elapsed);
+        assert!((2..=4).contains(&first_most.load(Ordering::SeqCst)));
+        assert!((2..=4).contains(&second_most.load(Ordering::SeqCst)));
+
+        // subresources are in the order the page refers to them,
+        // not the order they finished in
+        let expected: Vec<String> = (0..8)
+            .flat_map(|number| {
+                [&first, &second].map(|server| server.url(&format!("/image{}.gif", number)))
+            })
+            .collect();
+        let mut urls: Vec<&String> = archive
+            .subresources
+            .iter()
+            .flatten()
+            .map(|resource| &resource.url)
+            .collect();
+        // followed by the record of the capture
+        assert_eq!(urls.pop().unwrap(), crate::CAPTURE_INFO_URL);
+        assert_eq!(urls, expected.iter().collect::<Vec<_>>());
+        assert_eq!(report.fetched, expected);
+
+        let info = archive.capture_info().unwrap();
+        assert_eq!(info.url, page.url("/"));
+        assert_eq!(info.user_agent.as_ref(), Some(&options.user_agent));
+        let archive: WebArchive = crate::from_bytes(&archive.to_vec_binary().unwrap()).unwrap();
+        assert_eq!(archive.capture_info(), Some(info));
+    }
+
+    /// A server for a stylesheet with a background image, and a page
+    /// referring to it and to an image the server does not have,
+    /// counting the requests for the background.
+    fn stylesheet_server() -> (TestServer, WebArchive, Arc<AtomicUsize>) {
+        let background_requests = Arc::new(AtomicUsize::new(0));
+
+        let requests = background_requests.clone();
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/style.css" => Response::ok(b"body { background: url(bg.png) }".to_vec())
+                .header("Content-Type", "text/css; charset=utf-8")
+                .header("Date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            "/bg.png" => {
+                requests.fetch_add(1, Ordering::SeqCst);
+                Response::ok(b"\x89PNG\r\n\x1a\n".to_vec())
+            }
+            _ => Response::status(404),
+        });
+
+        let archive = WebArchive {
+            main_resource: resource(
+                &server.url("/"),
+                "text/html",
+                br#"<link rel="stylesheet" href="style.css"><img src="missing.gif"><iframe src="frame.html"></iframe><img src="data:,">"#,
+            ),
+            subresources: None,
+            subframe_archives: None,
+        };
+
+        (server, archive, background_requests)
+    }
+
+    #[test]
+    fn fetch_missing() {
+        let (server, mut archive, _) = stylesheet_server();
+
+        let report = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(archive.fetch_missing(&FetchOptions::default()))
+            .unwrap();
+
+        assert_eq!(
+            report.fetched,
+            [server.url("/style.css"), server.url("/bg.png")]
+        );
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].url, server.url("/missing.gif"));
+        assert!(report.failed[0].reason.contains("404"));
+
+        let subresources = archive.subresources.as_ref().unwrap();
+        assert_eq!(subresources[0].mime_type, "text/css");
+        assert_eq!(subresources[0].text_encoding_name.as_deref(), Some("utf-8"));
+        assert_eq!(subresources[1].mime_type, "image/png");
+        assert_eq!(subresources[1].text_encoding_name, None);
+
+        // the responses are archived too, and survive being written
+        let archive: WebArchive = crate::from_bytes(&archive.to_vec_binary().unwrap()).unwrap();
+        let stylesheet = &archive.subresources.as_ref().unwrap()[0];
+        let response = stylesheet.parse_response().unwrap().unwrap();
+        assert_eq!(response.url, Some(server.url("/style.css")));
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.mime_type.as_deref(), Some("text/css"));
+        assert_eq!(
+            response.headers.get("content-type"),
+            Some("text/css; charset=utf-8")
+        );
+        assert_eq!(
+            response.date(),
+            Some(std::time::UNIX_EPOCH + Duration::from_secs(784_111_777))
+        );
+    }
+
+    #[test]
+    fn fetch_missing_hooks() {
+        let (_server, mut archive, background_requests) = stylesheet_server();
+
+        let reports = Mutex::new(Vec::new());
+        let hooks = OperationHooks::new().on_progress(|progress| {
+            assert_eq!(progress.phase, Phase::Fetching);
+            reports.lock().unwrap().push(progress);
+        });
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(archive.fetch_missing_with_hooks(&FetchOptions::default(), &hooks))
+            .unwrap();
+        drop(hooks);
+
+        // the background is only found once the stylesheet is fetched
+        let reports = reports.into_inner().unwrap();
+        let counts: Vec<_> = reports
+            .iter()
+            .map(|progress| (progress.items_done, progress.items_total))
+            .collect();
+        assert_eq!(counts, [(1, Some(2)), (2, Some(2)), (3, Some(3))]);
+        assert_eq!(reports[2].bytes_done, 32 + 8);
+
+        // stop once the first round has finished
+        let (_server, mut archive, background_requests_after_cancel) = stylesheet_server();
+        let stop = AtomicBool::new(false);
+        let hooks = OperationHooks::new()
+            .on_progress(|progress| {
+                if progress.items_done == 2 {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            })
+            .cancel_when(|| stop.load(Ordering::SeqCst));
+
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(archive.fetch_missing_with_hooks(&FetchOptions::default(), &hooks));
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(archive.subresources, None);
+        assert_eq!(background_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(background_requests_after_cancel.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn fetch_retries() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/" => Response::ok(br#"<img src="flaky.gif"><img src="broken.gif">"#.to_vec())
+                .header("Content-Type", "text/html"),
+            // fails twice, then works
+            "/flaky.gif" if counted.fetch_add(1, Ordering::SeqCst) < 2 => Response::status(503),
+            "/flaky.gif" => Response::ok(b"GIF89a".to_vec()),
+            _ => Response::status(500),
+        });
+
+        let options = FetchOptions {
+            retry: RetryPolicy {
+                initial_backoff: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (archive, report) = runtime
+            .block_on(WebArchive::fetch(&server.url("/"), &options))
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(report.fetched, [server.url("/flaky.gif")]);
+        assert_eq!(archive.subresources.unwrap()[0].data, b"GIF89a");
+
+        // the final error is reported once retries run out
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].url, server.url("/broken.gif"));
+        assert!(report.failed[0].reason.contains("500"));
+
+        // without retries, the flaky image is not archived
+        requests.store(0, Ordering::SeqCst);
+        let options = FetchOptions {
+            retry: RetryPolicy::none(),
+            capture_info: false,
+            ..Default::default()
+        };
+        let (archive, report) = runtime
+            .block_on(WebArchive::fetch(&server.url("/"), &options))
+            .unwrap();
+        assert!(archive.subresources.is_none());
+        assert_eq!(report.failed.len(), 2);
+
+        // nothing is fetched once the deadline has passed
+        let options = FetchOptions {
+            deadline: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(matches!(
+            runtime.block_on(WebArchive::fetch(&server.url("/"), &options)),
+            Err(Error::DeadlineExceeded)
+        ));
+    }
+
+    /// A server which redirects `/old` to `/new`, a Shift_JIS page.
+    fn redirecting_server() -> TestServer {
+        TestServer::start(|request| match request.path.as_str() {
+            "/old" => Response::status(301).header("Location", "/new"),
+            "/new" => Response::ok(b"<p>\x93\xfa\x96\x7b</p>".to_vec())
+                .header("Content-Type", "text/html; charset=\"Shift_JIS\"")
+                .header("Set-Cookie", "a=1")
+                .header("Set-Cookie", "b=2"),
+            _ => Response::status(404),
+        })
+    }
+
+    fn assert_redirected(server: &TestServer, resource: &WebResource) {
+        assert_eq!(resource.url, server.url("/new"));
+        assert_eq!(resource.mime_type, "text/html");
+        assert_eq!(resource.text_encoding_name.as_deref(), Some("Shift_JIS"));
+        assert_eq!(resource.decoded_text().unwrap(), "<p>日本</p>");
+
+        let response = resource.parse_response().unwrap().unwrap();
+        assert_eq!(response.url, Some(server.url("/new")));
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.mime_type.as_deref(), Some("text/html"));
+        assert_eq!(
+            response.headers.get("content-type"),
+            Some("text/html; charset=\"Shift_JIS\"")
+        );
+        assert_eq!(response.headers.get("set-cookie"), Some("a=1, b=2"));
+    }
+
+    #[test]
+    fn resource_from_reqwest_response() {
+        let server = redirecting_server();
+
+        let resource = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async {
+                let response = reqwest::get(server.url("/old")).await?;
+                WebResource::from_reqwest_response(response).await
+            })
+            .unwrap();
+        assert_redirected(&server, &resource);
+
+        let response = reqwest::blocking::get(server.url("/old")).unwrap();
+        let resource = WebResource::from_blocking_reqwest_response(response).unwrap();
+        assert_redirected(&server, &resource);
+
+        // unsuccessful responses are kept, with their status
+        let response = reqwest::blocking::get(server.url("/gone")).unwrap();
+        let resource = WebResource::from_blocking_reqwest_response(response).unwrap();
+        assert_eq!(resource.response_status(), Some(404));
+        assert_eq!(resource.mime_type, "application/octet-stream");
+    }
+}
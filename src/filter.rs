@@ -0,0 +1,590 @@
+use crate::{Error, FilterExpression, Result, WebArchive, WebResource};
+
+/// Criteria for choosing some of the resources in an archive.
+///
+/// The default filter matches every resource.
+///
+/// ```rust
+/// # use anyhow::Result;
+/// use webarchive::{ResourceFilter, WebArchive};
+///
+/// # fn main() -> Result<()> {
+/// let archive: WebArchive = webarchive::from_file("fixtures/psxdatacenter.webarchive")?;
+///
+/// let filter = ResourceFilter {
+///     mime: Some("image/gif".to_string()),
+///     ..Default::default()
+/// };
+/// assert_eq!(archive.filter_resources(&filter).count(), 11);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceFilter {
+    /// Only match subresources, not the main resources of frames.
+    pub subresources_only: bool,
+
+    /// Only match the main resources of frames, not subresources.
+    pub frames_only: bool,
+
+    /// Only match resources with this MIME type, ignoring parameters
+    /// and case. A subtype of `*` matches any subtype, as in `image/*`.
+    pub mime: Option<String>,
+
+    /// Only match resources with at least this many bytes of data.
+    pub min_size: Option<usize>,
+
+    /// Only match resources with at most this many bytes of data.
+    pub max_size: Option<usize>,
+
+    /// Only match resources whose URLs match this.
+    pub url: Option<UrlMatcher>,
+
+    /// Only match resources for which this expression holds, as made
+    /// by [`ResourceFilter::parse`].
+    pub expression: Option<FilterExpression>,
+}
+
+impl ResourceFilter {
+    /// Whether `resource` meets the criteria, where `is_main_resource`
+    /// says whether it is the main resource of its frame.
+    pub fn matches(&self, resource: &WebResource, is_main_resource: bool) -> bool {
+        if self.subresources_only && is_main_resource {
+            return false;
+        }
+
+        if self.frames_only && !is_main_resource {
+            return false;
+        }
+
+        if let Some(pattern) = &self.mime {
+            if !mime_matches(pattern, &resource.mime_essence()) {
+                return false;
+            }
+        }
+
+        if self
+            .min_size
+            .is_some_and(|min_size| resource.data.len() < min_size)
+        {
+            return false;
+        }
+
+        if self
+            .max_size
+            .is_some_and(|max_size| resource.data.len() > max_size)
+        {
+            return false;
+        }
+
+        if let Some(matcher) = &self.url {
+            if !matcher.is_match(&resource.url) {
+                return false;
+            }
+        }
+
+        if let Some(expression) = &self.expression {
+            if !expression.matches(resource, is_main_resource) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A reusable test for resource URLs, made of exact URLs, wildcard
+/// patterns and regular expressions, any of which may match.
+///
+/// In wildcard patterns, `*` matches any run of characters, including
+/// `/`, so `**` means the same as `*`, and `*.css` matches stylesheets
+/// in any folder on any host. `?` matches any single character, and
+/// every other character only matches itself. Patterns must match the
+/// whole URL, including any query string.
+///
+/// Regular expressions use the syntax of the [`regex`] crate, and match
+/// anywhere in the URL unless anchored with `^` or `$`.
+///
+/// ```rust
+/// # use anyhow::Result;
+/// use webarchive::{UrlMatcher, WebArchive};
+///
+/// # fn main() -> Result<()> {
+/// let archive: WebArchive = webarchive::from_file("fixtures/psxdatacenter.webarchive")?;
+///
+/// let buttons = UrlMatcher::glob("*/buttons/*.gif");
+/// assert_eq!(archive.query(&buttons).len(), 11);
+///
+/// let logos = UrlMatcher::regex(r"logo\.(gif|jpg)$")?;
+/// assert_eq!(archive.query(&buttons.or(logos)).len(), 12);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`regex`]: https://docs.rs/regex
+#[derive(Debug, Clone)]
+pub struct UrlMatcher {
+    patterns: Vec<UrlPattern>,
+}
+
+#[derive(Debug, Clone)]
+enum UrlPattern {
+    Exact(String),
+    Glob(String),
+    Regex(regex::Regex),
+}
+
+impl UrlMatcher {
+    /// Match exactly this URL.
+    pub fn exact(url: &str) -> Self {
+        UrlMatcher {
+            patterns: vec![UrlPattern::Exact(url.to_string())],
+        }
+    }
+
+    /// Match URLs with a wildcard pattern; see [`glob_matches`].
+    pub fn glob(pattern: &str) -> Self {
+        UrlMatcher {
+            patterns: vec![UrlPattern::Glob(pattern.to_string())],
+        }
+    }
+
+    /// Match URLs containing a match for a regular expression.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        let regex =
+            regex::Regex::new(pattern).map_err(|error| Error::InvalidPattern(error.to_string()))?;
+
+        Ok(UrlMatcher {
+            patterns: vec![UrlPattern::Regex(regex)],
+        })
+    }
+
+    /// Match URLs which match any of `matchers`. If there are none,
+    /// nothing matches.
+    pub fn any(matchers: impl IntoIterator<Item = UrlMatcher>) -> Self {
+        UrlMatcher {
+            patterns: matchers
+                .into_iter()
+                .flat_map(|matcher| matcher.patterns)
+                .collect(),
+        }
+    }
+
+    /// Match URLs which match either this or `other`.
+    pub fn or(mut self, other: UrlMatcher) -> Self {
+        self.patterns.extend(other.patterns);
+        self
+    }
+
+    /// Whether `url` matches.
+    pub fn is_match(&self, url: &str) -> bool {
+        self.patterns.iter().any(|pattern| match pattern {
+            UrlPattern::Exact(exact) => exact == url,
+            UrlPattern::Glob(glob) => glob_matches(glob, url),
+            UrlPattern::Regex(regex) => regex.is_match(url),
+        })
+    }
+}
+
+impl PartialEq for UrlPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (UrlPattern::Exact(a), UrlPattern::Exact(b)) => a == b,
+            (UrlPattern::Glob(a), UrlPattern::Glob(b)) => a == b,
+            (UrlPattern::Regex(a), UrlPattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for UrlPattern {}
+
+impl PartialEq for UrlMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl Eq for UrlMatcher {}
+
+/// Parse a size in bytes, such as `512`, `10k` or `1.5M`.
+///
+/// The suffixes `k`, `M` and `G` multiply by powers of 1024, and may be
+/// in either case, and followed by `B` or `iB`. Fractional sizes are
+/// rounded down to a whole number of bytes.
+///
+/// ```rust
+/// assert_eq!(webarchive::parse_size("10k").unwrap(), 10_240);
+/// assert!(webarchive::parse_size("ten").is_err());
+/// ```
+pub fn parse_size(size: &str) -> Result<usize> {
+    let invalid = || Error::InvalidSize(size.to_string());
+
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(unit_start);
+
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+
+    if number.is_empty() {
+        return Err(invalid());
+    }
+
+    let bytes = match number.parse::<u64>() {
+        Ok(whole) => whole.checked_mul(multiplier).ok_or_else(invalid)?,
+        Err(_) => {
+            let fraction: f64 = number.parse().map_err(|_| invalid())?;
+            (fraction * multiplier as f64) as u64
+        }
+    };
+
+    usize::try_from(bytes).map_err(|_| invalid())
+}
+
+/// Whether `text`, such as a URL, matches a wildcard pattern, where
+/// `*` matches any run of characters (including `/`), and `?` matches
+/// any single character. The pattern must match the whole of `text`.
+///
+/// ```rust
+/// assert!(webarchive::glob_matches("https://*.example/*.png", "https://cdn.example/a/b.png"));
+/// assert!(!webarchive::glob_matches("*.png", "https://example.com/a.png?size=2"));
+/// ```
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and how much text it has consumed
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether a MIME type essence matches a pattern such as
+/// `text/html`, `image/*` or `*/*`.
+pub(crate) fn mime_matches(pattern: &str, essence: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+
+    match pattern.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((kind, "*")) => essence
+            .split_once('/')
+            .is_some_and(|(essence_kind, _)| essence_kind == kind),
+        _ => pattern == essence,
+    }
+}
+
+impl WebArchive {
+    /// Iterate over the resources which match `filter`, in the
+    /// same order as [`iter_resources`](WebArchive::iter_resources).
+    pub fn filter_resources<'a>(
+        &'a self,
+        filter: &'a ResourceFilter,
+    ) -> impl Iterator<Item = &'a WebResource> {
+        self.iter_frames().flat_map(move |(_, archive)| {
+            std::iter::once((&archive.main_resource, true))
+                .chain(
+                    archive
+                        .subresources
+                        .iter()
+                        .flatten()
+                        .map(|subresource| (subresource, false)),
+                )
+                .filter(move |(resource, is_main_resource)| {
+                    filter.matches(resource, *is_main_resource)
+                })
+                .map(|(resource, _)| resource)
+        })
+    }
+
+    /// The resources, at any depth, whose URLs match `matcher`, in the
+    /// same order as [`iter_resources`](WebArchive::iter_resources).
+    pub fn query(&self, matcher: &UrlMatcher) -> Vec<&WebResource> {
+        self.iter_resources()
+            .filter(|resource| matcher.is_match(&resource.url))
+            .collect()
+    }
+
+    /// Like [`query`](WebArchive::query), but allowing the resources
+    /// to be changed.
+    pub fn query_mut(&mut self, matcher: &UrlMatcher) -> Vec<&mut WebResource> {
+        let mut matched = Vec::new();
+
+        if matcher.is_match(&self.main_resource.url) {
+            matched.push(&mut self.main_resource);
+        }
+
+        matched.extend(
+            self.subresources
+                .iter_mut()
+                .flatten()
+                .filter(|subresource| matcher.is_match(&subresource.url)),
+        );
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            matched.extend(subframe_archive.query_mut(matcher));
+        }
+
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_matches, mime_matches, parse_size, ResourceFilter, UrlMatcher};
+    use crate::WebArchive;
+
+    fn crouton() -> WebArchive {
+        crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read Crouton webarchive fixture")
+    }
+
+    fn urls(archive: &WebArchive, filter: &ResourceFilter) -> Vec<String> {
+        archive
+            .filter_resources(filter)
+            .map(|resource| resource.url.clone())
+            .collect()
+    }
+
+    #[test]
+    fn mime_patterns() {
+        assert!(mime_matches("image/png", "image/png"));
+        assert!(mime_matches("Image/*", "image/png"));
+        assert!(mime_matches("*/*", "text/html"));
+        assert!(!mime_matches("image/*", "text/html"));
+        assert!(!mime_matches("image/png", "image/pngx"));
+        assert!(!mime_matches("image", "image/png"));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let cases = [
+            ("*", "", true),
+            ("*", "https://example.com/", true),
+            ("https://example.com/*", "https://example.com/a/b.css", true),
+            (
+                "*/buttons/b?1.jpg",
+                "http://psxdatacenter.com/buttons/ba1.jpg",
+                true,
+            ),
+            ("*a*b*c", "xaxxbxxxc", true),
+            ("exact", "exact", true),
+            // `*` crosses `/`, so `**` is the same as `*`
+            ("*.css", "https://example.com/css/main.css", true),
+            (
+                "**/fonts/**",
+                "https://example.com/static/fonts/a.woff",
+                true,
+            ),
+            ("*/fonts/*", "https://example.com/static/fonts/a.woff", true),
+            (
+                "https://*.example/*.png",
+                "https://cdn.example/a/b.png",
+                true,
+            ),
+            (
+                "*/buttons/b?1.jpg",
+                "http://psxdatacenter.com/buttons/b1.jpg",
+                false,
+            ),
+            ("https://example.com/*", "http://example.com/a", false),
+            // the whole URL must match, including the query string
+            ("*.png", "a.png.gz", false),
+            ("*.png", "https://example.com/a.png?size=2", false),
+            ("exact", "exactly", false),
+            ("?", "", false),
+            ("**/fonts/**", "https://example.com/fonts", false),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_matches(pattern, text),
+                expected,
+                "{:?} matching {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(UrlMatcher::glob(pattern).is_match(text), expected);
+        }
+    }
+
+    #[test]
+    fn url_matchers() {
+        let cases = [
+            (r"\.css$", "https://example.com/a.css", true),
+            (r"\.css$", "https://example.com/a.css?v=2", false),
+            ("fonts/", "https://example.com/static/fonts/a.woff", true),
+            ("^https:", "http://example.com/", false),
+            ("(?i)PNG", "https://example.com/a.png", true),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                UrlMatcher::regex(pattern).unwrap().is_match(text),
+                expected,
+                "{:?} matching {:?}",
+                pattern,
+                text
+            );
+        }
+
+        assert!(UrlMatcher::regex("(unclosed").is_err());
+
+        let matcher = UrlMatcher::exact("https://example.com/")
+            .or(UrlMatcher::glob("*.gif"))
+            .or(UrlMatcher::regex("/img/").unwrap());
+        assert!(matcher.is_match("https://example.com/"));
+        assert!(matcher.is_match("https://example.com/a.gif"));
+        assert!(matcher.is_match("https://example.com/img/a.png"));
+        assert!(!matcher.is_match("https://example.com/a.png"));
+        assert!(!UrlMatcher::any([]).is_match("https://example.com/"));
+
+        assert_eq!(UrlMatcher::glob("*.gif"), UrlMatcher::glob("*.gif"));
+        assert_ne!(UrlMatcher::glob("*.gif"), UrlMatcher::exact("*.gif"));
+    }
+
+    #[test]
+    fn query_psxdatacenter() {
+        let mut archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let gifs = UrlMatcher::glob("*.gif");
+
+        let urls: Vec<&str> = archive
+            .query(&gifs)
+            .iter()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        let filter = ResourceFilter {
+            url: Some(gifs.clone()),
+            ..Default::default()
+        };
+        let filtered: Vec<&str> = archive
+            .filter_resources(&filter)
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert!(!urls.is_empty());
+        assert_eq!(urls, filtered);
+
+        let count = urls.len();
+        for resource in archive.query_mut(&gifs) {
+            resource.mime_type = "image/x-gif".to_string();
+        }
+        assert_eq!(
+            archive
+                .iter_resources()
+                .filter(|resource| resource.mime_type == "image/x-gif")
+                .count(),
+            count
+        );
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10k").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("10KB").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("1.5M").unwrap(), 1024 * 1024 * 3 / 2);
+        assert_eq!(parse_size("2MiB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1g").unwrap(), 1 << 30);
+        assert_eq!(parse_size("100B").unwrap(), 100);
+
+        for invalid in ["", "k", "ten", "10x", "-5", "1.5.5M", "10 iB", "5iB"] {
+            assert!(parse_size(invalid).is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn filter_sizes() {
+        let archive = crouton();
+
+        assert_eq!(
+            urls(
+                &archive,
+                &ResourceFilter {
+                    min_size: Some(1024),
+                    ..Default::default()
+                }
+            ),
+            ["https://crouton.net/crouton.png"]
+        );
+        assert_eq!(
+            urls(
+                &archive,
+                &ResourceFilter {
+                    max_size: Some(134),
+                    ..Default::default()
+                }
+            ),
+            ["https://crouton.net/"]
+        );
+    }
+
+    #[test]
+    fn filter_resources() {
+        let archive = crouton();
+
+        assert_eq!(
+            urls(&archive, &ResourceFilter::default()),
+            ["https://crouton.net/", "https://crouton.net/crouton.png"]
+        );
+        assert_eq!(
+            urls(
+                &archive,
+                &ResourceFilter {
+                    subresources_only: true,
+                    ..Default::default()
+                }
+            ),
+            ["https://crouton.net/crouton.png"]
+        );
+        assert_eq!(
+            urls(
+                &archive,
+                &ResourceFilter {
+                    frames_only: true,
+                    ..Default::default()
+                }
+            ),
+            ["https://crouton.net/"]
+        );
+        assert_eq!(
+            urls(
+                &archive,
+                &ResourceFilter {
+                    mime: Some("text/*".to_string()),
+                    ..Default::default()
+                }
+            ),
+            ["https://crouton.net/"]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::hooks::HookedWriter;
+use crate::{Error, OperationHooks, Phase, Progress, Result, WebArchive};
+use std::cell::Cell;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a file to read between progress reports.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The kinds of property list a Web Archive can be stored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A binary property list, as Safari writes.
+    Binary,
+
+    /// An XML property list.
+    Xml,
+}
+
+impl ArchiveFormat {
+    /// Work out which format serialised property list data is in.
+    ///
+    /// Anything which is not a binary property list is assumed to be XML.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(b"bplist") {
+            ArchiveFormat::Binary
+        } else {
+            ArchiveFormat::Xml
+        }
+    }
+}
+
+/// Read an archive from a file, along with the format it was stored in,
+/// so that it can be written back the same way.
+pub fn from_file_with_format<P: AsRef<Path>>(path: P) -> Result<(WebArchive, ArchiveFormat)> {
+    from_file_with_hooks(path, &OperationHooks::default())
+}
+
+/// Read an archive from a file, as [`from_file_with_format`] does,
+/// reporting the bytes read to `hooks`, and stopping with
+/// [`Error::Cancelled`] if they say to.
+pub fn from_file_with_hooks<P: AsRef<Path>>(
+    path: P,
+    hooks: &OperationHooks<'_>,
+) -> Result<(WebArchive, ArchiveFormat)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut progress = Progress::new(Phase::Reading);
+    progress.bytes_total = file.metadata().ok().map(|metadata| metadata.len());
+
+    let mut data = Vec::with_capacity(progress.bytes_total.unwrap_or_default() as usize);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        hooks.check()?;
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..read]);
+        progress.bytes_done += read as u64;
+        hooks.report(progress);
+    }
+
+    hooks.check()?;
+    let archive = crate::from_bytes(&data)?;
+    Ok((archive, ArchiveFormat::detect(&data)))
+}
+
+/// Write an archive to a file in the given format, replacing the file
+/// only once the archive has been written in full.
+///
+/// The archive is first written to a temporary file beside `path`,
+/// which is then renamed over it, so an existing file is never left
+/// half-written.
+pub fn to_file_atomic<P: AsRef<Path>>(
+    path: P,
+    archive: &WebArchive,
+    format: ArchiveFormat,
+) -> Result<()> {
+    to_file_atomic_with_hooks(path, archive, format, &OperationHooks::default())
+}
+
+/// Write an archive to a file, as [`to_file_atomic`] does, reporting the
+/// bytes written to `hooks`.
+///
+/// If they say to stop, this fails with [`Error::Cancelled`], removing
+/// the temporary file and leaving any existing file at `path` as it was.
+pub fn to_file_atomic_with_hooks<P: AsRef<Path>>(
+    path: P,
+    archive: &WebArchive,
+    format: ArchiveFormat,
+    hooks: &OperationHooks<'_>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let cancelled = Cell::new(false);
+    let result = (|| {
+        hooks.check()?;
+
+        let mut progress = Progress::new(Phase::Writing);
+        if format == ArchiveFormat::Binary {
+            progress.bytes_total = Some(archive.estimated_binary_size() as u64);
+        }
+
+        let file = std::fs::File::create(&temporary)?;
+        let mut file =
+            std::io::BufWriter::new(HookedWriter::new(file, hooks, progress, &cancelled));
+        match format {
+            ArchiveFormat::Binary => crate::to_writer_binary(&mut file, archive)?,
+            ArchiveFormat::Xml => crate::to_writer_xml(&mut file, archive)?,
+        }
+        file.into_inner()
+            .map_err(|error| error.into_error())?
+            .into_inner()
+            .sync_all()?;
+
+        hooks.check()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    })();
+    // however the writer's error was wrapped, it was the hooks which
+    // stopped it
+    let result = match result {
+        Err(_) if cancelled.get() => Err(Error::Cancelled),
+        result => result,
+    };
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_file_with_format, from_file_with_hooks, to_file_atomic, to_file_atomic_with_hooks,
+        ArchiveFormat,
+    };
+    use crate::{Error, OperationHooks, Phase};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn round_trip_formats() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let (archive, format) = from_file_with_format("fixtures/crouton.webarchive").unwrap();
+        assert_eq!(format, ArchiveFormat::Binary);
+
+        for format in [ArchiveFormat::Xml, ArchiveFormat::Binary] {
+            let path = temp.path().join("crouton.webarchive");
+            to_file_atomic(&path, &archive, format).unwrap();
+
+            let (reread, detected) = from_file_with_format(&path).unwrap();
+            assert_eq!(detected, format);
+            assert_eq!(reread.fingerprint(), archive.fingerprint());
+        }
+
+        // nothing is left behind but the archive itself
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn read_with_progress() {
+        let reports = Mutex::new(Vec::new());
+        let hooks = OperationHooks::new().on_progress(|progress| {
+            assert_eq!(progress.phase, Phase::Reading);
+            reports.lock().unwrap().push(progress);
+        });
+
+        from_file_with_hooks("fixtures/psxdatacenter.webarchive", &hooks).unwrap();
+        drop(hooks);
+
+        let reports = reports.into_inner().unwrap();
+        assert!(reports.len() > 1);
+        let last = reports.last().unwrap();
+        assert_eq!(Some(last.bytes_done), last.bytes_total);
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].bytes_done < pair[1].bytes_done));
+    }
+
+    #[test]
+    fn cancelled_write_leaves_nothing() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let path = temp.path().join("psxdatacenter.webarchive");
+        std::fs::write(&path, b"the old archive").unwrap();
+
+        let (archive, _) = from_file_with_format("fixtures/psxdatacenter.webarchive").unwrap();
+
+        // stop once the first megabyte is written
+        let stop = AtomicBool::new(false);
+        let hooks = OperationHooks::new()
+            .on_progress(|progress| {
+                assert_eq!(progress.phase, Phase::Writing);
+                if progress.bytes_done >= 1_000_000 {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            })
+            .cancel_when(|| stop.load(Ordering::Relaxed));
+
+        for format in [ArchiveFormat::Xml, ArchiveFormat::Binary] {
+            stop.store(false, Ordering::Relaxed);
+            assert!(matches!(
+                to_file_atomic_with_hooks(&path, &archive, format, &hooks),
+                Err(Error::Cancelled)
+            ));
+        }
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"the old archive");
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,564 @@
+use crate::{Error, Result, WebArchive, WebResource};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// One step of a [`FramePath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSegment {
+    /// The position of the frame among its parent's subframe archives,
+    /// or zero for the root archive.
+    pub index: usize,
+
+    /// The frame's name, from its main resource's `frame_name`.
+    pub frame_name: Option<String>,
+
+    /// The URL of the frame's main resource.
+    pub main_url: String,
+}
+
+/// Where an archive sits in the hierarchy of subframe archives,
+/// as the list of frames leading to it from the root archive.
+///
+/// The first segment is always the root archive itself. Paths share
+/// their segments, so cloning one is cheap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePath {
+    segments: Arc<[FrameSegment]>,
+}
+
+impl FramePath {
+    fn root(archive: &WebArchive) -> Self {
+        FramePath {
+            segments: Arc::new([FrameSegment::new(0, archive)]),
+        }
+    }
+
+    fn child(&self, index: usize, archive: &WebArchive) -> Self {
+        let segments: Vec<FrameSegment> = self
+            .segments
+            .iter()
+            .cloned()
+            .chain(std::iter::once(FrameSegment::new(index, archive)))
+            .collect();
+
+        FramePath {
+            segments: segments.into(),
+        }
+    }
+
+    /// The frames leading to this one, starting with the root archive.
+    pub fn segments(&self) -> &[FrameSegment] {
+        &self.segments
+    }
+
+    /// How many frames deep this path is; zero for the root archive.
+    pub fn depth(&self) -> usize {
+        self.segments.len() - 1
+    }
+
+    /// Whether this is the path of the root archive.
+    pub fn is_root(&self) -> bool {
+        self.depth() == 0
+    }
+
+    /// The last segment of the path, describing the frame itself.
+    pub fn last(&self) -> &FrameSegment {
+        self.segments.last().expect("Frame paths are never empty")
+    }
+}
+
+impl FrameSegment {
+    fn new(index: usize, archive: &WebArchive) -> Self {
+        FrameSegment {
+            index,
+            frame_name: archive.main_resource.frame_name.clone(),
+            main_url: archive.main_resource.url.clone(),
+        }
+    }
+}
+
+/// Formats as breadcrumbs, such as `main → frame "nav" → frame 2`,
+/// naming frames by index where they have no `frame_name`.
+impl fmt::Display for FramePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("main")?;
+
+        for segment in &self.segments[1..] {
+            match &segment.frame_name {
+                Some(name) => write!(f, " → frame {:?}", name)?,
+                None => write!(f, " → frame {}", segment.index)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over an archive and its subframe archives.
+///
+/// Created by [`WebArchive::iter_frames`].
+pub struct Frames<'a> {
+    stack: Vec<(FramePath, &'a WebArchive)>,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = (FramePath, &'a WebArchive);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, archive) = self.stack.pop()?;
+
+        if let Some(subframe_archives) = &archive.subframe_archives {
+            self.stack.extend(
+                subframe_archives
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, subframe)| (path.child(index, subframe), subframe)),
+            );
+        }
+
+        Some((path, archive))
+    }
+}
+
+/// Identifies a subframe archive within a Web Archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameSelector {
+    /// The subframe archive at this position among the archive's
+    /// direct subframes, in document order, starting from zero.
+    Index(usize),
+
+    /// The first subframe archive, at any depth, whose main resource
+    /// has this `frame_name`.
+    Name(String),
+
+    /// The first subframe archive, at any depth, whose main resource
+    /// is stored at this URL.
+    Url(String),
+}
+
+impl FrameSelector {
+    /// Whether `frame` is the subframe archive this selects, by
+    /// name or URL. Indices depend on the parent, so never match.
+    fn matches(&self, frame: &WebArchive) -> bool {
+        match self {
+            FrameSelector::Index(_) => false,
+            FrameSelector::Name(name) => frame.main_resource.frame_name.as_ref() == Some(name),
+            FrameSelector::Url(url) => frame.main_resource.url == *url,
+        }
+    }
+}
+
+impl FromStr for FrameSelector {
+    type Err = std::convert::Infallible;
+
+    /// Parse a selector, treating anything which is a valid index as
+    /// an index, anything containing `://` as a URL, and anything else
+    /// as a frame name.
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        Ok(match selector.parse() {
+            Ok(index) => FrameSelector::Index(index),
+            Err(_) if selector.contains("://") => FrameSelector::Url(selector.to_string()),
+            Err(_) => FrameSelector::Name(selector.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for FrameSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameSelector::Index(index) => write!(f, "frame {}", index),
+            FrameSelector::Name(name) => write!(f, "frame {:?}", name),
+            FrameSelector::Url(url) => write!(f, "frame at {:?}", url),
+        }
+    }
+}
+
+/// What [`WebArchive::remove_subframe`] does with the
+/// subresources of the frame it removes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HoistPolicy {
+    /// Leave them in the removed frame.
+    #[default]
+    Discard,
+
+    /// Move them to the archive the frame was removed from, except
+    /// for those whose URLs it already stores, as other frames may
+    /// still need them.
+    Hoist,
+}
+
+impl WebArchive {
+    /// Iterate over this archive and all of its subframe archives,
+    /// at any depth, in document order, along with their paths.
+    ///
+    /// The first item is always the archive itself.
+    pub fn iter_frames(&self) -> Frames<'_> {
+        Frames {
+            stack: vec![(FramePath::root(self), self)],
+        }
+    }
+
+    /// Add a subframe archive after any others in this archive.
+    pub fn add_subframe(&mut self, frame: WebArchive) {
+        self.subframe_archives
+            .get_or_insert_with(Vec::new)
+            .push(frame);
+    }
+
+    /// Remove a subframe archive, returning it as a standalone archive.
+    ///
+    /// The returned archive's main resource has its `frame_name`
+    /// cleared, as it is no longer a frame. If this removes the last
+    /// subframe archive, `subframe_archives` becomes `None`.
+    ///
+    /// Returns `None`, leaving the archive unchanged, if no
+    /// subframe archive matches the selector.
+    pub fn take_subframe(&mut self, selector: &FrameSelector) -> Option<WebArchive> {
+        self.remove_subframe(selector, HoistPolicy::Discard)
+    }
+
+    /// Remove a subframe archive, like
+    /// [`take_subframe`](WebArchive::take_subframe), first moving its
+    /// subresources to the archive it belongs to if `policy` is
+    /// [`HoistPolicy::Hoist`]. Hoisted subresources are no longer
+    /// part of the returned archive.
+    pub fn remove_subframe(
+        &mut self,
+        selector: &FrameSelector,
+        policy: HoistPolicy,
+    ) -> Option<WebArchive> {
+        let (parent, index) = self.subframe_parent(selector)?;
+        let subframe_archives = parent.subframe_archives.as_mut()?;
+
+        let mut taken = subframe_archives.remove(index);
+        if subframe_archives.is_empty() {
+            parent.subframe_archives = None;
+        }
+
+        if policy == HoistPolicy::Hoist {
+            let (hoisted, kept): (Vec<WebResource>, Vec<WebResource>) = taken
+                .subresources
+                .take()
+                .into_iter()
+                .flatten()
+                .partition(|subresource| !parent.has_own_subresource(&subresource.url));
+
+            if !hoisted.is_empty() {
+                parent
+                    .subresources
+                    .get_or_insert_with(Vec::new)
+                    .extend(hoisted);
+            }
+            taken.subresources = Some(kept).filter(|kept| !kept.is_empty());
+        }
+
+        taken.main_resource.frame_name = None;
+        Some(taken)
+    }
+
+    /// Put `frame` in place of a subframe archive, returning the
+    /// one it replaced, with its `frame_name` cleared.
+    ///
+    /// If `frame`'s main resource has no `frame_name`, it takes
+    /// the name of the frame it replaces.
+    ///
+    /// Returns [`Error::FrameNotFound`], leaving the archive unchanged,
+    /// if no subframe archive matches the selector.
+    pub fn replace_subframe(
+        &mut self,
+        selector: &FrameSelector,
+        mut frame: WebArchive,
+    ) -> Result<WebArchive> {
+        let (parent, index) = self
+            .subframe_parent(selector)
+            .ok_or_else(|| Error::FrameNotFound(selector.to_string()))?;
+        let slot = &mut parent
+            .subframe_archives
+            .as_mut()
+            .expect("The parent of a subframe has subframe archives")[index];
+
+        if frame.main_resource.frame_name.is_none() {
+            frame.main_resource.frame_name = slot.main_resource.frame_name.clone();
+        }
+
+        let mut replaced = std::mem::replace(slot, frame);
+        replaced.main_resource.frame_name = None;
+        Ok(replaced)
+    }
+
+    /// Find the archive holding the subframe archive `selector`
+    /// matches, and the subframe's position within it.
+    ///
+    /// Indices only select direct subframes; names and URLs are
+    /// looked for among direct subframes first, then deeper ones.
+    fn subframe_parent(&mut self, selector: &FrameSelector) -> Option<(&mut WebArchive, usize)> {
+        let subframe_archives = self.subframe_archives.as_ref()?;
+
+        let position = match selector {
+            FrameSelector::Index(index) => {
+                return (*index < subframe_archives.len()).then_some((self, *index))
+            }
+            _ => subframe_archives
+                .iter()
+                .position(|frame| selector.matches(frame)),
+        };
+
+        match position {
+            Some(index) => Some((self, index)),
+            None => self
+                .subframe_archives
+                .iter_mut()
+                .flatten()
+                .find_map(|frame| frame.subframe_parent(selector)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameSelector, HoistPolicy};
+    use crate::test_support::resource;
+    use crate::{Error, WebArchive, WebResource};
+
+    fn psxdatacenter() -> WebArchive {
+        crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture")
+    }
+
+    #[test]
+    fn parse_selector() {
+        assert_eq!("2".parse(), Ok(FrameSelector::Index(2)));
+        assert_eq!(
+            "jlist".parse(),
+            Ok(FrameSelector::Name("jlist".to_string()))
+        );
+        assert_eq!(
+            "http://psxdatacenter.com/jlist.html".parse(),
+            Ok(FrameSelector::Url(
+                "http://psxdatacenter.com/jlist.html".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn iter_frames() {
+        let archive = psxdatacenter();
+
+        let frames: Vec<(String, usize, &str)> = archive
+            .iter_frames()
+            .map(|(path, frame)| {
+                assert_eq!(path.last().main_url, frame.main_resource.url);
+                let file_name = frame.main_resource.url.rsplit('/').next().unwrap();
+                (path.to_string(), path.depth(), file_name)
+            })
+            .collect();
+
+        assert_eq!(
+            frames,
+            [
+                ("main".to_string(), 0, "ntsc-j_list.html"),
+                (
+                    "main → frame \"<!--frame1-->\"".to_string(),
+                    1,
+                    "banner.html"
+                ),
+                ("main → frame \"<!--frame2-->\"".to_string(), 1, "nav.html"),
+                ("main → frame \"jbanner\"".to_string(), 1, "jbanner.html"),
+                ("main → frame \"jlist\"".to_string(), 1, "jlist.html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn iter_nested_frames() {
+        fn frame(url: &str, name: Option<&str>, subframes: Vec<WebArchive>) -> WebArchive {
+            WebArchive {
+                main_resource: WebResource {
+                    frame_name: name.map(str::to_string),
+                    ..resource(url, "text/html", "")
+                },
+                subresources: None,
+                subframe_archives: if subframes.is_empty() {
+                    None
+                } else {
+                    Some(subframes)
+                },
+            }
+        }
+
+        let archive = frame(
+            "about:root",
+            None,
+            vec![
+                frame(
+                    "about:nav",
+                    Some("nav"),
+                    vec![frame("about:subnav", Some("subnav"), vec![])],
+                ),
+                frame("about:body", None, vec![]),
+            ],
+        );
+
+        let paths: Vec<String> = archive
+            .iter_frames()
+            .map(|(path, _)| path.to_string())
+            .collect();
+
+        assert_eq!(
+            paths,
+            [
+                "main",
+                "main → frame \"nav\"",
+                "main → frame \"nav\" → frame \"subnav\"",
+                "main → frame 1",
+            ]
+        );
+
+        let (path, _) = archive.iter_frames().nth(2).unwrap();
+        let urls: Vec<&str> = path
+            .segments()
+            .iter()
+            .map(|segment| segment.main_url.as_str())
+            .collect();
+        assert_eq!(urls, ["about:root", "about:nav", "about:subnav"]);
+    }
+
+    #[test]
+    fn take_subframe() {
+        for selector in [
+            FrameSelector::Index(0),
+            FrameSelector::Name("<!--frame1-->".to_string()),
+            FrameSelector::Url("http://psxdatacenter.com/banner.html".to_string()),
+        ] {
+            let mut archive = psxdatacenter();
+
+            let frame = archive
+                .take_subframe(&selector)
+                .expect("Subframe not found");
+
+            assert_eq!(
+                frame.main_resource.url,
+                "http://psxdatacenter.com/banner.html"
+            );
+            assert!(frame.main_resource.frame_name.is_none());
+            assert_eq!(frame.subresources.as_ref().map(Vec::len), Some(2));
+            assert_eq!(archive.subframe_archives.as_ref().map(Vec::len), Some(3));
+            assert_eq!(
+                archive.subframe_archives.as_ref().unwrap()[0]
+                    .main_resource
+                    .url,
+                "http://psxdatacenter.com/nav.html"
+            );
+        }
+    }
+
+    #[test]
+    fn take_missing_subframe() {
+        let mut archive = psxdatacenter();
+
+        assert!(archive.take_subframe(&FrameSelector::Index(4)).is_none());
+        assert!(archive
+            .take_subframe(&FrameSelector::Name("nope".to_string()))
+            .is_none());
+        assert_eq!(archive.subframe_archives.as_ref().map(Vec::len), Some(4));
+    }
+
+    #[test]
+    fn take_last_subframe() {
+        let mut archive = psxdatacenter();
+
+        for _ in 0..4 {
+            assert!(archive.take_subframe(&FrameSelector::Index(0)).is_some());
+        }
+
+        assert!(archive.subframe_archives.is_none());
+    }
+
+    #[test]
+    fn remove_subframe_hoisting() {
+        let mut archive = psxdatacenter();
+        let nav = FrameSelector::Name("<!--frame2-->".to_string());
+
+        // texgrey.jpg is hoisted from the banner, so stays with the navigation frame
+        let banner = archive
+            .remove_subframe(&FrameSelector::Index(0), HoistPolicy::Hoist)
+            .unwrap();
+        assert!(banner.subresources.is_none());
+        let nav = archive.remove_subframe(&nav, HoistPolicy::Hoist).unwrap();
+        assert_eq!(
+            nav.subresources
+                .iter()
+                .flatten()
+                .map(|subresource| subresource.url.as_str())
+                .collect::<Vec<&str>>(),
+            ["http://psxdatacenter.com/images/texgrey.jpg"]
+        );
+
+        let urls: Vec<&str> = archive
+            .subresources
+            .iter()
+            .flatten()
+            .map(|subresource| subresource.url.as_str())
+            .collect();
+        assert_eq!(urls.len(), 17);
+        assert_eq!(
+            urls[..3],
+            [
+                "http://psxdatacenter.com/images/texgrey.jpg",
+                "http://psxdatacenter.com/images/logo.jpg",
+                "http://psxdatacenter.com/buttons/news1.gif",
+            ]
+        );
+        assert_eq!(archive.subframe_archives.as_ref().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn add_and_replace_subframes() {
+        let mut archive = psxdatacenter();
+        let jlist = FrameSelector::Url("http://psxdatacenter.com/jlist.html".to_string());
+
+        let mut frame = archive.take_subframe(&jlist).unwrap();
+        frame.main_resource.data = b"<p>Replaced</p>".to_vec();
+        assert!(archive.take_subframe(&jlist).is_none());
+
+        archive.add_subframe(frame);
+        let frames = archive.subframe_archives.as_ref().unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[3].main_resource.url,
+            "http://psxdatacenter.com/jlist.html"
+        );
+
+        let banner = psxdatacenter()
+            .take_subframe(&FrameSelector::Index(0))
+            .unwrap();
+        let replaced = archive
+            .replace_subframe(&FrameSelector::Name("jbanner".to_string()), banner)
+            .unwrap();
+        assert_eq!(
+            replaced.main_resource.url,
+            "http://psxdatacenter.com/jbanner.html"
+        );
+        assert!(replaced.main_resource.frame_name.is_none());
+
+        let frames = archive.subframe_archives.as_ref().unwrap();
+        assert_eq!(
+            frames[2].main_resource.url,
+            "http://psxdatacenter.com/banner.html"
+        );
+        assert_eq!(
+            frames[2].main_resource.frame_name.as_deref(),
+            Some("jbanner")
+        );
+
+        let missing = FrameSelector::Index(9);
+        assert!(matches!(
+            archive.replace_subframe(&missing, replaced),
+            Err(Error::FrameNotFound(selector)) if selector == "frame 9"
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{ReferenceKind, Result, WebArchive};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/// A resource in a [`DepGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepNode {
+    /// The URL of the resource.
+    pub url: String,
+
+    /// The MIME type of the stored resource,
+    /// or `None` if the archive does not contain it.
+    pub mime_type: Option<String>,
+
+    /// The size of the stored resource's data in bytes,
+    /// or `None` if the archive does not contain it.
+    pub size: Option<usize>,
+}
+
+impl DepNode {
+    /// Whether this resource is referenced but not stored in the archive.
+    pub fn is_missing(&self) -> bool {
+        self.size.is_none()
+    }
+}
+
+/// A reference from one node of a [`DepGraph`] to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepEdge {
+    /// Index of the referring node.
+    pub from: usize,
+
+    /// Index of the referenced node.
+    pub to: usize,
+
+    /// How the referenced resource is used.
+    pub kind: ReferenceKind,
+}
+
+/// Which resources in a Web Archive refer to which others.
+///
+/// Built by [`WebArchive::dependency_graph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepGraph {
+    /// Every stored resource, followed by any referenced URLs
+    /// which are missing from the archive.
+    pub nodes: Vec<DepNode>,
+
+    /// References between nodes, each listed once.
+    pub edges: Vec<DepEdge>,
+}
+
+impl DepGraph {
+    /// Write the graph in [Graphviz](https://graphviz.org) DOT format.
+    ///
+    /// Nodes are labelled with their URL, MIME type and size, and
+    /// those missing from the archive are drawn with dashed outlines.
+    pub fn to_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph webarchive {{")?;
+        writeln!(writer, "  rankdir=LR;")?;
+        writeln!(writer, "  node [shape=box];")?;
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            match (&node.mime_type, node.size) {
+                (Some(mime_type), Some(size)) => writeln!(
+                    writer,
+                    "  n{} [label=\"{}\\n{}, {} bytes\"];",
+                    index,
+                    dot_escape(&node.url),
+                    dot_escape(mime_type),
+                    size
+                )?,
+                _ => writeln!(
+                    writer,
+                    "  n{} [label=\"{}\\n(missing)\", style=dashed];",
+                    index,
+                    dot_escape(&node.url)
+                )?,
+            }
+        }
+
+        for edge in &self.edges {
+            writeln!(
+                writer,
+                "  n{} -> n{} [label=\"{}\"];",
+                edge.from, edge.to, edge.kind
+            )?;
+        }
+
+        writeln!(writer, "}}")
+    }
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl WebArchive {
+    /// Build a graph of which resources refer to which others,
+    /// across the archive and all its subframe archives.
+    ///
+    /// Resources stored more than once under the same URL, as often
+    /// happens with assets shared between frames, are a single node.
+    pub fn dependency_graph(&self) -> Result<DepGraph> {
+        let mut graph = DepGraph::default();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+
+        for resource in self.iter_resources() {
+            indices.entry(resource.url.clone()).or_insert_with(|| {
+                graph.nodes.push(DepNode {
+                    url: resource.url.clone(),
+                    mime_type: Some(resource.mime_type.clone()),
+                    size: Some(resource.data.len()),
+                });
+                graph.nodes.len() - 1
+            });
+        }
+
+        let mut edges = HashSet::new();
+
+        for resource in self.iter_resources() {
+            let from = indices[&resource.url];
+
+            for reference in resource.references()? {
+                let to = *indices.entry(reference.url.clone()).or_insert_with(|| {
+                    graph.nodes.push(DepNode {
+                        url: reference.url,
+                        mime_type: None,
+                        size: None,
+                    });
+                    graph.nodes.len() - 1
+                });
+
+                let edge = DepEdge {
+                    from,
+                    to,
+                    kind: reference.kind,
+                };
+
+                if edges.insert(edge) {
+                    graph.edges.push(edge);
+                }
+            }
+        }
+
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{ReferenceKind, WebArchive};
+
+    #[test]
+    fn dependency_graph() {
+        let archive = WebArchive {
+            main_resource: resource(
+                "https://example.com/",
+                "text/html",
+                r#"<link rel="stylesheet" href="style.css">
+                <script src="missing.js"></script>
+                <img src="logo.png"><img src="logo.png">"#,
+            ),
+            subresources: Some(vec![
+                resource(
+                    "https://example.com/style.css",
+                    "text/css",
+                    "body { background: url(logo.png) } h1 { background: url(gone.png) }",
+                ),
+                resource("https://example.com/logo.png", "image/png", "PNG"),
+            ]),
+            subframe_archives: None,
+        };
+
+        let graph = archive
+            .dependency_graph()
+            .expect("Could not build dependency graph");
+
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(
+            graph.nodes.iter().filter(|node| node.is_missing()).count(),
+            2
+        );
+        assert_eq!(graph.edges.len(), 5);
+        assert_eq!(
+            graph
+                .edges
+                .iter()
+                .filter(|edge| edge.kind == ReferenceKind::CssUrl)
+                .count(),
+            2
+        );
+
+        let mut dot = Vec::new();
+        graph.to_dot(&mut dot).expect("Could not write DOT");
+        let dot = String::from_utf8(dot).expect("DOT was not UTF-8");
+
+        assert!(dot.starts_with("digraph webarchive {\n"));
+        assert!(
+            dot.contains("  n1 [label=\"https://example.com/style.css\\ntext/css, 67 bytes\"];\n")
+        );
+        assert!(dot.contains(
+            "  n3 [label=\"https://example.com/missing.js\\n(missing)\", style=dashed];\n"
+        ));
+        assert!(dot.contains("  n0 -> n2 [label=\"image\"];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 5);
+    }
+
+    #[test]
+    fn dependency_graph_crouton() {
+        let crouton: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read Crouton webarchive fixture");
+
+        let graph = crouton
+            .dependency_graph()
+            .expect("Could not build dependency graph");
+
+        assert_eq!(graph.nodes.len(), 2);
+        assert!(graph.nodes.iter().all(|node| !node.is_missing()));
+        assert_eq!(
+            graph.edges,
+            [super::DepEdge {
+                from: 0,
+                to: 1,
+                kind: ReferenceKind::Image
+            }]
+        );
+    }
+}
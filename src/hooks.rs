@@ -0,0 +1,198 @@
+//! Reporting the progress of long operations, and cancelling them.
+
+use crate::{Error, Result};
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Write};
+
+/// What a long operation is doing, in a [`Progress`] report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Reading an archive file.
+    Reading,
+
+    /// Writing an archive file.
+    Writing,
+
+    /// Writing resources to files.
+    Extracting,
+
+    /// Converting an archive to another format.
+    Converting,
+
+    /// Fetching resources over HTTP.
+    Fetching,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Reading => "reading",
+            Phase::Writing => "writing",
+            Phase::Extracting => "extracting",
+            Phase::Converting => "converting",
+            Phase::Fetching => "fetching",
+        })
+    }
+}
+
+/// How far a long operation has got, as reported to
+/// [`OperationHooks::on_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// What the operation is doing.
+    pub phase: Phase,
+
+    /// How many items, such as resources, have been finished.
+    pub items_done: usize,
+
+    /// How many items there are, if known.
+    pub items_total: Option<usize>,
+
+    /// How many bytes have been read, written or fetched.
+    pub bytes_done: u64,
+
+    /// How many bytes there are, if known. This may be an estimate,
+    /// so `bytes_done` can pass it.
+    pub bytes_total: Option<u64>,
+}
+
+impl Progress {
+    pub(crate) fn new(phase: Phase) -> Self {
+        Progress {
+            phase,
+            items_done: 0,
+            items_total: None,
+            bytes_done: 0,
+            bytes_total: None,
+        }
+    }
+}
+
+type ProgressFn<'a> = Box<dyn Fn(Progress) + Send + Sync + 'a>;
+type CancelledFn<'a> = Box<dyn Fn() -> bool + Send + Sync + 'a>;
+
+/// Callbacks which long operations, such as
+/// [`WebArchive::extract_to`](crate::WebArchive::extract_to), make as
+/// they go, to report their progress and ask whether to stop.
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicBool, Ordering};
+///
+/// let stop = AtomicBool::new(false);
+/// let hooks = webarchive::OperationHooks::new()
+///     .on_progress(|progress| println!("{}: {} bytes", progress.phase, progress.bytes_done))
+///     .cancel_when(|| stop.load(Ordering::Relaxed));
+/// ```
+///
+/// The default hooks report nothing, and never cancel.
+#[derive(Default)]
+pub struct OperationHooks<'a> {
+    progress: Option<ProgressFn<'a>>,
+    cancelled: Option<CancelledFn<'a>>,
+}
+
+impl<'a> OperationHooks<'a> {
+    /// Hooks which report nothing, and never cancel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `progress` whenever the operation gets further.
+    ///
+    /// It may be called very often, so should return quickly.
+    pub fn on_progress(mut self, progress: impl Fn(Progress) + Send + Sync + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop the operation, with [`Error::Cancelled`], once `cancelled`
+    /// returns `true`.
+    ///
+    /// It is called whenever the operation could stop without leaving
+    /// anything half-done, so should return quickly.
+    pub fn cancel_when(mut self, cancelled: impl Fn() -> bool + Send + Sync + 'a) -> Self {
+        self.cancelled = Some(Box::new(cancelled));
+        self
+    }
+
+    /// Report progress.
+    pub(crate) fn report(&self, progress: Progress) {
+        if let Some(report) = &self.progress {
+            report(progress);
+        }
+    }
+
+    /// Whether the operation should stop.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.as_ref().is_some_and(|cancelled| cancelled())
+    }
+
+    /// Fail with [`Error::Cancelled`] if the operation should stop.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Debug for OperationHooks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OperationHooks")
+            .field("progress", &self.progress.is_some())
+            .field("cancelled", &self.cancelled.is_some())
+            .finish()
+    }
+}
+
+/// Passes writes on to another writer, reporting their progress, and
+/// failing once the hooks cancel.
+pub(crate) struct HookedWriter<'h, 'a, W> {
+    inner: W,
+    hooks: &'h OperationHooks<'a>,
+    progress: Progress,
+    cancelled: &'h Cell<bool>,
+}
+
+impl<'h, 'a, W: Write> HookedWriter<'h, 'a, W> {
+    /// Write to `inner`, reporting `progress` with the bytes written
+    /// added, and setting `cancelled` if the hooks cancel.
+    pub(crate) fn new(
+        inner: W,
+        hooks: &'h OperationHooks<'a>,
+        progress: Progress,
+        cancelled: &'h Cell<bool>,
+    ) -> Self {
+        HookedWriter {
+            inner,
+            hooks,
+            progress,
+            cancelled,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for HookedWriter<'_, '_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.hooks.is_cancelled() {
+            self.cancelled.set(true);
+            return Err(io::Error::other(Error::Cancelled));
+        }
+
+        let written = self.inner.write(buf)?;
+        self.progress.bytes_done += written as u64;
+        self.hooks.report(self.progress);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,175 @@
+//! Conversions between resources and the `http` crate's responses.
+
+use crate::text::parse_content_type;
+use crate::{Error, Headers, ResourceResponse, Result, WebResource};
+
+/// Headers which describe how a body was sent, rather than the body,
+/// which archived data, as it is stored decoded, no longer matches.
+const TRANSFER_HEADERS: &[&str] = &["Content-Encoding", "Content-Length", "Transfer-Encoding"];
+
+impl WebResource {
+    /// Make a resource of a response for `url`.
+    ///
+    /// Its MIME type and encoding come from the `Content-Type` header,
+    /// and its status and headers are kept as its archived
+    /// [`response`](WebResource::response).
+    pub fn from_http_response(url: &str, response: http::Response<Vec<u8>>) -> WebResource {
+        let (parts, data) = response.into_parts();
+
+        let (mime_type, charset) = parse_content_type(
+            parts
+                .headers
+                .get(http::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default(),
+            url,
+        );
+
+        let mut headers = Headers::default();
+        for (name, value) in &parts.headers {
+            headers.append(name.as_str(), &String::from_utf8_lossy(value.as_bytes()));
+        }
+        let response = ResourceResponse::new(
+            Some(url.to_string()),
+            Some(parts.status.as_u16()),
+            headers,
+            Some(mime_type.clone()),
+        );
+
+        WebResource {
+            data,
+            url: url.to_string(),
+            frame_name: None,
+            mime_type,
+            text_encoding_name: charset,
+            response: Some(
+                response
+                    .to_bytes()
+                    .expect("Writing a property list to memory can not fail"),
+            ),
+        }
+    }
+
+    /// The resource as a response, with the status and headers of its
+    /// archived response.
+    ///
+    /// Resources without an archived response, or whose response has
+    /// no status, are `200 OK`, and those without a `Content-Type`
+    /// header are given one from their MIME type and encoding. As
+    /// archived data is stored decoded, any `Content-Encoding` and
+    /// `Transfer-Encoding` headers are left out, and `Content-Length`
+    /// is the length of the data.
+    ///
+    /// Returns [`Error::InvalidResponse`] if the archived response
+    /// can not be parsed, or has headers which are not valid HTTP.
+    pub fn to_http_response(&self) -> Result<http::Response<Vec<u8>>> {
+        let archived = self.parse_response()?;
+        let mut response = http::Response::builder().status(
+            archived
+                .as_ref()
+                .and_then(|response| response.status)
+                .unwrap_or(200),
+        );
+
+        let mut has_content_type = false;
+        for (name, value) in archived.iter().flat_map(|response| response.headers.iter()) {
+            if TRANSFER_HEADERS
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(name))
+            {
+                continue;
+            }
+
+            has_content_type |= name.eq_ignore_ascii_case("Content-Type");
+            response = response.header(name, value);
+        }
+
+        if !has_content_type {
+            let content_type = match &self.text_encoding_name {
+                Some(charset) => format!("{}; charset={}", self.mime_type, charset),
+                None => self.mime_type.clone(),
+            };
+            response = response.header(http::header::CONTENT_TYPE, content_type);
+        }
+
+        response
+            .header(http::header::CONTENT_LENGTH, self.data.len())
+            .body(self.data.clone())
+            .map_err(|error| Error::InvalidResponse(error.to_string()))
+    }
+}
+
+impl TryFrom<&WebResource> for http::Response<Vec<u8>> {
+    type Error = Error;
+
+    /// See [`WebResource::to_http_response`].
+    fn try_from(resource: &WebResource) -> Result<Self> {
+        resource.to_http_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{WebArchive, WebResource};
+
+    #[test]
+    fn http_round_trip() {
+        let response = http::Response::builder()
+            .status(404)
+            .header("Content-Type", "text/html; charset=Shift_JIS")
+            .header("Cache-Control", "no-store")
+            .header("Set-Cookie", "a=1")
+            .header("Content-Length", "11")
+            .body(b"<p>\x93\xfa\x96\x7b</p>".to_vec())
+            .unwrap();
+
+        let resource = WebResource::from_http_response("https://example.com/missing", response);
+        assert_eq!(resource.mime_type, "text/html");
+        assert_eq!(resource.text_encoding_name.as_deref(), Some("Shift_JIS"));
+        assert_eq!(resource.response_status(), Some(404));
+        assert_eq!(resource.decoded_text().unwrap(), "<p>日本</p>");
+
+        let response: http::Response<Vec<u8>> = (&resource).try_into().unwrap();
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.body(), b"<p>\x93\xfa\x96\x7b</p>");
+
+        let headers: Vec<(&str, &str)> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                ("content-type", "text/html; charset=Shift_JIS"),
+                ("cache-control", "no-store"),
+                ("set-cookie", "a=1"),
+                ("content-length", "11"),
+            ]
+        );
+    }
+
+    #[test]
+    fn http_response_defaults() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        // without an archived response
+        let response = archive.main_resource.to_http_response().unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=UTF-8"
+        );
+        assert_eq!(response.body(), &archive.main_resource.data);
+
+        // with one Safari archived
+        let png = &archive.subresources.as_ref().unwrap()[0];
+        let response = png.to_http_response().unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert_eq!(response.headers()["etag"], "\"143e-4fa5647f1c100\"");
+        assert_eq!(response.headers()["content-length"], "5182");
+        assert_eq!(response.body(), &png.data);
+    }
+}
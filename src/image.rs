@@ -0,0 +1,218 @@
+//! Reading the dimensions of images from their headers, without
+//! decoding them.
+
+use crate::WebResource;
+
+impl WebResource {
+    /// The width and height of this resource in pixels, if its data
+    /// is a PNG, JPEG, GIF or WebP image.
+    ///
+    /// Only the image's header is read, so this is cheap even for large
+    /// images, and the dimensions are returned as declared, however
+    /// large. The format is recognised from the data, whatever the
+    /// resource's MIME type. Data which is truncated or corrupt, or
+    /// declares a width or height of zero, gives `None`.
+    pub fn image_dimensions(&self) -> Option<(u32, u32)> {
+        let data = &self.data[..];
+
+        let (width, height) = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            png_dimensions(data)?
+        } else if data.starts_with(b"\xff\xd8\xff") {
+            jpeg_dimensions(data)?
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            (u16_le(data, 6)?.into(), u16_le(data, 8)?.into())
+        } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            webp_dimensions(data)?
+        } else {
+            return None;
+        };
+
+        (width > 0 && height > 0).then_some((width, height))
+    }
+}
+
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    // the first chunk must be the image header
+    if data.get(12..16) != Some(b"IHDR") {
+        return None;
+    }
+
+    Some((u32_be(data, 16)?, u32_be(data, 20)?))
+}
+
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+
+    loop {
+        if *data.get(offset)? != 0xff {
+            return None;
+        }
+
+        // markers may be preceded by any number of fill bytes
+        let marker = *data.get(offset + 1)?;
+        match marker {
+            0xff => offset += 1,
+            // these markers stand alone, without a length
+            0x01 | 0xd0..=0xd7 => offset += 2,
+            // the start of a frame, except for the Huffman table,
+            // arithmetic coding and extension markers in that range
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let height = u16_be(data, offset + 5)?;
+                let width = u16_be(data, offset + 7)?;
+                return Some((width.into(), height.into()));
+            }
+            // the image data starts, or ends, before any frame header
+            0xd9 | 0xda => return None,
+            _ => {
+                let length = usize::from(u16_be(data, offset + 2)?);
+                if length < 2 {
+                    return None;
+                }
+                offset += 2 + length;
+            }
+        }
+    }
+}
+
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        // extended format, with a 24-bit canvas size
+        b"VP8X" => Some((u24_le(data, 24)? + 1, u24_le(data, 27)? + 1)),
+        // lossless, with 14-bit sizes after a signature byte
+        b"VP8L" => {
+            if *data.get(20)? != 0x2f {
+                return None;
+            }
+            let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        // lossy, with 14-bit sizes after a frame tag and start code
+        b"VP8 " => {
+            if data.get(23..26)? != b"\x9d\x01\x2a" {
+                return None;
+            }
+            let width = u16_le(data, 26)? & 0x3fff;
+            let height = u16_le(data, 28)? & 0x3fff;
+            Some((width.into(), height.into()))
+        }
+        _ => None,
+    }
+}
+
+fn u16_be(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u24_le(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::WebArchive;
+
+    #[test]
+    fn image_dimensions() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let crouton = &archive.subresources.as_ref().unwrap()[0];
+        assert_eq!(crouton.image_dimensions(), Some((33, 31)));
+        assert_eq!(archive.main_resource.image_dimensions(), None);
+
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let dimensions = |url: &str| archive.get_resource_by_url(url).unwrap().image_dimensions();
+        assert_eq!(
+            dimensions("http://psxdatacenter.com/images/logo.jpg"),
+            Some((700, 80))
+        );
+        assert_eq!(
+            dimensions("http://psxdatacenter.com/buttons/news1.gif"),
+            Some((100, 20))
+        );
+    }
+
+    #[test]
+    fn image_dimensions_webp() {
+        let mut lossy = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9d\x01\x2a".to_vec();
+        lossy.extend([0x80, 0x02, 0xe0, 0x01]);
+        assert_eq!(
+            resource("https://example.com/image", "image/png", &lossy).image_dimensions(),
+            Some((640, 480))
+        );
+
+        let mut lossless = b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f".to_vec();
+        lossless.extend((99u32 | 49 << 14).to_le_bytes());
+        assert_eq!(
+            resource("https://example.com/image", "image/png", &lossless).image_dimensions(),
+            Some((100, 50))
+        );
+
+        let mut extended = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0".to_vec();
+        extended.extend([0xff, 0x04, 0x00, 0xcf, 0x02, 0x00]);
+        assert_eq!(
+            resource("https://example.com/image", "image/png", &extended).image_dimensions(),
+            Some((1280, 720))
+        );
+    }
+
+    #[test]
+    fn image_dimensions_corrupt() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let png = &archive.subresources.as_ref().unwrap()[0].data;
+
+        // every truncation of the header is rejected, rather than misread
+        for length in 0..24 {
+            assert_eq!(
+                resource("https://example.com/image", "image/png", &png[..length])
+                    .image_dimensions(),
+                None
+            );
+        }
+
+        let mut huge = png.clone();
+        huge[16..24].copy_from_slice(&[0xff; 8]);
+        assert_eq!(
+            resource("https://example.com/image", "image/png", &huge).image_dimensions(),
+            Some((u32::MAX, u32::MAX))
+        );
+
+        let mut empty = png.clone();
+        empty[16..20].copy_from_slice(&[0; 4]);
+        assert_eq!(
+            resource("https://example.com/image", "image/png", &empty).image_dimensions(),
+            None
+        );
+
+        for data in [
+            &b"\xff\xd8\xff\xe0\x00\x00"[..],
+            b"\xff\xd8\xff\xe0\xff\xff",
+            b"\xff\xd8\xff\xda\x00\x02",
+            b"\xff\xd8\xffgarbage",
+            b"GIF89a\x01",
+            b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\0\0\0\x80\x02\xe0\x01",
+        ] {
+            assert_eq!(
+                resource("https://example.com/image", "image/png", data).image_dimensions(),
+                None
+            );
+        }
+    }
+}
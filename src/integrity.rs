@@ -0,0 +1,223 @@
+//! Checksum manifests, embedded in an archive as a subresource
+//! which browsers ignore, recording the digest of every resource
+//! so later changes can be detected.
+
+use crate::digest::sha256_hex;
+use crate::{Error, Result, WebArchive, WebResource};
+use serde::{Deserialize, Serialize};
+
+/// The URL of the subresource holding an embedded manifest.
+pub const MANIFEST_URL: &str = "about:webarchive-manifest";
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    main_resource: ManifestEntry,
+    resources: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    url: String,
+    sha256: String,
+}
+
+impl ManifestEntry {
+    fn of(resource: &WebResource) -> Self {
+        ManifestEntry {
+            url: resource.url.clone(),
+            sha256: sha256_hex(&resource.data),
+        }
+    }
+}
+
+/// The result of [`WebArchive::verify_manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifyReport {
+    /// Whether the main resource is the one recorded, with the same data.
+    pub main_resource_matches: bool,
+
+    /// URLs of resources whose data has changed.
+    pub modified: Vec<String>,
+
+    /// URLs of resources which were not recorded in the manifest.
+    pub added: Vec<String>,
+
+    /// URLs of recorded resources which are no longer stored.
+    pub removed: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Whether the archive is unchanged since the manifest was embedded.
+    pub fn is_intact(&self) -> bool {
+        self.main_resource_matches
+            && self.modified.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+impl WebArchive {
+    /// Record the SHA-256 digest of every resource, at any depth, in a
+    /// manifest stored as a subresource at [`MANIFEST_URL`], replacing
+    /// any existing manifest.
+    ///
+    /// The manifest is JSON, and is ignored by browsers, so the
+    /// archive still opens as before.
+    pub fn embed_manifest(&mut self) {
+        self.remove_manifest();
+
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            main_resource: ManifestEntry::of(&self.main_resource),
+            resources: self.iter_resources().map(ManifestEntry::of).collect(),
+        };
+
+        self.subresources
+            .get_or_insert_with(Vec::new)
+            .push(WebResource {
+                url: MANIFEST_URL.to_string(),
+                data: serde_json::to_vec_pretty(&manifest)
+                    .expect("Manifests can always be serialised"),
+                mime_type: "application/json".to_string(),
+                text_encoding_name: Some("UTF-8".to_string()),
+                frame_name: None,
+                response: None,
+            });
+    }
+
+    /// Whether the archive has an embedded manifest.
+    pub fn has_manifest(&self) -> bool {
+        self.manifest_resource().is_some()
+    }
+
+    /// Check the archive against its embedded manifest, reporting
+    /// any resources which have been modified, added or removed
+    /// since [`embed_manifest`](WebArchive::embed_manifest) was used.
+    ///
+    /// Resources are matched by URL; if several have the same URL,
+    /// they are matched in document order.
+    ///
+    /// Returns [`Error::NoManifest`] if the archive has no manifest, or
+    /// [`Error::InvalidManifest`] if it can not be read.
+    pub fn verify_manifest(&self) -> Result<VerifyReport> {
+        let manifest = self.manifest_resource().ok_or(Error::NoManifest)?;
+        let manifest: Manifest = serde_json::from_slice(&manifest.data)
+            .map_err(|error| Error::InvalidManifest(error.to_string()))?;
+
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {}",
+                manifest.version
+            )));
+        }
+
+        let mut recorded = manifest.resources;
+        let mut report = VerifyReport {
+            main_resource_matches: manifest.main_resource == ManifestEntry::of(&self.main_resource),
+            ..VerifyReport::default()
+        };
+
+        for resource in self.iter_resources() {
+            if resource.url == MANIFEST_URL {
+                continue;
+            }
+
+            match recorded.iter().position(|entry| entry.url == resource.url) {
+                Some(index) => {
+                    let entry = recorded.remove(index);
+                    if entry.sha256 != sha256_hex(&resource.data) {
+                        report.modified.push(entry.url);
+                    }
+                }
+                None => report.added.push(resource.url.clone()),
+            }
+        }
+
+        report.removed = recorded.into_iter().map(|entry| entry.url).collect();
+        Ok(report)
+    }
+
+    fn manifest_resource(&self) -> Option<&WebResource> {
+        self.subresources
+            .iter()
+            .flatten()
+            .find(|resource| resource.url == MANIFEST_URL)
+    }
+
+    fn remove_manifest(&mut self) {
+        if let Some(subresources) = &mut self.subresources {
+            subresources.retain(|resource| resource.url != MANIFEST_URL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MANIFEST_URL;
+    use crate::test_support::resource;
+    use crate::{Error, WebArchive};
+
+    fn psxdatacenter() -> WebArchive {
+        crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture")
+    }
+
+    #[test]
+    fn verify_intact_manifest() {
+        let mut archive = psxdatacenter();
+        assert!(matches!(archive.verify_manifest(), Err(Error::NoManifest)));
+
+        archive.embed_manifest();
+        archive.embed_manifest();
+
+        let manifests = archive
+            .iter_resources()
+            .filter(|resource| resource.url == MANIFEST_URL)
+            .count();
+        assert_eq!(manifests, 1);
+
+        // survives a round trip through the file format
+        let archive: WebArchive = crate::from_bytes(&archive.to_vec_binary().unwrap()).unwrap();
+        let report = archive.verify_manifest().unwrap();
+        assert!(report.is_intact(), "{:?}", report);
+    }
+
+    #[test]
+    fn verify_tampered_manifest() {
+        let mut archive = psxdatacenter();
+        archive.embed_manifest();
+
+        archive
+            .update_resource("http://psxdatacenter.com/images/logo.jpg", |logo| {
+                logo.data[100] ^= 1;
+            })
+            .unwrap();
+
+        let frames = archive.subframe_archives.as_mut().unwrap();
+        frames[3].main_resource.data.push(b'\n');
+        let jbanner_resources = frames[2].subresources.as_mut().unwrap();
+        let removed = jbanner_resources.pop().unwrap();
+        jbanner_resources.push(resource(
+            "http://psxdatacenter.com/new.gif",
+            &removed.mime_type,
+            removed.data,
+        ));
+
+        let report = archive.verify_manifest().unwrap();
+        assert!(!report.is_intact());
+        assert!(report.main_resource_matches);
+        assert_eq!(
+            report.modified,
+            [
+                "http://psxdatacenter.com/images/logo.jpg",
+                "http://psxdatacenter.com/jlist.html"
+            ]
+        );
+        assert_eq!(report.added, ["http://psxdatacenter.com/new.gif"]);
+        assert_eq!(report.removed, [removed.url]);
+    }
+}
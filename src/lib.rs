@@ -134,22 +134,176 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! ### Reproducible output
+//!
+//! Writing the same archive always produces the same bytes, in either
+//! format, on any platform. Fields are written in a fixed order, and
+//! nothing depends on the time, on hashing, or on where values happen
+//! to be in memory, so archives built in CI can be cached and signed.
 
 #![allow(clippy::tabs_in_doc_comments)]
 
 use serde::{Deserialize, Serialize};
 
-pub use plist::{
-    from_bytes, from_file, from_reader, from_reader_xml, to_file_binary, to_file_xml,
-    to_writer_binary, to_writer_xml,
+#[cfg(feature = "arbitrary")]
+mod arbitrary_archive;
+mod builder;
+mod canonical;
+pub mod convert;
+mod dedupe;
+mod diff;
+mod digest;
+mod edit;
+#[cfg(feature = "encrypt")]
+mod encrypt;
+mod error;
+mod estimate;
+mod expression;
+mod extract;
+#[cfg(feature = "fetch")]
+mod fetch;
+mod filter;
+mod format;
+mod frames;
+mod graph;
+mod hooks;
+#[cfg(feature = "http")]
+mod http_response;
+#[cfg(feature = "image")]
+mod image;
+mod integrity;
+mod links;
+mod lint;
+#[cfg(feature = "fetch")]
+mod live;
+mod merge;
+mod metadata;
+mod normalize;
+mod optimize;
+mod parse;
+mod provenance;
+mod reader;
+mod recover;
+mod references;
+#[cfg(feature = "cdp")]
+mod render;
+mod repack;
+mod replace;
+mod response;
+mod response_edit;
+#[cfg(feature = "fetch")]
+mod retry;
+mod rewrite;
+mod sanitize;
+mod search;
+#[cfg(feature = "serve")]
+pub mod serve;
+mod sniff;
+mod split;
+mod sri;
+mod strict;
+mod summary;
+mod table;
+#[cfg(all(test, feature = "fetch"))]
+mod test_server;
+#[cfg(test)]
+mod test_support;
+mod text;
+mod transcode;
+mod validate;
+mod value;
+#[cfg(feature = "streaming-xml")]
+mod xml;
+
+pub use builder::WebArchiveBuilder;
+pub use dedupe::{DedupeReport, DuplicateGroup, RewriteMap};
+#[cfg(feature = "diff")]
+pub use diff::ContentDiffOptions;
+pub use diff::{ArchiveDiff, ChangeKind, ContentDiff, ResourceChange, ResourceVersion};
+pub use digest::DigestAlgorithm;
+#[cfg(feature = "encrypt")]
+pub use encrypt::{from_reader_encrypted, is_encrypted, to_writer_encrypted};
+pub use error::{Error, Result};
+pub use expression::FilterExpression;
+pub use extract::{
+    ExtractLayout, ExtractedFile, ExtractionManifest, NameSource, PlanOptions, PlannedFile,
+    RenameReason, SchemeHandlers,
 };
-
-#[derive(Debug, Serialize, Deserialize)]
+#[cfg(feature = "fetch")]
+pub use fetch::{FetchFailure, FetchOptions, FetchReport};
+pub use filter::{glob_matches, parse_size, ResourceFilter, UrlMatcher};
+pub use format::{
+    from_file_with_format, from_file_with_hooks, to_file_atomic, to_file_atomic_with_hooks,
+    ArchiveFormat,
+};
+pub use frames::{FramePath, FrameSegment, FrameSelector, Frames, HoistPolicy};
+pub use graph::{DepEdge, DepGraph, DepNode};
+pub use hooks::{OperationHooks, Phase, Progress};
+pub use integrity::{VerifyReport, MANIFEST_URL};
+pub use links::{DeadLink, FrameLinks, Link, LinkKind};
+pub use lint::{lint_rule, LintConfig, LintFinding, LintLevel, LintReport, LintRule, LINT_RULES};
+#[cfg(feature = "fetch")]
+pub use live::{LiveReport, LiveResult, LiveStatus};
+pub use merge::{MergeBasis, MergeCandidate, MergeConflict, MergePolicy, MergeReport, MergeSide};
+pub use normalize::{UrlNormalization, TRACKING_PARAMETERS};
+pub use optimize::{
+    OptimizeOptions, OptimizePass, OptimizeReport, PassReport, DEFAULT_INLINE_MAX_SIZE,
+};
+pub use parse::{from_bytes_with_options, ParseOptions, ParseWarning, ParsedArchive};
+pub use provenance::{CaptureInfo, CAPTURE_INFO_URL};
+pub use reader::ResourceReader;
+pub use recover::{recover_from_bytes, RecoveredArchive};
+pub use references::{Completeness, Reference, ReferenceKind};
+#[cfg(feature = "cdp")]
+pub use render::RenderOptions;
+pub use repack::RepackOptions;
+pub use replace::{ReplaceOptions, ReplaceReport};
+pub use response::{Headers, ResourceResponse, ResponseLayout};
+#[cfg(feature = "fetch")]
+pub use retry::RetryPolicy;
+pub use rewrite::{SetUrlOptions, UrlRewriteReport};
+pub use sanitize::{SanitizePolicy, SanitizeRemoval, SanitizeReport, SanitizeRule};
+pub use search::SearchMatch;
+pub use sniff::{MimeMismatch, SniffConfidence};
+pub use sri::{SriResult, SriStatus};
+pub use strict::{StrictRule, StrictViolation, STRICT_MAX_DATA_LEN};
+pub use summary::{
+    ArchiveSummary, CollectionEntry, CollectionFailure, CollectionStats, Distribution,
+    MimeTypeStats, ResourceChecksum,
+};
+pub use table::TableOptions;
+pub use transcode::Utf8Resource;
+pub use validate::{Issue, IssueKind, Severity, ValidationReport};
+
+#[cfg(feature = "streaming-xml")]
+pub use xml::{from_bytes, from_file, from_reader};
+
+#[cfg(not(feature = "streaming-xml"))]
+pub use plist::{from_bytes, from_file, from_reader};
+pub use plist::{from_reader_xml, to_file_binary, to_file_xml, to_writer_binary, to_writer_xml};
+
+/// Nonstandard spellings of archive keys, as written by some tools
+/// other than Safari, each paired with the key Safari writes.
+///
+/// Archives using these are read as if they used Safari's keys, which
+/// are always the ones written, so reading and writing such an archive
+/// makes it standard.
+pub const KEY_ALIASES: &[(&str, &str)] = &[
+    ("WebResourceMimeType", "WebResourceMIMEType"),
+    ("WebResourceTextEncoding", "WebResourceTextEncodingName"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 /// Represents an individual web resource which would be requested
 /// as part of displaying the page represented by the Web Archive file.
 pub struct WebResource {
-    #[serde(rename = "WebResourceData", with = "serde_bytes")]
+    #[serde(
+        rename = "WebResourceData",
+        serialize_with = "serde_bytes::serialize",
+        deserialize_with = "parse::deserialize_data"
+    )]
     /// The raw binary data of the resource.
     ///
     /// This data should be interpreted based upon the values of
@@ -172,12 +326,13 @@ pub struct WebResource {
     /// <https://developer.mozilla.org/en-US/docs/Web/HTML/Element/frame>
     pub frame_name: Option<String>,
 
-    #[serde(rename = "WebResourceMIMEType")]
+    #[serde(rename = "WebResourceMIMEType", alias = "WebResourceMimeType")]
     /// The MIME type of the resource.
     pub mime_type: String,
 
     #[serde(
         rename = "WebResourceTextEncodingName",
+        alias = "WebResourceTextEncoding",
         default,
         deserialize_with = "ruma_serde::empty_string_as_none"
     )]
@@ -195,7 +350,131 @@ pub struct WebResource {
     pub response: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl WebResource {
+    /// Guess a file extension suitable for saving this resource to disk.
+    ///
+    /// Sources are consulted in order of how much they can be trusted:
+    ///
+    /// 1. the extension already present at the end of the URL's path,
+    ///    if it is one with a known MIME type,
+    /// 2. the resource's `mime_type`, ignoring any parameters such as
+    ///    `charset`, unless it is the uninformative
+    ///    `application/octet-stream`,
+    /// 3. the leading "magic" bytes of `data`, for PNG, JPEG, GIF,
+    ///    PDF and gzip content.
+    ///
+    /// If none of these yield an answer, falls back to `txt` for data
+    /// which looks like text, and `bin` for anything else.
+    pub fn guessed_extension(&self) -> &'static str {
+        self.url_extension()
+            .or_else(|| self.mime_extension())
+            .or_else(|| sniff::signature_extension(&self.data))
+            .unwrap_or_else(|| {
+                if std::str::from_utf8(&self.data).is_ok() && !self.data.contains(&0) {
+                    "txt"
+                } else {
+                    "bin"
+                }
+            })
+    }
+
+    /// The resource's data as a base64 `data:` URL, with its MIME type
+    /// (without parameters), suitable for using in place of its URL.
+    pub fn to_data_url(&self) -> String {
+        use base64::Engine;
+
+        format!(
+            "data:{};base64,{}",
+            self.mime_essence(),
+            base64::engine::general_purpose::STANDARD.encode(&self.data)
+        )
+    }
+
+    /// Read a resource from a file, to be stored at `url`.
+    ///
+    /// The MIME type is guessed from the file's extension, or, if it
+    /// has none we recognise, from the signature at the start of its
+    /// contents. Text types are given the UTF-8 encoding only if the
+    /// file is valid UTF-8; otherwise the encoding is left unset.
+    pub fn from_file(path: &std::path::Path, url: impl Into<String>) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+
+        let mime_type = mime_guess::from_path(path)
+            .first_raw()
+            .or_else(|| mime_guess::from_ext(sniff::signature_extension(&data)?).first_raw())
+            .unwrap_or_else(|| {
+                if std::str::from_utf8(&data).is_ok() && !data.contains(&0) {
+                    "text/plain"
+                } else {
+                    "application/octet-stream"
+                }
+            })
+            .to_string();
+
+        let mut resource = WebResource {
+            url: url.into(),
+            data,
+            mime_type,
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        };
+
+        if resource.is_text() && std::str::from_utf8(&resource.data).is_ok() {
+            resource.text_encoding_name = Some("UTF-8".to_string());
+        }
+
+        Ok(resource)
+    }
+
+    /// Read a resource from a file, like
+    /// [`from_file`](WebResource::from_file), to be stored at the
+    /// `file:` URL of its canonical path.
+    pub fn from_file_auto_url(path: &std::path::Path) -> std::io::Result<Self> {
+        let path = path.canonicalize()?;
+        let url = url::Url::from_file_path(&path).map_err(|()| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} can not be made into a URL", path),
+            )
+        })?;
+
+        WebResource::from_file(&path, url)
+    }
+
+    /// The extension at the end of the URL's path, if it is one we
+    /// recognise, as the matching `'static` string from `mime_guess`.
+    fn url_extension(&self) -> Option<&'static str> {
+        let url = url::Url::parse(&self.url).ok()?;
+        let file_name = url.path_segments()?.next_back()?;
+        let (_, extension) = file_name.rsplit_once('.')?;
+
+        let mime_type = mime_guess::from_ext(extension).first_raw()?;
+        mime_guess::get_mime_extensions_str(mime_type)?
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(extension))
+            .copied()
+    }
+
+    /// The preferred extension for the resource's declared MIME type.
+    fn mime_extension(&self) -> Option<&'static str> {
+        let essence = self.mime_type.split(';').next().unwrap_or_default().trim();
+
+        if essence.contains('*') || essence.eq_ignore_ascii_case("application/octet-stream") {
+            return None;
+        }
+
+        // mime_guess knows image/png as `pnz`, among others
+        let lowercase = essence.to_ascii_lowercase();
+        sniff::usual_extension(&lowercase).or_else(|| {
+            mime_guess::get_mime_extensions_str(essence)?
+                .last()
+                .copied()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 /// Represents an entire Web Archive file.
 pub struct WebArchive {
@@ -223,7 +502,124 @@ pub struct WebArchive {
 
 impl WebArchive {
     /// Print a list of all contained resources and subframe archives
+    ///
+    /// See [`write_list`](WebArchive::write_list).
     pub fn print_list(&self) {
+        let stdout = std::io::stdout();
+        // printing can only fail if stdout is closed, as with `println!`
+        self.write_list(&mut stdout.lock())
+            .expect("failed printing to stdout");
+    }
+
+    /// Write a list of all contained resources and subframe archives.
+    ///
+    /// Each archive, starting with this one, is described by its
+    /// [`summary`](WebArchive::summary), followed by one line for
+    /// each of its subresources.
+    pub fn write_list<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.write_filtered_list(w, &ResourceFilter::default())
+    }
+
+    /// Write a list like [`write_list`](WebArchive::write_list), but
+    /// only listing subresources which match `filter`.
+    ///
+    /// Every archive's summary is still written. If any subresources
+    /// were left out, a final line says how many, and their total size.
+    pub fn write_filtered_list<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        filter: &ResourceFilter,
+    ) -> std::io::Result<()> {
+        self.write_list_lines(w, filter, None, |_| None)
+    }
+
+    /// Write a list like
+    /// [`write_filtered_list`](WebArchive::write_filtered_list), with
+    /// the digest of each resource's data at the end of its line.
+    ///
+    /// The digest of each frame's main resource ends its summary line.
+    pub fn write_checksum_list<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        filter: &ResourceFilter,
+        algorithm: DigestAlgorithm,
+    ) -> std::io::Result<()> {
+        self.write_list_lines(w, filter, Some(algorithm), |_| None)
+    }
+
+    /// Write a list like
+    /// [`write_filtered_list`](WebArchive::write_filtered_list), with the
+    /// [dimensions](WebResource::image_dimensions) of each image after
+    /// its size, and its digest too if given an `algorithm`.
+    #[cfg(feature = "image")]
+    pub fn write_detailed_list<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        filter: &ResourceFilter,
+        algorithm: Option<DigestAlgorithm>,
+    ) -> std::io::Result<()> {
+        self.write_list_lines(w, filter, algorithm, WebResource::image_dimensions)
+    }
+
+    fn write_list_lines<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        filter: &ResourceFilter,
+        algorithm: Option<DigestAlgorithm>,
+        image_dimensions: fn(&WebResource) -> Option<(u32, u32)>,
+    ) -> std::io::Result<()> {
+        let digest = |resource: &WebResource| match algorithm {
+            Some(algorithm) => format!(" {}:{}", algorithm, resource.hex_digest(algorithm)),
+            None => String::new(),
+        };
+
+        let dimensions = |resource: &WebResource| match image_dimensions(resource) {
+            Some((width, height)) => format!(", {}×{}", width, height),
+            None => String::new(),
+        };
+
+        let mut filtered_count = 0;
+        let mut filtered_size = 0;
+
+        for (_, archive) in self.iter_frames() {
+            writeln!(w, "{}{}", archive.summary(), digest(&archive.main_resource))?;
+
+            for subresource in archive.subresources.iter().flatten() {
+                if !filter.matches(subresource, false) {
+                    filtered_count += 1;
+                    filtered_size += subresource.data.len();
+                    continue;
+                }
+
+                writeln!(
+                    w,
+                    "  - \"{}\" ({:?}, {} bytes{}){}",
+                    subresource.url,
+                    subresource.mime_type,
+                    subresource.data.len(),
+                    dimensions(subresource),
+                    digest(subresource)
+                )?;
+            }
+        }
+
+        if filtered_count > 0 {
+            writeln!(
+                w,
+                "{} resource{} filtered out ({} bytes)",
+                filtered_count,
+                if filtered_count == 1 { "" } else { "s" },
+                filtered_size
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// A one-line description of the archive, its main resource
+    /// and [`title`](WebArchive::title), and how many subresources
+    /// and subframe archives it contains.
+    pub fn summary(&self) -> String {
         let subresource_count = match &self.subresources {
             Some(subresources) => subresources.len(),
             None => 0,
@@ -234,34 +630,59 @@ impl WebArchive {
             None => 0,
         };
 
-        println!(
-            "WebArchive of \"{}\" ({:?}, {} bytes): {} subresource{}, {} subframe archive{} totalling {} bytes",
+        let title = match self.title() {
+            Ok(Some(title)) => format!(" titled {:?}", title),
+            _ => String::new(),
+        };
+
+        format!(
+            "WebArchive of \"{}\" ({:?}, {} bytes){}: {} subresource{}, {} subframe archive{} totalling {} bytes",
             self.main_resource.url,
             self.main_resource.mime_type,
             self.main_resource.data.len(),
+            title,
             subresource_count,
             if subresource_count == 1 { "" } else { "s" },
             subframe_archive_count,
             if subframe_archive_count == 1 { "" } else { "s" },
             self.total_size(),
-        );
+        )
+    }
 
-        if let Some(subresources) = &self.subresources {
-            subresources.iter().for_each(|subresource| {
-                println!(
-                    "  - \"{}\" ({:?}, {} bytes)",
-                    subresource.url,
-                    subresource.mime_type,
-                    subresource.data.len()
-                )
-            });
-        }
+    /// Iterate over every resource in the archive, in document order:
+    /// the main resource, then its subresources, then each subframe
+    /// archive's resources in turn.
+    pub fn iter_resources(&self) -> impl Iterator<Item = &WebResource> {
+        self.iter_frames().flat_map(|(_, archive)| {
+            std::iter::once(&archive.main_resource).chain(archive.subresources.iter().flatten())
+        })
+    }
 
-        if let Some(webarchives) = &self.subframe_archives {
-            webarchives
-                .iter()
-                .for_each(|webarchive| webarchive.print_list());
-        }
+    /// Serialise the archive as a binary property list, the format Safari uses.
+    ///
+    /// The same archive is always serialised to the same bytes.
+    pub fn to_vec_binary(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        to_writer_binary(&mut buf, self)?;
+        Ok(buf)
+    }
+
+    /// Serialise the archive as an XML property list.
+    ///
+    /// The buffer is sized up front for the base64 of every resource's
+    /// data and response, so writing a large archive doesn't keep
+    /// reallocating it.
+    pub fn to_vec_xml(&self) -> Result<Vec<u8>> {
+        let data_size: usize = self
+            .iter_resources()
+            .map(|resource| resource.data.len() + resource.response.as_ref().map_or(0, Vec::len))
+            .sum();
+
+        // base64 with its line breaks and indentation comes to just
+        // under one and a half times the data
+        let mut buf = Vec::with_capacity(data_size / 2 * 3 + 4096);
+        to_writer_xml(&mut buf, self)?;
+        Ok(buf)
     }
 
     /// Get the total size of all contained resources in bytes.
@@ -290,6 +711,7 @@ impl WebArchive {
 
 #[cfg(test)]
 mod tests {
+    use crate::test_support::resource;
     #[test]
     fn parse_crouton() {
         let bytes = include_bytes!("../fixtures/crouton.webarchive");
@@ -351,4 +773,226 @@ mod tests {
 
         // super::to_file_binary(std::path::Path::new("./crouton.output.webarchive"), &webarchive);
     }
+
+    #[test]
+    fn serialize_deterministically() {
+        let webarchive: super::WebArchive = super::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let serialize = || {
+            (
+                webarchive.to_vec_binary().unwrap(),
+                webarchive.to_vec_xml().unwrap(),
+            )
+        };
+        let expected = serialize();
+
+        let mut xml = Vec::new();
+        super::to_writer_xml(&mut xml, &webarchive).unwrap();
+        assert_eq!(expected.1, xml);
+
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..50).map(|_| scope.spawn(serialize)).collect();
+
+            for thread in threads {
+                assert_eq!(thread.join().unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn parse_nonstandard_keys() {
+        let webarchive: super::WebArchive =
+            super::from_file("fixtures/nonstandard-keys.webarchive")
+                .expect("Could not read nonstandard-keys webarchive fixture");
+
+        assert_eq!(webarchive.main_resource.mime_type, "text/html");
+        assert_eq!(
+            webarchive.main_resource.text_encoding_name.as_deref(),
+            Some("UTF-8")
+        );
+
+        // written back with Safari's keys
+        let mut xml = Vec::new();
+        super::to_writer_xml(&mut xml, &webarchive).unwrap();
+        assert_eq!(
+            String::from_utf8(xml).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>WebMainResource</key>
+	<dict>
+		<key>WebResourceData</key>
+		<data>
+		PGltZyBzcmM9ImRvdC5naWYiPg==
+		</data>
+		<key>WebResourceURL</key>
+		<string>https://example.com/</string>
+		<key>WebResourceMIMEType</key>
+		<string>text/html</string>
+		<key>WebResourceTextEncodingName</key>
+		<string>UTF-8</string>
+	</dict>
+	<key>WebSubresources</key>
+	<array>
+		<dict>
+			<key>WebResourceData</key>
+			<data>
+			R0lGODlh
+			</data>
+			<key>WebResourceURL</key>
+			<string>https://example.com/dot.gif</string>
+			<key>WebResourceMIMEType</key>
+			<string>image/gif</string>
+		</dict>
+	</array>
+</dict>
+</plist>"#
+        );
+
+        // and recovered as Safari's keys too
+        let mut binary = Vec::new();
+        plist::Value::from_file("fixtures/nonstandard-keys.webarchive")
+            .unwrap()
+            .to_writer_binary(&mut binary)
+            .unwrap();
+        let recovered = super::recover_from_bytes(&binary);
+        assert_eq!(recovered.archive, Some(webarchive));
+    }
+
+    #[test]
+    fn list_crouton() {
+        let webarchive: super::WebArchive = super::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read Crouton webarchive fixture");
+
+        assert_eq!(
+            webarchive.summary(),
+            "WebArchive of \"https://crouton.net/\" (\"text/html\", 134 bytes) titled \"Crouton\": 1 subresource, 0 subframe archives totalling 5316 bytes"
+        );
+
+        let mut list = Vec::new();
+        webarchive.write_list(&mut list).unwrap();
+
+        assert_eq!(
+            String::from_utf8(list).unwrap(),
+            format!(
+                "{}\n  - \"https://crouton.net/crouton.png\" (\"image/png\", 5182 bytes)\n",
+                webarchive.summary()
+            )
+        );
+    }
+
+    #[test]
+    fn from_file() {
+        use assert_fs::prelude::*;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let page = temp.child("page.html");
+        page.write_str("<p>Crouton</p>").unwrap();
+        let resource = super::WebResource::from_file(page.path(), "https://example.com/").unwrap();
+        assert_eq!(resource.url, "https://example.com/");
+        assert_eq!(resource.mime_type, "text/html");
+        assert_eq!(resource.text_encoding_name.as_deref(), Some("UTF-8"));
+        assert!(resource.response.is_none());
+
+        // Latin-1 text is not valid UTF-8, so no encoding is claimed
+        let latin1 = temp.child("notes.txt");
+        latin1.write_binary(b"caf\xe9").unwrap();
+        let resource =
+            super::WebResource::from_file(latin1.path(), "https://example.com/notes").unwrap();
+        assert_eq!(resource.mime_type, "text/plain");
+        assert!(resource.text_encoding_name.is_none());
+
+        let image = temp.child("crouton");
+        image
+            .write_binary(include_bytes!("../fixtures/crouton.net/crouton.png"))
+            .unwrap();
+        let resource = super::WebResource::from_file_auto_url(image.path()).unwrap();
+        assert_eq!(resource.mime_type, "image/png");
+        assert!(resource.text_encoding_name.is_none());
+        assert!(resource.url.starts_with("file:///"));
+        assert!(resource.url.ends_with("/crouton"));
+    }
+
+    #[test]
+    fn guessed_extension_priority() {
+        const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        // URL extension wins over both the MIME type and the data
+        assert_eq!(
+            resource("https://example.com/photo.JPEG", "image/png", PNG).guessed_extension(),
+            "jpeg"
+        );
+        assert_eq!(
+            resource("https://example.com/a/b.css?v=2#x", "text/plain", b"").guessed_extension(),
+            "css"
+        );
+
+        // unknown URL extensions fall through to the MIME type
+        assert_eq!(
+            resource("https://example.com/doc.nope", "application/pdf", b"").guessed_extension(),
+            "pdf"
+        );
+        assert_eq!(
+            resource("https://crouton.net/", "text/html", b"<html>").guessed_extension(),
+            "shtml"
+        );
+
+        assert_eq!(
+            resource("https://example.com/", "image/PNG", b"").guessed_extension(),
+            "png"
+        );
+
+        // MIME parameters are ignored
+        assert_eq!(
+            resource("https://example.com/", "text/css; charset=utf-8", b"").guessed_extension(),
+            "css"
+        );
+
+        // MIME type wins over the data
+        assert_eq!(
+            resource("https://example.com/", "application/pdf", PNG).guessed_extension(),
+            "pdf"
+        );
+    }
+
+    #[test]
+    fn guessed_extension_sniffing() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0", "png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "jpg"),
+            (b"GIF87a\x01\0", "gif"),
+            (b"GIF89a\x01\0", "gif"),
+            (b"%PDF-1.7\n", "pdf"),
+            (b"\x1f\x8b\x08\0", "gz"),
+            (b"just some text", "txt"),
+            (b"\0\x01\x02\x03", "bin"),
+        ];
+
+        for (data, expected) in cases {
+            for mime_type in ["application/octet-stream", "", "nonsense"] {
+                assert_eq!(
+                    resource("https://example.com/download", mime_type, data).guessed_extension(),
+                    *expected,
+                    "sniffing {:?} declared as {:?}",
+                    data,
+                    mime_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn guessed_extension_crouton() {
+        let webarchive: super::WebArchive =
+            super::from_bytes(include_bytes!("../fixtures/crouton.webarchive"))
+                .expect("Could not read Crouton webarchive fixture");
+
+        assert_eq!(webarchive.main_resource.guessed_extension(), "shtml");
+        assert_eq!(
+            webarchive.subresources.expect("No subresources found")[0].guessed_extension(),
+            "png"
+        );
+    }
 }
@@ -0,0 +1,427 @@
+//! The links archived documents make to other pages, as distinct from
+//! the [references](crate::Reference) they make to their subresources.
+
+use crate::references::{base_url, decode_attribute, html_base, resolve_url};
+use crate::{Error, FramePath, Result, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+
+/// What a [`Link`] leads to, by its URL's scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// A web page, at an `http` or `https` URL.
+    Web,
+
+    /// An email address, at a `mailto:` URL.
+    Mailto,
+
+    /// A telephone number, at a `tel:` URL.
+    Tel,
+
+    /// Anything else, such as an `ftp` URL.
+    Other,
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LinkKind::Web => "web",
+            LinkKind::Mailto => "mailto",
+            LinkKind::Tel => "tel",
+            LinkKind::Other => "other",
+        })
+    }
+}
+
+/// An `<a href>` link in an HTML document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Link {
+    /// The absolute URL, resolved against the document's URL
+    /// (or its `<base>` element).
+    pub url: String,
+
+    /// What the URL leads to.
+    pub kind: LinkKind,
+
+    /// The text within the link, with runs of whitespace collapsed
+    /// into single spaces.
+    pub text: String,
+
+    /// The `rel` keywords, such as `nofollow`, lowercased.
+    pub rel: Vec<String>,
+}
+
+impl Link {
+    /// Whether the link leads to the same origin as `page_url`: the
+    /// same scheme, host and port, where a default port is the same
+    /// as leaving it out.
+    ///
+    /// Links without an origin of their own, such as `mailto:` links,
+    /// never share one.
+    pub fn is_same_origin(&self, page_url: &str) -> bool {
+        match (url::Url::parse(&self.url), url::Url::parse(page_url)) {
+            (Ok(url), Ok(page_url)) => {
+                let origin = url.origin();
+                origin.is_tuple() && origin == page_url.origin()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The links in one frame of an archive, as listed by
+/// [`WebArchive::outbound_links`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameLinks {
+    /// Where the frame is in the archive.
+    pub frame: FramePath,
+
+    /// The links in the frame's main document, in document order.
+    pub links: Vec<Link>,
+}
+
+/// A link to a page on a site the archive has stored resources from,
+/// which is not stored itself, as found by
+/// [`WebArchive::dead_internal_links`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeadLink {
+    /// The URL of the document the link is in.
+    pub document_url: String,
+
+    /// The link.
+    #[serde(flatten)]
+    pub link: Link,
+}
+
+impl WebResource {
+    /// Find the pages this one links to with `<a href>` elements.
+    ///
+    /// Links to fragments of the same document, and `javascript:` and
+    /// `data:` URLs, are left out, as are URLs which can not be
+    /// resolved. Links are listed in document order, and may contain
+    /// duplicates. Resources other than HTML have no links.
+    pub fn outbound_links(&self) -> Result<Vec<Link>> {
+        if !self.is_html() {
+            return Ok(Vec::new());
+        }
+        let html = match self.decoded_text() {
+            Some(html) => html,
+            None => return Ok(Vec::new()),
+        };
+
+        let base = base_url(&self.url, html_base(&html)?.as_deref());
+
+        Ok(anchors(&html)?
+            .into_iter()
+            .filter_map(|(raw, text, rel)| {
+                let url = resolve_url(base.as_ref(), &raw)?;
+                let kind = match url.split(':').next() {
+                    Some("http" | "https") => LinkKind::Web,
+                    Some("mailto") => LinkKind::Mailto,
+                    Some("tel") => LinkKind::Tel,
+                    _ => LinkKind::Other,
+                };
+
+                Some(Link {
+                    url,
+                    kind,
+                    text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                    rel: rel
+                        .split_ascii_whitespace()
+                        .map(|rel| rel.to_ascii_lowercase())
+                        .collect(),
+                })
+            })
+            .collect())
+    }
+}
+
+impl WebArchive {
+    /// Find the pages linked to by the main document of this archive
+    /// and each of its subframes, at any depth, in document order.
+    ///
+    /// Every frame is listed, even those without links. See
+    /// [`WebResource::outbound_links`].
+    pub fn outbound_links(&self) -> Result<Vec<FrameLinks>> {
+        self.iter_frames()
+            .map(|(frame, archive)| {
+                Ok(FrameLinks {
+                    frame,
+                    links: archive.main_resource.outbound_links()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl WebArchive {
+    /// Find links to pages which would be expected in the archive, as
+    /// they have the same origin as a stored resource, but which are
+    /// not stored, such as pages a multi-page capture missed.
+    ///
+    /// Links match a stored resource whatever their fragment, and with
+    /// or without a trailing slash, so `/about/#team` is the page
+    /// stored as `/about`. Dead links are listed in document order,
+    /// frame by frame, once for each link to them.
+    pub fn dead_internal_links(&self) -> Result<Vec<DeadLink>> {
+        let mut origins = HashSet::new();
+        let mut stored = HashSet::new();
+        for resource in self.iter_resources() {
+            if let Ok(url) = url::Url::parse(&resource.url) {
+                if url.origin().is_tuple() {
+                    origins.insert(url.origin());
+                }
+                stored.insert(page_key(url));
+            }
+        }
+
+        let mut dead = Vec::new();
+        for frame in self.outbound_links()? {
+            let document_url = &frame.frame.last().main_url;
+
+            for link in frame.links {
+                let url = match url::Url::parse(&link.url) {
+                    Ok(url) if origins.contains(&url.origin()) => url,
+                    _ => continue,
+                };
+
+                if !stored.contains(&page_key(url)) {
+                    dead.push(DeadLink {
+                        document_url: document_url.clone(),
+                        link,
+                    });
+                }
+            }
+        }
+
+        Ok(dead)
+    }
+}
+
+/// A URL without its fragment or any trailing slash, for matching
+/// links with the pages they lead to.
+fn page_key(mut url: url::Url) -> String {
+    url.set_fragment(None);
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&path);
+    url.into()
+}
+
+/// The raw `href`, text and `rel` of each `<a href>` element in an
+/// HTML document.
+fn anchors(html: &str) -> Result<Vec<(String, String, String)>> {
+    use lol_html::{element, text, HtmlRewriter, Settings};
+
+    let found = RefCell::new(Vec::new());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("a[href]", |element| {
+                    found.borrow_mut().push((
+                        decode_attribute(&element.get_attribute("href").unwrap_or_default()),
+                        String::new(),
+                        decode_attribute(&element.get_attribute("rel").unwrap_or_default()),
+                    ));
+
+                    Ok(())
+                }),
+                text!("a[href]", |chunk| {
+                    if let Some((_, text, _)) = found.borrow_mut().last_mut() {
+                        text.push_str(&decode_attribute(chunk.as_str()));
+                    }
+
+                    Ok(())
+                }),
+            ],
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    Ok(found.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Link, LinkKind};
+    use crate::test_support::text_resource;
+    use crate::WebArchive;
+
+    fn link(url: &str, kind: LinkKind, text: &str, rel: &[&str]) -> Link {
+        Link {
+            url: url.to_string(),
+            kind,
+            text: text.to_string(),
+            rel: rel.iter().map(|rel| rel.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn outbound_links() {
+        let resource = text_resource(
+            "https://example.com/blog/post.html?draft=1",
+            "text/html",
+            concat!(
+                "<nav><a href=\"../about/\">About\n   <b>us</b></a>",
+                "<a href=\"#comments\">Skip to comments</a>",
+                "<a href=\"\">This post</a></nav>\n",
+                "<p>See <a href=\"https://other.example/a?b=1&amp;c=2\" rel=\"NoFollow external\">",
+                "their  post</a>, or <a href=\"other.html#part-2\">part 2</a>.</p>\n",
+                "<a href=\"javascript:void(0)\">Share</a>\n",
+                "<a href=\"mailto:author@example.com\">Email me</a>\n",
+                "<a href=\"tel:+1-555-0100\">Call</a>\n",
+                "<a href=\"ftp://files.example/pub/\">Files</a>\n",
+                "<a name=\"end\">No link</a><area href=\"map.html\">",
+            ),
+        );
+
+        assert_eq!(
+            resource.outbound_links().unwrap(),
+            [
+                link("https://example.com/about/", LinkKind::Web, "About us", &[]),
+                link(
+                    "https://other.example/a?b=1&c=2",
+                    LinkKind::Web,
+                    "their post",
+                    &["nofollow", "external"]
+                ),
+                link(
+                    "https://example.com/blog/other.html#part-2",
+                    LinkKind::Web,
+                    "part 2",
+                    &[]
+                ),
+                link(
+                    "mailto:author@example.com",
+                    LinkKind::Mailto,
+                    "Email me",
+                    &[]
+                ),
+                link("tel:+1-555-0100", LinkKind::Tel, "Call", &[]),
+                link("ftp://files.example/pub/", LinkKind::Other, "Files", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_origin() {
+        let page = "https://example.com/blog/";
+        let same_origin = |url: &str| link(url, LinkKind::Web, "", &[]).is_same_origin(page);
+
+        assert!(same_origin("https://example.com/about/"));
+        assert!(same_origin("https://EXAMPLE.com:443/"));
+        assert!(!same_origin("http://example.com/about/"));
+        assert!(!same_origin("https://example.com:8443/"));
+        assert!(!same_origin("https://www.example.com/"));
+        assert!(!link("mailto:a@example.com", LinkKind::Mailto, "", &[]).is_same_origin(page));
+    }
+
+    #[test]
+    fn dead_internal_links() {
+        let mut about = text_resource(
+            "https://example.com/about",
+            "text/html",
+            "<h1>About us</h1>",
+        );
+        about.frame_name = Some("about".to_string());
+
+        let archive = WebArchive {
+            main_resource: text_resource(
+                "https://example.com/",
+                "text/html",
+                concat!(
+                    "<a href=\"/about/#team\">Our team</a>\n",
+                    "<a href=\"/contact\">Contact us</a>\n",
+                    "<a href=\"https://example.com:8443/contact\">Elsewhere</a>\n",
+                    "<a href=\"http://example.com/contact\">Insecurely</a>\n",
+                    "<a href=\"https://other.example/\">Another site</a>\n",
+                    "<a href=\"mailto:hello@example.com\">Email us</a>\n",
+                    "<iframe src=\"/about\"></iframe>",
+                ),
+            ),
+            subresources: None,
+            subframe_archives: Some(vec![WebArchive {
+                main_resource: about,
+                subresources: None,
+                subframe_archives: None,
+            }]),
+        };
+
+        let dead = archive.dead_internal_links().unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].document_url, "https://example.com/");
+        assert_eq!(
+            dead[0].link,
+            link(
+                "https://example.com/contact",
+                LinkKind::Web,
+                "Contact us",
+                &[]
+            )
+        );
+
+        let crouton: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        assert!(crouton.dead_internal_links().unwrap().is_empty());
+    }
+
+    #[test]
+    fn outbound_links_base() {
+        let resource = text_resource(
+            "https://example.com/page.html",
+            "text/html",
+            "<base href=\"https://mirror.example/docs/\"><a href=\"intro.html\">Intro</a>",
+        );
+
+        assert_eq!(
+            resource.outbound_links().unwrap(),
+            [link(
+                "https://mirror.example/docs/intro.html",
+                LinkKind::Web,
+                "Intro",
+                &[]
+            )]
+        );
+    }
+
+    #[test]
+    fn outbound_links_by_frame() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let frames = archive.outbound_links().unwrap();
+        assert_eq!(frames.len(), archive.iter_frames().count());
+        assert!(frames[0].frame.is_root());
+
+        let nav = frames
+            .iter()
+            .find(|frame| frame.frame.last().main_url == "http://psxdatacenter.com/nav.html")
+            .unwrap();
+        // the navigation buttons are images, so have no text
+        assert_eq!(
+            nav.links[0],
+            link(
+                "http://psxdatacenter.com/sitenews.html",
+                LinkKind::Web,
+                "",
+                &[]
+            )
+        );
+
+        let crouton: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let png = &crouton.subresources.as_ref().unwrap()[0];
+        assert!(png.outbound_links().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+//! Stable identifiers and default levels for the checks
+//! [`WebArchive::validate`] makes, for reporting them as lints.
+
+use crate::{Issue, IssueKind, Result, Severity, WebArchive};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What to do about a lint rule's findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Leave the findings out.
+    Allow,
+
+    /// Report the findings.
+    Warn,
+
+    /// Report the findings, and treat the archive as failing.
+    Deny,
+}
+
+impl fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        })
+    }
+}
+
+/// A check made on archives, as listed in [`LINT_RULES`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LintRule {
+    /// A stable identifier, such as `WA001`, which never changes
+    /// meaning once released.
+    pub id: &'static str,
+
+    /// A short name, such as `missing-subresource`.
+    pub name: &'static str,
+
+    /// The kind of validation issue this rule reports.
+    pub kind: IssueKind,
+
+    /// What the rule checks for.
+    pub description: &'static str,
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.id, self.name)
+    }
+}
+
+/// Every lint rule, in order of identifier.
+///
+/// New checks are added to [`WebArchive::validate`] with a new
+/// [`IssueKind`], and given the next identifier here.
+pub const LINT_RULES: &[LintRule] = &[
+    LintRule {
+        id: "WA001",
+        name: "missing-subresource",
+        kind: IssueKind::MissingSubresource,
+        description: "a resource refers to another which is not stored",
+    },
+    LintRule {
+        id: "WA002",
+        name: "empty-url",
+        kind: IssueKind::EmptyUrl,
+        description: "a resource has an empty URL",
+    },
+    LintRule {
+        id: "WA003",
+        name: "duplicate-url",
+        kind: IssueKind::DuplicateUrl,
+        description: "more than one resource in the same frame has the same URL",
+    },
+    LintRule {
+        id: "WA004",
+        name: "missing-mime-type",
+        kind: IssueKind::MissingMimeType,
+        description: "a resource has no MIME type",
+    },
+    LintRule {
+        id: "WA005",
+        name: "missing-encoding",
+        kind: IssueKind::MissingEncoding,
+        description: "a text resource does not declare its encoding",
+    },
+    LintRule {
+        id: "WA006",
+        name: "charset-conflict",
+        kind: IssueKind::CharsetConflict,
+        description: "an HTML document's <meta> element declares another encoding",
+    },
+    LintRule {
+        id: "WA007",
+        name: "mime-mismatch",
+        kind: IssueKind::MimeMismatch,
+        description: "a resource's data has the signature of another MIME type",
+    },
+    LintRule {
+        id: "WA008",
+        name: "integrity-mismatch",
+        kind: IssueKind::IntegrityMismatch,
+        description: "a subresource does not match the integrity digest declared for it",
+    },
+    LintRule {
+        id: "WA009",
+        name: "manifest-mismatch",
+        kind: IssueKind::ManifestMismatch,
+        description: "a resource differs from the embedded checksum manifest",
+    },
+    LintRule {
+        id: "WA010",
+        name: "dead-link",
+        kind: IssueKind::DeadLink,
+        description: "a document links to a page on an archived site which is not stored",
+    },
+];
+
+/// Find a lint rule by its identifier or name, ignoring case.
+pub fn lint_rule(id_or_name: &str) -> Option<&'static LintRule> {
+    LINT_RULES.iter().find(|rule| {
+        rule.id.eq_ignore_ascii_case(id_or_name) || rule.name.eq_ignore_ascii_case(id_or_name)
+    })
+}
+
+impl IssueKind {
+    /// The lint rule which reports issues of this kind.
+    pub fn lint_rule(&self) -> &'static LintRule {
+        LINT_RULES
+            .iter()
+            .find(|rule| rule.kind == *self)
+            .expect("Every kind of issue has a lint rule")
+    }
+}
+
+/// Levels for lint rules, other than their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, LintLevel>,
+}
+
+impl LintConfig {
+    /// Report `rule`'s findings at `level`, whatever their severity.
+    pub fn set(&mut self, rule: &'static LintRule, level: LintLevel) -> &mut Self {
+        self.levels.insert(rule.id, level);
+        self
+    }
+
+    /// The level for an issue: the one set for its rule, or else
+    /// denied for errors and warned about for warnings.
+    pub fn level(&self, issue: &Issue) -> LintLevel {
+        match self.levels.get(issue.kind.lint_rule().id) {
+            Some(level) => *level,
+            None => match issue.severity {
+                Severity::Error => LintLevel::Deny,
+                Severity::Warning => LintLevel::Warn,
+            },
+        }
+    }
+}
+
+/// One issue found by [`WebArchive::lint`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LintFinding {
+    /// The rule which found it.
+    pub rule: &'static LintRule,
+
+    /// Whether it is denied or only warned about.
+    pub level: LintLevel,
+
+    /// The issue found.
+    #[serde(flatten)]
+    pub issue: Issue,
+}
+
+/// The result of [`WebArchive::lint`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LintReport {
+    /// Every finding which is not allowed, grouped by rule, in order
+    /// of rule identifier.
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    /// Whether any finding is denied.
+    pub fn has_denied(&self) -> bool {
+        self.count(LintLevel::Deny) > 0
+    }
+
+    /// How many findings have `level`.
+    pub fn count(&self, level: LintLevel) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.level == level)
+            .count()
+    }
+}
+
+impl WebArchive {
+    /// Make every check [`validate`](WebArchive::validate) makes, and
+    /// report its issues under their [`LintRule`]s, at the levels
+    /// `config` gives them, leaving out those which are allowed.
+    pub fn lint(&self, config: &LintConfig) -> Result<LintReport> {
+        let mut findings: Vec<LintFinding> = self
+            .validate()?
+            .issues
+            .into_iter()
+            .map(|issue| LintFinding {
+                rule: issue.kind.lint_rule(),
+                level: config.level(&issue),
+                issue,
+            })
+            .filter(|finding| finding.level != LintLevel::Allow)
+            .collect();
+
+        // a stable sort, so each rule's findings stay in document order
+        findings.sort_by_key(|finding| finding.rule.id);
+
+        Ok(LintReport { findings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint_rule, LintConfig, LintLevel, LINT_RULES};
+    use crate::{IssueKind, WebArchive};
+
+    #[test]
+    fn lint_rules() {
+        for (index, rule) in LINT_RULES.iter().enumerate() {
+            assert_eq!(rule.id, format!("WA{:03}", index + 1));
+            assert_eq!(rule.kind.lint_rule(), rule);
+            assert_eq!(
+                serde_json::to_value(rule.kind).unwrap(),
+                rule.name,
+                "rules are named after their kind of issue"
+            );
+        }
+
+        assert_eq!(lint_rule("wa001").unwrap().name, "missing-subresource");
+        assert_eq!(
+            lint_rule("integrity-mismatch").unwrap().kind,
+            IssueKind::IntegrityMismatch
+        );
+        assert!(lint_rule("WA999").is_none());
+    }
+
+    #[test]
+    fn lint_levels() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let subresources = archive.subresources.as_mut().unwrap();
+        subresources[0].mime_type = String::new();
+        subresources[0].text_encoding_name = None;
+        archive.main_resource.data = b"<img src=a.png><img src=b.png>".to_vec();
+
+        let report = archive.lint(&LintConfig::default()).unwrap();
+        let findings: Vec<(&str, LintLevel)> = report
+            .findings
+            .iter()
+            .map(|finding| (finding.rule.id, finding.level))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                ("WA001", LintLevel::Warn),
+                ("WA001", LintLevel::Warn),
+                ("WA004", LintLevel::Deny),
+            ]
+        );
+        assert!(report.has_denied());
+        assert!(report.findings[0]
+            .issue
+            .url
+            .as_deref()
+            .unwrap()
+            .ends_with("/a.png"));
+
+        let mut config = LintConfig::default();
+        config
+            .set(lint_rule("missing-subresource").unwrap(), LintLevel::Deny)
+            .set(lint_rule("WA004").unwrap(), LintLevel::Allow);
+        let report = archive.lint(&config).unwrap();
+        assert_eq!(report.count(LintLevel::Deny), 2);
+        assert_eq!(report.count(LintLevel::Warn), 0);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::fetch::Fetcher;
+use crate::{Error, FetchOptions, Result, WebArchive, WebResource};
+use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// How a stored resource compares to the live version on the web.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveStatus {
+    /// The server has the same data as is stored.
+    Unchanged,
+    /// The server has different data.
+    Changed,
+    /// The resource, or its server, no longer exists.
+    Gone,
+    /// The server would not provide the resource, such as by
+    /// denying access, failing, or not responding in time.
+    Blocked,
+}
+
+impl fmt::Display for LiveStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LiveStatus::Unchanged => "unchanged",
+            LiveStatus::Changed => "changed",
+            LiveStatus::Gone => "gone",
+            LiveStatus::Blocked => "blocked",
+        })
+    }
+}
+
+/// The result of checking one URL, in a [`LiveReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LiveResult {
+    /// The URL of the stored resource.
+    pub url: String,
+
+    /// How the live version compares.
+    pub status: LiveStatus,
+
+    /// The live HTTP status code, if the server responded.
+    pub http_status: Option<u16>,
+
+    /// Why the resource was considered gone or blocked.
+    pub reason: Option<String>,
+}
+
+/// The result of [`WebArchive::verify_live`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LiveReport {
+    /// One result per stored URL, in document order.
+    pub results: Vec<LiveResult>,
+}
+
+impl LiveReport {
+    /// How many URLs have the given status.
+    pub fn count(&self, status: LiveStatus) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+}
+
+impl WebArchive {
+    /// Compare every stored `http` or `https` resource, at any depth,
+    /// with the live version on the web.
+    ///
+    /// Each URL is requested once, with a `GET` request. Where the
+    /// archived response has an `ETag` or `Last-Modified` header, the
+    /// request is made conditional on them, so an unchanged resource
+    /// need not be downloaded again. Otherwise, the downloaded data is
+    /// compared with the stored data byte for byte.
+    ///
+    /// Requests are made concurrently, time out, and are retried, as set
+    /// by `options`.
+    /// Failing requests are reported as [`Gone`](LiveStatus::Gone) or
+    /// [`Blocked`](LiveStatus::Blocked), rather than returned as errors.
+    pub async fn verify_live(&self, options: &FetchOptions) -> Result<LiveReport> {
+        let fetcher = Fetcher::new(options)?;
+
+        let mut seen = HashSet::new();
+        let resources = self.iter_resources().filter(|resource| {
+            (resource.url.starts_with("http://") || resource.url.starts_with("https://"))
+                && seen.insert(resource.url.as_str())
+        });
+
+        let results = fetcher
+            .run_limited(
+                resources
+                    .map(|resource| (resource.url.clone(), check_resource(&fetcher, resource))),
+            )
+            .await;
+
+        Ok(LiveReport { results })
+    }
+}
+
+async fn check_resource(fetcher: &Fetcher<'_>, resource: &WebResource) -> LiveResult {
+    let archived = resource.parse_response().ok().flatten();
+    let conditional = |mut request: reqwest::RequestBuilder| {
+        if let Some(archived) = &archived {
+            if let Some(etag) = archived.headers.get("ETag") {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = archived.headers.get("Last-Modified") {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        request
+    };
+
+    let result = |status, http_status: Option<StatusCode>, reason: Option<String>| LiveResult {
+        url: resource.url.clone(),
+        status,
+        http_status: http_status.map(|status| status.as_u16()),
+        reason,
+    };
+
+    let response = match fetcher.get(&resource.url, conditional).await {
+        Ok(response) => response,
+        Err(error) => {
+            let status = match &error {
+                Error::Fetch(error) if !error.is_timeout() => LiveStatus::Gone,
+                _ => LiveStatus::Blocked,
+            };
+            return result(status, None, Some(error.to_string()));
+        }
+    };
+
+    let status = response.status();
+
+    match status {
+        StatusCode::NOT_MODIFIED => result(LiveStatus::Unchanged, Some(status), None),
+        StatusCode::NOT_FOUND | StatusCode::GONE => result(LiveStatus::Gone, Some(status), None),
+        status if status.is_success() => match response.bytes().await {
+            Ok(body) if body == resource.data => result(LiveStatus::Unchanged, Some(status), None),
+            Ok(_) => result(LiveStatus::Changed, Some(status), None),
+            Err(error) => result(LiveStatus::Blocked, Some(status), Some(error.to_string())),
+        },
+        status => result(
+            LiveStatus::Blocked,
+            Some(status),
+            Some(format!("server responded {}", status)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LiveStatus;
+    use crate::test_server::{Response, TestServer};
+    use crate::test_support::{resource, text_resource};
+    use crate::{FetchOptions, WebArchive};
+
+    #[test]
+    fn verify_live() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let html = archive.main_resource.data.clone();
+        let png_etag = "\"143e-4fa5647f1c100\"";
+
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/" => Response::ok(html.clone()),
+            "/crouton.png" if request.header("If-None-Match") == Some(png_etag) => {
+                Response::status(304)
+            }
+            "/crouton.png" => Response::ok(b"not the stored image".to_vec()),
+            "/style.css" => Response::ok(b"body { color: red }".to_vec()),
+            "/private.js" => Response::status(403),
+            _ => Response::status(404),
+        });
+
+        let mut archive = archive;
+        archive.main_resource.url = server.url("/");
+        let subresources = archive.subresources.as_mut().unwrap();
+        subresources[0].url = server.url("/crouton.png");
+        for path in ["/style.css", "/script.js", "/private.js"] {
+            subresources.push(text_resource(
+                &server.url(path),
+                "text/css",
+                b"body { color: blue }",
+            ));
+        }
+        subresources.push(resource(
+            "data:text/plain,ignored",
+            "text/plain",
+            b"ignored",
+        ));
+
+        let report = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(archive.verify_live(&FetchOptions::default()))
+            .unwrap();
+
+        let statuses: Vec<(&str, LiveStatus, Option<u16>)> = report
+            .results
+            .iter()
+            .map(|result| {
+                (
+                    result.url.rsplit('/').next().unwrap(),
+                    result.status,
+                    result.http_status,
+                )
+            })
+            .collect();
+
+        use LiveStatus::*;
+        assert_eq!(
+            statuses,
+            [
+                ("", Unchanged, Some(200)),
+                ("crouton.png", Unchanged, Some(304)),
+                ("style.css", Changed, Some(200)),
+                ("script.js", Gone, Some(404)),
+                ("private.js", Blocked, Some(403)),
+            ]
+        );
+        assert_eq!(report.count(Unchanged), 2);
+    }
+}
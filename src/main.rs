@@ -1,169 +1,8569 @@
 use anyhow::{Context, Result};
-use std::path::{Path, PathBuf};
 use clap::Parser;
-use webarchive::{WebArchive, WebResource};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use webarchive::{FrameSelector, WebArchive, WebResource};
 
-fn save(resource: WebResource, inside: &Path) -> std::io::Result<()> {
+fn save(resource: &WebResource, path: &Path) -> std::io::Result<()> {
     use std::io::Write;
-    let mut url: String = resource
-        .url
-        .clone()
-        .splitn(2, "//")
-        .last()
-        .expect("File should have a protocol")
-        .to_string();
 
-    if url.ends_with('/') {
-        // We need to generate a file name, as there wasn't one given
-        let guessed_ext = match mime_guess::get_mime_extensions_str(&resource.mime_type) {
-            None => "txt",
-            Some(mime_extensions) => mime_extensions
-                .last()
-                .expect("MIME returned no extensions in a Some; weird!"),
+    let parent_path = path.parent().expect("Could not get parent directory");
+
+    println!("Writing file {:?}...", path);
+
+    std::fs::create_dir_all(parent_path)?;
+    std::fs::File::create(path)?.write_all(&resource.data)?;
+    Ok(())
+}
+
+/// When a resource was last changed, according to its archived response
+fn modified_time(resource: &WebResource) -> Option<std::time::SystemTime> {
+    let response = resource.parse_response().ok()??;
+    response.last_modified().or_else(|| response.date())
+}
+
+/// A relative URL leading from the file at `from` to the file at `to`
+fn relative_link(from: &Path, to: &Path) -> String {
+    let from_dir: Vec<_> = from
+        .parent()
+        .map(|parent| parent.components().collect())
+        .unwrap_or_default();
+    let to: Vec<_> = to.components().collect();
+
+    let common = from_dir
+        .iter()
+        .zip(&to)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    let mut segments = vec!["..".to_string(); from_dir.len() - common];
+    segments.extend(to[common..].iter().map(|component| {
+        component
+            .as_os_str()
+            .to_string_lossy()
+            .replace('%', "%25")
+            .replace('?', "%3F")
+            .replace('#', "%23")
+            .replace(' ', "%20")
+    }));
+
+    segments.join("/")
+}
+
+/// Where each planned file is saved, by its resource's normalised URL,
+/// for converting links
+fn link_targets(plan: &[webarchive::PlannedFile]) -> HashMap<String, PathBuf> {
+    plan.iter()
+        .map(|planned| {
+            let url = match url::Url::parse(&planned.resource.url) {
+                Ok(url) => url.into(),
+                Err(_) => planned.resource.url.clone(),
+            };
+            (url, planned.path.clone())
+        })
+        .collect()
+}
+
+/// A copy of an HTML or CSS resource saved at `from` whose references to
+/// resources in `paths` are changed to relative links to where those will
+/// be saved, or `None` if nothing needed to change
+fn with_converted_links(
+    resource: &WebResource,
+    from: &Path,
+    paths: &HashMap<String, PathBuf>,
+) -> webarchive::Result<Option<WebResource>> {
+    if !resource.is_html() && !resource.is_css() {
+        return Ok(None);
+    }
+
+    let mut converted = WebResource {
+        url: resource.url.clone(),
+        data: resource.data.clone(),
+        mime_type: resource.mime_type.clone(),
+        text_encoding_name: resource.text_encoding_name.clone(),
+        frame_name: resource.frame_name.clone(),
+        response: None,
+    };
+
+    let converted_links = converted.rewrite_references(|url| {
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (url, None),
         };
 
-        url.push_str("_unnamed_index.");
-        url.push_str(guessed_ext);
+        let mut link = relative_link(from, paths.get(url)?);
+        if let Some(fragment) = fragment {
+            link.push('#');
+            link.push_str(fragment);
+        }
+
+        Some(link)
+    })?;
+
+    Ok((converted_links > 0).then_some(converted))
+}
+
+/// The start of `text`, up to `max_bytes` bytes or `max_lines` lines,
+/// ending at a character boundary, and whether any was left out
+fn text_head(text: &str, max_bytes: usize, max_lines: Option<usize>) -> (&str, bool) {
+    let end = match max_lines {
+        Some(0) => 0,
+        Some(lines) => text
+            .match_indices('\n')
+            .nth(lines - 1)
+            .map_or(text.len(), |(index, _)| index + 1),
+        None => {
+            let mut end = max_bytes.min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            end
+        }
+    };
+
+    (&text[..end], end < text.len())
+}
+
+/// Write `data` as `hexdump -C` does: offsets, 16 bytes per line in
+/// hexadecimal, and those which are printable ASCII, ending with the
+/// length
+fn write_hex_dump(out: &mut impl std::io::Write, data: &[u8]) -> std::io::Result<()> {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        write!(out, "{:08x}  ", line * 16)?;
+        for index in 0..16 {
+            match chunk.get(index) {
+                Some(byte) => write!(out, "{:02x} ", byte)?,
+                None => write!(out, "   ")?,
+            }
+            if index == 7 {
+                write!(out, " ")?;
+            }
+        }
+
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, " |{}|", ascii)?;
     }
 
-    let path = inside.join(&url);
-    let parent_path = path.parent().expect("Could not get parent directory");
+    writeln!(out, "{:08x}", data.len())
+}
 
-    println!("Writing file {:?}...", path);
+/// How `save_archive` writes resources
+#[derive(Debug, Default)]
+struct ExtractOptions {
+    /// Which resources to save
+    filter: webarchive::ResourceFilter,
+    /// Change links between HTML and CSS files to relative paths
+    convert_links: bool,
+    /// Set each file's modification time from its archived response
+    preserve_mtime: bool,
+    /// Write each resource's archived response next to it
+    with_responses: bool,
+    /// Write archived responses as JSON, where they can be parsed
+    decode_responses: bool,
+    /// Write a manifest of the files written
+    manifest: bool,
+    /// How to save files with the same contents as one already saved
+    dedupe_files: DedupeFiles,
+    /// Where to save each resource
+    plan: webarchive::PlanOptions,
+    /// Write text resources as UTF-8
+    transcode_to_utf8: bool,
+    /// Transcode text resources even where they are malformed
+    lossy: bool,
+}
 
-    std::fs::create_dir_all(parent_path)?;
-    std::fs::File::create(path)?.write_all(&resource.data)
+/// Where `extract --with-responses` writes the response for the
+/// resource at `path`, with `extension`
+fn response_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".response.");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Write the archived response of the resource saved at `path`, if it
+/// has one, as JSON if `decode` and it can be parsed, or else as the
+/// property list it is stored as, returning where it was written
+fn save_response(resource: &WebResource, path: &Path, decode: bool) -> Result<Option<PathBuf>> {
+    let raw = match &resource.response {
+        Some(raw) if !raw.is_empty() => raw,
+        _ => return Ok(None),
+    };
+
+    if decode {
+        match resource.parse_response() {
+            Ok(Some(response)) => {
+                let path = response_path(path, "json");
+                println!("Writing response {:?}...", path);
+                let mut json = serde_json::to_string_pretty(&response)?;
+                json.push('\n');
+                std::fs::write(&path, json)
+                    .with_context(|| format!("failed to write {:?}", path))?;
+                return Ok(Some(path));
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!(
+                "warning: could not parse the response for {:?}, so writing it as it is: {}",
+                resource.url, error
+            ),
+        }
+    }
+
+    let path = response_path(path, "plist");
+    println!("Writing response {:?}...", path);
+    std::fs::write(&path, raw).with_context(|| format!("failed to write {:?}", path))?;
+    Ok(Some(path))
+}
+
+/// The UTF-8 copy of a text resource to save in place of it, or `None`
+/// to save it as it is: if it is not text, or, unless `lossy`, is
+/// malformed in its encoding
+fn transcoded(resource: &WebResource, lossy: bool) -> Result<Option<webarchive::Utf8Resource>> {
+    let utf8 = resource
+        .to_utf8()
+        .with_context(|| format!("failed to transcode {:?}", resource.url))?;
+
+    Ok(utf8.filter(|utf8| lossy || !utf8.lossy))
 }
 
-fn save_archive(archive: WebArchive, inside: &Path) -> std::io::Result<()> {
-    println!("Saving main resource...");
-    save(archive.main_resource, inside)?;
+/// How `extract` saves files with the same contents as one already saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ArgEnum)]
+enum DedupeFiles {
+    /// Write the contents again
+    #[default]
+    Copy,
+    /// Make a hard link to the first file
+    Hardlink,
+    /// Make a relative symbolic link to the first file
+    Symlink,
+}
+
+/// How `create` captures pages
+#[cfg(feature = "cdp")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+enum Renderer {
+    /// Fetch the page and the subresources its HTML and CSS refer to
+    Http,
+    /// Load the page in Chrome or Chromium, over the DevTools Protocol
+    Chrome,
+}
+
+/// How `extract` lays out the files it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+enum Layout {
+    /// A folder for each host, and each folder in the URL's path
+    Default,
+    /// As `wget --mirror` does
+    Wget,
+}
+
+impl From<Layout> for webarchive::ExtractLayout {
+    fn from(layout: Layout) -> Self {
+        match layout {
+            Layout::Default => webarchive::ExtractLayout::Default,
+            Layout::Wget => webarchive::ExtractLayout::Wget,
+        }
+    }
+}
+
+/// Link `path` to the existing file `target`, replacing anything at
+/// `path`, where `relative_target` is `target` relative to `path`'s folder
+fn link_file(
+    mode: DedupeFiles,
+    target: &Path,
+    relative_target: &Path,
+    path: &Path,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(path.parent().expect("Could not get parent directory"))?;
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
 
-    if let Some(subresources) = archive.subresources {
-        println!("Saving subresources...");
-        subresources
-            .into_iter()
-            .for_each(|subresource| save(subresource, inside).expect("Could not save subresource"));
+    match mode {
+        DedupeFiles::Copy => unreachable!("copies are saved, not linked"),
+        DedupeFiles::Hardlink => std::fs::hard_link(target, path),
+        #[cfg(unix)]
+        DedupeFiles::Symlink => std::os::unix::fs::symlink(relative_target, path),
+        #[cfg(windows)]
+        DedupeFiles::Symlink => std::os::windows::fs::symlink_file(relative_target, path),
+        #[cfg(not(any(unix, windows)))]
+        DedupeFiles::Symlink => Err(std::io::ErrorKind::Unsupported.into()),
     }
+}
+
+/// The path leading from the folder of the file at `from` to the file at
+/// `to`, where both are relative to the same folder
+fn path_between(from: &Path, to: &Path) -> PathBuf {
+    let from_dir: Vec<_> = from
+        .parent()
+        .map(|parent| parent.components().collect())
+        .unwrap_or_default();
+    let to: Vec<_> = to.components().collect();
+
+    let common = from_dir
+        .iter()
+        .zip(&to)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    std::iter::repeat_n(std::path::Component::ParentDir, from_dir.len() - common)
+        .chain(to[common..].iter().copied())
+        .collect()
+}
 
-    if let Some(subframe_archives) = archive.subframe_archives {
-        println!("Saving subframe archives...");
-        subframe_archives.into_iter().for_each(|subframe_archive| {
-            save_archive(subframe_archive, inside).expect("Could not save subframe_archive")
+fn save_archive(archive: &WebArchive, inside: &Path, options: &ExtractOptions) -> Result<()> {
+    let plan = archive.plan_extraction_with(&options.plan);
+    let links = if options.convert_links {
+        link_targets(&plan)
+    } else {
+        HashMap::new()
+    };
+
+    let planned: HashMap<&str, &webarchive::PlannedFile> = plan
+        .iter()
+        .map(|planned| (planned.resource.url.as_str(), planned))
+        .collect();
+
+    // the first file saved with each digest, to link the others to
+    let mut saved: HashMap<String, &Path> = HashMap::new();
+    let mut manifest = webarchive::ExtractionManifest::default();
+    // resources whose URL schemes were unknown, so were named by digest
+    let mut unknown_schemes: Vec<(&str, &Path)> = Vec::new();
+
+    let mut save = |resource: &WebResource| -> Result<()> {
+        let planned_file = planned[resource.url.as_str()];
+        let relative_path = &planned_file.path;
+        if planned_file
+            .renamed
+            .contains(&webarchive::RenameReason::UnknownScheme)
+        {
+            unknown_schemes.push((planned_file.resource.url.as_str(), relative_path));
+        }
+        if planned_file.name_source == webarchive::NameSource::ContentDisposition {
+            println!(
+                "Naming {:?} from its Content-Disposition header...",
+                resource.url
+            );
+        }
+        let mut transcoded_from = None;
+        let transcoded = if options.transcode_to_utf8 {
+            let utf8 = transcoded(resource, options.lossy)?;
+            match &utf8 {
+                Some(utf8) if utf8.original_encoding != encoding_rs::UTF_8 => {
+                    println!(
+                        "Transcoding {:?} from {} to UTF-8...",
+                        resource.url,
+                        utf8.original_encoding.name()
+                    );
+                    transcoded_from = Some(utf8.original_encoding.name().to_string());
+                }
+                None if resource.is_text() => eprintln!(
+                    "warning: {:?} is not valid {}, so writing it unchanged",
+                    resource.url,
+                    resource.encoding().name()
+                ),
+                _ => {}
+            }
+            utf8.map(|utf8| utf8.resource)
+        } else {
+            None
+        };
+        let resource = transcoded.as_ref().unwrap_or(resource);
+
+        let converted = if options.convert_links {
+            with_converted_links(resource, relative_path, &links)
+                .with_context(|| format!("failed to convert links in {:?}", resource.url))?
+        } else {
+            None
+        };
+        let contents = converted.as_ref().unwrap_or(resource);
+        let path = inside.join(relative_path);
+
+        let response = if options.with_responses {
+            std::fs::create_dir_all(path.parent().expect("Could not get parent directory"))?;
+            save_response(resource, &path, options.decode_responses)?
+        } else {
+            None
+        };
+
+        let mut file = webarchive::ExtractedFile::new(planned_file, contents);
+        file.transcoded_from = transcoded_from;
+        file.response = response.and_then(|response| {
+            let relative = response.strip_prefix(inside).ok()?;
+            Some(
+                relative
+                    .to_string_lossy()
+                    .replace(std::path::MAIN_SEPARATOR, "/"),
+            )
         });
+        manifest.files.push(file);
+
+        if options.dedupe_files != DedupeFiles::Copy {
+            let digest = contents.hex_digest(webarchive::DigestAlgorithm::Sha256);
+
+            match saved.get(&digest) {
+                Some(&first) => {
+                    let target = inside.join(first);
+                    println!("Linking file {:?} to {:?}...", path, target);
+
+                    // hard links share the first file's modification time
+                    match link_file(
+                        options.dedupe_files,
+                        &target,
+                        &path_between(relative_path, first),
+                        &path,
+                    ) {
+                        Ok(()) => return Ok(()),
+                        Err(error) => eprintln!(
+                            "warning: could not link {:?} to {:?}, so copying it: {}",
+                            path, target, error
+                        ),
+                    }
+                }
+                None => {
+                    saved.insert(digest, relative_path);
+                }
+            }
+        }
+
+        save(contents, &path)?;
+
+        if options.preserve_mtime {
+            if let Some(modified) = modified_time(resource) {
+                filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(modified))
+                    .with_context(|| {
+                        format!("failed to set the modification time of {:?}", path)
+                    })?;
+            }
+        }
+
+        Ok(())
+    };
+
+    // only the first resource with each URL is planned, so skip any others
+    let is_planned =
+        |resource: &WebResource| std::ptr::eq(planned[resource.url.as_str()].resource, resource);
+
+    for (_, frame) in archive.iter_frames() {
+        if options.filter.matches(&frame.main_resource, true) && is_planned(&frame.main_resource) {
+            println!("Saving main resource...");
+            save(&frame.main_resource)?;
+        }
+
+        if let Some(subresources) = &frame.subresources {
+            println!("Saving subresources...");
+            for subresource in subresources {
+                if options.filter.matches(subresource, false) && is_planned(subresource) {
+                    save(subresource)?;
+                }
+            }
+        }
+
+        if frame.subframe_archives.is_some() {
+            println!("Saving subframe archives...");
+        }
+    }
+
+    if !unknown_schemes.is_empty() {
+        println!(
+            "Named {} resource(s) with unknown URL schemes after their digests:",
+            unknown_schemes.len()
+        );
+        for (url, path) in &unknown_schemes {
+            println!("  {:?} as {:?}", url, path);
+        }
+    }
+
+    if options.manifest {
+        println!(
+            "Writing manifest {:?}...",
+            inside.join(webarchive::ExtractionManifest::FILE_NAME)
+        );
+        manifest
+            .write_to(inside)
+            .context("failed to write the manifest")?;
     }
 
     Ok(())
 }
 
-/// Utility for inspecting or extracting a webarchive file
-#[derive(Debug, Parser)]
-#[clap(version)]
-enum Args {
-    /// List the contents of a webarchive file
-    Inspect {
-        #[clap(parse(from_os_str))]
-        /// File or folder to inspect
-        input: PathBuf,
-    },
+/// Compare the files in `folder` with where `save_archive` would have
+/// saved `archive` with `options`, printing each difference and
+/// returning how many there are
+fn verify_extraction(
+    archive: &WebArchive,
+    archive_path: &Path,
+    folder: &Path,
+    options: &ExtractOptions,
+) -> Result<usize> {
+    let plan = archive.plan_extraction_with(&options.plan);
+    let links = if options.convert_links {
+        link_targets(&plan)
+    } else {
+        HashMap::new()
+    };
 
-    /// Extract the contents of a webarchive file to individual files
-    Extract {
-        #[clap(parse(from_os_str))]
-        /// File or folder to convert
-        input: PathBuf,
+    let mut differences = 0;
+    let mut checked = 0;
 
-        #[clap(short, long, parse(from_os_str))]
-        /// File or folder name to output to.
-        ///
-        /// If omitted, files will be written to
-        /// the folder containing the input file.
-        output: Option<PathBuf>,
-    },
+    for planned in &plan {
+        if !options
+            .filter
+            .matches(planned.resource, planned.is_main_resource)
+        {
+            continue;
+        }
+
+        let path = folder.join(&planned.path);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                println!("Missing {:?} ({:?})", planned.path, planned.resource.url);
+                differences += 1;
+                continue;
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read {:?}", path));
+            }
+        };
+
+        let transcoded = if options.transcode_to_utf8 {
+            transcoded(planned.resource, options.lossy)?.map(|utf8| utf8.resource)
+        } else {
+            None
+        };
+        let resource = transcoded.as_ref().unwrap_or(planned.resource);
+
+        let converted = if options.convert_links {
+            with_converted_links(resource, &planned.path, &links)
+                .with_context(|| format!("failed to convert links in {:?}", resource.url))?
+        } else {
+            None
+        };
+        let expected = converted.as_ref().unwrap_or(resource);
+
+        let algorithm = webarchive::DigestAlgorithm::Sha256;
+        if algorithm.hex_digest(&data) != expected.hex_digest(algorithm) {
+            println!(
+                "Mismatched {:?} ({:?}, {} bytes, expected {})",
+                planned.path,
+                planned.resource.url,
+                data.len(),
+                expected.data.len()
+            );
+            differences += 1;
+        }
+
+        checked += 1;
+    }
+
+    let mut expected: std::collections::HashSet<PathBuf> =
+        plan.iter().map(|planned| planned.path.clone()).collect();
+    expected.insert(PathBuf::from(webarchive::ExtractionManifest::FILE_NAME));
+    if options.with_responses {
+        for planned in &plan {
+            if planned
+                .resource
+                .response
+                .as_ref()
+                .is_some_and(|raw| !raw.is_empty())
+            {
+                expected.insert(response_path(&planned.path, "plist"));
+                expected.insert(response_path(&planned.path, "json"));
+            }
+        }
+    }
+    let archive_path = archive_path.canonicalize().ok();
+
+    let mut folders = vec![PathBuf::new()];
+    while let Some(relative) = folders.pop() {
+        let entries = std::fs::read_dir(folder.join(&relative))
+            .with_context(|| format!("failed to list {:?}", folder.join(&relative)))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                folders.push(path);
+            } else if !expected.contains(&path) && entry.path().canonicalize().ok() != archive_path
+            {
+                println!("Extra {:?}", path);
+                differences += 1;
+            }
+        }
+    }
+
+    if differences == 0 {
+        println!("All {} file(s) match", checked);
+    }
+
+    Ok(differences)
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Open `path` with `browser`, a command followed by any arguments,
+/// or the system's default application for it
+fn launch(path: &Path, browser: Option<&str>) -> Result<()> {
+    match browser {
+        Some(command) => {
+            let mut words = command.split_whitespace();
+            let program = words.next().context("The browser command is empty")?;
 
-    match args {
-        Args::Inspect { input } => {
-            let webarchive: WebArchive = webarchive::from_file(&input)
-                .with_context(|| format!("failed to read {:?}", input))?;
+            let status = std::process::Command::new(program)
+                .args(words)
+                .arg(path)
+                .status()
+                .with_context(|| format!("failed to run {:?}", command))?;
 
-            webarchive.print_list();
+            if !status.success() {
+                anyhow::bail!("{:?} failed: {}", command, status);
+            }
 
             Ok(())
         }
+        None => open::that(path).with_context(|| format!("failed to open {:?}", path)),
+    }
+}
 
-        Args::Extract { input, output } => {
-            let webarchive: WebArchive = webarchive::from_file(&input)
-                .with_context(|| format!("failed to read {:?}", input))?;
+/// The standard reason phrase for common HTTP status codes
+fn reason_phrase(status: u16) -> Option<&'static str> {
+    Some(match status {
+        200 => "OK",
+        201 => "Created",
+        203 => "Non-Authoritative Information",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        410 => "Gone",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => return None,
+    })
+}
 
-            let output = match &output {
-                Some(path) => path,
-                None => input
-                    .parent()
-                    .context("Could not get an output directory")?,
+/// Parse a `--map` prefix mapping, such as `http://old.example=https://new.example`
+fn parse_mapping(mapping: &str) -> Result<(String, String), String> {
+    match mapping.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("expected OLD=NEW, not {:?}", mapping)),
+    }
+}
+
+/// Parse a lint rule given to `--allow` or `--deny`, by ID or name
+fn parse_lint_rule(rule: &str) -> Result<&'static webarchive::LintRule, String> {
+    webarchive::lint_rule(rule).ok_or_else(|| format!("unknown lint rule {:?}", rule))
+}
+
+/// Escape text for a GitHub Actions workflow command, where `property`
+/// says whether it is a property, rather than the message
+fn escape_workflow_command(text: &str, property: bool) -> String {
+    let mut escaped = text
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        escaped = escaped.replace(':', "%3A").replace(',', "%2C");
+    }
+    escaped
+}
+
+/// Parse a `--regex` substitution, such as `s#pattern#replacement#`,
+/// where the character after `s` separates the parts
+fn parse_substitution(substitution: &str) -> Result<(regex::Regex, String), String> {
+    let invalid = || format!("expected s#PATTERN#REPLACEMENT#, not {:?}", substitution);
+
+    let rest = substitution.strip_prefix('s').ok_or_else(invalid)?;
+    let delimiter = rest.chars().next().ok_or_else(invalid)?;
+    let parts: Vec<&str> = rest[delimiter.len_utf8()..].split(delimiter).collect();
+
+    match parts[..] {
+        [pattern, replacement, ""] => {
+            let pattern = regex::Regex::new(pattern).map_err(|error| error.to_string())?;
+            Ok((pattern, replacement.to_string()))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// A `sed` substitution, as parsed by [`parse_sed_expression`]
+#[derive(Debug, Clone)]
+struct SedExpression {
+    pattern: regex::Regex,
+    replacement: String,
+    /// Whether to replace every match, rather than the first on each line
+    global: bool,
+}
+
+/// Parse a `sed` substitution such as `s/PATTERN/REPLACEMENT/gi`, where
+/// the delimiter may be any punctuation, and may appear in the pattern
+/// or replacement when escaped with a backslash
+fn parse_sed_expression(expression: &str) -> Result<SedExpression, String> {
+    let invalid = || format!("expected s/PATTERN/REPLACEMENT/FLAGS, not {:?}", expression);
+
+    let rest = expression.strip_prefix('s').ok_or_else(invalid)?;
+    let delimiter = rest
+        .chars()
+        .next()
+        .filter(|delimiter| delimiter.is_ascii_punctuation() && *delimiter != '\\')
+        .ok_or_else(invalid)?;
+
+    let mut parts = vec![String::new()];
+    let mut chars = rest[delimiter.len_utf8()..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let part = parts.last_mut().expect("parts is never empty");
+                part.push(c);
+                part.extend(chars.next());
+            }
+            c if c == delimiter => parts.push(String::new()),
+            c => parts.last_mut().expect("parts is never empty").push(c),
+        }
+    }
+
+    let (pattern, replacement, flags) = match &parts[..] {
+        [pattern, replacement, flags] => (pattern, replacement, flags),
+        _ => return Err(invalid()),
+    };
+
+    let mut builder = regex::RegexBuilder::new(pattern);
+    let mut global = false;
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'i' => {
+                builder.case_insensitive(true);
+            }
+            flag => return Err(format!("unknown flag {:?} in {:?}", flag, expression)),
+        }
+    }
+
+    Ok(SedExpression {
+        pattern: builder.build().map_err(|error| error.to_string())?,
+        // the pattern keeps its escapes, as escaped punctuation means the same in a regex
+        replacement: replacement.replace(&format!("\\{}", delimiter), &delimiter.to_string()),
+        global,
+    })
+}
+
+/// Parse a `--status` range of HTTP status codes, such as `404`,
+/// `400..600`, `500..=599` or `400..`
+fn parse_status_range(range: &str) -> Result<(Bound<u16>, Bound<u16>), String> {
+    let status = |status: &str| {
+        status.trim().parse::<u16>().map_err(|_| {
+            format!(
+                "expected a status or range such as 400..600, not {:?}",
+                range
+            )
+        })
+    };
+
+    Ok(match range.split_once("..") {
+        None => {
+            let status = status(range)?;
+            (Bound::Included(status), Bound::Included(status))
+        }
+        Some((start, end)) => {
+            let start = match start.trim() {
+                "" => Bound::Unbounded,
+                start => Bound::Included(status(start)?),
             };
+            let end = match end.strip_prefix('=') {
+                Some(end) => Bound::Included(status(end)?),
+                None if end.trim().is_empty() => Bound::Unbounded,
+                None => Bound::Excluded(status(end)?),
+            };
+            (start, end)
+        }
+    })
+}
+
+/// One of the largest resources in an archive, for `--top`
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct LargeResource {
+    url: String,
+    mime_type: String,
+    size: usize,
+    /// Share of the archive's total size
+    percentage: f64,
+}
+
+/// What `inspect --format json` prints
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct Inspection {
+    #[serde(flatten)]
+    summary: webarchive::ArchiveSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksums: Option<Checksums>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest: Option<Vec<LargeResource>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture_info: Option<webarchive::CaptureInfo>,
+    #[cfg(feature = "image")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    images: Option<Vec<InspectedImage>>,
+}
+
+/// Print when and how the archive was captured, if it records it
+fn print_capture_info(archive: &WebArchive) {
+    if let Some(info) = archive.capture_info() {
+        println!(
+            "Captured {} by {} {}",
+            httpdate::fmt_http_date(info.captured_at),
+            info.tool,
+            info.tool_version
+        );
+    }
+}
+
+/// The checksums of an archive's resources, for `inspect --checksums`
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct Checksums {
+    algorithm: webarchive::DigestAlgorithm,
+    resources: Vec<webarchive::ResourceChecksum>,
+}
+
+/// The dimensions of an image, for `inspect --verbose`
+#[cfg(feature = "image")]
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct InspectedImage {
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+/// What `stats --format json` prints for a file
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct FileStats {
+    total_size: usize,
+    resource_count: usize,
+    frame_count: usize,
+    mime_types: BTreeMap<String, webarchive::MimeTypeStats>,
+    completeness: webarchive::Completeness,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest: Option<Vec<LargeResource>>,
+}
+
+/// What `stats --format json` prints for a folder
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct FolderStats<'a> {
+    #[serde(flatten)]
+    stats: &'a webarchive::CollectionStats,
+    file_sizes: Option<webarchive::Distribution>,
+    resource_counts: Option<webarchive::Distribution>,
+}
+
+/// One file's findings, of the list `lint --format json` prints
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct LintedFile<'a> {
+    file: &'a Path,
+    findings: &'a [webarchive::LintFinding],
+}
+
+fn largest_resources(webarchive: &WebArchive, n: usize) -> Vec<LargeResource> {
+    let total = webarchive.total_size().max(1);
 
-            save_archive(webarchive, output).context("Saving resources")
+    webarchive
+        .largest_resources(n)
+        .into_iter()
+        .map(|resource| LargeResource {
+            url: resource.url.clone(),
+            mime_type: resource.mime_type.clone(),
+            size: resource.data.len(),
+            percentage: resource.data.len() as f64 * 100.0 / total as f64,
+        })
+        .collect()
+}
+
+/// The `.webarchive` and `.webarchive.gz` files in `folder`, and in the
+/// folders inside it if `recursive`, in order of path
+fn archive_files(folder: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut archives = Vec::new();
+    let mut folders = vec![folder.to_path_buf()];
+
+    while let Some(folder) = folders.pop() {
+        for entry in
+            std::fs::read_dir(&folder).with_context(|| format!("failed to list {:?}", folder))?
+        {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if entry.file_type()?.is_dir() {
+                if recursive {
+                    folders.push(path);
+                }
+            } else if name.ends_with(".webarchive") || name.ends_with(".webarchive.gz") {
+                archives.push(path);
+            }
         }
     }
+
+    archives.sort();
+    Ok(archives)
 }
 
-#[cfg(test)]
-mod tests {
-    use assert_cmd::Command;
-    use assert_fs::prelude::*;
+/// Statistics over the archives in `folder`, read one at a time, with
+/// paths relative to it
+fn collection_stats(
+    files: &ArchiveFiles,
+    folder: &Path,
+    recursive: bool,
+) -> Result<webarchive::CollectionStats> {
+    let mut stats = webarchive::CollectionStats::default();
 
-    const CROUTON_WEBARCHIVE: &[u8] = include_bytes!("../fixtures/crouton.webarchive");
-    const CROUTON_INDEX_SHTML: &[u8] =
-        include_bytes!("../fixtures/crouton.net/_unnamed_index.shtml");
-    const CROUTON_PNG: &[u8] = include_bytes!("../fixtures/crouton.net/crouton.png");
+    for path in archive_files(folder, recursive)? {
+        let name = path
+            .strip_prefix(folder)
+            .unwrap_or(&path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
 
-    #[test]
-    fn list_crouton() {
-        let temp = assert_fs::TempDir::new().unwrap();
+        let read = || -> Result<_> {
+            let file_size = std::fs::metadata(&path)?.len();
+            let summary = files.read(&path)?.summary_info()?;
+            Ok((file_size, summary))
+        };
 
-        let input_file = temp.child("crouton.webarchive");
-        input_file
-            .write_binary(&CROUTON_WEBARCHIVE)
-            .expect("Couldn't write temporary file");
+        match read() {
+            Ok((file_size, summary)) => stats.add(name, file_size, &summary),
+            Err(error) => stats.add_failure(name, error.to_string()),
+        }
+    }
 
-        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+    Ok(stats)
+}
 
-        let assert = cmd.arg("inspect").arg(input_file.path()).assert();
+fn print_collection_stats(stats: &webarchive::CollectionStats) {
+    println!(
+        "{:<40} {:>12} {:>12} {:>9} {:>6} {:>9}",
+        "Archive", "File size", "Bytes", "Resources", "Frames", "Complete"
+    );
+    for entry in &stats.archives {
+        println!(
+            "{:<40} {:>12} {:>12} {:>9} {:>6} {:>8.1}%",
+            entry.path,
+            entry.file_size,
+            entry.total_size,
+            entry.resource_count,
+            entry.frame_count,
+            entry.completeness.ratio() * 100.0
+        );
+    }
+    println!();
 
-        assert.success().stdout(
-            "WebArchive of \"https://crouton.net/\" (\"text/html\", 134 bytes): 1 subresource, 0 subframe archives totalling 5316 bytes\n  \
-            - \"https://crouton.net/crouton.png\" (\"image/png\", 5182 bytes)\n",
+    println!(
+        "{} archive(s), {} of which could not be read",
+        stats.archives.len() + stats.failures.len(),
+        stats.failures.len()
+    );
+    for failure in &stats.failures {
+        println!("  {}: {}", failure.path, failure.error);
+    }
+    println!(
+        "{} archive(s) store everything they refer to",
+        stats
+            .archives
+            .iter()
+            .filter(|entry| entry.completeness.is_complete())
+            .count()
+    );
+
+    if let (Some(file_sizes), Some(resource_counts)) = (stats.file_sizes(), stats.resource_counts())
+    {
+        println!(
+            "File sizes: {} bytes in total, {} median, {} largest",
+            file_sizes.total, file_sizes.median, file_sizes.max
+        );
+        println!(
+            "Resources per archive: {} fewest, {} median, {} most",
+            resource_counts.min, resource_counts.median, resource_counts.max
         );
     }
+    println!();
 
-    #[test]
-    fn extract_crouton() {
-        let temp = assert_fs::TempDir::new().unwrap();
+    let total_bytes: usize = stats.mime_types.values().map(|stats| stats.bytes).sum();
+    println!(
+        "{:<24} {:>8} {:>12} {:>7}",
+        "MIME type", "Count", "Bytes", "Share"
+    );
+    for (mime_type, stats) in stats.top_mime_types(10) {
+        println!(
+            "{:<24} {:>8} {:>12} {:>6.1}%",
+            mime_type,
+            stats.count,
+            stats.bytes,
+            stats.bytes as f64 * 100.0 / total_bytes.max(1) as f64
+        );
+    }
+}
 
-        let input_file = temp.child("crouton.webarchive");
-        input_file
-            .write_binary(&CROUTON_WEBARCHIVE)
-            .expect("Couldn't write temporary file");
+/// Write one CSV row for each archive in `stats`, in order of path,
+/// with the error for those which could not be read
+fn write_collection_csv(stats: &webarchive::CollectionStats) -> Result<()> {
+    use std::io::Write;
 
-        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+    fn field(value: &str) -> std::borrow::Cow<'_, str> {
+        if value.contains(['"', ',', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\"")).into()
+        } else {
+            value.into()
+        }
+    }
 
-        let assert = cmd.arg("extract").arg(input_file.path()).assert();
+    let mut rows: Vec<[String; 8]> = stats
+        .archives
+        .iter()
+        .map(|entry| {
+            [
+                entry.path.clone(),
+                entry.url.clone(),
+                entry.file_size.to_string(),
+                entry.total_size.to_string(),
+                entry.resource_count.to_string(),
+                entry.frame_count.to_string(),
+                format!("{:.4}", entry.completeness.ratio()),
+                String::new(),
+            ]
+        })
+        .chain(stats.failures.iter().map(|failure| {
+            [
+                failure.path.clone(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                failure.error.clone(),
+            ]
+        }))
+        .collect();
+    rows.sort();
 
-        assert.success().stdout(format!(
-            "Saving main resource...\n\
-            Writing file \"{}/crouton.net/_unnamed_index.shtml\"...\n\
-            Saving subresources...\n\
-            Writing file \"{}/crouton.net/crouton.png\"...\n",
-            temp.path().display(),
-            temp.path().display()
-        ));
+    let mut stdout = std::io::stdout().lock();
+    writeln!(
+        stdout,
+        "path,url,file_size,total_size,resource_count,frame_count,completeness,error"
+    )?;
+    for row in &rows {
+        let row: Vec<_> = row.iter().map(|value| field(value)).collect();
+        writeln!(stdout, "{}", row.join(","))?;
+    }
 
-        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
-        temp.child("crouton.net/_unnamed_index.shtml")
+    Ok(())
+}
+
+fn print_largest_resources(webarchive: &WebArchive, n: usize) {
+    println!("{:>12} {:>7} {:<24} URL", "Bytes", "Share", "MIME type");
+    for resource in largest_resources(webarchive, n) {
+        println!(
+            "{:>12} {:>6.1}% {:<24} {}",
+            resource.size, resource.percentage, resource.mime_type, resource.url
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum InspectFormat {
+    Text,
+    Json,
+    Table,
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum StatsFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// How strictly URLs are compared, as a [`webarchive::UrlNormalization`]
+#[derive(Debug, Clone, Copy, Default, clap::ArgEnum)]
+enum UrlMatching {
+    Exact,
+    #[default]
+    Standard,
+    Loose,
+}
+
+impl From<UrlMatching> for webarchive::UrlNormalization {
+    fn from(matching: UrlMatching) -> Self {
+        match matching {
+            UrlMatching::Exact => webarchive::UrlNormalization::exact(),
+            UrlMatching::Standard => webarchive::UrlNormalization::default(),
+            UrlMatching::Loose => webarchive::UrlNormalization::loose(),
+        }
+    }
+}
+
+/// `path` for the first of several files, or with `-{number}` added
+/// before its extension for the others
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    if number == 1 {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+/// Print how a resource differs, for `diff`
+fn print_change(change: &webarchive::ResourceChange) {
+    use webarchive::{ChangeKind, ContentDiff};
+
+    match (change.kind, &change.old, &change.new) {
+        (ChangeKind::Removed, _, _) => println!("- {:?}", change.url),
+        (ChangeKind::Added, _, _) => println!("+ {:?}", change.url),
+        (ChangeKind::Modified, Some(old), Some(new)) if old.mime_type != new.mime_type => {
+            println!("~ {:?} ({} → {})", change.url, old.mime_type, new.mime_type)
+        }
+        (ChangeKind::Modified, _, _) => println!("~ {:?} (data changed)", change.url),
+    }
+
+    let sizes = || match (&change.old, &change.new) {
+        (Some(old), Some(new)) => format!("{} → {} bytes", old.size, new.size),
+        _ => String::new(),
+    };
+    match &change.content {
+        Some(ContentDiff::Text { hunks }) if hunks.is_empty() => {
+            println!("  same text, differently encoded")
+        }
+        Some(ContentDiff::Text { hunks }) => {
+            for hunk in hunks {
+                print!("{}", hunk);
+            }
+        }
+        Some(ContentDiff::Binary) => println!("  binary data, {}", sizes()),
+        Some(ContentDiff::TooLarge { max_size }) => println!(
+            "  {}, larger than {} bytes, so not compared",
+            sizes(),
+            max_size
+        ),
+        None => {}
+    }
+}
+
+/// Which copy `merge` keeps, as a [`webarchive::MergePolicy`]
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum MergePreference {
+    First,
+    Last,
+    Largest,
+    Newest,
+}
+
+impl From<MergePreference> for webarchive::MergePolicy {
+    fn from(preference: MergePreference) -> Self {
+        match preference {
+            MergePreference::First => webarchive::MergePolicy::First,
+            MergePreference::Last => webarchive::MergePolicy::Last,
+            MergePreference::Largest => webarchive::MergePolicy::Largest,
+            MergePreference::Newest => webarchive::MergePolicy::Newest,
+        }
+    }
+}
+
+/// Why `merge` kept the copy of a resource it did, and whose it was
+fn merge_decision(
+    conflict: &webarchive::MergeConflict,
+    preference: MergePreference,
+    incoming: &Path,
+) -> String {
+    use webarchive::{MergeBasis, MergeSide};
+
+    let kept = match conflict.kept {
+        MergeSide::Existing => "the existing copy".to_string(),
+        MergeSide::Incoming => format!("the copy from {:?}", incoming),
+    };
+    let (kept_copy, other_copy) = match conflict.kept {
+        MergeSide::Existing => (&conflict.existing, &conflict.incoming),
+        MergeSide::Incoming => (&conflict.incoming, &conflict.existing),
+    };
+    let dates = |kept: Option<std::time::SystemTime>, other: Option<std::time::SystemTime>| {
+        format!(
+            "{} against {}",
+            httpdate::fmt_http_date(kept.expect("Dates decided it")),
+            httpdate::fmt_http_date(other.expect("Dates decided it"))
+        )
+    };
+
+    let reason = match conflict.basis {
+        MergeBasis::Date => format!(
+            "with the later Date ({})",
+            dates(kept_copy.date, other_copy.date)
+        ),
+        MergeBasis::LastModified => format!(
+            "with the later Last-Modified ({})",
+            dates(kept_copy.last_modified, other_copy.last_modified)
+        ),
+        MergeBasis::Size => format!(
+            "with more data ({} bytes against {})",
+            kept_copy.size, other_copy.size
+        ),
+        MergeBasis::Order => match preference {
+            MergePreference::First => "as --prefer first".to_string(),
+            MergePreference::Last => "as --prefer last".to_string(),
+            MergePreference::Largest => "as both are the same size".to_string(),
+            MergePreference::Newest => "as neither is newer or larger".to_string(),
+        },
+    };
+
+    format!("kept {}, {}", kept, reason)
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum LintFormat {
+    Text,
+    Json,
+    Github,
+}
+
+/// Options for leaving out resources by size, or by a filter expression,
+/// shared between subcommands
+#[derive(Debug, clap::Args)]
+struct SizeFilter {
+    #[clap(long, parse(try_from_str = webarchive::parse_size))]
+    /// Leave out resources smaller than this, such as `512`, `10k` or `1.5M`
+    min_size: Option<usize>,
+
+    #[clap(long, parse(try_from_str = webarchive::parse_size))]
+    /// Leave out resources larger than this, such as `512`, `10k` or `1.5M`
+    max_size: Option<usize>,
+
+    #[clap(long, value_name = "EXPRESSION", parse(try_from_str = webarchive::FilterExpression::parse))]
+    /// Only use resources for which this holds, such as
+    /// `mime~^image/ and size>100k and url!~cdn\.example`. Tests url, mime,
+    /// size, encoding, frame and has_response, with =, !=, ~ (a regular
+    /// expression), !~, and <, <=, > and >= for size, joined by and, or, not
+    /// and parentheses
+    filter: Option<webarchive::FilterExpression>,
+}
+
+impl SizeFilter {
+    fn to_filter(&self) -> webarchive::ResourceFilter {
+        webarchive::ResourceFilter {
+            min_size: self.min_size,
+            max_size: self.max_size,
+            expression: self.filter.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Options for choosing resources by URL, shared between subcommands
+#[derive(Debug, clap::Args)]
+struct UrlFilter {
+    #[clap(long = "url-glob", parse(from_str = webarchive::UrlMatcher::glob))]
+    /// Only use resources whose URLs match this pattern, where `*`
+    /// matches anything, including `/`, and `?` matches any one character
+    url_globs: Vec<webarchive::UrlMatcher>,
+
+    #[clap(long = "url-regex", parse(try_from_str = webarchive::UrlMatcher::regex))]
+    /// Only use resources whose URLs contain a match for this regular expression
+    url_regexes: Vec<webarchive::UrlMatcher>,
+}
+
+impl UrlFilter {
+    /// A matcher for any of the patterns, or `None` if none were given
+    fn to_matcher(&self) -> Option<webarchive::UrlMatcher> {
+        if self.url_globs.is_empty() && self.url_regexes.is_empty() {
+            return None;
+        }
+
+        Some(webarchive::UrlMatcher::any(
+            self.url_globs.iter().chain(&self.url_regexes).cloned(),
+        ))
+    }
+}
+
+/// Options for fetching resources over HTTP, shared between subcommands
+#[cfg(feature = "fetch")]
+#[derive(Debug, clap::Args)]
+struct FetchArgs {
+    #[clap(long, default_value = "6")]
+    /// The most requests to make at once to each server, and, for `create
+    /// --url-list`, the most pages to capture at once
+    concurrency: usize,
+
+    #[clap(long, default_value = "30")]
+    /// Seconds to wait for each request
+    timeout: u64,
+
+    #[clap(long, default_value = "2")]
+    /// How many times to retry requests which time out, or get a 429 or 5xx status
+    retries: u32,
+
+    #[clap(long)]
+    /// Seconds to allow for all requests, including retries
+    deadline: Option<u64>,
+}
+
+#[cfg(feature = "fetch")]
+impl FetchArgs {
+    fn to_options(&self) -> webarchive::FetchOptions {
+        webarchive::FetchOptions {
+            concurrency_per_origin: self.concurrency,
+            timeout: std::time::Duration::from_secs(self.timeout),
+            deadline: self.deadline.map(std::time::Duration::from_secs),
+            retry: webarchive::RetryPolicy {
+                retries: self.retries,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// The `http` or `https` URL given in place of an input file, if it is one
+fn input_url(input: &Path) -> Option<&str> {
+    let input = input.to_str()?;
+    let scheme = input.split_once("://")?.0;
+    (scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")).then_some(input)
+}
+
+/// Downloads larger than this are kept in a temporary file, rather than
+/// in memory, until they have finished
+#[cfg(feature = "fetch")]
+const DOWNLOAD_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Download the archive at `url`, following redirects and using any
+/// username and password it has, showing progress on a terminal
+#[cfg(feature = "fetch")]
+fn download(url: &str) -> Result<Vec<u8>> {
+    use std::io::{IsTerminal, Read, Seek, Write};
+
+    let mut response = reqwest::blocking::Client::builder()
+        .user_agent(webarchive::FetchOptions::default().user_agent)
+        .timeout(None)
+        .build()?
+        .get(url)
+        .send()?;
+
+    let status = response.status();
+    anyhow::ensure!(
+        status == reqwest::StatusCode::OK,
+        "the server responded with {}",
+        status
+    );
+
+    let total = response.content_length();
+    let mut download = tempfile::SpooledTempFile::new(DOWNLOAD_MEMORY_LIMIT);
+    let show_progress = std::io::stderr().is_terminal();
+    let mut done = 0;
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        download.write_all(&buffer[..read])?;
+        done += read as u64;
+
+        if show_progress {
+            match total {
+                Some(total) => eprint!("\rDownloaded {} of {} bytes", done, total),
+                None => eprint!("\rDownloaded {} bytes", done),
+            }
+        }
+    }
+    if show_progress {
+        eprintln!();
+    }
+
+    let mut data = Vec::with_capacity(done as usize);
+    download.rewind()?;
+    download.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Print the URLs a fetch could not get, as warnings
+#[cfg(feature = "fetch")]
+fn warn_fetch_failures(report: &webarchive::FetchReport) {
+    for failure in &report.failed {
+        eprintln!(
+            "warning: could not fetch {:?}: {}",
+            failure.url, failure.reason
+        );
+    }
+}
+
+/// The URLs in a `create --url-list` file, one per line, leaving out
+/// blank lines and comments starting with `#`
+#[cfg(feature = "fetch")]
+fn parse_url_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// What `create --report` writes
+#[cfg(feature = "fetch")]
+#[derive(serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct BatchReport<'a> {
+    /// Each page, in the order they were listed
+    pages: &'a [CapturedPage],
+}
+
+/// Whether `create --url-list` captured one page
+#[cfg(feature = "fetch")]
+#[derive(Debug, serde::Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct CapturedPage {
+    /// The URL listed
+    url: String,
+    /// Where its webarchive was written, if it was captured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    /// How many subresources were fetched
+    subresources: usize,
+    /// How many subresources could not be fetched
+    failed_subresources: usize,
+    /// Why it could not be captured, if it wasn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// How `create` captures each page
+#[cfg(feature = "fetch")]
+struct Capturer {
+    fetch: webarchive::FetchOptions,
+    /// Set to render pages in a browser, rather than fetch them
+    #[cfg(feature = "cdp")]
+    render: Option<webarchive::RenderOptions>,
+}
+
+#[cfg(feature = "fetch")]
+impl Capturer {
+    async fn capture(
+        &self,
+        url: &str,
+    ) -> webarchive::Result<(WebArchive, webarchive::FetchReport)> {
+        #[cfg(feature = "cdp")]
+        if let Some(render) = &self.render {
+            return WebArchive::render(url, render).await;
+        }
+        WebArchive::fetch(url, &self.fetch).await
+    }
+}
+
+/// Archive each of `urls` into `folder`, as `create` does, capturing
+/// up to `concurrency` pages at once, and carrying on past any which
+/// fail
+#[cfg(feature = "fetch")]
+async fn create_batch(
+    files: &ArchiveFiles,
+    urls: &[String],
+    folder: &Path,
+    capturer: &Capturer,
+    concurrency: usize,
+) -> Vec<CapturedPage> {
+    use futures::StreamExt;
+
+    // pages finish in any order, but are named in the order listed,
+    // so the same list always gives the same names
+    let mut fetched = futures::stream::iter(urls)
+        .map(|url| async move { (url, capturer.capture(url).await) })
+        .buffered(concurrency.max(1));
+
+    let mut taken = std::collections::HashSet::new();
+    let mut pages = Vec::with_capacity(urls.len());
+    while let Some((url, result)) = fetched.next().await {
+        let mut page = CapturedPage {
+            url: url.clone(),
+            path: None,
+            subresources: 0,
+            failed_subresources: 0,
+            error: None,
+        };
+
+        match result {
+            Ok((webarchive, report)) => {
+                warn_fetch_failures(&report);
+                page.subresources = report.fetched.len();
+                page.failed_subresources = report.failed.len();
+
+                let path = folder.join(batch_file_name(&webarchive, url, &mut taken));
+                println!("Writing webarchive of {:?} to {:?}...", url, path);
+                match files.write(&path, &webarchive, webarchive::ArchiveFormat::Binary) {
+                    Ok(()) => page.path = Some(path),
+                    Err(error) => page.error = Some(format!("{:#}", error)),
+                }
+            }
+            Err(error) => page.error = Some(error.to_string()),
+        }
+
+        if let Some(error) = &page.error {
+            eprintln!("warning: could not capture {:?}: {}", url, error);
+        }
+        pages.push(page);
+    }
+
+    pages
+}
+
+/// The name of the file `create --url-list` writes the webarchive of
+/// `url` to: its title, or else its host and path, keeping only
+/// letters, digits, `.` and `_`, with a `-` between each run of them,
+/// and numbered if an earlier page in `taken`, ignoring case, already
+/// has it
+#[cfg(feature = "fetch")]
+fn batch_file_name(
+    webarchive: &WebArchive,
+    url: &str,
+    taken: &mut std::collections::HashSet<String>,
+) -> PathBuf {
+    const MAX_STEM_LENGTH: usize = 100;
+
+    let stem = webarchive
+        .title()
+        .ok()
+        .flatten()
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| {
+            let url = url::Url::parse(url).ok()?;
+            Some(format!("{}{}", url.host_str()?, url.path()))
+        })
+        .unwrap_or_else(|| url.to_string());
+
+    // runs of anything else, such as ` - ` or `://`, become one `-`
+    let name: String = stem
+        .split(|c: char| !(c.is_alphanumeric() || c == '.' || c == '_'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .take(MAX_STEM_LENGTH)
+        .collect();
+    let name = name.trim_matches(['-', '.']);
+    let path = PathBuf::from(format!(
+        "{}.webarchive",
+        if name.is_empty() { "page" } else { name }
+    ));
+
+    (1..)
+        .map(|number| numbered_path(&path, number))
+        .find(|path| taken.insert(path.to_string_lossy().to_lowercase()))
+        .expect("Some numbered path is always free")
+}
+
+/// How wide `inspect --format table` may be: the number of columns
+/// `COLUMNS` gives, if it is set to one, otherwise the width of the
+/// terminal stdout is, or `None` when stdout isn't a terminal, so that
+/// piped tables keep whole URLs
+fn table_width(columns: Option<&str>) -> Option<usize> {
+    if let Some(columns) = columns.and_then(|columns| columns.trim().parse().ok()) {
+        return Some(columns);
+    }
+
+    let (terminal_size::Width(width), _) = terminal_size::terminal_size_of(std::io::stdout())?;
+    Some(usize::from(width))
+}
+
+/// Whether to color output: only on a terminal, and unless `--no-color`
+/// is given or `NO_COLOR` is set to anything
+fn use_color(no_color: bool) -> bool {
+    use std::io::IsTerminal;
+
+    !no_color
+        && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stdout().is_terminal()
+}
+
+/// Print a table of which pages `create --url-list` captured
+#[cfg(feature = "fetch")]
+fn print_batch_table(pages: &[CapturedPage]) {
+    let width = pages
+        .iter()
+        .map(|page| page.url.chars().count())
+        .chain(["URL".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!("{:<6}  {:<width$}  Result", "Status", "URL", width = width);
+    for page in pages {
+        let (status, result) = match (&page.path, &page.error) {
+            (_, Some(error)) => ("failed", error.clone()),
+            (Some(path), None) if page.failed_subresources > 0 => (
+                "ok",
+                format!(
+                    "{}, {} subresource(s), {} missing",
+                    path.display(),
+                    page.subresources,
+                    page.failed_subresources
+                ),
+            ),
+            (Some(path), None) => (
+                "ok",
+                format!("{}, {} subresource(s)", path.display(), page.subresources),
+            ),
+            (None, None) => unreachable!("pages are either written or failed"),
+        };
+        println!(
+            "{:<6}  {:<width$}  {}",
+            status,
+            page.url,
+            result,
+            width = width
+        );
+    }
+
+    let captured = pages.iter().filter(|page| page.error.is_none()).count();
+    println!("Captured {} of {} page(s)", captured, pages.len());
+}
+
+/// Utility for inspecting or extracting a webarchive file
+#[derive(Debug, Parser)]
+#[clap(version)]
+struct Cli {
+    #[cfg(feature = "encrypt")]
+    #[clap(long, global = true, parse(from_os_str), value_name = "FILE")]
+    /// Decrypt input files encrypted with age using the identities in this file
+    identity: Option<PathBuf>,
+
+    #[cfg(feature = "encrypt")]
+    #[clap(long, global = true, value_name = "RECIPIENT")]
+    /// Encrypt output files with age to this recipient, such as `age1…`;
+    /// may be given more than once
+    encrypt_to: Vec<age::x25519::Recipient>,
+
+    #[clap(long, global = true)]
+    /// Read input files with mistakes Safari tolerates, such as resource
+    /// data stored as a string, fixing them with a warning
+    lenient: bool,
+
+    #[clap(long, global = true)]
+    /// Refuse to read input files with anything Safari would not have
+    /// written, such as relative URLs or nonstandard keys
+    strict_apple: bool,
+
+    #[clap(subcommand)]
+    command: Args,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Args {
+    /// List the contents of a webarchive file
+    Inspect {
+        #[clap(parse(from_os_str))]
+        /// File or folder to inspect, or, with the `fetch` feature, an http(s)
+        /// URL to download it from
+        input: PathBuf,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format; `json` prints a summary of the whole archive, and
+        /// `table` lines up each resource's URL, MIME type, size, encoding
+        /// and response status in columns
+        format: InspectFormat,
+
+        #[clap(long)]
+        /// Don't color `--format table`, which is otherwise colored on a
+        /// terminal unless NO_COLOR is set
+        no_color: bool,
+
+        #[clap(
+            long,
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "sha256",
+            possible_values = ["sha256", "sha384", "sha512", "sha1", "md5"]
+        )]
+        /// Print the digest of each resource, as in --checksums=md5;
+        /// defaults to sha256
+        checksums: Option<webarchive::DigestAlgorithm>,
+
+        #[clap(flatten)]
+        sizes: SizeFilter,
+
+        #[clap(long, value_name = "N")]
+        /// Also list the N largest resources, across all frames
+        top: Option<usize>,
+
+        #[cfg(feature = "image")]
+        #[clap(short, long)]
+        /// Also show the dimensions of each image
+        verbose: bool,
+    },
+
+    /// Print how much of a webarchive file each MIME type takes up
+    Stats {
+        #[clap(parse(from_os_str))]
+        /// File to summarise, or folder of `.webarchive` and
+        /// `.webarchive.gz` files to summarise together
+        input: PathBuf,
+
+        #[clap(short, long)]
+        /// Include archives in folders inside the folder given
+        recursive: bool,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format. `csv` lists one row for each archive
+        format: StatsFormat,
+
+        #[clap(long, value_name = "N")]
+        /// Also list the N largest resources, across all frames, of a file
+        top: Option<usize>,
+    },
+
+    /// Print the URL of each resource in webarchive files, one per line
+    Urls {
+        #[clap(parse(from_os_str), required = true)]
+        /// Files to list
+        inputs: Vec<PathBuf>,
+
+        #[clap(long, conflicts_with = "frames-only")]
+        /// Only list subresources, not the main resources of frames
+        subresources_only: bool,
+
+        #[clap(long)]
+        /// Only list the main resources of frames
+        frames_only: bool,
+
+        #[clap(long)]
+        /// Only list resources with this MIME type, such as `image/png` or `image/*`
+        mime: Option<String>,
+
+        #[clap(short = '0', long)]
+        /// End each URL with a NUL byte rather than a newline, for `xargs -0`
+        null: bool,
+
+        #[clap(long)]
+        /// Don't prefix URLs with their file name when listing several files
+        no_filename: bool,
+
+        #[clap(flatten)]
+        sizes: SizeFilter,
+
+        #[clap(flatten)]
+        url_filter: UrlFilter,
+    },
+
+    /// List the pages linked to from the documents in webarchive files, one per line
+    ///
+    /// Links are internal when they have the same origin (scheme, host and port) as
+    /// the main resource of the archive they are in, and external otherwise.
+    Links {
+        #[clap(parse(from_os_str), required = true)]
+        /// Files to list
+        inputs: Vec<PathBuf>,
+
+        #[clap(long, conflicts_with = "internal-only")]
+        /// Only list links to other origins than the main resource's
+        external_only: bool,
+
+        #[clap(long)]
+        /// Only list links to the main resource's origin
+        internal_only: bool,
+
+        #[clap(long)]
+        /// Follow each URL with a tab and the link's text
+        with_text: bool,
+
+        #[clap(long)]
+        /// List each URL once, in the order first found, after how many times it was linked to
+        count: bool,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format
+        format: Format,
+    },
+
+    /// Write the data of one resource in a webarchive file to standard output
+    Cat {
+        #[clap(parse(from_os_str))]
+        /// File to read from
+        input: PathBuf,
+
+        /// URL of the resource to print
+        url: String,
+    },
+
+    /// Print the start of one resource in a webarchive file, as text if
+    /// it is text, or else as a hex dump
+    Head {
+        #[clap(parse(from_os_str))]
+        /// File to read from
+        input: PathBuf,
+
+        /// URL of the resource to print
+        url: String,
+
+        #[clap(long, default_value = "256")]
+        /// How many bytes to print; for text, of the text as printed
+        bytes: usize,
+
+        #[clap(long, conflicts_with_all = &["bytes", "hex"])]
+        /// How many lines of a text resource to print, rather than bytes
+        lines: Option<usize>,
+
+        #[clap(long)]
+        /// Print a hex dump, even of a text resource
+        hex: bool,
+    },
+
+    /// Extract the contents of a webarchive file to individual files
+    Extract {
+        #[clap(parse(from_os_str))]
+        /// File or folder to convert, or, with the `fetch` feature, an http(s)
+        /// URL to download it from
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File or folder name to output to.
+        ///
+        /// If omitted, files will be written to
+        /// the folder containing the input file,
+        /// or the current folder for a URL.
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Change links between extracted HTML and CSS files
+        /// to relative paths, so they work offline
+        convert_links: bool,
+
+        #[clap(long)]
+        /// Set the modification time of each file from the `Last-Modified`
+        /// or `Date` header of its archived response, where it has one
+        preserve_mtime: bool,
+
+        #[clap(long)]
+        /// Name files after the `filename` in the `Content-Disposition`
+        /// header of their archived response, where they have one
+        use_response_filenames: bool,
+
+        #[clap(long)]
+        /// Write each frame's resources into a folder of its own, such
+        /// as `main` or `frame-0-banner.html`
+        by_frame: bool,
+
+        #[clap(long)]
+        /// Name the main resource `index.html`, as well as frames' main
+        /// resources whose URLs end in `/`, if they are HTML
+        main_as_index: bool,
+
+        #[clap(long, arg_enum, default_value = "default")]
+        /// How to name files: after their URL, in a folder for its host, or
+        /// as `wget --mirror` does, with `index.html` for URLs ending in `/`
+        /// and `%3F` before queries
+        layout: Layout,
+
+        #[clap(long, arg_enum, default_value = "copy")]
+        /// How to save files with the same contents as one already saved:
+        /// written again, or as a hard or symbolic link to the first, falling
+        /// back to writing them again where links can't be made
+        dedupe_files: DedupeFiles,
+
+        #[clap(long)]
+        /// Write each resource's archived HTTP response next to it, as
+        /// `<file>.response.plist`
+        with_responses: bool,
+
+        #[clap(long, requires = "with-responses")]
+        /// Write archived responses which can be parsed as
+        /// `<file>.response.json` instead
+        decode_responses: bool,
+
+        #[clap(long)]
+        /// Don't write `webarchive-manifest.json`, which records the URL,
+        /// type, size and digest of each file written
+        no_manifest: bool,
+
+        #[clap(long)]
+        /// Write text resources as UTF-8, decoded from their declared or
+        /// detected encoding, changing HTML `<meta charset>` declarations
+        /// to match. Resources which are malformed in their encoding are
+        /// written unchanged, with a warning
+        transcode_to_utf8: bool,
+
+        #[clap(long, requires = "transcode-to-utf8")]
+        /// With --transcode-to-utf8, transcode malformed resources too,
+        /// replacing what can't be decoded with U+FFFD
+        lossy: bool,
+
+        #[clap(flatten)]
+        sizes: SizeFilter,
+
+        #[clap(flatten)]
+        url_filter: UrlFilter,
+    },
+
+    /// Check that the files extracted from a webarchive file match its resources,
+    /// listing any which are missing, mismatched or extra
+    VerifyExtract {
+        #[clap(parse(from_os_str))]
+        /// File which was extracted
+        archive: PathBuf,
+
+        #[clap(parse(from_os_str))]
+        /// Folder it was extracted to
+        folder: PathBuf,
+
+        #[clap(long)]
+        /// The files were extracted with --convert-links
+        convert_links: bool,
+
+        #[clap(long)]
+        /// The files were extracted with --use-response-filenames
+        use_response_filenames: bool,
+
+        #[clap(long)]
+        /// The files were extracted with --by-frame
+        by_frame: bool,
+
+        #[clap(long)]
+        /// The files were extracted with --main-as-index
+        main_as_index: bool,
+
+        #[clap(long, arg_enum, default_value = "default")]
+        /// The --layout the files were extracted with
+        layout: Layout,
+
+        #[clap(long)]
+        /// The files were extracted with --transcode-to-utf8
+        transcode_to_utf8: bool,
+
+        #[clap(long, requires = "transcode-to-utf8")]
+        /// The files were extracted with --lossy
+        lossy: bool,
+
+        #[clap(long)]
+        /// The files were extracted with --with-responses, so response
+        /// files are expected
+        with_responses: bool,
+
+        #[clap(flatten)]
+        sizes: SizeFilter,
+
+        #[clap(flatten)]
+        url_filter: UrlFilter,
+    },
+
+    /// Rebuild a webarchive file from a folder it was extracted to, using
+    /// the `webarchive-manifest.json` extract wrote, so that edited files
+    /// keep their original URLs
+    Repack {
+        #[clap(parse(from_os_str))]
+        /// Folder the webarchive file was extracted to
+        folder: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the webarchive to
+        output: PathBuf,
+
+        #[clap(long, requires = "base-url")]
+        /// Add files which are not in the manifest as subresources
+        include_new: bool,
+
+        #[clap(long)]
+        /// URL which the paths of files added with --include-new are
+        /// relative to
+        base_url: Option<String>,
+    },
+
+    /// Extract a webarchive file to a temporary folder and open it in a browser
+    Open {
+        #[clap(parse(from_os_str))]
+        /// File to open
+        input: PathBuf,
+
+        #[clap(long)]
+        /// Leave the temporary folder in place, instead of removing it
+        /// once Enter is pressed
+        keep: bool,
+
+        #[clap(long)]
+        /// Command to open the page with, instead of the default browser
+        browser: Option<String>,
+
+        #[clap(long)]
+        /// Open the webarchive file itself with Safari, without extracting it
+        native: bool,
+    },
+
+    /// Print the archived HTTP response of one resource in a webarchive file
+    Headers {
+        #[clap(parse(from_os_str))]
+        /// File to look in
+        archive: PathBuf,
+
+        /// URL of the resource
+        url: String,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format
+        format: Format,
+
+        #[clap(long, conflicts_with = "format")]
+        /// Print the structure of the archived response's property list,
+        /// as it is stored, for responses which can not be understood
+        raw: bool,
+    },
+
+    /// Print a Graphviz DOT graph of which resources reference which others
+    Graph {
+        #[clap(parse(from_os_str))]
+        /// File to graph
+        input: PathBuf,
+    },
+
+    /// Print a sitemap.xml listing the HTML documents in webarchive files, for search indexers
+    Sitemap {
+        #[clap(parse(from_os_str), required = true)]
+        /// Files to list
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Make an HTML page listing the webarchive files in a folder, with their titles and thumbnails
+    Index {
+        #[clap(parse(from_os_str))]
+        /// Folder of webarchive files to list
+        dir: PathBuf,
+
+        #[clap(short, long, parse(from_os_str), default_value = "index.html")]
+        /// File name to write the page to
+        output: PathBuf,
+
+        #[clap(long)]
+        /// Write thumbnails to a `thumbnails` folder beside the page, rather than
+        /// embedding them in it
+        extract_thumbnails: bool,
+    },
+
+    /// Check a webarchive file for problems, and verify its checksum manifest if it has one
+    Validate {
+        #[clap(parse(from_os_str))]
+        /// File to check
+        input: PathBuf,
+    },
+
+    /// Check webarchive files for every known problem, grouped under stable rule IDs
+    ///
+    /// Errors are denied, and warnings only reported, unless --allow or --deny says
+    /// otherwise. Exits with status 1 if any finding is denied.
+    Lint {
+        #[clap(parse(from_os_str), required = true)]
+        /// Files to check
+        inputs: Vec<PathBuf>,
+
+        #[clap(long, value_name = "RULE", parse(try_from_str = parse_lint_rule))]
+        /// Leave out findings of a rule, by ID or name, as in WA001 or missing-subresource
+        allow: Vec<&'static webarchive::LintRule>,
+
+        #[clap(long, value_name = "RULE", parse(try_from_str = parse_lint_rule))]
+        /// Fail on findings of a rule, by ID or name, as in WA001 or missing-subresource
+        deny: Vec<&'static webarchive::LintRule>,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format, where `github` prints workflow commands which
+        /// annotate a GitHub Actions run
+        format: LintFormat,
+    },
+
+    /// Compare the resources of two webarchive files
+    Diff {
+        #[clap(parse(from_os_str))]
+        /// File to compare from
+        old: PathBuf,
+
+        #[clap(parse(from_os_str))]
+        /// File to compare to
+        new: PathBuf,
+
+        #[clap(long)]
+        /// Normalise both archives first, so URLs written differently compare equal
+        canonical: bool,
+
+        #[clap(long, arg_enum, requires = "canonical")]
+        /// How URLs are normalised with --canonical; `loose` also removes
+        /// tracking parameters such as `utm_source`. Defaults to `standard`
+        urls: Option<UrlMatching>,
+
+        #[cfg(feature = "diff")]
+        #[clap(long)]
+        /// Show how the text of each changed text resource changed
+        content: bool,
+
+        #[cfg(feature = "diff")]
+        #[clap(
+            long,
+            requires = "content",
+            default_value = "1M",
+            parse(try_from_str = webarchive::parse_size)
+        )]
+        /// Largest resource whose text is compared with --content, such as `512k`
+        max_content_size: usize,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Format to print the differences in
+        format: Format,
+    },
+
+    /// Combine webarchive files, such as two captures of the same page
+    Merge {
+        #[clap(parse(from_os_str), required = true, min_values = 2)]
+        /// Files to merge, in order; the first's main resource is kept
+        inputs: Vec<PathBuf>,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the merged webarchive to
+        output: PathBuf,
+
+        #[clap(long, arg_enum, default_value = "last")]
+        /// Which copy to keep when archives store a resource differently;
+        /// `newest` compares the dates of their archived responses
+        prefer: MergePreference,
+    },
+
+    /// Add a file to a webarchive file as a subresource
+    Add {
+        #[clap(parse(from_os_str))]
+        /// File to add to
+        archive: PathBuf,
+
+        #[clap(long)]
+        /// URL to store the resource at
+        url: String,
+
+        #[clap(long, parse(from_os_str))]
+        /// File to read the resource's data from
+        file: PathBuf,
+
+        #[clap(long)]
+        /// MIME type of the resource, if not guessed from the file name
+        mime: Option<String>,
+
+        #[clap(long)]
+        /// Text encoding of the resource; text which is valid UTF-8 defaults to UTF-8
+        encoding: Option<String>,
+
+        #[clap(long)]
+        /// Replace a resource which already has the URL
+        replace: bool,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the result to, instead of changing the archive in place
+        output: Option<PathBuf>,
+    },
+
+    /// Make another resource the main resource of a webarchive file
+    SetMain {
+        #[clap(parse(from_os_str))]
+        /// File to change
+        archive: PathBuf,
+
+        #[clap(long)]
+        /// URL of the subresource or subframe page to make the main resource
+        url: String,
+
+        #[clap(long)]
+        /// Drop the previous main resource, instead of keeping it as a subresource
+        discard_old: bool,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the result to, instead of changing the archive in place
+        output: Option<PathBuf>,
+    },
+
+    /// Change the URL a resource in a webarchive file is stored at
+    SetUrl {
+        #[clap(parse(from_os_str))]
+        /// File to change
+        archive: PathBuf,
+
+        #[clap(long)]
+        /// Current URL of the resource
+        from: String,
+
+        #[clap(long)]
+        /// URL to store the resource at instead
+        to: String,
+
+        #[clap(long)]
+        /// Also change references to the old URL within HTML and CSS resources
+        rewrite_references: bool,
+
+        #[clap(long)]
+        /// If a resource is already stored at the new URL, remove this one in its favour
+        merge: bool,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the result to, instead of changing the archive in place
+        output: Option<PathBuf>,
+    },
+
+    /// Remove subresources from a webarchive file
+    Remove {
+        #[clap(parse(from_os_str))]
+        /// File to remove from
+        archive: PathBuf,
+
+        #[clap(long = "url")]
+        /// Exact URL of subresources to remove
+        urls: Vec<String>,
+
+        #[clap(flatten)]
+        url_filter: UrlFilter,
+
+        #[clap(long, parse(try_from_str = parse_status_range))]
+        /// Remove subresources whose archived HTTP status is in this
+        /// range, such as `404` or `400..600`
+        status: Option<(Bound<u16>, Bound<u16>)>,
+
+        #[clap(long, value_name = "EXPRESSION", parse(try_from_str = webarchive::FilterExpression::parse))]
+        /// Remove subresources for which this filter expression holds, as
+        /// `inspect --filter` takes
+        filter: Option<webarchive::FilterExpression>,
+
+        #[clap(long)]
+        /// List what would be removed, without writing anything
+        dry_run: bool,
+
+        #[clap(long)]
+        /// Succeed even if nothing matched
+        ignore_missing: bool,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the result to, instead of changing the archive in place
+        output: Option<PathBuf>,
+    },
+
+    /// Change the URLs of resources in a webarchive file, and the references to them
+    RewriteUrls {
+        #[clap(parse(from_os_str))]
+        /// File to rewrite
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str), required_unless_present = "dry-run")]
+        /// File name to write the rewritten webarchive to
+        output: Option<PathBuf>,
+
+        #[clap(long = "map", parse(try_from_str = parse_mapping))]
+        /// Replace the URL prefix OLD with NEW, as in OLD=NEW.
+        ///
+        /// When several prefixes match, the longest is used.
+        mappings: Vec<(String, String)>,
+
+        #[clap(long = "regex", parse(try_from_str = parse_substitution))]
+        /// Replace matches of a regular expression, as in s#PATTERN#REPLACEMENT#,
+        /// after any --map has been applied
+        substitutions: Vec<(regex::Regex, String)>,
+
+        #[clap(long)]
+        /// List the URLs which would change, without writing anything
+        dry_run: bool,
+    },
+
+    /// Replace text within the resources of a webarchive file, like `sed`
+    Sed {
+        #[clap(parse(try_from_str = parse_sed_expression))]
+        /// Substitution to make, as in s/PATTERN/REPLACEMENT/FLAGS.
+        ///
+        /// Any punctuation may be the delimiter. Without the `g` flag,
+        /// only the first match on each line is replaced; the `i` flag
+        /// ignores case. The replacement may refer to capture groups
+        /// as `$1` or `${name}`.
+        expression: SedExpression,
+
+        #[clap(parse(from_os_str))]
+        /// File to change
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str), required_unless_present = "dry-run")]
+        /// File name to write the changed webarchive to
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Only change resources with this MIME type, such as `text/html` or `text/*`.
+        ///
+        /// Binary resources cannot be changed, so selecting any is an error.
+        mime: Option<String>,
+
+        #[clap(flatten)]
+        url_filter: UrlFilter,
+
+        #[clap(long, value_name = "EXPRESSION", parse(try_from_str = webarchive::FilterExpression::parse))]
+        /// Only change resources for which this filter expression holds, as
+        /// `inspect --filter` takes
+        filter: Option<webarchive::FilterExpression>,
+
+        #[clap(long)]
+        /// Also change HTML tags which refer to other resources, such as `<img src>`,
+        /// which may leave references to resources the archive does not have
+        include_references: bool,
+
+        #[clap(long)]
+        /// List how many replacements would be made, without writing anything
+        dry_run: bool,
+    },
+
+    /// Correct the MIME types of resources whose data looks like another type
+    FixMime {
+        #[clap(parse(from_os_str))]
+        /// File to fix
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str), required_unless_present = "dry-run")]
+        /// File name to write the fixed webarchive to
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Also use types guessed from text and markup, not just exact signatures
+        aggressive: bool,
+
+        #[clap(long)]
+        /// List the types which would change, without writing anything
+        dry_run: bool,
+    },
+
+    /// Remove duplicate copies of subresources from a webarchive file
+    Dedupe {
+        #[clap(parse(from_os_str))]
+        /// File to remove duplicates from
+        input: PathBuf,
+
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            required_unless_present = "report-only"
+        )]
+        /// File name to write the deduplicated webarchive to
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        /// List the duplicates without writing anything
+        report_only: bool,
+
+        #[clap(long)]
+        /// Also remove copies stored under different URLs,
+        /// pointing references to them at the copy which is kept
+        across_urls: bool,
+    },
+
+    /// Shrink a webarchive file by removing whatever it does not need to display
+    Optimize {
+        #[clap(parse(from_os_str))]
+        /// File to optimize
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the optimized webarchive to
+        output: PathBuf,
+
+        #[clap(long)]
+        /// Keep archived HTTP responses
+        keep_responses: bool,
+
+        #[clap(long)]
+        /// Keep duplicate copies of subresources
+        no_dedupe: bool,
+
+        #[clap(long, conflicts_with = "no-dedupe")]
+        /// Also remove copies stored under different URLs
+        across_urls: bool,
+
+        #[clap(long)]
+        /// Keep subresources nothing refers to, which may be used by scripts
+        keep_orphans: bool,
+
+        #[clap(long)]
+        /// Keep small subresources as they are, rather than inlining them
+        no_inline: bool,
+
+        #[clap(long, default_value_t = webarchive::DEFAULT_INLINE_MAX_SIZE)]
+        /// Largest subresource, in bytes, to inline as a `data:` URL
+        inline_max_size: usize,
+    },
+
+    /// Remove scripts and other active content from a webarchive file
+    ///
+    /// With no rules given, all of them are applied; otherwise only those given are.
+    Sanitize {
+        #[clap(parse(from_os_str))]
+        /// File to sanitize
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the sanitized webarchive to
+        output: PathBuf,
+
+        #[clap(long)]
+        /// Remove `on…` event handler attributes
+        inline_event_handlers: bool,
+
+        #[clap(long)]
+        /// Remove attributes holding `javascript:` URLs
+        javascript_urls: bool,
+
+        #[clap(long)]
+        /// Remove `<script>` elements
+        script_elements: bool,
+
+        #[clap(long)]
+        /// Remove subresources which are scripts
+        external_script_subresources: bool,
+
+        #[clap(long)]
+        /// Remove `<meta http-equiv="refresh">` elements
+        meta_refresh: bool,
+    },
+
+    /// Save a subframe archive of a webarchive file as its own webarchive file,
+    /// or split a webarchive file into parts no larger than a given size
+    Split {
+        #[clap(parse(from_os_str))]
+        /// File to take the frame from, or to split
+        input: PathBuf,
+
+        #[clap(short, long, required_unless_present = "max-size")]
+        /// Index of the frame within the main document, the frame's name,
+        /// or the URL of its main document
+        frame: Option<FrameSelector>,
+
+        #[clap(
+            long,
+            conflicts_with = "frame",
+            parse(try_from_str = webarchive::parse_size)
+        )]
+        /// Split the webarchive into parts no larger than this, such as `24M`,
+        /// which `merge` puts back together
+        max_size: Option<usize>,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the frame's webarchive to, or the first part; later
+        /// parts are numbered, as in `page-2.webarchive`
+        output: PathBuf,
+
+        #[clap(long, parse(from_os_str), requires = "frame")]
+        /// Also write the input webarchive, without the frame, to this file name
+        remaining: Option<PathBuf>,
+    },
+
+    /// Rebuild a truncated or corrupt webarchive file from whatever resources survived.
+    ///
+    /// Exits with status 1 if some resources were lost, or 2 if nothing could be recovered.
+    Repair {
+        #[clap(parse(from_os_str))]
+        /// File to recover resources from
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the repaired webarchive to
+        output: PathBuf,
+
+        #[clap(long)]
+        /// URL of the recovered resource to make the main resource, instead of
+        /// the original main resource, or the first HTML document recovered
+        main: Option<String>,
+    },
+
+    /// Compare the resources in a webarchive file with their live versions on the web
+    #[cfg(feature = "fetch")]
+    VerifyLive {
+        #[clap(parse(from_os_str))]
+        /// File to check
+        input: PathBuf,
+
+        #[clap(long, arg_enum, default_value = "text")]
+        /// Output format
+        format: Format,
+
+        #[clap(flatten)]
+        fetch: FetchArgs,
+    },
+
+    /// Make a webarchive file of a web page, or of each of a list of pages, fetching it
+    /// and everything it needs to display
+    #[cfg(feature = "fetch")]
+    Create {
+        #[clap(required_unless_present = "url-list")]
+        /// URL of the page to archive
+        url: Option<String>,
+
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            required_unless_present = "url-list",
+            conflicts_with = "url-list"
+        )]
+        /// File name to write the webarchive to
+        output: Option<PathBuf>,
+
+        #[clap(
+            long,
+            parse(from_os_str),
+            value_name = "FILE",
+            conflicts_with = "url",
+            requires = "out-dir"
+        )]
+        /// Archive each URL in this file, one per line, skipping blank lines and
+        /// those starting with `#`
+        url_list: Option<PathBuf>,
+
+        #[clap(long, parse(from_os_str), requires = "url-list")]
+        /// Folder to write the webarchives of --url-list into, named after each
+        /// page's title, or else its host and path
+        out_dir: Option<PathBuf>,
+
+        #[clap(long, parse(from_os_str), value_name = "FILE", requires = "url-list")]
+        /// Also write whether each page of --url-list was captured to this file,
+        /// as JSON
+        report: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Don't record when and how the page was captured in the webarchive
+        no_capture_info: bool,
+
+        #[cfg(feature = "cdp")]
+        #[clap(long, arg_enum, default_value = "http")]
+        /// How to capture pages: by fetching them, or by rendering them in the
+        /// browser at --browser-url, so what their scripts add is kept
+        renderer: Renderer,
+
+        #[cfg(feature = "cdp")]
+        #[clap(long, value_name = "URL", default_value = "http://127.0.0.1:9222")]
+        /// Where the DevTools of the browser for `--renderer chrome` listen, such
+        /// as those of one started with --remote-debugging-port=9222
+        browser_url: String,
+
+        #[cfg(feature = "cdp")]
+        #[clap(long, value_name = "SELECTOR")]
+        /// With `--renderer chrome`, capture pages once an element matches this
+        /// CSS selector, rather than once the network goes quiet
+        wait_selector: Option<String>,
+
+        #[clap(flatten)]
+        fetch: FetchArgs,
+    },
+
+    /// Fetch the subresources a webarchive file refers to but does not contain
+    #[cfg(feature = "fetch")]
+    Update {
+        #[clap(parse(from_os_str))]
+        /// File to update
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the updated webarchive to, instead of replacing the input
+        output: Option<PathBuf>,
+
+        #[clap(flatten)]
+        fetch: FetchArgs,
+    },
+
+    /// Convert the article in a webarchive file to Markdown, leaving out page furniture
+    #[cfg(feature = "readability")]
+    Readable {
+        #[clap(parse(from_os_str))]
+        /// File to convert
+        input: PathBuf,
+
+        #[clap(short, long, parse(from_os_str))]
+        /// File name to write the Markdown to, instead of printing it
+        output: Option<PathBuf>,
+
+        #[clap(long, arg_enum, default_value = "data-url")]
+        /// How to include images: embedded as data URLs, extracted to
+        /// a folder beside the output file, or linked to their URLs
+        images: Images,
+    },
+
+    /// Serve the resources in a webarchive file over HTTP, to browse it locally
+    #[cfg(feature = "serve")]
+    Serve {
+        #[clap(parse(from_os_str))]
+        /// File to serve
+        input: PathBuf,
+
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        /// Address to listen on
+        address: std::net::SocketAddr,
+
+        #[clap(long, arg_enum, default_value = "standard")]
+        /// How requested URLs are matched to resources; `loose` also
+        /// ignores tracking parameters such as `utm_source`
+        urls: UrlMatching,
+    },
+
+    /// Print the JSON Schema of what a command prints with `--format json`
+    #[cfg(feature = "schemars")]
+    Schema {
+        #[clap(arg_enum)]
+        /// The output to describe
+        output: SchemaOutput,
+    },
+}
+
+#[cfg(feature = "readability")]
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum Images {
+    DataUrl,
+    Files,
+    Remote,
+}
+
+#[cfg(feature = "schemars")]
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum SchemaOutput {
+    /// `inspect`
+    Inspect,
+    /// `stats` of a file
+    Stats,
+    /// `stats` of a folder
+    FolderStats,
+    /// `lint`
+    Lint,
+    /// The `webarchive-manifest.json` written by `extract`
+    Manifest,
+    /// `diff`
+    Diff,
+    /// The report written by `create --report`
+    #[cfg(feature = "fetch")]
+    CreateReport,
+}
+
+/// The bytes gzip files start with
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// Reads and writes webarchive files, decrypting and
+/// encrypting them as `--identity` and `--encrypt-to` ask
+struct ArchiveFiles {
+    parse_options: webarchive::ParseOptions,
+    #[cfg(feature = "encrypt")]
+    identities: Vec<Box<dyn age::Identity>>,
+    #[cfg(feature = "encrypt")]
+    recipients: Vec<age::x25519::Recipient>,
+}
+
+impl ArchiveFiles {
+    fn new(cli: &Cli) -> Result<Self> {
+        Ok(ArchiveFiles {
+            parse_options: webarchive::ParseOptions {
+                lenient: cli.lenient,
+                strict_apple: cli.strict_apple,
+                ..Default::default()
+            },
+            #[cfg(feature = "encrypt")]
+            identities: match &cli.identity {
+                Some(path) => {
+                    let context = || format!("failed to read identities from {:?}", path);
+                    let file = std::fs::File::open(path).with_context(context)?;
+                    age::IdentityFile::from_buffer(std::io::BufReader::new(file))
+                        .with_context(context)?
+                        .into_identities()
+                        .with_context(context)?
+                }
+                None => Vec::new(),
+            },
+            #[cfg(feature = "encrypt")]
+            recipients: cli.encrypt_to.clone(),
+        })
+    }
+
+    fn read(&self, path: impl AsRef<Path>) -> Result<WebArchive> {
+        Ok(self.read_with_format(path)?.0)
+    }
+
+    fn read_with_format(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(WebArchive, webarchive::ArchiveFormat)> {
+        let path = path.as_ref();
+        let mut data = match input_url(path) {
+            #[cfg(feature = "fetch")]
+            Some(url) => download(url)?,
+            #[cfg(not(feature = "fetch"))]
+            Some(_) => anyhow::bail!("reading archives from URLs needs the `fetch` feature"),
+            None => std::fs::read(path)?,
+        };
+
+        // collections of captures are often kept as `.webarchive.gz`
+        if data.starts_with(GZIP_MAGIC) {
+            use std::io::Read;
+
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+            data = decompressed;
+        }
+
+        #[cfg(feature = "encrypt")]
+        if webarchive::is_encrypted(&data) {
+            anyhow::ensure!(
+                !self.identities.is_empty(),
+                "it is encrypted with age; pass --identity to decrypt it"
+            );
+
+            let identities = self.identities.iter().map(|identity| identity.as_ref());
+            let archive = webarchive::from_reader_encrypted(&data[..], identities)?;
+            return Ok((archive, webarchive::ArchiveFormat::Binary));
+        }
+
+        let parsed = webarchive::from_bytes_with_options(&data, &self.parse_options)?;
+        for warning in &parsed.warnings {
+            eprintln!("warning: {:?}: {}", path, warning);
+        }
+        Ok((parsed.archive, webarchive::ArchiveFormat::detect(&data)))
+    }
+
+    fn write(
+        &self,
+        path: impl AsRef<Path>,
+        archive: &WebArchive,
+        format: webarchive::ArchiveFormat,
+    ) -> Result<()> {
+        let path = path.as_ref();
+
+        #[cfg(feature = "encrypt")]
+        if !self.recipients.is_empty() {
+            let folder = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let mut file = tempfile::NamedTempFile::new_in(folder)?;
+
+            let recipients = self.recipients.iter().map(|recipient| recipient as _);
+            webarchive::to_writer_encrypted(
+                std::io::BufWriter::new(&mut file),
+                archive,
+                recipients,
+            )?;
+            file.as_file().sync_all()?;
+            file.persist(path)?;
+            return Ok(());
+        }
+
+        Ok(webarchive::to_file_atomic(path, archive, format)?)
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let files = ArchiveFiles::new(&cli)?;
+
+    match cli.command {
+        Args::Inspect {
+            input,
+            format,
+            no_color,
+            checksums,
+            sizes,
+            top,
+            #[cfg(feature = "image")]
+            verbose,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            match format {
+                #[cfg(feature = "image")]
+                InspectFormat::Text if verbose => {
+                    webarchive.write_detailed_list(
+                        &mut std::io::stdout().lock(),
+                        &sizes.to_filter(),
+                        checksums,
+                    )?;
+                    print_capture_info(&webarchive);
+
+                    if let Some(n) = top {
+                        println!();
+                        print_largest_resources(&webarchive, n);
+                    }
+                }
+                InspectFormat::Text => {
+                    let mut stdout = std::io::stdout().lock();
+                    match checksums {
+                        Some(algorithm) => webarchive.write_checksum_list(
+                            &mut stdout,
+                            &sizes.to_filter(),
+                            algorithm,
+                        )?,
+                        None => webarchive.write_filtered_list(&mut stdout, &sizes.to_filter())?,
+                    }
+
+                    print_capture_info(&webarchive);
+
+                    if let Some(n) = top {
+                        println!();
+                        print_largest_resources(&webarchive, n);
+                    }
+                }
+                InspectFormat::Table => {
+                    let options = webarchive::TableOptions {
+                        width: table_width(std::env::var("COLUMNS").ok().as_deref()),
+                        color: use_color(no_color),
+                        checksums,
+                    };
+                    webarchive.write_table(
+                        &mut std::io::stdout().lock(),
+                        &sizes.to_filter(),
+                        &options,
+                    )?;
+                    print_capture_info(&webarchive);
+
+                    if let Some(n) = top {
+                        println!();
+                        print_largest_resources(&webarchive, n);
+                    }
+                }
+                InspectFormat::Json => {
+                    let inspection = Inspection {
+                        summary: webarchive
+                            .summary_info()
+                            .context("failed to summarise webarchive")?,
+                        checksums: checksums.map(|algorithm| Checksums {
+                            algorithm,
+                            resources: webarchive.checksums(algorithm),
+                        }),
+                        largest: top.map(|n| largest_resources(&webarchive, n)),
+                        capture_info: webarchive.capture_info(),
+                        #[cfg(feature = "image")]
+                        images: verbose.then(|| {
+                            webarchive
+                                .filter_resources(&sizes.to_filter())
+                                .filter_map(|resource| {
+                                    let (width, height) = resource.image_dimensions()?;
+                                    Some(InspectedImage {
+                                        url: resource.url.clone(),
+                                        width,
+                                        height,
+                                    })
+                                })
+                                .collect()
+                        }),
+                    };
+                    println!("{}", serde_json::to_string_pretty(&inspection)?);
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Stats {
+            input,
+            recursive,
+            format,
+            top,
+        } => {
+            if input.is_dir() {
+                anyhow::ensure!(top.is_none(), "--top only works on a single file");
+
+                let stats = collection_stats(&files, &input, recursive)?;
+                return match format {
+                    StatsFormat::Text => {
+                        print_collection_stats(&stats);
+                        Ok(())
+                    }
+                    StatsFormat::Json => {
+                        let stats = FolderStats {
+                            stats: &stats,
+                            file_sizes: stats.file_sizes(),
+                            resource_counts: stats.resource_counts(),
+                        };
+                        println!("{}", serde_json::to_string_pretty(&stats)?);
+                        Ok(())
+                    }
+                    StatsFormat::Csv => write_collection_csv(&stats),
+                };
+            }
+
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+            let summary = webarchive
+                .summary_info()
+                .context("failed to summarise webarchive")?;
+
+            match format {
+                StatsFormat::Text => {
+                    println!(
+                        "{} bytes in {} resource(s), across {} subframe(s)",
+                        summary.total_size, summary.resource_count, summary.frame_count
+                    );
+                    let completeness = summary.completeness;
+                    println!(
+                        "{} of {} referenced URL(s) stored ({:.1}% complete)",
+                        completeness.stored,
+                        completeness.referenced,
+                        completeness.ratio() * 100.0
+                    );
+                    println!();
+
+                    let mut mime_types: Vec<_> = summary.mime_types.iter().collect();
+                    mime_types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+
+                    println!(
+                        "{:<24} {:>8} {:>12} {:>7}",
+                        "MIME type", "Count", "Bytes", "Share"
+                    );
+                    for (mime_type, stats) in mime_types {
+                        println!(
+                            "{:<24} {:>8} {:>12} {:>6.1}%",
+                            mime_type,
+                            stats.count,
+                            stats.bytes,
+                            stats.bytes as f64 * 100.0 / summary.total_size.max(1) as f64
+                        );
+                    }
+
+                    if let Some(n) = top {
+                        println!();
+                        print_largest_resources(&webarchive, n);
+                    }
+                }
+                StatsFormat::Json => {
+                    let stats = FileStats {
+                        total_size: summary.total_size,
+                        resource_count: summary.resource_count,
+                        frame_count: summary.frame_count,
+                        mime_types: summary.mime_types,
+                        completeness: summary.completeness,
+                        largest: top.map(|n| largest_resources(&webarchive, n)),
+                    };
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                }
+                StatsFormat::Csv => {
+                    let mut stats = webarchive::CollectionStats::default();
+                    let file_size = std::fs::metadata(&input)?.len();
+                    stats.add(input.display().to_string(), file_size, &summary);
+                    write_collection_csv(&stats)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Urls {
+            inputs,
+            subresources_only,
+            frames_only,
+            mime,
+            null,
+            no_filename,
+            sizes,
+            url_filter,
+        } => {
+            use std::io::Write;
+
+            let filter = webarchive::ResourceFilter {
+                subresources_only,
+                frames_only,
+                mime,
+                url: url_filter.to_matcher(),
+                ..sizes.to_filter()
+            };
+            let terminator = if null { '\0' } else { '\n' };
+            let prefix = inputs.len() > 1 && !no_filename;
+            let mut stdout = std::io::stdout().lock();
+
+            for input in &inputs {
+                let webarchive: WebArchive = files
+                    .read(input)
+                    .with_context(|| format!("failed to read {:?}", input))?;
+
+                for resource in webarchive.filter_resources(&filter) {
+                    if prefix {
+                        write!(stdout, "{}:", input.display())?;
+                    }
+                    write!(stdout, "{}{}", resource.url, terminator)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Links {
+            inputs,
+            external_only,
+            internal_only,
+            with_text,
+            count,
+            format,
+        } => {
+            use std::io::Write;
+
+            /// A link listed by `links`
+            #[derive(serde::Serialize)]
+            struct ListedLink {
+                #[serde(skip_serializing_if = "Option::is_none")]
+                count: Option<usize>,
+                #[serde(flatten)]
+                link: webarchive::Link,
+                internal: bool,
+            }
+
+            let mut listed: Vec<ListedLink> = Vec::new();
+            let mut positions: HashMap<String, usize> = HashMap::new();
+
+            for input in &inputs {
+                let webarchive: WebArchive = files
+                    .read(input)
+                    .with_context(|| format!("failed to read {:?}", input))?;
+                let frames = webarchive
+                    .outbound_links()
+                    .with_context(|| format!("failed to find links in {:?}", input))?;
+
+                for link in frames.into_iter().flat_map(|frame| frame.links) {
+                    let internal = link.is_same_origin(&webarchive.main_resource.url);
+                    if (external_only && internal) || (internal_only && !internal) {
+                        continue;
+                    }
+
+                    if count {
+                        if let Some(&position) = positions.get(&link.url) {
+                            *listed[position].count.get_or_insert(0) += 1;
+                            continue;
+                        }
+                        positions.insert(link.url.clone(), listed.len());
+                    }
+
+                    listed.push(ListedLink {
+                        count: count.then_some(1),
+                        link,
+                        internal,
+                    });
+                }
+            }
+
+            match format {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&listed)?),
+                Format::Text => {
+                    let mut stdout = std::io::stdout().lock();
+                    for listed in &listed {
+                        if let Some(count) = listed.count {
+                            write!(stdout, "{}\t", count)?;
+                        }
+                        write!(stdout, "{}", listed.link.url)?;
+                        if with_text {
+                            write!(stdout, "\t{}", listed.link.text)?;
+                        }
+                        writeln!(stdout)?;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Cat { input, url } => {
+            use std::io::Write;
+
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let resource = webarchive
+                .get_resource_by_url(&url)
+                .with_context(|| format!("{:?} not found in {:?}", url, input))?;
+
+            std::io::stdout().lock().write_all(&resource.data)?;
+            Ok(())
+        }
+
+        Args::Head {
+            input,
+            url,
+            bytes,
+            lines,
+            hex,
+        } => {
+            use std::io::Write;
+
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let resource = webarchive
+                .get_resource_by_url(&url)
+                .with_context(|| format!("{:?} not found in {:?}", url, input))?;
+
+            let mut stdout = std::io::stdout().lock();
+            match resource.decoded_text().filter(|_| !hex) {
+                Some(text) => {
+                    let (head, truncated) = text_head(&text, bytes, lines);
+                    stdout.write_all(head.as_bytes())?;
+                    if truncated {
+                        writeln!(stdout, "…")?;
+                    }
+                }
+                None => {
+                    anyhow::ensure!(
+                        lines.is_none(),
+                        "{:?} is not text, so can not be printed by lines",
+                        url
+                    );
+                    write_hex_dump(
+                        &mut stdout,
+                        &resource.data[..bytes.min(resource.data.len())],
+                    )?;
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Extract {
+            input,
+            output,
+            convert_links,
+            preserve_mtime,
+            use_response_filenames,
+            by_frame,
+            main_as_index,
+            layout,
+            dedupe_files,
+            with_responses,
+            decode_responses,
+            no_manifest,
+            transcode_to_utf8,
+            lossy,
+            sizes,
+            url_filter,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let output = match &output {
+                Some(path) => path,
+                // a URL's "parent" is no place to write files
+                None if input_url(&input).is_some() => Path::new("."),
+                None => input
+                    .parent()
+                    .context("Could not get an output directory")?,
+            };
+
+            let options = ExtractOptions {
+                filter: webarchive::ResourceFilter {
+                    url: url_filter.to_matcher(),
+                    ..sizes.to_filter()
+                },
+                convert_links,
+                preserve_mtime,
+                dedupe_files,
+                plan: webarchive::PlanOptions {
+                    use_response_filenames,
+                    by_frame,
+                    main_as_index,
+                    layout: layout.into(),
+                    ..Default::default()
+                },
+                with_responses,
+                decode_responses,
+                manifest: !no_manifest,
+                transcode_to_utf8,
+                lossy,
+            };
+
+            save_archive(&webarchive, output, &options).context("Saving resources")
+        }
+
+        Args::VerifyExtract {
+            archive,
+            folder,
+            convert_links,
+            use_response_filenames,
+            by_frame,
+            main_as_index,
+            layout,
+            transcode_to_utf8,
+            lossy,
+            with_responses,
+            sizes,
+            url_filter,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            let options = ExtractOptions {
+                filter: webarchive::ResourceFilter {
+                    url: url_filter.to_matcher(),
+                    ..sizes.to_filter()
+                },
+                convert_links,
+                plan: webarchive::PlanOptions {
+                    use_response_filenames,
+                    by_frame,
+                    main_as_index,
+                    layout: layout.into(),
+                    ..Default::default()
+                },
+                transcode_to_utf8,
+                lossy,
+                with_responses,
+                ..Default::default()
+            };
+
+            let differences = verify_extraction(&webarchive, &archive, &folder, &options)?;
+            if differences > 0 {
+                anyhow::bail!(
+                    "{} difference(s) between {:?} and {:?}",
+                    differences,
+                    folder,
+                    archive
+                );
+            }
+
+            Ok(())
+        }
+
+        Args::Repack {
+            folder,
+            output,
+            include_new,
+            base_url,
+        } => {
+            let manifest = webarchive::ExtractionManifest::read_from(&folder)
+                .with_context(|| format!("failed to read the manifest in {:?}", folder))?;
+
+            let options = webarchive::RepackOptions {
+                new_files_base_url: base_url.filter(|_| include_new),
+            };
+            let webarchive = manifest
+                .repack(&folder, &options)
+                .with_context(|| format!("failed to repack {:?}", folder))?;
+
+            println!(
+                "Writing webarchive of {:?} with {} resource(s) to {:?}...",
+                webarchive.main_resource.url,
+                webarchive.iter_resources().count(),
+                output
+            );
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            Ok(())
+        }
+
+        Args::Open {
+            input,
+            keep,
+            browser,
+            native,
+        } => {
+            if native {
+                if browser.is_none() && !cfg!(target_os = "macos") {
+                    anyhow::bail!("--native needs Safari, which is only available on macOS");
+                }
+
+                return launch(&input, browser.as_deref());
+            }
+
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let temp_dir = tempfile::Builder::new()
+                .prefix("webarchive-")
+                .tempdir()
+                .context("failed to create a temporary folder")?;
+
+            let options = ExtractOptions {
+                convert_links: true,
+                ..Default::default()
+            };
+
+            save_archive(&webarchive, temp_dir.path(), &options).context("Saving resources")?;
+            println!("Extracted to {:?}", temp_dir.path());
+
+            let main_path = &webarchive.plan_extraction()[0].path;
+            launch(&temp_dir.path().join(main_path), browser.as_deref())?;
+
+            if keep {
+                println!("Keeping {:?}", temp_dir.into_path());
+            } else {
+                println!("Press Enter to remove {:?}...", temp_dir.path());
+                std::io::stdin().read_line(&mut String::new())?;
+                temp_dir
+                    .close()
+                    .context("failed to remove the temporary folder")?;
+            }
+
+            Ok(())
+        }
+
+        Args::Headers {
+            archive,
+            url,
+            format,
+            raw,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            let resource = webarchive
+                .get_resource_by_url(&url)
+                .ok_or_else(|| webarchive::Error::ResourceNotFound(url.clone()))?;
+
+            if resource.response.is_none() {
+                anyhow::bail!("{:?} has no archived response", url);
+            }
+
+            let response = resource
+                .parse_response()
+                .context("The archived response is not a property list")?
+                .expect("Resource has response data");
+
+            if raw {
+                println!("{:#?}", response.raw());
+                return Ok(());
+            }
+
+            match format {
+                Format::Text => {
+                    if let Some(status) = response.status {
+                        match reason_phrase(status) {
+                            Some(reason) => println!("HTTP/1.1 {} {}", status, reason),
+                            None => println!("HTTP/1.1 {}", status),
+                        }
+                    }
+
+                    for (name, value) in response.headers.iter() {
+                        println!("{}: {}", name, value);
+                    }
+                }
+                Format::Json => {
+                    #[derive(serde::Serialize)]
+                    struct Response {
+                        #[serde(flatten)]
+                        response: webarchive::ResourceResponse,
+                        expected_content_length: Option<u64>,
+                    }
+
+                    let response = Response {
+                        expected_content_length: response.expected_content_length(),
+                        response,
+                    };
+                    println!("{}", serde_json::to_string_pretty(&response)?);
+                }
+            }
+
+            Ok(())
+        }
+
+        Args::Graph { input } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let graph = webarchive
+                .dependency_graph()
+                .context("Collecting references")?;
+
+            graph
+                .to_dot(&mut std::io::stdout().lock())
+                .context("Writing graph")
+        }
+
+        Args::Sitemap { inputs } => {
+            let mut webarchives = Vec::new();
+            for input in &inputs {
+                let webarchive: WebArchive = files
+                    .read(input)
+                    .with_context(|| format!("failed to read {:?}", input))?;
+                webarchives.push(webarchive);
+            }
+
+            let webarchives: Vec<&WebArchive> = webarchives.iter().collect();
+            webarchive::convert::to_sitemap(&webarchives, std::io::stdout().lock())
+                .context("Writing sitemap")
+        }
+
+        Args::Index {
+            dir,
+            output,
+            extract_thumbnails,
+        } => {
+            use webarchive::convert::IndexEntry;
+
+            let mut inputs = Vec::new();
+            for entry in
+                std::fs::read_dir(&dir).with_context(|| format!("failed to list {:?}", dir))?
+            {
+                let path = entry?.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "webarchive")
+                {
+                    inputs.push(path);
+                }
+            }
+            inputs.sort();
+
+            let output = std::path::absolute(&output)?;
+            let thumbnails = output.with_file_name("thumbnails");
+
+            let mut entries = Vec::new();
+            for input in &inputs {
+                let read = || -> Result<_> {
+                    let webarchive: WebArchive = files.read(input)?;
+                    let summary = webarchive.summary_info()?;
+
+                    let thumbnail = match webarchive.thumbnail_candidate()? {
+                        Some(resource) if extract_thumbnails => {
+                            let path = thumbnails.join(format!(
+                                "{}.{}",
+                                input.file_stem().unwrap_or_default().to_string_lossy(),
+                                resource.guessed_extension()
+                            ));
+                            save(resource, &path)?;
+                            Some(relative_link(&output, &path))
+                        }
+                        Some(resource) => Some(resource.to_data_url()),
+                        None => None,
+                    };
+
+                    Ok((summary, thumbnail))
+                };
+
+                let (summary, thumbnail) = match read() {
+                    Ok((summary, thumbnail)) => (Ok(summary), thumbnail),
+                    Err(error) => {
+                        eprintln!("warning: could not read {:?}: {:#}", input, error);
+                        (Err(format!("{:#}", error)), None)
+                    }
+                };
+
+                entries.push(IndexEntry {
+                    name: input
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    href: relative_link(&output, &std::path::absolute(input)?),
+                    summary,
+                    thumbnail,
+                });
+            }
+
+            let title = std::path::absolute(&dir)?
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Web archives".to_string());
+            let html = webarchive::convert::to_collection_index(&title, &entries);
+
+            println!(
+                "Writing index of {} archive(s) to {:?}...",
+                entries.len(),
+                output
+            );
+            std::fs::write(&output, html).with_context(|| format!("failed to write {:?}", output))
+        }
+
+        Args::Validate { input } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let report = webarchive
+                .validate()
+                .with_context(|| format!("failed to validate {:?}", input))?;
+
+            for issue in &report.issues {
+                println!("{}", issue);
+            }
+
+            if webarchive.has_manifest() {
+                println!(
+                    "Checksum manifest {}",
+                    if report
+                        .issues
+                        .iter()
+                        .any(|issue| issue.kind == webarchive::IssueKind::ManifestMismatch)
+                    {
+                        "does not match"
+                    } else {
+                        "matches"
+                    }
+                );
+            }
+
+            let errors = report.errors().count();
+            let warnings = report.warnings().count();
+            println!(
+                "{} error{}, {} warning{}",
+                errors,
+                if errors == 1 { "" } else { "s" },
+                warnings,
+                if warnings == 1 { "" } else { "s" },
+            );
+
+            if report.has_errors() {
+                anyhow::bail!("{:?} is not valid", input);
+            }
+
+            Ok(())
+        }
+
+        Args::Add {
+            archive,
+            url,
+            file,
+            mime,
+            encoding,
+            replace,
+            output,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            let mut resource = WebResource::from_file(&file, url)
+                .with_context(|| format!("failed to read {:?}", file))?;
+
+            if let Some(mime) = mime {
+                resource.mime_type = mime;
+                resource.text_encoding_name = None;
+                if resource.is_text() && std::str::from_utf8(&resource.data).is_ok() {
+                    resource.text_encoding_name = Some("UTF-8".to_string());
+                }
+            }
+
+            if encoding.is_some() {
+                resource.text_encoding_name = encoding;
+            }
+
+            let url = resource.url.clone();
+            let size = resource.data.len();
+            let previous = webarchive
+                .add_subresource(resource, replace)
+                .map_err(|error| match error {
+                    webarchive::Error::DuplicateUrl(_) => {
+                        anyhow::anyhow!("{}; use --replace to overwrite it", error)
+                    }
+                    error => error.into(),
+                })?;
+
+            match previous {
+                Some(previous) => println!(
+                    "Replaced {:?} ({} bytes, was {} bytes)",
+                    url,
+                    size,
+                    previous.data.len()
+                ),
+                None => println!("Added {:?} ({} bytes)", url, size),
+            }
+
+            let output = output.unwrap_or(archive);
+            files
+                .write(&output, &webarchive, format)
+                .with_context(|| format!("failed to write {:?}", output))
+        }
+
+        Args::Lint {
+            inputs,
+            allow,
+            deny,
+            format,
+        } => {
+            let mut config = webarchive::LintConfig::default();
+            for rule in allow {
+                config.set(rule, webarchive::LintLevel::Allow);
+            }
+            for rule in deny {
+                config.set(rule, webarchive::LintLevel::Deny);
+            }
+
+            let mut reports = Vec::new();
+            for input in &inputs {
+                let webarchive: WebArchive = files
+                    .read(input)
+                    .with_context(|| format!("failed to read {:?}", input))?;
+                let report = webarchive
+                    .lint(&config)
+                    .with_context(|| format!("failed to check {:?}", input))?;
+                reports.push((input, report));
+            }
+
+            let label = |level| match level {
+                webarchive::LintLevel::Deny => "error",
+                _ => "warning",
+            };
+
+            match format {
+                LintFormat::Text => {
+                    for (input, report) in &reports {
+                        if report.findings.is_empty() {
+                            continue;
+                        }
+
+                        println!("{:?}:", input);
+                        let mut rule = None;
+                        for finding in &report.findings {
+                            if rule != Some(finding.rule.id) {
+                                rule = Some(finding.rule.id);
+                                println!("  {}", finding.rule);
+                            }
+
+                            match &finding.issue.url {
+                                Some(url) => println!(
+                                    "    {}: {:?}: {}",
+                                    label(finding.level),
+                                    url,
+                                    finding.issue.message
+                                ),
+                                None => println!(
+                                    "    {}: {}",
+                                    label(finding.level),
+                                    finding.issue.message
+                                ),
+                            }
+                        }
+                    }
+
+                    let count = |level| {
+                        reports
+                            .iter()
+                            .map(|(_, report)| report.count(level))
+                            .sum::<usize>()
+                    };
+                    let errors = count(webarchive::LintLevel::Deny);
+                    let warnings = count(webarchive::LintLevel::Warn);
+                    println!(
+                        "{} error{}, {} warning{}",
+                        errors,
+                        if errors == 1 { "" } else { "s" },
+                        warnings,
+                        if warnings == 1 { "" } else { "s" },
+                    );
+                }
+                LintFormat::Json => {
+                    let reports: Vec<_> = reports
+                        .iter()
+                        .map(|(input, report)| LintedFile {
+                            file: input,
+                            findings: &report.findings,
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&reports)?);
+                }
+                LintFormat::Github => {
+                    for (input, report) in &reports {
+                        for finding in &report.findings {
+                            let message = match &finding.issue.url {
+                                Some(url) => format!("{:?}: {}", url, finding.issue.message),
+                                None => finding.issue.message.clone(),
+                            };
+                            println!(
+                                "::{} file={},title={}::{}",
+                                label(finding.level),
+                                escape_workflow_command(&input.to_string_lossy(), true),
+                                escape_workflow_command(&finding.rule.to_string(), true),
+                                escape_workflow_command(&message, false),
+                            );
+                        }
+                    }
+                }
+            }
+
+            let denied = reports
+                .iter()
+                .filter(|(_, report)| report.has_denied())
+                .count();
+            if denied > 0 {
+                anyhow::bail!(
+                    "{} file{} failed linting",
+                    denied,
+                    if denied == 1 { "" } else { "s" }
+                );
+            }
+
+            Ok(())
+        }
+
+        Args::Diff {
+            old,
+            new,
+            canonical,
+            urls,
+            #[cfg(feature = "diff")]
+            content,
+            #[cfg(feature = "diff")]
+            max_content_size,
+            format,
+        } => {
+            let read = |path: &PathBuf| -> Result<WebArchive> {
+                let mut webarchive: WebArchive = files
+                    .read(path)
+                    .with_context(|| format!("failed to read {:?}", path))?;
+
+                if canonical {
+                    webarchive.canonicalize_with(urls.unwrap_or_default().into());
+                }
+
+                Ok(webarchive)
+            };
+            let old_archive = read(&old)?;
+            let new_archive = read(&new)?;
+
+            #[cfg(feature = "diff")]
+            let diff = if content {
+                let options = webarchive::ContentDiffOptions {
+                    max_size: max_content_size,
+                    ..Default::default()
+                };
+                old_archive.diff_content(&new_archive, &options)
+            } else {
+                old_archive.diff(&new_archive)
+            };
+            #[cfg(not(feature = "diff"))]
+            let diff = old_archive.diff(&new_archive);
+
+            match format {
+                Format::Text => {
+                    for change in &diff.changes {
+                        print_change(change);
+                    }
+                    if diff.is_empty() {
+                        println!("No differences");
+                    }
+                }
+                Format::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+            }
+
+            if !diff.is_empty() {
+                anyhow::bail!("{} resource(s) differ", diff.changes.len());
+            }
+
+            Ok(())
+        }
+
+        Args::Merge {
+            inputs,
+            output,
+            prefer,
+        } => {
+            let mut inputs = inputs.into_iter();
+            let first = inputs.next().expect("At least two inputs are required");
+            let mut webarchive: WebArchive = files
+                .read(&first)
+                .with_context(|| format!("failed to read {:?}", first))?;
+
+            for input in inputs {
+                let other: WebArchive = files
+                    .read(&input)
+                    .with_context(|| format!("failed to read {:?}", input))?;
+
+                println!("Merging {:?}...", input);
+                let report = webarchive.merge(other, prefer.into());
+                for conflict in &report.conflicts {
+                    println!(
+                        "~ {:?}: {}",
+                        conflict.url,
+                        merge_decision(conflict, prefer, &input)
+                    );
+                }
+                println!(
+                    "Added {} resource(s), {} identical, {} conflicting",
+                    report.added.len(),
+                    report.identical,
+                    report.conflicts.len()
+                );
+            }
+
+            println!("Writing merged webarchive to {:?}...", output);
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            Ok(())
+        }
+
+        Args::SetMain {
+            archive,
+            url,
+            discard_old,
+            output,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            let old_url = webarchive.main_resource.url.clone();
+            if old_url == url {
+                println!("{:?} is already the main resource", url);
+                return Ok(());
+            }
+
+            let discarded = webarchive.set_main_resource(&url, !discard_old)?;
+
+            println!("Promoted {:?} to the main resource", url);
+            match discarded {
+                Some(previous) => println!(
+                    "Discarded {:?} ({} bytes)",
+                    previous.url,
+                    previous.data.len()
+                ),
+                None => println!("Kept {:?} as a subresource", old_url),
+            }
+
+            let output = output.unwrap_or(archive);
+            files
+                .write(&output, &webarchive, format)
+                .with_context(|| format!("failed to write {:?}", output))
+        }
+
+        Args::SetUrl {
+            archive,
+            from,
+            to,
+            rewrite_references,
+            merge,
+            output,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            let report = webarchive
+                .set_resource_url(
+                    &from,
+                    &to,
+                    webarchive::SetUrlOptions {
+                        rewrite_references,
+                        merge,
+                    },
+                )
+                .map_err(|error| match error {
+                    webarchive::Error::DuplicateUrl(_) => anyhow::anyhow!(
+                        "{}; pass --merge to remove {:?} in its favour",
+                        error,
+                        from
+                    ),
+                    error => error.into(),
+                })?;
+
+            if report.resources.is_empty() {
+                println!("{:?} is already stored at that URL", from);
+                return Ok(());
+            }
+
+            for (old_url, new_url) in &report.resources {
+                println!("{:?} → {:?}", old_url, new_url);
+            }
+            if rewrite_references {
+                println!("Rewrote {} reference(s)", report.references);
+            }
+
+            let output = output.unwrap_or(archive);
+            files
+                .write(&output, &webarchive, format)
+                .with_context(|| format!("failed to write {:?}", output))
+        }
+
+        Args::Remove {
+            archive,
+            urls,
+            url_filter,
+            status,
+            filter,
+            dry_run,
+            ignore_missing,
+            output,
+        } => {
+            let matcher = match url_filter.to_matcher() {
+                None if urls.is_empty() && status.is_none() && filter.is_none() => anyhow::bail!(
+                    "nothing to remove; give at least one --url, --url-glob, --url-regex, \
+                    --status or --filter"
+                ),
+                patterns => webarchive::UrlMatcher::any(
+                    urls.iter()
+                        .map(|url| webarchive::UrlMatcher::exact(url))
+                        .chain(patterns),
+                ),
+            };
+
+            let (mut webarchive, format) = files
+                .read_with_format(&archive)
+                .with_context(|| format!("failed to read {:?}", archive))?;
+
+            for (_, frame) in webarchive.iter_frames() {
+                if urls.contains(&frame.main_resource.url) {
+                    anyhow::bail!(
+                        "refusing to remove {:?}, which is the main resource of a frame",
+                        frame.main_resource.url
+                    );
+                }
+            }
+
+            let matches = |resource: &WebResource| {
+                matcher.is_match(&resource.url)
+                    || status.is_some_and(|statuses| {
+                        resource
+                            .response_status()
+                            .is_some_and(|status| statuses.contains(&status))
+                    })
+                    || filter
+                        .as_ref()
+                        .is_some_and(|filter| filter.matches(resource, false))
+            };
+
+            let removed = if dry_run {
+                let filter = webarchive::ResourceFilter {
+                    subresources_only: true,
+                    ..Default::default()
+                };
+                let matched: Vec<&WebResource> = webarchive
+                    .filter_resources(&filter)
+                    .filter(|resource| matches(resource))
+                    .collect();
+
+                for resource in &matched {
+                    println!(
+                        "Would remove {:?} ({} bytes)",
+                        resource.url,
+                        resource.data.len()
+                    );
+                }
+                matched.len()
+            } else {
+                let size = webarchive.estimated_binary_size();
+                let removed = webarchive.strip(matches);
+
+                for resource in &removed {
+                    println!("Removed {:?} ({} bytes)", resource.url, resource.data.len());
+                }
+
+                if !removed.is_empty() {
+                    let output = output.unwrap_or(archive);
+                    files
+                        .write(&output, &webarchive, format)
+                        .with_context(|| format!("failed to write {:?}", output))?;
+                    println!(
+                        "{} bytes smaller",
+                        size - webarchive.estimated_binary_size()
+                    );
+                }
+                removed.len()
+            };
+
+            if removed == 0 && !ignore_missing {
+                anyhow::bail!("no subresources matched");
+            }
+
+            Ok(())
+        }
+
+        Args::RewriteUrls {
+            input,
+            output,
+            mut mappings,
+            substitutions,
+            dry_run,
+        } => {
+            if mappings.is_empty() && substitutions.is_empty() {
+                anyhow::bail!("nothing to rewrite; give at least one --map or --regex");
+            }
+
+            let (mut webarchive, format) = files
+                .read_with_format(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            mappings.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+
+            let report = webarchive
+                .rewrite_urls(|url| {
+                    let mut new_url = mappings
+                        .iter()
+                        .find_map(|(from, to)| {
+                            url.strip_prefix(from.as_str())
+                                .map(|rest| format!("{}{}", to, rest))
+                        })
+                        .unwrap_or_else(|| url.to_string());
+
+                    for (pattern, replacement) in &substitutions {
+                        new_url = pattern
+                            .replace_all(&new_url, replacement.as_str())
+                            .into_owned();
+                    }
+
+                    Some(new_url)
+                })
+                .context("Rewriting references")?;
+
+            for (old_url, new_url) in &report.resources {
+                println!("{:?} → {:?}", old_url, new_url);
+            }
+
+            let summary = format!(
+                "{} resource URL(s) and {} reference(s)",
+                report.resources.len(),
+                report.references
+            );
+
+            match output {
+                Some(output) if !dry_run => {
+                    files
+                        .write(&output, &webarchive, format)
+                        .with_context(|| format!("failed to write {:?}", output))?;
+                    println!("Rewrote {}", summary);
+                }
+                _ => println!("Would rewrite {}", summary),
+            }
+
+            Ok(())
+        }
+
+        Args::Sed {
+            expression,
+            input,
+            output,
+            mime,
+            url_filter,
+            filter,
+            include_references,
+            dry_run,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let filter = webarchive::ResourceFilter {
+                mime,
+                url: url_filter.to_matcher(),
+                expression: filter,
+                ..Default::default()
+            };
+
+            // without filters, binary resources are passed over rather than chosen
+            if filter != webarchive::ResourceFilter::default() {
+                if let Some(resource) = webarchive
+                    .filter_resources(&filter)
+                    .find(|resource| !resource.is_text())
+                {
+                    anyhow::bail!(
+                        "refusing to change {:?}, as it is binary ({:?}); \
+                        narrow --mime or the URL filters to text resources",
+                        resource.url,
+                        resource.mime_type
+                    );
+                }
+            }
+
+            let options = webarchive::ReplaceOptions {
+                include_references,
+                first_in_line: !expression.global,
+            };
+            let report = webarchive
+                .replace_text_with_options(
+                    &expression.pattern,
+                    &expression.replacement,
+                    &filter,
+                    options,
+                )
+                .context("failed to replace text")?;
+
+            for (url, count) in &report.resources {
+                println!("{:?}: {} replacement(s)", url, count);
+            }
+            if report.skipped > 0 {
+                println!(
+                    "Skipped {} match(es) within tags which refer to other resources; \
+                    pass --include-references to change them too",
+                    report.skipped
+                );
+            }
+            for url in &report.stale_lengths {
+                eprintln!(
+                    "Warning: {:?} changed length, so its archived Content-Length no longer matches",
+                    url
+                );
+            }
+
+            match output {
+                Some(output) if !dry_run => {
+                    files
+                        .write(&output, &webarchive, format)
+                        .with_context(|| format!("failed to write {:?}", output))?;
+                    println!("Made {} replacement(s)", report.total());
+                }
+                _ => println!("Would make {} replacement(s)", report.total()),
+            }
+
+            Ok(())
+        }
+
+        Args::FixMime {
+            input,
+            output,
+            aggressive,
+            dry_run,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let fixed = webarchive.fix_mime_types(aggressive);
+
+            if !fixed.is_empty() {
+                println!(
+                    "{:<24} {:<24} {:<16} URL",
+                    "Declared", "Sniffed", "Confidence"
+                );
+                for mismatch in &fixed {
+                    println!(
+                        "{:<24} {:<24} {:<16} {}",
+                        mismatch.declared,
+                        mismatch.sniffed,
+                        mismatch.confidence.to_string(),
+                        mismatch.url
+                    );
+                }
+            }
+
+            match output {
+                Some(output) if !dry_run => {
+                    files
+                        .write(&output, &webarchive, format)
+                        .with_context(|| format!("failed to write {:?}", output))?;
+                    println!("Fixed {} MIME type(s)", fixed.len());
+                }
+                _ => println!("Would fix {} MIME type(s)", fixed.len()),
+            }
+
+            Ok(())
+        }
+
+        Args::Dedupe {
+            input,
+            output,
+            report_only,
+            across_urls,
+        } => {
+            let mut webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let groups = webarchive.duplicate_groups(across_urls);
+            for group in &groups {
+                println!(
+                    "{} copies of {} bytes, wasting {} bytes:",
+                    group.urls.len(),
+                    group.size,
+                    group.wasted_bytes()
+                );
+                for url in &group.urls {
+                    println!("  - {:?}", url);
+                }
+            }
+
+            if report_only {
+                println!(
+                    "{} duplicate group{}, wasting {} bytes",
+                    groups.len(),
+                    if groups.len() == 1 { "" } else { "s" },
+                    groups
+                        .iter()
+                        .map(|group| group.wasted_bytes())
+                        .sum::<usize>()
+                );
+                return Ok(());
+            }
+
+            let output = output.context("an --output file is required")?;
+            let original_size = std::fs::metadata(&input)
+                .with_context(|| format!("failed to read {:?}", input))?
+                .len();
+
+            let report = webarchive
+                .dedupe(across_urls)
+                .context("failed to rewrite references")?;
+
+            println!("Writing deduplicated webarchive to {:?}...", output);
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            let size = std::fs::metadata(&output)
+                .with_context(|| format!("failed to read {:?}", output))?
+                .len();
+            println!(
+                "Removed {} subresource{} and rewrote {} reference{}, saving {} bytes ({} bytes total)",
+                report.removed,
+                if report.removed == 1 { "" } else { "s" },
+                report.rewritten_references,
+                if report.rewritten_references == 1 { "" } else { "s" },
+                original_size.saturating_sub(size),
+                size
+            );
+
+            Ok(())
+        }
+
+        Args::Optimize {
+            input,
+            output,
+            keep_responses,
+            no_dedupe,
+            across_urls,
+            keep_orphans,
+            no_inline,
+            inline_max_size,
+        } => {
+            let mut webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+            let original_size = std::fs::metadata(&input)
+                .with_context(|| format!("failed to read {:?}", input))?
+                .len();
+
+            let issue_key = |issue: &webarchive::Issue| (issue.kind, issue.url.clone());
+            let original_errors: std::collections::HashSet<_> = webarchive
+                .validate()
+                .with_context(|| format!("failed to validate {:?}", input))?
+                .errors()
+                .map(issue_key)
+                .collect();
+
+            let options = webarchive::OptimizeOptions {
+                strip_responses: !keep_responses,
+                dedupe: !no_dedupe,
+                across_urls,
+                drop_orphans: !keep_orphans,
+                inline_max_size: (!no_inline).then_some(inline_max_size),
+            };
+            let report = webarchive
+                .optimize(&options)
+                .context("failed to optimize webarchive")?;
+
+            println!("{:<24} {:>12}", "Pass", "Bytes saved");
+            for pass in &report.passes {
+                println!("{:<24} {:>12}", pass.pass.to_string(), pass.saved);
+            }
+            println!("{:<24} {:>12}", "Total", report.total_saved());
+
+            let validation = webarchive
+                .validate()
+                .context("failed to validate optimized webarchive")?;
+            let new_errors: Vec<_> = validation
+                .errors()
+                .filter(|issue| !original_errors.contains(&issue_key(issue)))
+                .collect();
+
+            if !new_errors.is_empty() {
+                for issue in new_errors {
+                    eprintln!("{}", issue);
+                }
+                anyhow::bail!(
+                    "optimizing {:?} introduced errors, so it was not written",
+                    input
+                );
+            }
+
+            println!("Writing optimized webarchive to {:?}...", output);
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            let size = std::fs::metadata(&output)
+                .with_context(|| format!("failed to read {:?}", output))?
+                .len();
+            println!("{} bytes, down from {} bytes", size, original_size);
+
+            Ok(())
+        }
+
+        Args::Sanitize {
+            input,
+            output,
+            inline_event_handlers,
+            javascript_urls,
+            script_elements,
+            external_script_subresources,
+            meta_refresh,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let policy = webarchive::SanitizePolicy {
+                inline_event_handlers,
+                javascript_urls,
+                script_elements,
+                external_script_subresources,
+                meta_refresh,
+            };
+            let policy = if policy == webarchive::SanitizePolicy::none() {
+                webarchive::SanitizePolicy::all()
+            } else {
+                policy
+            };
+
+            let report = webarchive
+                .sanitize(&policy)
+                .context("failed to sanitize webarchive")?;
+
+            for removal in &report.removals {
+                println!(
+                    "{:<30} {} in {:?}",
+                    removal.rule.to_string(),
+                    removal.what,
+                    removal.url
+                );
+            }
+
+            files
+                .write(&output, &webarchive, format)
+                .with_context(|| format!("failed to write {:?}", output))?;
+            println!(
+                "Removed {} item{}",
+                report.removals.len(),
+                if report.removals.len() == 1 { "" } else { "s" }
+            );
+
+            Ok(())
+        }
+
+        Args::Repair {
+            input,
+            output,
+            main,
+        } => {
+            let data =
+                std::fs::read(&input).with_context(|| format!("failed to read {:?}", input))?;
+            let recovered = webarchive::recover_from_bytes(&data);
+            let mut resources = recovered.resources;
+
+            let mut webarchive = match (&main, recovered.archive) {
+                (None, Some(webarchive)) => webarchive,
+                (main, _) => {
+                    let index = match main {
+                        Some(url) => resources
+                            .iter()
+                            .position(|resource| resource.url == *url)
+                            .with_context(|| format!("{:?} could not be recovered", url))?,
+                        None if resources.is_empty() => {
+                            eprintln!("Error: nothing could be recovered from {:?}", input);
+                            std::process::exit(2);
+                        }
+                        None => resources
+                            .iter()
+                            .position(WebResource::is_html)
+                            .unwrap_or_default(),
+                    };
+
+                    WebArchive {
+                        main_resource: resources.remove(index),
+                        subresources: None,
+                        subframe_archives: None,
+                    }
+                }
+            };
+
+            // keep resources whose place in the archive was lost
+            for resource in resources {
+                if webarchive
+                    .get_resource_by_url_with(&resource.url, webarchive::UrlNormalization::exact())
+                    .is_none()
+                {
+                    webarchive
+                        .subresources
+                        .get_or_insert_with(Vec::new)
+                        .push(resource);
+                }
+            }
+
+            for resource in webarchive.iter_resources() {
+                println!(
+                    "Recovered {:?} ({} bytes)",
+                    resource.url,
+                    resource.data.len()
+                );
+            }
+            for url in &recovered.lost {
+                println!("Lost {:?}", url);
+            }
+            if recovered.lost_unnamed > 0 {
+                println!(
+                    "Lost {} resource(s) whose URLs were also lost",
+                    recovered.lost_unnamed
+                );
+            }
+
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            let lost = recovered.lost.len() + recovered.lost_unnamed;
+            println!(
+                "Recovered {} resource(s) ({} bytes), lost {}",
+                webarchive.iter_resources().count(),
+                webarchive.total_size(),
+                lost
+            );
+
+            if lost > 0 {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+
+        Args::Split {
+            input,
+            frame,
+            max_size,
+            output,
+            remaining,
+        } => {
+            let mut webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let frame = match (frame, max_size) {
+                (Some(frame), _) => frame,
+                (None, Some(max_size)) => {
+                    let parts = webarchive
+                        .split(max_size)
+                        .with_context(|| format!("failed to split {:?}", input))?;
+
+                    for (index, part) in parts.iter().enumerate() {
+                        let path = numbered_path(&output, index + 1);
+                        println!(
+                            "Writing part {} of {}, {} resource(s), to {:?}...",
+                            index + 1,
+                            parts.len(),
+                            part.iter_resources().count() - usize::from(index > 0),
+                            path
+                        );
+                        files
+                            .write(&path, part, webarchive::ArchiveFormat::Binary)
+                            .with_context(|| format!("failed to write {:?}", path))?;
+                    }
+
+                    return Ok(());
+                }
+                (None, None) => unreachable!("clap requires --frame or --max-size"),
+            };
+
+            let subframe_archive = webarchive
+                .take_subframe(&frame)
+                .with_context(|| format!("{} not found in {:?}", frame, input))?;
+
+            println!(
+                "Writing frame \"{}\" to {:?}...",
+                subframe_archive.main_resource.url, output
+            );
+            files
+                .write(
+                    &output,
+                    &subframe_archive,
+                    webarchive::ArchiveFormat::Binary,
+                )
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            if let Some(remaining) = remaining {
+                println!("Writing remaining webarchive to {:?}...", remaining);
+                files
+                    .write(&remaining, &webarchive, webarchive::ArchiveFormat::Binary)
+                    .with_context(|| format!("failed to write {:?}", remaining))?;
+            }
+
+            Ok(())
+        }
+
+        #[cfg(feature = "fetch")]
+        Args::VerifyLive {
+            input,
+            format,
+            fetch,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let report = tokio::runtime::Runtime::new()?
+                .block_on(webarchive.verify_live(&fetch.to_options()))
+                .context("failed to check live resources")?;
+
+            match format {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                Format::Text => {
+                    use webarchive::LiveStatus::*;
+
+                    for result in &report.results {
+                        match &result.reason {
+                            Some(reason) => {
+                                println!("{}: {:?} ({})", result.status, result.url, reason)
+                            }
+                            None => println!("{}: {:?}", result.status, result.url),
+                        }
+                    }
+
+                    println!(
+                        "{} unchanged, {} changed, {} gone, {} blocked",
+                        report.count(Unchanged),
+                        report.count(Changed),
+                        report.count(Gone),
+                        report.count(Blocked),
+                    );
+                }
+            }
+
+            Ok(())
+        }
+
+        #[cfg(feature = "fetch")]
+        Args::Create {
+            url,
+            output,
+            url_list,
+            out_dir,
+            report,
+            no_capture_info,
+            #[cfg(feature = "cdp")]
+            renderer,
+            #[cfg(feature = "cdp")]
+            browser_url,
+            #[cfg(feature = "cdp")]
+            wait_selector,
+            fetch,
+        } => {
+            let capturer = Capturer {
+                fetch: webarchive::FetchOptions {
+                    capture_info: !no_capture_info,
+                    ..fetch.to_options()
+                },
+                #[cfg(feature = "cdp")]
+                render: (renderer == Renderer::Chrome).then(|| webarchive::RenderOptions {
+                    browser_url,
+                    wait_selector,
+                    timeout: std::time::Duration::from_secs(fetch.timeout),
+                    capture_info: !no_capture_info,
+                    ..Default::default()
+                }),
+            };
+
+            if let (Some(url_list), Some(out_dir)) = (url_list, out_dir) {
+                let text = std::fs::read_to_string(&url_list)
+                    .with_context(|| format!("failed to read {:?}", url_list))?;
+                let urls = parse_url_list(&text);
+                std::fs::create_dir_all(&out_dir)
+                    .with_context(|| format!("failed to create {:?}", out_dir))?;
+
+                let pages = tokio::runtime::Runtime::new()?.block_on(create_batch(
+                    &files,
+                    &urls,
+                    &out_dir,
+                    &capturer,
+                    fetch.concurrency,
+                ));
+                print_batch_table(&pages);
+
+                if let Some(report) = report {
+                    let mut json = serde_json::to_vec_pretty(&BatchReport { pages: &pages })?;
+                    json.push(b'\n');
+                    std::fs::write(&report, json)
+                        .with_context(|| format!("failed to write {:?}", report))?;
+                }
+
+                let failed = pages.iter().filter(|page| page.error.is_some()).count();
+                anyhow::ensure!(
+                    failed == 0,
+                    "{} of {} page(s) could not be captured",
+                    failed,
+                    pages.len()
+                );
+                return Ok(());
+            }
+
+            let url = url.expect("clap requires a URL without --url-list");
+            let output = output.expect("clap requires --output without --url-list");
+            let (webarchive, report) = tokio::runtime::Runtime::new()?
+                .block_on(capturer.capture(&url))
+                .with_context(|| format!("failed to capture {:?}", url))?;
+            warn_fetch_failures(&report);
+
+            println!(
+                "Writing webarchive of {:?} with {} subresource(s) to {:?}...",
+                url,
+                report.fetched.len(),
+                output
+            );
+            files
+                .write(&output, &webarchive, webarchive::ArchiveFormat::Binary)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            Ok(())
+        }
+
+        #[cfg(feature = "fetch")]
+        Args::Update {
+            input,
+            output,
+            fetch,
+        } => {
+            let (mut webarchive, format) = files
+                .read_with_format(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let report = tokio::runtime::Runtime::new()?
+                .block_on(webarchive.fetch_missing(&fetch.to_options()))
+                .context("failed to fetch missing subresources")?;
+            warn_fetch_failures(&report);
+
+            for url in &report.fetched {
+                println!("Fetched {:?}", url);
+            }
+
+            let output = output.unwrap_or(input);
+            println!(
+                "Writing webarchive with {} new subresource(s) to {:?}...",
+                report.fetched.len(),
+                output
+            );
+            files
+                .write(&output, &webarchive, format)
+                .with_context(|| format!("failed to write {:?}", output))?;
+
+            Ok(())
+        }
+
+        #[cfg(feature = "readability")]
+        Args::Readable {
+            input,
+            output,
+            images,
+        } => {
+            use webarchive::convert::{ImageMode, MarkdownOptions};
+
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+
+            let images = match images {
+                Images::DataUrl => ImageMode::DataUrl,
+                Images::Remote => ImageMode::Remote,
+                Images::Files => {
+                    let output = output
+                        .as_ref()
+                        .context("--images files requires an --output file")?;
+                    let folder = format!(
+                        "{}_files",
+                        output
+                            .file_stem()
+                            .context("Could not get the output file name")?
+                            .to_string_lossy()
+                    );
+
+                    let inside = output
+                        .parent()
+                        .context("Could not get an output directory")?
+                        .join(&folder);
+                    for planned in webarchive.plan_extraction() {
+                        if planned.resource.mime_essence().starts_with("image/") {
+                            save(planned.resource, &inside.join(&planned.path))?;
+                        }
+                    }
+
+                    ImageMode::Files(folder)
+                }
+            };
+
+            let markdown =
+                webarchive::convert::to_markdown(&webarchive, &MarkdownOptions { images })
+                    .context("failed to convert to Markdown")?;
+
+            match output {
+                Some(output) => std::fs::write(&output, markdown)
+                    .with_context(|| format!("failed to write {:?}", output)),
+                None => {
+                    print!("{}", markdown);
+                    Ok(())
+                }
+            }
+        }
+
+        #[cfg(feature = "serve")]
+        Args::Serve {
+            input,
+            address,
+            urls,
+        } => {
+            let webarchive: WebArchive = files
+                .read(&input)
+                .with_context(|| format!("failed to read {:?}", input))?;
+            let service = webarchive::serve::ArchiveService::with_normalization(
+                std::sync::Arc::new(webarchive),
+                urls.into(),
+            );
+
+            tokio::runtime::Runtime::new()?.block_on(async move {
+                let listener = tokio::net::TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("failed to listen on {}", address))?;
+                println!("Serving {:?} at http://{}/", input, listener.local_addr()?);
+
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    let service = hyper_util::service::TowerToHyperService::new(service.clone());
+
+                    tokio::spawn(async move {
+                        if let Err(error) = hyper::server::conn::http1::Builder::new()
+                            .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                            .await
+                        {
+                            eprintln!("warning: connection failed: {}", error);
+                        }
+                    });
+                }
+            })
+        }
+
+        #[cfg(feature = "schemars")]
+        Args::Schema { output } => {
+            // describe what is written, rather than what could be read
+            let generator = schemars::generate::SchemaSettings::draft2020_12()
+                .for_serialize()
+                .into_generator();
+            let schema = match output {
+                SchemaOutput::Inspect => generator.into_root_schema_for::<Inspection>(),
+                SchemaOutput::Stats => generator.into_root_schema_for::<FileStats>(),
+                SchemaOutput::FolderStats => generator.into_root_schema_for::<FolderStats>(),
+                SchemaOutput::Lint => generator.into_root_schema_for::<Vec<LintedFile>>(),
+                SchemaOutput::Manifest => {
+                    generator.into_root_schema_for::<webarchive::ExtractionManifest>()
+                }
+                SchemaOutput::Diff => generator.into_root_schema_for::<webarchive::ArchiveDiff>(),
+                #[cfg(feature = "fetch")]
+                SchemaOutput::CreateReport => generator.into_root_schema_for::<BatchReport>(),
+            };
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_cmd::Command;
+    use assert_fs::prelude::*;
+    use std::path::PathBuf;
+
+    const CROUTON_WEBARCHIVE: &[u8] = include_bytes!("../fixtures/crouton.webarchive");
+    const CROUTON_INDEX_SHTML: &[u8] =
+        include_bytes!("../fixtures/crouton.net/_unnamed_index.shtml");
+    const CROUTON_PNG: &[u8] = include_bytes!("../fixtures/crouton.net/crouton.png");
+    const PSXDATACENTER_WEBARCHIVE: &[u8] = include_bytes!("../fixtures/psxdatacenter.webarchive");
+    #[cfg(feature = "readability")]
+    const ARTICLE_WEBARCHIVE: &[u8] = include_bytes!("../fixtures/article.webarchive");
+
+    /// Serve the crouton fixture over HTTP on a local port, at
+    /// `/captures/crouton.webarchive`, redirected to from
+    /// `/old.webarchive`, and at `/private.webarchive` to the user
+    /// `user` with the password `secret`
+    #[cfg(feature = "fetch")]
+    fn serve_crouton() -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap_or_default();
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line);
+                }
+
+                let path = request[0].split(' ').nth(1).unwrap_or_default();
+                let authorized = request
+                    .iter()
+                    .any(|line| line.eq_ignore_ascii_case("authorization: Basic dXNlcjpzZWNyZXQ="));
+                let (status, headers, body): (_, _, &[u8]) = match path {
+                    "/captures/crouton.webarchive" => ("200 OK", "", CROUTON_WEBARCHIVE),
+                    "/private.webarchive" if authorized => ("200 OK", "", CROUTON_WEBARCHIVE),
+                    "/private.webarchive" => ("401 Unauthorized", "", b""),
+                    "/old.webarchive" => (
+                        "301 Moved Permanently",
+                        "Location: /captures/crouton.webarchive\r\n",
+                        b"",
+                    ),
+                    _ => ("404 Not Found", "", b""),
+                };
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    headers,
+                    body.len()
+                )
+                .and_then(|_| stream.write_all(body));
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    /// Serve a few pages over HTTP on a local port: `/a` and `/b`, both
+    /// titled "Page A", `/docs/c`, with no title, `/d`, with an image
+    /// which is missing, and `/broken`, which fails
+    #[cfg(feature = "fetch")]
+    fn serve_pages() -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&stream);
+                let _ = reader.read_line(&mut request_line);
+                for line in reader.lines() {
+                    if line.unwrap_or_default().is_empty() {
+                        break;
+                    }
+                }
+
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let (status, content_type, body): (_, _, &[u8]) = match path {
+                    "/a" => (
+                        "200 OK",
+                        "text/html",
+                        b"<title>Page A</title><img src=a.png>",
+                    ),
+                    "/b" => ("200 OK", "text/html", b"<title>Page  A</title>"),
+                    "/docs/c" => ("200 OK", "text/html", b"<p>No title"),
+                    "/d" => ("200 OK", "text/html", b"<title>D</title><img src=gone.png>"),
+                    "/a.png" => ("200 OK", "image/png", CROUTON_PNG),
+                    "/broken" => ("500 Internal Server Error", "text/plain", b""),
+                    _ => ("404 Not Found", "text/plain", b""),
+                };
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    content_type,
+                    body.len()
+                )
+                .and_then(|_| stream.write_all(body));
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    #[test]
+    #[cfg(feature = "fetch")]
+    fn create_url_list() {
+        let server = serve_pages();
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let url_list = temp.child("urls.txt");
+        url_list
+            .write_str(&format!(
+                "# pages to keep\n{0}/a\n{0}/broken\n\n  {0}/b\n{0}/docs/c\n{0}/d\n",
+                server
+            ))
+            .unwrap();
+        let out_dir = temp.child("captures");
+        let report = temp.child("report.json");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("create")
+            .arg("--url-list")
+            .arg(url_list.path())
+            .arg("--out-dir")
+            .arg(out_dir.path())
+            .arg("--concurrency")
+            .arg("2")
+            .arg("--report")
+            .arg(report.path())
+            .assert()
+            .failure();
+
+        // the failure doesn't stop the pages after it
+        let names = [
+            "Page-A.webarchive",
+            "Page-A-2.webarchive",
+            "127.0.0.1-docs-c.webarchive",
+            "D.webarchive",
+        ];
+        for (name, path) in names.iter().zip(["/a", "/b", "/docs/c", "/d"]) {
+            let archive: webarchive::WebArchive = webarchive::from_file(out_dir.child(name).path())
+                .unwrap_or_else(|error| panic!("{}: {}", name, error));
+            assert_eq!(archive.main_resource.url, format!("{}{}", server, path));
+        }
+        assert_eq!(std::fs::read_dir(out_dir.path()).unwrap().count(), 4);
+
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let table = output
+            .lines()
+            .skip_while(|line| !line.starts_with("Status"))
+            .map(|line| {
+                line.split_whitespace()
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            table,
+            [
+                "Status URL".to_string(),
+                format!("ok {}/a", server),
+                format!("failed {}/broken", server),
+                format!("ok {}/b", server),
+                format!("ok {}/docs/c", server),
+                format!("ok {}/d", server),
+                "Captured 4".to_string(),
+            ]
+        );
+        assert!(output.ends_with("Captured 4 of 5 page(s)\n"));
+        assert!(output.contains(&format!(
+            "{}, 0 subresource(s), 1 missing\n",
+            out_dir.child("D.webarchive").path().display()
+        )));
+        let errors = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(errors.contains("1 of 5 page(s) could not be captured"));
+
+        let json = std::fs::read(report.path()).unwrap();
+        #[cfg(feature = "schemars")]
+        assert_matches_schema("create-report", &json);
+        let report: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let pages = report["pages"].as_array().unwrap();
+        assert_eq!(pages.len(), 5);
+        assert_eq!(pages[0]["url"], format!("{}/a", server));
+        assert_eq!(pages[0]["subresources"], 1);
+        assert_eq!(
+            pages[0]["path"],
+            out_dir.child("Page-A.webarchive").path().to_str().unwrap()
+        );
+        assert!(pages[1].get("path").is_none());
+        assert!(pages[1]["error"].as_str().unwrap().contains("500"));
+        assert_eq!(pages[4]["failed_subresources"], 1);
+
+        // a single URL still needs --output, and a list --out-dir
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("create")
+            .arg(format!("{}/a", server))
+            .assert()
+            .failure();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("create")
+            .arg("--url-list")
+            .arg(url_list.path())
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    #[cfg(feature = "cdp")]
+    fn create_renderer_chrome() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output = temp.child("page.webarchive");
+
+        // nothing listens on the discard port
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("create")
+            .arg("http://127.0.0.1:9/")
+            .arg("-o")
+            .arg(output.path())
+            .arg("--renderer")
+            .arg("chrome")
+            .arg("--browser-url")
+            .arg("http://127.0.0.1:9")
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("browser error"), "{}", stderr);
+        assert!(!output.path().exists());
+
+        // only chrome renders pages in a browser
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("create")
+            .arg("http://127.0.0.1:9/")
+            .arg("-o")
+            .arg(output.path())
+            .arg("--renderer")
+            .arg("firefox")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    #[cfg(feature = "fetch")]
+    fn url_input() {
+        let server = serve_crouton();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let expected = cmd
+            .arg("inspect")
+            .arg("fixtures/crouton.webarchive")
+            .assert()
+            .success();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("inspect")
+            .arg(format!("{}/old.webarchive", server))
+            .assert()
+            .success()
+            .stdout(expected.get_output().stdout.clone());
+
+        // extracts into the current folder, not the URL's
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private = server.replace("http://", "http://user:secret@");
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.current_dir(temp.path())
+            .arg("extract")
+            .arg(format!("{}/private.webarchive", private))
+            .assert()
+            .success();
+        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
+
+        for (url, status) in [
+            (format!("{}/private.webarchive", server), "401 Unauthorized"),
+            (format!("{}/missing.webarchive", server), "404 Not Found"),
+        ] {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            let assert = cmd.arg("inspect").arg(&url).assert().failure();
+            let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+            assert!(
+                stderr.contains(&format!("the server responded with {}", status)),
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn list_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd.arg("inspect").arg(input_file.path()).assert();
+
+        assert.success().stdout(
+            "WebArchive of \"https://crouton.net/\" (\"text/html\", 134 bytes) titled \"Crouton\": 1 subresource, 0 subframe archives totalling 5316 bytes\n  \
+            - \"https://crouton.net/crouton.png\" (\"image/png\", 5182 bytes)\n",
+        );
+    }
+
+    #[test]
+    fn list_crouton_filtered() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("--max-size")
+            .arg("5k")
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout(
+            "WebArchive of \"https://crouton.net/\" (\"text/html\", 134 bytes) titled \"Crouton\": 1 subresource, 0 subframe archives totalling 5316 bytes\n\
+            1 resource filtered out (5182 bytes)\n",
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--min-size")
+            .arg("1k")
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout("https://crouton.net/crouton.png\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("inspect")
+            .arg("--min-size")
+            .arg("lots")
+            .arg(input_file.path())
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn inspect_json_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("--format")
+            .arg("json")
+            .arg(input_file.path())
+            .assert()
+            .success();
+
+        let summary: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Output was not JSON");
+
+        assert_eq!(summary["url"], "https://crouton.net/");
+        assert_eq!(summary["title"], "Crouton");
+        assert_eq!(summary["total_size"], 5316);
+        assert_eq!(summary["mime_types"]["image/png"]["bytes"], 5182);
+        assert_eq!(summary["captured_at"], "Sat, 30 May 2020 05:11:56 GMT");
+        assert!(summary.get("checksums").is_none());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn inspect_verbose_psxdatacenter() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("--verbose")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .assert()
+            .success();
+        let output = String::from_utf8_lossy(&assert.get_output().stdout);
+        assert!(output.contains(
+            "  - \"http://psxdatacenter.com/images/logo.jpg\" (\"image/jpeg\", 32099 bytes, 700×80)\n"
+        ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("--verbose")
+            .arg("--format")
+            .arg("json")
+            .arg("fixtures/crouton.webarchive")
+            .assert()
+            .success();
+
+        let summary: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Output was not JSON");
+        assert_eq!(
+            summary["images"],
+            serde_json::json!([{"url": "https://crouton.net/crouton.png", "width": 33, "height": 31}])
+        );
+    }
+
+    #[test]
+    fn inspect_table_crouton() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        // not a terminal, so not colored
+        cmd.arg("inspect")
+            .arg("--format")
+            .arg("table")
+            .arg("fixtures/crouton.webarchive")
+            .env_remove("COLUMNS")
+            .assert()
+            .success()
+            .stdout(
+                "URL                              MIME type  Size  Encoding  Response\n\
+                https://crouton.net/             text/html   134  UTF-8     -\n\
+                https://crouton.net/crouton.png  image/png  5182  -         200\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("inspect")
+            .arg("--format")
+            .arg("table")
+            .arg("--no-color")
+            .arg("fixtures/crouton.webarchive")
+            .env("COLUMNS", "60")
+            .assert()
+            .success()
+            .stdout(
+                "URL                      MIME type  Size  Encoding  Response\n\
+                https://crouton.net/     text/html   134  UTF-8     -\n\
+                https://cro…crouton.png  image/png  5182  -         200\n",
+            );
+    }
+
+    #[test]
+    fn table_width() {
+        assert_eq!(super::table_width(Some("72")), Some(72));
+        assert_eq!(super::table_width(Some(" 100\n")), Some(100));
+    }
+
+    #[test]
+    fn inspect_checksums_crouton() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("inspect")
+            .arg("--checksums")
+            .arg("fixtures/crouton.webarchive")
+            .assert()
+            .success()
+            .stdout(
+                "WebArchive of \"https://crouton.net/\" (\"text/html\", 134 bytes) titled \"Crouton\": 1 subresource, 0 subframe archives totalling 5316 bytes \
+                sha256:10908108cc00b9a3848f3777930ea98d04df9ae62ed8067a2255c4ccdd7948f7\n  \
+                - \"https://crouton.net/crouton.png\" (\"image/png\", 5182 bytes) \
+                sha256:16a66c794e3bea9fa627b52d1cf9f085100a71b162abe9e0d83160c3862acfbc\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("--format")
+            .arg("json")
+            .arg("--checksums=md5")
+            .arg("fixtures/crouton.webarchive")
+            .assert()
+            .success();
+
+        let summary: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Output was not JSON");
+
+        assert_eq!(summary["url"], "https://crouton.net/");
+        assert_eq!(summary["checksums"]["algorithm"], "md5");
+        assert_eq!(
+            summary["checksums"]["resources"][1]["url"],
+            "https://crouton.net/crouton.png"
+        );
+        assert_eq!(
+            summary["checksums"]["resources"][1]["digest"],
+            "0f6fa5aa862be778a73454565ccfc596"
+        );
+    }
+
+    #[test]
+    fn inspect_capture_info() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let mut archive: webarchive::WebArchive =
+            webarchive::from_bytes(CROUTON_WEBARCHIVE).unwrap();
+        let info = webarchive::CaptureInfo {
+            captured_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_590_815_516),
+            ..webarchive::CaptureInfo::new("https://crouton.net/", None)
+        };
+        archive.embed_capture_info(&info);
+        let input_file = temp.child("crouton.webarchive");
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd.arg("inspect").arg(input_file.path()).assert().success();
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(output.ends_with(&format!(
+            "\nCaptured Sat, 30 May 2020 05:11:56 GMT by webarchive {}\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("inspect")
+            .arg("--format")
+            .arg("json")
+            .arg(input_file.path())
+            .assert()
+            .success();
+        let summary: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Output was not JSON");
+        let recorded: webarchive::CaptureInfo =
+            serde_json::from_value(summary["capture_info"].clone()).unwrap();
+        assert_eq!(recorded, info);
+    }
+
+    #[test]
+    fn headers_crouton() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("headers")
+            .arg("fixtures/crouton.webarchive")
+            .arg("https://crouton.net/crouton.png")
+            .assert()
+            .success()
+            .stdout(
+                "HTTP/1.1 200 OK\n\
+                Server: Apache/2.4.25 (Debian)\n\
+                Content-Type: image/png\n\
+                Etag: \"143e-4fa5647f1c100\"\n\
+                Last-Modified: Mon, 26 May 2014 23:53:08 GMT\n\
+                Date: Sat, 30 May 2020 05:11:56 GMT\n\
+                Accept-Ranges: bytes\n\
+                Content-Length: 5182\n\
+                Keep-Alive: timeout=5, max=100\n\
+                Connection: Keep-Alive\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("headers")
+            .arg("fixtures/crouton.webarchive")
+            .arg("https://crouton.net/crouton.png")
+            .arg("--format")
+            .arg("json")
+            .assert()
+            .success();
+
+        let response: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Output was not JSON");
+
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["mime_type"], "image/png");
+        assert_eq!(response["expected_content_length"], 5182);
+        assert_eq!(response["headers"]["Etag"], "\"143e-4fa5647f1c100\"");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("headers")
+            .arg("fixtures/crouton.webarchive")
+            .arg("https://crouton.net/crouton.png")
+            .arg("--raw")
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        assert!(
+            stdout.contains("\"$archiver\": String(\n            \"NSKeyedArchiver\",\n        ),")
+        );
+
+        // the main resource was archived without a response
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("headers")
+            .arg("fixtures/crouton.webarchive")
+            .arg("https://crouton.net/")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    fn urls_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd.arg("urls").arg(input_file.path()).assert();
+
+        assert
+            .success()
+            .stdout("https://crouton.net/\nhttps://crouton.net/crouton.png\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("-0")
+            .arg("--subresources-only")
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout("https://crouton.net/crouton.png\0");
+    }
+
+    #[test]
+    fn urls_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--frames-only")
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout(
+            "http://psxdatacenter.com/ntsc-j_list.html\n\
+            http://psxdatacenter.com/banner.html\n\
+            http://psxdatacenter.com/nav.html\n\
+            http://psxdatacenter.com/jbanner.html\n\
+            http://psxdatacenter.com/jlist.html\n",
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--mime")
+            .arg("image/gif")
+            .arg(input_file.path())
+            .assert();
+
+        let gifs = [
+            "news1", "inf1", "emul1", "dl1", "comm1", "cont1", "err1", "lnk1", "ntscu1", "pal1",
+            "ntscj1",
+        ];
+        assert.success().stdout(
+            gifs.iter()
+                .map(|name| format!("http://psxdatacenter.com/buttons/{}.gif\n", name))
+                .collect::<String>(),
+        );
+    }
+
+    #[test]
+    fn urls_matching() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("urls")
+            .arg("--url-glob")
+            .arg("*/buttons/n*.gif")
+            .arg("--url-regex")
+            .arg(r"/j\w+\.html$")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .assert()
+            .success()
+            .stdout(
+                "http://psxdatacenter.com/buttons/news1.gif\n\
+                http://psxdatacenter.com/buttons/ntscu1.gif\n\
+                http://psxdatacenter.com/buttons/ntscj1.gif\n\
+                http://psxdatacenter.com/jbanner.html\n\
+                http://psxdatacenter.com/jlist.html\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("urls")
+            .arg("--url-regex")
+            .arg("(unclosed")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn urls_filter_expression() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("urls")
+            .arg("--filter")
+            .arg(r"mime~^image/ and size>30k and url!~texgrey or frame and size>1M")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .assert()
+            .success()
+            .stdout(
+                "http://psxdatacenter.com/images/logo.jpg\n\
+                http://psxdatacenter.com/jlist.html\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--filter")
+            .arg("mime=image/gif and colour=red")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(
+            stderr.contains("invalid filter at position 19: unknown field `colour`"),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn remove_filter_expression() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output = temp.child("removed.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("--filter")
+            .arg("mime=image/gif and not url~news")
+            .arg("-o")
+            .arg(output.path())
+            .assert()
+            .success();
+
+        let archive: webarchive::WebArchive = webarchive::from_file(output.path()).unwrap();
+        let gifs: Vec<String> = archive
+            .iter_resources()
+            .filter(|resource| resource.mime_type == "image/gif")
+            .map(|resource| resource.url.clone())
+            .collect();
+        assert_eq!(gifs, ["http://psxdatacenter.com/buttons/news1.gif"]);
+    }
+
+    #[test]
+    fn urls_several_files() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let crouton_file = temp.child("crouton.webarchive");
+        crouton_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let psxdatacenter_file = temp.child("psxdatacenter.webarchive");
+        psxdatacenter_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--mime")
+            .arg("text/html")
+            .arg(crouton_file.path())
+            .arg(psxdatacenter_file.path())
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[0],
+            format!("{}:https://crouton.net/", crouton_file.path().display())
+        );
+        assert_eq!(
+            lines[5],
+            format!(
+                "{}:http://psxdatacenter.com/jlist.html",
+                psxdatacenter_file.path().display()
+            )
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("urls")
+            .arg("--frames-only")
+            .arg("--no-filename")
+            .arg(crouton_file.path())
+            .arg(crouton_file.path())
+            .assert();
+
+        assert
+            .success()
+            .stdout("https://crouton.net/\nhttps://crouton.net/\n");
+    }
+
+    #[test]
+    fn extract_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd.arg("extract").arg(input_file.path()).assert();
+
+        assert.success().stdout(format!(
+            "Saving main resource...\n\
+            Writing file \"{}/crouton.net/_unnamed_index.shtml\"...\n\
+            Saving subresources...\n\
+            Writing file \"{}/crouton.net/crouton.png\"...\n\
+            Writing manifest \"{}/webarchive-manifest.json\"...\n",
+            temp.path().display(),
+            temp.path().display(),
+            temp.path().display()
+        ));
+
+        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
+        temp.child("crouton.net/_unnamed_index.shtml")
+            .assert(CROUTON_INDEX_SHTML);
+    }
+
+    #[test]
+    fn extract_crouton_main_as_index() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("extract")
+            .arg(input_file.path())
+            .arg("--main-as-index")
+            .assert();
+
+        assert.success().stdout(format!(
+            "Saving main resource...\n\
+            Writing file \"{}/crouton.net/index.html\"...\n\
+            Saving subresources...\n\
+            Writing file \"{}/crouton.net/crouton.png\"...\n\
+            Writing manifest \"{}/webarchive-manifest.json\"...\n",
+            temp.path().display(),
+            temp.path().display(),
+            temp.path().display()
+        ));
+
+        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
+        temp.child("crouton.net/index.html")
+            .assert(CROUTON_INDEX_SHTML);
+        assert!(!temp.child("crouton.net/_unnamed_index.shtml").exists());
+    }
+
+    #[test]
+    fn extract_wget_layout() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("--layout")
+            .arg("wget")
+            .arg("--convert-links")
+            .assert()
+            .success();
+
+        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
+        temp.child("crouton.net/index.html")
             .assert(CROUTON_INDEX_SHTML);
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input_file.path())
+            .arg(temp.path())
+            .arg("--layout")
+            .arg("wget")
+            .arg("--convert-links")
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn extract_main_as_index_links() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let html = |url: &str, data: &str| webarchive::WebResource {
+            url: url.to_string(),
+            data: data.as_bytes().to_vec(),
+            mime_type: "text/html".to_string(),
+            text_encoding_name: Some("UTF-8".to_string()),
+            frame_name: None,
+            response: None,
+        };
+        let archive = webarchive::WebArchive {
+            main_resource: html(
+                "https://example.com/",
+                "<link rel=\"prefetch\" href=\"index.html\"><iframe src=\"/nav/\"></iframe>",
+            ),
+            subresources: Some(vec![html("https://example.com/index.html", "old")]),
+            subframe_archives: Some(vec![webarchive::WebArchive {
+                main_resource: html(
+                    "https://example.com/nav/",
+                    "<link rel=\"prefetch\" href=\"/\">",
+                ),
+                subresources: None,
+                subframe_archives: None,
+            }]),
+        };
+        let input_file = temp.child("site.webarchive");
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let output = temp.child("site");
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output.path())
+            .arg("--main-as-index")
+            .arg("--convert-links")
+            .assert()
+            .success();
+
+        // the stored index.html is numbered, and links follow each file
+        let site = output.child("example.com");
+        site.child("index.html").assert(
+            "<link rel=\"prefetch\" href=\"index-2.html\"><iframe src=\"nav/index.html\"></iframe>",
+        );
+        site.child("index-2.html").assert("old");
+        site.child("nav/index.html")
+            .assert("<link rel=\"prefetch\" href=\"../index.html\">");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input_file.path())
+            .arg(output.path())
+            .arg("--main-as-index")
+            .arg("--convert-links")
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn verify_extract_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg("--convert-links")
+            .arg(input_file.path())
+            .assert()
+            .success();
+
+        let verify = || {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            cmd.arg("verify-extract")
+                .arg("--convert-links")
+                .arg(input_file.path())
+                .arg(temp.path())
+                .assert()
+        };
+
+        verify().success().stdout("All 2 file(s) match\n");
+
+        let mut png = CROUTON_PNG.to_vec();
+        png[100] ^= 0xff;
+        temp.child("crouton.net/crouton.png")
+            .write_binary(&png)
+            .unwrap();
+        temp.child("crouton.net/notes.txt")
+            .write_str("not from the archive")
+            .unwrap();
+
+        let assert = verify().failure().stdout(
+            "Mismatched \"crouton.net/crouton.png\" (\"https://crouton.net/crouton.png\", 5182 bytes, expected 5182)\n\
+            Extra \"crouton.net/notes.txt\"\n",
+        );
+        assert!(String::from_utf8_lossy(&assert.get_output().stderr)
+            .contains("2 difference(s) between"));
+
+        std::fs::remove_file(temp.child("crouton.net/_unnamed_index.shtml").path()).unwrap();
+        let assert = verify().failure();
+        assert!(
+            String::from_utf8_lossy(&assert.get_output().stdout).starts_with(
+                "Missing \"crouton.net/_unnamed_index.shtml\" (\"https://crouton.net/\")\n"
+            )
+        );
+    }
+
+    #[test]
+    fn extract_crouton_filtered() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("extract")
+            .arg("--min-size")
+            .arg("1k")
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout(format!(
+            "Saving subresources...\n\
+            Writing file \"{}/crouton.net/crouton.png\"...\n\
+            Writing manifest \"{}/webarchive-manifest.json\"...\n",
+            temp.path().display(),
+            temp.path().display()
+        ));
+
+        temp.child("crouton.net/crouton.png").assert(CROUTON_PNG);
+        assert!(!temp.child("crouton.net/_unnamed_index.shtml").exists());
+    }
+
+    #[test]
+    fn graph_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd.arg("graph").arg(input_file.path()).assert();
+
+        assert.success().stdout(
+            "digraph webarchive {\n  \
+            rankdir=LR;\n  \
+            node [shape=box];\n  \
+            n0 [label=\"https://crouton.net/\\ntext/html, 134 bytes\"];\n  \
+            n1 [label=\"https://crouton.net/crouton.png\\nimage/png, 5182 bytes\"];\n  \
+            n0 -> n1 [label=\"image\"];\n\
+            }\n",
+        );
+    }
+
+    #[test]
+    fn sitemap_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("sitemap")
+            .arg(input_file.path())
+            .arg(input_file.path())
+            .assert();
+
+        assert.success().stdout(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+            <url>\n    \
+            <loc>https://crouton.net/</loc>\n    \
+            <lastmod>2020-05-30T05:11:56+00:00</lastmod>\n  \
+            </url>\n\
+            </urlset>\n",
+        );
+    }
+
+    #[test]
+    fn index_collection() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let archives = temp.child("archives");
+        archives
+            .child("crouton.webarchive")
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        archives
+            .child("psxdatacenter.webarchive")
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        archives
+            .child("truncated.webarchive")
+            .write_binary(include_bytes!("../fixtures/truncated.webarchive"))
+            .expect("Couldn't write temporary file");
+        archives
+            .child("notes.txt")
+            .write_str("not an archive")
+            .expect("Couldn't write temporary file");
+
+        let output_file = temp.child("index.html");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("index")
+            .arg(archives.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--extract-thumbnails")
+            .assert()
+            .success();
+
+        temp.child("thumbnails/crouton.png").assert(CROUTON_PNG);
+        assert!(temp.child("thumbnails/psxdatacenter.jpg").path().exists());
+
+        let html = std::fs::read_to_string(output_file.path()).unwrap();
+        assert!(html.contains("<p>3 archive(s), of which 1 could not be read</p>"));
+        assert!(html.contains(
+            "<a class=\"thumbnail\" href=\"archives/crouton.webarchive\"><img src=\"thumbnails/crouton.png\""
+        ));
+        assert!(html.contains("<h2><a href=\"archives/psxdatacenter.webarchive\">PlayStation DataCenter - NTSC-J List</a></h2>"));
+        assert!(html.contains("<h2><a href=\"archives/truncated.webarchive\">truncated.webarchive</a></h2>\n<p class=\"error\">"));
+        assert!(!html.contains("notes.txt"));
+
+        // without --extract-thumbnails, they are embedded
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("index")
+            .arg(archives.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success();
+
+        let html = std::fs::read_to_string(output_file.path()).unwrap();
+        assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGgo"));
+    }
+
+    fn images_archive(html: &str, image_urls: &[&str]) -> webarchive::WebArchive {
+        let resource = |url: &str, mime_type: &str, data: Vec<u8>| webarchive::WebResource {
+            url: url.to_string(),
+            data,
+            mime_type: mime_type.to_string(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        };
+
+        let mut page = resource("https://example.com/", "text/html", html.into());
+        page.text_encoding_name = Some("UTF-8".to_string());
+
+        webarchive::WebArchive {
+            main_resource: page,
+            subresources: Some(
+                image_urls
+                    .iter()
+                    .map(|url| resource(url, "image/png", vec![7; 4096]))
+                    .collect(),
+            ),
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn links_by_origin() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let first_file = temp.child("first.webarchive");
+        let first = images_archive(
+            concat!(
+                "<a href=\"/about\">About</a>\n",
+                "<a href=\"http://example.com/about\">About, insecurely</a>\n",
+                "<a href=\"https://example.com:443/contact\">Contact</a>\n",
+                "<a href=\"https://example.com:8443/admin\">Admin</a>\n",
+                "<a href=\"https://other.example/\">Elsewhere</a>\n",
+                "<a href=\"mailto:hello@example.com\">Email</a>\n",
+            ),
+            &[],
+        );
+        webarchive::to_file_binary(first_file.path(), &first).unwrap();
+
+        let second_file = temp.child("second.webarchive");
+        let second = images_archive(
+            "<a href=\"https://other.example/\">Elsewhere again</a><a href=about>About</a>",
+            &[],
+        );
+        webarchive::to_file_binary(second_file.path(), &second).unwrap();
+
+        let links = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            let assert = cmd
+                .arg("links")
+                .arg(first_file.path())
+                .args(args)
+                .assert()
+                .success();
+            String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+        };
+
+        assert_eq!(
+            links(&["--internal-only", "--with-text"]),
+            "https://example.com/about\tAbout\n\
+            https://example.com/contact\tContact\n"
+        );
+        assert_eq!(
+            links(&["--external-only"]),
+            "http://example.com/about\n\
+            https://example.com:8443/admin\n\
+            https://other.example/\n\
+            mailto:hello@example.com\n"
+        );
+
+        let second_path = second_file.path().to_str().unwrap();
+        assert_eq!(
+            links(&[second_path, "--count"]),
+            "2\thttps://example.com/about\n\
+            1\thttp://example.com/about\n\
+            1\thttps://example.com/contact\n\
+            1\thttps://example.com:8443/admin\n\
+            2\thttps://other.example/\n\
+            1\tmailto:hello@example.com\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&links(&[second_path, "--count", "--format", "json"])).unwrap();
+        assert_eq!(
+            json[4],
+            serde_json::json!({
+                "count": 2,
+                "url": "https://other.example/",
+                "kind": "web",
+                "text": "Elsewhere",
+                "rel": [],
+                "internal": false,
+            })
+        );
+        assert_eq!(json[5]["kind"], "mailto");
+    }
+
+    #[test]
+    fn extract_crouton_preserve_mtime() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("--preserve-mtime")
+            .assert()
+            .success();
+
+        let modified = |path: &str| {
+            std::fs::metadata(temp.child(path).path())
+                .and_then(|metadata| metadata.modified())
+                .unwrap()
+        };
+
+        // Last-Modified: Mon, 26 May 2014 23:53:08 GMT
+        assert_eq!(
+            modified("crouton.net/crouton.png"),
+            UNIX_EPOCH + Duration::from_secs(1_401_148_388)
+        );
+
+        // the main resource has no archived response
+        assert!(
+            modified("crouton.net/_unnamed_index.shtml")
+                > UNIX_EPOCH + Duration::from_secs(1_590_815_516)
+        );
+    }
+
+    #[test]
+    fn extract_convert_links() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("page.webarchive");
+        let archive = images_archive(
+            r#"<img src="https://cdn.example/a b.png"><img src="/img/b.png?v=2#x"><img src="https://elsewhere.example/c.png">"#,
+            &[
+                "https://cdn.example/a%20b.png",
+                "https://example.com/img/b.png?v=2",
+            ],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("--convert-links")
+            .assert()
+            .success();
+
+        temp.child("example.com/_unnamed_index.shtml").assert(
+            r#"<img src="../cdn.example/a%2520b.png"><img src="img/b.png_v=2#x"><img src="https://elsewhere.example/c.png">"#,
+        );
+        temp.child("example.com/img/b.png_v=2")
+            .assert(&[7; 4096][..]);
+    }
+
+    /// Extract three identical images with `--dedupe-files mode`
+    #[test]
+    fn extract_host_only() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg("fixtures/host-only.webarchive")
+            .arg("-o")
+            .arg(temp.path())
+            .assert()
+            .success();
+
+        let site = temp.child("example.com");
+        site.child("_unnamed_index.shtml").assert(
+            "<!DOCTYPE html>\n<link rel=\"stylesheet\" href=\"/style.css\">\n<p><a href=\"/docs\">Docs</a></p>\n",
+        );
+        site.child("style.css")
+            .assert("body { font-family: sans-serif }\n");
+        site.child("docs/_unnamed_index.shtml")
+            .assert("<p><a href=\"/docs/intro.html\">Introduction</a></p>\n");
+        site.child("docs/intro.html").assert("<p>Hello</p>\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg("fixtures/host-only.webarchive")
+            .arg(temp.path())
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn extract_transcode_to_utf8() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("cafe.webarchive");
+        let mut archive: webarchive::WebArchive =
+            webarchive::from_file("fixtures/windows-1252.webarchive").unwrap();
+        let main = &mut archive.main_resource;
+        main.text_encoding_name = Some("windows-1252".to_string());
+        main.data
+            .splice(12..12, *b"<meta charset=\"windows-1252\">");
+        let text =
+            |url: &str, mime_type: &str, encoding: &str, data: &[u8]| webarchive::WebResource {
+                url: url.to_string(),
+                data: data.to_vec(),
+                mime_type: mime_type.to_string(),
+                text_encoding_name: Some(encoding.to_string()),
+                frame_name: None,
+                response: None,
+            };
+        archive.subresources = Some(vec![
+            text(
+                "http://example.com/style.css",
+                "text/css",
+                "windows-1252",
+                b"@charset \"windows-1252\";\np::after { content: \" \xa7\" }\n",
+            ),
+            text(
+                "http://example.com/broken.txt",
+                "text/plain",
+                "shift_jis",
+                b"ok \x82",
+            ),
+        ]);
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("extract")
+            .arg(input_file.path())
+            .arg("--transcode-to-utf8")
+            .assert()
+            .success()
+            .stderr(
+                "warning: \"http://example.com/broken.txt\" is not valid Shift_JIS, so writing it unchanged\n",
+            );
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(output.contains(
+            "Transcoding \"http://example.com/cafe.html\" from windows-1252 to UTF-8..."
+        ));
+
+        let site = temp.child("example.com");
+        let html = std::fs::read(site.child("cafe.html").path()).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<html><head><meta charset=\"utf-8\"><title>Café crème</title>"));
+        assert!(html.contains("“Déjà vu,” said the naïve maître d’hôtel — "));
+        assert!(html.contains("Coût : 12 €, pourboire compris. À bientôt !"));
+        site.child("style.css")
+            .assert("@charset \"UTF-8\";\np::after { content: \" §\" }\n");
+        site.child("broken.txt").assert(&b"ok \x82"[..]);
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input_file.path())
+            .arg(temp.path())
+            .arg("--transcode-to-utf8")
+            .assert()
+            .success();
+
+        // malformed resources are only transcoded if asked
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("--transcode-to-utf8")
+            .arg("--lossy")
+            .assert()
+            .success()
+            .stderr("");
+        site.child("broken.txt").assert("ok \u{FFFD}");
+    }
+
+    #[test]
+    fn extract_manifest() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input = "fixtures/psxdatacenter.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(temp.path())
+            .assert()
+            .success();
+
+        let manifest = webarchive::ExtractionManifest::read_from(temp.path()).unwrap();
+        assert_eq!(manifest.files.len(), 50);
+        for file in &manifest.files {
+            let data = std::fs::read(temp.path().join(&file.path)).unwrap();
+            assert_eq!(data.len() as u64, file.size, "{}", file.path);
+            assert_eq!(
+                webarchive::DigestAlgorithm::Sha256.hex_digest(&data),
+                file.sha256
+            );
+        }
+
+        let main = &manifest.files[0];
+        assert_eq!(main.url, "http://psxdatacenter.com/ntsc-j_list.html");
+        assert_eq!(main.path, "psxdatacenter.com/ntsc-j_list.html");
+        assert_eq!(main.mime_type, "text/html");
+        assert_eq!(main.text_encoding_name.as_deref(), Some("UTF-8"));
+        assert!(main.frame.is_empty());
+
+        let logo = manifest
+            .get("http://psxdatacenter.com/images/logo.jpg")
+            .unwrap();
+        assert_eq!(logo.path, "psxdatacenter.com/images/logo.jpg");
+        assert_eq!(logo.mime_type, "image/jpeg");
+        assert_eq!(logo.size, 32099);
+        assert_eq!(logo.frame, [0]);
+        assert!(logo.renamed.is_empty());
+
+        let nav = manifest.get("http://psxdatacenter.com/nav.html").unwrap();
+        assert_eq!(nav.frame, [1]);
+
+        // the manifest isn't an extra file
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input)
+            .arg(temp.path())
+            .assert()
+            .success();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(temp.path())
+            .arg("--no-manifest")
+            .assert()
+            .success();
+        assert!(!temp
+            .child(webarchive::ExtractionManifest::FILE_NAME)
+            .exists());
+    }
+
+    #[test]
+    fn repack() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let folder = temp.child("extracted");
+        let input = "fixtures/crouton.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(folder.path())
+            .arg("--with-responses")
+            .assert()
+            .success();
+
+        let page = folder.child("crouton.net/_unnamed_index.shtml");
+        let html = std::fs::read_to_string(page.path()).unwrap();
+        page.write_str(&html.replace("Crouton", "Edited crouton"))
+            .unwrap();
+        folder.child("notes.txt").write_str("Some notes").unwrap();
+
+        let output = temp.child("repacked.webarchive");
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("repack")
+            .arg(folder.path())
+            .arg("-o")
+            .arg(output.path())
+            .assert()
+            .success()
+            .stdout(format!(
+                "Writing webarchive of \"https://crouton.net/\" with 2 resource(s) to {:?}...\n",
+                output.path()
+            ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("cat")
+            .arg(output.path())
+            .arg("https://crouton.net/")
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("<title> Edited crouton"));
+
+        // everything else is as it was
+        let original: webarchive::WebArchive = webarchive::from_file(input).unwrap();
+        let repacked: webarchive::WebArchive = webarchive::from_file(output.path()).unwrap();
+        assert_eq!(repacked.subresources, original.subresources);
+        assert_eq!(
+            repacked.main_resource.mime_type,
+            original.main_resource.mime_type
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("repack")
+            .arg(folder.path())
+            .arg("-o")
+            .arg(output.path())
+            .arg("--include-new")
+            .arg("--base-url")
+            .arg("https://crouton.net/")
+            .assert()
+            .success();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("cat")
+            .arg(output.path())
+            .arg("https://crouton.net/notes.txt")
+            .assert()
+            .success()
+            .stdout("Some notes");
+    }
+
+    #[test]
+    fn extract_by_frame() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input = "fixtures/psxdatacenter.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(temp.path())
+            .arg("--by-frame")
+            .arg("--convert-links")
+            .assert()
+            .success();
+
+        let mut folders: Vec<String> = std::fs::read_dir(temp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().unwrap().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        folders.sort();
+        assert_eq!(
+            folders,
+            [
+                "frame-0-banner.html",
+                "frame-1-nav.html",
+                "frame-2-jbanner.html",
+                "frame-3-jlist.html",
+                "main",
+            ]
+        );
+
+        let main =
+            std::fs::read_to_string(temp.child("main/psxdatacenter.com/ntsc-j_list.html").path())
+                .unwrap();
+        assert!(main.contains("src=\"../../frame-1-nav.html/psxdatacenter.com/nav.html\""));
+        assert!(temp
+            .child("frame-1-nav.html/psxdatacenter.com/buttons/news1.gif")
+            .path()
+            .exists());
+
+        let manifest = webarchive::ExtractionManifest::read_from(temp.path()).unwrap();
+        let logo = manifest
+            .get("http://psxdatacenter.com/images/logo.jpg")
+            .unwrap();
+        assert_eq!(
+            logo.path,
+            "frame-0-banner.html/psxdatacenter.com/images/logo.jpg"
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input)
+            .arg(temp.path())
+            .arg("--by-frame")
+            .arg("--convert-links")
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn extract_with_responses() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input = "fixtures/crouton.webarchive";
+        let archive: webarchive::WebArchive = webarchive::from_file(input).unwrap();
+        let embedded = archive.subresources.as_ref().unwrap()[0]
+            .response
+            .clone()
+            .unwrap();
+        assert_eq!(embedded.len(), 1825);
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(temp.path())
+            .arg("--with-responses")
+            .assert()
+            .success();
+
+        let site = temp.child("crouton.net");
+        assert_eq!(
+            std::fs::read(site.child("crouton.png.response.plist").path()).unwrap(),
+            embedded
+        );
+        // the page has no response to write
+        assert!(!site.child("_unnamed_index.shtml.response.plist").exists());
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input)
+            .arg(temp.path())
+            .arg("--with-responses")
+            .assert()
+            .success();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(temp.path())
+            .arg("--with-responses")
+            .arg("--decode-responses")
+            .assert()
+            .success();
+
+        let site = temp.child("crouton.net");
+        assert!(!site.child("crouton.png.response.plist").exists());
+        let json = std::fs::read_to_string(site.child("crouton.png.response.json").path()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["headers"]["Content-Length"], "5182");
+    }
+
+    #[test]
+    fn extract_response_filenames() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("page.webarchive");
+        let mut archive = images_archive(
+            "<img src=download/8f3a2c>",
+            &["https://example.com/download/8f3a2c"],
+        );
+        let mut headers = webarchive::Headers::default();
+        headers.append(
+            "Content-Disposition",
+            "attachment; filename*=UTF-8''r%C3%A9sum%C3%A9%20chart.png",
+        );
+        let response = webarchive::ResourceResponse::new(None, Some(200), headers, None);
+        archive.subresources.as_mut().unwrap()[0].response = Some(response.to_bytes().unwrap());
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("extract")
+            .arg(input_file.path())
+            .arg("--use-response-filenames")
+            .arg("--convert-links")
+            .assert()
+            .success();
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(output.contains(
+            "Naming \"https://example.com/download/8f3a2c\" from its Content-Disposition header..."
+        ));
+
+        let page = temp.child("example.com");
+        page.child("download/résumé chart.png")
+            .assert(&[7; 4096][..]);
+        assert!(!page.child("download/8f3a2c").exists());
+        page.child("_unnamed_index.shtml")
+            .assert(r#"<img src="download/résumé%20chart.png">"#);
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("verify-extract")
+            .arg(input_file.path())
+            .arg(temp.path())
+            .arg("--use-response-filenames")
+            .arg("--convert-links")
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn extract_unknown_schemes() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("page.webarchive");
+        let archive = images_archive(
+            "<img src=cid:logo@example.com><img src=logo.png>",
+            &["cid:logo@example.com", "https://example.com/logo.png"],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd.arg("extract").arg(input_file.path()).assert().success();
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(output.contains(concat!(
+            "Named 1 resource(s) with unknown URL schemes after their digests:\n",
+            "  \"cid:logo@example.com\" as \"_scheme/b65939b5a59495c7.png\"\n",
+        )));
+
+        temp.child("_scheme/b65939b5a59495c7.png")
+            .assert(&[7; 4096][..]);
+        temp.child("example.com/logo.png").assert(&[7; 4096][..]);
+
+        let manifest = webarchive::ExtractionManifest::read_from(temp.path()).unwrap();
+        assert_eq!(
+            manifest.get("cid:logo@example.com").unwrap().renamed,
+            [webarchive::RenameReason::UnknownScheme]
+        );
+
+        // an archive without any leaves the summary out
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("extract")
+            .arg("fixtures/crouton.webarchive")
+            .arg("-o")
+            .arg(temp.child("crouton").path())
+            .assert()
+            .success();
+        let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(!output.contains("unknown URL schemes"));
+    }
+
+    fn extract_duplicates(mode: &str) -> assert_fs::TempDir {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("page.webarchive");
+        let archive = images_archive(
+            "<img src=a.png><img src=img/b.png><img src=img/deep/c.png>",
+            &[
+                "https://example.com/a.png",
+                "https://example.com/img/b.png",
+                "https://example.com/img/deep/c.png",
+            ],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("extract")
+            .arg(input_file.path())
+            .arg("--dedupe-files")
+            .arg(mode)
+            .assert()
+            .success();
+
+        for path in ["a.png", "img/b.png", "img/deep/c.png"] {
+            temp.child("example.com").child(path).assert(&[7; 4096][..]);
+        }
+
+        temp
+    }
+
+    #[test]
+    fn extract_dedupe_copy() {
+        let temp = extract_duplicates("copy");
+
+        let metadata =
+            std::fs::symlink_metadata(temp.child("example.com/img/b.png").path()).unwrap();
+        assert!(metadata.is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_dedupe_hardlink() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = extract_duplicates("hardlink");
+
+        let inode = |path: &str| {
+            std::fs::metadata(temp.child("example.com").child(path).path())
+                .unwrap()
+                .ino()
+        };
+        assert_eq!(inode("a.png"), inode("img/b.png"));
+        assert_eq!(inode("a.png"), inode("img/deep/c.png"));
+        assert_ne!(inode("a.png"), inode("_unnamed_index.shtml"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_dedupe_symlink() {
+        let temp = extract_duplicates("symlink");
+
+        let target = |path: &str| {
+            std::fs::read_link(temp.child("example.com").child(path).path())
+                .ok()
+                .map(|target| target.to_string_lossy().into_owned())
+        };
+        assert_eq!(target("a.png"), None);
+        assert_eq!(target("img/b.png").as_deref(), Some("../a.png"));
+        assert_eq!(target("img/deep/c.png").as_deref(), Some("../../a.png"));
+    }
+
+    /// Where links can't be made, files are copied instead
+    #[cfg(not(unix))]
+    #[test]
+    fn extract_dedupe_fallback() {
+        for mode in ["hardlink", "symlink"] {
+            extract_duplicates(mode);
+        }
+    }
+
+    #[test]
+    fn open_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let temp_dir = |stdout: &str| -> PathBuf {
+            let line = stdout
+                .lines()
+                .find_map(|line| line.strip_prefix("Extracted to "))
+                .expect("No temporary folder was printed");
+            PathBuf::from(line.trim_matches('"'))
+        };
+
+        // the launcher is run with the main document, then the folder is removed
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("open")
+            .arg(input_file.path())
+            .arg("--browser")
+            .arg("echo launching")
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        let dir = temp_dir(&stdout);
+        let index = dir.join("crouton.net/_unnamed_index.shtml");
+        assert!(stdout.contains(&format!("launching {}\n", index.display())));
+        assert!(!dir.exists());
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("open")
+            .arg(input_file.path())
+            .arg("--browser")
+            .arg("true")
+            .arg("--keep")
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        let dir = temp_dir(&stdout);
+        assert_eq!(
+            std::fs::read(dir.join("crouton.net/crouton.png")).unwrap(),
+            CROUTON_PNG
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("open")
+            .arg(input_file.path())
+            .arg("--browser")
+            .arg("false")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn lenient_string_data() {
+        let input = "fixtures/string-data.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd.arg("inspect").arg(input).assert().failure();
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("expected data"));
+
+        // fixed, with a warning, and written back as data
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output = temp.child("fixed.webarchive");
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("--lenient")
+            .arg("fix-mime")
+            .arg(input)
+            .arg("-o")
+            .arg(output.path())
+            .assert()
+            .success()
+            .stderr(
+                "warning: \"fixtures/string-data.webarchive\": \"https://example.com/\": WebResourceData is a <string>, not <data>\n\
+                warning: \"fixtures/string-data.webarchive\": \"https://example.com/frame.html\": WebResourceData is a <string>, not <data>\n",
+            );
+
+        let fixed: webarchive::WebArchive = webarchive::from_file(output.path()).unwrap();
+        assert_eq!(
+            fixed.main_resource.data,
+            "<h1>Héllo</h1>\n<iframe src=\"frame.html\"></iframe>".as_bytes()
+        );
+    }
+
+    #[test]
+    fn head() {
+        let input = "fixtures/crouton.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("head")
+            .arg(input)
+            .arg("https://crouton.net/crouton.png")
+            .arg("--bytes")
+            .arg("40")
+            .assert()
+            .success()
+            .stdout(
+                "00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+                00000010  00 00 00 21 00 00 00 1f  08 06 00 00 00 69 94 a4  |...!.........i..|\n\
+                00000020  b2 00 00 0a 41 69 43 43                           |....AiCC|\n\
+                00000028\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("head")
+            .arg(input)
+            .arg("https://crouton.net/")
+            .arg("--bytes")
+            .arg("60")
+            .assert()
+            .success()
+            .stdout("<html><head><title> Crouton\n</title>\n</head><body bgcolor=\"w…\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("head")
+            .arg(input)
+            .arg("https://crouton.net/")
+            .arg("--lines")
+            .arg("2")
+            .assert()
+            .success()
+            .stdout("<html><head><title> Crouton\n</title>\n…\n");
+
+        // short enough to print whole
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("head")
+            .arg(input)
+            .arg("https://crouton.net/")
+            .assert()
+            .success();
+        assert!(assert.get_output().stdout.ends_with(b"</body></html>"));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("head")
+            .arg(input)
+            .arg("https://crouton.net/")
+            .arg("--hex")
+            .arg("--bytes")
+            .arg("6")
+            .assert()
+            .success()
+            .stdout(
+                "00000000  3c 68 74 6d 6c 3e                                 |<html>|\n\
+                00000006\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("head")
+            .arg(input)
+            .arg("https://crouton.net/crouton.png")
+            .arg("--lines")
+            .arg("2")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn strict_apple() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("--strict-apple")
+            .arg("inspect")
+            .arg("fixtures/crouton.webarchive")
+            .assert()
+            .success();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("--strict-apple")
+            .arg("inspect")
+            .arg("fixtures/nonstandard-keys.webarchive")
+            .assert()
+            .failure();
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Safari would not write this archive"));
+        assert!(stderr.contains("unknown key \"WebResourceMimeType\""));
+    }
+
+    #[test]
+    fn image_only() {
+        let input = "fixtures/image-only.webarchive";
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("inspect")
+            .arg(input)
+            .assert()
+            .success()
+            .stdout("WebArchive of \"https://crouton.net/crouton.png\" (\"image/png\", 5182 bytes): 0 subresources, 0 subframe archives totalling 5182 bytes\n")
+            .stderr("");
+
+        // the image itself is opened, rather than a page
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("open")
+            .arg(input)
+            .arg("--browser")
+            .arg("echo launching")
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let launched = stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("launching "))
+            .expect("The browser was not launched");
+        assert!(launched.ends_with("/crouton.net/crouton.png"));
+
+        // it is its own thumbnail
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("archives/image-only.webarchive")
+            .write_binary(&std::fs::read(input).unwrap())
+            .unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("index")
+            .arg(temp.child("archives").path())
+            .arg("-o")
+            .arg(temp.child("index.html").path())
+            .assert()
+            .success();
+        let html = std::fs::read_to_string(temp.child("index.html").path()).unwrap();
+        assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn add_and_cat_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let css_file = temp.child("extra.css");
+        css_file
+            .write_str("body { font-family: \"Crouton Sans\"; }\n")
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("add")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("https://crouton.net/extra.css")
+            .arg("--file")
+            .arg(css_file.path())
+            .assert()
+            .success()
+            .stdout("Added \"https://crouton.net/extra.css\" (38 bytes)\n");
+
+        let archive = std::fs::read(input_file.path()).unwrap();
+        assert!(archive.starts_with(b"bplist"));
+
+        let added: webarchive::WebArchive = webarchive::from_file(input_file.path()).unwrap();
+        let resource = added
+            .get_resource_by_url("https://crouton.net/extra.css")
+            .unwrap();
+        assert_eq!(resource.mime_type, "text/css");
+        assert_eq!(resource.text_encoding_name.as_deref(), Some("UTF-8"));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("cat")
+            .arg(input_file.path())
+            .arg("https://crouton.net/extra.css")
+            .assert()
+            .success()
+            .stdout(std::fs::read(css_file.path()).unwrap());
+
+        // adding it again needs --replace
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("add")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("https://crouton.net/extra.css")
+            .arg("--file")
+            .arg(css_file.path())
+            .assert()
+            .failure();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let output_file = temp.child("replaced.webarchive");
+        cmd.arg("add")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("https://crouton.net/crouton.png")
+            .arg("--file")
+            .arg(css_file.path())
+            .arg("--mime")
+            .arg("image/png")
+            .arg("--replace")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout("Replaced \"https://crouton.net/crouton.png\" (38 bytes, was 5182 bytes)\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("cat")
+            .arg(input_file.path())
+            .arg("https://crouton.net/crouton.png")
+            .assert()
+            .success()
+            .stdout(CROUTON_PNG);
+    }
+
+    #[test]
+    fn stats_folder() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd.arg("stats").arg("fixtures").assert().success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains(
+            "\ncrouton.webarchive                               7511         5316         2      0    100.0%\n"
+        ));
+        assert!(stdout.contains(
+            "\nfragments.webarchive                              860          481         3      0     66.7%\n"
+        ));
+        assert!(stdout.contains("\n14 archive(s), 2 of which could not be read\n"));
+        assert!(stdout.contains("\n10 archive(s) store everything they refer to\n"));
+        assert!(stdout.contains("\n  string-data.webarchive: "));
+        assert!(stdout.contains("\n  truncated.webarchive: "));
+        assert!(stdout.contains("\nResources per archive: 1 fewest, 2 median, 52 most\n"));
+        assert!(stdout.contains("\ntext/html                      19      1974536   81.9%\n"));
+
+        // gzipped archives in folders inside it are read with --recursive
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.child("2020/05/crouton.webarchive")
+            .write_binary(CROUTON_WEBARCHIVE)
+            .unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(
+            &mut gzip,
+            &std::fs::read("fixtures/psxdatacenter.webarchive").unwrap(),
+        )
+        .unwrap();
+        temp.child("2021/psxdatacenter.webarchive.gz")
+            .write_binary(&gzip.finish().unwrap())
+            .unwrap();
+        temp.child("notes.txt").write_str("not an archive").unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("stats")
+            .arg(temp.path())
+            .arg("--format")
+            .arg("csv")
+            .assert()
+            .success()
+            .stdout(
+                "path,url,file_size,total_size,resource_count,frame_count,completeness,error\n",
+            );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("stats")
+            .arg(temp.path())
+            .arg("--recursive")
+            .arg("--format")
+            .arg("json")
+            .assert()
+            .success();
+
+        let stats: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        let archives = stats["archives"].as_array().unwrap();
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives[0]["path"], "2020/05/crouton.webarchive");
+        assert_eq!(archives[1]["path"], "2021/psxdatacenter.webarchive.gz");
+        assert_eq!(archives[1]["resource_count"], 52);
+        assert_eq!(archives[1]["completeness"]["ratio"], 1.0);
+        assert_eq!(stats["failures"].as_array().unwrap().len(), 0);
+        assert_eq!(stats["resource_counts"]["max"], 52);
+        assert_eq!(stats["mime_types"]["image/png"]["count"], 1);
+    }
+
+    #[test]
+    fn stats_top_psxdatacenter() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("stats")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("--top")
+            .arg("5")
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let (mime_types, largest) = stdout.split_once("\n\n       Bytes").unwrap();
+        assert!(mime_types.starts_with(
+            "2381434 bytes in 52 resource(s), across 4 subframe(s)\n\
+            49 of 49 referenced URL(s) stored (100.0% complete)\n"
+        ));
+        assert!(mime_types.contains("\ntext/html                       5      1965488   82.5%\n"));
+        assert_eq!(
+            largest,
+            concat!(
+                "   Share MIME type                URL\n",
+                "     1929687   81.0% text/html                http://psxdatacenter.com/jlist.html\n",
+                "      107128    4.5% image/jpeg               http://psxdatacenter.com/images/texgrey.jpg\n",
+                "      107128    4.5% image/jpeg               http://psxdatacenter.com/images/texgrey.jpg\n",
+                "      107128    4.5% image/jpeg               http://psxdatacenter.com/images/texgrey.jpg\n",
+                "       32099    1.3% image/jpeg               http://psxdatacenter.com/images/logo.jpg\n",
+            )
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("inspect")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("--format")
+            .arg("json")
+            .arg("--top=2")
+            .assert()
+            .success();
+
+        let inspection: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        let largest = inspection["largest"].as_array().unwrap();
+        assert_eq!(largest.len(), 2);
+        assert_eq!(largest[0]["url"], "http://psxdatacenter.com/jlist.html");
+        assert_eq!(largest[0]["size"], 1929687);
+        assert_eq!(largest[1]["mime_type"], "image/jpeg");
+    }
+
+    #[test]
+    fn sed_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output_file = temp.child("renamed.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("sed")
+            .arg("s/PlayStation DataCenter/PSX DataCenter/")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("--url-glob")
+            .arg("*/ntsc-j_list.html")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout(concat!(
+                "\"http://psxdatacenter.com/ntsc-j_list.html\": 1 replacement(s)\n",
+                "Made 1 replacement(s)\n",
+            ));
+
+        let renamed: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(
+            renamed.title().unwrap().as_deref(),
+            Some("PSX DataCenter - NTSC-J List")
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("sed")
+            .arg("s#images/logo#images/other#")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("--mime")
+            .arg("text/html")
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout(concat!(
+                "Skipped 1 match(es) within tags which refer to other resources; ",
+                "pass --include-references to change them too\n",
+                "Would make 0 replacement(s)\n",
+            ));
+    }
+
+    #[test]
+    fn sed_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output_file = temp.child("ivory.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("sed")
+            .arg(r#"s|"WHITE"|"ivory"|i"#)
+            .arg("fixtures/crouton.webarchive")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout(concat!(
+                "\"https://crouton.net/\": 1 replacement(s)\n",
+                "Made 1 replacement(s)\n",
+            ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("cat")
+            .arg(output_file.path())
+            .arg("https://crouton.net/")
+            .assert()
+            .success()
+            .stdout(concat!(
+                "<html><head><title> Crouton\n",
+                "</title>\n",
+                "</head><body bgcolor=\"ivory\" text=\"black\">\n",
+                "<img src=\"crouton.png\" alt=\"Crouton\">\n",
+                "\n",
+                "\n",
+                "</body></html>",
+            ));
+
+        // only the first match on each line, unless given the g flag
+        for (expression, count) in [("s/o/0/", 4), ("s/o/0/g", 10)] {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+            cmd.arg("sed")
+                .arg(expression)
+                .arg("fixtures/crouton.webarchive")
+                .arg("--dry-run")
+                .arg("--include-references")
+                .assert()
+                .success()
+                .stdout(format!(
+                    "\"https://crouton.net/\": {0} replacement(s)\nWould make {0} replacement(s)\n",
+                    count
+                ));
+        }
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("sed")
+            .arg("s/PNG/GIF/")
+            .arg("fixtures/crouton.webarchive")
+            .arg("--url-glob")
+            .arg("*.png")
+            .arg("--dry-run")
+            .assert()
+            .failure();
+        assert!(String::from_utf8_lossy(&assert.get_output().stderr)
+            .contains("refusing to change \"https://crouton.net/crouton.png\", as it is binary"));
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn encrypt_crouton() {
+        use age::secrecy::ExposeSecret;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let encrypted_file = temp.child("encrypted.webarchive");
+        let identity_file = temp.child("key.txt");
+        let wrong_identity_file = temp.child("wrong.txt");
+
+        let identity = age::x25519::Identity::generate();
+        identity_file
+            .write_str(identity.to_string().expose_secret())
+            .unwrap();
+        wrong_identity_file
+            .write_str(
+                age::x25519::Identity::generate()
+                    .to_string()
+                    .expose_secret(),
+            )
+            .unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("dedupe")
+            .arg("fixtures/crouton.webarchive")
+            .arg("-o")
+            .arg(encrypted_file.path())
+            .arg("--encrypt-to")
+            .arg(identity.to_public().to_string())
+            .assert()
+            .success();
+
+        let encrypted = std::fs::read(encrypted_file.path()).unwrap();
+        assert!(webarchive::is_encrypted(&encrypted));
+
+        let decrypted =
+            webarchive::from_reader_encrypted(&encrypted[..], [&identity as _]).unwrap();
+        let original: webarchive::WebArchive =
+            webarchive::from_file("fixtures/crouton.webarchive").unwrap();
+        assert_eq!(decrypted, original);
+
+        // any command can read it, given the identity
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("cat")
+            .arg("--identity")
+            .arg(identity_file.path())
+            .arg(encrypted_file.path())
+            .arg("https://crouton.net/crouton.png")
+            .assert()
+            .success()
+            .stdout(CROUTON_PNG);
+
+        let stderr = |cmd: &mut Command| {
+            let assert = cmd
+                .arg("inspect")
+                .arg(encrypted_file.path())
+                .assert()
+                .failure();
+            String::from_utf8_lossy(&assert.get_output().stderr).into_owned()
+        };
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        assert!(stderr(&mut cmd).contains("pass --identity to decrypt it"));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("--identity").arg(wrong_identity_file.path());
+        assert!(stderr(&mut cmd).contains("none of the given identities can decrypt this archive"));
+    }
+
+    #[test]
+    fn diff_canonical() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let mut webarchive: webarchive::WebArchive =
+            webarchive::from_file("fixtures/psxdatacenter.webarchive").unwrap();
+        let old_file = temp.child("old.webarchive");
+        webarchive::to_file_binary(old_file.path(), &webarchive).unwrap();
+
+        let logo = webarchive
+            .get_resource_by_url_mut("http://psxdatacenter.com/images/logo.jpg")
+            .unwrap();
+        logo.url = "HTTP://PSXDATACENTER.COM:80/images/../images/logo.jpg".to_string();
+        webarchive.subframe_archives.as_mut().unwrap().reverse();
+        let new_file = temp.child("new.webarchive");
+        webarchive::to_file_binary(new_file.path(), &webarchive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("diff")
+            .arg(old_file.path())
+            .arg(new_file.path())
+            .assert()
+            .failure()
+            .stdout(concat!(
+                "- \"http://psxdatacenter.com/images/logo.jpg\"\n",
+                "+ \"HTTP://PSXDATACENTER.COM:80/images/../images/logo.jpg\"\n",
+            ));
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("2 resource(s) differ"));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("diff")
+            .arg(old_file.path())
+            .arg(new_file.path())
+            .arg("--canonical")
+            .assert()
+            .success()
+            .stdout("No differences\n");
+
+        // tracking parameters only compare equal with --urls loose
+        let logo = webarchive
+            .get_resource_by_url_mut("http://psxdatacenter.com/images/logo.jpg#top")
+            .unwrap();
+        logo.url = "http://psxdatacenter.com/images/logo.jpg?utm_source=feed#top".to_string();
+        webarchive::to_file_binary(new_file.path(), &webarchive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("diff")
+            .arg(old_file.path())
+            .arg(new_file.path())
+            .arg("--canonical")
+            .assert()
+            .failure()
+            .stdout(concat!(
+                "- \"http://psxdatacenter.com/images/logo.jpg\"\n",
+                "+ \"http://psxdatacenter.com/images/logo.jpg?utm_source=feed\"\n",
+            ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("diff")
+            .arg(old_file.path())
+            .arg(new_file.path())
+            .args(["--canonical", "--urls", "loose"])
+            .assert()
+            .success()
+            .stdout("No differences\n");
+    }
+
+    #[cfg(feature = "diff")]
+    #[test]
+    fn diff_content() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("diff")
+            .arg("fixtures/diff/old.webarchive")
+            .arg("fixtures/diff/new.webarchive")
+            .arg("--content")
+            .assert()
+            .failure()
+            .stdout(concat!(
+                "~ \"http://example.com/\" (data changed)\n",
+                "@@ -2,14 +2,15 @@\n",
+                " <html>\n",
+                " <head>\n",
+                " <title>Café menu</title>\n",
+                "-<link rel=\"stylesheet\" href=\"style.css\">\n",
+                "+<link rel=\"stylesheet\" href=\"print.css\">\n",
+                " </head>\n",
+                " <body>\n",
+                " <h1>Café menu</h1>\n",
+                " <ul>\n",
+                " <li>Espresso</li>\n",
+                "-<li>Latte</li>\n",
+                "+<li>Flat white</li>\n",
+                " <li>Mocha</li>\n",
+                "+<li>Cortado</li>\n",
+                " </ul>\n",
+                " <img src=\"logo.png\">\n",
+                " </body>\n",
+                "~ \"http://example.com/logo.png\" (data changed)\n",
+                "  binary data, 32 → 40 bytes\n",
+                "- \"http://example.com/style.css\"\n",
+                "+ \"http://example.com/print.css\"\n",
+            ));
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("4 resource(s) differ"));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("diff")
+            .arg("fixtures/diff/old.webarchive")
+            .arg("fixtures/diff/new.webarchive")
+            .arg("--content")
+            .arg("--max-content-size")
+            .arg("100")
+            .arg("--format")
+            .arg("json")
+            .assert()
+            .failure();
+
+        let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        assert_eq!(
+            json["changes"][0]["content"],
+            serde_json::json!({ "kind": "too_large", "max_size": 100 })
+        );
+        assert_eq!(json["changes"][1]["new"]["size"], 40);
+        assert_eq!(json["changes"][2]["kind"], "removed");
+        assert_eq!(json["changes"][2].get("content"), None);
+    }
+
+    #[test]
+    fn merge() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let capture = |name: &str, data: &[u8], date: &str| {
+            let mut archive = images_archive("<img src=a.png>", &["https://example.com/a.png"]);
+            let image = &mut archive.subresources.as_mut().unwrap()[0];
+            image.data = data.to_vec();
+            let mut headers = webarchive::Headers::default();
+            headers.set("Date", date);
+            let response = webarchive::ResourceResponse::new(None, Some(200), headers, None);
+            image.response = Some(response.to_bytes().unwrap());
+
+            let file = temp.child(name);
+            webarchive::to_file_binary(file.path(), &archive).unwrap();
+            file
+        };
+        let older = capture(
+            "older.webarchive",
+            &[1; 64],
+            "Sun, 01 Jan 2023 00:00:00 GMT",
+        );
+        let newer = capture("newer.webarchive", &[2; 8], "Mon, 02 Jan 2023 00:00:00 GMT");
+        let output = temp.child("merged.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("merge")
+            .arg(newer.path())
+            .arg(older.path())
+            .arg("--prefer")
+            .arg("newest")
+            .arg("-o")
+            .arg(output.path())
+            .assert()
+            .success()
+            .stdout(format!(
+                concat!(
+                    "Merging {:?}...\n",
+                    "~ \"https://example.com/a.png\": kept the existing copy, with the later Date ",
+                    "(Mon, 02 Jan 2023 00:00:00 GMT against Sun, 01 Jan 2023 00:00:00 GMT)\n",
+                    "Added 0 resource(s), 1 identical, 1 conflicting\n",
+                    "Writing merged webarchive to {:?}...\n",
+                ),
+                older.path(),
+                output.path()
+            ));
+
+        let merged: webarchive::WebArchive = webarchive::from_file(output.path()).unwrap();
+        assert_eq!(merged.subresources.unwrap()[0].data, [2; 8]);
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("merge")
+            .arg(newer.path())
+            .arg(older.path())
+            .arg("--prefer")
+            .arg("largest")
+            .arg("-o")
+            .arg(output.path())
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains(&format!(
+            "kept the copy from {:?}, with more data (64 bytes against 8)",
+            older.path()
+        )));
+
+        let merged: webarchive::WebArchive = webarchive::from_file(output.path()).unwrap();
+        assert_eq!(merged.subresources.unwrap()[0].data, [1; 64]);
+    }
+
+    #[test]
+    fn set_main_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let output_file = temp.child("banner.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("set-main")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("http://psxdatacenter.com/banner.html")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout(concat!(
+                "Promoted \"http://psxdatacenter.com/banner.html\" to the main resource\n",
+                "Kept \"http://psxdatacenter.com/ntsc-j_list.html\" as a subresource\n",
+            ));
+
+        let banner: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(
+            banner.main_resource.url,
+            "http://psxdatacenter.com/banner.html"
+        );
+        assert_eq!(banner.subresources.as_ref().map(Vec::len), Some(3));
+        assert_eq!(banner.subframe_archives.as_ref().map(Vec::len), Some(3));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("set-main")
+            .arg(output_file.path())
+            .arg("--url")
+            .arg("http://psxdatacenter.com/images/logo.jpg")
+            .arg("--discard-old")
+            .assert()
+            .success()
+            .stdout(concat!(
+                "Promoted \"http://psxdatacenter.com/images/logo.jpg\" to the main resource\n",
+                "Discarded \"http://psxdatacenter.com/banner.html\" (782 bytes)\n",
+            ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("set-main")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("http://psxdatacenter.com/nope.html")
+            .assert();
+
+        let stderr = String::from_utf8(assert.failure().get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("no resource with the URL"));
+
+        input_file.assert(PSXDATACENTER_WEBARCHIVE);
+    }
+
+    #[test]
+    fn set_url_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let output_file = temp.child("moved.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("set-url")
+            .arg(input_file.path())
+            .arg("--from")
+            .arg("http://psxdatacenter.com/images/logo.jpg")
+            .arg("--to")
+            .arg("http://psxdatacenter.com/images/psx-logo.jpg")
+            .arg("--rewrite-references")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout(concat!(
+                "\"http://psxdatacenter.com/images/logo.jpg\" → \"http://psxdatacenter.com/images/psx-logo.jpg\"\n",
+                "Rewrote 1 reference(s)\n",
+            ));
+
+        let moved: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert!(moved
+            .get_resource_by_url("http://psxdatacenter.com/images/logo.jpg")
+            .is_none());
+        assert!(moved
+            .get_resource_by_url("http://psxdatacenter.com/images/psx-logo.jpg")
+            .is_some());
+        assert!(moved.missing_subresources().unwrap().is_empty());
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("set-url")
+            .arg(input_file.path())
+            .arg("--from")
+            .arg("http://psxdatacenter.com/images/logo.jpg")
+            .arg("--to")
+            .arg("http://psxdatacenter.com/images/texgrey.jpg")
+            .assert();
+
+        let stderr = String::from_utf8(assert.failure().get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("already exists; pass --merge"));
+        input_file.assert(PSXDATACENTER_WEBARCHIVE);
+    }
+
+    #[test]
+    fn rewrite_urls_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let output_file = temp.child("rewritten.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("rewrite-urls")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--map")
+            .arg("http://psxdatacenter.com=https://psx.example")
+            .arg("--map")
+            .arg("http://psxdatacenter.com/buttons/=https://buttons.example/")
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains(
+            "\"http://psxdatacenter.com/buttons/b01.jpg\" → \"https://buttons.example/b01.jpg\"\n"
+        ));
+        assert!(stdout.ends_with("Rewrote 52 resource URL(s) and 52 reference(s)\n"));
+
+        let rewritten: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert!(rewritten.iter_resources().all(|resource| {
+            resource.url.starts_with("https://psx.example/")
+                || resource.url.starts_with("https://buttons.example/")
+        }));
+        assert!(rewritten.missing_subresources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rewrite_urls_dry_run() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("rewrite-urls")
+            .arg("fixtures/crouton.webarchive")
+            .arg("--regex")
+            .arg("s#\\.png$#.webp#")
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout(concat!(
+                "\"https://crouton.net/crouton.png\" → \"https://crouton.net/crouton.webp\"\n",
+                "Would rewrite 1 resource URL(s) and 1 reference(s)\n",
+            ));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("rewrite-urls")
+            .arg("fixtures/crouton.webarchive")
+            .arg("--regex")
+            .arg("s#unterminated")
+            .arg("--dry-run")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn fix_mime_mislabeled() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output_file = temp.child("fixed.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("fix-mime")
+            .arg("fixtures/mislabeled.webarchive")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success()
+            .stdout(concat!(
+                "Declared                 Sniffed                  Confidence       URL\n",
+                "text/html                image/png                exact signature  https://crouton.net/crouton/\n",
+                "Fixed 1 MIME type(s)\n",
+            ));
+
+        let fixed: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        let png = &fixed.subresources.as_ref().unwrap()[0];
+        assert_eq!(png.mime_type, "image/png");
+        assert_eq!(png.text_encoding_name, None);
+        assert_eq!(fixed.main_resource.mime_type, "text/html");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("extract")
+            .arg(output_file.path())
+            .assert()
+            .success();
+
+        temp.child("crouton.net/crouton/_unnamed_index.png")
+            .assert(CROUTON_PNG);
+    }
+
+    #[test]
+    fn fix_mime_dry_run() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("fix-mime")
+            .arg("fixtures/crouton.webarchive")
+            .arg("--aggressive")
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout("Would fix 0 MIME type(s)\n");
+    }
+
+    #[test]
+    fn remove_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let output_file = temp.child("removed.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("remove")
+            .arg(input_file.path())
+            .arg("--url-glob")
+            .arg("*/buttons/b?1.jpg")
+            .arg("--url")
+            .arg("http://psxdatacenter.com/images/logo.jpg")
+            .arg("--dry-run")
+            .assert();
+
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        assert_eq!(stdout.lines().count(), 29);
+        assert_eq!(
+            stdout.lines().next(),
+            Some("Would remove \"http://psxdatacenter.com/images/logo.jpg\" (32099 bytes)")
+        );
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg(input_file.path())
+            .arg("--url-glob")
+            .arg("*/buttons/b?1.jpg")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success();
+
+        let removed: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        let jbanner = &removed.subframe_archives.as_ref().unwrap()[2];
+        let urls: Vec<&str> = jbanner
+            .subresources
+            .iter()
+            .flatten()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(urls, ["http://psxdatacenter.com/images/texgrey.jpg"]);
+
+        // the input is untouched when writing elsewhere
+        input_file.assert(PSXDATACENTER_WEBARCHIVE);
+    }
+
+    #[test]
+    fn remove_error_responses() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output_file = temp.child("fixed.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg("fixtures/broken-image.webarchive")
+            .arg("--status")
+            .arg("400..600")
+            .arg("--dry-run")
+            .assert()
+            .success()
+            .stdout("Would remove \"https://crouton.net/missing.png\" (274 bytes)\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg("fixtures/broken-image.webarchive")
+            .arg("--status")
+            .arg("500..")
+            .arg("--dry-run")
+            .assert()
+            .failure();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg("fixtures/broken-image.webarchive")
+            .arg("--status")
+            .arg("400-600")
+            .assert()
+            .failure();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg("fixtures/broken-image.webarchive")
+            .arg("--status")
+            .arg("404")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success();
+
+        let fixed: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        let urls: Vec<&str> = fixed
+            .iter_resources()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            ["https://crouton.net/", "https://crouton.net/crouton.png"]
+        );
+    }
+
+    #[test]
+    fn remove_refusals() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(CROUTON_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg(input_file.path())
+            .arg("--url")
+            .arg("https://crouton.net/")
+            .assert()
+            .failure();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg(input_file.path())
+            .arg("--url-glob")
+            .arg("*.gif")
+            .assert()
+            .failure();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg(input_file.path())
+            .arg("--url-glob")
+            .arg("*.gif")
+            .arg("--ignore-missing")
+            .assert()
+            .success()
+            .stdout("");
+
+        input_file.assert(CROUTON_WEBARCHIVE);
+
+        // in place
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("remove")
+            .arg(input_file.path())
+            .arg("--url-glob")
+            .arg("*.png")
+            .assert()
+            .success();
+
+        let removed: webarchive::WebArchive = webarchive::from_file(input_file.path()).unwrap();
+        assert!(removed.subresources.is_none());
+    }
+
+    #[test]
+    fn dedupe_across_urls() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("images.webarchive");
+        let archive = images_archive(
+            r#"<img src="one.png"><img src="two.png"><img src="three.png">"#,
+            &[
+                "https://example.com/one.png",
+                "https://example.com/two.png",
+                "https://example.com/three.png",
+            ],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+        let input_size = std::fs::metadata(input_file.path()).unwrap().len() as usize;
+
+        let expected_size = images_archive(
+            r#"<img src="one.png"><img src="https://example.com/one.png"><img src="https://example.com/one.png">"#,
+            &["https://example.com/one.png"],
+        )
+        .to_vec_binary()
+        .unwrap()
+        .len();
+        assert!(expected_size < input_size);
+
+        let output_file = temp.child("deduped.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("dedupe")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--across-urls")
+            .assert();
+
+        assert.success().stdout(format!(
+            "3 copies of 4096 bytes, wasting 8192 bytes:\n  \
+            - \"https://example.com/one.png\"\n  \
+            - \"https://example.com/two.png\"\n  \
+            - \"https://example.com/three.png\"\n\
+            Writing deduplicated webarchive to {:?}...\n\
+            Removed 2 subresources and rewrote 2 references, saving {} bytes ({} bytes total)\n",
+            output_file.path(),
+            input_size - expected_size,
+            expected_size
+        ));
+
+        let deduped: webarchive::WebArchive =
+            webarchive::from_file(output_file.path()).expect("Couldn't read deduped webarchive");
+        assert_eq!(deduped.subresources.as_ref().map(Vec::len), Some(1));
+        assert!(deduped.missing_subresources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dedupe_report_only() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("images.webarchive");
+        let archive = images_archive(
+            r#"<img src="one.png">"#,
+            &["https://example.com/one.png", "https://example.com/one.png"],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("dedupe")
+            .arg(input_file.path())
+            .arg("--report-only")
+            .assert();
+
+        assert.success().stdout(
+            "2 copies of 4096 bytes, wasting 4096 bytes:\n  \
+            - \"https://example.com/one.png\"\n  \
+            - \"https://example.com/one.png\"\n\
+            1 duplicate group, wasting 4096 bytes\n",
+        );
+    }
+
+    #[test]
+    fn optimize_padded_crouton() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        // crouton, with an extra copy of its image and a large unused resource
+        let mut archive: webarchive::WebArchive =
+            webarchive::from_file("fixtures/crouton.webarchive").unwrap();
+        let subresources = archive.subresources.as_mut().unwrap();
+        let image = &subresources[0];
+        let copy = webarchive::WebResource {
+            url: image.url.clone(),
+            data: image.data.clone(),
+            mime_type: image.mime_type.clone(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        };
+        subresources.push(copy);
+        subresources.push(webarchive::WebResource {
+            url: "https://crouton.net/padding.bin".to_string(),
+            data: vec![0; 20_000],
+            mime_type: "application/octet-stream".to_string(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        });
+
+        let input_file = temp.child("padded.webarchive");
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+        let input_size = std::fs::metadata(input_file.path()).unwrap().len();
+
+        let output_file = temp.child("optimized.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("optimize")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let passes: Vec<&str> = stdout
+            .lines()
+            .skip(1)
+            .take(4)
+            .map(|line| line[..24].trim_end())
+            .collect();
+        assert_eq!(
+            passes,
+            [
+                "strip responses",
+                "dedupe",
+                "drop orphans",
+                "inline small resources"
+            ]
+        );
+
+        let output_size = std::fs::metadata(output_file.path()).unwrap().len();
+        assert!(output_size + 20_000 < input_size);
+        assert!(stdout.ends_with(&format!(
+            "{} bytes, down from {} bytes\n",
+            output_size, input_size
+        )));
+
+        let optimized: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        let urls: Vec<&str> = optimized
+            .iter_resources()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            ["https://crouton.net/", "https://crouton.net/crouton.png"]
+        );
+        assert!(optimized.main_resource.response.is_none());
+    }
+
+    #[test]
+    fn sanitize_rules() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("scripted.webarchive");
+        let archive = images_archive(
+            r#"<script src="app.js"></script><img src="one.png" onclick="zoom()" alt='a &amp; b'>"#,
+            &["https://example.com/one.png", "https://example.com/app.js"],
+        );
+        webarchive::to_file_binary(input_file.path(), &archive).unwrap();
+
+        let output_file = temp.child("sanitized.webarchive");
+        let sanitize = |rules: &[&str]| {
+            Command::cargo_bin(assert_cmd::crate_name!())
+                .unwrap()
+                .arg("sanitize")
+                .arg(input_file.path())
+                .arg("-o")
+                .arg(output_file.path())
+                .args(rules)
+                .assert()
+                .success()
+        };
+
+        // only the rules given are applied
+        sanitize(&["--inline-event-handlers"]).stdout(
+            "inline-event-handlers          onclick attribute of <img> in \"https://example.com/\"\n\
+            Removed 1 item\n",
+        );
+        let sanitized: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(
+            sanitized.main_resource.data,
+            br#"<script src="app.js"></script><img src="one.png" alt='a &amp; b'>"#
+        );
+        assert_eq!(sanitized.subresources.unwrap().len(), 2);
+
+        // and all of them without any
+        sanitize(&[]).stdout(
+            "external-script-subresources   image/png subresource in \"https://example.com/app.js\"\n\
+            script-elements                <script> element in \"https://example.com/\"\n\
+            inline-event-handlers          onclick attribute of <img> in \"https://example.com/\"\n\
+            Removed 3 items\n",
+        );
+        let sanitized: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(
+            sanitized.main_resource.data,
+            br#"<img src="one.png" alt='a &amp; b'>"#
+        );
+        assert_eq!(sanitized.subresources.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn split_psxdatacenter() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("psxdatacenter.webarchive");
+        input_file
+            .write_binary(PSXDATACENTER_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+
+        let output_file = temp.child("banner.webarchive");
+        let remaining_file = temp.child("remaining.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        let assert = cmd
+            .arg("split")
+            .arg(input_file.path())
+            .arg("--frame")
+            .arg("<!--frame1-->")
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--remaining")
+            .arg(remaining_file.path())
+            .assert();
+
+        assert.success().stdout(format!(
+            "Writing frame \"http://psxdatacenter.com/banner.html\" to {:?}...\n\
+            Writing remaining webarchive to {:?}...\n",
+            output_file.path(),
+            remaining_file.path()
+        ));
+
+        let frame: webarchive::WebArchive =
+            webarchive::from_file(output_file.path()).expect("Couldn't read frame webarchive");
+        assert_eq!(
+            frame.main_resource.url,
+            "http://psxdatacenter.com/banner.html"
+        );
+        assert!(frame.main_resource.frame_name.is_none());
+        assert_eq!(frame.subresources.map(|s| s.len()), Some(2));
+        assert!(frame.subframe_archives.is_none());
+
+        let remaining: webarchive::WebArchive = webarchive::from_file(remaining_file.path())
+            .expect("Couldn't read remaining webarchive");
+        assert_eq!(remaining.subframe_archives.map(|s| s.len()), Some(3));
+    }
+
+    #[test]
+    fn split_max_size() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let psxdatacenter: webarchive::WebArchive =
+            webarchive::from_file("fixtures/psxdatacenter.webarchive").unwrap();
+        let nav = psxdatacenter.subframe_archives.unwrap().remove(1);
+        let input_file = temp.child("nav.webarchive");
+        webarchive::to_file_binary(input_file.path(), &nav).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("split")
+            .arg(input_file.path())
+            .arg("--max-size")
+            .arg("120k")
+            .arg("-o")
+            .arg(temp.child("part.webarchive").path())
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.starts_with("Writing part 1 of 2, 2 resource(s), to "));
+        assert!(stdout.contains(&format!(
+            "Writing part 2 of 2, 15 resource(s), to {:?}...\n",
+            temp.child("part-2.webarchive").path()
+        )));
+
+        for name in ["part.webarchive", "part-2.webarchive"] {
+            let size = std::fs::metadata(temp.child(name).path()).unwrap().len();
+            assert!(size <= 120 * 1024, "{} is {} bytes", name, size);
+        }
+
+        let merged_file = temp.child("merged.webarchive");
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("merge")
+            .arg(temp.child("part.webarchive").path())
+            .arg(temp.child("part-2.webarchive").path())
+            .arg("-o")
+            .arg(merged_file.path())
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("Added 15 resource(s), 0 identical, 0 conflicting\n"));
+
+        let merged: webarchive::WebArchive = webarchive::from_file(merged_file.path()).unwrap();
+        let urls = |archive: &webarchive::WebArchive| {
+            let mut urls: Vec<_> = archive
+                .iter_resources()
+                .map(|resource| (resource.url.clone(), resource.data.len()))
+                .collect();
+            urls.sort();
+            urls
+        };
+        assert_eq!(urls(&merged), urls(&nav));
+
+        // --frame and --max-size can't be used together
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("split")
+            .arg(input_file.path())
+            .arg("--frame")
+            .arg("0")
+            .arg("--max-size")
+            .arg("120k")
+            .arg("-o")
+            .arg(temp.child("part.webarchive").path())
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn repair_truncated() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let output_file = temp.child("repaired.webarchive");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("repair")
+            .arg("fixtures/truncated.webarchive")
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .code(1)
+            .stdout(concat!(
+                "Recovered \"https://crouton.net/\" (125 bytes)\n",
+                "Recovered \"https://crouton.net/crouton.png\" (5182 bytes)\n",
+                "Lost 1 resource(s) whose URLs were also lost\n",
+                "Recovered 2 resource(s) (5307 bytes), lost 1\n",
+            ));
+
+        let repaired: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(repaired.main_resource.url, "https://crouton.net/");
+        assert!(repaired.subresources.unwrap()[0].response.is_some());
+
+        // with only the offset table cut off, everything survives
+        let input_file = temp.child("crouton.webarchive");
+        input_file
+            .write_binary(&CROUTON_WEBARCHIVE[..CROUTON_WEBARCHIVE.len() - 40])
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("repair")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--main")
+            .arg("https://crouton.net/crouton.png")
+            .assert()
+            .success();
+
+        let repaired: webarchive::WebArchive = webarchive::from_file(output_file.path()).unwrap();
+        assert_eq!(
+            repaired.main_resource.url,
+            "https://crouton.net/crouton.png"
+        );
+        assert_eq!(repaired.subresources.map(|s| s.len()), Some(1));
+
+        // cut off before any resource was complete
+        input_file
+            .write_binary(&CROUTON_WEBARCHIVE[..200])
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("repair")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .assert()
+            .code(2);
+    }
+
+    #[test]
+    #[cfg(feature = "readability")]
+    fn readable_article() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let input_file = temp.child("article.webarchive");
+        input_file
+            .write_binary(ARTICLE_WEBARCHIVE)
+            .expect("Couldn't write temporary file");
+        let output_file = temp.child("croutons.md");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+
+        cmd.arg("readable")
+            .arg(input_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .arg("--images")
+            .arg("files")
+            .assert()
+            .success();
+
+        output_file.assert(include_str!("../fixtures/article.md"));
+        assert!(temp
+            .child("croutons_files/kitchen.example.com/2020/05/images/croutons.png")
+            .path()
+            .is_file());
+    }
+
+    #[test]
+    fn validate_manifest() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let mut archive: webarchive::WebArchive =
+            webarchive::from_bytes(CROUTON_WEBARCHIVE).expect("Couldn't read fixture");
+        archive.embed_manifest();
+
+        let input_file = temp.child("crouton.webarchive");
+        webarchive::to_file_binary(input_file.path(), &archive)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("validate")
+            .arg(input_file.path())
+            .assert()
+            .success()
+            .stdout("Checksum manifest matches\n0 errors, 0 warnings\n");
+
+        archive.subresources.as_mut().unwrap()[0].data.pop();
+        webarchive::to_file_binary(input_file.path(), &archive)
+            .expect("Couldn't write temporary file");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("validate")
+            .arg(input_file.path())
+            .assert()
+            .failure()
+            .stdout(
+                "error: \"https://crouton.net/crouton.png\": modified since the manifest was embedded\n\
+                Checksum manifest does not match\n\
+                1 error, 0 warnings\n",
+            );
+    }
+
+    /// An archive with a page which trips several lint rules at once
+    fn linty_archive() -> webarchive::WebArchive {
+        let resource = |url: &str, mime_type: &str, data: &[u8]| webarchive::WebResource {
+            url: url.to_string(),
+            data: data.to_vec(),
+            mime_type: mime_type.to_string(),
+            text_encoding_name: None,
+            frame_name: None,
+            response: None,
+        };
+
+        let mut page = resource(
+            "https://example.com/",
+            "text/html",
+            br#"<meta charset=windows-1252>
+<link rel=stylesheet href=style.css>
+<script src="app.js" integrity="sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI="></script>
+<img src="crouton.gif"><img src="missing.png">"#,
+        );
+        page.text_encoding_name = Some("UTF-8".to_string());
+        let mut script = resource("https://example.com/app.js", "text/javascript", b"alert(2)");
+        script.text_encoding_name = Some("UTF-8".to_string());
+
+        webarchive::WebArchive {
+            main_resource: page,
+            subresources: Some(vec![
+                resource("https://example.com/style.css", "text/css", b"body {}"),
+                script,
+                resource("https://example.com/crouton.gif", "image/gif", CROUTON_PNG),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn lint_text() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input_file = temp.child("linty.webarchive");
+        webarchive::to_file_binary(input_file.path(), &linty_archive()).unwrap();
+        let clean_file = temp.child("crouton.webarchive");
+        clean_file.write_binary(CROUTON_WEBARCHIVE).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("lint")
+            .arg(input_file.path())
+            .arg(clean_file.path())
+            .assert()
+            .failure()
+            .stdout(format!(
+                "{:?}:\n  \
+                WA001 missing-subresource\n    \
+                warning: \"https://example.com/missing.png\": image reference is not stored\n  \
+                WA005 missing-encoding\n    \
+                warning: \"https://example.com/style.css\": \"text/css\" resource has no text encoding\n  \
+                WA006 charset-conflict\n    \
+                warning: \"https://example.com/\": encoded as \"UTF-8\", but its <meta> element declares \"windows-1252\"\n  \
+                WA007 mime-mismatch\n    \
+                warning: \"https://example.com/crouton.gif\": declared as \"image/gif\", but is \"image/png\"\n  \
+                WA008 integrity-mismatch\n    \
+                error: \"https://example.com/app.js\": does not match the integrity \"sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI=\" declared by \"https://example.com/\"\n\
+                1 error, 4 warnings\n",
+                input_file.path()
+            ))
+            .stderr("Error: 1 file failed linting\n");
+
+        // rules can be given by ID or name
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.arg("lint")
+            .arg(input_file.path())
+            .args(["--allow", "WA001", "--allow", "missing-encoding"])
+            .args(["--allow", "charset-conflict", "--allow", "wa007"])
+            .args(["--allow", "integrity-mismatch"])
+            .assert()
+            .success()
+            .stdout("0 errors, 0 warnings\n");
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("lint")
+            .arg(clean_file.path())
+            .args(["--deny", "WA999"])
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("unknown lint rule \"WA999\""), "{}", stderr);
+    }
+
+    #[test]
+    fn lint_github() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input_file = temp.child("linty.webarchive");
+        webarchive::to_file_binary(input_file.path(), &linty_archive()).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd
+            .arg("lint")
+            .arg(input_file.path())
+            .args(["--format", "github", "--deny", "WA001", "--allow", "WA008"])
+            .assert()
+            .failure();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            format!(
+                "::error file={},title=WA001 missing-subresource::\"https://example.com/missing.png\": image reference is not stored",
+                input_file.path().to_string_lossy().replace(':', "%3A")
+            )
+        );
+        assert!(lines[1..].iter().all(|line| line.starts_with("::warning ")));
+    }
+
+    /// Check `json` against the schema `webarchive schema <output>`
+    /// prints, not allowing properties the schema doesn't describe
+    #[cfg(feature = "schemars")]
+    fn assert_matches_schema(output: &str, json: &[u8]) {
+        fn close(schema: &mut serde_json::Value) {
+            match schema {
+                serde_json::Value::Object(object) => {
+                    if object.contains_key("properties") {
+                        object.insert("unevaluatedProperties".into(), false.into());
+                    }
+                    object.values_mut().for_each(close);
+                }
+                serde_json::Value::Array(array) => array.iter_mut().for_each(close),
+                _ => {}
+            }
+        }
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let assert = cmd.arg("schema").arg(output).assert().success();
+        let mut schema: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).expect("Schema was not JSON");
+        close(&mut schema);
+
+        let validator = jsonschema::validator_for(&schema).expect("Schema was not valid");
+        let instance: serde_json::Value =
+            serde_json::from_slice(json).expect("Output was not JSON");
+        let errors: Vec<String> = validator
+            .iter_errors(&instance)
+            .map(|error| format!("{} at {}", error, error.instance_path()))
+            .collect();
+        assert!(errors.is_empty(), "{} output: {:#?}", output, errors);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn schema_matches_output() {
+        // `lint` fails when it finds problems, but prints them all the same
+        let run = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            cmd.args(args).output().unwrap().stdout
+        };
+
+        for input in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+        ] {
+            let json = run(&["inspect", "--format", "json", input]);
+            assert_matches_schema("inspect", &json);
+            let mut args = vec![
+                "inspect",
+                "--format",
+                "json",
+                "--checksums=md5",
+                "--top",
+                "3",
+            ];
+            if cfg!(feature = "image") {
+                args.push("--verbose");
+            }
+            args.push(input);
+            assert_matches_schema("inspect", &run(&args));
+
+            let json = run(&["stats", "--format", "json", input]);
+            assert_matches_schema("stats", &json);
+            let json = run(&["stats", "--format", "json", "--top", "3", input]);
+            assert_matches_schema("stats", &json);
+        }
+
+        // including archives which could not be read
+        let json = run(&["stats", "--format", "json", "fixtures"]);
+        assert_matches_schema("folder-stats", &json);
+        let temp = assert_fs::TempDir::new().unwrap();
+        let json = run(&["stats", "--format", "json", &temp.path().to_string_lossy()]);
+        assert_matches_schema("folder-stats", &json);
+
+        let input_file = temp.child("linty.webarchive");
+        webarchive::to_file_binary(input_file.path(), &linty_archive()).unwrap();
+        let json = run(&[
+            "lint",
+            "--format",
+            "json",
+            &input_file.path().to_string_lossy(),
+            "fixtures/crouton.webarchive",
+        ]);
+        assert_matches_schema("lint", &json);
+
+        let folder = temp.child("extracted");
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .arg("extract")
+            .arg("fixtures/psxdatacenter.webarchive")
+            .arg("-o")
+            .arg(folder.path())
+            .assert()
+            .success();
+        let json = std::fs::read(folder.child("webarchive-manifest.json").path()).unwrap();
+        assert_matches_schema("manifest", &json);
+
+        let mut args = vec![
+            "diff",
+            "--format",
+            "json",
+            "fixtures/diff/old.webarchive",
+            "fixtures/diff/new.webarchive",
+        ];
+        assert_matches_schema("diff", &run(&args));
+        if cfg!(feature = "diff") {
+            args.push("--content");
+            assert_matches_schema("diff", &run(&args));
+            args.extend(["--max-content-size", "100"]);
+            assert_matches_schema("diff", &run(&args));
+        }
     }
 }
@@ -0,0 +1,504 @@
+use crate::summary::serialize_http_date;
+use crate::{WebArchive, WebResource};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+/// Which copy [`WebArchive::merge`] keeps when both archives store a
+/// resource at the same URL, but differently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MergePolicy {
+    /// Keep the copy already in the archive being merged into.
+    First,
+
+    /// Keep the copy from the archive being merged in.
+    #[default]
+    Last,
+
+    /// Keep the copy with more data, or the last if they are the
+    /// same size.
+    Largest,
+
+    /// Keep the copy which was fetched or changed most recently.
+    ///
+    /// The `Date` headers of the copies' archived responses are
+    /// compared if both have one, and failing that their
+    /// `Last-Modified` headers. If neither tells them apart, the
+    /// copy is chosen as for [`Largest`](MergePolicy::Largest).
+    Newest,
+}
+
+/// Which archive a copy of a resource came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeSide {
+    /// The archive being merged into.
+    Existing,
+
+    /// The archive being merged in.
+    Incoming,
+}
+
+/// What decided which copy of a resource [`WebArchive::merge`] kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeBasis {
+    /// One copy's archived response has a later `Date` header.
+    Date,
+
+    /// One copy's archived response has a later `Last-Modified` header.
+    LastModified,
+
+    /// One copy has more data.
+    Size,
+
+    /// The policy keeps the first or last copy, or nothing else told
+    /// the copies apart.
+    Order,
+}
+
+/// One copy of a resource which [`WebArchive::merge`] chose between.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MergeCandidate {
+    /// The size of its data, in bytes.
+    pub size: usize,
+
+    /// The `Date` header of its archived response.
+    ///
+    /// Serialised as an HTTP date, such as `Sat, 30 May 2020 05:11:56 GMT`.
+    #[serde(serialize_with = "serialize_http_date")]
+    pub date: Option<SystemTime>,
+
+    /// The `Last-Modified` header of its archived response.
+    ///
+    /// Serialised as an HTTP date, such as `Sat, 30 May 2020 05:11:56 GMT`.
+    #[serde(serialize_with = "serialize_http_date")]
+    pub last_modified: Option<SystemTime>,
+}
+
+impl MergeCandidate {
+    /// Describe `resource`. An archived response which can not be
+    /// parsed is treated as missing.
+    fn new(resource: &WebResource) -> Self {
+        let response = resource.parse_response().ok().flatten();
+        MergeCandidate {
+            size: resource.data.len(),
+            date: response.as_ref().and_then(|response| response.date()),
+            last_modified: response.and_then(|response| response.last_modified()),
+        }
+    }
+}
+
+/// A resource which both archives stored differently, and which
+/// copy [`WebArchive::merge`] kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MergeConflict {
+    /// The URL both copies are stored at.
+    pub url: String,
+
+    /// The archive whose copy was kept.
+    pub kept: MergeSide,
+
+    /// What decided it.
+    pub basis: MergeBasis,
+
+    /// The copy in the archive being merged into.
+    pub existing: MergeCandidate,
+
+    /// The copy from the archive being merged in.
+    pub incoming: MergeCandidate,
+}
+
+/// What [`WebArchive::merge`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeReport {
+    /// The URL of each resource which was added, in document order.
+    pub added: Vec<String>,
+
+    /// How many resources both archives stored identically.
+    pub identical: usize,
+
+    /// Each resource both archives stored differently, in document order.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl WebArchive {
+    /// Add the resources and frames of `other` to this archive, such
+    /// as to combine two captures of the same page.
+    ///
+    /// Frames are matched by the URLs of their main resources, starting
+    /// with this archive and `other`, and each of `other`'s resources is
+    /// added to the matching frame, or its frame is added as a subframe
+    /// if none matches. `other`'s main resource becomes a subresource if
+    /// it does not match this archive's.
+    ///
+    /// Where a frame already stores a resource at the same URL, `policy`
+    /// decides which copy is kept, and the choice is listed in the
+    /// report. Resources in different frames are never compared, as
+    /// each frame only loads subresources from its own archive.
+    ///
+    /// The page listing the contents of a part made by
+    /// [`split`](WebArchive::split) is left out, so merging the parts
+    /// of a split archive gives back the original.
+    pub fn merge(&mut self, other: WebArchive, policy: MergePolicy) -> MergeReport {
+        let mut report = MergeReport::default();
+        merge_frame(self, other, policy, &mut report);
+        report
+    }
+}
+
+fn merge_frame(
+    frame: &mut WebArchive,
+    other: WebArchive,
+    policy: MergePolicy,
+    report: &mut MergeReport,
+) {
+    let WebArchive {
+        main_resource,
+        subresources,
+        subframe_archives,
+    } = other;
+
+    // the page listing what a part of a split archive holds
+    // isn't part of the original
+    let main_resource = Some(main_resource)
+        .filter(|resource| !resource.url.starts_with(crate::split::PART_URL_PREFIX));
+
+    for resource in main_resource
+        .into_iter()
+        .chain(subresources.into_iter().flatten())
+    {
+        let existing = if frame.main_resource.url == resource.url {
+            Some(&mut frame.main_resource)
+        } else {
+            frame
+                .subresources
+                .iter_mut()
+                .flatten()
+                .find(|existing| existing.url == resource.url)
+        };
+
+        match existing {
+            Some(existing) => resolve(existing, resource, policy, report),
+            None => {
+                report.added.push(resource.url.clone());
+                frame
+                    .subresources
+                    .get_or_insert_with(Vec::new)
+                    .push(resource);
+            }
+        }
+    }
+
+    for subframe in subframe_archives.into_iter().flatten() {
+        let existing = frame
+            .subframe_archives
+            .iter_mut()
+            .flatten()
+            .find(|existing| existing.main_resource.url == subframe.main_resource.url);
+
+        match existing {
+            Some(existing) => merge_frame(existing, subframe, policy, report),
+            None => {
+                report.added.extend(
+                    subframe
+                        .iter_resources()
+                        .map(|resource| resource.url.clone()),
+                );
+                frame.add_subframe(subframe);
+            }
+        }
+    }
+}
+
+/// Keep whichever of `existing` and `incoming` `policy` prefers,
+/// in `existing`'s place
+fn resolve(
+    existing: &mut WebResource,
+    incoming: WebResource,
+    policy: MergePolicy,
+    report: &mut MergeReport,
+) {
+    if *existing == incoming {
+        report.identical += 1;
+        return;
+    }
+
+    let conflict = choose(existing, &incoming, policy);
+    if conflict.kept == MergeSide::Incoming {
+        let frame_name = existing.frame_name.take();
+        *existing = incoming;
+        existing.frame_name = frame_name.or(existing.frame_name.take());
+    }
+    report.conflicts.push(conflict);
+}
+
+fn choose(existing: &WebResource, incoming: &WebResource, policy: MergePolicy) -> MergeConflict {
+    let ours = MergeCandidate::new(existing);
+    let theirs = MergeCandidate::new(incoming);
+
+    let later = |ordering: Ordering, basis| match ordering {
+        Ordering::Less => Some((MergeSide::Incoming, basis)),
+        Ordering::Greater => Some((MergeSide::Existing, basis)),
+        Ordering::Equal => None,
+    };
+    let compare = |ours: Option<SystemTime>, theirs: Option<SystemTime>, basis| {
+        later(ours?.cmp(&theirs?), basis)
+    };
+    let size = || later(ours.size.cmp(&theirs.size), MergeBasis::Size);
+
+    let decided = match policy {
+        MergePolicy::First => Some((MergeSide::Existing, MergeBasis::Order)),
+        MergePolicy::Last => None,
+        MergePolicy::Largest => size(),
+        MergePolicy::Newest => compare(ours.date, theirs.date, MergeBasis::Date)
+            .or_else(|| {
+                compare(
+                    ours.last_modified,
+                    theirs.last_modified,
+                    MergeBasis::LastModified,
+                )
+            })
+            .or_else(size),
+    };
+    let (kept, basis) = decided.unwrap_or((MergeSide::Incoming, MergeBasis::Order));
+
+    MergeConflict {
+        url: existing.url.clone(),
+        kept,
+        basis,
+        existing: ours,
+        incoming: theirs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeBasis, MergePolicy, MergeSide};
+    use crate::test_support::text_resource;
+    use crate::{Headers, ResourceResponse, WebArchive, WebResource};
+
+    /// A stylesheet, served with `headers` if there are any
+    fn stylesheet(url: &str, data: &str, headers: &[(&str, &str)]) -> WebResource {
+        let mut resource = text_resource(url, "text/css", data);
+        if !headers.is_empty() {
+            let mut response_headers = Headers::default();
+            for (name, value) in headers {
+                response_headers.set(name, value);
+            }
+            let response = ResourceResponse::new(
+                Some(url.to_string()),
+                Some(200),
+                response_headers,
+                Some("text/css".to_string()),
+            );
+            resource.response = Some(response.to_bytes().unwrap());
+        }
+        resource
+    }
+
+    fn archive(subresources: Vec<WebResource>) -> WebArchive {
+        WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", "<p>Hello</p>"),
+            subresources: Some(subresources),
+            subframe_archives: None,
+        }
+    }
+
+    const OLDER: &str = "Sun, 01 Jan 2023 00:00:00 GMT";
+    const NEWER: &str = "Mon, 02 Jan 2023 00:00:00 GMT";
+    const URL: &str = "https://example.com/style.css";
+
+    /// Merge a copy of `URL` into another, returning the data kept
+    /// and the decision
+    fn merge(
+        existing: WebResource,
+        incoming: WebResource,
+        policy: MergePolicy,
+    ) -> (String, MergeSide, MergeBasis) {
+        let mut base = archive(vec![existing]);
+        let report = base.merge(archive(vec![incoming]), policy);
+
+        assert_eq!(report.identical, 1, "main resources are identical");
+        assert!(report.added.is_empty());
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.url, URL);
+
+        let data = base.get_resource_by_url(URL).unwrap().data.clone();
+        (
+            String::from_utf8(data).unwrap(),
+            conflict.kept,
+            conflict.basis,
+        )
+    }
+
+    #[test]
+    fn newest() {
+        use MergeBasis::*;
+        use MergeSide::*;
+
+        type Capture<'a> = (&'a [(&'a str, &'a str)], &'a str);
+
+        #[rustfmt::skip]
+        let cases: &[(Capture, Capture, (&str, MergeSide, MergeBasis))] = &[
+            // later Date wins, whichever side it is on
+            ((&[("Date", NEWER)], "a"), (&[("Date", OLDER)], "bb"), ("a", Existing, Date)),
+            ((&[("Date", OLDER)], "aa"), (&[("Date", NEWER)], "b"), ("b", Incoming, Date)),
+            // Date is compared before Last-Modified
+            (
+                (&[("Date", NEWER), ("Last-Modified", OLDER)], "a"),
+                (&[("Date", OLDER), ("Last-Modified", NEWER)], "b"),
+                ("a", Existing, Date),
+            ),
+            // Last-Modified is compared if both don't have a Date
+            (
+                (&[("Date", NEWER), ("Last-Modified", OLDER)], "a"),
+                (&[("Last-Modified", NEWER)], "b"),
+                ("b", Incoming, LastModified),
+            ),
+            // equal dates fall back to size
+            ((&[("Date", NEWER)], "aa"), (&[("Date", NEWER)], "b"), ("aa", Existing, Size)),
+            // missing dates fall back to size
+            ((&[("Date", NEWER)], "a"), (&[], "bb"), ("bb", Incoming, Size)),
+            ((&[], "aa"), (&[], "b"), ("aa", Existing, Size)),
+            // and then to the last copy
+            ((&[("Date", NEWER)], "a"), (&[("Date", NEWER)], "b"), ("b", Incoming, Order)),
+            ((&[], "a"), (&[], "b"), ("b", Incoming, Order)),
+            // unparseable dates are missing
+            ((&[("Date", "yesterday")], "aa"), (&[("Date", OLDER)], "b"), ("aa", Existing, Size)),
+        ];
+
+        for ((existing_headers, existing_data), (incoming_headers, incoming_data), expected) in
+            cases
+        {
+            let decision = merge(
+                stylesheet(URL, existing_data, existing_headers),
+                stylesheet(URL, incoming_data, incoming_headers),
+                MergePolicy::Newest,
+            );
+            assert_eq!(
+                (decision.0.as_str(), decision.1, decision.2),
+                *expected,
+                "{:?} {:?} against {:?} {:?}",
+                existing_headers,
+                existing_data,
+                incoming_headers,
+                incoming_data
+            );
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let older = || stylesheet(URL, "aa", &[("Date", OLDER)]);
+        let newer = || stylesheet(URL, "b", &[("Date", NEWER)]);
+
+        assert_eq!(
+            merge(newer(), older(), MergePolicy::First),
+            ("b".to_string(), MergeSide::Existing, MergeBasis::Order)
+        );
+        assert_eq!(
+            merge(newer(), older(), MergePolicy::Last),
+            ("aa".to_string(), MergeSide::Incoming, MergeBasis::Order)
+        );
+        assert_eq!(
+            merge(older(), newer(), MergePolicy::Largest),
+            ("aa".to_string(), MergeSide::Existing, MergeBasis::Size)
+        );
+        assert_eq!(
+            merge(
+                text_resource(URL, "text/css", "a"),
+                text_resource(URL, "text/css", "b"),
+                MergePolicy::Largest
+            ),
+            ("b".to_string(), MergeSide::Incoming, MergeBasis::Order)
+        );
+    }
+
+    #[test]
+    fn report() {
+        let mut base = archive(vec![stylesheet(URL, "a", &[("Date", OLDER)])]);
+        let mut other = archive(vec![
+            stylesheet(URL, "b", &[("Date", NEWER), ("Last-Modified", OLDER)]),
+            text_resource("https://example.com/print.css", "text/css", "c"),
+        ]);
+        other.main_resource.data = b"<p>Hello again</p>".to_vec();
+
+        let report = base.merge(other, MergePolicy::Newest);
+        assert_eq!(report.added, ["https://example.com/print.css"]);
+        assert_eq!(report.identical, 0);
+        assert_eq!(
+            serde_json::to_value(&report.conflicts).unwrap(),
+            serde_json::json!([
+                {
+                    "url": "https://example.com/",
+                    "kept": "incoming",
+                    "basis": "size",
+                    "existing": { "size": 12, "date": null, "last_modified": null },
+                    "incoming": { "size": 18, "date": null, "last_modified": null },
+                },
+                {
+                    "url": URL,
+                    "kept": "incoming",
+                    "basis": "date",
+                    "existing": { "size": 1, "date": OLDER, "last_modified": null },
+                    "incoming": { "size": 1, "date": NEWER, "last_modified": OLDER },
+                },
+            ])
+        );
+        assert_eq!(base.main_resource.data, b"<p>Hello again</p>");
+        assert_eq!(base.subresources.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn frames() {
+        let frame = |url: &str, subresources| WebArchive {
+            main_resource: WebResource {
+                frame_name: Some("content".to_string()),
+                ..text_resource(url, "text/html", "<p>Frame</p>")
+            },
+            subresources: Some(subresources),
+            subframe_archives: None,
+        };
+
+        let mut base = archive(vec![]);
+        base.add_subframe(frame(
+            "https://example.com/a.html",
+            vec![stylesheet(URL, "a", &[("Date", OLDER)])],
+        ));
+
+        let mut other = archive(vec![]);
+        other.main_resource.url = "https://example.com/other".to_string();
+        other.add_subframe(frame(
+            "https://example.com/a.html",
+            vec![stylesheet(URL, "b", &[("Date", NEWER)])],
+        ));
+        other.add_subframe(frame("https://example.com/b.html", vec![]));
+
+        let report = base.merge(other, MergePolicy::Newest);
+        assert_eq!(
+            report.added,
+            ["https://example.com/other", "https://example.com/b.html"]
+        );
+        assert_eq!(report.identical, 1);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].kept, MergeSide::Incoming);
+
+        // the other main resource is a subresource now
+        assert_eq!(base.main_resource.url, "https://example.com/");
+        assert_eq!(
+            base.subresources.as_ref().unwrap()[0].url,
+            "https://example.com/other"
+        );
+
+        let subframes = base.subframe_archives.as_ref().unwrap();
+        assert_eq!(subframes.len(), 2);
+        assert_eq!(subframes[0].subresources.as_ref().unwrap()[0].data, b"b");
+        assert_eq!(
+            subframes[0].main_resource.frame_name.as_deref(),
+            Some("content")
+        );
+    }
+}
@@ -0,0 +1,594 @@
+use crate::references::{base_url, decode_attribute, resolve_url};
+use crate::{Error, Result, WebArchive, WebResource};
+use std::cell::RefCell;
+
+/// Images smaller than this are assumed to be spacers, tracking
+/// pixels, or decoration, and never chosen as a thumbnail.
+const MIN_THUMBNAIL_SIZE: usize = 2048;
+
+/// `<meta>` properties naming an image which represents the page,
+/// in order of preference.
+const THUMBNAIL_META: &[&str] = &[
+    "og:image",
+    "og:image:url",
+    "og:image:secure_url",
+    "twitter:image",
+    "twitter:image:src",
+];
+
+/// Information about an HTML document from its `<head>`.
+#[derive(Default)]
+pub(crate) struct DocumentMetadata {
+    base: Option<String>,
+
+    /// The text of the first `<title>` element, with
+    /// character references decoded.
+    title: Option<String>,
+    title_complete: bool,
+
+    /// `<meta>` elements' `property` (or `name`), lowercased,
+    /// and `content`, in document order.
+    meta: Vec<(String, String)>,
+
+    /// `<link>` elements, in document order.
+    links: Vec<Link>,
+}
+
+/// A `<link>` element.
+pub(crate) struct Link {
+    /// The `rel` keywords, lowercased.
+    pub(crate) rels: Vec<String>,
+    pub(crate) href: String,
+    pub(crate) sizes: Option<String>,
+}
+
+impl Link {
+    /// The largest of the icon sizes declared by the `sizes`
+    /// attribute, as a number of pixels, with `any` (as used for
+    /// scalable icons) larger than all others.
+    fn largest_size(&self) -> u64 {
+        self.sizes
+            .as_deref()
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+            .filter_map(|size| {
+                if size.eq_ignore_ascii_case("any") {
+                    return Some(u64::MAX);
+                }
+
+                let (width, height) = size.split_once(['x', 'X'])?;
+                Some(width.parse::<u64>().ok()? * height.parse::<u64>().ok()?)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl DocumentMetadata {
+    /// The document's title, falling back to its `og:title`
+    /// property if it has no `<title>`, or an empty one.
+    pub(crate) fn title(&self) -> Option<String> {
+        [self.title.as_deref(), self.meta("og:title")]
+            .into_iter()
+            .flatten()
+            .map(normalize_whitespace)
+            .find(|title| !title.is_empty())
+    }
+
+    /// The content of the first `<meta>` element with the given
+    /// `property` or `name`.
+    pub(crate) fn meta(&self, property: &str) -> Option<&str> {
+        self.meta
+            .iter()
+            .find(|(key, _)| key == property)
+            .map(|(_, content)| content.as_str())
+    }
+
+    /// `<link>` elements with any of the given `rel`s.
+    pub(crate) fn links<'a>(&'a self, rels: &'a [&str]) -> impl Iterator<Item = &'a Link> {
+        self.links
+            .iter()
+            .filter(move |link| link.rels.iter().any(|rel| rels.contains(&rel.as_str())))
+    }
+
+    /// Resolve a URL from the document against its `<base>`
+    /// element, or else the URL of the document itself.
+    pub(crate) fn resolve(&self, document_url: &str, raw: &str) -> Option<String> {
+        resolve_url(base_url(document_url, self.base.as_deref()).as_ref(), raw)
+    }
+}
+
+impl WebResource {
+    /// Parse the metadata of an HTML resource, or `None`
+    /// for other types of resource.
+    pub(crate) fn document_metadata(&self) -> Result<Option<DocumentMetadata>> {
+        if !self.is_html() {
+            return Ok(None);
+        }
+
+        match self.decoded_text() {
+            Some(text) => Ok(Some(document_metadata(&text)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl WebArchive {
+    /// The title of the main document.
+    ///
+    /// This is the text of its `<title>` element, or its Open Graph
+    /// `og:title` property if the title is empty, with runs of
+    /// whitespace (including newlines) collapsed into single spaces.
+    ///
+    /// Returns `Ok(None)` if the main resource is not HTML,
+    /// or has no title.
+    pub fn title(&self) -> Result<Option<String>> {
+        Ok(self
+            .main_resource
+            .document_metadata()?
+            .and_then(|metadata| metadata.title()))
+    }
+
+    /// Choose a stored image which could represent the archive,
+    /// such as in a gallery of thumbnails.
+    ///
+    /// In order of preference, this is:
+    ///
+    /// 1. The image named by the main document's Open Graph `og:image`
+    ///    `<meta>` property, or its `og:image:url` or
+    ///    `og:image:secure_url` variants,
+    /// 2. The image named by its `twitter:image` (or `twitter:image:src`)
+    ///    `<meta>` property,
+    /// 3. Its first `<link rel="apple-touch-icon">` (or
+    ///    `apple-touch-icon-precomposed`),
+    /// 4. Otherwise, the largest `image/*` subresource of at least 2 KiB,
+    ///    at any depth, preferring the first in document order.
+    ///
+    /// Images named by the main document are only chosen if they are
+    /// stored in the archive; if not, the next option is tried. Archives
+    /// of a bare image are their own thumbnail, whatever its size.
+    pub fn thumbnail_candidate(&self) -> Result<Option<&WebResource>> {
+        if self.main_resource.mime_essence().starts_with("image/") {
+            return Ok(Some(&self.main_resource));
+        }
+
+        if let Some(metadata) = self.main_resource.document_metadata()? {
+            let document_url = &self.main_resource.url;
+
+            let named = THUMBNAIL_META
+                .iter()
+                .filter_map(|property| metadata.meta(property))
+                .chain(
+                    metadata
+                        .links(&["apple-touch-icon", "apple-touch-icon-precomposed"])
+                        .map(|link| link.href.as_str()),
+                );
+
+            for raw in named {
+                if let Some(resource) = metadata
+                    .resolve(document_url, raw)
+                    .and_then(|url| self.get_resource_by_url(&url))
+                {
+                    return Ok(Some(resource));
+                }
+            }
+        }
+
+        Ok(self
+            .iter_resources()
+            .skip(1)
+            .filter(|resource| {
+                resource.mime_essence().starts_with("image/")
+                    && resource.data.len() >= MIN_THUMBNAIL_SIZE
+            })
+            .fold(
+                None,
+                |largest: Option<&WebResource>, resource| match largest {
+                    Some(largest) if largest.data.len() >= resource.data.len() => Some(largest),
+                    _ => Some(resource),
+                },
+            ))
+    }
+}
+
+impl WebArchive {
+    /// Find the stored icon for the archive's page.
+    ///
+    /// In order of preference, this is:
+    ///
+    /// 1. The main document's `<link rel="icon">` (or `rel="shortcut icon"`),
+    ///    choosing the one with the largest `sizes` if there are several,
+    ///    where `sizes="any"` is larger than any other,
+    /// 2. `/favicon.ico` at the main resource's origin,
+    /// 3. The first `image/x-icon` or `image/vnd.microsoft.icon`
+    ///    resource, at any depth.
+    ///
+    /// Icons named by the main document are only chosen if they are
+    /// stored in the archive; if not, the next option is tried.
+    pub fn favicon(&self) -> Result<Option<&WebResource>> {
+        let document_url = &self.main_resource.url;
+
+        if let Some(metadata) = self.main_resource.document_metadata()? {
+            let mut icons: Vec<(&Link, &WebResource)> = metadata
+                .links(&["icon"])
+                .filter_map(|link| {
+                    let url = metadata.resolve(document_url, &link.href)?;
+                    Some((link, self.get_resource_by_url(&url)?))
+                })
+                .collect();
+
+            // stable, so the first of equally-sized icons wins
+            icons.sort_by_key(|(link, _)| std::cmp::Reverse(link.largest_size()));
+
+            if let Some((_, icon)) = icons.first() {
+                return Ok(Some(icon));
+            }
+        }
+
+        if let Some(icon) = url::Url::parse(document_url)
+            .and_then(|url| url.join("/favicon.ico"))
+            .ok()
+            .and_then(|url| self.get_resource_by_url(url.as_str()))
+        {
+            return Ok(Some(icon));
+        }
+
+        Ok(self.iter_resources().skip(1).find(|resource| {
+            matches!(
+                resource.mime_essence().as_str(),
+                "image/x-icon" | "image/vnd.microsoft.icon"
+            )
+        }))
+    }
+}
+
+/// Scan an HTML document for its metadata.
+fn document_metadata(html: &str) -> Result<DocumentMetadata> {
+    use lol_html::{element, text, HtmlRewriter, Settings};
+
+    let metadata = RefCell::new(DocumentMetadata::default());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("base[href]", |element| {
+                    if let Some(href) = element.get_attribute("href") {
+                        metadata
+                            .borrow_mut()
+                            .base
+                            .get_or_insert(decode_attribute(&href));
+                    }
+
+                    Ok(())
+                }),
+                element!("meta[content]", |element| {
+                    let key = element
+                        .get_attribute("property")
+                        .or_else(|| element.get_attribute("name"));
+
+                    if let (Some(key), Some(content)) = (key, element.get_attribute("content")) {
+                        metadata.borrow_mut().meta.push((
+                            decode_attribute(&key).trim().to_ascii_lowercase(),
+                            decode_attribute(&content),
+                        ));
+                    }
+
+                    Ok(())
+                }),
+                element!("link[href]", |element| {
+                    let rels = element
+                        .get_attribute("rel")
+                        .unwrap_or_default()
+                        .to_ascii_lowercase()
+                        .split_ascii_whitespace()
+                        .map(str::to_string)
+                        .collect();
+
+                    if let Some(href) = element.get_attribute("href") {
+                        metadata.borrow_mut().links.push(Link {
+                            rels,
+                            href: decode_attribute(&href),
+                            sizes: element.get_attribute("sizes"),
+                        });
+                    }
+
+                    Ok(())
+                }),
+                text!("title", |chunk| {
+                    let mut metadata = metadata.borrow_mut();
+
+                    if !metadata.title_complete {
+                        metadata
+                            .title
+                            .get_or_insert_with(String::new)
+                            .push_str(chunk.as_str());
+                        metadata.title_complete = chunk.last_in_text_node();
+                    }
+
+                    Ok(())
+                }),
+            ],
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    let mut metadata = metadata.into_inner();
+    metadata.title = metadata
+        .title
+        .map(|title| html_escape::decode_html_entities(&title).into_owned());
+
+    Ok(metadata)
+}
+
+/// Collapse runs of whitespace into single spaces, and trim the ends.
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{WebArchive, WebResource};
+
+    fn image(url: &str, size: usize) -> WebResource {
+        resource(url, "image/png", vec![0; size])
+    }
+
+    fn page(head: &str, subresources: Vec<WebResource>) -> WebArchive {
+        WebArchive {
+            main_resource: resource(
+                "https://example.com/page/",
+                "text/html",
+                format!("<html><head>{}</head><body></body></html>", head).as_bytes(),
+            ),
+            subresources: Some(subresources),
+            subframe_archives: None,
+        }
+    }
+
+    fn thumbnail_url(archive: &WebArchive) -> Option<&str> {
+        archive
+            .thumbnail_candidate()
+            .expect("Could not choose thumbnail")
+            .map(|resource| resource.url.as_str())
+    }
+
+    fn favicon_url(archive: &WebArchive) -> Option<&str> {
+        archive
+            .favicon()
+            .expect("Could not find favicon")
+            .map(|resource| resource.url.as_str())
+    }
+
+    const HEAD: &str = r#"
+        <link rel="apple-touch-icon" href="/touch.png">
+        <meta name="twitter:image" content="twitter.png">
+        <meta property="og:image" content="https://example.com/og.png?a=1&amp;b=2">
+    "#;
+
+    #[test]
+    fn title() {
+        let archive = page(
+            "<title>\n  Fish &amp; Chips\n\t&mdash; a   history\n</title>\
+            <meta property=\"og:title\" content=\"Not this\">",
+            vec![],
+        );
+        assert_eq!(
+            archive.title().unwrap().as_deref(),
+            Some("Fish & Chips — a history")
+        );
+
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        assert_eq!(archive.title().unwrap().as_deref(), Some("Crouton"));
+    }
+
+    #[test]
+    fn title_fallback() {
+        let empty_title = page(
+            "<title> </title><meta property=\"og:title\" content=\" Open &amp; Graph \">",
+            vec![],
+        );
+        assert_eq!(
+            empty_title.title().unwrap().as_deref(),
+            Some("Open & Graph")
+        );
+
+        let untitled = page("", vec![]);
+        assert_eq!(untitled.title().unwrap(), None);
+
+        let mut text = page("<title>Text</title>", vec![]);
+        text.main_resource.mime_type = "text/plain".to_string();
+        assert_eq!(text.title().unwrap(), None);
+    }
+
+    #[test]
+    fn favicon_link() {
+        let head = r#"
+            <link rel="shortcut icon" href="/small.png" sizes="16x16">
+            <link rel="icon" href="/missing.png" sizes="512x512">
+            <link rel="icon" href="icon.svg" type="image/svg+xml" sizes="any">
+            <link rel="icon" href="/large.png" sizes="32x32 64x64">
+        "#;
+
+        let icons = || {
+            vec![
+                image("https://example.com/small.png", 10),
+                image("https://example.com/large.png", 10),
+                resource("https://example.com/favicon.ico", "image/x-icon", [0; 10]),
+                resource(
+                    "https://example.com/page/icon.svg",
+                    "image/svg+xml",
+                    b"<svg/>",
+                ),
+            ]
+        };
+
+        let archive = page(head, icons());
+        assert_eq!(
+            favicon_url(&archive),
+            Some("https://example.com/page/icon.svg")
+        );
+
+        let mut without_svg = icons();
+        without_svg.pop();
+        let archive = page(head, without_svg);
+        assert_eq!(favicon_url(&archive), Some("https://example.com/large.png"));
+
+        let archive = page(
+            r#"<link rel="icon" href="a.png"><link rel="icon" href="b.png">"#,
+            vec![
+                image("https://example.com/page/b.png", 10),
+                image("https://example.com/page/a.png", 10),
+            ],
+        );
+        assert_eq!(
+            favicon_url(&archive),
+            Some("https://example.com/page/a.png")
+        );
+    }
+
+    #[test]
+    fn favicon_fallbacks() {
+        let head = r#"<link rel="icon" href="/missing.png">"#;
+
+        let archive = page(
+            head,
+            vec![
+                resource("https://example.com/other.ico", "image/x-icon", [0; 10]),
+                resource("https://example.com/favicon.ico", "image/x-icon", [0; 10]),
+            ],
+        );
+        assert_eq!(
+            favicon_url(&archive),
+            Some("https://example.com/favicon.ico")
+        );
+
+        let archive = page(
+            head,
+            vec![
+                image("https://example.com/logo.png", 10),
+                resource(
+                    "https://cdn.example.com/icons/site.ico",
+                    "image/vnd.microsoft.icon",
+                    [0; 10],
+                ),
+            ],
+        );
+        assert_eq!(
+            favicon_url(&archive),
+            Some("https://cdn.example.com/icons/site.ico")
+        );
+
+        let archive = page(head, vec![image("https://example.com/logo.png", 10)]);
+        assert_eq!(favicon_url(&archive), None);
+    }
+
+    #[test]
+    fn thumbnail_og_image() {
+        let archive = page(
+            HEAD,
+            vec![
+                image("https://example.com/touch.png", 100),
+                image("https://example.com/page/twitter.png", 100),
+                image("https://example.com/og.png?a=1&b=2", 100),
+                image("https://example.com/huge.png", 100_000),
+            ],
+        );
+
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://example.com/og.png?a=1&b=2")
+        );
+    }
+
+    #[test]
+    fn thumbnail_twitter_image() {
+        // og:image is not stored, so is skipped
+        let archive = page(
+            HEAD,
+            vec![
+                image("https://example.com/touch.png", 100),
+                image("https://example.com/page/twitter.png", 100),
+            ],
+        );
+
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://example.com/page/twitter.png")
+        );
+    }
+
+    #[test]
+    fn thumbnail_touch_icon() {
+        let archive = page(
+            HEAD,
+            vec![
+                image("https://example.com/touch.png", 100),
+                image("https://example.com/huge.png", 100_000),
+            ],
+        );
+
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://example.com/touch.png")
+        );
+    }
+
+    #[test]
+    fn thumbnail_largest_image() {
+        let archive = page(
+            "<title>No metadata</title>",
+            vec![
+                image("https://example.com/small.png", 4096),
+                resource(
+                    "https://example.com/big.js",
+                    "text/javascript",
+                    [b' '; 9000],
+                ),
+                image("https://example.com/first.png", 8192),
+                image("https://example.com/second.png", 8192),
+                image("https://example.com/pixel.gif", 43),
+            ],
+        );
+
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://example.com/first.png")
+        );
+
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://crouton.net/crouton.png")
+        );
+    }
+
+    #[test]
+    fn thumbnail_none() {
+        let archive = page(HEAD, vec![image("https://example.com/pixel.gif", 43)]);
+
+        assert_eq!(thumbnail_url(&archive), None);
+    }
+
+    #[test]
+    fn thumbnail_image_only() {
+        let archive: WebArchive = crate::from_file("fixtures/image-only.webarchive")
+            .expect("Could not read image-only webarchive fixture");
+
+        assert_eq!(
+            thumbnail_url(&archive),
+            Some("https://crouton.net/crouton.png")
+        );
+        assert_eq!(archive.title().unwrap(), None);
+    }
+}
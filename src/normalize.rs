@@ -0,0 +1,219 @@
+use crate::glob_matches;
+
+/// Query parameters which only record where a visitor came from, removed
+/// by [`UrlNormalization::strip_tracking_parameters`]. `*` matches any
+/// run of characters, as in [`glob_matches`].
+pub const TRACKING_PARAMETERS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "yclid", "_ga",
+];
+
+/// How URLs are put in a standard form before they are compared, so
+/// that `http://Example.com:80/a#top` and `http://example.com/a` are
+/// found to be the same resource.
+///
+/// The default standardises URLs and removes fragments, which refer to
+/// part of a resource rather than another one, but leaves queries as
+/// they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UrlNormalization {
+    /// Lowercase the scheme and host, remove the port if it is the
+    /// scheme's default, resolve `.` and `..` segments and
+    /// percent-encode characters as a browser would.
+    pub standardize: bool,
+
+    /// Remove the fragment, such as `#top`.
+    pub strip_fragment: bool,
+
+    /// Remove query parameters which only record where a visitor came
+    /// from, listed in [`TRACKING_PARAMETERS`], such as `utm_source`.
+    /// The `?` is removed too, if no other parameters are left.
+    pub strip_tracking_parameters: bool,
+}
+
+impl UrlNormalization {
+    /// Compare URLs exactly as they are written.
+    pub fn exact() -> Self {
+        UrlNormalization {
+            standardize: false,
+            strip_fragment: false,
+            strip_tracking_parameters: false,
+        }
+    }
+
+    /// Apply every rule, also removing tracking parameters.
+    pub fn loose() -> Self {
+        UrlNormalization {
+            strip_tracking_parameters: true,
+            ..Default::default()
+        }
+    }
+
+    /// `url` in its normal form.
+    ///
+    /// URLs which can not be parsed are not standardised, but still have
+    /// their fragment and tracking parameters removed.
+    ///
+    /// ```rust
+    /// use webarchive::UrlNormalization;
+    ///
+    /// let normalization = UrlNormalization::default();
+    /// assert_eq!(
+    ///     normalization.normalize("HTTP://Example.com:80/a/../b#top"),
+    ///     "http://example.com/b"
+    /// );
+    /// assert_eq!(
+    ///     UrlNormalization::loose().normalize("https://example.com/?id=1&utm_source=feed"),
+    ///     "https://example.com/?id=1"
+    /// );
+    /// ```
+    pub fn normalize(&self, url: &str) -> String {
+        let mut url = if self.standardize {
+            url::Url::parse(url).map_or_else(|_| url.to_string(), String::from)
+        } else {
+            url.to_string()
+        };
+
+        if self.strip_fragment {
+            if let Some(start) = url.find('#') {
+                url.truncate(start);
+            }
+        }
+
+        if self.strip_tracking_parameters {
+            url = strip_tracking_parameters(&url);
+        }
+
+        url
+    }
+
+    /// Whether `a` and `b` are the same once normalised.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        a == b || self.normalize(a) == self.normalize(b)
+    }
+}
+
+impl Default for UrlNormalization {
+    fn default() -> Self {
+        UrlNormalization {
+            standardize: true,
+            strip_fragment: true,
+            strip_tracking_parameters: false,
+        }
+    }
+}
+
+/// `url` without tracking parameters, leaving the others exactly as
+/// they were written
+fn strip_tracking_parameters(url: &str) -> String {
+    let (before_fragment, fragment) = match url.find('#') {
+        Some(start) => url.split_at(start),
+        None => (url, ""),
+    };
+    let Some((before_query, query)) = before_fragment.split_once('?') else {
+        return url.to_string();
+    };
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|parameter| {
+            let name = parameter.split('=').next().unwrap_or_default();
+            !TRACKING_PARAMETERS
+                .iter()
+                .any(|pattern| glob_matches(pattern, &name.to_ascii_lowercase()))
+        })
+        .collect();
+
+    if kept.is_empty() {
+        format!("{}{}", before_query, fragment)
+    } else {
+        format!("{}?{}{}", before_query, kept.join("&"), fragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UrlNormalization;
+
+    #[test]
+    fn normalize() {
+        let exact = UrlNormalization::exact();
+        let standard = UrlNormalization::default();
+        let loose = UrlNormalization::loose();
+        let fragments_only = UrlNormalization {
+            strip_fragment: true,
+            ..UrlNormalization::exact()
+        };
+
+        #[rustfmt::skip]
+        let cases: &[(UrlNormalization, &str, &str)] = &[
+            // exact leaves everything alone
+            (exact, "HTTP://Example.com:80/a#top", "HTTP://Example.com:80/a#top"),
+            (exact, "https://example.com/?utm_source=feed", "https://example.com/?utm_source=feed"),
+            // scheme and host are lowercased
+            (standard, "HTTPS://EXAMPLE.com/Path", "https://example.com/Path"),
+            // default ports are removed, but not others
+            (standard, "http://example.com:80/a", "http://example.com/a"),
+            (standard, "https://example.com:443/a", "https://example.com/a"),
+            (standard, "http://example.com:8080/a", "http://example.com:8080/a"),
+            (standard, "https://example.com:80/a", "https://example.com:80/a"),
+            // an empty path is made `/`, and dot segments are resolved
+            (standard, "https://example.com", "https://example.com/"),
+            (standard, "https://example.com/a/./b/../c", "https://example.com/a/c"),
+            // characters are percent-encoded as browsers do
+            (standard, "https://example.com/a b", "https://example.com/a%20b"),
+            // fragments are removed, even empty ones
+            (standard, "https://example.com/a#top", "https://example.com/a"),
+            (standard, "https://example.com/a#", "https://example.com/a"),
+            (standard, "https://example.com/sprite.svg#icon", "https://example.com/sprite.svg"),
+            // queries are kept, tracking parameters and all
+            (standard, "https://example.com/?b=2&a=1", "https://example.com/?b=2&a=1"),
+            (standard, "https://example.com/?utm_source=feed", "https://example.com/?utm_source=feed"),
+            (standard, "https://example.com/?", "https://example.com/?"),
+            // loose removes tracking parameters, keeping the rest in order
+            (loose, "https://example.com/?utm_source=feed", "https://example.com/"),
+            (loose, "https://example.com/?id=1&utm_medium=email&utm_campaign=x", "https://example.com/?id=1"),
+            (loose, "https://example.com/?fbclid=abc&id=1&gclid=def", "https://example.com/?id=1"),
+            (loose, "https://example.com/?UTM_Source=feed&id=1", "https://example.com/?id=1"),
+            (loose, "https://example.com/?utm=1&outm_source=2", "https://example.com/?utm=1&outm_source=2"),
+            (loose, "https://example.com/?utm_source", "https://example.com/"),
+            (loose, "HTTP://Example.com:80/a?utm_source=x#top", "http://example.com/a"),
+            // rules apply independently
+            (fragments_only, "HTTP://Example.com:80/a#top", "HTTP://Example.com:80/a"),
+            (
+                UrlNormalization { strip_tracking_parameters: true, ..UrlNormalization::exact() },
+                "HTTP://Example.com/?utm_source=x#top",
+                "HTTP://Example.com/#top",
+            ),
+            // URLs which can't be parsed aren't standardised
+            (standard, "/relative/Path#top", "/relative/Path"),
+            (loose, "not a URL?utm_source=x&id=1", "not a URL?id=1"),
+            (standard, "", ""),
+            // non-special schemes keep their case-sensitive parts
+            (standard, "DATA:text/plain,Hello", "data:text/plain,Hello"),
+            (standard, "mailto:Someone@Example.com", "mailto:Someone@Example.com"),
+        ];
+
+        for (normalization, url, expected) in cases {
+            assert_eq!(
+                normalization.normalize(url),
+                *expected,
+                "{:?} with {:?}",
+                url,
+                normalization
+            );
+        }
+    }
+
+    #[test]
+    fn matches() {
+        let standard = UrlNormalization::default();
+        assert!(standard.matches("http://Example.com:80/a#top", "http://example.com/a"));
+        assert!(!standard.matches("http://example.com/a?utm_source=x", "http://example.com/a"));
+        assert!(UrlNormalization::loose()
+            .matches("http://example.com/a?utm_source=x", "http://example.com/a"));
+        assert!(
+            !UrlNormalization::exact().matches("http://example.com:80/a", "http://example.com/a")
+        );
+        assert!(UrlNormalization::exact().matches("http://example.com/a", "http://example.com/a"));
+    }
+}
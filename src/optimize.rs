@@ -0,0 +1,394 @@
+use crate::{Result, WebArchive, WebResource, MANIFEST_URL};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The largest resource [`OptimizeOptions::default`] inlines, in bytes.
+///
+/// Past this, the growth from base64 encoding outweighs what is
+/// saved by no longer storing a separate resource.
+pub const DEFAULT_INLINE_MAX_SIZE: usize = 128;
+
+/// One of the steps taken by [`WebArchive::optimize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum OptimizePass {
+    /// [`strip_responses`](WebArchive::strip_responses)
+    StripResponses,
+    /// [`dedupe`](WebArchive::dedupe)
+    Dedupe,
+    /// [`drop_orphans`](WebArchive::drop_orphans)
+    DropOrphans,
+    /// [`inline_small_resources`](WebArchive::inline_small_resources)
+    InlineSmallResources,
+}
+
+impl fmt::Display for OptimizePass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OptimizePass::StripResponses => "strip responses",
+            OptimizePass::Dedupe => "dedupe",
+            OptimizePass::DropOrphans => "drop orphans",
+            OptimizePass::InlineSmallResources => "inline small resources",
+        })
+    }
+}
+
+/// Which passes [`WebArchive::optimize`] makes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// Remove the archived HTTP response of every resource.
+    pub strip_responses: bool,
+
+    /// Remove duplicate subresources.
+    pub dedupe: bool,
+
+    /// When deduplicating, also remove copies stored under different URLs.
+    pub across_urls: bool,
+
+    /// Remove subresources which nothing in their frame refers to.
+    pub drop_orphans: bool,
+
+    /// Inline subresources of at most this many bytes as `data:` URLs.
+    pub inline_max_size: Option<usize>,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            strip_responses: true,
+            dedupe: true,
+            across_urls: false,
+            drop_orphans: true,
+            inline_max_size: Some(DEFAULT_INLINE_MAX_SIZE),
+        }
+    }
+}
+
+/// How much one pass of [`WebArchive::optimize`] shrank the archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PassReport {
+    /// Which pass this was.
+    pub pass: OptimizePass,
+
+    /// How many bytes smaller the archive's
+    /// [estimated binary size](WebArchive::estimated_binary_size)
+    /// became. Negative if it grew.
+    pub saved: i64,
+}
+
+/// What [`WebArchive::optimize`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OptimizeReport {
+    /// Each pass which was made, in the order they were made.
+    pub passes: Vec<PassReport>,
+}
+
+impl OptimizeReport {
+    /// How many bytes smaller the archive became across all passes.
+    pub fn total_saved(&self) -> i64 {
+        self.passes.iter().map(|pass| pass.saved).sum()
+    }
+}
+
+impl WebArchive {
+    /// Shrink the archive by making each of the passes enabled in
+    /// `options`, in the order they are listed in [`OptimizePass`].
+    ///
+    /// If the archive has an embedded checksum manifest, it is
+    /// replaced with one describing the optimised archive.
+    pub fn optimize(&mut self, options: &OptimizeOptions) -> Result<OptimizeReport> {
+        let mut report = OptimizeReport::default();
+        let mut size = self.estimated_binary_size();
+
+        let mut record = |archive: &WebArchive, pass| {
+            let new_size = archive.estimated_binary_size();
+            report.passes.push(PassReport {
+                pass,
+                saved: size as i64 - new_size as i64,
+            });
+            size = new_size;
+        };
+
+        if options.strip_responses {
+            self.strip_responses();
+            record(self, OptimizePass::StripResponses);
+        }
+
+        if options.dedupe {
+            self.dedupe(options.across_urls)?;
+            record(self, OptimizePass::Dedupe);
+        }
+
+        if options.drop_orphans {
+            self.drop_orphans()?;
+            record(self, OptimizePass::DropOrphans);
+        }
+
+        if let Some(max_size) = options.inline_max_size {
+            self.inline_small_resources(max_size)?;
+            record(self, OptimizePass::InlineSmallResources);
+        }
+
+        if self.has_manifest() {
+            self.embed_manifest();
+        }
+
+        Ok(report)
+    }
+
+    /// Remove the archived HTTP response of every resource, at any
+    /// depth, returning how many were removed.
+    ///
+    /// Safari does not need responses to display an archive, but they
+    /// record details such as when it was saved, and caching headers.
+    pub fn strip_responses(&mut self) -> usize {
+        let mut stripped = 0;
+
+        self.for_each_resource_mut(&mut |resource| {
+            if resource.response.take().is_some() {
+                stripped += 1;
+            }
+        });
+
+        stripped
+    }
+
+    /// Remove, and return, subresources which can not be reached by
+    /// following [`references`](WebResource::references) from the
+    /// main resource of their frame, in this archive or any subframe
+    /// archive.
+    ///
+    /// Resources which are only loaded by scripts look like orphans,
+    /// so this may break pages which rely on them. The embedded
+    /// checksum manifest is never removed.
+    pub fn drop_orphans(&mut self) -> Result<Vec<WebResource>> {
+        let mut orphans = Vec::new();
+
+        if let Some(subresources) = self.subresources.take() {
+            let reachable = self.reachable_urls(&subresources)?;
+
+            let (kept, dropped): (Vec<WebResource>, Vec<WebResource>) =
+                subresources.into_iter().partition(|subresource| {
+                    subresource.url == MANIFEST_URL
+                        || reachable.contains(&normalize_url(&subresource.url))
+                });
+
+            self.subresources = Some(kept).filter(|kept| !kept.is_empty());
+            orphans.extend(dropped);
+        }
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            orphans.extend(subframe_archive.drop_orphans()?);
+        }
+
+        Ok(orphans)
+    }
+
+    /// Replace references to small subresources with `data:` URLs
+    /// holding their contents, and remove them, in this archive and
+    /// any subframe archive. Returns how many were removed.
+    ///
+    /// Only subresources of at most `max_size` bytes, which are not a
+    /// text type, and which are referred to from an HTML or CSS resource
+    /// in their frame, are inlined.
+    pub fn inline_small_resources(&mut self, max_size: usize) -> Result<usize> {
+        let mut inlined = 0;
+
+        let data_urls: HashMap<String, String> = self
+            .subresources
+            .iter()
+            .flatten()
+            .filter(|subresource| {
+                subresource.data.len() <= max_size
+                    && !subresource.is_text()
+                    && !subresource.mime_essence().is_empty()
+            })
+            .map(|subresource| (normalize_url(&subresource.url), subresource.to_data_url()))
+            .collect();
+
+        if !data_urls.is_empty() {
+            let mut used = HashSet::new();
+            let mut rewrite = |resource: &mut WebResource| {
+                resource.rewrite_references(|url| {
+                    let data_url = data_urls.get(url)?;
+                    used.insert(url.to_string());
+                    Some(data_url.clone())
+                })
+            };
+
+            rewrite(&mut self.main_resource)?;
+            for subresource in self.subresources.iter_mut().flatten() {
+                rewrite(subresource)?;
+            }
+
+            if let Some(subresources) = &mut self.subresources {
+                let before = subresources.len();
+                subresources.retain(|subresource| !used.contains(&normalize_url(&subresource.url)));
+                inlined += before - subresources.len();
+
+                if subresources.is_empty() {
+                    self.subresources = None;
+                }
+            }
+        }
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            inlined += subframe_archive.inline_small_resources(max_size)?;
+        }
+
+        Ok(inlined)
+    }
+
+    /// The URLs referred to, directly or through other subresources,
+    /// by this frame's main resource.
+    fn reachable_urls(&self, subresources: &[WebResource]) -> Result<HashSet<String>> {
+        let mut reachable = HashSet::new();
+        let mut queue = vec![&self.main_resource];
+
+        while let Some(resource) = queue.pop() {
+            for reference in resource.references()? {
+                if reachable.insert(reference.url.clone()) {
+                    queue.extend(
+                        subresources
+                            .iter()
+                            .filter(|subresource| normalize_url(&subresource.url) == reference.url),
+                    );
+                }
+            }
+        }
+
+        Ok(reachable)
+    }
+}
+
+/// A URL in the form [`references`](WebResource::references) are
+/// resolved to, so stored URLs can be compared with them.
+pub(crate) fn normalize_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => url.into(),
+        Err(_) => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OptimizeOptions, OptimizePass};
+    use crate::test_support::resource;
+    use crate::WebArchive;
+
+    fn page() -> WebArchive {
+        let mut html = resource(
+            "https://example.com/",
+            "text/html",
+            br#"<link rel="stylesheet" href="style.css"><img src="dot.gif">"#,
+        );
+        html.text_encoding_name = Some("UTF-8".to_string());
+        html.response = Some(vec![0; 512]);
+
+        WebArchive {
+            main_resource: html,
+            subresources: Some(vec![
+                resource(
+                    "https://example.com/style.css",
+                    "text/css",
+                    b"body { background: url(bg.png) }",
+                ),
+                resource("https://example.com/bg.png", "image/png", [1; 4096]),
+                resource("https://example.com/dot.gif", "image/gif", b"GIF89a"),
+                resource("https://example.com/unused.png", "image/png", [2; 4096]),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    fn urls(archive: &WebArchive) -> Vec<&str> {
+        archive
+            .iter_resources()
+            .map(|resource| resource.url.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn drop_orphans() {
+        let mut archive = page();
+        let orphans: Vec<String> = archive
+            .drop_orphans()
+            .unwrap()
+            .into_iter()
+            .map(|orphan| orphan.url)
+            .collect();
+
+        assert_eq!(orphans, ["https://example.com/unused.png"]);
+
+        // bg.png is only reachable through the stylesheet
+        assert_eq!(
+            urls(&archive),
+            [
+                "https://example.com/",
+                "https://example.com/style.css",
+                "https://example.com/bg.png",
+                "https://example.com/dot.gif",
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_small_resources() {
+        let mut archive = page();
+
+        assert_eq!(archive.inline_small_resources(16).unwrap(), 1);
+        assert!(!urls(&archive).contains(&"https://example.com/dot.gif"));
+        assert_eq!(
+            std::str::from_utf8(&archive.main_resource.data).unwrap(),
+            r#"<link rel="stylesheet" href="style.css"><img src="data:image/gif;base64,R0lGODlh">"#
+        );
+        assert!(archive.missing_subresources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn optimize() {
+        let mut archive = page();
+        archive.embed_manifest();
+        let before = archive.to_vec_binary().unwrap().len();
+
+        let report = archive.optimize(&OptimizeOptions::default()).unwrap();
+        let after = archive.to_vec_binary().unwrap().len();
+
+        let passes: Vec<OptimizePass> = report.passes.iter().map(|pass| pass.pass).collect();
+        assert_eq!(
+            passes,
+            [
+                OptimizePass::StripResponses,
+                OptimizePass::Dedupe,
+                OptimizePass::DropOrphans,
+                OptimizePass::InlineSmallResources,
+            ]
+        );
+        assert!(report.passes[0].saved >= 512);
+        assert_eq!(report.passes[1].saved, 0);
+        assert!(report.passes[2].saved >= 4096);
+
+        assert!(before - after >= 4096 + 512);
+        assert!(archive.main_resource.response.is_none());
+        assert!(archive.verify_manifest().unwrap().is_intact());
+    }
+
+    #[test]
+    fn optimize_nothing() {
+        let mut archive = page();
+        let report = archive
+            .optimize(&OptimizeOptions {
+                strip_responses: false,
+                dedupe: false,
+                across_urls: false,
+                drop_orphans: false,
+                inline_max_size: None,
+            })
+            .unwrap();
+
+        assert!(report.passes.is_empty());
+        assert_eq!(urls(&archive).len(), 5);
+    }
+}
@@ -0,0 +1,337 @@
+//! Reading archives which other tools have written with mistakes.
+
+use crate::strict::unknown_keys;
+use crate::{Error, Result, StrictRule, StrictViolation, WebArchive};
+use plist::{Dictionary, Value};
+use serde::{de, Deserializer, Serialize};
+use std::fmt;
+
+/// Options for [`from_bytes_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Accept mistakes Safari tolerates, as in hand-made archives,
+    /// fixing each and recording a [`ParseWarning`], rather than
+    /// failing to read the archive at all.
+    pub lenient: bool,
+
+    /// Fail to read anything Safari would not have written, as
+    /// [`WebArchive::validate_strict`] finds, and any key Safari does
+    /// not write, with [`Error::NotSafari`].
+    pub strict_apple: bool,
+
+    /// Rules of [`strict_apple`](ParseOptions::strict_apple) to only
+    /// record a [`ParseWarning::NotSafari`] for, rather than failing.
+    pub strict_warnings: Vec<StrictRule>,
+}
+
+/// A mistake in an archive which was fixed while reading it leniently,
+/// as [`ParseOptions::lenient`] allows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ParseWarning {
+    /// The resource's `WebResourceData` was a `<string>`, rather than
+    /// `<data>`, so its UTF-8 bytes were taken as its data.
+    StringData {
+        /// The resource's URL.
+        url: String,
+    },
+
+    /// The archive has something Safari would not have written, which
+    /// [`ParseOptions::strict_warnings`] allows.
+    NotSafari {
+        /// What it has.
+        violation: StrictViolation,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::StringData { url } => {
+                write!(f, "{:?}: WebResourceData is a <string>, not <data>", url)
+            }
+            ParseWarning::NotSafari { violation } => violation.fmt(f),
+        }
+    }
+}
+
+/// An archive read by [`from_bytes_with_options`], with the mistakes
+/// fixed while reading it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParsedArchive {
+    /// The archive, as it would have been written without mistakes.
+    pub archive: WebArchive,
+
+    /// The mistakes fixed, in the order they were found.
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Read an archive from a binary or XML property list, as
+/// [`from_bytes`](crate::from_bytes) does, with `options`.
+///
+/// Without [`lenient`](ParseOptions::lenient), this accepts exactly what
+/// `from_bytes` does, and never has any warnings. With it, mistakes
+/// Safari tolerates are fixed, so writing the archive back out makes it
+/// one any reader accepts.
+///
+/// With [`strict_apple`](ParseOptions::strict_apple), this instead fails
+/// for anything Safari would not have written, other than the rules in
+/// [`strict_warnings`](ParseOptions::strict_warnings), which are warned
+/// about.
+pub fn from_bytes_with_options(data: &[u8], options: &ParseOptions) -> Result<ParsedArchive> {
+    if !options.lenient && !options.strict_apple {
+        return Ok(ParsedArchive {
+            archive: crate::from_bytes(data)?,
+            warnings: Vec::new(),
+        });
+    }
+
+    let mut value = Value::from_reader(std::io::Cursor::new(data))?;
+    let mut warnings = Vec::new();
+    let mut violations = Vec::new();
+    if let Some(archive) = value.as_dictionary_mut() {
+        if options.strict_apple {
+            unknown_keys(archive, &mut violations);
+        }
+        if options.lenient {
+            fix_archive(archive, &mut warnings);
+        }
+    }
+
+    let archive: WebArchive = if warnings.is_empty() {
+        crate::from_bytes(data)?
+    } else {
+        let mut fixed = Vec::new();
+        value.to_writer_binary(&mut fixed)?;
+        crate::from_bytes(&fixed)?
+    };
+
+    if options.strict_apple {
+        violations.extend(archive.validate_strict());
+
+        let (warned, denied): (Vec<_>, Vec<_>) = violations
+            .into_iter()
+            .partition(|violation| options.strict_warnings.contains(&violation.rule()));
+        if !denied.is_empty() {
+            return Err(Error::NotSafari(denied));
+        }
+        warnings.extend(
+            warned
+                .into_iter()
+                .map(|violation| ParseWarning::NotSafari { violation }),
+        );
+    }
+
+    Ok(ParsedArchive { archive, warnings })
+}
+
+/// Deserialise `WebResourceData`, accepting only data, and not the
+/// strings `serde_bytes` would otherwise take the bytes of, so that
+/// reading leniently is the only way to accept those.
+pub(crate) fn deserialize_data<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u8>, D::Error> {
+    struct DataVisitor;
+
+    impl<'de> de::Visitor<'de> for DataVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("data")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> std::result::Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> std::result::Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(DataVisitor)
+}
+
+/// Fix the resources of an archive and of its subframe archives
+fn fix_archive(archive: &mut Dictionary, warnings: &mut Vec<ParseWarning>) {
+    if let Some(resource) = archive
+        .get_mut("WebMainResource")
+        .and_then(Value::as_dictionary_mut)
+    {
+        fix_resource(resource, warnings);
+    }
+
+    if let Some(resources) = archive
+        .get_mut("WebSubresources")
+        .and_then(Value::as_array_mut)
+    {
+        for resource in resources.iter_mut().filter_map(Value::as_dictionary_mut) {
+            fix_resource(resource, warnings);
+        }
+    }
+
+    if let Some(archives) = archive
+        .get_mut("WebSubframeArchives")
+        .and_then(Value::as_array_mut)
+    {
+        for archive in archives.iter_mut().filter_map(Value::as_dictionary_mut) {
+            fix_archive(archive, warnings);
+        }
+    }
+}
+
+fn fix_resource(resource: &mut Dictionary, warnings: &mut Vec<ParseWarning>) {
+    if let Some(Value::String(text)) = resource.get("WebResourceData") {
+        let data = Value::Data(text.clone().into_bytes());
+        resource.insert("WebResourceData".to_string(), data);
+
+        warnings.push(ParseWarning::StringData {
+            url: resource
+                .get("WebResourceURL")
+                .and_then(Value::as_string)
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes_with_options, ParseOptions, ParseWarning};
+    use crate::{Error, StrictRule, StrictViolation};
+
+    const STRING_DATA: &[u8] = include_bytes!("../fixtures/string-data.webarchive");
+
+    #[test]
+    fn string_data_strict() {
+        assert!(crate::from_bytes::<crate::WebArchive>(STRING_DATA).is_err());
+        assert!(from_bytes_with_options(STRING_DATA, &ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn string_data_lenient() {
+        let parsed = from_bytes_with_options(
+            STRING_DATA,
+            &ParseOptions {
+                lenient: true,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed.warnings,
+            [
+                ParseWarning::StringData {
+                    url: "https://example.com/".to_string()
+                },
+                ParseWarning::StringData {
+                    url: "https://example.com/frame.html".to_string()
+                },
+            ]
+        );
+
+        let archive = &parsed.archive;
+        assert_eq!(
+            archive.main_resource.data,
+            "<h1>Héllo</h1>\n<iframe src=\"frame.html\"></iframe>".as_bytes()
+        );
+        assert_eq!(
+            archive.subresources.as_ref().unwrap()[0].data,
+            b"h1 { color: teal }"
+        );
+        assert_eq!(
+            archive.subframe_archives.as_ref().unwrap()[0]
+                .main_resource
+                .data,
+            b"<p>Framed</p>"
+        );
+
+        // written back as data, which can then be read strictly
+        let mut xml = Vec::new();
+        crate::to_writer_xml(&mut xml, archive).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(
+            "<key>WebResourceData</key>\n\t\t<data>\n\t\tPGgxPkjDqWxsbzwvaDE+CjxpZnJhbWUgc3JjPSJmcmFtZS5odG1sIj48L2lmcmFtZT4=\n\t\t</data>"
+        ));
+        let reread: crate::WebArchive = crate::from_bytes(xml.as_bytes()).unwrap();
+        assert_eq!(reread, parsed.archive);
+
+        // archives without mistakes have no warnings
+        let parsed = from_bytes_with_options(
+            &reread.to_vec_binary().unwrap(),
+            &ParseOptions {
+                lenient: true,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(parsed.archive, reread);
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn strict_apple_keys() {
+        let strict = ParseOptions {
+            strict_apple: true,
+            ..ParseOptions::default()
+        };
+        let data = include_bytes!("../fixtures/crouton.webarchive");
+        let parsed = from_bytes_with_options(data, &strict).unwrap();
+        assert!(parsed.warnings.is_empty());
+
+        let data = include_bytes!("../fixtures/nonstandard-keys.webarchive");
+        match from_bytes_with_options(data, &strict) {
+            Err(Error::NotSafari(violations)) => {
+                let rules: Vec<_> = violations.iter().map(StrictViolation::rule).collect();
+                assert!(!rules.is_empty());
+                assert!(rules.iter().all(|rule| *rule == StrictRule::UnknownKey));
+                assert!(violations.iter().any(|violation| matches!(
+                    violation,
+                    StrictViolation::UnknownKey { key, url: Some(_) } if key == "WebResourceMimeType"
+                )));
+            }
+            other => panic!("expected strict violations, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn strict_apple_downgrade() {
+        let mut archive: crate::WebArchive =
+            crate::from_file("fixtures/crouton.webarchive").unwrap();
+        archive.main_resource.frame_name = Some("top".to_string());
+        archive.subresources.as_mut().unwrap()[0].url = "relative.png".to_string();
+        let data = archive.to_vec_binary().unwrap();
+
+        let mut options = ParseOptions {
+            strict_apple: true,
+            strict_warnings: vec![StrictRule::MainFrameName],
+            ..ParseOptions::default()
+        };
+        match from_bytes_with_options(&data, &options) {
+            Err(Error::NotSafari(violations)) => assert_eq!(
+                violations,
+                [StrictViolation::RelativeUrl {
+                    url: "relative.png".to_string()
+                }]
+            ),
+            other => panic!("expected strict violations, got {:?}", other),
+        }
+
+        options.strict_warnings.push(StrictRule::RelativeUrl);
+        let parsed = from_bytes_with_options(&data, &options).unwrap();
+        assert_eq!(parsed.archive, archive);
+        assert_eq!(
+            parsed
+                .warnings
+                .iter()
+                .map(|warning| match warning {
+                    ParseWarning::NotSafari { violation } => violation.rule(),
+                    other => panic!("unexpected warning {:?}", other),
+                })
+                .collect::<Vec<_>>(),
+            [StrictRule::MainFrameName, StrictRule::RelativeUrl]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Records of when and how an archive was captured, embedded in it as
+//! a subresource which browsers ignore, as the format has no timestamp
+//! of its own.
+
+use crate::{WebArchive, WebResource};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The URL of the subresource holding an archive's [`CaptureInfo`].
+pub const CAPTURE_INFO_URL: &str = "about:webarchive-capture-info";
+
+const CAPTURE_INFO_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    version: u32,
+    #[serde(flatten)]
+    info: CaptureInfo,
+}
+
+/// When and how an archive was captured, as recorded by
+/// `WebArchive::fetch`, with the `fetch` feature, and read back by
+/// [`WebArchive::capture_info`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CaptureInfo {
+    /// The name of the tool which captured the archive.
+    pub tool: String,
+
+    /// The version of that tool.
+    pub tool_version: String,
+
+    /// When the capture started, to the second.
+    ///
+    /// Serialised as an HTTP date, which is always in UTC, such as
+    /// `Sat, 30 May 2020 05:11:56 GMT`.
+    #[serde(
+        serialize_with = "serialize_date",
+        deserialize_with = "deserialize_date"
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    pub captured_at: SystemTime,
+
+    /// The URL that was requested, before any redirects.
+    pub url: String,
+
+    /// The `User-Agent` header sent with each request, if any was.
+    pub user_agent: Option<String>,
+}
+
+impl CaptureInfo {
+    /// A record of this crate capturing `url` now, sending `user_agent`.
+    pub fn new(url: impl Into<String>, user_agent: Option<String>) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        CaptureInfo {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            // HTTP dates only hold whole seconds
+            captured_at: UNIX_EPOCH + Duration::from_secs(now.as_secs()),
+            url: url.into(),
+            user_agent,
+        }
+    }
+}
+
+fn serialize_date<S: Serializer>(date: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&httpdate::fmt_http_date(*date))
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let date = String::deserialize(deserializer)?;
+    httpdate::parse_http_date(&date).map_err(serde::de::Error::custom)
+}
+
+impl WebArchive {
+    /// Store `info` as JSON in a subresource at [`CAPTURE_INFO_URL`],
+    /// replacing any existing record, as
+    /// [`embed_manifest`](WebArchive::embed_manifest) does with
+    /// checksums.
+    pub fn embed_capture_info(&mut self, info: &CaptureInfo) {
+        if let Some(subresources) = &mut self.subresources {
+            subresources.retain(|resource| resource.url != CAPTURE_INFO_URL);
+        }
+
+        let record = Record {
+            version: CAPTURE_INFO_VERSION,
+            info: info.clone(),
+        };
+
+        self.subresources
+            .get_or_insert_with(Vec::new)
+            .push(WebResource {
+                url: CAPTURE_INFO_URL.to_string(),
+                data: serde_json::to_vec_pretty(&record)
+                    .expect("Capture records can always be serialised"),
+                mime_type: "application/json".to_string(),
+                text_encoding_name: Some("UTF-8".to_string()),
+                frame_name: None,
+                response: None,
+            });
+    }
+
+    /// When and how the archive was captured, if it was recorded by
+    /// [`embed_capture_info`](WebArchive::embed_capture_info), and the
+    /// record can be read.
+    pub fn capture_info(&self) -> Option<CaptureInfo> {
+        let resource = self
+            .subresources
+            .iter()
+            .flatten()
+            .find(|resource| resource.url == CAPTURE_INFO_URL)?;
+        let record: Record = serde_json::from_slice(&resource.data).ok()?;
+        (record.version == CAPTURE_INFO_VERSION).then_some(record.info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptureInfo, CAPTURE_INFO_URL};
+    use crate::WebArchive;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn capture_info() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        assert_eq!(archive.capture_info(), None);
+
+        let info = CaptureInfo::new("http://crouton.net", Some("webarchive/0.2.1".to_string()));
+        assert_eq!(info.tool, "webarchive");
+        assert_eq!(info.tool_version, env!("CARGO_PKG_VERSION"));
+        let age = SystemTime::now().duration_since(info.captured_at).unwrap();
+        assert!(age < Duration::from_secs(60));
+
+        archive.embed_capture_info(&info);
+        archive.embed_capture_info(&info);
+        let records = archive
+            .iter_resources()
+            .filter(|resource| resource.url == CAPTURE_INFO_URL)
+            .count();
+        assert_eq!(records, 1);
+
+        // survives a round trip through the file format
+        let archive: WebArchive = crate::from_bytes(&archive.to_vec_binary().unwrap()).unwrap();
+        assert_eq!(archive.capture_info(), Some(info));
+    }
+
+    #[test]
+    fn capture_info_json() {
+        let mut archive = WebArchive::from_text("hello", "about:hello");
+        archive.embed_capture_info(&CaptureInfo {
+            tool: "webarchive".to_string(),
+            tool_version: "0.2.1".to_string(),
+            captured_at: UNIX_EPOCH + Duration::from_secs(1_590_815_516),
+            url: "https://example.com/".to_string(),
+            user_agent: None,
+        });
+
+        let record = &archive.subresources.as_ref().unwrap()[0];
+        assert_eq!(record.mime_type, "application/json");
+        assert_eq!(
+            String::from_utf8_lossy(&record.data),
+            r#"{
+  "version": 1,
+  "tool": "webarchive",
+  "tool_version": "0.2.1",
+  "captured_at": "Sat, 30 May 2020 05:11:56 GMT",
+  "url": "https://example.com/",
+  "user_agent": null
+}"#
+        );
+
+        // records which can't be read are ignored
+        archive.subresources.as_mut().unwrap()[0].data = b"{\"version\": 2}".to_vec();
+        assert_eq!(archive.capture_info(), None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::WebResource;
+use std::io::{self, BufRead, Cursor, Read, Seek, SeekFrom};
+
+/// Reads a resource's data in place, as returned by
+/// [`WebResource::reader`].
+#[derive(Debug, Clone)]
+pub struct ResourceReader<'a> {
+    cursor: Cursor<&'a [u8]>,
+}
+
+impl WebResource {
+    /// A reader over the resource's data, for APIs which take a
+    /// [`Read`], such as image decoders or `serde_json::from_reader`,
+    /// without copying the data.
+    ///
+    /// The data is read as stored, so a resource archived with a
+    /// `Content-Encoding` is read still encoded.
+    pub fn reader(&self) -> ResourceReader<'_> {
+        ResourceReader {
+            cursor: Cursor::new(&self.data),
+        }
+    }
+}
+
+impl ResourceReader<'_> {
+    /// The position of the next byte to be read.
+    pub fn position(&self) -> u64 {
+        self.cursor.position()
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        let data = self.cursor.get_ref();
+        data.len()
+            .saturating_sub(self.cursor.position().try_into().unwrap_or(usize::MAX))
+    }
+}
+
+impl Read for ResourceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.cursor.read(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.cursor.read_exact(buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.cursor.read_to_end(buf)
+    }
+}
+
+impl BufRead for ResourceReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.cursor.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cursor.consume(amt)
+    }
+}
+
+impl Seek for ResourceReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.cursor.position())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WebArchive;
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+
+    #[test]
+    fn read_png_header() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let png = &archive.subresources.as_ref().unwrap()[0];
+
+        let decoder = png::Decoder::new(png.reader());
+        let reader = decoder.read_info().expect("Could not read PNG header");
+        assert_eq!((reader.info().width, reader.info().height), (33, 31));
+
+        let mut reader = png.reader();
+        assert_eq!(reader.remaining(), 5182);
+        assert_eq!(reader.fill_buf().unwrap()[..4], *b"\x89PNG");
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        assert_eq!(reader.position(), 5178);
+
+        let mut end = Vec::new();
+        reader.read_to_end(&mut end).unwrap();
+        assert_eq!(end, png.data[5178..]);
+        assert_eq!(reader.remaining(), 0);
+    }
+}
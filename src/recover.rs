@@ -0,0 +1,418 @@
+//! Salvaging what can be read from damaged binary property lists.
+//!
+//! Binary property lists end with a table of where each object
+//! starts, so an archive which was cut off can not be read at all
+//! by [`from_bytes`](crate::from_bytes). Safari, and this crate,
+//! write objects one after the other in the order they are numbered,
+//! so when the table is missing, objects can still be found by
+//! reading from the start until the data runs out.
+
+use crate::{WebArchive, WebResource};
+use std::ops::Range;
+
+/// The magic number and version every binary property list starts with.
+const HEADER: &[u8] = b"bplist00";
+
+/// The size of the trailer which ends every binary property list.
+const TRAILER_SIZE: usize = 32;
+
+/// How deeply subframe archives are followed, in case damage has
+/// made an archive appear to contain itself.
+const MAX_DEPTH: usize = 64;
+
+/// What [`recover_from_bytes`] could salvage from a damaged archive.
+#[derive(Debug, Default)]
+pub struct RecoveredArchive {
+    /// The archive, rebuilt from whatever survived, if its main
+    /// resource was found. Subresources and subframe archives
+    /// which could not be read are left out.
+    pub archive: Option<WebArchive>,
+
+    /// Every resource which could be read in full, at any depth,
+    /// in the order they were stored, whether or not they could
+    /// be placed in [`archive`](RecoveredArchive::archive).
+    pub resources: Vec<WebResource>,
+
+    /// The URLs of resources which were found, but whose data or
+    /// MIME type was lost, so could not be salvaged.
+    pub lost: Vec<String>,
+
+    /// How many more resources are known to have been lost,
+    /// without even their URLs surviving.
+    pub lost_unnamed: usize,
+}
+
+/// Salvage what can be read from a binary property list archive
+/// which is truncated or partly corrupt.
+///
+/// Every resource whose URL, data and MIME type survived is kept,
+/// along with its other fields if they survived too. Nothing is
+/// recovered from other formats, including XML property lists.
+/// Archives which are not damaged are recovered in full.
+pub fn recover_from_bytes(data: &[u8]) -> RecoveredArchive {
+    let mut recovered = RecoveredArchive::default();
+
+    let objects = match Objects::read(data) {
+        Some(objects) => objects,
+        None => return recovered,
+    };
+
+    for (index, object) in objects.objects.iter().enumerate() {
+        if let Some(Object::Dictionary(entries)) = object {
+            if objects.entry(entries, "WebResourceURL").is_none() {
+                continue;
+            }
+
+            match objects.resource(index as u64) {
+                Ok(resource) => recovered.resources.push(resource),
+                Err(Some(url)) => recovered.lost.push(url),
+                Err(None) => recovered.lost_unnamed += 1,
+            }
+        }
+    }
+
+    recovered.archive = objects.archive(objects.top, 0, &mut recovered.lost_unnamed);
+    recovered
+}
+
+/// An object in a binary property list, with any
+/// references to other objects as their numbers.
+enum Object {
+    Data(Range<usize>),
+    String(String),
+    Array(Vec<u64>),
+    Dictionary(Vec<(u64, u64)>),
+    /// Numbers, dates and the like, which archives do not use.
+    Other,
+}
+
+/// The objects of a binary property list, by number, where
+/// `None` is an object which could not be read.
+struct Objects<'a> {
+    data: &'a [u8],
+    objects: Vec<Option<Object>>,
+    top: u64,
+}
+
+impl<'a> Objects<'a> {
+    fn read(data: &'a [u8]) -> Option<Self> {
+        if !data.starts_with(HEADER) {
+            return None;
+        }
+
+        Some(Self::read_with_offsets(data).unwrap_or_else(|| Self::scan(data)))
+    }
+
+    /// Read the objects listed in the offset table, if the
+    /// trailer describing it is intact.
+    fn read_with_offsets(data: &'a [u8]) -> Option<Self> {
+        let trailer = data.len().checked_sub(TRAILER_SIZE)?;
+        let trailer = &data[trailer..];
+        let offset_size = usize::from(trailer[6]);
+        let ref_size = usize::from(trailer[7]);
+        let count = usize::try_from(read_int(&trailer[8..16])).ok()?;
+        let top = read_int(&trailer[16..24]);
+        let table = usize::try_from(read_int(&trailer[24..32])).ok()?;
+
+        if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+            return None;
+        }
+
+        let table = data.get(table..table.checked_add(count.checked_mul(offset_size)?)?)?;
+        let objects = table
+            .chunks(offset_size)
+            .map(|offset| {
+                let offset = usize::try_from(read_int(offset)).ok()?;
+                read_object(data, offset, ref_size).map(|(object, _)| object)
+            })
+            .collect();
+
+        Some(Objects { data, objects, top })
+    }
+
+    /// Read objects one after another from the start, for as long as
+    /// they can be read, trying each size of object reference and
+    /// using whichever reads furthest.
+    fn scan(data: &'a [u8]) -> Self {
+        let mut best: Option<(usize, Vec<Option<Object>>)> = None;
+
+        for ref_size in [1, 2, 4, 8] {
+            let mut objects = Vec::new();
+            let mut offset = HEADER.len();
+
+            while let Some((object, end)) = read_object(data, offset, ref_size) {
+                objects.push(Some(object));
+                offset = end;
+            }
+
+            // writers use the smallest references which can number every object
+            let addressable = ref_size >= 8 || objects.len() <= 1 << (8 * ref_size);
+            if addressable && best.as_ref().is_none_or(|(end, _)| offset > *end) {
+                best = Some((offset, objects));
+            }
+        }
+
+        Objects {
+            data,
+            objects: best.map(|(_, objects)| objects).unwrap_or_default(),
+            top: 0,
+        }
+    }
+
+    fn get(&self, index: u64) -> Option<&Object> {
+        self.objects.get(usize::try_from(index).ok()?)?.as_ref()
+    }
+
+    fn string(&self, index: u64) -> Option<&str> {
+        match self.get(index)? {
+            Object::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self, index: u64) -> Option<&'a [u8]> {
+        match self.get(index)? {
+            Object::Data(range) => Some(&self.data[range.clone()]),
+            _ => None,
+        }
+    }
+
+    /// The number of the value stored under `key` in a dictionary, or
+    /// under one of its [aliases](crate::KEY_ALIASES).
+    fn entry(&self, entries: &[(u64, u64)], key: &str) -> Option<u64> {
+        entries
+            .iter()
+            .find(|(name, _)| {
+                self.string(*name).is_some_and(|name| {
+                    name == key
+                        || crate::KEY_ALIASES
+                            .iter()
+                            .any(|&(alias, canonical)| canonical == key && alias == name)
+                })
+            })
+            .map(|(_, value)| *value)
+    }
+
+    /// Read the resource stored in object `index`, or if it can not be
+    /// read in full, its URL, if that survived.
+    fn resource(&self, index: u64) -> Result<WebResource, Option<String>> {
+        let entries = match self.get(index) {
+            Some(Object::Dictionary(entries)) => entries,
+            _ => return Err(None),
+        };
+
+        let string = |key| self.string(self.entry(entries, key)?).map(str::to_string);
+        let url = string("WebResourceURL").ok_or(None)?;
+        let data = self
+            .entry(entries, "WebResourceData")
+            .and_then(|data| self.bytes(data));
+
+        match (data, string("WebResourceMIMEType")) {
+            (Some(data), Some(mime_type)) => Ok(WebResource {
+                data: data.to_vec(),
+                url,
+                mime_type,
+                frame_name: string("WebResourceFrameName").filter(|name| !name.is_empty()),
+                text_encoding_name: string("WebResourceTextEncodingName")
+                    .filter(|encoding| !encoding.is_empty()),
+                response: self
+                    .entry(entries, "WebResourceResponse")
+                    .and_then(|response| self.bytes(response))
+                    .map(<[u8]>::to_vec),
+            }),
+            _ => Err(Some(url)),
+        }
+    }
+
+    /// Rebuild the archive stored in object `index`, if its main resource
+    /// survived, counting references to resources which did not at all.
+    fn archive(&self, index: u64, depth: usize, lost_unnamed: &mut usize) -> Option<WebArchive> {
+        let entries = match self.get(index) {
+            Some(Object::Dictionary(entries)) if depth < MAX_DEPTH => entries,
+            _ => return None,
+        };
+
+        let main_resource = self
+            .resource(self.entry(entries, "WebMainResource")?)
+            .ok()?;
+
+        let list = |key| match self.entry(entries, key).and_then(|list| self.get(list)) {
+            Some(Object::Array(items)) => items.as_slice(),
+            _ => &[],
+        };
+
+        let subresources: Vec<WebResource> = list("WebSubresources")
+            .iter()
+            .filter_map(|&item| {
+                if self.get(item).is_none() {
+                    *lost_unnamed += 1;
+                }
+                self.resource(item).ok()
+            })
+            .collect();
+
+        let subframe_archives: Vec<WebArchive> = list("WebSubframeArchives")
+            .iter()
+            .filter_map(|&item| self.archive(item, depth + 1, lost_unnamed))
+            .collect();
+
+        Some(WebArchive {
+            main_resource,
+            subresources: Some(subresources).filter(|list| !list.is_empty()),
+            subframe_archives: Some(subframe_archives).filter(|list| !list.is_empty()),
+        })
+    }
+}
+
+/// Read a big-endian unsigned integer of up to 8 bytes.
+fn read_int(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |int, byte| (int << 8) | u64::from(*byte))
+}
+
+/// Read the object starting at `offset`, returning it and where it
+/// ends, or `None` if it is cut off or not a valid object.
+fn read_object(data: &[u8], offset: usize, ref_size: usize) -> Option<(Object, usize)> {
+    let marker = *data.get(offset)?;
+    let info = usize::from(marker & 0x0f);
+    let start = offset + 1;
+
+    // the number of items or bytes, which larger objects store separately
+    let length = || -> Option<(usize, usize)> {
+        if info != 0x0f {
+            return Some((info, start));
+        }
+
+        let marker = *data.get(start)?;
+        let size = 1 << (marker & 0x0f);
+        if marker & 0xf0 != 0x10 || size > 8 {
+            return None;
+        }
+
+        let bytes = data.get(start + 1..start + 1 + size)?;
+        Some((usize::try_from(read_int(bytes)).ok()?, start + 1 + size))
+    };
+
+    let sized = |size: usize| {
+        let end = start.checked_add(size)?;
+        (end <= data.len()).then_some((Object::Other, end))
+    };
+
+    let refs = |bytes: &[u8]| -> Vec<u64> { bytes.chunks(ref_size).map(read_int).collect() };
+
+    match marker >> 4 {
+        0x0 if matches!(info, 0x00 | 0x08 | 0x09 | 0x0f) => Some((Object::Other, start)),
+        0x1 if info <= 4 => sized(1 << info),
+        0x2 if matches!(info, 2 | 3) => sized(1 << info),
+        0x3 if info == 3 => sized(8),
+        0x8 if info < 8 => sized(info + 1),
+        0x4 => {
+            let (length, start) = length()?;
+            let end = start.checked_add(length)?;
+            (end <= data.len()).then_some((Object::Data(start..end), end))
+        }
+        0x5 => {
+            let (length, start) = length()?;
+            let bytes = data.get(start..start.checked_add(length)?)?;
+            let string = std::str::from_utf8(bytes)
+                .ok()
+                .filter(|_| bytes.is_ascii())?;
+            Some((Object::String(string.to_string()), start + length))
+        }
+        0x6 => {
+            let (length, start) = length()?;
+            let end = start.checked_add(length.checked_mul(2)?)?;
+            let units: Vec<u16> = data
+                .get(start..end)?
+                .chunks(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            Some((Object::String(String::from_utf16(&units).ok()?), end))
+        }
+        0xa | 0xc => {
+            let (length, start) = length()?;
+            let end = start.checked_add(length.checked_mul(ref_size)?)?;
+            Some((Object::Array(refs(data.get(start..end)?)), end))
+        }
+        0xd => {
+            let (length, start) = length()?;
+            let middle = start.checked_add(length.checked_mul(ref_size)?)?;
+            let end = middle.checked_add(length * ref_size)?;
+            let keys = refs(data.get(start..middle)?);
+            let values = refs(data.get(middle..end)?);
+            Some((
+                Object::Dictionary(keys.into_iter().zip(values).collect()),
+                end,
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recover_from_bytes;
+
+    const PSXDATACENTER: &[u8] = include_bytes!("../fixtures/psxdatacenter.webarchive");
+
+    #[test]
+    fn recover_intact() {
+        let recovered = recover_from_bytes(PSXDATACENTER);
+        let archive = recovered.archive.expect("Archive was not recovered");
+        let original: crate::WebArchive = crate::from_bytes(PSXDATACENTER).unwrap();
+
+        assert_eq!(archive, original);
+        assert_eq!(recovered.resources.len(), original.iter_resources().count());
+        assert!(recovered.lost.is_empty());
+        assert_eq!(recovered.lost_unnamed, 0);
+    }
+
+    #[test]
+    fn recover_truncated() {
+        let original: crate::WebArchive = crate::from_bytes(PSXDATACENTER).unwrap();
+        let mut previous = 0;
+
+        for length in [2_500, 200_000, 1_000_000, PSXDATACENTER.len() - 100] {
+            let truncated = &PSXDATACENTER[..length];
+            assert!(crate::from_bytes::<crate::WebArchive>(truncated).is_err());
+
+            let recovered = recover_from_bytes(truncated);
+            assert!(
+                !recovered.resources.is_empty(),
+                "Nothing recovered from {} bytes",
+                length
+            );
+            assert!(recovered.resources.len() >= previous);
+            previous = recovered.resources.len();
+
+            let archive = recovered.archive.expect("Archive was not recovered");
+            assert_eq!(archive.main_resource.url, original.main_resource.url);
+
+            // every salvaged resource is intact
+            for resource in &recovered.resources {
+                let original = original.get_resource_by_url(&resource.url).unwrap();
+                assert_eq!(resource.data.len(), original.data.len());
+                assert_eq!(resource.mime_type, original.mime_type);
+            }
+
+            if length < 2_000_000 {
+                assert!(!recovered.lost.is_empty() || recovered.lost_unnamed > 0);
+            } else {
+                // only the offset table was cut off
+                assert_eq!(archive, original);
+            }
+        }
+    }
+
+    #[test]
+    fn recover_nothing() {
+        let recovered = recover_from_bytes(b"<?xml version=\"1.0\"");
+        assert!(recovered.archive.is_none());
+        assert!(recovered.resources.is_empty());
+
+        let recovered = recover_from_bytes(&PSXDATACENTER[..100]);
+        assert!(recovered.archive.is_none());
+        assert!(recovered.resources.is_empty());
+    }
+}
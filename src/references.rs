@@ -0,0 +1,862 @@
+use crate::{Error, Result, UrlNormalization, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+
+/// The way in which one resource refers to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ReferenceKind {
+    /// A stylesheet, from `<link rel="stylesheet">`.
+    Stylesheet,
+    /// A script, from `<script src>`.
+    Script,
+    /// An image, from `<img>`, `<picture>`, or a `background` attribute.
+    Image,
+    /// Audio, video, or a text track for them.
+    Media,
+    /// A document displayed in a `<frame>` or `<iframe>`.
+    Frame,
+    /// Content for an `<object>` or `<embed>` element.
+    Object,
+    /// A page icon, such as a favicon or touch icon.
+    Icon,
+    /// Anything else a `<link>` element asks to be fetched,
+    /// such as preloads and web app manifests.
+    Link,
+    /// A stylesheet included with a CSS `@import` rule.
+    CssImport,
+    /// Any other `url()` within CSS, such as a background image or font.
+    CssUrl,
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReferenceKind::Stylesheet => "stylesheet",
+            ReferenceKind::Script => "script",
+            ReferenceKind::Image => "image",
+            ReferenceKind::Media => "media",
+            ReferenceKind::Frame => "frame",
+            ReferenceKind::Object => "object",
+            ReferenceKind::Icon => "icon",
+            ReferenceKind::Link => "link",
+            ReferenceKind::CssImport => "css-import",
+            ReferenceKind::CssUrl => "css-url",
+        })
+    }
+}
+
+/// A URL referred to by an HTML or CSS resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    /// The absolute URL, resolved against the referring resource's URL
+    /// (or its `<base>` element, in HTML).
+    pub url: String,
+
+    /// How the URL is used by the referring resource.
+    pub kind: ReferenceKind,
+}
+
+/// How much of what an archive refers to it stores, as
+/// [`WebArchive::completeness`] finds.
+///
+/// It is serialised with its [`ratio`](Completeness::ratio) too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "SerializedCompleteness")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Completeness {
+    /// The number of different URLs referred to by the archive's
+    /// documents and stylesheets.
+    pub referenced: usize,
+
+    /// How many of them the archive stores.
+    pub stored: usize,
+
+    /// How many of them it doesn't, as
+    /// [`missing_subresources`](WebArchive::missing_subresources) lists.
+    pub missing: usize,
+}
+
+impl Completeness {
+    /// The fraction stored, from 0.0 to 1.0, which is 1.0 if nothing
+    /// is referred to.
+    pub fn ratio(&self) -> f64 {
+        match self.referenced {
+            0 => 1.0,
+            referenced => self.stored as f64 / referenced as f64,
+        }
+    }
+
+    /// Whether every URL referred to is stored.
+    pub fn is_complete(&self) -> bool {
+        self.missing == 0
+    }
+}
+
+/// How a [`Completeness`] is serialised
+#[derive(Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+struct SerializedCompleteness {
+    /// The number of different URLs referred to by the archive's
+    /// documents and stylesheets.
+    referenced: usize,
+
+    /// How many of them the archive stores.
+    stored: usize,
+
+    /// How many of them it doesn't.
+    missing: usize,
+
+    /// The fraction stored, from 0.0 to 1.0, which is 1.0 if nothing
+    /// is referred to.
+    ratio: f64,
+}
+
+impl From<Completeness> for SerializedCompleteness {
+    fn from(completeness: Completeness) -> Self {
+        SerializedCompleteness {
+            referenced: completeness.referenced,
+            stored: completeness.stored,
+            missing: completeness.missing,
+            ratio: completeness.ratio(),
+        }
+    }
+}
+
+impl WebResource {
+    /// Find the URLs of other resources this one needs in order
+    /// to be displayed.
+    ///
+    /// HTML documents are scanned for elements which load resources,
+    /// including inline `<style>` elements and `style` attributes,
+    /// and CSS stylesheets for `@import` rules and `url()` values.
+    /// Other types of resource have no references.
+    ///
+    /// Inline `data:` URLs, `javascript:` URLs, and references to
+    /// fragments of the same document are not included, nor are URLs
+    /// which can not be resolved. References are listed in document
+    /// order, and may contain duplicates.
+    pub fn references(&self) -> Result<Vec<Reference>> {
+        let raw = if self.is_html() {
+            match self.decoded_text() {
+                Some(text) => html_references(&text)?,
+                None => RawReferences::default(),
+            }
+        } else if self.is_css() {
+            match self.decoded_text() {
+                Some(text) => RawReferences {
+                    base: None,
+                    references: css_references(&text),
+                },
+                None => RawReferences::default(),
+            }
+        } else {
+            RawReferences::default()
+        };
+
+        Ok(raw.resolve(&self.url))
+    }
+}
+
+impl WebArchive {
+    /// List URLs referenced by resources in the archive, including those
+    /// in subframe archives, for which no resource is stored anywhere
+    /// in the archive.
+    ///
+    /// URLs are compared in the [default](UrlNormalization::default)
+    /// normal form, so a reference to `sprite.svg#icon` is satisfied by
+    /// a resource stored at `sprite.svg`. Each URL is listed once, as
+    /// its first reference gives it, with the kind of that reference.
+    pub fn missing_subresources(&self) -> Result<Vec<Reference>> {
+        self.missing_subresources_with(UrlNormalization::default())
+    }
+
+    /// List URLs referenced but not stored, like
+    /// [`missing_subresources`](WebArchive::missing_subresources),
+    /// comparing URLs as `normalization` says.
+    pub fn missing_subresources_with(
+        &self,
+        normalization: UrlNormalization,
+    ) -> Result<Vec<Reference>> {
+        let stored = self.stored_urls(&normalization);
+
+        Ok(self
+            .referenced_urls(&normalization)?
+            .into_iter()
+            .filter(|(url, _)| !stored.contains(url))
+            .map(|(_, reference)| reference)
+            .collect())
+    }
+
+    /// Count how many of the URLs referenced by resources in the
+    /// archive, at any depth, it stores, such as to find which of a
+    /// collection of captures are missing the most.
+    ///
+    /// URLs are compared as by
+    /// [`missing_subresources`](WebArchive::missing_subresources).
+    /// Documents and stylesheets stored more than once, such as in
+    /// several frames, are only read once.
+    ///
+    /// ```rust
+    /// # use anyhow::Result;
+    /// use webarchive::WebArchive;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let archive: WebArchive = webarchive::from_file("fixtures/fragments.webarchive")?;
+    ///
+    /// let completeness = archive.completeness()?;
+    /// assert_eq!(completeness.missing, 1);
+    /// assert!(completeness.ratio() < 1.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn completeness(&self) -> Result<Completeness> {
+        let normalization = UrlNormalization::default();
+        let stored = self.stored_urls(&normalization);
+        let referenced = self.referenced_urls(&normalization)?;
+
+        let stored = referenced
+            .iter()
+            .filter(|(url, _)| stored.contains(url))
+            .count();
+        let referenced = referenced.len();
+
+        Ok(Completeness {
+            referenced,
+            stored,
+            missing: referenced - stored,
+        })
+    }
+
+    /// Whether every URL referenced by resources in the archive is
+    /// stored in it; see [`completeness`](WebArchive::completeness).
+    pub fn is_complete(&self) -> Result<bool> {
+        Ok(self.completeness()?.is_complete())
+    }
+
+    /// The URL of every resource, at any depth, in `normalization`'s
+    /// normal form
+    fn stored_urls(&self, normalization: &UrlNormalization) -> HashSet<String> {
+        self.iter_resources()
+            .map(|resource| normalization.normalize(&resource.url))
+            .collect()
+    }
+
+    /// Each URL referenced by resources in the archive, once, in
+    /// `normalization`'s normal form, with its first reference
+    fn referenced_urls(
+        &self,
+        normalization: &UrlNormalization,
+    ) -> Result<Vec<(String, Reference)>> {
+        let mut scanned = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut referenced = Vec::new();
+
+        for resource in self.iter_resources() {
+            // the same document or stylesheet refers to the same URLs
+            if !scanned.insert((resource.url.as_str(), resource.data.as_slice())) {
+                continue;
+            }
+
+            for reference in resource.references()? {
+                let url = normalization.normalize(&reference.url);
+                if seen.insert(url.clone()) {
+                    referenced.push((url, reference));
+                }
+            }
+        }
+
+        Ok(referenced)
+    }
+}
+
+/// References as written in a document, before URL resolution.
+#[derive(Default)]
+struct RawReferences {
+    base: Option<String>,
+    references: Vec<(String, ReferenceKind)>,
+}
+
+impl RawReferences {
+    fn resolve(self, document_url: &str) -> Vec<Reference> {
+        let base = base_url(document_url, self.base.as_deref());
+
+        self.references
+            .into_iter()
+            .filter_map(|(raw, kind)| {
+                Some(Reference {
+                    url: resolve_url(base.as_ref(), &raw)?,
+                    kind,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The URL relative references in a document are resolved against:
+/// its `<base>` element's URL, if any, resolved against the document's.
+pub(crate) fn base_url(document_url: &str, base: Option<&str>) -> Option<url::Url> {
+    let document_url = url::Url::parse(document_url).ok();
+
+    match (&document_url, base) {
+        (Some(document_url), Some(base)) => document_url.join(base).ok(),
+        (None, Some(base)) => url::Url::parse(base).ok(),
+        (document_url, None) => document_url.clone(),
+    }
+}
+
+/// Resolve a URL as written in a document, skipping those which
+/// do not refer to another resource.
+pub(crate) fn resolve_url(base: Option<&url::Url>, raw: &str) -> Option<String> {
+    let raw = raw.trim();
+
+    if raw.is_empty() || raw.starts_with('#') {
+        return None;
+    }
+
+    let url = match base {
+        Some(base) => base.join(raw).ok()?,
+        None => url::Url::parse(raw).ok()?,
+    };
+
+    if matches!(url.scheme(), "data" | "javascript" | "about" | "blob") {
+        return None;
+    }
+
+    Some(url.into())
+}
+
+/// Which attributes of an element refer to resources, and how.
+pub(crate) fn element_attributes(
+    tag: &str,
+    element: &lol_html::html_content::Element,
+) -> &'static [(&'static str, ReferenceKind)] {
+    use ReferenceKind::*;
+
+    match tag {
+        "link" => {
+            let rel = element
+                .get_attribute("rel")
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let rel: Vec<&str> = rel.split_ascii_whitespace().collect();
+
+            if rel.contains(&"stylesheet") {
+                &[("href", Stylesheet)]
+            } else if rel
+                .iter()
+                .any(|rel| matches!(*rel, "icon" | "apple-touch-icon" | "mask-icon"))
+                || rel.iter().any(|rel| rel.starts_with("apple-touch-icon"))
+            {
+                &[("href", Icon)]
+            } else if rel
+                .iter()
+                .any(|rel| matches!(*rel, "preload" | "modulepreload" | "prefetch" | "manifest"))
+            {
+                &[("href", Link)]
+            } else {
+                &[]
+            }
+        }
+        "script" => &[("src", Script)],
+        "img" => &[("src", Image), ("srcset", Image)],
+        "source" => &[("src", Media), ("srcset", Image)],
+        "input" => &[("src", Image)],
+        "video" => &[("src", Media), ("poster", Image)],
+        "audio" | "track" => &[("src", Media)],
+        "frame" | "iframe" => &[("src", Frame)],
+        "object" => &[("data", Object)],
+        "embed" => &[("src", Object)],
+        "body" | "table" | "td" | "th" | "tr" => &[("background", Image)],
+        _ => &[],
+    }
+}
+
+/// Scan an HTML document for references.
+fn html_references(html: &str) -> Result<RawReferences> {
+    use lol_html::{element, text, HtmlRewriter, Settings};
+
+    let raw = RefCell::new(RawReferences::default());
+    let style = RefCell::new(String::new());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("*", |element| {
+                    let tag = element.tag_name();
+                    let mut raw = raw.borrow_mut();
+
+                    if tag == "base" {
+                        if let Some(href) = element.get_attribute("href") {
+                            raw.base.get_or_insert(decode_attribute(&href));
+                        }
+                    }
+
+                    for (attribute, kind) in element_attributes(&tag, element) {
+                        if let Some(value) = element.get_attribute(attribute) {
+                            let value = decode_attribute(&value);
+
+                            if *attribute == "srcset" {
+                                raw.references.extend(
+                                    srcset_urls(&value).map(|url| (url.to_string(), *kind)),
+                                );
+                            } else {
+                                raw.references.push((value, *kind));
+                            }
+                        }
+                    }
+
+                    if let Some(declarations) = element.get_attribute("style") {
+                        raw.references
+                            .extend(css_references(&decode_attribute(&declarations)));
+                    }
+
+                    Ok(())
+                }),
+                text!("style", |chunk| {
+                    style.borrow_mut().push_str(chunk.as_str());
+
+                    if chunk.last_in_text_node() {
+                        let css = style.take();
+                        raw.borrow_mut().references.extend(css_references(&css));
+                    }
+
+                    Ok(())
+                }),
+            ],
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    Ok(raw.into_inner())
+}
+
+/// The raw `href` of an HTML document's first `<base>` element.
+pub(crate) fn html_base(html: &str) -> Result<Option<String>> {
+    Ok(html_references(html)?.base)
+}
+
+/// Decode character references within an attribute value.
+pub(crate) fn decode_attribute(value: &str) -> String {
+    html_escape::decode_html_entities(value).into_owned()
+}
+
+/// The URLs from an `srcset` attribute's image candidate strings.
+pub(crate) fn srcset_urls(srcset: &str) -> impl Iterator<Item = &str> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_ascii_whitespace().next())
+}
+
+/// Scan a stylesheet, or a list of declarations, for references.
+fn css_references(css: &str) -> Vec<(String, ReferenceKind)> {
+    let css = strip_css_comments(css);
+    let mut references = Vec::new();
+    let mut rest = css.as_str();
+
+    while let Some(position) = find_url_or_import(rest) {
+        rest = &rest[position..];
+
+        if starts_with_ignore_case(rest, "@import") {
+            rest = rest["@import".len()..].trim_start();
+
+            if let Some((value, remainder)) = css_string(rest) {
+                references.push((value, ReferenceKind::CssImport));
+                rest = remainder;
+            } else if let Some((value, remainder)) = css_url(rest) {
+                references.push((value, ReferenceKind::CssImport));
+                rest = remainder;
+            }
+        } else if let Some((value, remainder)) = css_url(rest) {
+            references.push((value, ReferenceKind::CssUrl));
+            rest = remainder;
+        } else {
+            rest = &rest["url(".len()..];
+        }
+    }
+
+    references
+}
+
+/// Replace the URLs in a stylesheet, or a list of declarations,
+/// which `f` returns a new URL for, returning the new CSS and
+/// how many were replaced.
+///
+/// `f` is given the URL as written, with any escapes resolved.
+/// Comments are left untouched.
+pub(crate) fn rewrite_css(
+    css: &str,
+    f: &mut impl FnMut(&str) -> Option<String>,
+) -> (String, usize) {
+    let mut rewritten = String::with_capacity(css.len());
+    let mut count = 0;
+    let mut rest = css;
+
+    loop {
+        let (code, comment, remainder) = match rest.find("/*") {
+            Some(start) => {
+                let end = rest[start + 2..]
+                    .find("*/")
+                    .map_or(rest.len(), |end| start + 2 + end + 2);
+                (&rest[..start], &rest[start..end], &rest[end..])
+            }
+            None => (rest, "", ""),
+        };
+
+        count += rewrite_css_code(code, f, &mut rewritten);
+        rewritten.push_str(comment);
+
+        if remainder.is_empty() {
+            return (rewritten, count);
+        }
+        rest = remainder;
+    }
+}
+
+/// Rewrite CSS which contains no comments, appending it to `rewritten`.
+fn rewrite_css_code(
+    css: &str,
+    f: &mut impl FnMut(&str) -> Option<String>,
+    rewritten: &mut String,
+) -> usize {
+    let mut count = 0;
+    let mut rest = css;
+
+    while let Some(position) = find_url_or_import(rest) {
+        rewritten.push_str(&rest[..position]);
+        rest = &rest[position..];
+
+        if starts_with_ignore_case(rest, "@import") {
+            let after_keyword = rest["@import".len()..].trim_start();
+            rewritten.push_str(&rest[..rest.len() - after_keyword.len()]);
+            rest = after_keyword;
+
+            // `@import url(...)` is left for the next iteration
+            if let Some((value, remainder)) = css_string(rest) {
+                match f(&value) {
+                    Some(url) => {
+                        rewritten.push_str(&quote_css(&url));
+                        count += 1;
+                    }
+                    None => rewritten.push_str(&rest[..rest.len() - remainder.len()]),
+                }
+                rest = remainder;
+            }
+        } else if let Some((value, remainder)) = css_url(rest) {
+            match f(&value) {
+                Some(url) => {
+                    rewritten.push_str("url(");
+                    rewritten.push_str(&quote_css(&url));
+                    rewritten.push(')');
+                    count += 1;
+                }
+                None => rewritten.push_str(&rest[..rest.len() - remainder.len()]),
+            }
+            rest = remainder;
+        } else {
+            rewritten.push_str(&rest[.."url(".len()]);
+            rest = &rest["url(".len()..];
+        }
+    }
+
+    rewritten.push_str(rest);
+    count
+}
+
+/// Write a value as a double-quoted CSS string.
+fn quote_css(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\a "),
+            _ => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+fn strip_css_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => return stripped,
+        }
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+fn starts_with_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .get(..needle.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(needle))
+}
+
+/// Find the next `@import` or `url(` token.
+fn find_url_or_import(css: &str) -> Option<usize> {
+    css.char_indices().map(|(index, _)| index).find(|&index| {
+        let rest = &css[index..];
+        starts_with_ignore_case(rest, "@import") || starts_with_ignore_case(rest, "url(")
+    })
+}
+
+/// Parse a quoted CSS string, returning its contents and the remaining input.
+fn css_string(css: &str) -> Option<(String, &str)> {
+    let quote = css.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = css[1..].find(quote)? + 1;
+
+    Some((unescape_css(&css[1..end]), &css[end + 1..]))
+}
+
+/// Parse a `url()` function, returning its value and the remaining input.
+fn css_url(css: &str) -> Option<(String, &str)> {
+    if !starts_with_ignore_case(css, "url(") {
+        return None;
+    }
+
+    let inner = css["url(".len()..].trim_start();
+
+    let (value, rest) = match css_string(inner) {
+        Some((value, rest)) => (value, rest),
+        None => {
+            let end = inner.find(')')?;
+            (unescape_css(inner[..end].trim_end()), &inner[end..])
+        }
+    };
+
+    let rest = rest.trim_start();
+    Some((value, rest.strip_prefix(')').unwrap_or(rest)))
+}
+
+/// Resolve the backslash escapes which may appear in CSS URLs.
+fn unescape_css(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        let mut hex = String::new();
+        while hex.len() < 6 && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(chars.next().unwrap());
+        }
+
+        if hex.is_empty() {
+            if let Some(escaped) = chars.next() {
+                unescaped.push(escaped);
+            }
+        } else {
+            if chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                chars.next();
+            }
+
+            unescaped.extend(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32));
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReferenceKind::*;
+    use crate::test_support::text_resource;
+    use crate::WebResource;
+
+    fn references(resource: &WebResource) -> Vec<(String, super::ReferenceKind)> {
+        resource
+            .references()
+            .expect("Could not collect references")
+            .into_iter()
+            .map(|reference| (reference.url, reference.kind))
+            .collect()
+    }
+
+    #[test]
+    fn html_references() {
+        let page = text_resource(
+            "https://example.com/dir/page.html",
+            "text/html",
+            r##"<html><head>
+            <link rel="stylesheet" href="style.css">
+            <link rel="canonical" href="https://example.com/elsewhere">
+            <link rel="shortcut icon" href="/favicon.ico">
+            <script src="app.js?a=1&amp;b=2"></script>
+            <style>body { background: url("bg.png") } /* url(commented.png) */</style>
+            </head><body background="tile.gif">
+            <img src="a.png" srcset="a-2x.png 2x, a-3x.png 3x">
+            <img src="data:image/png;base64,AAAA">
+            <a href="#top" style="background-image: url(icon.svg)">top</a>
+            <iframe src="//other.example/frame.html"></iframe>
+            </body></html>"##,
+        );
+
+        assert_eq!(
+            references(&page),
+            [
+                ("https://example.com/dir/style.css", Stylesheet),
+                ("https://example.com/favicon.ico", Icon),
+                ("https://example.com/dir/app.js?a=1&b=2", Script),
+                ("https://example.com/dir/bg.png", CssUrl),
+                ("https://example.com/dir/tile.gif", Image),
+                ("https://example.com/dir/a.png", Image),
+                ("https://example.com/dir/a-2x.png", Image),
+                ("https://example.com/dir/a-3x.png", Image),
+                ("https://example.com/dir/icon.svg", CssUrl),
+                ("https://other.example/frame.html", Frame),
+            ]
+            .map(|(url, kind)| (url.to_string(), kind))
+        );
+    }
+
+    #[test]
+    fn html_base_element() {
+        let page = text_resource(
+            "https://example.com/page.html",
+            "text/html",
+            r#"<base href="https://cdn.example/assets/"><img src="a.png">"#,
+        );
+
+        assert_eq!(
+            references(&page),
+            [("https://cdn.example/assets/a.png".to_string(), Image)]
+        );
+    }
+
+    #[test]
+    fn css_references() {
+        let stylesheet = text_resource(
+            "https://example.com/css/main.css",
+            "text/css; charset=utf-8",
+            r#"@import "reset.css";
+            @import url('print.css') print;
+            /* @import "ignored.css"; */
+            @font-face { src: URL( ../fonts/f\2e woff2 ) }
+            .logo { background: url(data:image/gif;base64,R0lG) }"#,
+        );
+
+        assert_eq!(
+            references(&stylesheet),
+            [
+                ("https://example.com/css/reset.css", CssImport),
+                ("https://example.com/css/print.css", CssImport),
+                ("https://example.com/fonts/f.woff2", CssUrl),
+            ]
+            .map(|(url, kind)| (url.to_string(), kind))
+        );
+    }
+
+    #[test]
+    fn missing_subresources() {
+        let psxdatacenter: crate::WebArchive =
+            crate::from_file("fixtures/psxdatacenter.webarchive")
+                .expect("Could not read psxdatacenter webarchive fixture");
+
+        let crouton: crate::WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read Crouton webarchive fixture");
+
+        assert!(crouton
+            .missing_subresources()
+            .expect("Could not collect references")
+            .is_empty());
+
+        let missing = psxdatacenter
+            .missing_subresources()
+            .expect("Could not collect references");
+        let mut urls: Vec<&str> = missing.iter().map(|r| r.url.as_str()).collect();
+        urls.sort_unstable();
+        urls.dedup();
+        assert_eq!(urls.len(), missing.len());
+    }
+
+    #[test]
+    fn completeness() {
+        let crouton: crate::WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read Crouton webarchive fixture");
+        let completeness = crouton
+            .completeness()
+            .expect("Could not collect references");
+        assert_eq!(
+            completeness,
+            super::Completeness {
+                referenced: 1,
+                stored: 1,
+                missing: 0,
+            }
+        );
+        assert!(completeness.is_complete());
+        assert!(crouton.is_complete().unwrap());
+
+        // missing.png was left out when it was archived
+        let fragments: crate::WebArchive = crate::from_file("fixtures/fragments.webarchive")
+            .expect("Could not read fragments webarchive fixture");
+        let completeness = fragments
+            .completeness()
+            .expect("Could not collect references");
+        assert_eq!(
+            completeness,
+            super::Completeness {
+                referenced: 3,
+                stored: 2,
+                missing: 1,
+            }
+        );
+        assert_eq!(completeness.ratio(), 2.0 / 3.0);
+        assert!(!fragments.is_complete().unwrap());
+
+        // nothing referred to is nothing missing
+        let image_only: crate::WebArchive = crate::from_file("fixtures/image-only.webarchive")
+            .expect("Could not read image-only webarchive fixture");
+        assert_eq!(image_only.completeness().unwrap().ratio(), 1.0);
+    }
+
+    #[test]
+    fn missing_subresources_with_fragments() {
+        let fragments: crate::WebArchive = crate::from_file("fixtures/fragments.webarchive")
+            .expect("Could not read fragments webarchive fixture");
+
+        // references to part of a stored resource are satisfied by it
+        let missing = fragments
+            .missing_subresources()
+            .expect("Could not collect references");
+        assert_eq!(
+            missing
+                .iter()
+                .map(|reference| reference.url.as_str())
+                .collect::<Vec<_>>(),
+            ["http://example.com/images/missing.png#top"]
+        );
+
+        let missing = fragments
+            .missing_subresources_with(crate::UrlNormalization::exact())
+            .expect("Could not collect references");
+        assert_eq!(missing.len(), 4);
+    }
+}
@@ -0,0 +1,644 @@
+//! Capturing pages by rendering them in Chrome or Chromium, driven over
+//! the Chrome DevTools Protocol, so pages built by scripts are archived
+//! as they are shown.
+
+use crate::{
+    CaptureInfo, Error, FetchFailure, FetchReport, Headers, ResourceResponse, Result, WebArchive,
+    WebResource,
+};
+use chromiumoxide::cdp::browser_protocol::dom::{GetDocumentParams, GetOuterHtmlParams, Node};
+use chromiumoxide::cdp::browser_protocol::network::{
+    self, EventLoadingFailed, EventLoadingFinished, EventRequestWillBeSent, EventResponseReceived,
+    GetResponseBodyParams, RequestId, ResourceType,
+};
+use chromiumoxide::cdp::browser_protocol::page::FrameId;
+use chromiumoxide::{Browser, Page};
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often to check whether a page has finished loading.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Options for [`WebArchive::render`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Where the browser's DevTools listen: an `http` address, such as
+    /// that of a browser started with `--remote-debugging-port=9222`,
+    /// or the `ws` URL of its debugger.
+    pub browser_url: String,
+
+    /// Wait for an element matching this CSS selector to appear, rather
+    /// than for the network to go quiet.
+    pub wait_selector: Option<String>,
+
+    /// How long no requests must be in flight for the page to count as
+    /// loaded.
+    pub idle_time: Duration,
+
+    /// The longest to wait for the page to load. Without a
+    /// [`wait_selector`](RenderOptions::wait_selector), a page whose
+    /// network never goes quiet is captured as it is by then.
+    pub timeout: Duration,
+
+    /// Record when and how the page was captured, in a [`CaptureInfo`]
+    /// stored in the archive.
+    pub capture_info: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            browser_url: "http://127.0.0.1:9222".to_string(),
+            wait_selector: None,
+            idle_time: Duration::from_millis(500),
+            timeout: Duration::from_secs(30),
+            capture_info: true,
+        }
+    }
+}
+
+/// What the browser reported about one request
+struct Request {
+    response: Option<Arc<EventResponseReceived>>,
+    finished: bool,
+    failure: Option<String>,
+}
+
+/// The requests a page made, in the order it made them
+#[derive(Default)]
+struct Network {
+    order: Vec<RequestId>,
+    requests: HashMap<RequestId, Request>,
+    in_flight: HashSet<RequestId>,
+    last_activity: Option<Instant>,
+}
+
+enum NetworkEvent {
+    Sent(Arc<EventRequestWillBeSent>),
+    Received(Arc<EventResponseReceived>),
+    Finished(Arc<EventLoadingFinished>),
+    Failed(Arc<EventLoadingFailed>),
+}
+
+impl Network {
+    fn record(&mut self, event: NetworkEvent) {
+        self.last_activity = Some(Instant::now());
+        match event {
+            NetworkEvent::Sent(event) => {
+                // redirects are sent again with the same ID
+                if !self.requests.contains_key(&event.request_id) {
+                    self.order.push(event.request_id.clone());
+                }
+                self.requests.insert(
+                    event.request_id.clone(),
+                    Request {
+                        response: None,
+                        finished: false,
+                        failure: None,
+                    },
+                );
+                self.in_flight.insert(event.request_id.clone());
+            }
+            NetworkEvent::Received(event) => {
+                if let Some(request) = self.requests.get_mut(&event.request_id) {
+                    request.response = Some(event);
+                }
+            }
+            NetworkEvent::Finished(event) => {
+                if let Some(request) = self.requests.get_mut(&event.request_id) {
+                    request.finished = true;
+                }
+                self.in_flight.remove(&event.request_id);
+            }
+            NetworkEvent::Failed(event) => {
+                if let Some(request) = self.requests.get_mut(&event.request_id) {
+                    request.failure = Some(event.error_text.clone());
+                }
+                self.in_flight.remove(&event.request_id);
+            }
+        }
+    }
+
+    /// Whether nothing has been in flight for `idle_time`
+    fn is_idle(&self, idle_time: Duration) -> bool {
+        self.in_flight.is_empty()
+            && self
+                .last_activity
+                .is_none_or(|last_activity| last_activity.elapsed() >= idle_time)
+    }
+}
+
+/// A frame of the rendered page, and those inside it
+struct RenderedFrame {
+    id: Option<FrameId>,
+    url: String,
+    name: Option<String>,
+    html: String,
+    subframes: Vec<RenderedFrame>,
+}
+
+impl WebArchive {
+    /// Make an archive of the page at `url` by loading it in the
+    /// browser [`options`](RenderOptions::browser_url) connect to, so
+    /// that pages built by their scripts are archived as they are shown.
+    ///
+    /// Once the page has loaded, and the network has gone quiet or the
+    /// [`wait_selector`](RenderOptions::wait_selector) matches, its
+    /// document, as the scripts left it, is the main resource. The
+    /// responses the browser received are its subresources, so assets
+    /// loaded by scripts are archived with the bytes the page used.
+    /// Frames' documents become subframe archives, holding the
+    /// responses they requested.
+    ///
+    /// Fails if the browser can not be reached, the page can not be
+    /// loaded, or no element matches the selector before the
+    /// [`timeout`](RenderOptions::timeout). Responses whose bodies the
+    /// browser didn't keep, and requests which failed, are listed in
+    /// the report instead.
+    pub async fn render(url: &str, options: &RenderOptions) -> Result<(WebArchive, FetchReport)> {
+        let (browser, mut handler) = Browser::connect(options.browser_url.as_str()).await?;
+        let handling = tokio::spawn(async move {
+            while let Some(event) = handler.next().await {
+                if event.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = render_in(&browser, url, options).await;
+
+        // the browser is the user's, so it is left running
+        handling.abort();
+        result
+    }
+}
+
+async fn render_in(
+    browser: &Browser,
+    url: &str,
+    options: &RenderOptions,
+) -> Result<(WebArchive, FetchReport)> {
+    let capture_info = CaptureInfo::new(url, browser.user_agent().await.ok());
+    let page = browser.new_page("about:blank").await?;
+    let result = render_page(&page, url, options).await;
+    let _ = page.close().await;
+
+    let (mut archive, report) = result?;
+    if options.capture_info {
+        archive.embed_capture_info(&capture_info);
+    }
+    Ok((archive, report))
+}
+
+async fn render_page(
+    page: &Page,
+    url: &str,
+    options: &RenderOptions,
+) -> Result<(WebArchive, FetchReport)> {
+    let network = Arc::new(Mutex::new(Network::default()));
+    let events = futures::stream::select_all([
+        page.event_listener::<EventRequestWillBeSent>()
+            .await?
+            .map(NetworkEvent::Sent)
+            .boxed(),
+        page.event_listener::<EventResponseReceived>()
+            .await?
+            .map(NetworkEvent::Received)
+            .boxed(),
+        page.event_listener::<EventLoadingFinished>()
+            .await?
+            .map(NetworkEvent::Finished)
+            .boxed(),
+        page.event_listener::<EventLoadingFailed>()
+            .await?
+            .map(NetworkEvent::Failed)
+            .boxed(),
+    ]);
+    let recording = tokio::spawn({
+        let network = network.clone();
+        events.for_each(move |event| {
+            network.lock().expect("Network lock poisoned").record(event);
+            futures::future::ready(())
+        })
+    });
+
+    let result = async {
+        page.execute(network::EnableParams::default()).await?;
+        let deadline = Instant::now() + options.timeout;
+        tokio::time::timeout(options.timeout, page.goto(url))
+            .await
+            .map_err(|_| Error::Browser(format!("{} did not load in time", url)))??;
+        wait_until_loaded(page, &network, options, deadline).await?;
+
+        let frame = rendered_frame(page).await?;
+        harvest(page, &network, frame).await
+    }
+    .await;
+
+    recording.abort();
+    result
+}
+
+/// Wait until an element matches the selector, or else until the
+/// network has been quiet long enough or `deadline` passes
+async fn wait_until_loaded(
+    page: &Page,
+    network: &Mutex<Network>,
+    options: &RenderOptions,
+    deadline: Instant,
+) -> Result<()> {
+    loop {
+        match &options.wait_selector {
+            Some(selector) => {
+                if page.find_element(selector.as_str()).await.is_ok() {
+                    return Ok(());
+                }
+                if Instant::now() >= deadline {
+                    return Err(Error::Browser(format!(
+                        "nothing matched {:?} in time",
+                        selector
+                    )));
+                }
+            }
+            None => {
+                let network = network.lock().expect("Network lock poisoned");
+                if network.is_idle(options.idle_time) || Instant::now() >= deadline {
+                    return Ok(());
+                }
+            }
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// The page's document and those of its frames, as they are now
+async fn rendered_frame(page: &Page) -> Result<RenderedFrame> {
+    let document = page
+        .execute(GetDocumentParams {
+            depth: Some(-1),
+            pierce: Some(true),
+        })
+        .await?
+        .result
+        .root;
+
+    let mut frame = RenderedFrame {
+        id: page.mainframe().await?,
+        url: document.document_url.clone().unwrap_or_default(),
+        name: None,
+        html: outer_html(page, &document).await?,
+        subframes: Vec::new(),
+    };
+    collect_subframes(page, &document, &mut frame.subframes).await?;
+    Ok(frame)
+}
+
+/// Add the frames whose elements are within `node` to `subframes`,
+/// in document order
+async fn collect_subframes(
+    page: &Page,
+    node: &Node,
+    subframes: &mut Vec<RenderedFrame>,
+) -> Result<()> {
+    for child in node.children.iter().flatten() {
+        match &child.content_document {
+            Some(document) => {
+                let mut frame = RenderedFrame {
+                    id: child.frame_id.clone(),
+                    url: document.document_url.clone().unwrap_or_default(),
+                    name: attribute(child, "name"),
+                    html: outer_html(page, document).await?,
+                    subframes: Vec::new(),
+                };
+                Box::pin(collect_subframes(page, document, &mut frame.subframes)).await?;
+                subframes.push(frame);
+            }
+            None => Box::pin(collect_subframes(page, child, subframes)).await?,
+        }
+    }
+    Ok(())
+}
+
+async fn outer_html(page: &Page, node: &Node) -> Result<String> {
+    let params = GetOuterHtmlParams {
+        node_id: Some(node.node_id),
+        ..Default::default()
+    };
+    Ok(page.execute(params).await?.result.outer_html)
+}
+
+/// The value of the attribute `name` of an element
+fn attribute(node: &Node, name: &str) -> Option<String> {
+    node.attributes
+        .as_ref()?
+        .chunks(2)
+        .find(|pair| pair[0].eq_ignore_ascii_case(name))
+        .and_then(|pair| pair.get(1).cloned())
+}
+
+/// An archive of `frame`, with the responses the page received as the
+/// subresources of the frames which requested them
+async fn harvest(
+    page: &Page,
+    network: &Mutex<Network>,
+    frame: RenderedFrame,
+) -> Result<(WebArchive, FetchReport)> {
+    let mut report = FetchReport::default();
+    let mut documents: HashMap<FrameId, WebResource> = HashMap::new();
+    let mut subresources: HashMap<Option<FrameId>, Vec<WebResource>> = HashMap::new();
+    let mut seen = HashSet::new();
+
+    let requests: Vec<_> = {
+        let mut network = network.lock().expect("Network lock poisoned");
+        let order = std::mem::take(&mut network.order);
+        order
+            .into_iter()
+            .filter_map(|id| Some((id.clone(), network.requests.remove(&id)?)))
+            .collect()
+    };
+
+    for (id, request) in requests {
+        let Some(received) = request.response else {
+            if let Some(reason) = request.failure {
+                report.failed.push(FetchFailure {
+                    url: id.inner().clone(),
+                    reason,
+                });
+            }
+            continue;
+        };
+        let response = &received.response;
+        if !response.url.starts_with("http") || !request.finished {
+            continue;
+        }
+
+        let is_document = received.r#type == ResourceType::Document;
+        // a frame's document is its rendered DOM, not the HTML it
+        // was sent, so only its response is kept
+        let data = if is_document {
+            Vec::new()
+        } else {
+            if !seen.insert((received.frame_id.clone(), response.url.clone())) {
+                continue;
+            }
+            match response_body(page, &id).await {
+                Ok(data) => data,
+                Err(error) => {
+                    report.failed.push(FetchFailure {
+                        url: response.url.clone(),
+                        reason: error.to_string(),
+                    });
+                    continue;
+                }
+            }
+        };
+
+        let resource = resource(response, data)?;
+        match (is_document, &received.frame_id) {
+            (true, Some(frame_id)) => {
+                documents.insert(frame_id.clone(), resource);
+            }
+            (true, None) => {}
+            (false, frame_id) => {
+                report.fetched.push(resource.url.clone());
+                subresources
+                    .entry(frame_id.clone())
+                    .or_default()
+                    .push(resource);
+            }
+        }
+    }
+
+    // responses from frames which are gone are kept with the page
+    let mut archive = frame_archive(frame, &mut documents, &mut subresources);
+    let orphans = subresources.into_values().flatten();
+    let mut orphans = orphans.peekable();
+    if orphans.peek().is_some() {
+        archive
+            .subresources
+            .get_or_insert_with(Vec::new)
+            .extend(orphans);
+    }
+
+    Ok((archive, report))
+}
+
+fn frame_archive(
+    frame: RenderedFrame,
+    documents: &mut HashMap<FrameId, WebResource>,
+    subresources: &mut HashMap<Option<FrameId>, Vec<WebResource>>,
+) -> WebArchive {
+    let response = frame
+        .id
+        .as_ref()
+        .and_then(|id| documents.remove(id))
+        .and_then(|document| document.response);
+    let main_resource = WebResource {
+        url: frame.url,
+        data: frame.html.into_bytes(),
+        mime_type: "text/html".to_string(),
+        text_encoding_name: Some("UTF-8".to_string()),
+        frame_name: frame.name,
+        response,
+    };
+
+    let own = subresources.remove(&frame.id).unwrap_or_default();
+    let subframes: Vec<WebArchive> = frame
+        .subframes
+        .into_iter()
+        .map(|subframe| frame_archive(subframe, documents, subresources))
+        .collect();
+
+    WebArchive {
+        main_resource,
+        subresources: (!own.is_empty()).then_some(own),
+        subframe_archives: (!subframes.is_empty()).then_some(subframes),
+    }
+}
+
+async fn response_body(page: &Page, id: &RequestId) -> Result<Vec<u8>> {
+    let body = page
+        .execute(GetResponseBodyParams::new(id.clone()))
+        .await?
+        .result;
+    if body.base64_encoded {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode(&body.body)
+            .map_err(|error| Error::Browser(error.to_string()))
+    } else {
+        Ok(body.body.into_bytes())
+    }
+}
+
+/// A resource of a response the browser received, with its status and
+/// headers archived
+fn resource(response: &network::Response, data: Vec<u8>) -> Result<WebResource> {
+    let mut headers = Headers::default();
+    if let Some(received) = response.headers.inner().as_object() {
+        for (name, value) in received {
+            // repeated headers are joined with newlines
+            for value in value.as_str().unwrap_or_default().split('\n') {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    let archived = ResourceResponse::new(
+        Some(response.url.clone()),
+        u16::try_from(response.status).ok(),
+        headers,
+        Some(response.mime_type.clone()),
+    );
+
+    Ok(WebResource {
+        url: response.url.clone(),
+        data,
+        mime_type: response.mime_type.clone(),
+        text_encoding_name: (!response.charset.is_empty()).then(|| response.charset.clone()),
+        frame_name: None,
+        response: Some(archived.to_bytes()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderOptions;
+    use crate::test_server::{Response, TestServer};
+    use crate::{Error, WebArchive};
+    use chromiumoxide::{Browser, BrowserConfig};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    /// Launch a headless browser, returning its DevTools address, or
+    /// `None` where Chrome or Chromium isn't installed
+    async fn launch() -> Option<(Browser, String)> {
+        let config = BrowserConfig::builder().no_sandbox().build().ok()?;
+        let (browser, mut handler) = Browser::launch(config).await.ok()?;
+        tokio::spawn(async move { while handler.next().await.is_some() {} });
+        let address = browser.websocket_address().clone();
+        Some((browser, address))
+    }
+
+    fn page_server() -> TestServer {
+        TestServer::start(|request| {
+            let (body, mime_type): (&[u8], _) = match request.path.as_str() {
+                "/" => (
+                    concat!(
+                        "<!DOCTYPE html><title>Rendered</title>",
+                        "<iframe name=side src=/frame.html></iframe>",
+                        "<script>setTimeout(() => {",
+                        "  let image = new Image();",
+                        "  image.id = 'late';",
+                        "  image.src = '/late.png';",
+                        "  document.body.append(image);",
+                        "}, 100)</script>",
+                    )
+                    .as_bytes(),
+                    "text/html",
+                ),
+                "/frame.html" => (
+                    b"<link rel=stylesheet href=/frame.css><p>Frame</p>",
+                    "text/html",
+                ),
+                "/frame.css" => (b"p { color: red }", "text/css"),
+                "/late.png" => (b"\x89PNG\r\n\x1a\n", "image/png"),
+                _ => return Response::status(404),
+            };
+            Response::ok(body.to_vec()).header("Content-Type", mime_type)
+        })
+    }
+
+    #[test]
+    fn render() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let Some((_browser, browser_url)) = runtime.block_on(launch()) else {
+            eprintln!("skipping, as no browser could be launched");
+            return;
+        };
+        let server = page_server();
+        let options = RenderOptions {
+            browser_url,
+            ..Default::default()
+        };
+
+        let (archive, report) = runtime
+            .block_on(WebArchive::render(&server.url("/"), &options))
+            .unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+
+        // the document as the script left it
+        let main = &archive.main_resource;
+        assert_eq!(main.url, server.url("/"));
+        assert_eq!(main.mime_type, "text/html");
+        let html = String::from_utf8(main.data.clone()).unwrap();
+        assert!(html.contains("id=\"late\""), "{}", html);
+        let response = main.parse_response().unwrap().unwrap();
+        assert_eq!(response.status, Some(200));
+
+        let late = archive
+            .get_resource_by_url(&server.url("/late.png"))
+            .unwrap();
+        assert_eq!(late.data, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(late.mime_type, "image/png");
+
+        let subframes = archive.subframe_archives.as_ref().unwrap();
+        assert_eq!(subframes.len(), 1);
+        let frame = &subframes[0];
+        assert_eq!(frame.main_resource.url, server.url("/frame.html"));
+        assert_eq!(frame.main_resource.frame_name.as_deref(), Some("side"));
+        let subresources = frame.subresources.as_ref().unwrap();
+        assert_eq!(subresources[0].url, server.url("/frame.css"));
+        assert_eq!(subresources[0].data, b"p { color: red }");
+
+        let info = archive.capture_info().unwrap();
+        assert_eq!(info.url, server.url("/"));
+        assert!(info.user_agent.is_some());
+    }
+
+    #[test]
+    fn wait_selector() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let Some((_browser, browser_url)) = runtime.block_on(launch()) else {
+            eprintln!("skipping, as no browser could be launched");
+            return;
+        };
+        let server = page_server();
+
+        let options = RenderOptions {
+            browser_url: browser_url.clone(),
+            wait_selector: Some("#late".to_string()),
+            ..Default::default()
+        };
+        let (archive, _) = runtime
+            .block_on(WebArchive::render(&server.url("/"), &options))
+            .unwrap();
+        assert!(String::from_utf8_lossy(&archive.main_resource.data).contains("late.png"));
+
+        let options = RenderOptions {
+            browser_url,
+            wait_selector: Some("#never".to_string()),
+            timeout: Duration::from_secs(1),
+            ..Default::default()
+        };
+        match runtime.block_on(WebArchive::render(&server.url("/"), &options)) {
+            Err(Error::Browser(message)) => assert!(message.contains("#never"), "{}", message),
+            other => panic!("Unexpected result {:?}", other.map(|(_, report)| report)),
+        }
+    }
+
+    #[test]
+    fn unreachable_browser() {
+        let options = RenderOptions {
+            browser_url: "http://127.0.0.1:9".to_string(),
+            ..Default::default()
+        };
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(WebArchive::render("http://example.com/", &options));
+        assert!(matches!(result, Err(Error::Browser(_))));
+    }
+}
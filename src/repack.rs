@@ -0,0 +1,351 @@
+//! Rebuilding archives from the files extracted from them.
+
+use crate::{Error, ExtractedFile, ExtractionManifest, Headers, ResourceResponse, Result};
+use crate::{WebArchive, WebResource};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Options for [`ExtractionManifest::repack`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepackOptions {
+    /// Add files which are not in the manifest as subresources of the
+    /// root archive, at their path relative to the folder, resolved
+    /// against this URL.
+    pub new_files_base_url: Option<String>,
+}
+
+/// A frame's resources, as they are read back
+#[derive(Default)]
+struct RepackedFrame {
+    main_resource: Option<WebResource>,
+    subresources: Vec<WebResource>,
+}
+
+impl ExtractionManifest {
+    /// Rebuild the archive this manifest's files were extracted from,
+    /// out of their contents in `folder`, which may have been edited.
+    ///
+    /// Each resource gets back its URL, MIME type, encoding and frame
+    /// name, and the frames are rebuilt as they were. Responses written
+    /// beside the files, as the command-line tool's
+    /// `extract --with-responses` does, are attached again, and those
+    /// written as JSON are turned back into property lists.
+    ///
+    /// Fails with [`Error::IncompleteExtraction`] if any frame's main
+    /// resource, or any frame holding another, is not in the manifest,
+    /// as when only some resources were extracted.
+    pub fn repack(&self, folder: impl AsRef<Path>, options: &RepackOptions) -> Result<WebArchive> {
+        let folder = folder.as_ref();
+        let mut frames: BTreeMap<Vec<usize>, RepackedFrame> = BTreeMap::new();
+
+        for file in &self.files {
+            let resource = WebResource {
+                data: std::fs::read(folder.join(&file.path))?,
+                url: file.url.clone(),
+                frame_name: file.frame_name.clone(),
+                mime_type: file.mime_type.clone(),
+                text_encoding_name: file.text_encoding_name.clone(),
+                response: read_response(folder, file)?,
+            };
+
+            let frame = frames.entry(file.frame.clone()).or_default();
+            if file.is_main_resource && frame.main_resource.is_none() {
+                frame.main_resource = Some(resource);
+            } else {
+                frame.subresources.push(resource);
+            }
+        }
+
+        if let Some(base_url) = &options.new_files_base_url {
+            let base_url = url::Url::parse(base_url)
+                .map_err(|error| Error::InvalidUrl(format!("{:?}: {}", base_url, error)))?;
+            let new = self.new_resources(folder, &base_url)?;
+            frames
+                .entry(Vec::new())
+                .or_default()
+                .subresources
+                .extend(new);
+        }
+
+        for path in frames.keys() {
+            if let Some(missing) = (0..path.len())
+                .map(|depth| &path[..depth])
+                .find(|parent| !frames.contains_key(*parent))
+            {
+                return Err(Error::IncompleteExtraction(format!(
+                    "frame {:?} holds a frame, but has no files",
+                    missing
+                )));
+            }
+        }
+
+        build_frame(&[], &mut frames)
+    }
+
+    /// Read every file in `folder` which this manifest does not list,
+    /// and which is not one of the files an extraction writes beside
+    /// others, as a resource with a URL under `base_url`
+    fn new_resources(&self, folder: &Path, base_url: &url::Url) -> Result<Vec<WebResource>> {
+        let mut known: HashSet<&str> = self.files.iter().map(|file| file.path.as_str()).collect();
+        known.extend(
+            self.files
+                .iter()
+                .filter_map(|file| file.response.as_deref()),
+        );
+        known.insert(ExtractionManifest::FILE_NAME);
+
+        let mut files = Vec::new();
+        let mut folders = vec![PathBuf::new()];
+        while let Some(relative) = folders.pop() {
+            let mut entries = std::fs::read_dir(folder.join(&relative))?
+                .map(|entry| entry.map(|entry| (entry.file_name(), entry)))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (name, entry) in entries {
+                let path = relative.join(name);
+                if entry.file_type()?.is_dir() {
+                    folders.push(path);
+                    continue;
+                }
+
+                let url_path = path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if known.contains(url_path.as_str())
+                    || url_path.ends_with(".response.plist")
+                    || url_path.ends_with(".response.json")
+                {
+                    continue;
+                }
+
+                let url = base_url
+                    .join(&url_path)
+                    .map_err(|error| Error::InvalidUrl(format!("{:?}: {}", url_path, error)))?;
+                files.push(WebResource::from_file(&entry.path(), url.as_str())?);
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Build the archive of the frame at `path`, and those inside it, out
+/// of the resources in `frames`
+fn build_frame(
+    path: &[usize],
+    frames: &mut BTreeMap<Vec<usize>, RepackedFrame>,
+) -> Result<WebArchive> {
+    let frame = frames
+        .remove(path)
+        .expect("Every frame holding another has been checked to have files");
+    let main_resource = frame.main_resource.ok_or_else(|| {
+        Error::IncompleteExtraction(format!("frame {:?} has no main resource", path))
+    })?;
+
+    let children: Vec<Vec<usize>> = frames
+        .keys()
+        .filter(|child| child.len() == path.len() + 1 && child.starts_with(path))
+        .cloned()
+        .collect();
+    let subframe_archives = children
+        .iter()
+        .map(|child| build_frame(child, frames))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(WebArchive {
+        main_resource,
+        subresources: Some(frame.subresources).filter(|resources| !resources.is_empty()),
+        subframe_archives: Some(subframe_archives).filter(|archives| !archives.is_empty()),
+    })
+}
+
+/// The archived response written beside `file`, if there is one, as the
+/// property list archives store
+fn read_response(folder: &Path, file: &ExtractedFile) -> Result<Option<Vec<u8>>> {
+    let path = match &file.response {
+        Some(response) => folder.join(response),
+        None => {
+            let path = folder.join(format!("{}.response.plist", file.path));
+            if !path.exists() {
+                return Ok(None);
+            }
+            path
+        }
+    };
+
+    let data = std::fs::read(&path)?;
+    if path.extension().is_none_or(|extension| extension != "json") {
+        return Ok(Some(data));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&data).map_err(std::io::Error::from)?;
+    let mut headers = Headers::default();
+    for (name, value) in json["headers"].as_object().into_iter().flatten() {
+        if let Some(value) = value.as_str() {
+            headers.append(name, value);
+        }
+    }
+
+    let response = ResourceResponse::new(
+        json["url"].as_str().map(str::to_string),
+        json["status"]
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok()),
+        headers,
+        json["mime_type"].as_str().map(str::to_string),
+    );
+    Ok(Some(response.to_bytes()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RepackOptions;
+    use crate::test_support::text_resource;
+    use crate::{
+        Error, ExtractedFile, ExtractionManifest, Headers, OperationHooks, ResourceResponse,
+        WebArchive, WebResource,
+    };
+
+    fn framed() -> WebArchive {
+        let frame = |url: &str, name: &str, subframes: Option<Vec<WebArchive>>| WebArchive {
+            main_resource: WebResource {
+                frame_name: Some(name.to_string()),
+                ..text_resource(url, "text/html", "<p>Framed</p>")
+            },
+            subresources: None,
+            subframe_archives: subframes,
+        };
+
+        WebArchive {
+            main_resource: text_resource(
+                "https://example.com/",
+                "text/html",
+                "<frameset><frame src=nav.html><frame src=body.html></frameset>",
+            ),
+            subresources: Some(vec![text_resource(
+                "https://example.com/style.css",
+                "text/css",
+                "p { color: teal }",
+            )]),
+            subframe_archives: Some(vec![
+                frame("https://example.com/nav.html", "nav", None),
+                frame(
+                    "https://example.com/body.html",
+                    "body",
+                    Some(vec![frame("https://example.com/inner.html", "inner", None)]),
+                ),
+            ]),
+        }
+    }
+
+    /// Extract `archive` into `folder`, with a manifest
+    fn extract(archive: &WebArchive, folder: &std::path::Path) -> ExtractionManifest {
+        archive
+            .extract_to(folder, &OperationHooks::default())
+            .unwrap();
+        let manifest = ExtractionManifest {
+            files: archive
+                .plan_extraction()
+                .iter()
+                .map(|planned| ExtractedFile::new(planned, planned.resource))
+                .collect(),
+        };
+        manifest.write_to(folder).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn repack_frames() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = framed();
+        let manifest = extract(&archive, temp.path());
+
+        let repacked = manifest
+            .repack(temp.path(), &RepackOptions::default())
+            .unwrap();
+        assert_eq!(repacked, archive);
+
+        // edits are picked up
+        std::fs::write(temp.path().join("example.com/nav.html"), "<p>Edited</p>").unwrap();
+        let repacked = manifest
+            .repack(temp.path(), &RepackOptions::default())
+            .unwrap();
+        let nav = &repacked.subframe_archives.as_ref().unwrap()[0].main_resource;
+        assert_eq!(nav.data, b"<p>Edited</p>");
+        assert_eq!(nav.frame_name.as_deref(), Some("nav"));
+    }
+
+    #[test]
+    fn repack_incomplete() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut manifest = extract(&framed(), temp.path());
+
+        // the body frame's main resource, but not the frame inside it
+        manifest
+            .files
+            .retain(|file| file.url != "https://example.com/body.html");
+
+        assert!(matches!(
+            manifest.repack(temp.path(), &RepackOptions::default()),
+            Err(Error::IncompleteExtraction(_))
+        ));
+    }
+
+    #[test]
+    fn repack_new_files_and_responses() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let archive = WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", "<p>Hello</p>"),
+            subresources: None,
+            subframe_archives: None,
+        };
+        let mut manifest = extract(&archive, temp.path());
+
+        let mut headers = Headers::default();
+        headers.append("Content-Type", "text/html");
+        let response = ResourceResponse::new(
+            Some("https://example.com/".to_string()),
+            Some(200),
+            headers,
+            Some("text/html".to_string()),
+        );
+        std::fs::write(
+            temp.path()
+                .join("example.com/_unnamed_index.shtml.response.json"),
+            serde_json::to_vec(&response).unwrap(),
+        )
+        .unwrap();
+        manifest.files[0].response = Some("example.com/_unnamed_index.shtml.response.json".into());
+
+        std::fs::create_dir(temp.path().join("example.com/images")).unwrap();
+        std::fs::write(
+            temp.path().join("example.com/images/new.png"),
+            b"\x89PNG\r\n\x1a\n",
+        )
+        .unwrap();
+
+        let repacked = manifest
+            .repack(temp.path(), &RepackOptions::default())
+            .unwrap();
+        assert_eq!(repacked.subresources, None);
+        let reparsed = repacked.main_resource.parse_response().unwrap().unwrap();
+        assert_eq!(reparsed.status, Some(200));
+        assert_eq!(reparsed.headers.get("content-type"), Some("text/html"));
+
+        let repacked = manifest
+            .repack(
+                temp.path(),
+                &RepackOptions {
+                    new_files_base_url: Some("https://example.org/".to_string()),
+                },
+            )
+            .unwrap();
+        let new = &repacked.subresources.as_ref().unwrap()[0];
+        assert_eq!(new.url, "https://example.org/example.com/images/new.png");
+        assert_eq!(new.mime_type, "image/png");
+        assert_eq!(repacked.subresources.as_ref().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,402 @@
+use crate::references::element_attributes;
+use crate::{Error, ResourceFilter, Result, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::ops::Range;
+
+/// What [`WebArchive::replace_text`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReplaceReport {
+    /// The URL of each resource which was changed, and how many
+    /// replacements were made within it, in document order.
+    pub resources: Vec<(String, usize)>,
+
+    /// How many matches were left alone because they were
+    /// within an HTML tag which refers to another resource.
+    pub skipped: usize,
+
+    /// The URLs of HTML resources whose archived responses gave their
+    /// length in a `Content-Length` header, which no longer matches
+    /// now that replacements have changed their length.
+    pub stale_lengths: Vec<String>,
+}
+
+impl ReplaceReport {
+    /// How many replacements were made across all resources.
+    pub fn total(&self) -> usize {
+        self.resources.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// Options for [`WebArchive::replace_text_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaceOptions {
+    /// Also replace matches within HTML tags which refer
+    /// to other resources, such as `<img src>`.
+    pub include_references: bool,
+
+    /// Only replace the first match on each line, as `sed` does
+    /// without its `g` flag, rather than every match.
+    pub first_in_line: bool,
+}
+
+impl WebArchive {
+    /// Replace every match for `pattern` in the text of resources which
+    /// match `filter`, at any depth, with `replacement`, which may refer
+    /// to capture groups as in [`Regex::replace_all`](regex::Regex::replace_all).
+    ///
+    /// Resources are decoded as [`decoded_text`](WebResource::decoded_text)
+    /// does, and written back with the same encoding. Resources which are
+    /// not a text type are skipped.
+    ///
+    /// Unless `include_references` is set, matches within the start tag
+    /// of an HTML element which refers to another resource, such as
+    /// `<img src>`, or has a `style` attribute, are left alone, as
+    /// changing them could leave references which no longer lead to
+    /// stored resources. Matches which span such a tag in full, such as
+    /// those removing the whole element, are still replaced.
+    pub fn replace_text(
+        &mut self,
+        pattern: &regex::Regex,
+        replacement: &str,
+        filter: &ResourceFilter,
+        include_references: bool,
+    ) -> Result<ReplaceReport> {
+        self.replace_text_with_options(
+            pattern,
+            replacement,
+            filter,
+            ReplaceOptions {
+                include_references,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like [`replace_text`](WebArchive::replace_text), with more control
+    /// over which matches are replaced.
+    pub fn replace_text_with_options(
+        &mut self,
+        pattern: &regex::Regex,
+        replacement: &str,
+        filter: &ResourceFilter,
+        options: ReplaceOptions,
+    ) -> Result<ReplaceReport> {
+        let mut report = ReplaceReport::default();
+        let replacer = Replacer {
+            pattern,
+            replacement,
+            filter,
+            options,
+        };
+
+        replacer.replace_in_frame(self, &mut report)?;
+        Ok(report)
+    }
+}
+
+struct Replacer<'a> {
+    pattern: &'a regex::Regex,
+    replacement: &'a str,
+    filter: &'a ResourceFilter,
+    options: ReplaceOptions,
+}
+
+impl Replacer<'_> {
+    fn replace_in_frame(&self, archive: &mut WebArchive, report: &mut ReplaceReport) -> Result<()> {
+        self.replace_in_resource(&mut archive.main_resource, true, report)?;
+
+        for subresource in archive.subresources.iter_mut().flatten() {
+            self.replace_in_resource(subresource, false, report)?;
+        }
+
+        for subframe_archive in archive.subframe_archives.iter_mut().flatten() {
+            self.replace_in_frame(subframe_archive, report)?;
+        }
+
+        Ok(())
+    }
+
+    fn replace_in_resource(
+        &self,
+        resource: &mut WebResource,
+        is_main_resource: bool,
+        report: &mut ReplaceReport,
+    ) -> Result<()> {
+        if !self.filter.matches(resource, is_main_resource) {
+            return Ok(());
+        }
+
+        let text = match resource.decoded_text() {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+
+        let protected = if resource.is_html() && !self.options.include_references {
+            reference_tags(&text)?
+        } else {
+            Vec::new()
+        };
+        let mut protected = protected.iter().peekable();
+
+        let mut output = String::with_capacity(text.len());
+        let mut copied_to = 0;
+        let mut count = 0;
+        // where the line holding the last replacement ends, when only replacing the first
+        let mut line_end = None;
+
+        for captures in self.pattern.captures_iter(&text) {
+            let found = captures
+                .get(0)
+                .expect("Captures always include the whole match");
+
+            if line_end.is_some_and(|line_end| found.start() < line_end) {
+                continue;
+            }
+
+            // tags are in document order, so those before this match are done with
+            while protected.next_if(|tag| tag.end <= found.start()).is_some() {}
+
+            let cuts_tag = protected
+                .clone()
+                .take_while(|tag| tag.start < found.end())
+                .any(|tag| found.start() > tag.start || found.end() < tag.end);
+
+            if cuts_tag {
+                report.skipped += 1;
+                continue;
+            }
+
+            output.push_str(&text[copied_to..found.start()]);
+            captures.expand(self.replacement, &mut output);
+            copied_to = found.end();
+            count += 1;
+
+            if self.options.first_in_line {
+                line_end = Some(
+                    text[found.start()..]
+                        .find('\n')
+                        .map_or(text.len(), |offset| found.start() + offset),
+                );
+            }
+        }
+
+        if count > 0 {
+            let old_length = resource.data.len();
+
+            output.push_str(&text[copied_to..]);
+            resource.set_text(&output);
+            report.resources.push((resource.url.clone(), count));
+
+            if resource.is_html() && resource.data.len() != old_length {
+                let expected_length = resource
+                    .parse_response()
+                    .ok()
+                    .flatten()
+                    .and_then(|response| response.expected_content_length());
+
+                if expected_length == Some(old_length as u64) {
+                    report.stale_lengths.push(resource.url.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the start tags of elements which refer to other
+/// resources are in an HTML document, in document order.
+fn reference_tags(html: &str) -> Result<Vec<Range<usize>>> {
+    use lol_html::{element, HtmlRewriter, Settings};
+
+    let tags = RefCell::new(Vec::new());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!("*", |element| {
+                let tag = element.tag_name();
+                let refers = element.has_attribute("style")
+                    || element_attributes(&tag, element)
+                        .iter()
+                        .any(|(attribute, _)| element.has_attribute(attribute));
+
+                if refers {
+                    tags.borrow_mut().push(element.source_location().bytes());
+                }
+
+                Ok(())
+            })],
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    Ok(tags.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::text_resource;
+    use crate::{ResourceFilter, WebArchive};
+    use regex::Regex;
+
+    fn page(html: &str) -> WebArchive {
+        WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", html),
+            subresources: None,
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn replace_shift_jis() {
+        let mut archive: WebArchive = crate::from_file("fixtures/shift_jis.webarchive")
+            .expect("Could not read Shift JIS webarchive fixture");
+        archive.main_resource.text_encoding_name = Some("Shift_JIS".to_string());
+
+        let report = archive
+            .replace_text(
+                &Regex::new("(プレイステーション)の(ゲーム)").unwrap(),
+                "$2 for the $1",
+                &ResourceFilter::default(),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            report.resources,
+            [("http://example.jp/nihongo.html".to_string(), 1)]
+        );
+
+        // still Shift JIS, so decoding as such round-trips
+        let (text, _, malformed) = encoding_rs::SHIFT_JIS.decode(&archive.main_resource.data);
+        assert!(!malformed);
+        assert!(text.contains("<p>ゲーム for the プレイステーション一覧。</p>"));
+        assert!(text.contains("<title>日本語のページ</title>"));
+        assert_eq!(
+            archive.main_resource.text_encoding_name.as_deref(),
+            Some("Shift_JIS")
+        );
+    }
+
+    #[test]
+    fn replace_first_in_line() {
+        let mut archive = page("<p>one one</p>\n<p>one</p>\n<p>two</p>");
+        let options = crate::ReplaceOptions {
+            first_in_line: true,
+            ..Default::default()
+        };
+
+        let report = archive
+            .replace_text_with_options(
+                &Regex::new("one").unwrap(),
+                "1",
+                &ResourceFilter::default(),
+                options,
+            )
+            .unwrap();
+
+        assert_eq!(report.total(), 2);
+        assert_eq!(
+            archive.main_resource.data,
+            b"<p>1 one</p>\n<p>1</p>\n<p>two</p>"
+        );
+    }
+
+    #[test]
+    fn replace_stale_lengths() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        // give the page a response stating its current length
+        let original = archive.subresources.as_ref().unwrap()[0]
+            .response
+            .clone()
+            .unwrap();
+        let mut response = crate::ResourceResponse::from_bytes(&original).unwrap();
+        let length = archive.main_resource.data.len().to_string();
+        response.edit(|headers| headers.set("Content-Length", &length));
+        archive.main_resource.response = Some(response.to_bytes_preserving(&original).unwrap());
+
+        let filter = ResourceFilter::default();
+        let report = archive
+            .replace_text(&Regex::new("white").unwrap(), "black", &filter, false)
+            .unwrap();
+        assert_eq!(report.total(), 1);
+        assert!(report.stale_lengths.is_empty());
+
+        let report = archive
+            .replace_text(&Regex::new("Crouton").unwrap(), "Croutons", &filter, false)
+            .unwrap();
+        assert_eq!((report.total(), report.skipped), (1, 1));
+        assert_eq!(report.stale_lengths, ["https://crouton.net/"]);
+    }
+
+    #[test]
+    fn replace_keeps_references() {
+        let html = r#"<p>example.com</p><img src="https://example.com/a.png" alt="example.com"><a href="https://example.com/">x</a>"#;
+        let pattern = Regex::new(r"example\.com").unwrap();
+
+        let mut archive = page(html);
+        let report = archive
+            .replace_text(&pattern, "example.org", &ResourceFilter::default(), false)
+            .unwrap();
+
+        assert_eq!(report.total(), 2);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(
+            std::str::from_utf8(&archive.main_resource.data).unwrap(),
+            r#"<p>example.org</p><img src="https://example.com/a.png" alt="example.com"><a href="https://example.org/">x</a>"#
+        );
+
+        let mut archive = page(html);
+        let report = archive
+            .replace_text(&pattern, "example.org", &ResourceFilter::default(), true)
+            .unwrap();
+        assert_eq!(report.total(), 4);
+        assert_eq!(report.skipped, 0);
+    }
+
+    #[test]
+    fn replace_whole_elements() {
+        let mut archive = page(
+            r#"<p>Hi</p><script src="https://tracker.example/t.js"></script><script>track()</script>"#,
+        );
+
+        let report = archive
+            .replace_text(
+                &Regex::new(r"<script[^>]*>[^<]*</script>").unwrap(),
+                "",
+                &ResourceFilter {
+                    mime: Some("text/html".to_string()),
+                    ..Default::default()
+                },
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(report.total(), 2);
+        assert_eq!(archive.main_resource.data, b"<p>Hi</p>");
+
+        let report = archive
+            .replace_text(
+                &Regex::new("Hi").unwrap(),
+                "Bye",
+                &ResourceFilter {
+                    subresources_only: true,
+                    ..Default::default()
+                },
+                false,
+            )
+            .unwrap();
+        assert_eq!(report.total(), 0);
+    }
+}
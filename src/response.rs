@@ -0,0 +1,712 @@
+//! Parsing of archived `WebResourceResponse` data.
+//!
+//! Safari stores each resource's `NSURLResponse` as a property list
+//! produced by `NSKeyedArchiver`. Objects in the archive are stored
+//! in a flat `$objects` list, and refer to each other by UID, so the
+//! response is found by following UIDs from `$top`.
+//!
+//! Current versions of Safari number the response's properties, as
+//! `__nsurlrequest_proto_prop_obj_N`. Older versions name them, as
+//! `URL`, `statusCode` and so on, and some tools store those names in
+//! a plain dictionary, without any UIDs.
+
+use crate::{Error, Result, WebArchive, WebResource};
+use plist::{Dictionary, Value};
+use serde::{Serialize, Serializer};
+use std::ops::RangeBounds;
+use std::time::SystemTime;
+
+/// The HTTP headers of a [`ResourceResponse`], in archived order.
+///
+/// Serialised as a map from names to values, in the same order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// The value of the first header called `name`,
+    /// compared case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the headers' names and values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The number of headers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set the header called `name`, compared case-insensitively,
+    /// to `value`, replacing the first header with that name and
+    /// removing any others, or adding it at the end.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            Some(index) => {
+                self.entries[index].1 = value.to_string();
+                let mut position = 0;
+                self.entries.retain(|(key, _)| {
+                    position += 1;
+                    position - 1 == index || !key.eq_ignore_ascii_case(name)
+                });
+            }
+            None => self.entries.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Add a header called `name` at the end, keeping any others
+    /// with that name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// Remove every header called `name`, compared
+    /// case-insensitively, returning how many were removed.
+    pub fn remove(&mut self, name: &str) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        before - self.entries.len()
+    }
+}
+
+impl Serialize for Headers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+/// How an archived response is laid out, as found by
+/// [`ResourceResponse::from_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ResponseLayout {
+    /// An `NSKeyedArchiver` archive with numbered properties,
+    /// as saved by current versions of Safari.
+    Keyed,
+
+    /// Named properties, such as `statusCode` and `allHeaderFields`,
+    /// as saved by older versions of Safari, either in an
+    /// `NSKeyedArchiver` archive or a plain dictionary.
+    Legacy,
+
+    /// Neither, so only the [`raw`](ResourceResponse::raw)
+    /// property list is available.
+    Unknown,
+}
+
+/// The numbered property current archives store headers in.
+pub(crate) const KEYED_HEADERS: &str = "__nsurlrequest_proto_prop_obj_4";
+
+/// Names older archives use for each property of a response.
+const LEGACY_URL: &[&str] = &["URL", "NSURL", "url"];
+const LEGACY_STATUS: &[&str] = &["statusCode", "StatusCode", "status"];
+pub(crate) const LEGACY_HEADERS: &[&str] = &["allHeaderFields", "AllHeaderFields", "headers"];
+const LEGACY_MIME_TYPE: &[&str] = &["MIMEType", "mimeType"];
+
+/// The server's response to the request for a resource,
+/// as archived alongside it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ResourceResponse {
+    /// The URL the response was received from.
+    pub url: Option<String>,
+
+    /// The HTTP status code, if this was an HTTP response.
+    pub status: Option<u16>,
+
+    /// The HTTP response headers.
+    pub headers: Headers,
+
+    /// The MIME type the response was interpreted as.
+    pub mime_type: Option<String>,
+
+    #[serde(skip)]
+    layout: ResponseLayout,
+
+    #[serde(skip)]
+    raw: Value,
+}
+
+impl ResourceResponse {
+    /// A response with the given properties, to store with a resource
+    /// using [`to_bytes`](ResourceResponse::to_bytes).
+    pub fn new(
+        url: Option<String>,
+        status: Option<u16>,
+        headers: Headers,
+        mime_type: Option<String>,
+    ) -> Self {
+        let mut response = ResourceResponse {
+            url,
+            status,
+            headers,
+            mime_type,
+            layout: ResponseLayout::Legacy,
+            raw: Value::Boolean(false),
+        };
+
+        response.raw = response.to_plain();
+        response
+    }
+
+    /// The response as archived data, for
+    /// [`WebResource::response`](crate::WebResource::response).
+    ///
+    /// This writes a new binary property list of a plain dictionary,
+    /// with `URL`, `statusCode`, `allHeaderFields` and `MIMEType`
+    /// entries, rather than the `NSKeyedArchiver` archive Safari
+    /// writes. To change the headers of a response Safari archived,
+    /// use [`to_bytes_preserving`](ResourceResponse::to_bytes_preserving)
+    /// instead.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &self.to_plain())?;
+        Ok(bytes)
+    }
+
+    /// The response as a plain dictionary, in the older layout.
+    /// Headers with the same name are joined with commas.
+    fn to_plain(&self) -> Value {
+        let mut headers = Dictionary::new();
+        for (name, value) in self.headers.iter() {
+            let value = match headers.get(name).and_then(Value::as_string) {
+                Some(previous) => format!("{}, {}", previous, value),
+                None => value.to_string(),
+            };
+            headers.insert(name.to_string(), value.into());
+        }
+
+        let mut response = Dictionary::new();
+        if let Some(url) = &self.url {
+            response.insert(LEGACY_URL[0].to_string(), url.clone().into());
+        }
+        if let Some(status) = self.status {
+            response.insert(LEGACY_STATUS[0].to_string(), u64::from(status).into());
+        }
+        response.insert(LEGACY_HEADERS[0].to_string(), headers.into());
+        if let Some(mime_type) = &self.mime_type {
+            response.insert(LEGACY_MIME_TYPE[0].to_string(), mime_type.clone().into());
+        }
+
+        Value::Dictionary(response)
+    }
+
+    /// Parse archived response data, as found in
+    /// [`WebResource::response`](crate::WebResource::response).
+    ///
+    /// Both the current and older layouts are understood. Data which
+    /// is a property list in neither layout is still returned, with
+    /// only its [`raw`](ResourceResponse::raw) value; an error is only
+    /// returned if it is not a property list at all.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let raw: Value = plist::from_bytes(bytes)?;
+
+        let mut response = ResourceResponse {
+            url: None,
+            status: None,
+            headers: Headers::default(),
+            mime_type: None,
+            layout: ResponseLayout::Unknown,
+            raw: Value::Boolean(false),
+        };
+
+        if let Ok(archive) = KeyedArchive::new(&raw).or_else(|_| KeyedArchive::flat(&raw)) {
+            if let Some(properties) = archive.response() {
+                response.parse_keyed(&archive, properties);
+                if response.layout == ResponseLayout::Unknown {
+                    response.parse_legacy(&archive, properties);
+                }
+            }
+        }
+
+        response.raw = raw;
+        Ok(response)
+    }
+
+    fn parse_keyed(&mut self, archive: &KeyedArchive, response: &Dictionary) {
+        let property = |index: usize| {
+            archive.resolve(response.get(&format!("__nsurlrequest_proto_prop_obj_{}", index))?)
+        };
+
+        if !response
+            .keys()
+            .any(|key| key.starts_with("__nsurlrequest_proto_prop_obj_"))
+        {
+            return;
+        }
+
+        self.layout = ResponseLayout::Keyed;
+        self.url = property(0).and_then(|url| archive.url(url));
+        self.status = property(3)
+            .and_then(Value::as_unsigned_integer)
+            .and_then(|status| u16::try_from(status).ok());
+        self.headers = property(4)
+            .map(|headers| archive.headers(headers))
+            .unwrap_or_default();
+        self.mime_type = property(6).and_then(Value::as_string).map(str::to_string);
+    }
+
+    fn parse_legacy(&mut self, archive: &KeyedArchive, response: &Dictionary) {
+        let property = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| archive.resolve(response.get(name)?))
+        };
+
+        let url = property(LEGACY_URL);
+        let status = property(LEGACY_STATUS);
+        let headers = property(LEGACY_HEADERS);
+        let mime_type = property(LEGACY_MIME_TYPE);
+
+        if url.is_none() && status.is_none() && headers.is_none() && mime_type.is_none() {
+            return;
+        }
+
+        self.layout = ResponseLayout::Legacy;
+        self.url = url.and_then(|url| archive.url(url));
+        self.status = status
+            .and_then(Value::as_unsigned_integer)
+            .and_then(|status| u16::try_from(status).ok());
+        self.headers = headers
+            .map(|headers| archive.headers(headers))
+            .unwrap_or_default();
+        self.mime_type = mime_type.and_then(Value::as_string).map(str::to_string);
+    }
+
+    /// Change the response's headers with `f`.
+    ///
+    /// Use [`to_bytes_preserving`](ResourceResponse::to_bytes_preserving)
+    /// to store the result.
+    pub fn edit(&mut self, f: impl FnOnce(&mut Headers)) {
+        f(&mut self.headers);
+    }
+
+    /// How the archived response was laid out.
+    pub fn layout(&self) -> ResponseLayout {
+        self.layout
+    }
+
+    /// The archived response as it was stored, for reading
+    /// anything this type does not understand.
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    /// The length of the response body, from its `Content-Length` header.
+    pub fn expected_content_length(&self) -> Option<u64> {
+        self.headers.get("Content-Length")?.trim().parse().ok()
+    }
+
+    /// When the response was sent, from its `Date` header.
+    pub fn date(&self) -> Option<SystemTime> {
+        self.header_date("Date")
+    }
+
+    /// When the resource was last changed, from its `Last-Modified` header.
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        self.header_date("Last-Modified")
+    }
+
+    fn header_date(&self, name: &str) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.headers.get(name)?.trim()).ok()
+    }
+}
+
+impl WebResource {
+    /// Parse the archived response for this resource,
+    /// if it has one.
+    pub fn parse_response(&self) -> Result<Option<ResourceResponse>> {
+        self.response
+            .as_deref()
+            .map(ResourceResponse::from_bytes)
+            .transpose()
+    }
+
+    /// The HTTP status code of the archived response, if the
+    /// resource has one which can be parsed.
+    pub fn response_status(&self) -> Option<u16> {
+        self.parse_response().ok()??.status
+    }
+}
+
+impl WebArchive {
+    /// The subresources, at any depth, whose archived response has
+    /// an HTTP error status, of 400 or above, in document order.
+    ///
+    /// These usually hold an error page, rather than what the page
+    /// asked for.
+    pub fn error_resources(&self) -> Vec<&WebResource> {
+        self.iter_frames()
+            .flat_map(|(_, frame)| frame.subresources.iter().flatten())
+            .filter(|subresource| {
+                subresource
+                    .response_status()
+                    .is_some_and(|status| status >= 400)
+            })
+            .collect()
+    }
+
+    /// Remove, and return, the subresources, at any depth, whose
+    /// archived response has an HTTP status within `statuses`, such as
+    /// `400..600`.
+    ///
+    /// Resources without a response which can be parsed are kept.
+    pub fn strip_error_responses(&mut self, statuses: impl RangeBounds<u16>) -> Vec<WebResource> {
+        self.strip(|subresource| {
+            subresource
+                .response_status()
+                .is_some_and(|status| statuses.contains(&status))
+        })
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidResponse(message.to_string())
+}
+
+/// Follows UIDs within an `NSKeyedArchiver` property list.
+///
+/// A plain dictionary can be read in the same way, as one
+/// with no objects to refer to.
+struct KeyedArchive<'a> {
+    top: &'a Dictionary,
+    objects: &'a [Value],
+}
+
+impl<'a> KeyedArchive<'a> {
+    fn new(archive: &'a Value) -> Result<Self> {
+        let archive = archive
+            .as_dictionary()
+            .ok_or_else(|| invalid("not a dictionary"))?;
+
+        Ok(KeyedArchive {
+            top: archive
+                .get("$top")
+                .and_then(Value::as_dictionary)
+                .ok_or_else(|| invalid("no $top dictionary"))?,
+            objects: archive
+                .get("$objects")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("no $objects list"))?,
+        })
+    }
+
+    /// Treat a plain dictionary as an archive without UIDs.
+    fn flat(archive: &'a Value) -> Result<Self> {
+        Ok(KeyedArchive {
+            top: archive
+                .as_dictionary()
+                .ok_or_else(|| invalid("not a dictionary"))?,
+            objects: &[],
+        })
+    }
+
+    fn top(&self, key: &str) -> Option<&'a Value> {
+        self.resolve(self.top.get(key)?)
+    }
+
+    /// The dictionary of the response's properties: the
+    /// `WebResourceResponse` object, or a flat archive's top level.
+    fn response(&self) -> Option<&'a Dictionary> {
+        match self.top("WebResourceResponse") {
+            Some(response) => response.as_dictionary(),
+            None if self.objects.is_empty() => Some(self.top),
+            None => None,
+        }
+    }
+
+    /// The object a value refers to, or the value itself if it
+    /// is not a UID. The `$null` object resolves to `None`.
+    fn resolve(&self, value: &'a Value) -> Option<&'a Value> {
+        let value = match value.as_uid() {
+            Some(uid) => self.objects.get(usize::try_from(uid.get()).ok()?)?,
+            None => value,
+        };
+
+        match value.as_string() {
+            Some("$null") => None,
+            _ => Some(value),
+        }
+    }
+
+    fn url(&self, url: &'a Value) -> Option<String> {
+        if let Some(url) = url.as_string() {
+            return Some(url.to_string());
+        }
+
+        let url = url.as_dictionary()?;
+        let relative = self.resolve(url.get("NS.relative")?)?.as_string()?;
+
+        match url
+            .get("NS.base")
+            .and_then(|base| self.resolve(base))
+            .and_then(|base| self.url(base))
+        {
+            Some(base) => Some(url::Url::parse(&base).ok()?.join(relative).ok()?.into()),
+            None => Some(relative.to_string()),
+        }
+    }
+
+    fn headers(&self, headers: &'a Value) -> Headers {
+        if let Some(headers) = headers
+            .as_dictionary()
+            .filter(|headers| !headers.contains_key("NS.keys"))
+        {
+            // a plain dictionary, rather than an archived NSDictionary
+            let entries = headers
+                .iter()
+                .filter_map(|(name, value)| Some((name, self.resolve(value)?.as_string()?)))
+                .filter(|(name, _)| !name.starts_with("__"))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+
+            return Headers { entries };
+        }
+
+        let strings = |key: &str| -> Vec<Option<&'a str>> {
+            headers
+                .as_dictionary()
+                .and_then(|headers| headers.get(key))
+                .and_then(Value::as_array)
+                .map(|items| {
+                    items
+                        .iter()
+                        .map(|item| self.resolve(item).and_then(Value::as_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let entries = strings("NS.keys")
+            .into_iter()
+            .zip(strings("NS.objects"))
+            .filter_map(|(name, value)| Some((name?, value?)))
+            // private entries, such as `__hhaa__`, are not headers
+            .filter(|(name, _)| !name.starts_with("__"))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Headers { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Headers, ResourceResponse, ResponseLayout};
+    use crate::WebArchive;
+    use plist::{Dictionary, Value};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parse_crouton_response() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        assert!(archive.main_resource.parse_response().unwrap().is_none());
+
+        let png = &archive.subresources.as_ref().unwrap()[0];
+        let response = png
+            .parse_response()
+            .expect("Could not parse response")
+            .expect("No response");
+
+        assert_eq!(
+            response.url.as_deref(),
+            Some("https://crouton.net/crouton.png")
+        );
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(response.expected_content_length(), Some(5182));
+
+        let names: Vec<&str> = response.headers.iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            [
+                "Server",
+                "Content-Type",
+                "Etag",
+                "Last-Modified",
+                "Date",
+                "Accept-Ranges",
+                "Content-Length",
+                "Keep-Alive",
+                "Connection"
+            ]
+        );
+        assert_eq!(response.headers.get("etag"), Some("\"143e-4fa5647f1c100\""));
+
+        // Sat, 30 May 2020 05:11:56 GMT
+        assert_eq!(
+            response.date(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_590_815_516))
+        );
+
+        // Mon, 26 May 2014 23:53:08 GMT
+        assert_eq!(
+            response.last_modified(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_401_148_388))
+        );
+    }
+
+    #[test]
+    fn serialize_crouton_response() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        let response = archive.subresources.as_ref().unwrap()[0]
+            .parse_response()
+            .unwrap()
+            .unwrap();
+        let json = serde_json::to_string(&response).unwrap();
+
+        assert!(json.starts_with(
+            r#"{"url":"https://crouton.net/crouton.png","status":200,"headers":{"Server":"Apache/2.4.25 (Debian)","Content-Type":"image/png","#
+        ));
+        assert!(json.ends_with(r#""Connection":"Keep-Alive"},"mime_type":"image/png"}"#));
+    }
+
+    #[test]
+    fn strip_error_responses() {
+        let mut archive: WebArchive = crate::from_file("fixtures/broken-image.webarchive")
+            .expect("Could not read broken image webarchive fixture");
+
+        let statuses: Vec<Option<u16>> = archive
+            .iter_resources()
+            .map(|resource| resource.response_status())
+            .collect();
+        assert_eq!(statuses, [None, Some(200), Some(404)]);
+
+        let errors: Vec<&str> = archive
+            .error_resources()
+            .iter()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(errors, ["https://crouton.net/missing.png"]);
+
+        assert!(archive.strip_error_responses(500..600).is_empty());
+        assert_eq!(archive.strip_error_responses(..=200).len(), 1);
+
+        let mut archive: WebArchive = crate::from_file("fixtures/broken-image.webarchive").unwrap();
+        let stripped = archive.strip_error_responses(400..600);
+        assert_eq!(stripped.len(), 1);
+        assert_eq!(stripped[0].url, "https://crouton.net/missing.png");
+        assert!(archive.error_resources().is_empty());
+        assert_eq!(archive.iter_resources().count(), 2);
+    }
+
+    #[test]
+    fn parse_legacy_response() {
+        let parse = |fixture: &str| {
+            let archive: WebArchive =
+                crate::from_file(fixture).expect("Could not read webarchive fixture");
+            archive.subresources.as_ref().unwrap()[0]
+                .parse_response()
+                .expect("Could not parse response")
+                .expect("No response")
+        };
+
+        let keyed = parse("fixtures/crouton.webarchive");
+        let legacy = parse("fixtures/legacy-response.webarchive");
+
+        assert_eq!(keyed.layout(), ResponseLayout::Keyed);
+        assert_eq!(legacy.layout(), ResponseLayout::Legacy);
+        assert_eq!(legacy.url, keyed.url);
+        assert_eq!(legacy.status, keyed.status);
+        assert_eq!(legacy.headers, keyed.headers);
+        assert_eq!(legacy.mime_type, keyed.mime_type);
+        assert_ne!(legacy.raw(), keyed.raw());
+    }
+
+    #[test]
+    fn parse_flat_response() {
+        let mut headers = Dictionary::new();
+        headers.insert("Content-Type".to_string(), "text/css".into());
+        headers.insert("Content-Length".to_string(), "12".into());
+
+        let mut response = Dictionary::new();
+        response.insert("URL".to_string(), "https://example.com/a.css".into());
+        response.insert("statusCode".to_string(), 200.into());
+        response.insert("allHeaderFields".to_string(), headers.into());
+
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &Value::Dictionary(response)).unwrap();
+        let response = ResourceResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(response.layout(), ResponseLayout::Legacy);
+        assert_eq!(response.url.as_deref(), Some("https://example.com/a.css"));
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.expected_content_length(), Some(12));
+        assert_eq!(response.headers.get("content-type"), Some("text/css"));
+        assert_eq!(response.mime_type, None);
+    }
+
+    #[test]
+    fn write_new_response() {
+        let mut headers = Headers::default();
+        headers.append("Content-Type", "text/css");
+        headers.append("Vary", "Accept");
+        headers.append("Vary", "Accept-Encoding");
+
+        let response = ResourceResponse::new(
+            Some("https://example.com/a.css".to_string()),
+            Some(200),
+            headers,
+            Some("text/css".to_string()),
+        );
+        let parsed = ResourceResponse::from_bytes(&response.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(parsed.layout(), ResponseLayout::Legacy);
+        assert_eq!(parsed.url, response.url);
+        assert_eq!(parsed.status, Some(200));
+        assert_eq!(parsed.mime_type.as_deref(), Some("text/css"));
+        assert_eq!(parsed.headers.get("vary"), Some("Accept, Accept-Encoding"));
+        assert_eq!(parsed.raw(), response.raw());
+
+        // the headers of the new response can then be edited in place
+        let mut edited = parsed.clone();
+        edited.edit(|headers| headers.set("Content-Type", "text/plain"));
+        let bytes = edited
+            .to_bytes_preserving(&response.to_bytes().unwrap())
+            .unwrap();
+        let edited = ResourceResponse::from_bytes(&bytes).unwrap();
+        assert_eq!(edited.headers.get("content-type"), Some("text/plain"));
+        assert_eq!(edited.status, Some(200));
+    }
+
+    #[test]
+    fn parse_unknown_response() {
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &Value::Array(vec!["surprise".into()])).unwrap();
+        let response = ResourceResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(response.layout(), ResponseLayout::Unknown);
+        assert_eq!(response.status, None);
+        assert!(response.headers.is_empty());
+        assert_eq!(response.raw(), &Value::Array(vec!["surprise".into()]));
+    }
+
+    #[test]
+    fn parse_invalid_response() {
+        assert!(ResourceResponse::from_bytes(b"not a plist").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+//! Writing edited headers back into archived response data.
+//!
+//! Safari's unarchiver expects the response's object graph to be just
+//! as it left it, so rather than writing a new archive, the original
+//! is patched: only the header dictionary's keys and values, and the
+//! `__hhaa__` cache of them, are replaced. Objects which are no
+//! longer referred to are then removed, so removed header values do
+//! not linger in the data.
+
+use crate::response::{KEYED_HEADERS, LEGACY_HEADERS};
+use crate::{Error, ResourceResponse, ResponseLayout, Result, WebArchive};
+use plist::{Dictionary, Uid, Value};
+
+/// The private header entry where `CFNetwork` caches the headers,
+/// as a base64 property list.
+const HEADER_CACHE: &str = "__hhaa__";
+
+impl ResourceResponse {
+    /// Write the response's headers into `original`, the archived data
+    /// it was parsed from, keeping the rest of the archive as it was.
+    ///
+    /// Only the headers are written; changes to other fields are not.
+    /// The result is a binary property list, like those Safari saves.
+    ///
+    /// Returns [`Error::InvalidResponse`] if `original` is not in a
+    /// layout which can be edited, or has no headers to replace.
+    pub fn to_bytes_preserving(&self, original: &[u8]) -> Result<Vec<u8>> {
+        let mut archive: Value = plist::from_bytes(original)?;
+        let root = archive
+            .as_dictionary_mut()
+            .ok_or_else(|| invalid("not a dictionary"))?;
+
+        match self.layout() {
+            ResponseLayout::Keyed | ResponseLayout::Legacy if root.contains_key("$objects") => {
+                self.patch_keyed(root)?;
+                compact(root);
+            }
+            ResponseLayout::Legacy => self.patch_flat(root)?,
+            _ => return Err(invalid("unknown layout")),
+        }
+
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &archive)?;
+        Ok(bytes)
+    }
+
+    /// Replace the keys and values of the header
+    /// dictionary within an `NSKeyedArchiver` archive.
+    fn patch_keyed(&self, root: &mut Dictionary) -> Result<()> {
+        let response = root
+            .get("$top")
+            .and_then(Value::as_dictionary)
+            .and_then(|top| top.get("WebResourceResponse"))
+            .and_then(Value::as_uid)
+            .ok_or_else(|| invalid("no WebResourceResponse object"))?
+            .get();
+
+        let objects = root
+            .get_mut("$objects")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| invalid("no $objects list"))?;
+
+        let headers = object(objects, response)
+            .and_then(Value::as_dictionary)
+            .and_then(|response| {
+                std::iter::once(KEYED_HEADERS)
+                    .chain(LEGACY_HEADERS.iter().copied())
+                    .find_map(|key| response.get(key)?.as_uid())
+            })
+            .ok_or_else(|| invalid("no headers to replace"))?
+            .get();
+
+        let header_dictionary = object(objects, headers)
+            .and_then(Value::as_dictionary)
+            .ok_or_else(|| invalid("headers are not a dictionary"))?;
+
+        if !header_dictionary.contains_key("NS.keys") {
+            // a plain dictionary, which may refer to strings by UID
+            let plain = self.plain_headers();
+            objects[headers as usize] = Value::Dictionary(plain);
+            return Ok(());
+        }
+
+        let uids = |key: &str| -> Vec<Uid> {
+            header_dictionary
+                .get(key)
+                .and_then(Value::as_array)
+                .map(|items| items.iter().filter_map(Value::as_uid).copied().collect())
+                .unwrap_or_default()
+        };
+        let old_entries: Vec<(Uid, Uid)> = uids("NS.keys")
+            .into_iter()
+            .zip(uids("NS.objects"))
+            .collect();
+
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        for (name, value) in self.headers.iter() {
+            keys.push(Value::Uid(string_uid(objects, name)));
+            values.push(Value::Uid(string_uid(objects, value)));
+        }
+
+        // keep private entries, refreshing the cache of the headers
+        for (key, value) in old_entries {
+            let name = match object(objects, key.get()).and_then(Value::as_string) {
+                Some(name) if name.starts_with("__") => name.to_string(),
+                _ => continue,
+            };
+
+            let value = if name == HEADER_CACHE {
+                let cache = self.header_cache()?;
+                string_uid(objects, &cache)
+            } else {
+                value
+            };
+
+            keys.push(Value::Uid(key));
+            values.push(Value::Uid(value));
+        }
+
+        let header_dictionary = objects[headers as usize]
+            .as_dictionary_mut()
+            .expect("Headers were checked to be a dictionary");
+        header_dictionary.insert("NS.keys".to_string(), Value::Array(keys));
+        header_dictionary.insert("NS.objects".to_string(), Value::Array(values));
+
+        Ok(())
+    }
+
+    /// Replace the header dictionary of a plain dictionary.
+    fn patch_flat(&self, root: &mut Dictionary) -> Result<()> {
+        let response = match root.get_mut("WebResourceResponse") {
+            Some(response) => response
+                .as_dictionary_mut()
+                .ok_or_else(|| invalid("response is not a dictionary"))?,
+            None => root,
+        };
+
+        let key = LEGACY_HEADERS
+            .iter()
+            .find(|key| response.contains_key(key))
+            .ok_or_else(|| invalid("no headers to replace"))?;
+
+        response.insert(key.to_string(), Value::Dictionary(self.plain_headers()));
+        Ok(())
+    }
+
+    fn plain_headers(&self) -> Dictionary {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+            .collect()
+    }
+
+    /// The `__hhaa__` entry for the headers: a binary property list
+    /// mapping each name to a list of values, in base64.
+    fn header_cache(&self) -> Result<String> {
+        use base64::Engine;
+
+        let mut cache = Dictionary::new();
+        for (name, value) in self.headers.iter() {
+            match cache.get_mut(name).and_then(Value::as_array_mut) {
+                Some(values) => values.push(value.into()),
+                None => {
+                    cache.insert(name.to_string(), Value::Array(vec![value.into()]));
+                }
+            }
+        }
+
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &Value::Dictionary(cache))?;
+        Ok(format!(
+            "\r\n\r\n{}",
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ))
+    }
+}
+
+impl WebArchive {
+    /// Remove the headers called `names`, compared case-insensitively,
+    /// such as `Set-Cookie`, from the archived response of every
+    /// resource, at any depth, returning how many were removed.
+    ///
+    /// Responses which can not be parsed, or do not have any of
+    /// the headers, are left exactly as they were.
+    pub fn scrub_headers(&mut self, names: &[&str]) -> Result<usize> {
+        let mut scrubbed = 0;
+        let mut error = None;
+
+        self.for_each_resource_mut(&mut |resource| {
+            let data = match &resource.response {
+                Some(data) if error.is_none() => data,
+                _ => return,
+            };
+
+            let mut response = match ResourceResponse::from_bytes(data) {
+                Ok(response) if response.layout() != ResponseLayout::Unknown => response,
+                _ => return,
+            };
+
+            let mut removed = 0;
+            response.edit(|headers| {
+                removed = names.iter().map(|name| headers.remove(name)).sum();
+            });
+
+            if removed > 0 {
+                match response.to_bytes_preserving(data) {
+                    Ok(data) => {
+                        resource.response = Some(data);
+                        scrubbed += removed;
+                    }
+                    Err(edit_error) => error = Some(edit_error),
+                }
+            }
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(scrubbed),
+        }
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidResponse(message.to_string())
+}
+
+fn object(objects: &[Value], uid: u64) -> Option<&Value> {
+    objects.get(usize::try_from(uid).ok()?)
+}
+
+/// The UID of a string object, adding one if the archive has none.
+fn string_uid(objects: &mut Vec<Value>, string: &str) -> Uid {
+    // the first object is always `$null`
+    let index = match objects
+        .iter()
+        .skip(1)
+        .position(|object| object.as_string() == Some(string))
+    {
+        Some(index) => index + 1,
+        None => {
+            objects.push(Value::String(string.to_string()));
+            objects.len() - 1
+        }
+    };
+
+    Uid::new(index as u64)
+}
+
+/// Remove objects which can no longer be reached from `$top`,
+/// renumbering the UIDs which refer to the rest.
+fn compact(root: &mut Dictionary) {
+    let objects = match root.get("$objects").and_then(Value::as_array) {
+        Some(objects) => objects,
+        None => return,
+    };
+
+    let mut reachable = vec![false; objects.len()];
+    // `$null` stays first, whether or not anything refers to it
+    reachable[0] = true;
+
+    let mut queue = Vec::new();
+    if let Some(top) = root.get("$top") {
+        collect_uids(top, &mut queue);
+    }
+
+    while let Some(index) = queue.pop() {
+        if let Some(seen @ false) = reachable.get_mut(index) {
+            *seen = true;
+            collect_uids(&objects[index], &mut queue);
+        }
+    }
+
+    if reachable.iter().all(|reachable| *reachable) {
+        return;
+    }
+
+    let mut renumbered = Vec::with_capacity(objects.len());
+    let mut next = 0;
+    for reachable in &reachable {
+        renumbered.push(next);
+        next += u64::from(*reachable);
+    }
+
+    let renumber = &mut |uid: &mut Uid| {
+        if let Some(&index) = usize::try_from(uid.get())
+            .ok()
+            .and_then(|index| renumbered.get(index))
+        {
+            *uid = Uid::new(index);
+        }
+    };
+
+    if let Some(Value::Array(objects)) = root.get_mut("$objects") {
+        let mut index = 0;
+        objects.retain(|_| {
+            index += 1;
+            reachable[index - 1]
+        });
+        objects
+            .iter_mut()
+            .for_each(|object| renumber_uids(object, renumber));
+    }
+
+    if let Some(top) = root.get_mut("$top") {
+        renumber_uids(top, renumber);
+    }
+}
+
+fn collect_uids(value: &Value, uids: &mut Vec<usize>) {
+    match value {
+        Value::Uid(uid) => uids.extend(usize::try_from(uid.get()).ok()),
+        Value::Array(items) => items.iter().for_each(|item| collect_uids(item, uids)),
+        Value::Dictionary(entries) => entries.values().for_each(|item| collect_uids(item, uids)),
+        _ => {}
+    }
+}
+
+fn renumber_uids(value: &mut Value, f: &mut impl FnMut(&mut Uid)) {
+    match value {
+        Value::Uid(uid) => f(uid),
+        Value::Array(items) => items.iter_mut().for_each(|item| renumber_uids(item, f)),
+        Value::Dictionary(entries) => entries.values_mut().for_each(|item| renumber_uids(item, f)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ResourceResponse, WebArchive};
+    use plist::Value;
+
+    fn crouton() -> WebArchive {
+        crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture")
+    }
+
+    /// Every class name in an archive, in order.
+    fn class_names(archive: &Value) -> Vec<String> {
+        archive.as_dictionary().unwrap()["$objects"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|object| object.as_dictionary()?.get("$classname")?.as_string())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn edit_crouton_response() {
+        let archive = crouton();
+        let original = archive.subresources.as_ref().unwrap()[0]
+            .response
+            .as_deref()
+            .unwrap();
+        let before = ResourceResponse::from_bytes(original).unwrap();
+
+        let mut response = before.clone();
+        response.edit(|headers| {
+            assert_eq!(headers.remove("etag"), 1);
+            headers.set("Server", "Apache");
+        });
+        let patched = response.to_bytes_preserving(original).unwrap();
+        let after = ResourceResponse::from_bytes(&patched).unwrap();
+
+        // only the edited headers changed
+        assert_eq!(after.headers.get("Etag"), None);
+        assert_eq!(after.headers.get("Server"), Some("Apache"));
+        let unchanged = |response: &ResourceResponse| -> Vec<(String, String)> {
+            response
+                .headers
+                .iter()
+                .filter(|(name, _)| !matches!(*name, "Etag" | "Server"))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(unchanged(&after), unchanged(&before));
+        assert_eq!(after.headers.len(), before.headers.len() - 1);
+        assert_eq!(after.url, before.url);
+        assert_eq!(after.status, before.status);
+        assert_eq!(after.mime_type, before.mime_type);
+        assert_eq!(after.layout(), before.layout());
+
+        // the object graph around the headers is intact
+        let (before, after) = (before.raw(), after.raw());
+        for key in ["$archiver", "$version"] {
+            assert_eq!(
+                after.as_dictionary().unwrap()[key],
+                before.as_dictionary().unwrap()[key]
+            );
+        }
+        assert_eq!(class_names(after), class_names(before));
+
+        let objects = after.as_dictionary().unwrap()["$objects"]
+            .as_array()
+            .unwrap();
+        assert_eq!(objects[0].as_string(), Some("$null"));
+        assert!(!objects
+            .iter()
+            .any(|object| object.as_string() == Some("\"143e-4fa5647f1c100\"")));
+
+        // the header cache was refreshed to match
+        let cache = objects
+            .iter()
+            .filter_map(Value::as_string)
+            .find(|string| string.starts_with("\r\n\r\n"))
+            .unwrap();
+        let cache = {
+            use base64::Engine;
+            base64::engine::general_purpose::STANDARD
+                .decode(cache.trim())
+                .unwrap()
+        };
+        let cache: Value = plist::from_bytes(&cache).unwrap();
+        let cache = cache.as_dictionary().unwrap();
+        assert_eq!(cache.len(), 8);
+        assert_eq!(cache["Server"], Value::Array(vec!["Apache".into()]));
+    }
+
+    #[test]
+    fn edit_legacy_response() {
+        let archive: WebArchive = crate::from_file("fixtures/legacy-response.webarchive")
+            .expect("Could not read legacy response webarchive fixture");
+        let original = archive.subresources.as_ref().unwrap()[0]
+            .response
+            .as_deref()
+            .unwrap();
+
+        let mut response = ResourceResponse::from_bytes(original).unwrap();
+        response.edit(|headers| headers.set("Cache-Control", "no-store"));
+        let patched = response.to_bytes_preserving(original).unwrap();
+
+        let after = ResourceResponse::from_bytes(&patched).unwrap();
+        assert_eq!(after.headers, response.headers);
+        assert_eq!(after.headers.get("cache-control"), Some("no-store"));
+    }
+
+    #[test]
+    fn scrub_headers() {
+        let mut archive = crouton();
+        let original = archive.subresources.as_ref().unwrap()[0].response.clone();
+
+        assert_eq!(archive.scrub_headers(&["Set-Cookie"]).unwrap(), 0);
+        assert_eq!(archive.subresources.as_ref().unwrap()[0].response, original);
+
+        assert_eq!(archive.scrub_headers(&["date", "Keep-Alive"]).unwrap(), 2);
+        let response = archive.subresources.as_ref().unwrap()[0]
+            .parse_response()
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.headers.get("Date"), None);
+        assert_eq!(response.headers.get("Keep-Alive"), None);
+        assert_eq!(response.headers.len(), 7);
+        assert_eq!(response.status, Some(200));
+    }
+}
@@ -0,0 +1,283 @@
+//! Retrying requests which fail, backing off between attempts.
+
+use crate::{Error, Result};
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// When and how often to retry a request which fails, as part of
+/// [`FetchOptions`](crate::FetchOptions).
+///
+/// Requests which time out, can not connect, or get a response with
+/// one of `retry_statuses` are retried, waiting longer before each
+/// attempt: `initial_backoff`, then twice that, and so on, up to
+/// `max_backoff`. Each wait is shortened by a random amount, of up to
+/// half, so that many requests failing at once don't all retry at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to retry a request, after the first attempt.
+    pub retries: u32,
+
+    /// The longest to wait before the first retry.
+    pub initial_backoff: Duration,
+
+    /// The longest to wait before any retry.
+    pub max_backoff: Duration,
+
+    /// The HTTP status codes to retry requests which get.
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    /// Retry twice, after up to half a second and then up to a second, on
+    /// `429 Too Many Requests` and any `5xx` status.
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            retry_statuses: std::iter::once(429).chain(500..600).collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a response with `status` should be retried.
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// How long to wait before retry number `retry`, counting from 0,
+    /// given `jitter` between 0 and 1 choosing how much to shorten it.
+    pub fn backoff(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_backoff);
+
+        backoff.mul_f64(1.0 - jitter.clamp(0.0, 1.0) / 2.0)
+    }
+
+    /// Run `attempt` until it succeeds, `retryable` says its result
+    /// should not be retried, or this policy runs out of retries, and
+    /// return its last result.
+    ///
+    /// `attempt` is passed how long it may take, which is `timeout`,
+    /// or less if that would take it past `deadline`. Once `deadline`
+    /// is reached, this fails with [`Error::DeadlineExceeded`] rather
+    /// than making another attempt.
+    pub(crate) async fn run<T, F: Future<Output = Result<T>>>(
+        &self,
+        clock: &impl Clock,
+        timeout: Duration,
+        deadline: Option<Instant>,
+        mut attempt: impl FnMut(Duration) -> F,
+        retryable: impl Fn(&Result<T>) -> bool,
+    ) -> Result<T> {
+        let mut retry = 0;
+
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(clock.now());
+                    if remaining.is_zero() {
+                        return Err(Error::DeadlineExceeded);
+                    }
+                    timeout.min(remaining)
+                }
+                None => timeout,
+            };
+
+            let result = attempt(timeout).await;
+            if retry >= self.retries || !retryable(&result) {
+                return result;
+            }
+
+            let backoff = self.backoff(retry, jitter());
+            if deadline.is_some_and(|deadline| clock.now() + backoff >= deadline) {
+                return result;
+            }
+
+            clock.sleep(backoff).await;
+            retry += 1;
+        }
+    }
+}
+
+/// A random number between 0 and 1, good enough to spread retries out.
+fn jitter() -> f64 {
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The time, and a way to wait for it to pass, so that retries can be
+/// tested without waiting.
+pub(crate) trait Clock {
+    fn now(&self) -> Instant;
+    async fn sleep(&self, duration: Duration);
+}
+
+/// The real time, as kept by Tokio.
+pub(crate) struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, RetryPolicy};
+    use crate::Error;
+    use std::cell::{Cell, RefCell};
+    use std::time::{Duration, Instant};
+
+    /// A clock which only moves when slept on, noting each sleep.
+    struct SimulatedClock {
+        now: Cell<Instant>,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl SimulatedClock {
+        fn new() -> Self {
+            SimulatedClock {
+                now: Cell::new(Instant::now()),
+                sleeps: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Clock for SimulatedClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        async fn sleep(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            ..Default::default()
+        }
+    }
+
+    /// Run `policy` over attempts which return `statuses` in turn,
+    /// retrying the statuses it says to, and return the result along
+    /// with the timeouts each attempt was given.
+    fn run(
+        policy: &RetryPolicy,
+        clock: &SimulatedClock,
+        deadline: Option<Instant>,
+        statuses: &[u16],
+    ) -> (crate::Result<u16>, Vec<Duration>) {
+        let mut timeouts = Vec::new();
+        let mut statuses = statuses.iter();
+
+        let result = futures::executor::block_on(policy.run(
+            clock,
+            Duration::from_secs(30),
+            deadline,
+            |timeout| {
+                timeouts.push(timeout);
+                let status = *statuses.next().expect("Ran out of statuses");
+                async move { Ok(status) }
+            },
+            |result| matches!(result, Ok(status) if policy.retries_status(*status)),
+        ));
+
+        (result, timeouts)
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = policy(10);
+        let seconds = |retry, jitter| policy.backoff(retry, jitter).as_secs_f64();
+
+        assert_eq!(seconds(0, 0.0), 1.0);
+        assert_eq!(seconds(1, 0.0), 2.0);
+        assert_eq!(seconds(2, 0.0), 4.0);
+        assert_eq!(seconds(3, 0.0), 5.0);
+        assert_eq!(seconds(u32::MAX, 0.0), 5.0);
+
+        // jitter takes off up to half
+        assert_eq!(seconds(1, 0.5), 1.5);
+        assert_eq!(seconds(1, 1.0), 1.0);
+        assert_eq!(seconds(1, 7.0), 1.0);
+    }
+
+    #[test]
+    fn retry_until_success() {
+        let clock = SimulatedClock::new();
+        let (result, timeouts) = run(&policy(3), &clock, None, &[503, 429, 200, 500]);
+
+        assert_eq!(result.unwrap(), 200);
+        assert_eq!(timeouts.len(), 3);
+
+        let sleeps = clock.sleeps.borrow();
+        assert_eq!(sleeps.len(), 2);
+        assert!(sleeps[0] >= Duration::from_millis(500) && sleeps[0] <= Duration::from_secs(1));
+        assert!(sleeps[1] >= Duration::from_secs(1) && sleeps[1] <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn retry_gives_up() {
+        let clock = SimulatedClock::new();
+        let (result, timeouts) = run(&policy(2), &clock, None, &[503, 503, 502, 200]);
+        assert_eq!(result.unwrap(), 502);
+        assert_eq!(timeouts.len(), 3);
+
+        // statuses outside the set are not retried
+        let (result, timeouts) = run(&policy(2), &clock, None, &[404, 200]);
+        assert_eq!(result.unwrap(), 404);
+        assert_eq!(timeouts.len(), 1);
+
+        let (result, timeouts) = run(&RetryPolicy::none(), &clock, None, &[503, 200]);
+        assert_eq!(result.unwrap(), 503);
+        assert_eq!(timeouts.len(), 1);
+    }
+
+    #[test]
+    fn retry_deadline() {
+        let clock = SimulatedClock::new();
+        let start = clock.now();
+
+        // attempts are given no longer than is left, and no retry
+        // is made which could only start after the deadline
+        let deadline = start + Duration::from_secs(2);
+        let (result, timeouts) = run(&policy(5), &clock, Some(deadline), &[503, 503, 503]);
+        assert_eq!(result.unwrap(), 503);
+        assert_eq!(timeouts[0], Duration::from_secs(2));
+        assert!(timeouts
+            .iter()
+            .skip(1)
+            .all(|timeout| *timeout < Duration::from_secs(2)));
+        assert!(clock.now() < deadline);
+
+        // nothing is attempted once the deadline has passed
+        let (result, timeouts) = run(&policy(5), &clock, Some(start), &[200]);
+        assert!(matches!(result, Err(Error::DeadlineExceeded)));
+        assert!(timeouts.is_empty());
+    }
+}
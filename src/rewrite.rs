@@ -0,0 +1,513 @@
+use crate::references::{
+    base_url, decode_attribute, element_attributes, html_base, resolve_url, rewrite_css,
+    srcset_urls,
+};
+use crate::{Error, Result, UrlNormalization, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+
+/// What [`WebArchive::rewrite_urls`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UrlRewriteReport {
+    /// The old and new URL of each resource whose URL was
+    /// changed, in document order.
+    pub resources: Vec<(String, String)>,
+
+    /// How many references within HTML and CSS resources were changed.
+    pub references: usize,
+}
+
+/// How [`WebArchive::set_resource_url`] changes a resource's URL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SetUrlOptions {
+    /// Also change references to the old URL within HTML and CSS
+    /// resources, so they lead to the new one.
+    pub rewrite_references: bool,
+
+    /// If another resource already has the new URL, remove this
+    /// one in its favour, rather than failing.
+    pub merge: bool,
+}
+
+impl WebArchive {
+    /// Change the URLs of resources, at any depth, along with the
+    /// references to them, so the archive stays consistent.
+    ///
+    /// `f` is called with the URL of each resource, and the absolute
+    /// URL of each reference found by
+    /// [`references`](WebResource::references), and returns the URL
+    /// to use instead, or `None` to leave it as it is. It should give
+    /// the same answer for the same URL, or references will no longer
+    /// lead to the resources they used to.
+    ///
+    /// Resources are not checked for colliding URLs afterwards.
+    pub fn rewrite_urls(
+        &mut self,
+        mut f: impl FnMut(&str) -> Option<String>,
+    ) -> Result<UrlRewriteReport> {
+        let mut report = UrlRewriteReport::default();
+        let mut error = None;
+
+        self.for_each_resource_mut(&mut |resource| {
+            if error.is_some() {
+                return;
+            }
+
+            // references are resolved against the old URL, so change it last
+            match resource.rewrite_references(&mut f) {
+                Ok(count) => report.references += count,
+                Err(rewrite_error) => error = Some(rewrite_error),
+            }
+
+            if let Some(url) = f(&resource.url).filter(|url| *url != resource.url) {
+                let old_url = std::mem::replace(&mut resource.url, url.clone());
+                report.resources.push((old_url, url));
+            }
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(report),
+        }
+    }
+
+    /// Change the URL of the resource stored at `from`, at any depth,
+    /// to `to`, such as to remove a session token from it.
+    ///
+    /// Returns [`Error::ResourceNotFound`] if no resource has the URL
+    /// `from`, and [`Error::DuplicateUrl`] if one already has the URL
+    /// `to`, unless [`merge`](SetUrlOptions::merge) is set. Main
+    /// resources can not be merged away. The archive is unchanged
+    /// whenever an error is returned.
+    pub fn set_resource_url(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: SetUrlOptions,
+    ) -> Result<UrlRewriteReport> {
+        let mut report = UrlRewriteReport::default();
+        if from == to {
+            return Ok(report);
+        }
+
+        if self
+            .get_resource_by_url_with(from, UrlNormalization::exact())
+            .is_none()
+        {
+            return Err(Error::ResourceNotFound(from.to_string()));
+        }
+
+        let merged = if self
+            .get_resource_by_url_with(to, UrlNormalization::exact())
+            .is_some()
+        {
+            if !options.merge || self.remove_subresource(from).is_none() {
+                return Err(Error::DuplicateUrl(to.to_string()));
+            }
+
+            report.resources.push((from.to_string(), to.to_string()));
+            true
+        } else {
+            false
+        };
+
+        if options.rewrite_references {
+            // references are compared in the form they are resolved to
+            let resolved = url::Url::parse(from).map(String::from).ok();
+            let rewritten = self.rewrite_urls(|url| {
+                (url == from || Some(url) == resolved.as_deref()).then(|| to.to_string())
+            })?;
+
+            report.resources.extend(rewritten.resources);
+            report.references = rewritten.references;
+        } else if !merged {
+            self.update_resource(from, |resource| resource.url = to.to_string())?;
+            report.resources.push((from.to_string(), to.to_string()));
+        }
+
+        Ok(report)
+    }
+}
+
+impl WebResource {
+    /// Change the URLs this resource refers to, as found by
+    /// [`references`](WebResource::references).
+    ///
+    /// `f` is called with each reference's absolute URL, and returns
+    /// the URL to refer to instead, or `None` to leave it as it is.
+    /// Replacements are written as they are returned, so should
+    /// usually be absolute URLs.
+    ///
+    /// The document is re-encoded with its original encoding, unless
+    /// that encoding can not be written (such as UTF-16), in which case
+    /// it is written as UTF-8 and `text_encoding_name` is updated.
+    /// Resources other than HTML and CSS are left unchanged.
+    ///
+    /// Returns how many references were changed.
+    pub fn rewrite_references(
+        &mut self,
+        mut f: impl FnMut(&str) -> Option<String>,
+    ) -> Result<usize> {
+        let (text, count) = if self.is_html() {
+            let text = match self.decoded_text() {
+                Some(text) => text,
+                None => return Ok(0),
+            };
+
+            let base = base_url(&self.url, html_base(&text)?.as_deref());
+            rewrite_html(&text, &mut |raw: &str| {
+                replacement(base.as_ref(), raw, &mut f)
+            })?
+        } else if self.is_css() {
+            let text = match self.decoded_text() {
+                Some(text) => text,
+                None => return Ok(0),
+            };
+
+            let base = url::Url::parse(&self.url).ok();
+            rewrite_css(&text, &mut |raw: &str| {
+                replacement(base.as_ref(), raw, &mut f)
+            })
+        } else {
+            return Ok(0);
+        };
+
+        if count > 0 {
+            self.set_text(&text);
+        }
+
+        Ok(count)
+    }
+}
+
+/// The URL to write in place of `raw`, if `f` wants it changed.
+fn replacement(
+    base: Option<&url::Url>,
+    raw: &str,
+    f: &mut impl FnMut(&str) -> Option<String>,
+) -> Option<String> {
+    let url = resolve_url(base, raw)?;
+    f(&url).filter(|replacement| *replacement != url)
+}
+
+/// Rewrite the references within an HTML document, returning the
+/// new document and how many references were changed.
+fn rewrite_html(html: &str, f: &mut impl FnMut(&str) -> Option<String>) -> Result<(String, usize)> {
+    use lol_html::html_content::ContentType;
+    use lol_html::{element, text, HtmlRewriter, Settings};
+
+    let f = RefCell::new(f);
+    let count = RefCell::new(0);
+    let style = RefCell::new(String::new());
+    let mut output = Vec::with_capacity(html.len());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("*", |element| {
+                    let tag = element.tag_name();
+                    let mut f = f.borrow_mut();
+                    let mut count = count.borrow_mut();
+
+                    for (attribute, _) in element_attributes(&tag, element) {
+                        let value = match element.get_attribute(attribute) {
+                            Some(value) => decode_attribute(&value),
+                            None => continue,
+                        };
+
+                        let rewritten = if *attribute == "srcset" {
+                            rewrite_srcset(&value, &mut **f, &mut count)
+                        } else {
+                            f(&value).inspect(|_| *count += 1)
+                        };
+
+                        if let Some(rewritten) = rewritten {
+                            element.set_attribute(
+                                attribute,
+                                &html_escape::encode_double_quoted_attribute(&rewritten),
+                            )?;
+                        }
+                    }
+
+                    if let Some(declarations) = element.get_attribute("style") {
+                        let (declarations, changed) =
+                            rewrite_css(&decode_attribute(&declarations), &mut **f);
+
+                        if changed > 0 {
+                            *count += changed;
+                            element.set_attribute(
+                                "style",
+                                &html_escape::encode_double_quoted_attribute(&declarations),
+                            )?;
+                        }
+                    }
+
+                    Ok(())
+                }),
+                text!("style", |chunk| {
+                    // gather the whole stylesheet, then write it out in place of the last chunk
+                    style.borrow_mut().push_str(chunk.as_str());
+
+                    if chunk.last_in_text_node() {
+                        let css = style.take();
+                        let (css, changed) = rewrite_css(&css, &mut **f.borrow_mut());
+                        *count.borrow_mut() += changed;
+                        chunk.replace(&css, ContentType::Html);
+                    } else {
+                        chunk.remove();
+                    }
+
+                    Ok(())
+                }),
+            ],
+            ..Settings::new()
+        },
+        |bytes: &[u8]| output.extend_from_slice(bytes),
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    let output = String::from_utf8(output).map_err(|error| Error::Html(error.to_string()))?;
+    Ok((output, count.into_inner()))
+}
+
+/// Rewrite the URLs of an `srcset` attribute's image candidates,
+/// keeping their descriptors, if any of them change.
+fn rewrite_srcset(
+    srcset: &str,
+    f: &mut impl FnMut(&str) -> Option<String>,
+    count: &mut usize,
+) -> Option<String> {
+    let mut changed = false;
+
+    let candidates: Vec<String> = srcset
+        .split(',')
+        .zip(srcset_urls(srcset).map(Some).chain(std::iter::repeat(None)))
+        .map(|(candidate, url)| {
+            let candidate = candidate.trim();
+            let url = match url {
+                Some(url) if candidate.starts_with(url) => url,
+                _ => return candidate.to_string(),
+            };
+
+            match f(url) {
+                Some(replacement) => {
+                    changed = true;
+                    *count += 1;
+                    format!("{}{}", replacement, &candidate[url.len()..])
+                }
+                None => candidate.to_string(),
+            }
+        })
+        .collect();
+
+    changed.then(|| candidates.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SetUrlOptions;
+    use crate::test_support::text_resource;
+    use crate::{Error, WebArchive};
+
+    fn swap_a_for_b(url: &str) -> Option<String> {
+        url.strip_suffix("/a.png")
+            .map(|prefix| format!("{}/b.png", prefix))
+    }
+
+    #[test]
+    fn rewrite_html() {
+        let mut page = text_resource(
+            "https://example.com/dir/page.html",
+            "text/html",
+            r#"<img src="a.png" alt="A &amp; B"><img srcset="a.png 1x, c.png 2x">
+<style>/* url(a.png) */ body { background: url(a.png) }</style>
+<p style="background: url('./a.png')">a.png</p><a href="a.png">a</a>"#,
+        );
+
+        assert_eq!(page.rewrite_references(swap_a_for_b).unwrap(), 4);
+        assert_eq!(
+            std::str::from_utf8(&page.data).unwrap(),
+            r#"<img src="https://example.com/dir/b.png" alt="A &amp; B"><img srcset="https://example.com/dir/b.png 1x, c.png 2x">
+<style>/* url(a.png) */ body { background: url("https://example.com/dir/b.png") }</style>
+<p style="background: url(&quot;https://example.com/dir/b.png&quot;)">a.png</p><a href="a.png">a</a>"#
+        );
+    }
+
+    #[test]
+    fn rewrite_css() {
+        let mut stylesheet = text_resource(
+            "https://example.com/css/main.css",
+            "text/css",
+            r#"@import "a.png"; @import url(c.css); .x { background: URL( a.png ) }"#,
+        );
+
+        assert_eq!(stylesheet.rewrite_references(swap_a_for_b).unwrap(), 2);
+        assert_eq!(
+            std::str::from_utf8(&stylesheet.data).unwrap(),
+            r#"@import "https://example.com/css/b.png"; @import url(c.css); .x { background: url("https://example.com/css/b.png") }"#
+        );
+    }
+
+    #[test]
+    fn rewrite_unchanged() {
+        let html = r#"<img src="c.png">"#;
+        let mut page = text_resource("https://example.com/", "text/html", html);
+        let mut image = text_resource("https://example.com/a.png", "image/png", "a.png");
+
+        assert_eq!(page.rewrite_references(swap_a_for_b).unwrap(), 0);
+        assert_eq!(page.data, html.as_bytes());
+        assert_eq!(image.rewrite_references(swap_a_for_b).unwrap(), 0);
+    }
+
+    #[test]
+    fn rewrite_urls() {
+        let mut archive = WebArchive {
+            main_resource: text_resource(
+                "https://old.example/",
+                "text/html",
+                r#"<img src="a.png"><link rel="stylesheet" href="/css/main.css"><a href="https://other.example/">"#,
+            ),
+            subresources: Some(vec![
+                text_resource("https://old.example/a.png", "image/png", "a"),
+                text_resource(
+                    "https://old.example/css/main.css",
+                    "text/css",
+                    "body { background: url(../a.png) }",
+                ),
+            ]),
+            subframe_archives: None,
+        };
+
+        let report = archive
+            .rewrite_urls(|url| {
+                url.strip_prefix("https://old.example/")
+                    .map(|path| format!("https://new.example/{}", path))
+            })
+            .unwrap();
+
+        assert_eq!(report.resources.len(), 3);
+        assert_eq!(
+            report.resources[1],
+            (
+                "https://old.example/a.png".to_string(),
+                "https://new.example/a.png".to_string()
+            )
+        );
+        assert_eq!(report.references, 3);
+        assert_eq!(
+            std::str::from_utf8(&archive.main_resource.data).unwrap(),
+            r#"<img src="https://new.example/a.png"><link rel="stylesheet" href="https://new.example/css/main.css"><a href="https://other.example/">"#
+        );
+        assert!(archive.missing_subresources().unwrap().is_empty());
+    }
+
+    fn tokened() -> WebArchive {
+        WebArchive {
+            main_resource: text_resource(
+                "https://example.com/",
+                "text/html",
+                r#"<img src="/a.png?session=123"><img src="b.png">"#,
+            ),
+            subresources: Some(vec![
+                text_resource("https://example.com/a.png?session=123", "image/png", "a"),
+                text_resource("https://example.com/b.png", "image/png", "b"),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn set_resource_url() {
+        let mut archive = tokened();
+        let report = archive
+            .set_resource_url(
+                "https://example.com/a.png?session=123",
+                "https://example.com/a.png",
+                SetUrlOptions::default(),
+            )
+            .unwrap();
+
+        assert_eq!(report.resources.len(), 1);
+        assert_eq!(report.references, 0);
+        assert_eq!(
+            archive.subresources.as_ref().unwrap()[0].url,
+            "https://example.com/a.png"
+        );
+        assert_eq!(
+            archive.missing_subresources().unwrap()[0].url,
+            "https://example.com/a.png?session=123"
+        );
+
+        let mut archive = tokened();
+        let report = archive
+            .set_resource_url(
+                "https://example.com/a.png?session=123",
+                "https://example.com/a.png",
+                SetUrlOptions {
+                    rewrite_references: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(report.resources.len(), 1);
+        assert_eq!(report.references, 1);
+        assert_eq!(
+            std::str::from_utf8(&archive.main_resource.data).unwrap(),
+            r#"<img src="https://example.com/a.png"><img src="b.png">"#
+        );
+        assert!(archive.missing_subresources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn set_resource_url_collision() {
+        let mut archive = tokened();
+        let from = "https://example.com/a.png?session=123";
+        let to = "https://example.com/b.png";
+
+        assert!(matches!(
+            archive.set_resource_url(from, to, SetUrlOptions::default()),
+            Err(Error::DuplicateUrl(url)) if url == to
+        ));
+        assert!(matches!(
+            archive.set_resource_url("https://example.com/c.png", to, SetUrlOptions::default()),
+            Err(Error::ResourceNotFound(_))
+        ));
+        assert!(matches!(
+            archive.set_resource_url(
+                "https://example.com/",
+                to,
+                SetUrlOptions {
+                    merge: true,
+                    ..Default::default()
+                }
+            ),
+            Err(Error::DuplicateUrl(_))
+        ));
+        assert_eq!(archive.subresources.as_ref().unwrap().len(), 2);
+
+        let report = archive
+            .set_resource_url(
+                from,
+                to,
+                SetUrlOptions {
+                    rewrite_references: true,
+                    merge: true,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(report.references, 1);
+        assert_eq!(archive.subresources.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            std::str::from_utf8(&archive.main_resource.data).unwrap(),
+            r#"<img src="https://example.com/b.png"><img src="b.png">"#
+        );
+    }
+}
@@ -0,0 +1,503 @@
+//! Removing active content, such as scripts, from archived pages.
+
+use crate::optimize::normalize_url;
+use crate::references::decode_attribute;
+use crate::{Error, ReferenceKind, Result, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+
+/// What [`WebArchive::sanitize`] removes. Each kind of active content
+/// can be removed, or kept, independently of the others.
+///
+/// The default removes all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SanitizePolicy {
+    /// Remove `on…` attributes, such as `onclick` and `onload`.
+    pub inline_event_handlers: bool,
+
+    /// Remove attributes holding `javascript:` URLs, such as
+    /// `<a href="javascript:…">`.
+    pub javascript_urls: bool,
+
+    /// Remove `<script>` elements, along with their contents.
+    pub script_elements: bool,
+
+    /// Remove stored subresources which are scripts, either because
+    /// they are loaded by `<script src>` or have a JavaScript MIME type.
+    pub external_script_subresources: bool,
+
+    /// Remove `<meta http-equiv="refresh">` elements, which can send
+    /// the reader elsewhere when the page is opened.
+    pub meta_refresh: bool,
+}
+
+impl SanitizePolicy {
+    /// A policy which removes every kind of active content.
+    pub fn all() -> Self {
+        SanitizePolicy {
+            inline_event_handlers: true,
+            javascript_urls: true,
+            script_elements: true,
+            external_script_subresources: true,
+            meta_refresh: true,
+        }
+    }
+
+    /// A policy which removes nothing, to turn rules on one by one.
+    pub fn none() -> Self {
+        SanitizePolicy {
+            inline_event_handlers: false,
+            javascript_urls: false,
+            script_elements: false,
+            external_script_subresources: false,
+            meta_refresh: false,
+        }
+    }
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        SanitizePolicy::all()
+    }
+}
+
+/// Which part of a [`SanitizePolicy`] caused a removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SanitizeRule {
+    /// [`SanitizePolicy::inline_event_handlers`]
+    InlineEventHandlers,
+    /// [`SanitizePolicy::javascript_urls`]
+    JavascriptUrls,
+    /// [`SanitizePolicy::script_elements`]
+    ScriptElements,
+    /// [`SanitizePolicy::external_script_subresources`]
+    ExternalScriptSubresources,
+    /// [`SanitizePolicy::meta_refresh`]
+    MetaRefresh,
+}
+
+impl fmt::Display for SanitizeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SanitizeRule::InlineEventHandlers => "inline-event-handlers",
+            SanitizeRule::JavascriptUrls => "javascript-urls",
+            SanitizeRule::ScriptElements => "script-elements",
+            SanitizeRule::ExternalScriptSubresources => "external-script-subresources",
+            SanitizeRule::MetaRefresh => "meta-refresh",
+        })
+    }
+}
+
+/// One thing [`WebArchive::sanitize`] removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SanitizeRemoval {
+    /// The URL of the resource it was removed from, or of the
+    /// subresource which was removed.
+    pub url: String,
+
+    /// The rule which removed it.
+    pub rule: SanitizeRule,
+
+    /// What was removed, such as `onclick attribute of <a>`.
+    pub what: String,
+}
+
+/// What [`WebArchive::sanitize`] removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SanitizeReport {
+    /// Everything removed, in document order, with subresources
+    /// which were removed first.
+    pub removals: Vec<SanitizeRemoval>,
+}
+
+impl SanitizeReport {
+    /// How many removals the given rule made.
+    pub fn count(&self, rule: SanitizeRule) -> usize {
+        self.removals
+            .iter()
+            .filter(|removal| removal.rule == rule)
+            .count()
+    }
+}
+
+impl WebArchive {
+    /// Remove active content from the archive, at any depth, as
+    /// `policy` says, so that opening it runs less, or none, of the
+    /// page's scripts.
+    ///
+    /// HTML documents are changed only where something is removed;
+    /// everything else, including the way attributes are quoted and
+    /// characters escaped, is left exactly as it was.
+    pub fn sanitize(&mut self, policy: &SanitizePolicy) -> Result<SanitizeReport> {
+        let mut report = SanitizeReport::default();
+
+        if policy.external_script_subresources {
+            let mut scripts = HashSet::new();
+            for resource in self.iter_resources() {
+                for reference in resource.references()? {
+                    if reference.kind == ReferenceKind::Script {
+                        scripts.insert(reference.url);
+                    }
+                }
+            }
+
+            self.remove_scripts(&scripts, &mut report);
+        }
+
+        let mut result = Ok(());
+        self.for_each_resource_mut(&mut |resource| {
+            if result.is_ok() && resource.is_html() {
+                result = sanitize_resource(resource, policy, &mut report);
+            }
+        });
+        result?;
+
+        Ok(report)
+    }
+
+    /// Remove subresources which are scripts from this archive and
+    /// its subframe archives.
+    fn remove_scripts(&mut self, scripts: &HashSet<String>, report: &mut SanitizeReport) {
+        if let Some(subresources) = &mut self.subresources {
+            subresources.retain(|subresource| {
+                let is_script = is_javascript(subresource)
+                    || scripts.contains(&normalize_url(&subresource.url));
+
+                if is_script {
+                    report.removals.push(SanitizeRemoval {
+                        url: subresource.url.clone(),
+                        rule: SanitizeRule::ExternalScriptSubresources,
+                        what: format!("{} subresource", subresource.mime_essence()),
+                    });
+                }
+
+                !is_script
+            });
+
+            if subresources.is_empty() {
+                self.subresources = None;
+            }
+        }
+
+        for subframe_archive in self.subframe_archives.iter_mut().flatten() {
+            subframe_archive.remove_scripts(scripts, report);
+        }
+    }
+}
+
+/// Whether a resource's MIME type is one browsers run as JavaScript.
+fn is_javascript(resource: &WebResource) -> bool {
+    matches!(
+        resource.mime_essence().as_str(),
+        "text/javascript"
+            | "application/javascript"
+            | "application/x-javascript"
+            | "application/ecmascript"
+            | "text/ecmascript"
+    )
+}
+
+/// Sanitize one HTML resource, writing it back only if anything
+/// was removed.
+fn sanitize_resource(
+    resource: &mut WebResource,
+    policy: &SanitizePolicy,
+    report: &mut SanitizeReport,
+) -> Result<()> {
+    let text = match resource.decoded_text() {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+
+    let (html, removed) = sanitize_html(&text, policy)?;
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    resource.set_text(&html);
+    report
+        .removals
+        .extend(removed.into_iter().map(|(rule, what)| SanitizeRemoval {
+            url: resource.url.clone(),
+            rule,
+            what,
+        }));
+
+    Ok(())
+}
+
+/// Attributes which hold URLs a browser may navigate to or load,
+/// where a `javascript:` URL would run.
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "action", "formaction", "xlink:href", "data"];
+
+/// Whether an attribute value is a `javascript:` URL, as browsers
+/// read it: ignoring leading and trailing spaces and control
+/// characters, tabs and newlines within it, and case.
+fn is_javascript_url(value: &str) -> bool {
+    let url: String = decode_attribute(value)
+        .trim_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .take("javascript:".len())
+        .collect();
+
+    url.eq_ignore_ascii_case("javascript:")
+}
+
+/// Remove active content from an HTML document, returning the new
+/// document along with what was removed, and by which rule.
+fn sanitize_html(
+    html: &str,
+    policy: &SanitizePolicy,
+) -> Result<(String, Vec<(SanitizeRule, String)>)> {
+    use lol_html::{element, HtmlRewriter, Settings};
+
+    let removed = RefCell::new(Vec::new());
+    let mut output = Vec::with_capacity(html.len());
+    let mut handlers = Vec::new();
+
+    if policy.script_elements {
+        handlers.push(element!("script", |element| {
+            element.remove();
+            removed
+                .borrow_mut()
+                .push((SanitizeRule::ScriptElements, "<script> element".to_string()));
+            Ok(())
+        }));
+    }
+
+    if policy.meta_refresh {
+        handlers.push(element!("meta[http-equiv]", |element| {
+            let is_refresh = element.get_attribute("http-equiv").is_some_and(|value| {
+                decode_attribute(&value)
+                    .trim()
+                    .eq_ignore_ascii_case("refresh")
+            });
+
+            if is_refresh {
+                element.remove();
+                removed.borrow_mut().push((
+                    SanitizeRule::MetaRefresh,
+                    "<meta http-equiv=\"refresh\"> element".to_string(),
+                ));
+            }
+            Ok(())
+        }));
+    }
+
+    if policy.inline_event_handlers || policy.javascript_urls {
+        handlers.push(element!("*", |element| {
+            // nothing within a removed element needs reporting
+            if element.removed() {
+                return Ok(());
+            }
+
+            let tag = element.tag_name();
+            let names: Vec<String> = element
+                .attributes()
+                .iter()
+                .map(|attribute| attribute.name())
+                .collect();
+
+            for name in names {
+                let rule = if policy.inline_event_handlers && name.starts_with("on") {
+                    SanitizeRule::InlineEventHandlers
+                } else if policy.javascript_urls
+                    && URL_ATTRIBUTES.contains(&name.as_str())
+                    && element
+                        .get_attribute(&name)
+                        .is_some_and(|value| is_javascript_url(&value))
+                {
+                    SanitizeRule::JavascriptUrls
+                } else {
+                    continue;
+                };
+
+                element.remove_attribute(&name);
+                removed
+                    .borrow_mut()
+                    .push((rule, format!("{} attribute of <{}>", name, tag)));
+            }
+            Ok(())
+        }));
+    }
+
+    if handlers.is_empty() {
+        return Ok((html.to_string(), Vec::new()));
+    }
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: handlers,
+            ..Settings::new()
+        },
+        |bytes: &[u8]| output.extend_from_slice(bytes),
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    let output = String::from_utf8(output).map_err(|error| Error::Html(error.to_string()))?;
+    Ok((output, removed.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SanitizePolicy, SanitizeRule};
+    use crate::test_support::text_resource;
+    use crate::WebArchive;
+
+    const PAGE: &str = concat!(
+        "<!DOCTYPE html>\n",
+        "<html><head>\n",
+        "<meta http-equiv=\"Refresh\" content=\"0; url=https://elsewhere.example/\">\n",
+        "<meta charset=utf-8>\n",
+        "<script src=\"app.js\"></script>\n",
+        "<script>alert(\"hi &amp; bye\")</script>\n",
+        "</head><body onload='start()' class=\"a  b\">\n",
+        "<a href=\" JaVa&#x09;script:go()\" title='&quot;Go&quot; &amp; see'>Go</a>\n",
+        "<a href=\"/page?a=1&amp;b=2\" onClick=\"track()\" data-x=caf&eacute;>Page</a>\n",
+        "<form action=\"javascript:void(0)\"><button formaction=\"/submit\">OK</button></form>\n",
+        "</body></html>\n",
+    );
+
+    fn page() -> WebArchive {
+        WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", PAGE),
+            subresources: Some(vec![
+                text_resource("https://example.com/app.js", "text/plain", "start()"),
+                text_resource("https://example.com/worker.js", "text/javascript", ""),
+                text_resource("https://example.com/style.css", "text/css", ""),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    fn sanitized(policy: SanitizePolicy) -> (String, WebArchive, super::SanitizeReport) {
+        let mut archive = page();
+        let report = archive.sanitize(&policy).unwrap();
+        let html = String::from_utf8(archive.main_resource.data.clone()).unwrap();
+        (html, archive, report)
+    }
+
+    #[test]
+    fn sanitize_all() {
+        let (html, archive, report) = sanitized(SanitizePolicy::all());
+
+        assert_eq!(
+            html,
+            concat!(
+                "<!DOCTYPE html>\n",
+                "<html><head>\n",
+                "\n",
+                "<meta charset=utf-8>\n",
+                "\n",
+                "\n",
+                "</head><body class=\"a  b\">\n",
+                "<a title='&quot;Go&quot; &amp; see'>Go</a>\n",
+                "<a href=\"/page?a=1&amp;b=2\" data-x=caf&eacute;>Page</a>\n",
+                "<form><button formaction=\"/submit\">OK</button></form>\n",
+                "</body></html>\n",
+            )
+        );
+
+        let urls: Vec<&str> = archive
+            .iter_resources()
+            .map(|resource| resource.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            ["https://example.com/", "https://example.com/style.css"]
+        );
+
+        let removals: Vec<(SanitizeRule, &str)> = report
+            .removals
+            .iter()
+            .map(|removal| (removal.rule, removal.what.as_str()))
+            .collect();
+        use SanitizeRule::*;
+        assert_eq!(
+            removals,
+            [
+                (ExternalScriptSubresources, "text/plain subresource"),
+                (ExternalScriptSubresources, "text/javascript subresource"),
+                (MetaRefresh, "<meta http-equiv=\"refresh\"> element"),
+                (ScriptElements, "<script> element"),
+                (ScriptElements, "<script> element"),
+                (InlineEventHandlers, "onload attribute of <body>"),
+                (JavascriptUrls, "href attribute of <a>"),
+                (InlineEventHandlers, "onclick attribute of <a>"),
+                (JavascriptUrls, "action attribute of <form>"),
+            ]
+        );
+        assert_eq!(report.count(ScriptElements), 2);
+    }
+
+    #[test]
+    fn sanitize_handlers_only() {
+        // scripts stay, but nothing runs from an attribute
+        let (html, archive, report) = sanitized(SanitizePolicy {
+            inline_event_handlers: true,
+            javascript_urls: true,
+            ..SanitizePolicy::none()
+        });
+
+        assert_eq!(
+            html,
+            concat!(
+                "<!DOCTYPE html>\n",
+                "<html><head>\n",
+                "<meta http-equiv=\"Refresh\" content=\"0; url=https://elsewhere.example/\">\n",
+                "<meta charset=utf-8>\n",
+                "<script src=\"app.js\"></script>\n",
+                "<script>alert(\"hi &amp; bye\")</script>\n",
+                "</head><body class=\"a  b\">\n",
+                "<a title='&quot;Go&quot; &amp; see'>Go</a>\n",
+                "<a href=\"/page?a=1&amp;b=2\" data-x=caf&eacute;>Page</a>\n",
+                "<form><button formaction=\"/submit\">OK</button></form>\n",
+                "</body></html>\n",
+            )
+        );
+        assert_eq!(archive.iter_resources().count(), 4);
+        assert_eq!(report.removals.len(), 4);
+    }
+
+    #[test]
+    fn sanitize_nothing() {
+        let (html, archive, report) = sanitized(SanitizePolicy::none());
+        assert_eq!(html, PAGE);
+        assert_eq!(archive.iter_resources().count(), 4);
+        assert!(report.removals.is_empty());
+
+        // each rule on its own only removes its own kind of content
+        for (policy, rule, count) in [
+            (
+                SanitizePolicy {
+                    meta_refresh: true,
+                    ..SanitizePolicy::none()
+                },
+                SanitizeRule::MetaRefresh,
+                1,
+            ),
+            (
+                SanitizePolicy {
+                    external_script_subresources: true,
+                    ..SanitizePolicy::none()
+                },
+                SanitizeRule::ExternalScriptSubresources,
+                2,
+            ),
+        ] {
+            let (_, _, report) = sanitized(policy);
+            assert_eq!(report.removals.len(), count);
+            assert_eq!(report.count(rule), count);
+        }
+    }
+}
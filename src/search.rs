@@ -0,0 +1,153 @@
+use crate::{FramePath, Result, WebArchive, WebResource};
+use std::ops::Range;
+
+/// Where a pattern matched the text of a resource,
+/// found by [`WebArchive::search`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// The URL of the resource the match is in.
+    pub url: String,
+
+    /// The frame the resource belongs to.
+    pub frame: FramePath,
+
+    /// The line the match starts on, counting from 1.
+    pub line_number: usize,
+
+    /// Where the match starts and ends, as byte offsets into the
+    /// resource's [decoded text](WebResource::decoded_text), rather
+    /// than its data.
+    pub range: Range<usize>,
+
+    /// The line the match starts on, without its line ending.
+    pub line: String,
+}
+
+impl WebArchive {
+    /// Find every match for `pattern` within the text of resources,
+    /// at any depth, in document order.
+    ///
+    /// Each resource is decoded as
+    /// [`decoded_text`](WebResource::decoded_text) does, once however
+    /// many times it matches. Resources which are not a text type are
+    /// skipped.
+    pub fn search(&self, pattern: &regex::Regex) -> Result<Vec<SearchMatch>> {
+        let mut matches = Vec::new();
+
+        for (frame, archive) in self.iter_frames() {
+            for resource in
+                std::iter::once(&archive.main_resource).chain(archive.subresources.iter().flatten())
+            {
+                search_resource(resource, &frame, pattern, &mut matches);
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+fn search_resource(
+    resource: &WebResource,
+    frame: &FramePath,
+    pattern: &regex::Regex,
+    matches: &mut Vec<SearchMatch>,
+) {
+    let text = match resource.decoded_text() {
+        Some(text) => text,
+        None => return,
+    };
+
+    // count lines as matches are found, rather than from the start each time
+    let mut line_number = 1;
+    let mut counted_to = 0;
+
+    for found in pattern.find_iter(&text) {
+        line_number += text[counted_to..found.start()]
+            .bytes()
+            .filter(|byte| *byte == b'\n')
+            .count();
+        counted_to = found.start();
+
+        let line_start = text[..found.start()].rfind('\n').map_or(0, |end| end + 1);
+        let line_end = text[found.start()..]
+            .find('\n')
+            .map_or(text.len(), |end| found.start() + end);
+
+        matches.push(SearchMatch {
+            url: resource.url.clone(),
+            frame: frame.clone(),
+            line_number,
+            range: found.range(),
+            line: text[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WebArchive;
+    use regex::Regex;
+
+    fn shift_jis() -> WebArchive {
+        crate::from_file("fixtures/shift_jis.webarchive")
+            .expect("Could not read Shift JIS webarchive fixture")
+    }
+
+    #[test]
+    fn search_multibyte() {
+        let mut archive = shift_jis();
+        archive.main_resource.text_encoding_name = Some("Shift_JIS".to_string());
+
+        let matches = archive
+            .search(&Regex::new("ゲーム|ページ").unwrap())
+            .unwrap();
+        let text = archive.main_resource.decoded_text().unwrap();
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(&text[matches[0].range.clone()], "ページ");
+        assert_eq!(matches[0].line_number, 1);
+        assert_eq!(
+            matches[0].line,
+            "<html><head><title>日本語のページ</title></head><body>"
+        );
+
+        // offsets are into the decoded text, which is longer than the data
+        assert_eq!(&text[matches[1].range.clone()], "ゲーム");
+        assert_eq!(matches[1].line_number, 3);
+        assert_eq!(matches[1].line, "<p>プレイステーションのゲーム一覧。</p>");
+        assert!(matches[1].range.start > archive.main_resource.data.len() - 20);
+        assert!(matches[1].frame.is_root());
+    }
+
+    #[test]
+    #[cfg(feature = "chardet")]
+    fn search_detected_encoding() {
+        let matches = shift_jis()
+            .search(&Regex::new("シフトJIS").unwrap())
+            .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 2);
+    }
+
+    #[test]
+    fn search_frames() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let matches = archive
+            .search(&Regex::new(r"(?i)<frame\b").unwrap())
+            .unwrap();
+        assert_eq!(matches.len(), 4);
+        assert!(matches.iter().all(|found| found.frame.is_root()));
+
+        let matches = archive.search(&Regex::new("texgrey").unwrap()).unwrap();
+        assert!(!matches.is_empty());
+        assert!(matches.iter().all(|found| !found.frame.is_root()));
+        assert!(matches
+            .windows(2)
+            .all(|pair| pair[0].url != pair[1].url || pair[0].range.start < pair[1].range.start));
+    }
+}
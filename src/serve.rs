@@ -0,0 +1,534 @@
+//! Serving archived resources over HTTP, as a [tower] service.
+//!
+//! [`ArchiveService`] can be mounted within an existing server, such as
+//! under a path in an [axum](https://docs.rs/axum) router, or served on
+//! its own with [hyper], as `webarchive serve` does.
+//!
+//! [tower]: https://docs.rs/tower
+//! [hyper]: https://docs.rs/hyper
+
+use crate::digest::sha256_hex;
+use crate::{UrlNormalization, WebArchive, WebResource};
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::Full;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A service which responds to requests with the archive's resources.
+///
+/// Requests are matched to resources by their path and query alone,
+/// whatever host the resources came from, so that a page's links to
+/// its own resources lead back to the service. A request for `/`
+/// gets the main resource, unless a resource has that path. Paths and
+/// queries are compared in the [default](UrlNormalization::default)
+/// normal form, or as given to
+/// [`with_normalization`](ArchiveService::with_normalization).
+///
+/// Responses have a `Content-Type` with the resource's encoding, and
+/// an `ETag` of its digest, so `If-None-Match` requests get a
+/// `304 Not Modified` response. `HEAD` requests are answered, as are
+/// `Range` requests for a single range of bytes, which media players
+/// use to seek within audio and video.
+#[derive(Clone)]
+pub struct ArchiveService {
+    archive: Arc<WebArchive>,
+    resources: Arc<ResourceIndex>,
+}
+
+/// Where to find each resource, by path, and what to tag it with.
+struct ResourceIndex {
+    /// Each resource's position in [`WebArchive::iter_resources`],
+    /// by path and query, and by path without query.
+    paths: HashMap<String, usize>,
+
+    /// Each resource's `ETag`, by position.
+    etags: Vec<String>,
+
+    /// Where each resource is in the archive, by position.
+    locations: Vec<ResourceLocation>,
+
+    /// How requested paths are normalised to look them up.
+    normalization: UrlNormalization,
+}
+
+/// Where a resource is in the archive, so it can be found without
+/// walking the archive for every request.
+struct ResourceLocation {
+    /// The position of each subframe archive leading to its frame.
+    frame: Vec<usize>,
+
+    /// Its position among its frame's subresources, or `None` for the
+    /// frame's main resource.
+    subresource: Option<usize>,
+}
+
+impl ResourceLocation {
+    fn resource<'a>(&self, archive: &'a WebArchive) -> &'a WebResource {
+        let frame = self.frame.iter().fold(archive, |archive, index| {
+            &archive
+                .subframe_archives
+                .as_ref()
+                .expect("Located frames exist")[*index]
+        });
+        match self.subresource {
+            Some(index) => &frame
+                .subresources
+                .as_ref()
+                .expect("Located subresources exist")[index],
+            None => &frame.main_resource,
+        }
+    }
+}
+
+impl ArchiveService {
+    /// Serve the resources in `archive`.
+    pub fn new(archive: Arc<WebArchive>) -> Self {
+        Self::with_normalization(archive, UrlNormalization::default())
+    }
+
+    /// Serve the resources in `archive`, matching requests to them by
+    /// comparing URLs as `normalization` says, such as
+    /// [`UrlNormalization::loose`] to ignore tracking parameters.
+    pub fn with_normalization(archive: Arc<WebArchive>, normalization: UrlNormalization) -> Self {
+        let mut paths = HashMap::new();
+        let mut etags = Vec::new();
+        let mut locations = Vec::new();
+
+        // in the same order as `iter_resources`
+        for (frame_path, frame) in archive.iter_frames() {
+            let frame_indices: Vec<usize> = frame_path.segments()[1..]
+                .iter()
+                .map(|segment| segment.index)
+                .collect();
+            let resources = std::iter::once((None, &frame.main_resource)).chain(
+                frame
+                    .subresources
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .map(|(index, resource)| (Some(index), resource)),
+            );
+
+            for (subresource, resource) in resources {
+                let index = locations.len();
+                etags.push(format!("\"{}\"", sha256_hex(&resource.data)));
+                locations.push(ResourceLocation {
+                    frame: frame_indices.clone(),
+                    subresource,
+                });
+
+                if let Some((path, query)) = path_and_query(&resource.url, normalization) {
+                    if let Some(query) = query {
+                        paths.entry(format!("{}?{}", path, query)).or_insert(index);
+                    }
+                    paths.entry(path).or_insert(index);
+                }
+            }
+        }
+        paths.entry("/".to_string()).or_insert(0);
+
+        ArchiveService {
+            archive,
+            resources: Arc::new(ResourceIndex {
+                paths,
+                etags,
+                locations,
+                normalization,
+            }),
+        }
+    }
+
+    /// The archive being served.
+    pub fn archive(&self) -> &Arc<WebArchive> {
+        &self.archive
+    }
+
+    /// Respond to a request.
+    fn respond<B>(&self, request: &Request<B>) -> Response<Full<Bytes>> {
+        let method = request.method();
+        if method != Method::GET && method != Method::HEAD {
+            return plain(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(body(method, b"Method not allowed"))
+                .expect("Method not allowed response is valid");
+        }
+
+        let uri = request.uri();
+        let requested = uri
+            .path_and_query()
+            .map_or(uri.path(), |path| path.as_str());
+        let index = path_and_query(
+            &format!("http://localhost{}", requested),
+            self.resources.normalization,
+        )
+        .and_then(|(path, query)| {
+            query
+                .and_then(|query| self.resources.paths.get(&format!("{}?{}", path, query)))
+                .or_else(|| self.resources.paths.get(&path))
+        });
+        let (resource, etag) = match index {
+            Some(index) => (
+                self.resources.locations[*index].resource(&self.archive),
+                &self.resources.etags[*index],
+            ),
+            None => {
+                return plain(StatusCode::NOT_FOUND)
+                    .body(body(method, b"Not found"))
+                    .expect("Not found response is valid")
+            }
+        };
+
+        let content_type = match &resource.text_encoding_name {
+            Some(charset) if resource.is_text() => {
+                format!("{}; charset={}", resource.mime_essence(), charset)
+            }
+            _ => resource.mime_essence(),
+        };
+        let response = Response::builder()
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(&content_type)
+                    .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+            )
+            .header(header::ETAG, etag)
+            .header(header::ACCEPT_RANGES, "bytes");
+
+        let headers = request.headers();
+        if headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| etag_matches(value, etag))
+        {
+            return response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Full::default())
+                .expect("Not modified response is valid");
+        }
+
+        let data = &resource.data;
+        let if_range = headers
+            .get(header::IF_RANGE)
+            .is_none_or(|value| value.as_bytes() == etag.as_bytes());
+        let range = headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok())
+            .filter(|_| if_range)
+            .map_or(ByteRange::Whole, |range| byte_range(range, data.len()));
+
+        match range {
+            ByteRange::Part(start, end) => response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end - 1, data.len()),
+                )
+                .header(header::CONTENT_LENGTH, end - start)
+                .body(body(method, &data[start..end])),
+            ByteRange::Unsatisfiable => response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", data.len()))
+                .body(Full::default()),
+            ByteRange::Whole => response
+                .header(header::CONTENT_LENGTH, data.len())
+                .body(body(method, data)),
+        }
+        .expect("Resource response is valid")
+    }
+}
+
+impl<B> tower_service::Service<Request<B>> for ArchiveService {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        ready(Ok(self.respond(&request)))
+    }
+}
+
+/// The normalised path and query of `url`, which requests are matched by
+fn path_and_query(url: &str, normalization: UrlNormalization) -> Option<(String, Option<String>)> {
+    let url = url::Url::parse(&normalization.normalize(url)).ok()?;
+    Some((url.path().to_string(), url.query().map(str::to_string)))
+}
+
+/// The start of a plain text response.
+fn plain(status: StatusCode) -> http::response::Builder {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+}
+
+/// A response body of `data`, or nothing for `HEAD` requests.
+fn body(method: &Method, data: &[u8]) -> Full<Bytes> {
+    if method == Method::HEAD {
+        Full::default()
+    } else {
+        Full::new(Bytes::copy_from_slice(data))
+    }
+}
+
+/// Whether an `If-None-Match` header matches `etag`, comparing
+/// weakly, as that header is compared.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Which bytes of a resource to respond with.
+enum ByteRange {
+    /// All of them.
+    Whole,
+
+    /// Those from the start up to, but not including, the end.
+    Part(usize, usize),
+
+    /// None, as the range asked for is beyond the end.
+    Unsatisfiable,
+}
+
+/// The bytes a `Range` header asks for, of data `len` bytes long.
+///
+/// Only a single range is served; for headers asking for several, or
+/// which can't be parsed, the whole of the data is, as if there were
+/// no header.
+fn byte_range(range: &str, len: usize) -> ByteRange {
+    let parse = |number: &str| number.trim().parse::<usize>().ok();
+
+    let (start, end) = match range.trim().strip_prefix("bytes=") {
+        Some(range) if !range.contains(',') => match range.split_once('-') {
+            Some(range) => range,
+            None => return ByteRange::Whole,
+        },
+        _ => return ByteRange::Whole,
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match parse(suffix) {
+            Some(suffix) => (len.saturating_sub(suffix), len),
+            None => return ByteRange::Whole,
+        },
+        (start, "") => match parse(start) {
+            Some(start) => (start, len),
+            None => return ByteRange::Whole,
+        },
+        (start, end) => match (parse(start), parse(end)) {
+            (Some(start), Some(end)) if start <= end => (start, end.saturating_add(1).min(len)),
+            _ => return ByteRange::Whole,
+        },
+    };
+
+    if start < end {
+        ByteRange::Part(start, end)
+    } else {
+        ByteRange::Unsatisfiable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArchiveService;
+    use crate::test_support::resource;
+    use bytes::Bytes;
+    use http::{Request, Response, StatusCode};
+    use http_body_util::{BodyExt, Full};
+    use std::sync::Arc;
+    use std::task::Poll;
+    use tower_test::mock::Spawn;
+
+    const CROUTON_PNG: &[u8] = include_bytes!("../fixtures/crouton.net/crouton.png");
+
+    fn service() -> Spawn<ArchiveService> {
+        let archive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        Spawn::new(ArchiveService::new(Arc::new(archive)))
+    }
+
+    /// Make a request of the service, returning the response and its body.
+    fn request(
+        service: &mut Spawn<ArchiveService>,
+        request: http::request::Builder,
+    ) -> (Response<Full<Bytes>>, Bytes) {
+        assert!(matches!(
+            service.poll_ready::<Request<()>>(),
+            Poll::Ready(Ok(()))
+        ));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let response = runtime
+            .block_on(service.call(request.body(()).unwrap()))
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let data = runtime.block_on(body.clone().collect()).unwrap().to_bytes();
+
+        (Response::from_parts(parts, body), data)
+    }
+
+    #[test]
+    fn serve_ok() {
+        let mut service = service();
+
+        let (response, body) = request(&mut service, Request::get("/"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=UTF-8"
+        );
+        assert_eq!(body, service.get_ref().archive().main_resource.data);
+
+        // whatever host a request is for
+        let (response, body) =
+            request(&mut service, Request::get("http://example.com/crouton.png"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert_eq!(response.headers()["content-length"], "5182");
+        assert_eq!(body, CROUTON_PNG);
+
+        let (response, body) = request(&mut service, Request::head("/crouton.png"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-length"], "5182");
+        assert!(body.is_empty());
+
+        let (response, _) = request(&mut service, Request::post("/crouton.png"));
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn serve_not_modified() {
+        let mut service = service();
+
+        let (response, _) = request(&mut service, Request::get("/crouton.png"));
+        let etag = response.headers()["etag"].clone();
+
+        let (response, body) = request(
+            &mut service,
+            Request::get("/crouton.png").header("If-None-Match", &etag),
+        );
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()["etag"], etag);
+        assert!(body.is_empty());
+
+        let (response, _) = request(
+            &mut service,
+            Request::get("/crouton.png").header("If-None-Match", "\"something else\""),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn serve_not_found() {
+        let mut service = service();
+
+        let (response, body) = request(&mut service, Request::get("/missing.png"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body, "Not found");
+    }
+
+    #[test]
+    fn serve_range() {
+        let mut service = service();
+
+        for (range, status, content_range, expected) in [
+            ("bytes=0-7", 206, "bytes 0-7/5182", &CROUTON_PNG[..8]),
+            (
+                "bytes=5180-",
+                206,
+                "bytes 5180-5181/5182",
+                &CROUTON_PNG[5180..],
+            ),
+            (
+                "bytes=-2",
+                206,
+                "bytes 5180-5181/5182",
+                &CROUTON_PNG[5180..],
+            ),
+            (
+                "bytes=5100-9999",
+                206,
+                "bytes 5100-5181/5182",
+                &CROUTON_PNG[5100..],
+            ),
+            ("bytes=9999-", 416, "bytes */5182", &[][..]),
+        ] {
+            let (response, body) = request(
+                &mut service,
+                Request::get("/crouton.png").header("Range", range),
+            );
+            assert_eq!(response.status(), status, "{}", range);
+            assert_eq!(response.headers()["content-range"], content_range);
+            assert_eq!(body, expected, "{}", range);
+        }
+
+        // several ranges are served as the whole
+        let (response, body) = request(
+            &mut service,
+            Request::get("/crouton.png").header("Range", "bytes=0-1,4-5"),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key("content-range"));
+        assert_eq!(body, CROUTON_PNG);
+    }
+
+    #[test]
+    fn serve_frames() {
+        let archive: crate::WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let archive = Arc::new(archive);
+        let mut service = Spawn::new(ArchiveService::new(archive.clone()));
+
+        // resources inside subframe archives are found, and the first
+        // resource with a path is served for it
+        let mut served = std::collections::HashSet::new();
+        for resource in archive.iter_resources() {
+            let path = url::Url::parse(&resource.url).unwrap().path().to_string();
+            if served.insert(path.clone()) {
+                let (response, body) = request(&mut service, Request::get(path.as_str()));
+                assert_eq!(response.status(), StatusCode::OK, "{}", path);
+                assert_eq!(body, resource.data, "{}", path);
+            }
+        }
+        assert!(archive.subframe_archives.is_some());
+    }
+
+    #[test]
+    fn serve_normalization() {
+        let page = |query: &str| {
+            resource(
+                &format!("https://example.com/page{}", query),
+                "text/plain",
+                query,
+            )
+        };
+        let archive = Arc::new(crate::WebArchive {
+            main_resource: page(""),
+            subresources: Some(vec![page("?id=1"), page("?id=2"), page("?id=3#top")]),
+            subframe_archives: None,
+        });
+
+        let mut service = Spawn::new(ArchiveService::new(archive.clone()));
+        let (_, body) = request(&mut service, Request::get("/page?id=2"));
+        assert_eq!(body, "?id=2");
+        let (_, body) = request(&mut service, Request::get("/a/../page?id=3"));
+        assert_eq!(body, "?id=3#top");
+        // an unknown query falls back to the path alone
+        let (_, body) = request(&mut service, Request::get("/page?id=2&utm_source=feed"));
+        assert_eq!(body, "");
+
+        let loose = crate::UrlNormalization::loose();
+        let mut service = Spawn::new(ArchiveService::with_normalization(archive, loose));
+        let (_, body) = request(&mut service, Request::get("/page?id=2&utm_source=feed"));
+        assert_eq!(body, "?id=2");
+        let (_, body) = request(&mut service, Request::get("/page?utm_source=feed"));
+        assert_eq!(body, "");
+    }
+}
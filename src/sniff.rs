@@ -0,0 +1,608 @@
+//! Guessing the type of resource data from its contents, following
+//! the relevant parts of the [WHATWG MIME Sniffing Standard].
+//!
+//! [WHATWG MIME Sniffing Standard]: https://mimesniff.spec.whatwg.org/
+
+use crate::{WebArchive, WebResource};
+use serde::Serialize;
+use std::fmt;
+
+/// A byte pattern which identifies a file format.
+struct Signature {
+    /// The bytes to look for at the start of the data.
+    pattern: &'static [u8],
+    /// Which bits of each byte of `pattern` must match; if empty, all of them.
+    mask: &'static [u8],
+    /// The format's MIME type.
+    mime_type: &'static str,
+    /// The usual file extension for the format.
+    extension: &'static str,
+}
+
+impl Signature {
+    fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.pattern.len()
+            && self.pattern.iter().enumerate().all(|(index, byte)| {
+                let mask = self.mask.get(index).copied().unwrap_or(0xff);
+                data[index] & mask == byte & mask
+            })
+    }
+}
+
+const fn signature(
+    pattern: &'static [u8],
+    mime_type: &'static str,
+    extension: &'static str,
+) -> Signature {
+    Signature {
+        pattern,
+        mask: &[],
+        mime_type,
+        extension,
+    }
+}
+
+const fn masked_signature(
+    pattern: &'static [u8],
+    mask: &'static [u8],
+    mime_type: &'static str,
+    extension: &'static str,
+) -> Signature {
+    Signature {
+        pattern,
+        mask,
+        mime_type,
+        extension,
+    }
+}
+
+/// Skips the four bytes of a RIFF or IFF chunk's size.
+const CHUNK_MASK: &[u8] = b"\xff\xff\xff\xff\0\0\0\0\xff\xff\xff\xff";
+
+/// Formats which are identified by an exact signature.
+const SIGNATURES: &[Signature] = &[
+    // images
+    signature(b"\x89PNG\r\n\x1a\n", "image/png", "png"),
+    signature(b"\xff\xd8\xff", "image/jpeg", "jpg"),
+    signature(b"GIF87a", "image/gif", "gif"),
+    signature(b"GIF89a", "image/gif", "gif"),
+    masked_signature(b"RIFF\0\0\0\0WEBPVP", CHUNK_MASK, "image/webp", "webp"),
+    signature(b"BM", "image/bmp", "bmp"),
+    signature(b"\0\0\x01\0", "image/x-icon", "ico"),
+    signature(b"\0\0\x02\0", "image/x-icon", "cur"),
+    // audio and video
+    masked_signature(b"FORM\0\0\0\0AIFF", CHUNK_MASK, "audio/aiff", "aiff"),
+    masked_signature(b"RIFF\0\0\0\0WAVE", CHUNK_MASK, "audio/wave", "wav"),
+    masked_signature(b"RIFF\0\0\0\0AVI ", CHUNK_MASK, "video/avi", "avi"),
+    signature(b"ID3", "audio/mpeg", "mp3"),
+    signature(b"OggS\0", "application/ogg", "ogg"),
+    signature(b"MThd\0\0\0\x06", "audio/midi", "mid"),
+    signature(b"\x1a\x45\xdf\xa3", "video/webm", "webm"),
+    // fonts
+    signature(b"wOFF", "font/woff", "woff"),
+    signature(b"wOF2", "font/woff2", "woff2"),
+    // documents and archives
+    signature(b"%PDF-", "application/pdf", "pdf"),
+    signature(b"%!PS-Adobe-", "application/postscript", "ps"),
+    signature(b"\x1f\x8b\x08", "application/x-gzip", "gz"),
+    signature(b"PK\x03\x04", "application/zip", "zip"),
+    signature(b"Rar!\x1a\x07\0", "application/x-rar-compressed", "rar"),
+];
+
+/// Tags which, at the start of a document, mean it is HTML, when
+/// followed by a space or `>`.
+const HTML_TAGS: &[&[u8]] = &[
+    b"<!DOCTYPE HTML",
+    b"<HTML",
+    b"<HEAD",
+    b"<SCRIPT",
+    b"<IFRAME",
+    b"<H1",
+    b"<DIV",
+    b"<FONT",
+    b"<TABLE",
+    b"<A",
+    b"<STYLE",
+    b"<TITLE",
+    b"<B",
+    b"<BODY",
+    b"<BR",
+    b"<P",
+    b"<!--",
+];
+
+/// What sniffing found out about some data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Sniffed {
+    /// The MIME type the data looks like.
+    pub(crate) mime_type: &'static str,
+    /// Whether the type was identified by an exact signature, rather
+    /// than by looking for markup or the absence of binary bytes.
+    pub(crate) exact: bool,
+}
+
+/// How sure [`WebArchive::detect_mime_mismatches`] is about a
+/// resource's sniffed type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SniffConfidence {
+    /// The data starts with a format's exact signature, or a byte
+    /// order mark.
+    Signature,
+
+    /// The data only looks like markup or text.
+    Heuristic,
+}
+
+impl fmt::Display for SniffConfidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SniffConfidence::Signature => "exact signature",
+            SniffConfidence::Heuristic => "heuristic",
+        })
+    }
+}
+
+/// A resource whose contents do not look like its declared MIME type,
+/// found by [`WebArchive::detect_mime_mismatches`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MimeMismatch {
+    /// The URL of the resource.
+    pub url: String,
+
+    /// The MIME type the resource declares, without parameters.
+    pub declared: String,
+
+    /// The MIME type the resource's data looks like.
+    pub sniffed: &'static str,
+
+    /// How the sniffed type was identified.
+    pub confidence: SniffConfidence,
+}
+
+impl fmt::Display for MimeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "declared {:?}, but looks like {:?} ({})",
+            self.declared, self.sniffed, self.confidence
+        )
+    }
+}
+
+/// Alternative names for the types in [`SIGNATURES`], which
+/// servers commonly send.
+const ALIASES: &[(&str, &str)] = &[
+    ("image/jpg", "image/jpeg"),
+    ("image/pjpeg", "image/jpeg"),
+    ("image/x-png", "image/png"),
+    ("image/x-ms-bmp", "image/bmp"),
+    ("image/vnd.microsoft.icon", "image/x-icon"),
+    ("audio/x-aiff", "audio/aiff"),
+    ("audio/wav", "audio/wave"),
+    ("audio/x-wav", "audio/wave"),
+    ("video/x-msvideo", "video/avi"),
+    ("audio/mp3", "audio/mpeg"),
+    ("audio/ogg", "application/ogg"),
+    ("video/ogg", "application/ogg"),
+    ("audio/mid", "audio/midi"),
+    ("audio/x-midi", "audio/midi"),
+    ("audio/webm", "video/webm"),
+    ("audio/mp4", "video/mp4"),
+    ("application/font-woff", "font/woff"),
+    ("application/x-font-woff", "font/woff"),
+    ("application/gzip", "application/x-gzip"),
+    ("application/x-zip-compressed", "application/zip"),
+    ("application/vnd.rar", "application/x-rar-compressed"),
+];
+
+/// Whether data sniffed as `sniffed` is plausibly of the `declared` type.
+fn is_compatible(declared: &str, sniffed: Sniffed) -> bool {
+    let declared = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == declared)
+        .map_or(declared, |(_, canonical)| canonical);
+
+    if declared == sniffed.mime_type {
+        return true;
+    }
+
+    let declared_text = declared.starts_with("text/")
+        || declared.ends_with("+xml")
+        || declared.ends_with("+json")
+        || matches!(
+            declared,
+            "application/javascript"
+                | "application/x-javascript"
+                | "application/json"
+                | "application/xml"
+        );
+
+    match sniffed.mime_type {
+        // any text type may start with a byte order mark, or be plain text
+        "text/plain" => declared_text,
+        // HTML which was declared as plain text will be shown as source
+        "text/html" | "text/xml" => declared_text && declared != "text/plain",
+        _ => false,
+    }
+}
+
+/// The usual extension for the format identified by the signature at
+/// the start of `data`, if it has one.
+pub(crate) fn signature_extension(data: &[u8]) -> Option<&'static str> {
+    if is_mp4(data) {
+        return Some("mp4");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|signature| signature.matches(data))
+        .map(|signature| signature.extension)
+}
+
+/// The usual extension for a MIME type which has a signature,
+/// such as `png` for `image/png`.
+pub(crate) fn usual_extension(mime_type: &str) -> Option<&'static str> {
+    if mime_type == "video/mp4" {
+        return Some("mp4");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|signature| signature.mime_type == mime_type)
+        .map(|signature| signature.extension)
+}
+
+/// Guess the type of `data` from its contents alone.
+pub(crate) fn sniff(data: &[u8]) -> Option<Sniffed> {
+    let exact = |mime_type| Sniffed {
+        mime_type,
+        exact: true,
+    };
+    let heuristic = |mime_type| Sniffed {
+        mime_type,
+        exact: false,
+    };
+
+    if is_mp4(data) {
+        return Some(exact("video/mp4"));
+    }
+
+    if let Some(signature) = SIGNATURES.iter().find(|signature| signature.matches(data)) {
+        return Some(exact(signature.mime_type));
+    }
+
+    // byte order marks only appear at the start of text
+    if [&b"\xfe\xff"[..], b"\xff\xfe", b"\xef\xbb\xbf"]
+        .iter()
+        .any(|bom| data.starts_with(bom))
+    {
+        return Some(exact("text/plain"));
+    }
+
+    let start = data
+        .iter()
+        .position(|byte| !matches!(byte, b'\t' | b'\n' | b'\x0c' | b'\r' | b' '))
+        .unwrap_or(data.len());
+    let markup = &data[start..];
+
+    let is_html = HTML_TAGS.iter().any(|tag| {
+        markup.len() > tag.len()
+            && markup[..tag.len()].eq_ignore_ascii_case(tag)
+            && matches!(markup[tag.len()], b' ' | b'>')
+    });
+    if is_html {
+        return Some(heuristic("text/html"));
+    }
+
+    if markup.starts_with(b"<?xml") {
+        return Some(heuristic("text/xml"));
+    }
+
+    let is_binary = data
+        .iter()
+        .any(|byte| matches!(byte, 0x00..=0x08 | 0x0b | 0x0e..=0x1a | 0x1c..=0x1f));
+    if !data.is_empty() && !is_binary {
+        return Some(heuristic("text/plain"));
+    }
+
+    None
+}
+
+/// Whether `data` is an MP4 file, whose `ftyp` box names an `mp4` brand.
+fn is_mp4(data: &[u8]) -> bool {
+    if data.len() < 12 {
+        return false;
+    }
+
+    let box_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if box_size < 12
+        || !box_size.is_multiple_of(4)
+        || data.len() < box_size
+        || &data[4..8] != b"ftyp"
+    {
+        return false;
+    }
+
+    // the major brand, then the compatible brands, skipping the minor version
+    data[8..box_size]
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(index, _)| *index != 1)
+        .any(|(_, brand)| brand.starts_with(b"mp4"))
+}
+
+impl WebResource {
+    /// Guess the MIME type of the resource's data from its contents,
+    /// ignoring the declared [`mime_type`](WebResource::mime_type).
+    ///
+    /// Images, audio, video, fonts, PDF, PostScript and common archive
+    /// formats are recognised by their signatures. Otherwise, data
+    /// which starts with common HTML tags is `text/html`, data which
+    /// starts with an XML declaration is `text/xml`, and data with no
+    /// binary control characters is `text/plain`.
+    ///
+    /// Returns `None` for empty data, and binary data of other types.
+    pub fn sniff_mime(&self) -> Option<&'static str> {
+        sniff(&self.data).map(|sniffed| sniffed.mime_type)
+    }
+}
+
+impl WebArchive {
+    /// Find resources, at any depth, whose data does not look like
+    /// their declared MIME type, in document order.
+    ///
+    /// Resources without a declared type, or whose type can not be
+    /// sniffed, are skipped. Common aliases, such as `image/jpg`, are
+    /// accepted, and text sniffed only by heuristics is accepted for
+    /// any declared text type, so most findings with a
+    /// [`Heuristic`](SniffConfidence::Heuristic) confidence are binary
+    /// types which contain text.
+    pub fn detect_mime_mismatches(&self) -> Vec<MimeMismatch> {
+        self.iter_resources().filter_map(mime_mismatch).collect()
+    }
+
+    /// Change the MIME type of resources, at any depth, whose data has
+    /// the signature of another type to the type it looks like,
+    /// returning what was changed.
+    ///
+    /// If `aggressive` is set, types sniffed only by heuristics are
+    /// used too. When a resource becomes a text type, its encoding is
+    /// set to UTF-8 or UTF-16 if its data is valid and has no encoding
+    /// declared; when it stops being one, its encoding is removed.
+    pub fn fix_mime_types(&mut self, aggressive: bool) -> Vec<MimeMismatch> {
+        let mut fixed = Vec::new();
+
+        self.for_each_resource_mut(&mut |resource| {
+            let mismatch = match mime_mismatch(resource) {
+                Some(mismatch)
+                    if aggressive || mismatch.confidence == SniffConfidence::Signature =>
+                {
+                    mismatch
+                }
+                _ => return,
+            };
+
+            let was_text = resource.is_text();
+            resource.mime_type = mismatch.sniffed.to_string();
+
+            if !resource.is_text() {
+                resource.text_encoding_name = None;
+            } else if !was_text || resource.text_encoding_name.is_none() {
+                resource.text_encoding_name = text_encoding(&resource.data).map(str::to_string);
+            }
+
+            fixed.push(mismatch);
+        });
+
+        fixed
+    }
+}
+
+/// Compare a resource's declared and sniffed types.
+fn mime_mismatch(resource: &WebResource) -> Option<MimeMismatch> {
+    let declared = resource.mime_essence();
+    let sniffed = sniff(&resource.data)?;
+
+    if declared.is_empty() || is_compatible(&declared, sniffed) {
+        return None;
+    }
+
+    Some(MimeMismatch {
+        url: resource.url.clone(),
+        declared,
+        sniffed: sniffed.mime_type,
+        confidence: if sniffed.exact {
+            SniffConfidence::Signature
+        } else {
+            SniffConfidence::Heuristic
+        },
+    })
+}
+
+/// The encoding of text `data`, from its byte order mark, or UTF-8
+/// if it is valid UTF-8.
+fn text_encoding(data: &[u8]) -> Option<&'static str> {
+    match encoding_rs::Encoding::for_bom(data) {
+        Some((encoding, _)) => Some(encoding.name()),
+        None => std::str::from_utf8(data).is_ok().then_some("UTF-8"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff, SniffConfidence, Sniffed};
+    use crate::test_support::resource;
+    use crate::WebArchive;
+
+    fn sniffed(data: &[u8]) -> Option<&'static str> {
+        sniff(data).map(|sniffed| sniffed.mime_type)
+    }
+
+    #[test]
+    fn sniff_signatures() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "image/jpeg"),
+            (b"GIF87a\x01\0", "image/gif"),
+            (b"GIF89a\x01\0", "image/gif"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp"),
+            (b"BM\x36\0\0\0", "image/bmp"),
+            (b"\0\0\x01\0\x01\0", "image/x-icon"),
+            (b"\0\0\x02\0\x01\0", "image/x-icon"),
+            (b"FORM\0\0\x10\0AIFFCOMM", "audio/aiff"),
+            (b"RIFF\x24\x08\0\0WAVEfmt ", "audio/wave"),
+            (b"RIFF\x24\x08\0\0AVI LIST", "video/avi"),
+            (b"ID3\x04\0\0", "audio/mpeg"),
+            (b"OggS\0\x02", "application/ogg"),
+            (b"MThd\0\0\0\x06\0\x01", "audio/midi"),
+            (b"\x1a\x45\xdf\xa3\x01\0", "video/webm"),
+            (b"\0\0\0\x18ftypmp42\0\0\0\0isommp42", "video/mp4"),
+            (b"\0\0\0\x14ftypisom\0\0\x02\0mp41", "video/mp4"),
+            (b"wOFF\0\x01\0\0", "font/woff"),
+            (b"wOF2\0\x01\0\0", "font/woff2"),
+            (b"%PDF-1.7\n", "application/pdf"),
+            (b"%!PS-Adobe-3.0\n", "application/postscript"),
+            (b"\x1f\x8b\x08\0", "application/x-gzip"),
+            (b"PK\x03\x04\x14\0", "application/zip"),
+            (b"Rar!\x1a\x07\0\xcf", "application/x-rar-compressed"),
+            (b"\xef\xbb\xbfhello", "text/plain"),
+            (b"\xff\xfeh\0i\0", "text/plain"),
+        ];
+
+        for (data, expected) in cases {
+            assert_eq!(
+                sniff(data),
+                Some(Sniffed {
+                    mime_type: expected,
+                    exact: true
+                }),
+                "sniffing {:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn sniff_crouton_png() {
+        let png = include_bytes!("../fixtures/crouton.net/crouton.png");
+        assert_eq!(sniffed(png), Some("image/png"));
+
+        let index = include_bytes!("../fixtures/crouton.net/_unnamed_index.shtml");
+        assert_eq!(sniffed(index), Some("text/html"));
+    }
+
+    #[test]
+    fn sniff_heuristics() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"<!doctype html><p>", Some("text/html")),
+            (b"\n  <HTML lang=en>", Some("text/html")),
+            (b"<p>Crouton</p>", Some("text/html")),
+            (b"<!-- comment -->", Some("text/html")),
+            (b"<?xml version=\"1.0\"?><rss/>", Some("text/xml")),
+            (b"<pre>", Some("text/plain")),
+            (b"body { color: red }", Some("text/plain")),
+            (b"", None),
+            (b"\0\x01\x02\x03", None),
+            // truncated signatures and boxes are not enough
+            (b"\x89PN", Some("text/plain")),
+            (b"\0\0\0\x18ftypmp42", None),
+        ];
+
+        for (data, expected) in cases {
+            assert_eq!(sniffed(data), *expected, "sniffing {:?}", data);
+
+            if let Some(sniffed) = sniff(data) {
+                assert!(!sniffed.exact, "{:?} is not an exact match", data);
+            }
+        }
+    }
+
+    #[test]
+    fn detect_mime_mismatches() {
+        let png = include_bytes!("../fixtures/crouton.net/crouton.png");
+
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/html", b"<p>Hello</p>"),
+            subresources: Some(vec![
+                resource("https://example.com/a.png", "image/png", png),
+                resource("https://example.com/b.png", "text/html; charset=utf-8", png),
+                resource(
+                    "https://example.com/c.jpg",
+                    "image/jpg",
+                    b"\xff\xd8\xff\xe0",
+                ),
+                resource("https://example.com/d.css", "text/css", b"body {}"),
+                resource(
+                    "https://example.com/e.js",
+                    "text/javascript",
+                    b"<!-- hidden",
+                ),
+                resource("https://example.com/f.txt", "text/plain", b"\xef\xbb\xbfhi"),
+                resource(
+                    "https://example.com/g.bin",
+                    "application/octet-stream",
+                    b"\0\x01",
+                ),
+                resource("https://example.com/h", "", png),
+            ]),
+            subframe_archives: None,
+        };
+
+        let mismatches = archive.detect_mime_mismatches();
+        assert_eq!(mismatches.len(), 1, "{:?}", mismatches);
+        assert_eq!(mismatches[0].url, "https://example.com/b.png");
+        assert_eq!(mismatches[0].declared, "text/html");
+        assert_eq!(mismatches[0].sniffed, "image/png");
+        assert_eq!(mismatches[0].confidence, SniffConfidence::Signature);
+
+        let archive = WebArchive {
+            main_resource: resource("https://example.com/", "text/plain", b"<html><p>Hi"),
+            subresources: Some(vec![resource(
+                "https://example.com/a.gif",
+                "image/gif",
+                b"Not Found",
+            )]),
+            subframe_archives: None,
+        };
+        let confidences: Vec<(&str, SniffConfidence)> = archive
+            .detect_mime_mismatches()
+            .iter()
+            .map(|mismatch| (mismatch.sniffed, mismatch.confidence))
+            .collect();
+        assert_eq!(
+            confidences,
+            [
+                ("text/html", SniffConfidence::Heuristic),
+                ("text/plain", SniffConfidence::Heuristic)
+            ]
+        );
+    }
+
+    #[test]
+    fn fix_mime_types() {
+        let mut archive: WebArchive = crate::from_file("fixtures/mislabeled.webarchive")
+            .expect("Could not read mislabeled webarchive fixture");
+        archive.main_resource.mime_type = "application/octet-stream".to_string();
+        archive.main_resource.text_encoding_name = None;
+
+        let fixed = archive.fix_mime_types(false);
+        assert_eq!(fixed.len(), 1);
+        assert_eq!(fixed[0].url, "https://crouton.net/crouton/");
+
+        let png = &archive.subresources.as_ref().unwrap()[0];
+        assert_eq!(png.mime_type, "image/png");
+        assert_eq!(png.text_encoding_name, None);
+        assert_eq!(archive.main_resource.mime_type, "application/octet-stream");
+
+        let fixed = archive.fix_mime_types(true);
+        assert_eq!(fixed.len(), 1);
+        assert_eq!(fixed[0].confidence, SniffConfidence::Heuristic);
+        assert_eq!(archive.main_resource.mime_type, "text/html");
+        assert_eq!(
+            archive.main_resource.text_encoding_name.as_deref(),
+            Some("UTF-8")
+        );
+        assert!(archive.detect_mime_mismatches().is_empty());
+    }
+}
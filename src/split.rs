@@ -0,0 +1,518 @@
+use crate::{Error, Result, WebArchive, WebArchiveBuilder, WebResource};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+/// The start of the URL of the page listing the contents of each
+/// overflow archive made by [`WebArchive::split`], followed by the
+/// number of the part.
+pub(crate) const PART_URL_PREFIX: &str = "about:webarchive-part-";
+
+/// Room for the length of a part's page to take up more bytes, once
+/// its list of contents is filled in.
+const INDEX_LENGTH_SLACK: usize = 9;
+
+/// Where a subresource is stored: the indexes through the subframe
+/// archives to its frame, empty for the main frame, and its position
+/// among that frame's subresources.
+type Origin = (Vec<usize>, usize);
+
+/// A part being packed
+struct Part {
+    builder: WebArchiveBuilder,
+    urls: Vec<String>,
+    placements: Vec<Origin>,
+    /// The index here of each of the original's frames which has a
+    /// copy in this part, by its index in the original
+    frames: HashMap<usize, usize>,
+    index_growth: usize,
+}
+
+impl Part {
+    fn new(builder: WebArchiveBuilder) -> Self {
+        Part {
+            builder,
+            urls: Vec::new(),
+            placements: Vec::new(),
+            frames: HashMap::new(),
+            index_growth: 0,
+        }
+    }
+
+    /// The path in this part to the frame at `frame` in the original,
+    /// if it has a copy here
+    fn frame_path(&self, frame: &[usize]) -> Option<Vec<usize>> {
+        match frame.split_first() {
+            None => Some(Vec::new()),
+            Some((top, rest)) => self.frames.get(top).map(|index| {
+                std::iter::once(*index)
+                    .chain(rest.iter().copied())
+                    .collect()
+            }),
+        }
+    }
+
+    /// The predicted size of the part with `resource` added to the
+    /// frame at `frame` in the original, copying that frame from
+    /// `frames` if there is no copy here yet
+    fn estimated_size_with(
+        &self,
+        frame: &[usize],
+        resource: &WebResource,
+        frames: &[WebArchive],
+    ) -> usize {
+        match self.frame_path(frame) {
+            Some(path) if path.is_empty() => self.builder.estimated_size_with(resource),
+            Some(path) => self.builder.estimated_size_with_in_frame(&path, resource),
+            None => {
+                let mut copy = frames[frame[0]].clone();
+                let subframe = frame[1..].iter().fold(&mut copy, |archive, index| {
+                    &mut archive
+                        .subframe_archives
+                        .as_mut()
+                        .expect("The frame exists")[*index]
+                });
+                subframe.subresources = Some(vec![resource.clone()]);
+                self.builder.estimated_size_with_subframe_archive(&copy)
+            }
+        }
+    }
+
+    /// Add `resource`, from `origin` in the original, to the same frame
+    /// here, copying that frame from `frames` if there is no copy yet
+    fn add(&mut self, origin: Origin, resource: WebResource, frames: &[WebArchive]) -> Result<()> {
+        let (frame, position) = origin;
+        let path = match self.frame_path(&frame) {
+            Some(path) => path,
+            None => {
+                self.frames
+                    .insert(frame[0], self.builder.subframe_archive_count());
+                self.builder
+                    .add_subframe_archive(frames[frame[0]].clone())?;
+                self.frame_path(&frame).expect("The frame was just copied")
+            }
+        };
+
+        if path.is_empty() {
+            self.builder.add_subresource(resource)?;
+        } else {
+            self.builder.add_frame_subresource(&path, resource)?;
+        }
+        self.placements.push((path, position));
+        Ok(())
+    }
+}
+
+impl WebArchive {
+    /// Split the archive into parts which are each no larger than
+    /// `max_bytes` once serialised as a binary property list, such as
+    /// to send a large capture as several email attachments.
+    ///
+    /// The first part keeps the main resource and every subframe
+    /// archive's main resource, along with as many subresources, from
+    /// any frame, as fit, preferring those which are referred to most
+    /// often, then the smallest. The rest are packed into overflow
+    /// archives, whose main resource is a generated page listing what
+    /// they contain, stored at a URL starting `about:webarchive-part-`.
+    /// Subresources of a subframe archive go in a copy of its frame,
+    /// without the frame's other subresources, and stay in document
+    /// order within each frame of each part.
+    ///
+    /// [`merge`](WebArchive::merge)ing the overflow archives into the
+    /// first gives back the original resources, each in its own frame,
+    /// as it leaves out those pages. An archive which already fits is
+    /// returned as it is.
+    ///
+    /// Returns [`Error::BudgetExceeded`] if the main resources of the
+    /// archive and its frames, or any one subresource, do not fit in a
+    /// part on their own, naming the resource or frame which did not
+    /// fit, or an error if an HTML or CSS resource can not be parsed to
+    /// count the references to each subresource.
+    pub fn split(self, max_bytes: usize) -> Result<Vec<WebArchive>> {
+        if self.fits_within(max_bytes) {
+            return Ok(vec![self]);
+        }
+
+        let mut reference_counts: HashMap<String, usize> = HashMap::new();
+        for resource in self.iter_resources() {
+            for reference in resource.references()? {
+                *reference_counts.entry(reference.url).or_default() += 1;
+            }
+        }
+
+        let WebArchive {
+            main_resource,
+            subresources,
+            subframe_archives,
+        } = self;
+        let main_url = main_resource.url.clone();
+
+        let mut subresources: Vec<(Origin, WebResource)> = subresources
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(position, resource)| ((Vec::new(), position), resource))
+            .collect();
+        let frames: Vec<WebArchive> = subframe_archives
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, frame)| without_subresources(frame, vec![index], &mut subresources))
+            .collect();
+
+        let mut first = WebArchiveBuilder::new(main_resource).max_total_size(max_bytes);
+        if first.estimated_size() > max_bytes {
+            return Err(Error::BudgetExceeded {
+                url: main_url,
+                would_be: first.estimated_size(),
+            });
+        }
+        for frame in &frames {
+            first.add_subframe_archive(frame.clone())?;
+        }
+        let mut first = Part::new(first);
+        first.frames = (0..frames.len()).map(|index| (index, index)).collect();
+
+        subresources.sort_by(|(a_origin, a), (b_origin, b)| {
+            let references = |resource: &WebResource| {
+                Reverse(reference_counts.get(&resource.url).copied().unwrap_or(0))
+            };
+            (references(a), a.data.len(), a_origin).cmp(&(references(b), b.data.len(), b_origin))
+        });
+
+        let mut parts: Vec<Part> = Vec::new();
+        for (origin, resource) in subresources {
+            let frame = &origin.0;
+            if first.estimated_size_with(frame, &resource, &frames) <= max_bytes {
+                first.add(origin, resource, &frames)?;
+                continue;
+            }
+
+            let entry_len = index_entry(&resource.url).len();
+            let would_be = |part: &Part| {
+                part.estimated_size_with(frame, &resource, &frames)
+                    + part.index_growth
+                    + entry_len
+                    + INDEX_LENGTH_SLACK
+            };
+
+            let part = match parts.iter().position(|part| would_be(part) <= max_bytes) {
+                Some(index) => &mut parts[index],
+                None => {
+                    let part = Part::new(WebArchiveBuilder::new(index_page(
+                        parts.len() + 2,
+                        &main_url,
+                        &[],
+                    )));
+                    if would_be(&part) > max_bytes {
+                        return Err(Error::BudgetExceeded {
+                            would_be: would_be(&part),
+                            url: resource.url,
+                        });
+                    }
+                    parts.push(part);
+                    parts.last_mut().expect("A part was just added")
+                }
+            };
+
+            part.index_growth += entry_len;
+            part.urls.push(resource.url.clone());
+            part.add(origin, resource, &frames)?;
+        }
+
+        let mut archives = vec![in_document_order(first.builder.build(), first.placements)];
+        for (index, part) in parts.into_iter().enumerate() {
+            let mut archive = in_document_order(part.builder.build(), part.placements);
+            archive.main_resource = index_page(index + 2, &main_url, &part.urls);
+            archives.push(archive);
+        }
+
+        Ok(archives)
+    }
+}
+
+/// `frame`, and its subframe archives, without their subresources,
+/// which are added to `subresources` along with where they came from,
+/// `frame` being at `path`
+fn without_subresources(
+    mut frame: WebArchive,
+    path: Vec<usize>,
+    subresources: &mut Vec<(Origin, WebResource)>,
+) -> WebArchive {
+    subresources.extend(
+        frame
+            .subresources
+            .take()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(position, resource)| ((path.clone(), position), resource)),
+    );
+
+    frame.subframe_archives = frame.subframe_archives.map(|subframe_archives| {
+        subframe_archives
+            .into_iter()
+            .enumerate()
+            .map(|(index, subframe)| {
+                let mut path = path.clone();
+                path.push(index);
+                without_subresources(subframe, path, subresources)
+            })
+            .collect()
+    });
+
+    frame
+}
+
+/// `archive` with the subresources of each frame, added in the order
+/// of `placements`, sorted by their positions in the original
+fn in_document_order(mut archive: WebArchive, placements: Vec<Origin>) -> WebArchive {
+    let mut positions: BTreeMap<Vec<usize>, Vec<usize>> = BTreeMap::new();
+    for (frame, position) in placements {
+        positions.entry(frame).or_default().push(position);
+    }
+
+    for (frame, positions) in positions {
+        let frame = frame.iter().fold(&mut archive, |archive, index| {
+            &mut archive
+                .subframe_archives
+                .as_mut()
+                .expect("Resources were added to the frame")[*index]
+        });
+
+        if let Some(subresources) = frame.subresources.take() {
+            let mut subresources: Vec<_> = positions.into_iter().zip(subresources).collect();
+            subresources.sort_by_key(|(position, _)| *position);
+            frame.subresources = Some(
+                subresources
+                    .into_iter()
+                    .map(|(_, resource)| resource)
+                    .collect(),
+            );
+        }
+    }
+
+    archive
+}
+
+/// The page listing the contents of overflow archive `number`
+fn index_page(number: usize, main_url: &str, urls: &[String]) -> WebResource {
+    let mut html = format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<title>Part {number} of {title}</title>\n",
+            "<h1>Part {number} of <a href=\"{href}\">{title}</a></h1>\n",
+            "<p>Merge this archive with the first part to see the page.</p>\n",
+            "<ul>\n",
+        ),
+        number = number,
+        href = encode_double_quoted_attribute(main_url),
+        title = encode_text(main_url),
+    );
+    for url in urls {
+        html.push_str(&index_entry(url));
+    }
+    html.push_str("</ul>\n");
+
+    WebArchive::from_html(&html, format!("{}{}", PART_URL_PREFIX, number)).main_resource
+}
+
+fn index_entry(url: &str) -> String {
+    format!(
+        "<li><a href=\"{}\">{}</a></li>\n",
+        encode_double_quoted_attribute(url),
+        encode_text(url)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{Error, MergePolicy, WebArchive, WebResource};
+
+    /// Each resource's URL and data, in URL order
+    fn contents(archive: &WebArchive) -> Vec<(&str, &[u8])> {
+        let mut contents: Vec<_> = archive
+            .iter_resources()
+            .map(|resource| (resource.url.as_str(), resource.data.as_slice()))
+            .collect();
+        contents.sort();
+        contents
+    }
+
+    fn image(url: &str, size: usize) -> WebResource {
+        // different for each URL, as identical data is only stored once
+        let data: Vec<u8> = url.bytes().cycle().take(size).collect();
+        resource(url, "image/png", data)
+    }
+
+    #[test]
+    fn split() {
+        let nav = || -> WebArchive {
+            let psxdatacenter: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+                .expect("Could not read psxdatacenter webarchive fixture");
+            psxdatacenter.subframe_archives.unwrap().remove(1)
+        };
+        let original = nav();
+
+        for max_bytes in [120_000, 150_000] {
+            let parts = nav().split(max_bytes).unwrap();
+            assert!(parts.len() > 1);
+
+            for (index, part) in parts.iter().enumerate() {
+                let size = part.to_vec_binary().unwrap().len();
+                assert!(size <= max_bytes, "part {} is {} bytes", index + 1, size);
+                if index == 0 {
+                    continue;
+                }
+
+                assert_eq!(
+                    part.main_resource.url,
+                    format!("about:webarchive-part-{}", index + 1)
+                );
+                let page = String::from_utf8(part.main_resource.data.clone()).unwrap();
+                for subresource in part.subresources.as_ref().unwrap() {
+                    assert!(page.contains(&format!("<a href=\"{}\">", subresource.url)));
+                }
+            }
+
+            let mut parts = parts.into_iter();
+            let mut merged = parts.next().unwrap();
+            assert_eq!(merged.main_resource, original.main_resource);
+            for part in parts {
+                let report = merged.merge(part, MergePolicy::default());
+                assert!(report.conflicts.is_empty());
+            }
+            assert_eq!(contents(&merged), contents(&original));
+        }
+    }
+
+    #[test]
+    fn split_frames() {
+        let original: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        // too small for every frame whole, as jlist.html alone is 1.9 MB
+        let max_bytes = 2100 * 1024;
+        let parts = original.clone().split(max_bytes).unwrap();
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert!(part.to_vec_binary().unwrap().len() <= max_bytes);
+        }
+
+        // the first part has every frame, in order, and the second
+        // copies of those frames whose subresources it holds
+        let frame_urls = |archive: &WebArchive| -> Vec<String> {
+            archive
+                .subframe_archives
+                .iter()
+                .flatten()
+                .map(|frame| frame.main_resource.url.clone())
+                .collect()
+        };
+        assert_eq!(frame_urls(&parts[0]), frame_urls(&original));
+        assert!(parts[1].subresources.is_none());
+        let page = String::from_utf8(parts[1].main_resource.data.clone()).unwrap();
+        for frame in parts[1].subframe_archives.as_ref().unwrap() {
+            assert!(frame.subresources.is_some());
+            for subresource in frame.subresources.as_ref().unwrap() {
+                assert!(page.contains(&format!("<a href=\"{}\">", subresource.url)));
+            }
+        }
+
+        let mut parts = parts.into_iter();
+        let mut merged = parts.next().unwrap();
+        for part in parts {
+            let report = merged.merge(part, MergePolicy::default());
+            assert!(report.conflicts.is_empty());
+        }
+        assert_eq!(frame_urls(&merged), frame_urls(&original));
+        for (merged, original) in merged
+            .subframe_archives
+            .iter()
+            .flatten()
+            .zip(original.subframe_archives.iter().flatten())
+        {
+            assert_eq!(contents(merged), contents(original));
+        }
+    }
+
+    #[test]
+    fn most_referenced_first() {
+        let archive = || {
+            let mut archive = WebArchive::from_html(
+                "<img src=a.png><img src=b.png><img src=b.png><img src=c.png>",
+                "https://example.com/",
+            );
+            archive.subresources = Some(vec![
+                image("https://example.com/a.png", 4096),
+                image("https://example.com/b.png", 4096),
+                image("https://example.com/c.png", 2048),
+                image("https://example.com/unused.png", 1024),
+            ]);
+            archive
+        };
+
+        let urls = |archive: &WebArchive| -> Vec<String> {
+            archive
+                .subresources
+                .iter()
+                .flatten()
+                .map(|resource| resource.url.clone())
+                .collect()
+        };
+
+        // b.png is referred to twice, then c.png is smaller than a.png,
+        // and unused.png is referred to by nothing
+        let parts = archive().split(5000).unwrap();
+        let parts: Vec<_> = parts.iter().map(urls).collect();
+        assert_eq!(
+            parts,
+            [
+                vec!["https://example.com/b.png"],
+                vec![
+                    "https://example.com/c.png",
+                    "https://example.com/unused.png"
+                ],
+                vec!["https://example.com/a.png"],
+            ]
+        );
+
+        let parts = archive().split(7000).unwrap();
+        let parts: Vec<_> = parts.iter().map(urls).collect();
+        assert_eq!(
+            parts,
+            [
+                vec!["https://example.com/b.png", "https://example.com/c.png"],
+                vec![
+                    "https://example.com/a.png",
+                    "https://example.com/unused.png"
+                ],
+            ]
+        );
+
+        // an archive which fits is left alone
+        assert_eq!(archive().split(20_000).unwrap(), [archive()]);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut archive = WebArchive::from_html("<img src=a.png>", "https://example.com/");
+        archive.subresources = Some(vec![image("https://example.com/a.png", 4096)]);
+
+        match archive.split(2048) {
+            Err(Error::BudgetExceeded { url, would_be }) => {
+                assert_eq!(url, "https://example.com/a.png");
+                assert!(would_be > 4096);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let archive = WebArchive::from_binary(vec![0; 4096], "image/png", "https://example.com/");
+        assert!(matches!(
+            archive.split(2048),
+            Err(Error::BudgetExceeded { .. })
+        ));
+    }
+}
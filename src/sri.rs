@@ -0,0 +1,303 @@
+//! Checking stored subresources against the Subresource Integrity
+//! (SRI) digests pages declare for them.
+
+use crate::optimize::normalize_url;
+use crate::references::{base_url, decode_attribute, html_base, resolve_url};
+use crate::{DigestAlgorithm, Error, Result, WebArchive, WebResource};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Whether a stored subresource has the digest declared for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SriStatus {
+    /// The subresource is stored, and matches one of its digests.
+    Match,
+
+    /// The subresource is stored, but matches none of its digests,
+    /// so a browser would refuse to use it.
+    Mismatch,
+
+    /// The subresource is not stored.
+    Missing,
+}
+
+impl fmt::Display for SriStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SriStatus::Match => "match",
+            SriStatus::Mismatch => "mismatch",
+            SriStatus::Missing => "missing",
+        })
+    }
+}
+
+/// The result of checking one `integrity` attribute, as by
+/// [`WebArchive::verify_sri`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SriResult {
+    /// The URL of the HTML document declaring the digest.
+    pub document_url: String,
+
+    /// The URL of the subresource the digest is for, resolved
+    /// against the document's.
+    pub url: String,
+
+    /// The algorithm checked: the strongest one the attribute uses.
+    pub algorithm: DigestAlgorithm,
+
+    /// The `integrity` attribute, as written.
+    pub integrity: String,
+
+    /// The digest of the stored subresource with `algorithm`, in
+    /// base64 as `integrity` has it, if it is stored.
+    pub actual: Option<String>,
+
+    /// Whether the stored subresource matches.
+    pub status: SriStatus,
+}
+
+impl WebArchive {
+    /// Check the `integrity` attribute of each `<script>` and `<link>`
+    /// element in the archive's HTML documents, at any depth, against
+    /// the subresource it refers to, as a browser would before using it.
+    ///
+    /// Of the `sha256`, `sha384` and `sha512` digests an attribute
+    /// lists, only those with the strongest algorithm are checked,
+    /// and the subresource need match only one of them. Attributes
+    /// listing none of these algorithms are ignored, as browsers do.
+    ///
+    /// Results are in document order.
+    pub fn verify_sri(&self) -> Result<Vec<SriResult>> {
+        let mut stored: HashMap<String, &WebResource> = HashMap::new();
+        for resource in self.iter_resources() {
+            stored
+                .entry(normalize_url(&resource.url))
+                .or_insert(resource);
+        }
+
+        let mut results = Vec::new();
+
+        for document in self.iter_resources().filter(|resource| resource.is_html()) {
+            let html = match document.decoded_text() {
+                Some(html) => html,
+                None => continue,
+            };
+
+            let base = base_url(&document.url, html_base(&html)?.as_deref());
+
+            for (raw, integrity) in integrity_attributes(&html)? {
+                let url = match resolve_url(base.as_ref(), &raw) {
+                    Some(url) => url,
+                    None => continue,
+                };
+                let (algorithm, expected) = match strongest_digests(&integrity) {
+                    Some(digests) => digests,
+                    None => continue,
+                };
+
+                let actual = stored.get(&url).map(|resource| {
+                    use base64::Engine;
+                    base64::engine::general_purpose::STANDARD
+                        .encode(algorithm.digest(&resource.data))
+                });
+
+                let status = match &actual {
+                    None => SriStatus::Missing,
+                    Some(actual) if expected.contains(actual) => SriStatus::Match,
+                    Some(_) => SriStatus::Mismatch,
+                };
+
+                results.push(SriResult {
+                    document_url: document.url.clone(),
+                    url,
+                    algorithm,
+                    integrity,
+                    actual,
+                    status,
+                });
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+/// The strongest algorithm an `integrity` attribute uses, with the
+/// digests it lists for it, in standard base64.
+fn strongest_digests(integrity: &str) -> Option<(DigestAlgorithm, Vec<String>)> {
+    let digests: Vec<(DigestAlgorithm, String)> = integrity
+        .split_ascii_whitespace()
+        .filter_map(|token| {
+            // anything after a `?` is an option, which no browser uses yet
+            let token = token.split('?').next().unwrap_or_default();
+            let (algorithm, digest) = token.split_once('-')?;
+
+            let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+                "sha256" => DigestAlgorithm::Sha256,
+                "sha384" => DigestAlgorithm::Sha384,
+                "sha512" => DigestAlgorithm::Sha512,
+                _ => return None,
+            };
+
+            // accept the URL-safe alphabet, as browsers do
+            Some((algorithm, digest.replace('-', "+").replace('_', "/")))
+        })
+        .collect();
+
+    let strongest = digests
+        .iter()
+        .map(|(algorithm, _)| *algorithm)
+        .max_by_key(|algorithm| match algorithm {
+            DigestAlgorithm::Sha512 => 3,
+            DigestAlgorithm::Sha384 => 2,
+            _ => 1,
+        })?;
+
+    Some((
+        strongest,
+        digests
+            .into_iter()
+            .filter(|(algorithm, _)| *algorithm == strongest)
+            .map(|(_, digest)| digest)
+            .collect(),
+    ))
+}
+
+/// The raw URL and `integrity` attribute of each `<script src>` and
+/// `<link href>` element in an HTML document with both.
+fn integrity_attributes(html: &str) -> Result<Vec<(String, String)>> {
+    use lol_html::{element, HtmlRewriter, Settings};
+
+    let found = RefCell::new(Vec::new());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!(
+                "script[integrity], link[integrity]",
+                |element| {
+                    let attribute = if element.tag_name() == "script" {
+                        "src"
+                    } else {
+                        "href"
+                    };
+
+                    if let (Some(url), Some(integrity)) = (
+                        element.get_attribute(attribute),
+                        element.get_attribute("integrity"),
+                    ) {
+                        found
+                            .borrow_mut()
+                            .push((decode_attribute(&url), decode_attribute(&integrity)));
+                    }
+
+                    Ok(())
+                }
+            )],
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    Ok(found.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SriStatus;
+    use crate::test_support::text_resource;
+    use crate::{DigestAlgorithm, WebArchive};
+
+    // the digests of `alert(1)`
+    const SHA256: &str = "sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI=";
+    const SHA384: &str = "sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW";
+
+    /// A page whose stylesheet has been changed since its digest was
+    /// taken, so its integrity attribute deliberately mismatches.
+    fn sri_fixture() -> WebArchive {
+        let html = format!(
+            concat!(
+                "<script src=\"/app.js\" integrity=\"{}\"></script>\n",
+                "<script src=\"app.js?strong\" integrity=\"{} sha384-wrong {}\"></script>\n",
+                "<link rel=stylesheet href=\"style.css\" integrity=\"{}\">\n",
+                "<script src=\"missing.js\" integrity=\"{}\"></script>\n",
+                "<script src=\"legacy.js\" integrity=\"md5-abc\"></script>\n",
+            ),
+            SHA256, SHA256, SHA384, SHA256, SHA256,
+        );
+
+        WebArchive {
+            main_resource: text_resource("https://example.com/page/", "text/html", &html),
+            subresources: Some(vec![
+                text_resource("https://example.com/app.js", "text/javascript", "alert(1)"),
+                text_resource(
+                    "https://example.com/page/app.js?strong",
+                    "text/javascript",
+                    "alert(1)",
+                ),
+                text_resource("https://example.com/page/style.css", "text/css", "alert(2)"),
+                text_resource("https://example.com/page/legacy.js", "text/javascript", ""),
+            ]),
+            subframe_archives: None,
+        }
+    }
+
+    #[test]
+    fn verify_sri() {
+        let results = sri_fixture().verify_sri().unwrap();
+
+        let checked: Vec<(&str, DigestAlgorithm, SriStatus)> = results
+            .iter()
+            .map(|result| (result.url.as_str(), result.algorithm, result.status))
+            .collect();
+        assert_eq!(
+            checked,
+            [
+                (
+                    "https://example.com/app.js",
+                    DigestAlgorithm::Sha256,
+                    SriStatus::Match
+                ),
+                (
+                    "https://example.com/page/app.js?strong",
+                    DigestAlgorithm::Sha384,
+                    SriStatus::Match
+                ),
+                (
+                    "https://example.com/page/style.css",
+                    DigestAlgorithm::Sha256,
+                    SriStatus::Mismatch
+                ),
+                (
+                    "https://example.com/page/missing.js",
+                    DigestAlgorithm::Sha256,
+                    SriStatus::Missing
+                ),
+            ]
+        );
+
+        assert_eq!(results[0].actual.as_deref(), SHA256.strip_prefix("sha256-"));
+        assert_eq!(results[2].integrity, SHA256);
+        assert_ne!(results[2].actual.as_deref(), SHA256.strip_prefix("sha256-"));
+        assert_eq!(results[3].actual, None);
+        assert!(results
+            .iter()
+            .all(|result| result.document_url == "https://example.com/page/"));
+    }
+
+    #[test]
+    fn verify_sri_fixtures() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        assert!(archive.verify_sri().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,430 @@
+//! Checking that archives are ones Safari itself would write.
+
+use crate::{WebArchive, WebResource};
+use plist::{Dictionary, Value};
+use serde::Serialize;
+use std::fmt;
+
+/// The largest resource data [`WebArchive::validate_strict`] accepts:
+/// the largest length a signed 32-bit integer can describe.
+pub const STRICT_MAX_DATA_LEN: usize = i32::MAX as usize;
+
+/// The keys Safari writes in an archive's dictionary.
+const ARCHIVE_KEYS: &[&str] = &["WebMainResource", "WebSubresources", "WebSubframeArchives"];
+
+/// The keys Safari writes in a resource's dictionary.
+const RESOURCE_KEYS: &[&str] = &[
+    "WebResourceData",
+    "WebResourceURL",
+    "WebResourceFrameName",
+    "WebResourceMIMEType",
+    "WebResourceTextEncodingName",
+    "WebResourceResponse",
+];
+
+/// A check made by [`WebArchive::validate_strict`], and by reading with
+/// [`ParseOptions::strict_apple`](crate::ParseOptions::strict_apple),
+/// for something Safari never writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum StrictRule {
+    /// An archive or resource has a key Safari does not write, such as
+    /// one of the [`KEY_ALIASES`](crate::KEY_ALIASES).
+    ///
+    /// Safari ignores keys it does not know, so an aliased MIME type
+    /// or encoding is lost, and the resource read without it. Only
+    /// reading can find these, as a [`WebArchive`] has nowhere to keep
+    /// them.
+    UnknownKey,
+
+    /// A resource's URL is relative, or empty.
+    ///
+    /// Safari matches requests against resources by their absolute
+    /// URL, so such a resource is never used.
+    RelativeUrl,
+
+    /// A resource has an empty MIME type.
+    ///
+    /// Safari decides how to display a resource from its MIME type
+    /// alone, without sniffing, so such a resource is shown as a
+    /// download, or not at all.
+    MissingMimeType,
+
+    /// The top-level main resource has a frame name.
+    ///
+    /// Safari only names the main resources of subframe archives, and
+    /// a named top-level page is taken to belong to a frame which does
+    /// not exist.
+    MainFrameName,
+
+    /// A subframe archive's main resource is not `text/html`.
+    ///
+    /// Safari archives frames as the HTML documents which contain
+    /// them, so other types in a frame are not displayed.
+    NonHtmlSubframe,
+
+    /// A resource's data is longer than [`STRICT_MAX_DATA_LEN`].
+    ///
+    /// Apple's property list readers describe data lengths with signed
+    /// 32-bit integers in places, and Safari reads whole archives into
+    /// memory, so such archives fail to open.
+    OversizedData,
+}
+
+impl fmt::Display for StrictRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StrictRule::UnknownKey => "unknown-key",
+            StrictRule::RelativeUrl => "relative-url",
+            StrictRule::MissingMimeType => "missing-mime-type",
+            StrictRule::MainFrameName => "main-frame-name",
+            StrictRule::NonHtmlSubframe => "non-html-subframe",
+            StrictRule::OversizedData => "oversized-data",
+        })
+    }
+}
+
+/// Something in an archive Safari never writes, as found by
+/// [`WebArchive::validate_strict`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum StrictViolation {
+    /// See [`StrictRule::UnknownKey`].
+    UnknownKey {
+        /// The key.
+        key: String,
+
+        /// The URL of the resource with the key, or `None` if an
+        /// archive has it.
+        url: Option<String>,
+    },
+
+    /// See [`StrictRule::RelativeUrl`].
+    RelativeUrl {
+        /// The resource's URL.
+        url: String,
+    },
+
+    /// See [`StrictRule::MissingMimeType`].
+    MissingMimeType {
+        /// The resource's URL.
+        url: String,
+    },
+
+    /// See [`StrictRule::MainFrameName`].
+    MainFrameName {
+        /// The main resource's URL.
+        url: String,
+
+        /// Its frame name.
+        frame_name: String,
+    },
+
+    /// See [`StrictRule::NonHtmlSubframe`].
+    NonHtmlSubframe {
+        /// The subframe's main resource's URL.
+        url: String,
+
+        /// Its MIME type.
+        mime_type: String,
+    },
+
+    /// See [`StrictRule::OversizedData`].
+    OversizedData {
+        /// The resource's URL.
+        url: String,
+
+        /// The length of its data, in bytes.
+        len: usize,
+    },
+}
+
+impl StrictViolation {
+    /// The rule this breaks.
+    pub fn rule(&self) -> StrictRule {
+        match self {
+            StrictViolation::UnknownKey { .. } => StrictRule::UnknownKey,
+            StrictViolation::RelativeUrl { .. } => StrictRule::RelativeUrl,
+            StrictViolation::MissingMimeType { .. } => StrictRule::MissingMimeType,
+            StrictViolation::MainFrameName { .. } => StrictRule::MainFrameName,
+            StrictViolation::NonHtmlSubframe { .. } => StrictRule::NonHtmlSubframe,
+            StrictViolation::OversizedData { .. } => StrictRule::OversizedData,
+        }
+    }
+}
+
+impl fmt::Display for StrictViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrictViolation::UnknownKey {
+                key,
+                url: Some(url),
+            } => {
+                write!(f, "{:?}: unknown key {:?}", url, key)
+            }
+            StrictViolation::UnknownKey { key, url: None } => {
+                write!(f, "unknown archive key {:?}", key)
+            }
+            StrictViolation::RelativeUrl { url } => write!(f, "{:?}: URL is not absolute", url),
+            StrictViolation::MissingMimeType { url } => write!(f, "{:?}: no MIME type", url),
+            StrictViolation::MainFrameName { url, frame_name } => write!(
+                f,
+                "{:?}: top-level main resource has the frame name {:?}",
+                url, frame_name
+            ),
+            StrictViolation::NonHtmlSubframe { url, mime_type } => {
+                write!(f, "{:?}: subframe is {:?}, not HTML", url, mime_type)
+            }
+            StrictViolation::OversizedData { url, len } => {
+                write!(f, "{:?}: {} bytes of data is too large", url, len)
+            }
+        }
+    }
+}
+
+impl WebArchive {
+    /// Find anything in the archive Safari would not have written, which
+    /// may stop it opening in Safari as expected.
+    ///
+    /// Every [`StrictRule`] but [`UnknownKey`](StrictRule::UnknownKey) is
+    /// checked, in every frame. To check that too, read the archive with
+    /// [`ParseOptions::strict_apple`](crate::ParseOptions::strict_apple).
+    pub fn validate_strict(&self) -> Vec<StrictViolation> {
+        let mut violations = Vec::new();
+
+        if let Some(frame_name) = &self.main_resource.frame_name {
+            violations.push(StrictViolation::MainFrameName {
+                url: self.main_resource.url.clone(),
+                frame_name: frame_name.clone(),
+            });
+        }
+
+        self.validate_strict_frame(&mut violations);
+        violations
+    }
+
+    fn validate_strict_frame(&self, violations: &mut Vec<StrictViolation>) {
+        validate_strict_resource(&self.main_resource, violations);
+        for resource in self.subresources.iter().flatten() {
+            validate_strict_resource(resource, violations);
+        }
+
+        for archive in self.subframe_archives.iter().flatten() {
+            let resource = &archive.main_resource;
+            if resource.mime_essence() != "text/html" {
+                violations.push(StrictViolation::NonHtmlSubframe {
+                    url: resource.url.clone(),
+                    mime_type: resource.mime_type.clone(),
+                });
+            }
+
+            archive.validate_strict_frame(violations);
+        }
+    }
+}
+
+fn validate_strict_resource(resource: &WebResource, violations: &mut Vec<StrictViolation>) {
+    if url::Url::parse(&resource.url).is_err() {
+        violations.push(StrictViolation::RelativeUrl {
+            url: resource.url.clone(),
+        });
+    }
+
+    if resource.mime_type.is_empty() {
+        violations.push(StrictViolation::MissingMimeType {
+            url: resource.url.clone(),
+        });
+    }
+
+    if resource.data.len() > STRICT_MAX_DATA_LEN {
+        violations.push(StrictViolation::OversizedData {
+            url: resource.url.clone(),
+            len: resource.data.len(),
+        });
+    }
+}
+
+/// Find the keys Safari does not write in an archive read as a property
+/// list, and those of its resources and subframe archives.
+pub(crate) fn unknown_keys(archive: &Dictionary, violations: &mut Vec<StrictViolation>) {
+    for key in archive.keys() {
+        if !ARCHIVE_KEYS.contains(&key.as_str()) {
+            violations.push(StrictViolation::UnknownKey {
+                key: key.clone(),
+                url: None,
+            });
+        }
+    }
+
+    let main_resource = archive
+        .get("WebMainResource")
+        .and_then(Value::as_dictionary);
+    let subresources = archive
+        .get("WebSubresources")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_dictionary);
+    for resource in main_resource.into_iter().chain(subresources) {
+        let url = resource
+            .get("WebResourceURL")
+            .and_then(Value::as_string)
+            .unwrap_or_default();
+        for key in resource.keys() {
+            if !RESOURCE_KEYS.contains(&key.as_str()) {
+                violations.push(StrictViolation::UnknownKey {
+                    key: key.clone(),
+                    url: Some(url.to_string()),
+                });
+            }
+        }
+    }
+
+    for archive in archive
+        .get("WebSubframeArchives")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_dictionary)
+    {
+        unknown_keys(archive, violations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StrictRule, StrictViolation};
+    use crate::test_support::text_resource;
+    use crate::{WebArchive, WebResource};
+
+    fn archive() -> WebArchive {
+        WebArchive {
+            main_resource: text_resource("https://example.com/", "text/html", "<p>Hello</p>"),
+            subresources: Some(vec![text_resource(
+                "https://example.com/style.css",
+                "text/css",
+                "<p>Hello</p>",
+            )]),
+            subframe_archives: Some(vec![WebArchive {
+                main_resource: WebResource {
+                    frame_name: Some("frame".to_string()),
+                    ..text_resource(
+                        "https://example.com/frame.html",
+                        "text/html",
+                        "<p>Hello</p>",
+                    )
+                },
+                subresources: None,
+                subframe_archives: None,
+            }]),
+        }
+    }
+
+    fn rules(archive: &WebArchive) -> Vec<StrictRule> {
+        archive
+            .validate_strict()
+            .iter()
+            .map(StrictViolation::rule)
+            .collect()
+    }
+
+    #[test]
+    fn strict_fixtures() {
+        assert_eq!(rules(&archive()), []);
+
+        for fixture in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+        ] {
+            let archive: WebArchive =
+                crate::from_file(fixture).expect("Could not read webarchive fixture");
+            assert_eq!(archive.validate_strict(), [], "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn strict_relative_url() {
+        let mut archive = archive();
+        archive.subresources.as_mut().unwrap()[0].url = "style.css".to_string();
+        assert_eq!(
+            archive.validate_strict(),
+            [StrictViolation::RelativeUrl {
+                url: "style.css".to_string()
+            }]
+        );
+
+        archive.subresources.as_mut().unwrap()[0].url = String::new();
+        assert_eq!(rules(&archive), [StrictRule::RelativeUrl]);
+    }
+
+    #[test]
+    fn strict_missing_mime_type() {
+        let mut archive = archive();
+        archive.subframe_archives.as_mut().unwrap()[0]
+            .subresources
+            .get_or_insert_with(Vec::new)
+            .push(text_resource(
+                "https://example.com/frame.js",
+                "",
+                "<p>Hello</p>",
+            ));
+        assert_eq!(
+            archive.validate_strict(),
+            [StrictViolation::MissingMimeType {
+                url: "https://example.com/frame.js".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn strict_main_frame_name() {
+        let mut archive = archive();
+        archive.main_resource.frame_name = Some("top".to_string());
+        assert_eq!(
+            archive.validate_strict(),
+            [StrictViolation::MainFrameName {
+                url: "https://example.com/".to_string(),
+                frame_name: "top".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn strict_non_html_subframe() {
+        let mut archive = archive();
+        archive.subframe_archives.as_mut().unwrap()[0]
+            .main_resource
+            .mime_type = "image/png".to_string();
+        assert_eq!(
+            archive.validate_strict(),
+            [StrictViolation::NonHtmlSubframe {
+                url: "https://example.com/frame.html".to_string(),
+                mime_type: "image/png".to_string(),
+            }]
+        );
+
+        // parameters are not part of the type
+        archive.subframe_archives.as_mut().unwrap()[0]
+            .main_resource
+            .mime_type = "text/HTML; charset=utf-8".to_string();
+        assert_eq!(rules(&archive), []);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn strict_oversized_data() {
+        let mut archive = archive();
+        let len = super::STRICT_MAX_DATA_LEN + 1;
+        // zeroed allocations are not touched, so this is cheap
+        archive.subresources.as_mut().unwrap()[0].data = vec![0; len];
+        assert_eq!(
+            archive.validate_strict(),
+            [StrictViolation::OversizedData {
+                url: "https://example.com/style.css".to_string(),
+                len,
+            }]
+        );
+    }
+}
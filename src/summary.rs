@@ -0,0 +1,486 @@
+use crate::digest::to_hex;
+use crate::{Completeness, DigestAlgorithm, Result, WebArchive, WebResource};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// Overview of a Web Archive, as returned by
+/// [`WebArchive::summary_info`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub struct ArchiveSummary {
+    /// The URL of the main resource.
+    pub url: String,
+
+    /// The title of the main document, see [`WebArchive::title`].
+    pub title: Option<String>,
+
+    /// The total size of all resources' data, in bytes.
+    pub total_size: usize,
+
+    /// The number of resources, including main resources of frames.
+    pub resource_count: usize,
+
+    /// The number of subframe archives, at any depth.
+    pub frame_count: usize,
+
+    /// Resources counted by MIME type, without parameters.
+    pub mime_types: BTreeMap<String, MimeTypeStats>,
+
+    /// See [`WebArchive::fingerprint`].
+    pub fingerprint: String,
+
+    /// How much of what the archive refers to it stores, see
+    /// [`WebArchive::completeness`].
+    pub completeness: Completeness,
+
+    /// Roughly when the archive was made, from the latest `Date`
+    /// header among the archived responses.
+    ///
+    /// Serialised as an HTTP date, such as `Sat, 30 May 2020 05:11:56 GMT`.
+    #[serde(serialize_with = "serialize_http_date")]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    pub captured_at: Option<SystemTime>,
+}
+
+/// How many resources of a MIME type an archive contains.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MimeTypeStats {
+    /// The number of resources.
+    pub count: usize,
+
+    /// The total size of their data, in bytes.
+    pub bytes: usize,
+}
+
+/// The digest of one resource's data, as listed by
+/// [`WebArchive::checksums`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ResourceChecksum {
+    /// The resource's URL.
+    pub url: String,
+
+    /// The size of the resource's data, in bytes.
+    pub size: usize,
+
+    /// The digest of the resource's data, as lowercase hexadecimal.
+    pub digest: String,
+}
+
+/// Statistics over a collection of archives, such as a folder of
+/// captures, built up one archive at a time so that only each one's
+/// summary is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CollectionStats {
+    /// Each archive added, in the order they were added.
+    pub archives: Vec<CollectionEntry>,
+
+    /// Each archive which could not be read, in the order they were
+    /// added.
+    pub failures: Vec<CollectionFailure>,
+
+    /// Resources counted by MIME type, across every archive.
+    pub mime_types: BTreeMap<String, MimeTypeStats>,
+}
+
+/// One archive in a [`CollectionStats`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CollectionEntry {
+    /// Where the archive was read from.
+    pub path: String,
+
+    /// The URL of its main resource.
+    pub url: String,
+
+    /// The size of the archive's file, in bytes.
+    pub file_size: u64,
+
+    /// The total size of all its resources' data, in bytes.
+    pub total_size: usize,
+
+    /// The number of its resources, including main resources of frames.
+    pub resource_count: usize,
+
+    /// The number of its subframe archives, at any depth.
+    pub frame_count: usize,
+
+    /// How much of what it refers to it stores.
+    pub completeness: Completeness,
+}
+
+/// An archive in a [`CollectionStats`] which could not be read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CollectionFailure {
+    /// Where the archive was read from.
+    pub path: String,
+
+    /// Why it could not be read.
+    pub error: String,
+}
+
+/// How a number is spread over the archives in a [`CollectionStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Distribution {
+    /// The sum over every archive.
+    pub total: u64,
+
+    /// The smallest.
+    pub min: u64,
+
+    /// The middle one, or the mean of the middle two, rounded down.
+    pub median: u64,
+
+    /// The largest.
+    pub max: u64,
+}
+
+impl Distribution {
+    /// How `values` are spread, or `None` if there are none.
+    fn of(mut values: Vec<u64>) -> Option<Self> {
+        values.sort_unstable();
+        let middle = values.len() / 2;
+        let median = match values.len() {
+            0 => return None,
+            len if len % 2 == 0 => (values[middle - 1] + values[middle]) / 2,
+            _ => values[middle],
+        };
+
+        Some(Distribution {
+            total: values.iter().sum(),
+            min: values[0],
+            median,
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+impl CollectionStats {
+    /// Count the archive read from `path`, whose file is `file_size`
+    /// bytes long.
+    pub fn add(&mut self, path: impl Into<String>, file_size: u64, summary: &ArchiveSummary) {
+        for (mime_type, stats) in &summary.mime_types {
+            let total = self.mime_types.entry(mime_type.clone()).or_default();
+            total.count += stats.count;
+            total.bytes += stats.bytes;
+        }
+
+        self.archives.push(CollectionEntry {
+            path: path.into(),
+            url: summary.url.clone(),
+            file_size,
+            total_size: summary.total_size,
+            resource_count: summary.resource_count,
+            frame_count: summary.frame_count,
+            completeness: summary.completeness,
+        });
+    }
+
+    /// Note that the archive at `path` could not be read, because of
+    /// `error`.
+    pub fn add_failure(&mut self, path: impl Into<String>, error: impl Into<String>) {
+        self.failures.push(CollectionFailure {
+            path: path.into(),
+            error: error.into(),
+        });
+    }
+
+    /// How the sizes of the archives' files are spread, or `None` if
+    /// none were added.
+    pub fn file_sizes(&self) -> Option<Distribution> {
+        Distribution::of(self.archives.iter().map(|entry| entry.file_size).collect())
+    }
+
+    /// How many resources the archives have, or `None` if none were
+    /// added.
+    pub fn resource_counts(&self) -> Option<Distribution> {
+        Distribution::of(
+            self.archives
+                .iter()
+                .map(|entry| entry.resource_count as u64)
+                .collect(),
+        )
+    }
+
+    /// The `n` MIME types with the most bytes across every archive,
+    /// most first, with those of the same size in order of name.
+    pub fn top_mime_types(&self, n: usize) -> Vec<(&str, &MimeTypeStats)> {
+        let mut mime_types: Vec<_> = self
+            .mime_types
+            .iter()
+            .map(|(mime_type, stats)| (mime_type.as_str(), stats))
+            .collect();
+        mime_types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        mime_types.truncate(n);
+        mime_types
+    }
+}
+
+pub(crate) fn serialize_http_date<S: Serializer>(
+    date: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(date) => serializer.serialize_some(&httpdate::fmt_http_date(*date)),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl WebArchive {
+    /// Describe the archive: its URL, title, size and contents,
+    /// and when it was made.
+    ///
+    /// Returns an error if the main document's HTML can not be parsed.
+    /// Archived responses which can not be parsed are left out of the
+    /// capture date.
+    pub fn summary_info(&self) -> Result<ArchiveSummary> {
+        let mut mime_types = BTreeMap::<String, MimeTypeStats>::new();
+        let mut captured_at = None;
+
+        for resource in self.iter_resources() {
+            let stats = mime_types.entry(resource.mime_essence()).or_default();
+            stats.count += 1;
+            stats.bytes += resource.data.len();
+
+            // responses which can't be parsed are left out, as elsewhere
+            if let Some(date) = resource
+                .parse_response()
+                .ok()
+                .flatten()
+                .and_then(|response| response.date())
+            {
+                captured_at = captured_at.max(Some(date));
+            }
+        }
+
+        Ok(ArchiveSummary {
+            url: self.main_resource.url.clone(),
+            title: self.title()?,
+            total_size: self.total_size(),
+            resource_count: mime_types.values().map(|stats| stats.count).sum(),
+            frame_count: self.iter_frames().count() - 1,
+            mime_types,
+            fingerprint: self.fingerprint(),
+            completeness: self.completeness()?,
+            captured_at,
+        })
+    }
+
+    /// The digest of every resource's data, at any depth,
+    /// in document order.
+    pub fn checksums(&self, algorithm: DigestAlgorithm) -> Vec<ResourceChecksum> {
+        self.iter_resources()
+            .map(|resource| ResourceChecksum {
+                url: resource.url.clone(),
+                size: resource.data.len(),
+                digest: resource.hex_digest(algorithm),
+            })
+            .collect()
+    }
+
+    /// The `n` largest resources, at any depth, largest first, with
+    /// resources of the same size in order of URL.
+    ///
+    /// Only the `n` chosen are sorted, so this stays quick for
+    /// archives with many resources.
+    pub fn largest_resources(&self, n: usize) -> Vec<&WebResource> {
+        let order = |a: &&WebResource, b: &&WebResource| {
+            b.data
+                .len()
+                .cmp(&a.data.len())
+                .then_with(|| a.url.cmp(&b.url))
+        };
+
+        let mut resources: Vec<&WebResource> = self.iter_resources().collect();
+        if n == 0 {
+            return Vec::new();
+        } else if n < resources.len() {
+            resources.select_nth_unstable_by(n - 1, order);
+            resources.truncate(n);
+        }
+
+        resources.sort_by(order);
+        resources
+    }
+
+    /// A SHA-256 hash of every resource's URL, MIME type and data,
+    /// in document order, as a lowercase hexadecimal string.
+    ///
+    /// Archives with the same content have the same fingerprint,
+    /// whichever property list format they were stored in, and
+    /// whether or not their responses were archived.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+
+        for resource in self.iter_resources() {
+            for field in [
+                resource.url.as_bytes(),
+                resource.mime_type.as_bytes(),
+                &resource.data,
+            ] {
+                hasher.update((field.len() as u64).to_be_bytes());
+                hasher.update(field);
+            }
+        }
+
+        to_hex(&hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveSummary, CollectionStats, Distribution, MimeTypeStats};
+    use crate::{Completeness, WebArchive};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn summary_info_crouton() {
+        let archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+
+        assert_eq!(
+            archive.summary_info().unwrap(),
+            ArchiveSummary {
+                url: "https://crouton.net/".to_string(),
+                title: Some("Crouton".to_string()),
+                total_size: 5316,
+                resource_count: 2,
+                frame_count: 0,
+                mime_types: [
+                    (
+                        "image/png".to_string(),
+                        MimeTypeStats {
+                            count: 1,
+                            bytes: 5182
+                        }
+                    ),
+                    (
+                        "text/html".to_string(),
+                        MimeTypeStats {
+                            count: 1,
+                            bytes: 134
+                        }
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+                fingerprint: "8739816258c0f216329d02863c4f8f8344ed4ce624cab629859e9910d04c1648"
+                    .to_string(),
+                completeness: Completeness {
+                    referenced: 1,
+                    stored: 1,
+                    missing: 0,
+                },
+                captured_at: Some(UNIX_EPOCH + Duration::from_secs(1_590_815_516)),
+            }
+        );
+    }
+
+    #[test]
+    fn summary_info_corrupt_response() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        let png = &mut archive.subresources.as_mut().unwrap()[0];
+        png.response = Some(b"not a property list".to_vec());
+        assert!(png.parse_response().is_err());
+
+        let summary = archive.summary_info().unwrap();
+        assert_eq!(summary.resource_count, 2);
+        assert_eq!(summary.captured_at, None);
+    }
+
+    #[test]
+    fn summary_info_psxdatacenter() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+        let summary = archive.summary_info().unwrap();
+
+        assert_eq!(
+            summary.title.as_deref(),
+            Some("PlayStation DataCenter - NTSC-J List")
+        );
+        assert_eq!(summary.frame_count, 4);
+        assert_eq!(summary.resource_count, archive.iter_resources().count());
+        assert_eq!(summary.mime_types["text/html"].count, 5);
+    }
+
+    #[test]
+    fn largest_resources() {
+        let archive: WebArchive = crate::from_file("fixtures/psxdatacenter.webarchive")
+            .expect("Could not read psxdatacenter webarchive fixture");
+
+        let mut sorted: Vec<(usize, &str)> = archive
+            .iter_resources()
+            .map(|resource| (resource.data.len(), resource.url.as_str()))
+            .collect();
+        sorted.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+
+        for n in [0, 1, 5, 40, 1000] {
+            let largest: Vec<(usize, &str)> = archive
+                .largest_resources(n)
+                .into_iter()
+                .map(|resource| (resource.data.len(), resource.url.as_str()))
+                .collect();
+            assert_eq!(largest, sorted[..n.min(sorted.len())]);
+        }
+    }
+
+    #[test]
+    fn collection_stats() {
+        let mut stats = CollectionStats::default();
+        assert_eq!(stats.file_sizes(), None);
+        assert_eq!(stats.resource_counts(), None);
+
+        for (path, file_size) in [
+            ("fixtures/crouton.webarchive", 5_700),
+            ("fixtures/psxdatacenter.webarchive", 2_400_000),
+            ("fixtures/image-only.webarchive", 5_500),
+            ("fixtures/host-only.webarchive", 1_000),
+        ] {
+            let archive: WebArchive = crate::from_file(path).unwrap();
+            stats.add(path, file_size, &archive.summary_info().unwrap());
+        }
+        stats.add_failure("fixtures/truncated.webarchive", "unexpected end of file");
+
+        assert_eq!(stats.archives.len(), 4);
+        assert_eq!(stats.archives[1].resource_count, 52);
+        assert!(stats.archives[0].completeness.is_complete());
+        assert_eq!(stats.failures[0].path, "fixtures/truncated.webarchive");
+        assert_eq!(
+            stats.file_sizes(),
+            Some(Distribution {
+                total: 2_412_200,
+                min: 1_000,
+                median: 5_600,
+                max: 2_400_000,
+            })
+        );
+        assert_eq!(
+            stats.resource_counts(),
+            Some(Distribution {
+                total: 59,
+                min: 1,
+                median: 3,
+                max: 52,
+            })
+        );
+
+        let top = stats.top_mime_types(2);
+        assert_eq!(top[0].0, "text/html");
+        assert_eq!(top[1].0, "image/jpeg");
+        assert_eq!(
+            stats.mime_types["image/png"],
+            MimeTypeStats {
+                count: 2,
+                bytes: 5182 * 2
+            }
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{DigestAlgorithm, ResourceFilter, WebArchive, WebResource};
+use std::borrow::Cow;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The narrowest URLs are shortened to, however little room the
+/// table has.
+const MIN_URL_WIDTH: usize = 16;
+
+/// Space between columns.
+const GUTTER: &str = "  ";
+
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Options for [`WebArchive::write_table`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableOptions {
+    /// The widest a line of the table may be, in terminal columns,
+    /// such as the width of the terminal it is shown in. URLs are
+    /// shortened in the middle to fit. `None` never shortens them.
+    pub width: Option<usize>,
+
+    /// Highlight, with ANSI escape codes, the sizes of resources much
+    /// larger than the rest, and text resources without an encoding.
+    pub color: bool,
+
+    /// Add a column with the digest of each resource's data.
+    pub checksums: Option<DigestAlgorithm>,
+}
+
+/// One row of the table, before it is laid out
+struct Row {
+    url: String,
+    mime_type: String,
+    size: usize,
+    encoding: Option<String>,
+    is_text: bool,
+    response: String,
+    digest: Option<String>,
+}
+
+impl Row {
+    fn new(resource: &WebResource, checksums: Option<DigestAlgorithm>) -> Self {
+        let response = match resource.parse_response() {
+            Ok(Some(response)) => match response.status {
+                Some(status) => status.to_string(),
+                None => "yes".to_string(),
+            },
+            Ok(None) => "-".to_string(),
+            Err(_) => "invalid".to_string(),
+        };
+
+        Row {
+            url: resource.url.clone(),
+            mime_type: resource.mime_type.clone(),
+            size: resource.data.len(),
+            encoding: resource.text_encoding_name.clone(),
+            is_text: resource.is_text(),
+            response,
+            digest: checksums.map(|algorithm| resource.hex_digest(algorithm)),
+        }
+    }
+}
+
+impl WebArchive {
+    /// Write a table of the resources which match `filter`, at any
+    /// depth, with a column each for their URL, MIME type, size in
+    /// bytes, text encoding and the status of their archived response.
+    ///
+    /// Columns are lined up by how wide their text is shown in a
+    /// terminal, so URLs with wide characters still line up.
+    pub fn write_table<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        filter: &ResourceFilter,
+        options: &TableOptions,
+    ) -> std::io::Result<()> {
+        let rows: Vec<Row> = self
+            .filter_resources(filter)
+            .map(|resource| Row::new(resource, options.checksums))
+            .collect();
+        let outlier_size = outlier_size(&rows);
+
+        let mut headers = vec!["URL", "MIME type", "Size", "Encoding", "Response"];
+        if options.checksums.is_some() {
+            headers.push("Digest");
+        }
+
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                let mut cells = vec![
+                    row.mime_type.clone(),
+                    row.size.to_string(),
+                    row.encoding.clone().unwrap_or_else(|| "-".to_string()),
+                    row.response.clone(),
+                ];
+                cells.extend(row.digest.clone());
+                cells
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = headers.iter().map(|header| header.width()).collect();
+        for row in &cells {
+            for (width, cell) in widths[1..].iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+
+        let longest_url = rows
+            .iter()
+            .map(|row| row.url.width())
+            .max()
+            .unwrap_or_default()
+            .max(widths[0]);
+        widths[0] = match options.width {
+            Some(width) => {
+                // each column after the URL has a gutter before it
+                let others: usize = widths[1..].iter().map(|width| width + GUTTER.len()).sum();
+                longest_url.min(width.saturating_sub(others).max(MIN_URL_WIDTH))
+            }
+            None => longest_url,
+        };
+
+        let header_cells: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+        write_line(w, &widths, &header_cells[0], &header_cells[1..], |_| None)?;
+
+        for (row, cells) in rows.iter().zip(&cells) {
+            let url = ellipsize_middle(&row.url, widths[0]);
+            write_line(w, &widths, &url, cells, |column| {
+                if !options.color {
+                    return None;
+                }
+                match column {
+                    // the size
+                    1 if outlier_size.is_some_and(|outlier_size| row.size > outlier_size) => {
+                        Some(YELLOW)
+                    }
+                    // the encoding
+                    2 if row.is_text && row.encoding.is_none() => Some(RED),
+                    _ => None,
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write one line of the table, with the size right-aligned, and
+/// each cell after the URL coloured as `color` gives by its index
+fn write_line<W: std::io::Write>(
+    w: &mut W,
+    widths: &[usize],
+    url: &str,
+    cells: &[String],
+    color: impl Fn(usize) -> Option<&'static str>,
+) -> std::io::Result<()> {
+    let mut line = pad(url, widths[0], false);
+    for (index, (cell, width)) in cells.iter().zip(&widths[1..]).enumerate() {
+        let is_last = index == cells.len() - 1;
+        // the size
+        let padded = if index == 1 {
+            pad(cell, *width, true)
+        } else if is_last {
+            cell.clone()
+        } else {
+            pad(cell, *width, false)
+        };
+
+        line.push_str(GUTTER);
+        match color(index) {
+            Some(color) => {
+                line.push_str(color);
+                line.push_str(&padded);
+                line.push_str(RESET);
+            }
+            None => line.push_str(&padded),
+        }
+    }
+    writeln!(w, "{}", line)
+}
+
+/// `text` padded with spaces to be `width` columns wide
+fn pad(text: &str, width: usize, right_align: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(text.width()));
+    if right_align {
+        padding + text
+    } else {
+        format!("{}{}", text, padding)
+    }
+}
+
+/// The size above which a resource is unusually large for the
+/// archive, by the interquartile range of the sizes, or `None` if
+/// there are too few resources to tell
+fn outlier_size(rows: &[Row]) -> Option<usize> {
+    if rows.len() < 4 {
+        return None;
+    }
+
+    let mut sizes: Vec<usize> = rows.iter().map(|row| row.size).collect();
+    sizes.sort_unstable();
+    let lower = sizes[sizes.len() / 4];
+    let upper = sizes[sizes.len() * 3 / 4];
+    Some(upper + (upper - lower) * 3 / 2)
+}
+
+/// Shorten `text` to at most `width` terminal columns by replacing
+/// its middle with an ellipsis, keeping the start of a URL, with its
+/// host, and the end, with its file name.
+///
+/// Characters are never split, so wide characters may leave the
+/// result a column narrower than `width`.
+pub(crate) fn ellipsize_middle(text: &str, width: usize) -> Cow<'_, str> {
+    if text.width() <= width {
+        return Cow::Borrowed(text);
+    }
+    if width == 0 {
+        return Cow::Borrowed("");
+    }
+
+    // the ellipsis takes one column
+    let budget = width - 1;
+    let mut head_width = 0;
+    let head_end = text
+        .char_indices()
+        .find(|(_, c)| {
+            let c_width = c.width().unwrap_or_default();
+            if head_width + c_width > budget - budget / 2 {
+                return true;
+            }
+            head_width += c_width;
+            false
+        })
+        .map_or(text.len(), |(index, _)| index);
+
+    // the tail has whatever the head couldn't use
+    let tail_budget = budget - head_width;
+    let mut tail_width = 0;
+    let tail_start = text[head_end..]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| {
+            let c_width = c.width().unwrap_or_default();
+            if tail_width + c_width > tail_budget {
+                return false;
+            }
+            tail_width += c_width;
+            true
+        })
+        .last()
+        .map_or(text.len(), |(index, _)| head_end + index);
+
+    Cow::Owned(format!("{}…{}", &text[..head_end], &text[tail_start..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ellipsize_middle, TableOptions};
+    use crate::test_support::resource;
+    use crate::{ResourceFilter, WebArchive};
+    use unicode_width::UnicodeWidthStr;
+
+    #[test]
+    fn ellipsize_short() {
+        assert_eq!(
+            ellipsize_middle("https://a.example/", 18),
+            "https://a.example/"
+        );
+        assert_eq!(
+            ellipsize_middle("https://a.example/", 40),
+            "https://a.example/"
+        );
+        assert_eq!(ellipsize_middle("", 0), "");
+    }
+
+    #[test]
+    fn ellipsize_long() {
+        let url = "https://example.com/images/photo.jpg";
+        let shortened = ellipsize_middle(url, 20);
+        assert_eq!(shortened, "https://ex…photo.jpg");
+        assert_eq!(shortened.width(), 20);
+
+        assert_eq!(ellipsize_middle(url, 2), "h…");
+        assert_eq!(ellipsize_middle(url, 1), "…");
+        assert_eq!(ellipsize_middle(url, 0), "");
+    }
+
+    #[test]
+    fn ellipsize_long_host() {
+        let url = format!("https://{}.example/a.png", "sub".repeat(40));
+        let shortened = ellipsize_middle(&url, 30);
+        assert_eq!(shortened.width(), 30);
+        assert!(shortened.starts_with("https://subsub"), "{}", shortened);
+        assert!(shortened.ends_with(".example/a.png"), "{}", shortened);
+    }
+
+    #[test]
+    fn ellipsize_multibyte() {
+        // two columns each
+        let url = "https://例え.jp/画像/写真.png";
+        for width in 0..url.width() {
+            let shortened = ellipsize_middle(url, width);
+            assert!(shortened.width() <= width, "{:?} at {}", shortened, width);
+            assert!(
+                shortened.width() + 1 >= width,
+                "{:?} at {}",
+                shortened,
+                width
+            );
+        }
+        assert_eq!(ellipsize_middle(url, 12), "https:….png");
+
+        // one column, but several bytes each
+        assert_eq!(ellipsize_middle("ééééééééé", 5), "éé…éé");
+    }
+
+    fn render_table(archive: &WebArchive, options: &TableOptions) -> String {
+        let mut table = Vec::new();
+        archive
+            .write_table(&mut table, &ResourceFilter::default(), options)
+            .unwrap();
+        String::from_utf8(table).unwrap()
+    }
+
+    #[test]
+    fn write_table() {
+        let archive: WebArchive =
+            crate::from_bytes(include_bytes!("../fixtures/crouton.webarchive")).unwrap();
+
+        let table = render_table(&archive, &TableOptions::default());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 1 + archive.iter_resources().count());
+        assert!(lines[0].starts_with("URL"), "{}", lines[0]);
+        assert!(lines[0].ends_with("  Encoding  Response"), "{}", lines[0]);
+        assert!(lines[1].starts_with("https://crouton.net/"), "{}", lines[1]);
+        assert!(lines[1].contains("text/html"), "{}", lines[1]);
+        assert!(lines[1].contains("UTF-8"), "{}", lines[1]);
+        assert!(!table.contains('\x1b'));
+
+        // columns line up
+        let mime_column = lines[0].find("MIME type").unwrap();
+        for line in &lines[1..] {
+            assert_eq!(line.chars().nth(mime_column - 1), Some(' '), "{}", line);
+            assert_ne!(line.chars().nth(mime_column), Some(' '), "{}", line);
+        }
+
+        let narrow = render_table(
+            &archive,
+            &TableOptions {
+                width: Some(60),
+                ..Default::default()
+            },
+        );
+        for line in narrow.lines() {
+            assert!(line.width() <= 60, "{:?}", line);
+        }
+        assert!(narrow.contains('…'));
+    }
+
+    #[test]
+    fn write_table_color() {
+        let mut archive: WebArchive =
+            crate::from_bytes(include_bytes!("../fixtures/crouton.webarchive")).unwrap();
+        let subresources = archive.subresources.get_or_insert_with(Vec::new);
+        for (index, size) in [10, 12, 11, 13, 14, 12, 11, 50000].into_iter().enumerate() {
+            subresources.push(crate::WebResource {
+                text_encoding_name: (index != 0).then(|| "UTF-8".to_string()),
+                ..resource(
+                    &format!("https://crouton.net/{}.txt", index),
+                    "text/plain",
+                    vec![b'a'; size],
+                )
+            });
+        }
+
+        let options = TableOptions {
+            color: true,
+            ..Default::default()
+        };
+        let table = render_table(&archive, &options);
+        let line = |url: &str| {
+            table
+                .lines()
+                .find(|line| line.starts_with(url))
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(line("https://crouton.net/7.txt").contains("\x1b[33m50000\x1b[0m"));
+        assert!(line("https://crouton.net/0.txt").contains("\x1b[31m-"));
+        assert!(!line("https://crouton.net/1.txt").contains('\x1b'));
+    }
+}
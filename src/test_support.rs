@@ -0,0 +1,23 @@
+//! Shared helpers for building archives in tests.
+
+use crate::WebResource;
+
+/// A resource with no text encoding, frame name or response.
+pub(crate) fn resource(url: &str, mime_type: &str, data: impl AsRef<[u8]>) -> WebResource {
+    WebResource {
+        url: url.to_string(),
+        data: data.as_ref().to_vec(),
+        mime_type: mime_type.to_string(),
+        text_encoding_name: None,
+        frame_name: None,
+        response: None,
+    }
+}
+
+/// A resource encoded as UTF-8, with no frame name or response.
+pub(crate) fn text_resource(url: &str, mime_type: &str, data: impl AsRef<[u8]>) -> WebResource {
+    WebResource {
+        text_encoding_name: Some("UTF-8".to_string()),
+        ..resource(url, mime_type, data)
+    }
+}
@@ -0,0 +1,294 @@
+use crate::WebResource;
+use std::borrow::Cow;
+
+impl WebResource {
+    /// The MIME type without any parameters, lowercased.
+    ///
+    /// For example, `text/HTML; charset=utf-8` becomes `text/html`.
+    pub fn mime_essence(&self) -> String {
+        self.mime_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+    }
+
+    /// Whether the resource is an HTML document.
+    pub fn is_html(&self) -> bool {
+        matches!(
+            self.mime_essence().as_str(),
+            "text/html" | "application/xhtml+xml"
+        )
+    }
+
+    /// Whether the resource is a CSS stylesheet.
+    pub fn is_css(&self) -> bool {
+        self.mime_essence() == "text/css"
+    }
+
+    /// Whether the resource's MIME type describes textual data.
+    pub fn is_text(&self) -> bool {
+        let essence = self.mime_essence();
+
+        essence.starts_with("text/")
+            || essence.ends_with("+xml")
+            || essence.ends_with("+json")
+            || matches!(
+                essence.as_str(),
+                "application/javascript"
+                    | "application/ecmascript"
+                    | "application/x-javascript"
+                    | "application/json"
+                    | "application/xml"
+            )
+    }
+
+    /// The encoding which should be used to decode the resource's data.
+    ///
+    /// Uses `text_encoding_name` when it names an encoding known to
+    /// the [Encoding Standard](https://encoding.spec.whatwg.org/),
+    /// otherwise UTF-8.
+    ///
+    /// With the `chardet` feature, resources without a
+    /// `text_encoding_name` use [`detect_encoding`](WebResource::detect_encoding)
+    /// instead.
+    pub fn encoding(&self) -> &'static encoding_rs::Encoding {
+        match &self.text_encoding_name {
+            Some(label) => encoding_rs::Encoding::for_label(label.as_bytes()),
+            #[cfg(feature = "chardet")]
+            None => self.detect_encoding(),
+            #[cfg(not(feature = "chardet"))]
+            None => None,
+        }
+        .unwrap_or(encoding_rs::UTF_8)
+    }
+
+    /// Work out the encoding of a text resource's data,
+    /// ignoring its `text_encoding_name`.
+    ///
+    /// In order of preference, this uses:
+    ///
+    /// 1. A byte order mark at the start of the data,
+    /// 2. For HTML, a `<meta charset>` or `<meta http-equiv="Content-Type">`
+    ///    declaration within the first 1024 bytes,
+    /// 3. The `charset` parameter of the archived response's
+    ///    `Content-Type` header,
+    /// 4. A guess based on the data itself, and the URL's top-level domain.
+    ///
+    /// Returns `None` for resources which are not a text type.
+    #[cfg(feature = "chardet")]
+    pub fn detect_encoding(&self) -> Option<&'static encoding_rs::Encoding> {
+        if !self.is_text() {
+            return None;
+        }
+
+        if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(&self.data) {
+            return Some(encoding);
+        }
+
+        if self.is_html() {
+            if let Some(encoding) = meta_charset(&self.data) {
+                return Some(encoding);
+            }
+        }
+
+        if let Some(encoding) = self.parse_response().ok().flatten().and_then(|response| {
+            charset_parameter(response.headers.get("Content-Type")?)
+                .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        }) {
+            return Some(encoding);
+        }
+
+        let url = url::Url::parse(&self.url).ok();
+        let tld = url
+            .as_ref()
+            .and_then(url::Url::domain)
+            .and_then(|domain| domain.rsplit('.').next());
+
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(&self.data, true);
+        Some(detector.guess(tld.map(str::as_bytes), true))
+    }
+
+    /// Decode the resource's data as text.
+    ///
+    /// Returns `None` for resources which are not a text type.
+    /// A byte order mark, if present, takes precedence over the
+    /// declared encoding, and malformed sequences are replaced
+    /// with U+FFFD.
+    pub fn decoded_text(&self) -> Option<Cow<'_, str>> {
+        if !self.is_text() {
+            return None;
+        }
+
+        let (text, _, _) = self.encoding().decode(&self.data);
+        Some(text)
+    }
+
+    /// Replace the resource's data with `text`, encoded with its
+    /// [`encoding`](WebResource::encoding), unless that encoding can
+    /// not be written (such as UTF-16), in which case it is written as
+    /// UTF-8 and `text_encoding_name` is updated.
+    pub(crate) fn set_text(&mut self, text: &str) {
+        let encoding = self.encoding();
+        let (data, output_encoding, _) = encoding.encode(text);
+        self.data = data.into_owned();
+
+        if output_encoding != encoding {
+            self.text_encoding_name = Some(output_encoding.name().to_string());
+        }
+    }
+}
+
+#[cfg(feature = "chardet")]
+impl crate::WebArchive {
+    /// Set the `text_encoding_name` of every text resource without
+    /// one, at any depth, to the encoding found by
+    /// [`detect_encoding`](WebResource::detect_encoding).
+    pub fn fill_missing_encodings(&mut self) {
+        self.for_each_resource_mut(&mut |resource| {
+            if resource.text_encoding_name.is_none() {
+                if let Some(encoding) = resource.detect_encoding() {
+                    resource.text_encoding_name = Some(encoding.name().to_string());
+                }
+            }
+        });
+    }
+}
+
+/// The MIME type and charset of a `Content-Type` header, guessing
+/// the MIME type from `url` if the header has none.
+#[cfg(any(feature = "fetch", feature = "http"))]
+pub(crate) fn parse_content_type(content_type: &str, url: &str) -> (String, Option<String>) {
+    let (mime_type, parameters) = content_type.split_once(';').unwrap_or((content_type, ""));
+    let charset = parameters.split(';').find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    });
+
+    let mime_type = match mime_type.trim() {
+        "" => mime_guess::from_path(url)
+            .first_raw()
+            .unwrap_or("application/octet-stream"),
+        mime_type => mime_type,
+    }
+    .to_ascii_lowercase();
+
+    (mime_type, charset)
+}
+
+/// Find an encoding declared by a `<meta>` element, looking
+/// only at the first 1024 bytes, as browsers do.
+pub(crate) fn meta_charset(html: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    let head = &html[..html.len().min(1024)];
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+
+    head.split("<meta").skip(1).find_map(|tag| {
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        let encoding = encoding_rs::Encoding::for_label(charset_parameter(tag)?.as_bytes())?;
+
+        // a document declaring itself UTF-16 can't be, if its
+        // declaration could be read as ASCII
+        if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+            Some(encoding_rs::UTF_8)
+        } else {
+            Some(encoding)
+        }
+    })
+}
+
+/// The value following `charset=`, as found in a `Content-Type`
+/// header or `<meta>` element, without quotes.
+fn charset_parameter(text: &str) -> Option<&str> {
+    let lowercase = text.to_ascii_lowercase();
+    let start = lowercase.find("charset")? + "charset".len();
+    let value = text[start..].trim_start().strip_prefix('=')?.trim_start();
+    let value = value.trim_start_matches(['"', '\'']);
+    let end = value
+        .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+        .unwrap_or(value.len());
+
+    Some(&value[..end]).filter(|value| !value.is_empty())
+}
+
+#[cfg(all(test, feature = "chardet"))]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{WebArchive, WebResource};
+
+    fn encoding_name(resource: &WebResource) -> Option<&'static str> {
+        resource.detect_encoding().map(|encoding| encoding.name())
+    }
+
+    #[test]
+    fn detect_declared_encoding() {
+        // the byte order mark wins over the declaration
+        assert_eq!(
+            encoding_name(&resource(
+                "https://example.com/",
+                "text/html",
+                b"\xef\xbb\xbf<meta charset=windows-1252>"
+            )),
+            Some("UTF-8")
+        );
+
+        assert_eq!(
+            encoding_name(&resource(
+                "https://example.com/",
+                "text/html",
+                b"<head><meta http-equiv=\"Content-Type\" content=\"text/html; charset='Shift_JIS'\">"
+            )),
+            Some("Shift_JIS")
+        );
+
+        assert_eq!(
+            encoding_name(&resource(
+                "https://example.com/",
+                "text/html",
+                b"<META CHARSET=\"koi8-r\">"
+            )),
+            Some("KOI8-R")
+        );
+
+        assert_eq!(
+            encoding_name(&resource("https://example.com/", "image/png", b"\x89PNG")),
+            None
+        );
+    }
+
+    #[test]
+    fn detect_fixture_encodings() {
+        for (fixture, encoding, snippet) in [
+            (
+                "fixtures/windows-1252.webarchive",
+                "windows-1252",
+                "“Déjà vu,”",
+            ),
+            (
+                "fixtures/shift_jis.webarchive",
+                "Shift_JIS",
+                "日本語の文書です",
+            ),
+        ] {
+            let mut archive: WebArchive =
+                crate::from_file(fixture).expect("Could not read webarchive fixture");
+            assert!(archive.main_resource.text_encoding_name.is_none());
+
+            assert_eq!(encoding_name(&archive.main_resource), Some(encoding));
+            assert!(archive
+                .main_resource
+                .decoded_text()
+                .unwrap()
+                .contains(snippet));
+
+            archive.fill_missing_encodings();
+            assert_eq!(
+                archive.main_resource.text_encoding_name.as_deref(),
+                Some(encoding)
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Rewriting text resources as UTF-8.
+
+use crate::references::decode_attribute;
+use crate::{Error, Result, WebResource};
+
+/// A text resource rewritten as UTF-8, by [`WebResource::to_utf8`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Utf8Resource {
+    /// The resource, with its data as UTF-8, and a `text_encoding_name`
+    /// of `UTF-8`.
+    pub resource: WebResource,
+
+    /// The encoding its data was decoded from.
+    pub original_encoding: &'static encoding_rs::Encoding,
+
+    /// Whether any of its data was malformed in that encoding, and so
+    /// was replaced with U+FFFD.
+    pub lossy: bool,
+}
+
+impl WebResource {
+    /// A copy of a text resource with its data decoded from its
+    /// [`encoding`](WebResource::encoding) and written as UTF-8.
+    ///
+    /// So the copy stays consistent, HTML documents have their
+    /// `<meta charset>` and `<meta http-equiv="Content-Type">`
+    /// declarations changed to UTF-8, as do stylesheets' `@charset`
+    /// rules. A byte order mark is removed.
+    ///
+    /// Returns `None` for resources which are not a text type, and an
+    /// error only if an HTML document can not be rewritten.
+    pub fn to_utf8(&self) -> Result<Option<Utf8Resource>> {
+        if !self.is_text() {
+            return Ok(None);
+        }
+
+        // decoding prefers a byte order mark to the encoding given
+        let (text, original_encoding, lossy) = self.encoding().decode(&self.data);
+
+        let text = if self.is_html() {
+            utf8_meta_charset(&text)?
+        } else if self.is_css() {
+            utf8_css_charset(&text)
+        } else {
+            text.into_owned()
+        };
+
+        Ok(Some(Utf8Resource {
+            resource: WebResource {
+                data: text.into_bytes(),
+                url: self.url.clone(),
+                frame_name: self.frame_name.clone(),
+                mime_type: self.mime_type.clone(),
+                text_encoding_name: Some("UTF-8".to_string()),
+                response: self.response.clone(),
+            },
+            original_encoding,
+            lossy,
+        }))
+    }
+}
+
+/// Change the encodings `<meta>` elements declare to UTF-8.
+fn utf8_meta_charset(html: &str) -> Result<String> {
+    use lol_html::{element, HtmlRewriter, Settings};
+
+    let mut output = Vec::with_capacity(html.len());
+
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("meta[charset]", |element| {
+                    element.set_attribute("charset", "utf-8")?;
+                    Ok(())
+                }),
+                element!("meta[http-equiv][content]", |element| {
+                    let is_content_type =
+                        element.get_attribute("http-equiv").is_some_and(|value| {
+                            decode_attribute(&value)
+                                .trim()
+                                .eq_ignore_ascii_case("content-type")
+                        });
+
+                    if is_content_type {
+                        let content = element
+                            .get_attribute("content")
+                            .map(|value| decode_attribute(&value))
+                            .unwrap_or_default();
+                        let mime_type = content.split(';').next().unwrap_or_default().trim();
+                        let mime_type = if mime_type.is_empty() {
+                            "text/html"
+                        } else {
+                            mime_type
+                        };
+
+                        element
+                            .set_attribute("content", &format!("{}; charset=utf-8", mime_type))?;
+                    }
+                    Ok(())
+                }),
+            ],
+            ..Settings::new()
+        },
+        |bytes: &[u8]| output.extend_from_slice(bytes),
+    );
+
+    rewriter
+        .write(html.as_bytes())
+        .map_err(|error| Error::Html(error.to_string()))?;
+    rewriter
+        .end()
+        .map_err(|error| Error::Html(error.to_string()))?;
+
+    String::from_utf8(output).map_err(|error| Error::Html(error.to_string()))
+}
+
+/// Change the encoding a stylesheet's `@charset` rule declares to UTF-8.
+///
+/// The rule must be the very first thing in the stylesheet, exactly as
+/// `@charset "name";`, to count.
+fn utf8_css_charset(css: &str) -> String {
+    match css
+        .strip_prefix("@charset \"")
+        .and_then(|rest| rest.split_once("\";"))
+    {
+        Some((_, rest)) => format!("@charset \"UTF-8\";{}", rest),
+        None => css.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::WebResource;
+
+    #[test]
+    fn to_utf8_html() {
+        let html = WebResource {
+            text_encoding_name: Some("windows-1252".to_string()),
+            ..resource(
+                "https://example.com/",
+                "text/html",
+                b"<meta charset=\"windows-1252\">\n\
+            <meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">\n\
+            <p>Caf\xe9 \x93quoted\x94 \x80</p>",
+            )
+        };
+
+        let utf8 = html.to_utf8().unwrap().unwrap();
+        assert_eq!(utf8.original_encoding, encoding_rs::WINDOWS_1252);
+        assert!(!utf8.lossy);
+        assert_eq!(utf8.resource.text_encoding_name.as_deref(), Some("UTF-8"));
+        assert_eq!(
+            std::str::from_utf8(&utf8.resource.data).unwrap(),
+            "<meta charset=\"utf-8\">\n\
+            <meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\">\n\
+            <p>Café “quoted” €</p>"
+        );
+    }
+
+    #[test]
+    fn to_utf8_css() {
+        let css = WebResource {
+            text_encoding_name: Some("shift_jis".to_string()),
+            ..resource(
+                "https://example.com/",
+                "text/css",
+                b"@charset \"shift_jis\";\n.yen::before { content: \"\x81\x8f\" }",
+            )
+        };
+
+        let utf8 = css.to_utf8().unwrap().unwrap();
+        assert_eq!(utf8.original_encoding, encoding_rs::SHIFT_JIS);
+        assert_eq!(
+            std::str::from_utf8(&utf8.resource.data).unwrap(),
+            "@charset \"UTF-8\";\n.yen::before { content: \"￥\" }"
+        );
+    }
+
+    #[test]
+    fn to_utf8_lossy() {
+        // a byte order mark wins, and is removed
+        let text = WebResource {
+            text_encoding_name: Some("windows-1252".to_string()),
+            ..resource(
+                "https://example.com/",
+                "text/plain",
+                b"\xef\xbb\xbfh\xc3\xa9",
+            )
+        };
+        let utf8 = text.to_utf8().unwrap().unwrap();
+        assert_eq!(utf8.original_encoding, encoding_rs::UTF_8);
+        assert_eq!(utf8.resource.data, "hé".as_bytes());
+
+        let text = WebResource {
+            text_encoding_name: Some("shift_jis".to_string()),
+            ..resource("https://example.com/", "text/plain", b"ok \x82")
+        };
+        let utf8 = text.to_utf8().unwrap().unwrap();
+        assert!(utf8.lossy);
+        assert_eq!(utf8.resource.data, "ok \u{FFFD}".as_bytes());
+
+        let image = resource("https://example.com/", "image/png", b"\x89PNG");
+        assert_eq!(image.to_utf8().unwrap(), None);
+    }
+}
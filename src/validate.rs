@@ -0,0 +1,401 @@
+use crate::text::meta_charset;
+use crate::{Result, SniffConfidence, SriStatus, WebArchive};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// How serious a validation [`Issue`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The archive will probably work, but not entirely as expected.
+    Warning,
+
+    /// The archive is broken, or has been tampered with.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The kind of problem an [`Issue`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum IssueKind {
+    /// A resource has an empty URL.
+    EmptyUrl,
+    /// More than one resource in the same frame has the same URL.
+    DuplicateUrl,
+    /// A resource has no MIME type.
+    MissingMimeType,
+    /// A text resource does not declare its encoding.
+    MissingEncoding,
+    /// A resource refers to another which is not stored.
+    MissingSubresource,
+    /// A resource differs from the embedded checksum manifest.
+    ManifestMismatch,
+    /// A resource's data has the signature of a different MIME type
+    /// to the one it declares.
+    MimeMismatch,
+    /// A subresource does not match the integrity digest a page
+    /// declares for it.
+    IntegrityMismatch,
+    /// An HTML document's `<meta>` element declares a different
+    /// encoding to its `text_encoding_name`.
+    CharsetConflict,
+    /// A document links to a page on a site the archive has stored
+    /// resources from, which is not stored.
+    DeadLink,
+}
+
+/// A problem found by [`WebArchive::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Issue {
+    /// How serious the problem is.
+    pub severity: Severity,
+
+    /// What sort of problem it is.
+    pub kind: IssueKind,
+
+    /// The URL of the resource with the problem, if it
+    /// concerns a single resource.
+    pub url: Option<String>,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.url {
+            Some(url) => write!(f, "{}: {:?}: {}", self.severity, url, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// The result of [`WebArchive::validate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    /// Every problem found, in document order within each check.
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Whether any issue is an error.
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// The issues which are errors.
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    /// The issues which are warnings.
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    fn push(&mut self, severity: Severity, kind: IssueKind, url: Option<&str>, message: String) {
+        self.issues.push(Issue {
+            severity,
+            kind,
+            url: url.map(str::to_string),
+            message,
+        });
+    }
+}
+
+impl WebArchive {
+    /// Check the archive for problems which would stop it
+    /// working as expected.
+    ///
+    /// Errors are reported for resources with an empty URL or MIME
+    /// type, subresources which do not match the integrity digests
+    /// pages declare for them (see [`verify_sri`](WebArchive::verify_sri)),
+    /// and, if the archive has an embedded checksum manifest,
+    /// resources which no longer match it. Warnings are reported for
+    /// text resources without an encoding, HTML documents whose
+    /// `<meta>` element declares another encoding, URLs stored more
+    /// than once in the same frame, references to resources which are
+    /// not stored, links to pages which are not stored (see
+    /// [`dead_internal_links`](WebArchive::dead_internal_links)), and
+    /// resources whose data has the signature of another type (see
+    /// [`detect_mime_mismatches`](WebArchive::detect_mime_mismatches)).
+    ///
+    /// Returns an error only if the manifest or a resource's HTML
+    /// can not be read.
+    pub fn validate(&self) -> Result<ValidationReport> {
+        use IssueKind::*;
+        use Severity::*;
+
+        let mut report = ValidationReport::default();
+
+        for (_, frame) in self.iter_frames() {
+            let mut urls = HashSet::new();
+            let resources =
+                std::iter::once(&frame.main_resource).chain(frame.subresources.iter().flatten());
+
+            for resource in resources {
+                let url = Some(resource.url.as_str());
+
+                if resource.url.is_empty() {
+                    report.push(Error, EmptyUrl, None, "resource has no URL".to_string());
+                } else if !urls.insert(resource.url.as_str()) {
+                    report.push(
+                        Warning,
+                        DuplicateUrl,
+                        url,
+                        "stored more than once in the same frame".to_string(),
+                    );
+                }
+
+                if resource.mime_type.trim().is_empty() {
+                    report.push(Error, MissingMimeType, url, "no MIME type".to_string());
+                } else if resource.is_text() && resource.text_encoding_name.is_none() {
+                    report.push(
+                        Warning,
+                        MissingEncoding,
+                        url,
+                        format!("{:?} resource has no text encoding", resource.mime_type),
+                    );
+                }
+
+                if let (true, Some(declared), Some(meta)) = (
+                    resource.is_html(),
+                    &resource.text_encoding_name,
+                    meta_charset(&resource.data),
+                ) {
+                    let encoding = encoding_rs::Encoding::for_label(declared.as_bytes());
+                    if encoding.is_some_and(|encoding| encoding != meta) {
+                        report.push(
+                            Warning,
+                            CharsetConflict,
+                            url,
+                            format!(
+                                "encoded as {:?}, but its <meta> element declares {:?}",
+                                declared,
+                                meta.name()
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        for reference in self.missing_subresources()? {
+            report.push(
+                Warning,
+                MissingSubresource,
+                Some(&reference.url),
+                format!("{} reference is not stored", reference.kind),
+            );
+        }
+
+        for dead in self.dead_internal_links()? {
+            report.push(
+                Warning,
+                DeadLink,
+                Some(&dead.link.url),
+                format!(
+                    "linked to from {:?} as {:?}, but not stored",
+                    dead.document_url, dead.link.text
+                ),
+            );
+        }
+
+        for mismatch in self.detect_mime_mismatches() {
+            if mismatch.confidence == SniffConfidence::Signature {
+                report.push(
+                    Warning,
+                    MimeMismatch,
+                    Some(&mismatch.url),
+                    format!(
+                        "declared as {:?}, but is {:?}",
+                        mismatch.declared, mismatch.sniffed
+                    ),
+                );
+            }
+        }
+
+        for result in self.verify_sri()? {
+            if result.status == SriStatus::Mismatch {
+                report.push(
+                    Error,
+                    IntegrityMismatch,
+                    Some(&result.url),
+                    format!(
+                        "does not match the integrity {:?} declared by {:?}",
+                        result.integrity, result.document_url
+                    ),
+                );
+            }
+        }
+
+        if self.has_manifest() {
+            let verified = self.verify_manifest()?;
+
+            if !verified.main_resource_matches {
+                report.push(
+                    Error,
+                    ManifestMismatch,
+                    Some(&self.main_resource.url),
+                    "main resource differs from the manifest".to_string(),
+                );
+            }
+
+            for (urls, message) in [
+                (
+                    &verified.modified,
+                    "modified since the manifest was embedded",
+                ),
+                (&verified.added, "added since the manifest was embedded"),
+                (&verified.removed, "removed since the manifest was embedded"),
+            ] {
+                for url in urls {
+                    report.push(Error, ManifestMismatch, Some(url), message.to_string());
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueKind, Severity};
+    use crate::test_support::{resource, text_resource};
+    use crate::WebArchive;
+
+    #[test]
+    fn validate_fixtures() {
+        for fixture in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+        ] {
+            let archive: WebArchive =
+                crate::from_file(fixture).expect("Could not read webarchive fixture");
+            let report = archive.validate().unwrap();
+
+            assert!(!report.has_errors(), "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn validate_problems() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        archive.embed_manifest();
+
+        let subresources = archive.subresources.as_mut().unwrap();
+        subresources[0].data.truncate(100);
+        subresources.push(resource("", "text/css", b"body {}"));
+        archive.main_resource.mime_type = String::new();
+
+        let report = archive.validate().unwrap();
+        let issues: Vec<(Severity, IssueKind, Option<&str>)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.severity, issue.kind, issue.url.as_deref()))
+            .collect();
+
+        use IssueKind::*;
+        use Severity::*;
+        assert_eq!(
+            issues,
+            [
+                (Error, MissingMimeType, Some("https://crouton.net/")),
+                (Error, EmptyUrl, None),
+                (Warning, MissingEncoding, Some("")),
+                (
+                    Error,
+                    ManifestMismatch,
+                    Some("https://crouton.net/crouton.png")
+                ),
+                (Error, ManifestMismatch, Some("")),
+            ]
+        );
+        assert_eq!(report.errors().count(), 4);
+        assert_eq!(
+            report.issues[3].to_string(),
+            "error: \"https://crouton.net/crouton.png\": modified since the manifest was embedded"
+        );
+    }
+
+    #[test]
+    fn validate_mime_mismatch() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        archive.subresources.as_mut().unwrap()[0].mime_type = "text/html".to_string();
+
+        let report = archive.validate().unwrap();
+        let mismatches: Vec<String> = report
+            .warnings()
+            .filter(|issue| issue.kind == IssueKind::MimeMismatch)
+            .map(|issue| issue.to_string())
+            .collect();
+
+        assert_eq!(
+            mismatches,
+            ["warning: \"https://crouton.net/crouton.png\": declared as \"text/html\", but is \"image/png\""]
+        );
+    }
+
+    #[test]
+    fn validate_integrity_mismatch() {
+        // the digest of `alert(1)`, for a script which has since changed
+        let archive = WebArchive {
+            main_resource: text_resource(
+                "https://example.com/",
+                "text/html",
+                r#"<script src="app.js" integrity="sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI="></script>"#,
+            ),
+            subresources: Some(vec![text_resource(
+                "https://example.com/app.js",
+                "text/javascript",
+                "alert(2)",
+            )]),
+            subframe_archives: None,
+        };
+
+        let report = archive.validate().unwrap();
+        let errors: Vec<String> = report.errors().map(|issue| issue.to_string()).collect();
+        assert_eq!(
+            errors,
+            ["error: \"https://example.com/app.js\": does not match the integrity \"sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI=\" declared by \"https://example.com/\""]
+        );
+        assert_eq!(report.issues[0].kind, IssueKind::IntegrityMismatch);
+    }
+
+    #[test]
+    fn validate_dead_link() {
+        let mut archive: WebArchive = crate::from_file("fixtures/crouton.webarchive")
+            .expect("Could not read crouton webarchive fixture");
+        archive.main_resource.data =
+            b"<a href=\"crouton.png\">Crouton</a> <a href=\"/recipes\">Recipes</a>".to_vec();
+
+        let report = archive.validate().unwrap();
+        let warnings: Vec<String> = report.warnings().map(|issue| issue.to_string()).collect();
+        assert_eq!(
+            warnings,
+            ["warning: \"https://crouton.net/recipes\": linked to from \"https://crouton.net/\" as \"Recipes\", but not stored"]
+        );
+        assert_eq!(report.issues[0].kind.lint_rule().id, "WA010");
+    }
+}
@@ -0,0 +1,127 @@
+//! Converting archives to and from untyped property list values.
+
+use crate::{Result, WebArchive, WebResource};
+use plist::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+impl WebArchive {
+    /// The archive as a property list value: exactly the dictionary
+    /// serialising it writes, with the same keys and types.
+    ///
+    /// This is an escape hatch for working with structure this crate
+    /// does not model, such as keys other tools add. Change the value,
+    /// then check it with [`from_value`](WebArchive::from_value).
+    pub fn to_value(&self) -> Value {
+        to_value(self)
+    }
+
+    /// Read an archive from a property list value, as it would be read
+    /// from a file holding that value.
+    ///
+    /// For any archive, `WebArchive::from_value(archive.to_value())`
+    /// gives back an equal archive.
+    pub fn from_value(value: Value) -> Result<WebArchive> {
+        from_value(&value)
+    }
+}
+
+impl WebResource {
+    /// The resource as a property list value, as it is written inside
+    /// an archive. See [`WebArchive::to_value`].
+    pub fn to_value(&self) -> Value {
+        to_value(self)
+    }
+
+    /// Read a resource from a property list value, as it would be read
+    /// inside an archive. See [`WebArchive::from_value`].
+    pub fn from_value(value: Value) -> Result<WebResource> {
+        from_value(&value)
+    }
+}
+
+/// Serialise `item` the way it is written to a file, then read that
+/// back as a value, so the two can not differ
+fn to_value(item: &impl Serialize) -> Value {
+    let mut binary = Vec::new();
+    plist::to_writer_binary(&mut binary, item)
+        .expect("Archives can always be serialised to memory");
+    Value::from_reader(std::io::Cursor::new(binary))
+        .expect("A property list just written can always be read")
+}
+
+/// Write `value` as it would be stored in a file, then read that back
+fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T> {
+    let mut binary = Vec::new();
+    value.to_writer_binary(&mut binary)?;
+    Ok(plist::from_bytes(&binary)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::resource;
+    use crate::{Error, WebArchive, WebResource};
+    use plist::Value;
+
+    #[test]
+    fn round_trip_fixtures() {
+        for path in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+        ] {
+            let archive: WebArchive = crate::from_file(path).unwrap();
+            let value = archive.to_value();
+
+            // the same dictionary as is written to a file
+            let written = archive.to_vec_binary().unwrap();
+            assert_eq!(
+                value,
+                Value::from_reader(std::io::Cursor::new(written)).unwrap()
+            );
+            assert_eq!(WebArchive::from_value(value).unwrap(), archive, "{}", path);
+
+            let resource = &archive.main_resource;
+            let value = resource.to_value();
+            assert_eq!(WebResource::from_value(value).unwrap(), *resource);
+        }
+    }
+
+    #[test]
+    fn edit_value() {
+        let mut value = Value::from_file("fixtures/crouton.webarchive").unwrap();
+        let main_resource = value
+            .as_dictionary_mut()
+            .and_then(|archive| archive.get_mut("WebMainResource"))
+            .and_then(Value::as_dictionary_mut)
+            .unwrap();
+
+        main_resource.insert(
+            "WebResourceURL".to_string(),
+            Value::String("https://crouton.net/index.html".to_string()),
+        );
+        let archive = WebArchive::from_value(value.clone()).unwrap();
+        assert_eq!(archive.main_resource.url, "https://crouton.net/index.html");
+
+        // keys the crate doesn't model are refused, as in a file
+        value
+            .as_dictionary_mut()
+            .unwrap()
+            .insert("WebExperimentalKey".to_string(), Value::Boolean(true));
+        assert!(matches!(
+            WebArchive::from_value(value),
+            Err(Error::Plist(_))
+        ));
+
+        let resource = resource("https://example.com/", "text/plain", b"hello");
+        let value = resource.to_value();
+        let dictionary = value.as_dictionary().unwrap();
+        assert_eq!(
+            dictionary.keys().collect::<Vec<_>>(),
+            ["WebResourceData", "WebResourceURL", "WebResourceMIMEType"]
+        );
+        assert_eq!(
+            dictionary["WebResourceData"],
+            Value::Data(b"hello".to_vec())
+        );
+    }
+}
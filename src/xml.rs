@@ -0,0 +1,682 @@
+//! Reading XML property lists, decoding each `<data>` element's base64
+//! straight from the input into the bytes it holds.
+//!
+//! The `plist` crate's XML reader collects the text of each element in
+//! a string before decoding it, so while a large resource is read its
+//! base64 text, at four thirds of its size, is held beside the decoded
+//! data. This reader instead finds how long the text is, then goes back
+//! and decodes it a chunk at a time into a buffer of the right size.
+
+use crate::ArchiveFormat;
+use base64::alphabet::STANDARD;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use plist::stream::{Event, OwnedEvent};
+use plist::{Error, Value};
+use serde::de::{DeserializeOwned, Error as _};
+use std::borrow::Cow;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How much of the input to read at a time.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// How many base64 characters to decode at a time, once whitespace
+/// between them is left out: a multiple of four, so that each chunk
+/// decodes to whole bytes.
+const DECODE_CHUNK: usize = 16 * 1024;
+
+/// Decodes base64 as the `plist` crate does, with or without padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Deserializes an instance of type `T` from a byte slice.
+///
+/// This reads property lists as [`plist::from_bytes`] does, but needs
+/// less memory to read XML ones.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    from_reader(Cursor::new(bytes))
+}
+
+/// Deserializes an instance of type `T` from a plist file of any encoding.
+///
+/// This reads property lists as [`plist::from_file`] does, but needs
+/// less memory to read XML ones.
+pub fn from_file<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T, Error> {
+    let file = std::fs::File::open(path).map_err(io_error)?;
+    from_reader(file)
+}
+
+/// Deserializes an instance of type `T` from a seekable byte stream
+/// containing a plist of any encoding.
+///
+/// This reads property lists as [`plist::from_reader`] does, but needs
+/// less memory to read XML ones.
+pub fn from_reader<R: Read + Seek, T: DeserializeOwned>(mut reader: R) -> Result<T, Error> {
+    let start = reader.stream_position().map_err(io_error)?;
+    let mut magic = Vec::with_capacity(8);
+    (&mut reader)
+        .take(8)
+        .read_to_end(&mut magic)
+        .map_err(io_error)?;
+    reader.seek(SeekFrom::Start(start)).map_err(io_error)?;
+
+    match ArchiveFormat::detect(&magic) {
+        ArchiveFormat::Binary => plist::from_reader(reader),
+        ArchiveFormat::Xml => {
+            let mut deserializer = plist::Deserializer::new(XmlEvents::new(reader)?);
+            T::deserialize(&mut deserializer)
+        }
+    }
+}
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::custom(error)
+}
+
+/// Buffered input which can go back to an earlier position.
+struct Input<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// The range of `buffer` read and not yet consumed
+    start: usize,
+    end: usize,
+    /// The position in `reader` of the start of `buffer`
+    offset: u64,
+}
+
+impl<R: Read + Seek> Input<R> {
+    fn new(mut reader: R) -> std::io::Result<Self> {
+        let offset = reader.stream_position()?;
+
+        Ok(Input {
+            reader,
+            buffer: vec![0; BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            offset,
+        })
+    }
+
+    /// The position in `reader` of the next byte
+    fn position(&self) -> u64 {
+        self.offset + self.start as u64
+    }
+
+    /// The bytes read and not yet consumed; at least `len` of them,
+    /// unless the input ends first
+    fn peek(&mut self, len: usize) -> std::io::Result<&[u8]> {
+        if self.end - self.start < len {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.offset += self.start as u64;
+            self.end -= self.start;
+            self.start = 0;
+            if self.buffer.len() < len {
+                self.buffer.resize(len, 0);
+            }
+
+            while self.end < len {
+                match self.reader.read(&mut self.buffer[self.end..]) {
+                    Ok(0) => break,
+                    Ok(read) => self.end += read,
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        Ok(&self.buffer[self.start..self.end])
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+    }
+
+    fn seek(&mut self, position: u64) -> std::io::Result<()> {
+        self.reader.seek(SeekFrom::Start(position))?;
+        self.offset = position;
+        self.start = 0;
+        self.end = 0;
+        Ok(())
+    }
+}
+
+/// A start or end tag.
+struct Tag {
+    name: String,
+    closing: bool,
+    /// Whether the tag closes itself, as in `<true/>`
+    empty: bool,
+}
+
+/// The events of an XML property list, for [`plist::Deserializer`].
+struct XmlEvents<R> {
+    input: Input<R>,
+    /// The names of the elements opened and not yet closed
+    open: Vec<String>,
+    /// The end of an empty `<array/>` or `<dict/>`, to be given next
+    pending_end: bool,
+    finished: bool,
+}
+
+impl<R: Read + Seek> XmlEvents<R> {
+    fn new(reader: R) -> Result<Self, Error> {
+        Ok(XmlEvents {
+            input: Input::new(reader).map_err(io_error)?,
+            open: Vec::new(),
+            pending_end: false,
+            finished: false,
+        })
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::custom(format!("{} at byte {}", message, self.input.position()))
+    }
+
+    fn peek(&mut self, len: usize) -> Result<&[u8], Error> {
+        self.input.peek(len).map_err(io_error)
+    }
+
+    fn starts_with(&mut self, prefix: &[u8]) -> Result<bool, Error> {
+        Ok(self.peek(prefix.len())?.starts_with(prefix))
+    }
+
+    /// Skip any whitespace, comments, processing instructions and
+    /// document type declarations
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            let available = self.peek(1)?;
+            let whitespace = available
+                .iter()
+                .take_while(|byte| byte.is_ascii_whitespace())
+                .count();
+            if whitespace > 0 {
+                self.input.consume(whitespace);
+            } else if self.starts_with(b"<!--")? {
+                self.skip_past(b"-->")?;
+            } else if self.starts_with(b"<?")? {
+                self.skip_past(b"?>")?;
+            } else if self.starts_with(b"<!DOCTYPE")? {
+                // the declaration may have an internal subset, in brackets
+                let mut depth = 0;
+                loop {
+                    match self.peek(1)?.first() {
+                        Some(b'[') => depth += 1,
+                        Some(b']') => depth -= 1,
+                        Some(b'>') if depth == 0 => break,
+                        Some(_) => {}
+                        None => return Err(self.error("unclosed document type declaration")),
+                    }
+                    self.input.consume(1);
+                }
+                self.input.consume(1);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Move past the next `end`
+    fn skip_past(&mut self, end: &[u8]) -> Result<(), Error> {
+        loop {
+            let available = self.peek(end.len())?;
+            if available.len() < end.len() {
+                return Err(self.error("unexpected end of document"));
+            }
+            match find(available, end) {
+                Some(index) => {
+                    self.input.consume(index + end.len());
+                    return Ok(());
+                }
+                // keep what could be the start of `end`
+                None => {
+                    let len = available.len() - (end.len() - 1);
+                    self.input.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Read up to and past the next `end`, giving what came before it
+    fn read_until(&mut self, end: u8) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        loop {
+            let available = self.peek(1)?;
+            if available.is_empty() {
+                return Err(self.error("unexpected end of document"));
+            }
+            match available.iter().position(|&byte| byte == end) {
+                Some(index) => {
+                    bytes.extend_from_slice(&available[..index]);
+                    self.input.consume(index + 1);
+                    return Ok(bytes);
+                }
+                None => {
+                    let len = available.len();
+                    bytes.extend_from_slice(available);
+                    self.input.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Read the tag at the current position, ignoring its attributes
+    fn read_tag(&mut self) -> Result<Tag, Error> {
+        if !self.starts_with(b"<")? {
+            return Err(self.error("expected an element"));
+        }
+        self.input.consume(1);
+        let inside = self.read_until(b'>')?;
+
+        let (closing, inside) = match &inside[..] {
+            [b'/', inside @ ..] => (true, inside),
+            inside => (false, inside),
+        };
+        let (empty, inside) = match inside {
+            [inside @ .., b'/'] => (true, inside),
+            inside => (false, inside),
+        };
+        let name_end = inside
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(inside.len());
+        let name = std::str::from_utf8(&inside[..name_end])
+            .map_err(|_| self.error("element name is not UTF-8"))?;
+
+        Ok(Tag {
+            name: name.to_string(),
+            closing,
+            empty,
+        })
+    }
+
+    /// Expect the end tag of `name` next
+    fn expect_end(&mut self, name: &str) -> Result<(), Error> {
+        let tag = self.read_tag()?;
+        if tag.closing && tag.name == name {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected </{}>", name)))
+        }
+    }
+
+    /// The text of the element `tag` opened, up to and past its end tag
+    fn read_text(&mut self, tag: &Tag) -> Result<String, Error> {
+        let mut text = Vec::new();
+        if tag.empty {
+            return Ok(String::new());
+        }
+
+        loop {
+            let available = self.peek(1)?;
+            if available.is_empty() {
+                return Err(self.error("unexpected end of document"));
+            }
+            let len = available
+                .iter()
+                .position(|&byte| byte == b'<' || byte == b'&')
+                .unwrap_or(available.len());
+            text.extend_from_slice(&available[..len]);
+            self.input.consume(len);
+            if len > 0 {
+                continue;
+            }
+
+            if self.starts_with(b"&")? {
+                self.input.consume(1);
+                let reference = self.read_until(b';')?;
+                let character = decode_reference(&reference)
+                    .ok_or_else(|| self.error("unknown character reference"))?;
+                let mut buffer = [0; 4];
+                text.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+            } else if self.starts_with(b"<![CDATA[")? {
+                self.input.consume(b"<![CDATA[".len());
+                loop {
+                    let available = self.peek(3)?;
+                    if available.len() < 3 {
+                        return Err(self.error("unclosed CDATA"));
+                    }
+                    if let Some(index) = find(available, b"]]>") {
+                        text.extend_from_slice(&available[..index]);
+                        self.input.consume(index + 3);
+                        break;
+                    }
+                    let len = available.len() - 2;
+                    text.extend_from_slice(&available[..len]);
+                    self.input.consume(len);
+                }
+            } else if self.starts_with(b"<!--")? {
+                self.skip_past(b"-->")?;
+            } else if self.starts_with(b"<?")? {
+                self.skip_past(b"?>")?;
+            } else {
+                self.expect_end(&tag.name)?;
+                return String::from_utf8(text).map_err(|_| self.error("text is not UTF-8"));
+            }
+        }
+    }
+
+    /// The bytes the `<data>` element `tag` opened holds, decoded
+    /// without keeping their text
+    fn read_data(&mut self, tag: &Tag) -> Result<Vec<u8>, Error> {
+        if tag.empty {
+            return Ok(Vec::new());
+        }
+
+        // base64 has no `<`, so the text ends at the next one. If it
+        // isn't all in the buffer, find where, then go back to decode it
+        let start = self.input.position();
+        let mut len = 0;
+        let mut characters = 0;
+        loop {
+            let available = self.peek(1)?;
+            if available.is_empty() {
+                return Err(self.error("unexpected end of document"));
+            }
+            let text_len = available
+                .iter()
+                .position(|&byte| byte == b'<')
+                .unwrap_or(available.len());
+            len += text_len;
+            characters += available[..text_len]
+                .iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .count();
+            if text_len < available.len() {
+                break;
+            }
+            self.input.consume(text_len);
+        }
+        if self.input.position() != start {
+            self.input.seek(start).map_err(io_error)?;
+        }
+
+        let mut data = Vec::with_capacity(characters.div_ceil(4) * 3);
+        let mut chunk = Vec::with_capacity(DECODE_CHUNK.min(characters));
+        let mut remaining = len;
+        while remaining > 0 {
+            let available = self.peek(1)?;
+            if available.is_empty() {
+                return Err(self.error("unexpected end of document"));
+            }
+            let available = &available[..available.len().min(remaining)];
+            let mut valid = true;
+            for &byte in available {
+                if byte.is_ascii_whitespace() {
+                    continue;
+                }
+                chunk.push(byte);
+                if chunk.len() == DECODE_CHUNK {
+                    valid &= BASE64.decode_vec(&chunk, &mut data).is_ok();
+                    chunk.clear();
+                }
+            }
+            let consumed = available.len();
+            self.input.consume(consumed);
+            remaining -= consumed;
+            if !valid {
+                return Err(self.error("invalid base64 data"));
+            }
+        }
+        BASE64
+            .decode_vec(&chunk, &mut data)
+            .map_err(|_| self.error("invalid base64 data"))?;
+
+        self.expect_end("data")?;
+        Ok(data)
+    }
+
+    /// Read a date, integer or real, as the `plist` crate's own XML
+    /// reader does, as they are small and rare in archives
+    fn read_scalar(&mut self, tag: &Tag) -> Result<OwnedEvent, Error> {
+        let text = self.read_text(tag)?;
+        let document = format!("<plist><{0}>{1}</{0}></plist>", tag.name, text);
+
+        match Value::from_reader_xml(document.as_bytes()) {
+            Ok(Value::Date(date)) => Ok(Event::Date(date)),
+            Ok(Value::Integer(integer)) => Ok(Event::Integer(integer)),
+            Ok(Value::Real(real)) => Ok(Event::Real(real)),
+            _ => Err(self.error(&format!("invalid <{}>", tag.name))),
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<OwnedEvent>, Error> {
+        if std::mem::take(&mut self.pending_end) {
+            return Ok(Some(Event::EndCollection));
+        }
+
+        loop {
+            self.skip_misc()?;
+            if self.peek(1)?.is_empty() {
+                return match self.open.is_empty() {
+                    true => Ok(None),
+                    false => Err(self.error("unclosed element")),
+                };
+            }
+
+            let tag = self.read_tag()?;
+            if tag.closing {
+                match self.open.pop() {
+                    Some(name) if name == tag.name => {}
+                    _ => return Err(self.error("unexpected closing tag")),
+                }
+                match tag.name.as_str() {
+                    "array" | "dict" => return Ok(Some(Event::EndCollection)),
+                    _ => continue,
+                }
+            }
+
+            let event = match tag.name.as_str() {
+                "plist" if !tag.empty => {
+                    self.open.push(tag.name);
+                    continue;
+                }
+                "array" | "dict" => {
+                    let event = match tag.name.as_str() {
+                        "array" => Event::StartArray(None),
+                        _ => Event::StartDictionary(None),
+                    };
+                    if tag.empty {
+                        self.pending_end = true;
+                    } else {
+                        self.open.push(tag.name);
+                    }
+                    event
+                }
+                "key" | "string" => Event::String(Cow::Owned(self.read_text(&tag)?)),
+                "data" => Event::Data(Cow::Owned(self.read_data(&tag)?)),
+                "true" | "false" => {
+                    if !tag.empty {
+                        self.expect_end(&tag.name)?;
+                    }
+                    Event::Boolean(tag.name == "true")
+                }
+                "date" | "integer" | "real" => self.read_scalar(&tag)?,
+                name => return Err(self.error(&format!("unknown element <{}>", name))),
+            };
+            return Ok(Some(event));
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for XmlEvents<R> {
+    type Item = Result<OwnedEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.read_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(error) => {
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Where `needle` first appears in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The character a reference such as `amp` or `#x20` stands for
+fn decode_reference(reference: &[u8]) -> Option<char> {
+    match reference {
+        b"lt" => Some('<'),
+        b"gt" => Some('>'),
+        b"amp" => Some('&'),
+        b"quot" => Some('"'),
+        b"apos" => Some('\''),
+        [b'#', b'x', hex @ ..] => {
+            char::from_u32(u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?)
+        }
+        [b'#', decimal @ ..] => char::from_u32(std::str::from_utf8(decimal).ok()?.parse().ok()?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes, from_reader, Input, XmlEvents};
+    use crate::WebArchive;
+    use plist::stream::Event;
+    use plist::Value;
+    use std::io::Cursor;
+
+    /// Read `xml` both with the `plist` crate's reader and this one
+    fn both<T: serde::de::DeserializeOwned>(xml: &[u8]) -> (T, T) {
+        (plist::from_bytes(xml).unwrap(), from_bytes(xml).unwrap())
+    }
+
+    #[test]
+    fn same_as_plist_crate() {
+        for path in [
+            "fixtures/crouton.webarchive",
+            "fixtures/psxdatacenter.webarchive",
+            "fixtures/shift_jis.webarchive",
+        ] {
+            let archive: WebArchive = plist::from_file(path).unwrap();
+            let mut xml = Vec::new();
+            crate::to_writer_xml(&mut xml, &archive).unwrap();
+
+            let (old, new): (WebArchive, WebArchive) = both(&xml);
+            assert_eq!(old, new, "{}", path);
+            assert_eq!(new, archive, "{}", path);
+
+            // binary archives are still read by the `plist` crate
+            let binary: WebArchive = crate::from_file(path).unwrap();
+            assert_eq!(binary, archive, "{}", path);
+        }
+
+        for path in [
+            "fixtures/nonstandard-keys.webarchive",
+            "fixtures/string-data.webarchive",
+        ] {
+            let xml = std::fs::read(path).unwrap();
+            let (old, new): (Value, Value) = both(&xml);
+            assert_eq!(old, new, "{}", path);
+        }
+    }
+
+    #[test]
+    fn xml_syntax() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+            \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+            <plist version=\"1.0\">\n\
+            <!-- a comment -->\n\
+            <dict>\n\
+            \t<key>text</key>\n\
+            \t<string>a &lt;b&gt; &amp; &#233;&#x20AC;\r\n<![CDATA[<raw> & ]]><!-- gone -->end</string>\n\
+            \t<key>empty</key><string/>\n\
+            \t<key>data</key>\n\
+            \t<data>\n\
+            \taGVs\n\
+            \tbG8g\n\
+            \td29y bGQ=\n\
+            \t</data>\n\
+            \t<key>unpadded</key><data>aGk</data>\n\
+            \t<key>nothing</key><data></data>\n\
+            \t<key>list</key><array><true/><false></false><array/><dict/></array>\n\
+            \t<key>numbers</key><array><integer>-3</integer><integer>0x10</integer>\
+            <real>1.5</real></array>\n\
+            \t<key>date</key><date>2020-05-30T05:11:56Z</date>\n\
+            </dict>\n\
+            </plist>\n";
+
+        let (old, new): (Value, Value) = both(xml.as_bytes());
+        assert_eq!(old, new);
+
+        let dictionary = new.as_dictionary().unwrap();
+        assert_eq!(
+            dictionary["text"].as_string(),
+            Some("a <b> & é€\r\n<raw> & end")
+        );
+        assert_eq!(dictionary["data"].as_data(), Some(&b"hello world"[..]));
+        assert_eq!(dictionary["unpadded"].as_data(), Some(&b"hi"[..]));
+    }
+
+    #[test]
+    fn larger_than_buffer() {
+        let data: Vec<u8> = (0..300_000u32).map(|index| (index % 251) as u8).collect();
+        let text = "é".repeat(100_000);
+        let value = Value::Array(vec![Value::Data(data), Value::String(text)]);
+        let mut xml = Vec::new();
+        value.to_writer_xml(&mut xml).unwrap();
+
+        // starting partway through the reader
+        let mut reader = Cursor::new([&b"junk"[..], &xml].concat());
+        reader.set_position(4);
+        assert_eq!(from_reader::<_, Value>(reader).unwrap(), value);
+
+        // the data is decoded into exactly as much space as it needs,
+        // even when its text is read in small pieces
+        let mut input = Input::new(Cursor::new(&xml)).unwrap();
+        input.buffer.truncate(1000);
+        let mut events = XmlEvents {
+            input,
+            open: Vec::new(),
+            pending_end: false,
+            finished: false,
+        };
+        let data = events
+            .find_map(|event| match event.unwrap() {
+                Event::Data(data) => Some(data.into_owned()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(data.len(), 300_000);
+        assert_eq!(data.capacity(), 300_000);
+    }
+
+    #[test]
+    fn xml_errors() {
+        for xml in [
+            "<plist><dict><key>a</key><string>b</string></plist>",
+            "<plist><data>!!!!</data></plist>",
+            "<plist><data>aGVs",
+            "<plist><string>a &bogus; b</string></plist>",
+            "<plist><wat/></plist>",
+            "<plist><integer>one</integer></plist>",
+            "<plist><array>",
+        ] {
+            assert!(from_bytes::<Value>(xml.as_bytes()).is_err(), "{}", xml);
+            assert!(
+                plist::from_bytes::<Value>(xml.as_bytes()).is_err(),
+                "{}",
+                xml
+            );
+        }
+    }
+}
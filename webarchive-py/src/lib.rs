@@ -0,0 +1,227 @@
+//! Python bindings for the [`webarchive`] crate.
+//!
+//! Build them with [maturin](https://www.maturin.rs), which installs
+//! a `webarchive` Python module:
+//!
+//! ```python
+//! import webarchive
+//!
+//! archive = webarchive.WebArchive.from_file("page.webarchive")
+//! print(archive.main_resource.url, len(archive.subresources))
+//! ```
+//!
+//! Reading and writing archives release the GIL, so archives can be
+//! processed in parallel from a thread pool.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::path::PathBuf;
+use webarchive::ArchiveFormat;
+
+create_exception!(
+    webarchive,
+    WebArchiveError,
+    PyException,
+    "Raised when an archive can not be read, written or converted."
+);
+
+/// Turn any error from the `webarchive` crate into a [`WebArchiveError`].
+fn archive_error(error: impl std::fmt::Display) -> PyErr {
+    WebArchiveError::new_err(error.to_string())
+}
+
+/// Parse the `format` argument Python callers pass.
+fn archive_format(format: &str) -> PyResult<ArchiveFormat> {
+    match format {
+        "binary" => Ok(ArchiveFormat::Binary),
+        "xml" => Ok(ArchiveFormat::Xml),
+        _ => Err(PyValueError::new_err(format!(
+            "format must be \"binary\" or \"xml\", not {:?}",
+            format
+        ))),
+    }
+}
+
+/// An entire Web Archive file.
+#[pyclass(module = "webarchive", name = "WebArchive", frozen)]
+struct PyWebArchive {
+    archive: webarchive::WebArchive,
+}
+
+#[pymethods]
+impl PyWebArchive {
+    /// Read an archive from a file, in either binary or XML
+    /// property list format.
+    #[staticmethod]
+    fn from_file(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let archive = py
+            .detach(|| webarchive::from_file(&path))
+            .map_err(archive_error)?;
+        Ok(PyWebArchive { archive })
+    }
+
+    /// Read an archive from the contents of a file.
+    #[staticmethod]
+    fn from_bytes(py: Python<'_>, data: &[u8]) -> PyResult<Self> {
+        let archive = py
+            .detach(|| webarchive::from_bytes(data))
+            .map_err(archive_error)?;
+        Ok(PyWebArchive { archive })
+    }
+
+    /// Write the archive to a file, as a `"binary"` or `"xml"`
+    /// property list, replacing the file only once the archive has
+    /// been written in full.
+    #[pyo3(signature = (path, format = "binary"))]
+    fn to_file(&self, py: Python<'_>, path: PathBuf, format: &str) -> PyResult<()> {
+        let format = archive_format(format)?;
+        py.detach(|| webarchive::to_file_atomic(&path, &self.archive, format))
+            .map_err(archive_error)
+    }
+
+    /// The archive as the contents of a file, as a `"binary"` or
+    /// `"xml"` property list.
+    #[pyo3(signature = (format = "binary"))]
+    fn to_bytes<'py>(&self, py: Python<'py>, format: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let format = archive_format(format)?;
+        let data = py
+            .detach(|| match format {
+                ArchiveFormat::Binary => self.archive.to_vec_binary(),
+                ArchiveFormat::Xml => self.archive.to_vec_xml(),
+            })
+            .map_err(archive_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// The resource shown upon opening the archive.
+    #[getter]
+    fn main_resource(&self, py: Python<'_>) -> PyWebResource {
+        PyWebResource::new(py, &self.archive.main_resource)
+    }
+
+    /// The resources the main resource may refer to.
+    #[getter]
+    fn subresources(&self, py: Python<'_>) -> Vec<PyWebResource> {
+        self.archive
+            .subresources
+            .iter()
+            .flatten()
+            .map(|subresource| PyWebResource::new(py, subresource))
+            .collect()
+    }
+
+    /// The archives of frames within the page.
+    #[getter]
+    fn subframe_archives(&self) -> Vec<PyWebArchive> {
+        self.archive
+            .subframe_archives
+            .iter()
+            .flatten()
+            .map(|archive| PyWebArchive {
+                archive: archive.clone(),
+            })
+            .collect()
+    }
+
+    /// List every resource in the archive, at any depth, in document
+    /// order, as dictionaries.
+    fn manifest<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let mut entries = Vec::new();
+
+        for (path, archive) in self.archive.iter_frames() {
+            let resources = std::iter::once((&archive.main_resource, true)).chain(
+                archive
+                    .subresources
+                    .iter()
+                    .flatten()
+                    .map(|subresource| (subresource, false)),
+            );
+
+            for (resource, is_main_resource) in resources {
+                let entry = PyDict::new(py);
+                entry.set_item("url", &resource.url)?;
+                entry.set_item("mime_type", &resource.mime_type)?;
+                entry.set_item("text_encoding_name", &resource.text_encoding_name)?;
+                entry.set_item("size", resource.data.len())?;
+                entry.set_item("frame", path.to_string())?;
+                entry.set_item("is_main_resource", is_main_resource)?;
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Describe the archive as a dictionary: its URL, title, size,
+    /// contents by MIME type, and when it was made, as `webarchive
+    /// inspect --format json` does.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let summary = self.archive.summary_info().map_err(archive_error)?;
+        let json = serde_json::to_string(&summary).map_err(archive_error)?;
+        py.import("json")?.call_method1("loads", (json,))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<WebArchive of {:?}>", self.archive.main_resource.url)
+    }
+}
+
+/// An individual resource within an archive.
+///
+/// This is a copy of the resource at the time it was read; changing
+/// it does not change the archive.
+#[pyclass(module = "webarchive", name = "WebResource", get_all, set_all)]
+struct PyWebResource {
+    /// The URL the resource was archived from.
+    url: String,
+
+    /// The resource's data.
+    data: Py<PyBytes>,
+
+    /// The MIME type of the resource.
+    mime_type: String,
+
+    /// The text encoding of the resource, if it declares one.
+    text_encoding_name: Option<String>,
+
+    /// The name of the frame the resource is for, if any.
+    frame_name: Option<String>,
+
+    /// The archived HTTP response, as a property list, if any.
+    response: Option<Py<PyBytes>>,
+}
+
+#[pymethods]
+impl PyWebResource {
+    fn __repr__(&self) -> String {
+        format!("<WebResource {:?} ({:?})>", self.url, self.mime_type)
+    }
+}
+
+impl PyWebResource {
+    fn new(py: Python<'_>, resource: &webarchive::WebResource) -> Self {
+        PyWebResource {
+            url: resource.url.clone(),
+            data: PyBytes::new(py, &resource.data).unbind(),
+            mime_type: resource.mime_type.clone(),
+            text_encoding_name: resource.text_encoding_name.clone(),
+            frame_name: resource.frame_name.clone(),
+            response: resource
+                .response
+                .as_ref()
+                .map(|response| PyBytes::new(py, response).unbind()),
+        }
+    }
+}
+
+/// Work with Apple's Web Archive file format.
+#[pymodule]
+#[pyo3(name = "webarchive")]
+fn webarchive_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyWebArchive>()?;
+    module.add_class::<PyWebResource>()?;
+    module.add("WebArchiveError", module.py().get_type::<WebArchiveError>())?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+from concurrent.futures import ThreadPoolExecutor
+from pathlib import Path
+
+import pytest
+
+import webarchive
+
+FIXTURES = Path(__file__).parent.parent.parent / "fixtures"
+CROUTON = FIXTURES / "crouton.webarchive"
+PSXDATACENTER = FIXTURES / "psxdatacenter.webarchive"
+
+
+def test_from_file():
+    archive = webarchive.WebArchive.from_file(CROUTON)
+
+    main_resource = archive.main_resource
+    assert main_resource.url == "https://crouton.net/"
+    assert main_resource.mime_type == "text/html"
+    assert main_resource.text_encoding_name == "UTF-8"
+    assert main_resource.frame_name is None
+    assert b"<title> Crouton\n</title>" in main_resource.data
+
+    [crouton] = archive.subresources
+    assert crouton.url == "https://crouton.net/crouton.png"
+    assert crouton.mime_type == "image/png"
+    assert isinstance(crouton.data, bytes)
+    assert crouton.data == (FIXTURES / "crouton.net" / "crouton.png").read_bytes()
+
+    assert archive.subframe_archives == []
+
+
+def test_subframe_archives():
+    archive = webarchive.WebArchive.from_file(PSXDATACENTER)
+
+    frames = archive.subframe_archives
+    assert [frame.main_resource.url for frame in frames] == [
+        "http://psxdatacenter.com/banner.html",
+        "http://psxdatacenter.com/nav.html",
+        "http://psxdatacenter.com/jbanner.html",
+        "http://psxdatacenter.com/jlist.html",
+    ]
+    assert frames[2].main_resource.frame_name == "jbanner"
+
+
+def test_round_trip(tmp_path):
+    archive = webarchive.WebArchive.from_file(CROUTON)
+
+    for format in ["binary", "xml"]:
+        path = tmp_path / f"crouton-{format}.webarchive"
+        archive.to_file(path, format=format)
+        assert path.read_bytes() == archive.to_bytes(format)
+
+        copy = webarchive.WebArchive.from_file(path)
+        assert copy.to_bytes() == archive.to_bytes()
+
+    assert archive.to_bytes().startswith(b"bplist00")
+    assert archive.to_bytes("xml").startswith(b"<?xml")
+
+    with pytest.raises(ValueError, match="format must be"):
+        archive.to_bytes("json")
+
+
+def test_manifest():
+    archive = webarchive.WebArchive.from_file(CROUTON)
+
+    assert archive.manifest() == [
+        {
+            "url": "https://crouton.net/",
+            "mime_type": "text/html",
+            "text_encoding_name": "UTF-8",
+            "size": 134,
+            "frame": "main",
+            "is_main_resource": True,
+        },
+        {
+            "url": "https://crouton.net/crouton.png",
+            "mime_type": "image/png",
+            "text_encoding_name": None,
+            "size": 5182,
+            "frame": "main",
+            "is_main_resource": False,
+        },
+    ]
+
+
+def test_stats():
+    stats = webarchive.WebArchive.from_file(CROUTON).stats()
+
+    assert stats["url"] == "https://crouton.net/"
+    assert stats["title"] == "Crouton"
+    assert stats["total_size"] == 5316
+    assert stats["resource_count"] == 2
+    assert stats["mime_types"]["image/png"] == {"count": 1, "bytes": 5182}
+
+
+def test_errors(tmp_path):
+    with pytest.raises(webarchive.WebArchiveError):
+        webarchive.WebArchive.from_bytes(b"not a webarchive")
+
+    with pytest.raises(webarchive.WebArchiveError):
+        webarchive.WebArchive.from_file(tmp_path / "missing.webarchive")
+
+    assert issubclass(webarchive.WebArchiveError, Exception)
+
+
+def test_threads():
+    paths = [CROUTON, PSXDATACENTER] * 8
+
+    with ThreadPoolExecutor(max_workers=4) as pool:
+        archives = list(pool.map(webarchive.WebArchive.from_file, paths))
+
+    assert [archive.main_resource.url for archive in archives[:2]] == [
+        "https://crouton.net/",
+        "http://psxdatacenter.com/ntsc-j_list.html",
+    ]
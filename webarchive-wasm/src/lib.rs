@@ -0,0 +1,144 @@
+//! Inspect Web Archive files in the browser.
+//!
+//! This wraps the [`webarchive`] crate for JavaScript, using
+//! [wasm-bindgen](https://rustwasm.github.io/docs/wasm-bindgen/), which
+//! also writes TypeScript definitions for everything exported here.
+//!
+//! ```js
+//! import { WebArchiveJs } from "webarchive-wasm";
+//!
+//! const archive = WebArchiveJs.parse(new Uint8Array(await file.arrayBuffer()));
+//! for (const entry of archive.manifest()) {
+//!   console.log(entry.url, entry.mimeType, entry.size);
+//! }
+//! ```
+
+use wasm_bindgen::prelude::*;
+use webarchive::WebArchive;
+
+/// A Web Archive, read from the bytes of a `.webarchive` file.
+#[wasm_bindgen]
+pub struct WebArchiveJs {
+    archive: WebArchive,
+}
+
+#[wasm_bindgen]
+impl WebArchiveJs {
+    /// Read an archive from the contents of a `.webarchive` file,
+    /// either a binary or an XML property list.
+    pub fn parse(data: &[u8]) -> Result<WebArchiveJs, JsError> {
+        Ok(WebArchiveJs {
+            archive: webarchive::from_bytes(data)?,
+        })
+    }
+
+    /// List every resource in the archive, at any depth, in document
+    /// order.
+    pub fn manifest(&self) -> Vec<ManifestEntry> {
+        let mut entries = Vec::new();
+
+        for (path, archive) in self.archive.iter_frames() {
+            let resources = std::iter::once((&archive.main_resource, true)).chain(
+                archive
+                    .subresources
+                    .iter()
+                    .flatten()
+                    .map(|subresource| (subresource, false)),
+            );
+
+            for (resource, is_main_resource) in resources {
+                #[cfg(feature = "image")]
+                let dimensions = resource.image_dimensions();
+
+                entries.push(ManifestEntry {
+                    url: resource.url.clone(),
+                    mime_type: resource.mime_type.clone(),
+                    text_encoding_name: resource.text_encoding_name.clone(),
+                    size: resource.data.len(),
+                    frame: path.to_string(),
+                    is_main_resource,
+                    #[cfg(feature = "image")]
+                    width: dimensions.map(|(width, _)| width),
+                    #[cfg(feature = "image")]
+                    height: dimensions.map(|(_, height)| height),
+                });
+            }
+        }
+
+        entries
+    }
+
+    /// The data of the resource stored at `url`, or `undefined` if
+    /// there is none.
+    #[wasm_bindgen(js_name = resourceData)]
+    pub fn resource_data(&self, url: &str) -> Option<Vec<u8>> {
+        self.archive
+            .get_resource_by_url(url)
+            .map(|resource| resource.data.clone())
+    }
+
+    /// Convert the archive to a single HTML document, with everything
+    /// it refers to embedded as `data:` URLs, suitable for showing in
+    /// an `<iframe srcdoc>`.
+    ///
+    /// Throws if the main resource is not HTML.
+    #[wasm_bindgen(js_name = toSingleHtml)]
+    pub fn to_single_html(&self) -> Result<String, JsError> {
+        Ok(webarchive::convert::to_single_html(&self.archive)?)
+    }
+
+    /// Convert the archived article to Markdown, with images embedded
+    /// as `data:` URLs.
+    ///
+    /// Throws if the main resource is not HTML.
+    #[cfg(feature = "readability")]
+    #[wasm_bindgen(js_name = toMarkdown)]
+    pub fn to_markdown(&self) -> Result<String, JsError> {
+        Ok(webarchive::convert::to_markdown(
+            &self.archive,
+            &Default::default(),
+        )?)
+    }
+
+    /// Describe the archive as JSON: its URL, title, size, contents by
+    /// MIME type, and when it was made, as `webarchive inspect
+    /// --format json` does.
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(&self.archive.summary_info()?)?)
+    }
+}
+
+/// One resource, as listed by [`WebArchiveJs::manifest`].
+#[wasm_bindgen(getter_with_clone)]
+pub struct ManifestEntry {
+    /// The URL the resource was archived from.
+    pub url: String,
+
+    /// The MIME type of the resource.
+    #[wasm_bindgen(js_name = mimeType)]
+    pub mime_type: String,
+
+    /// The text encoding of the resource, if it declares one.
+    #[wasm_bindgen(js_name = textEncodingName)]
+    pub text_encoding_name: Option<String>,
+
+    /// The size of the resource's data, in bytes.
+    pub size: usize,
+
+    /// The frame the resource belongs to, such as `main` or
+    /// `main → frame "nav"`.
+    pub frame: String,
+
+    /// Whether the resource is the main resource of its frame.
+    #[wasm_bindgen(js_name = isMainResource)]
+    pub is_main_resource: bool,
+
+    /// The width of the resource in pixels, if it is an image.
+    #[cfg(feature = "image")]
+    pub width: Option<u32>,
+
+    /// The height of the resource in pixels, if it is an image.
+    #[cfg(feature = "image")]
+    pub height: Option<u32>,
+}
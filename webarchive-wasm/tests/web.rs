@@ -0,0 +1,64 @@
+//! Run with `wasm-pack test --node webarchive-wasm`.
+
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen_test::wasm_bindgen_test;
+use webarchive_wasm::WebArchiveJs;
+
+const CROUTON_WEBARCHIVE: &[u8] = include_bytes!("../../fixtures/crouton.webarchive");
+const CROUTON_PNG: &[u8] = include_bytes!("../../fixtures/crouton.net/crouton.png");
+
+#[wasm_bindgen_test]
+fn manifest() {
+    let archive = WebArchiveJs::parse(CROUTON_WEBARCHIVE).unwrap();
+    let manifest = archive.manifest();
+
+    assert_eq!(manifest.len(), 2);
+    assert_eq!(manifest[0].url, "https://crouton.net/");
+    assert_eq!(manifest[0].mime_type, "text/html");
+    assert_eq!(manifest[0].frame, "main");
+    assert!(manifest[0].is_main_resource);
+    assert_eq!(manifest[1].url, "https://crouton.net/crouton.png");
+    assert_eq!(manifest[1].mime_type, "image/png");
+    assert_eq!(manifest[1].size, CROUTON_PNG.len());
+    assert!(!manifest[1].is_main_resource);
+}
+
+#[wasm_bindgen_test]
+fn resource_data() {
+    let archive = WebArchiveJs::parse(CROUTON_WEBARCHIVE).unwrap();
+
+    assert_eq!(
+        archive
+            .resource_data("https://crouton.net/crouton.png")
+            .as_deref(),
+        Some(CROUTON_PNG)
+    );
+    assert_eq!(
+        archive.resource_data("https://crouton.net/missing.png"),
+        None
+    );
+}
+
+#[wasm_bindgen_test]
+fn to_single_html() {
+    let archive = WebArchiveJs::parse(CROUTON_WEBARCHIVE).unwrap();
+    let html = archive.to_single_html().unwrap();
+
+    assert!(html.contains(r#"<img src="data:image/png;base64,iVBORw0KGgo"#));
+    assert!(!html.contains("crouton.png"));
+}
+
+#[wasm_bindgen_test]
+fn to_json() {
+    let archive = WebArchiveJs::parse(CROUTON_WEBARCHIVE).unwrap();
+    let json = archive.to_json().unwrap();
+
+    assert!(json.contains(r#""url":"https://crouton.net/""#));
+    assert!(json.contains(r#""resource_count":2"#));
+}
+
+#[wasm_bindgen_test]
+fn parse_error() {
+    assert!(WebArchiveJs::parse(b"not a webarchive").is_err());
+}